@@ -11,16 +11,67 @@ pub mod autostart;
 /// 列出所有账号
 #[tauri::command]
 pub async fn list_accounts() -> Result<Vec<Account>, String> {
-    modules::list_accounts()
+    let mut accounts = modules::list_accounts()?;
+    let now = chrono::Utc::now().timestamp();
+    for account in &mut accounts {
+        account.snooze_remaining_seconds = account
+            .proxy_disabled_until
+            .filter(|until| account.proxy_disabled && *until > now)
+            .map(|until| until - now);
+        account.reliability_grade = modules::account_reliability::get_report(&account.id)
+            .ok()
+            .map(|report| report.grade);
+    }
+    merge_live_proxy_usage(&mut accounts);
+    Ok(accounts)
+}
+
+/// 反代运行中时，用号池内存中尚未落盘的使用时间覆盖账号文件中的记录
+fn merge_live_proxy_usage(accounts: &mut [Account]) {
+    let Some(manager) = crate::proxy::token_manager::active() else {
+        return;
+    };
+    for account in accounts {
+        account.last_proxy_used_at = account.last_proxy_used_at.max(manager.last_proxy_used_at(&account.id));
+    }
+}
+
+/// 找出最近 `days` 天内未被反代使用、也未切换到 IDE 的账号 (供批量禁用/删除)
+#[tauri::command]
+pub async fn find_idle_accounts(days: u32) -> Result<Vec<modules::account::IdleAccount>, String> {
+    if days == 0 {
+        return Err("天数必须大于 0".to_string());
+    }
+    let mut accounts = modules::list_accounts()?;
+    merge_live_proxy_usage(&mut accounts);
+    Ok(modules::account::find_idle_accounts(&accounts, days, chrono::Utc::now().timestamp()))
+}
+
+/// 各账号 token 的过期快照 (维护面板)
+#[tauri::command]
+pub async fn get_token_expiries() -> Result<Vec<modules::account::TokenExpiry>, String> {
+    let accounts = modules::list_accounts()?;
+    Ok(modules::account::token_expiries(&accounts, chrono::Utc::now().timestamp()))
 }
 
-/// 添加账号
+/// 列出手动停用超过 auto_expire_manual_disables_days 天的账号 (未配置时为空)
+#[tauri::command]
+pub async fn list_stale_disables() -> Result<Vec<modules::proxy_disable::StaleDisable>, String> {
+    let Some(days) = modules::config::load_app_config()?.auto_expire_manual_disables_days else {
+        return Ok(Vec::new());
+    };
+    let accounts = modules::list_accounts()?;
+    Ok(modules::proxy_disable::find_stale_disables(&accounts, days, chrono::Utc::now().timestamp()))
+}
+
+/// 添加账号 (Refresh Token 文本/批量导入)
 #[tauri::command]
 pub async fn add_account(
     app: tauri::AppHandle,
     _email: String,
     refresh_token: String,
-) -> Result<Account, String> {
+) -> Result<modules::import_conflict::ImportReport, String> {
+    modules::instance_lock::ensure_writable()?;
     // 1. 使用 refresh_token 获取 access_token
     // 注意：这里我们忽略传入的 _email，而是直接去 Google 获取真实的邮箱
     let token_res = modules::oauth::refresh_access_token(&refresh_token).await?;
@@ -38,15 +89,23 @@ pub async fn add_account(
         None, // session_id
     );
 
-    // 4. 使用真实的 email 添加或更新账号
-    let account =
-        modules::upsert_account(user_info.email.clone(), user_info.get_display_name(), token)?;
-
-    modules::logger::log_info(&format!("添加账号成功: {}", account.email));
+    // 4. 使用真实的 email 添加或更新账号 (与已有 token 冲突时按策略处理)
+    let mut report = modules::import_conflict::ImportReport::default();
+    modules::import_conflict::import_account(
+        user_info.email.clone(),
+        user_info.get_display_name(),
+        token,
+        modules::import_conflict::ImportedDevice::default(),
+        modules::import_conflict::configured_policy(),
+        &mut report,
+    )
+    .await?;
 
     // 5. 自动触发刷新额度
-    let mut account = account;
-    let _ = internal_refresh_account_quota(&app, &mut account).await;
+    for account in report.imported.iter_mut() {
+        modules::logger::log_info(&format!("添加账号成功: {}", account.email));
+        let _ = internal_refresh_account_quota(&app, account).await;
+    }
 
     // 6. If proxy is running, reload token pool so changes take effect immediately.
     let _ = crate::commands::proxy::reload_proxy_accounts(
@@ -54,18 +113,24 @@ pub async fn add_account(
     )
     .await;
 
-    Ok(account)
+    Ok(report)
 }
 
 /// 删除账号
 #[tauri::command]
-pub async fn delete_account(app: tauri::AppHandle, account_id: String) -> Result<(), String> {
+pub async fn delete_account(
+    app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    account_id: String,
+) -> Result<(), String> {
+    modules::instance_lock::ensure_writable()?;
     modules::logger::log_info(&format!("收到删除账号请求: {}", account_id));
     modules::delete_account(&account_id).map_err(|e| {
         modules::logger::log_error(&format!("删除账号失败: {}", e));
         e
     })?;
-    modules::logger::log_info(&format!("账号删除成功: {}", account_id));
+    modules::logger::log_info(&format!("账号已移入回收站: {}", account_id));
+    reload_proxy_pool(&proxy_state).await;
 
     // 强制同步托盘
     crate::modules::tray::update_tray_menus(&app);
@@ -76,8 +141,10 @@ pub async fn delete_account(app: tauri::AppHandle, account_id: String) -> Result
 #[tauri::command]
 pub async fn delete_accounts(
     app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
     account_ids: Vec<String>,
 ) -> Result<(), String> {
+    modules::instance_lock::ensure_writable()?;
     modules::logger::log_info(&format!(
         "收到批量删除请求，共 {} 个账号",
         account_ids.len()
@@ -86,16 +153,82 @@ pub async fn delete_accounts(
         modules::logger::log_error(&format!("批量删除失败: {}", e));
         e
     })?;
+    reload_proxy_pool(&proxy_state).await;
 
     // 强制同步托盘
     crate::modules::tray::update_tray_menus(&app);
     Ok(())
 }
 
+/// 反代服务运行时按磁盘上的账号重新加载号池
+async fn reload_proxy_pool(proxy_state: &crate::commands::proxy::ProxyServiceState) {
+    if let Some(instance) = proxy_state.instance.read().await.as_ref() {
+        if let Err(e) = instance.token_manager.load_accounts().await {
+            modules::logger::log_warn(&format!("重新加载反代号池失败: {}", e));
+        }
+    }
+}
+
+/// 列出回收站中的账号
+#[tauri::command]
+pub async fn list_trashed_accounts() -> Result<Vec<modules::account_trash::TrashedAccount>, String> {
+    modules::account_trash::list_trashed_accounts()
+}
+
+/// 从回收站恢复账号；同邮箱账号已存在时返回待处理冲突 (通过 resolve_import_conflict 处理)
+#[tauri::command]
+pub async fn restore_trashed_account(
+    app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    account_id: String,
+) -> Result<modules::account_trash::TrashRestoreResult, String> {
+    modules::instance_lock::ensure_writable()?;
+    let result = modules::account_trash::restore_trashed_account(&account_id).await?;
+    if result.restored.is_some() {
+        reload_proxy_pool(&proxy_state).await;
+        crate::modules::tray::update_tray_menus(&app);
+    }
+    Ok(result)
+}
+
+/// 永久删除回收站条目 (older_than_days 为空时清空回收站)，返回删除数量
+#[tauri::command]
+pub async fn purge_trash(older_than_days: Option<u32>) -> Result<usize, String> {
+    modules::instance_lock::ensure_writable()?;
+    modules::account_trash::purge_trash(older_than_days)
+}
+
+/// 导出账号报表 (csv / json，不含 token)，返回导出的账号数
+#[tauri::command]
+pub async fn export_accounts_report(path: String, format: String) -> Result<usize, String> {
+    let format = modules::account_report::ReportFormat::parse(&format)?;
+    modules::account_report::export_accounts_report(std::path::Path::new(&path), format)
+}
+
+/// 导出脱敏的诊断包 (zip，不含 token / 邮箱 / 密钥)，返回写入的文件路径
+#[tauri::command]
+pub async fn create_diagnostic_bundle(
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    path: String,
+) -> Result<String, String> {
+    // 反代运行中时使用监控器的实时统计，否则回退到持久化的统计
+    let running = proxy_state.instance.read().await.is_some();
+    let live_stats = if running {
+        crate::commands::proxy::get_proxy_stats(proxy_state).await.ok()
+    } else {
+        None
+    };
+    let inputs = modules::diagnostics::collect_inputs(live_stats)?;
+    let entries = modules::diagnostics::write_bundle(std::path::Path::new(&path), &inputs)?;
+    modules::logger::log_info(&format!("诊断包已导出: {} ({} 个条目)", path, entries.len()));
+    Ok(path)
+}
+
 /// 重新排序账号列表
 /// 根据传入的账号ID数组顺序更新账号排列
 #[tauri::command]
 pub async fn reorder_accounts(account_ids: Vec<String>) -> Result<(), String> {
+    modules::instance_lock::ensure_writable()?;
     modules::logger::log_info(&format!("收到账号重排序请求，共 {} 个账号", account_ids.len()));
     modules::account::reorder_accounts(&account_ids).map_err(|e| {
         modules::logger::log_error(&format!("账号重排序失败: {}", e));
@@ -103,9 +236,21 @@ pub async fn reorder_accounts(account_ids: Vec<String>) -> Result<(), String> {
     })
 }
 
+/// 按指标 (quota / tier / last_used / email) 一键排序账号列表，返回新的账号ID顺序
+#[tauri::command]
+pub async fn reorder_accounts_by(metric: String, descending: bool) -> Result<Vec<String>, String> {
+    modules::instance_lock::ensure_writable()?;
+    let metric = modules::account::AccountSortMetric::parse(&metric)?;
+    modules::account::reorder_accounts_by(metric, descending).map_err(|e| {
+        modules::logger::log_error(&format!("账号排序失败: {}", e));
+        e
+    })
+}
+
 /// 切换账号
 #[tauri::command]
 pub async fn switch_account(app: tauri::AppHandle, account_id: String) -> Result<(), String> {
+    modules::instance_lock::ensure_writable()?;
     let res = modules::switch_account(&account_id).await;
     if res.is_ok() {
         crate::modules::tray::update_tray_menus(&app);
@@ -113,6 +258,30 @@ pub async fn switch_account(app: tauri::AppHandle, account_id: String) -> Result
     res
 }
 
+/// 预演切换账号：返回将使用的路径、设备指纹与进程操作，不执行任何写入
+#[tauri::command]
+pub async fn switch_account_dry_run(account_id: String) -> Result<modules::switch_plan::SwitchPlan, String> {
+    modules::switch_plan::dry_run(&account_id).await
+}
+
+/// 按账号顺序切换到下一个可用账号 (循环)
+#[tauri::command]
+pub async fn switch_to_next_account(app: tauri::AppHandle) -> Result<Account, String> {
+    modules::instance_lock::ensure_writable()?;
+    let account = modules::account::switch_to_adjacent_account(true).await?;
+    crate::modules::tray::update_tray_menus(&app);
+    Ok(account)
+}
+
+/// 按账号顺序切换到上一个可用账号 (循环)
+#[tauri::command]
+pub async fn switch_to_previous_account(app: tauri::AppHandle) -> Result<Account, String> {
+    modules::instance_lock::ensure_writable()?;
+    let account = modules::account::switch_to_adjacent_account(false).await?;
+    crate::modules::tray::update_tray_menus(&app);
+    Ok(account)
+}
+
 /// 获取当前账号
 #[tauri::command]
 pub async fn get_current_account() -> Result<Option<Account>, String> {
@@ -194,11 +363,28 @@ pub async fn fetch_account_quota(
 pub use modules::account::RefreshStats;
 
 /// 刷新所有账号配额
+///
+/// 反代服务运行时默认跳过处于限流锁定期的账号 (记为推迟)，`force` 为 true 时全部刷新；
+/// `background` 为 true (定时自动刷新) 时在静默时段内跳过，用户手动刷新不受影响
 #[tauri::command]
 pub async fn refresh_all_quotas(
     proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    force: bool,
+    background: Option<bool>,
 ) -> Result<RefreshStats, String> {
-    let stats = modules::account::refresh_all_quotas_logic().await?;
+    if background.unwrap_or(false) && modules::scheduler::background_paused("background quota refresh") {
+        return Ok(RefreshStats { total: 0, success: 0, failed: 0, deferred: 0, details: Vec::new() });
+    }
+    let lockouts = if force {
+        std::collections::HashMap::new()
+    } else {
+        let instance_lock = proxy_state.instance.read().await;
+        instance_lock
+            .as_ref()
+            .map(|instance| instance.token_manager.lockout_snapshot())
+            .unwrap_or_default()
+    };
+    let stats = modules::account::refresh_all_quotas_with_lockouts(&lockouts).await?;
 
     // 同步到运行中的反代服务（如果已启动）
     let instance_lock = proxy_state.instance.read().await;
@@ -219,6 +405,14 @@ pub async fn refresh_all_quotas(
 
     Ok(stats)
 }
+
+/// 各账号各模型距配额刷新的时间 (基于本地保存的 quota.models[].reset_time)
+#[tauri::command]
+pub async fn get_quota_reset_times() -> Result<Vec<modules::quota::QuotaResetTime>, String> {
+    let accounts = modules::list_accounts()?;
+    Ok(modules::quota::quota_reset_times(&accounts, chrono::Utc::now().timestamp()))
+}
+
 /// 获取设备指纹（当前 storage.json + 账号绑定）
 #[tauri::command]
 pub async fn get_device_profiles(
@@ -242,6 +436,14 @@ pub async fn preview_generate_profile() -> Result<crate::models::DeviceProfile,
     Ok(crate::modules::device::generate_profile())
 }
 
+/// 校验指纹格式，返回逐字段的错误
+#[tauri::command]
+pub async fn validate_device_profile(
+    profile: crate::models::DeviceProfile,
+) -> Result<(), Vec<String>> {
+    crate::modules::device::validate_device_profile(&profile)
+}
+
 /// 使用给定指纹直接绑定
 #[tauri::command]
 pub async fn bind_device_profile_with_profile(
@@ -288,6 +490,12 @@ pub async fn delete_device_version(account_id: String, version_id: String) -> Re
     modules::delete_device_version(&account_id, &version_id)
 }
 
+/// 按 max_device_history 清理账号的历史指纹，返回移除条数
+#[tauri::command]
+pub async fn prune_device_history(account_id: String) -> Result<usize, String> {
+    modules::prune_device_history(&account_id)
+}
+
 /// 打开设备存储目录
 #[tauri::command]
 pub async fn open_device_folder(app: tauri::AppHandle) -> Result<(), String> {
@@ -313,33 +521,76 @@ pub async fn load_config() -> Result<AppConfig, String> {
 pub async fn save_config(
     app: tauri::AppHandle,
     proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
-    config: AppConfig,
+    mut config: AppConfig,
 ) -> Result<(), String> {
-    modules::save_app_config(&config)?;
+    crate::proxy::upstream::client::validate_upstream_tls(&config.proxy)?;
+    // 客户端 Key 由 /setup 页面与 Key 管理命令维护，界面快照中的列表可能已过期
+    modules::config::keep_disk_client_keys(&mut config.proxy)?;
+    crate::proxy::pricing::PricingTable::from_config(&config.pricing)?;
+    modules::config::save_app_config_with_mapping(&config, modules::model_mapping_store::MappingChangeSource::Ui)?;
+    modules::token_crypto::set_encrypt_on_save(config.encrypt_tokens_at_rest);
 
     // 通知托盘配置已更新
     let _ = app.emit("config://updated", ());
+    if let Some(monitor) = proxy_state.monitor.read().await.as_ref() {
+        crate::commands::proxy::sync_monitor_pricing(monitor, &config);
+    }
 
     // 热更新正在运行的服务
     let instance_lock = proxy_state.instance.read().await;
     if let Some(instance) = instance_lock.as_ref() {
-        // 更新模型映射
-        instance.axum_server.update_mapping(&config.proxy).await;
-        // 更新上游代理
-        instance
-            .axum_server
-            .update_proxy(config.proxy.upstream_proxy.clone())
-            .await;
-        // 更新安全策略 (auth)
-        instance.axum_server.update_security(&config.proxy).await;
-        // 更新 z.ai 配置
-        instance.axum_server.update_zai(&config.proxy).await;
+        // 更新模型映射 / 上游代理 / 安全策略 (auth) / z.ai 配置
+        instance.axum_server.apply_proxy_config(&config.proxy).await;
+        instance.token_manager.set_account_identifier_mode(config.proxy.log_account_identifier);
         tracing::debug!("已同步热更新反代服务配置");
     }
 
     Ok(())
 }
 
+/// 列出与默认值不同的配置项 (敏感字段已脱敏)，便于附在问题反馈中
+#[tauri::command]
+pub async fn diff_config_from_defaults() -> Result<Vec<modules::config::ConfigFieldDiff>, String> {
+    let config = modules::load_app_config()?;
+    let mut diffs = modules::config::diff_config_from_defaults(&config);
+    // 附上映射变更历史摘要 (只含版本、时间、来源与别名)
+    let history = modules::model_mapping_store::load_history(&modules::account::get_data_dir()?)?;
+    if !history.is_empty() {
+        diffs.push(modules::config::ConfigFieldDiff {
+            field: "model_mapping.history".to_string(),
+            current: modules::model_mapping_store::history_summary(&history),
+            default: serde_json::json!([]),
+        });
+    }
+    Ok(diffs)
+}
+
+/// 从磁盘重新加载配置并热更新运行中的服务 (用于直接编辑配置文件的场景)
+#[tauri::command]
+pub async fn reload_config_from_disk(
+    app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+) -> Result<AppConfig, String> {
+    let config = modules::load_app_config()?;
+    crate::proxy::upstream::client::validate_upstream_tls(&config.proxy)?;
+    modules::token_crypto::set_encrypt_on_save(config.encrypt_tokens_at_rest);
+
+    // 通知前端与托盘配置已更新
+    let _ = app.emit("config://updated", ());
+    if let Some(monitor) = proxy_state.monitor.read().await.as_ref() {
+        crate::commands::proxy::sync_monitor_pricing(monitor, &config);
+    }
+
+    let instance_lock = proxy_state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        instance.axum_server.apply_proxy_config(&config.proxy).await;
+        instance.token_manager.set_account_identifier_mode(config.proxy.log_account_identifier);
+        modules::logger::log_info("已从磁盘重新加载配置并热更新反代服务");
+    }
+
+    Ok(config)
+}
+
 // --- OAuth 命令 ---
 
 #[tauri::command]
@@ -407,6 +658,61 @@ pub async fn start_oauth_login(app_handle: tauri::AppHandle) -> Result<Account,
     Ok(account)
 }
 
+/// 重新授权已有账号 (账号因 invalid_grant 被禁用后使用)
+/// 授权返回的邮箱必须与原账号一致，成功后原地更新 Token 并解除禁用
+#[tauri::command]
+pub async fn reauthorize_account(
+    app_handle: tauri::AppHandle,
+    account_id: String,
+) -> Result<Account, String> {
+    let existing = modules::load_account(&account_id)?;
+    modules::logger::log_info(&format!("开始重新授权账号: {}", existing.email));
+
+    let token_res =
+        modules::oauth_server::start_oauth_flow_for_account(app_handle.clone(), &existing.email)
+            .await?;
+
+    let user_info = modules::oauth::get_user_info(&token_res.access_token).await?;
+    modules::ensure_reauthorized_email(&existing.email, &user_info.email)?;
+
+    // Google 对已授权过的应用可能不再返回 refresh_token，此时沿用原值会继续 invalid_grant
+    let refresh_token = token_res.refresh_token.ok_or_else(|| {
+        "未获取到新的 Refresh Token，请在 https://myaccount.google.com/permissions 撤销 'Antigravity Tools' 的访问权限后重试"
+            .to_string()
+    })?;
+
+    let project_id = crate::proxy::project_resolver::fetch_project_id(&token_res.access_token)
+        .await
+        .ok()
+        .or_else(|| existing.token.project_id.clone());
+
+    let token_data = TokenData::new(
+        token_res.access_token,
+        refresh_token,
+        token_res.expires_in,
+        Some(existing.email.clone()),
+        project_id,
+        None,
+    );
+
+    // upsert_account 在 Token 变化时会清除 disabled 标记
+    let mut account = modules::upsert_account(
+        existing.email.clone(),
+        user_info.get_display_name().or(existing.name.clone()),
+        token_data,
+    )?;
+    modules::logger::log_info(&format!("账号重新授权成功: {}", account.email));
+
+    let _ = internal_refresh_account_quota(&app_handle, &mut account).await;
+
+    let _ = crate::commands::proxy::reload_proxy_accounts(
+        app_handle.state::<crate::commands::proxy::ProxyServiceState>(),
+    )
+    .await;
+
+    Ok(account)
+}
+
 /// 完成 OAuth 授权（不自动打开浏览器）
 #[tauri::command]
 pub async fn complete_oauth_login(app_handle: tauri::AppHandle) -> Result<Account, String> {
@@ -488,54 +794,93 @@ pub async fn cancel_oauth_login() -> Result<(), String> {
 // --- 导入命令 ---
 
 #[tauri::command]
-pub async fn import_v1_accounts(app: tauri::AppHandle) -> Result<Vec<Account>, String> {
-    let accounts = modules::migration::import_from_v1().await?;
+pub async fn import_v1_accounts(app: tauri::AppHandle) -> Result<modules::import_conflict::ImportReport, String> {
+    modules::instance_lock::ensure_writable()?;
+    let report = modules::migration::import_from_v1().await?;
 
     // 对导入的账号尝试刷新一波
-    for mut account in accounts.clone() {
+    for mut account in report.imported.clone() {
         let _ = internal_refresh_account_quota(&app, &mut account).await;
     }
 
-    Ok(accounts)
+    Ok(report)
 }
 
 #[tauri::command]
-pub async fn import_from_db(app: tauri::AppHandle) -> Result<Account, String> {
+pub async fn import_from_db(
+    app: tauri::AppHandle,
+    capture_device: Option<bool>,
+) -> Result<modules::import_conflict::ImportReport, String> {
+    modules::instance_lock::ensure_writable()?;
     // 同步函数包装为 async
-    let mut account = modules::migration::import_from_db().await?;
+    let mut report = modules::migration::import_from_db(capture_device.unwrap_or(false)).await?;
 
-    // 既然是从数据库导入（即 IDE 当前账号），自动将其设为 Manager 的当前账号
-    let account_id = account.id.clone();
-    modules::account::set_current_account_id(&account_id)?;
+    // 既然是从数据库导入（即 IDE 当前账号），自动将其设为 Manager 的当前账号 (冲突待处理时不切换)
+    if let Some(account) = report.imported.first_mut() {
+        modules::account::set_current_account_id(&account.id)?;
 
-    // 自动触发刷新额度
-    let _ = internal_refresh_account_quota(&app, &mut account).await;
+        // 自动触发刷新额度
+        let _ = internal_refresh_account_quota(&app, account).await;
 
-    // 刷新托盘图标展示
-    crate::modules::tray::update_tray_menus(&app);
+        // 刷新托盘图标展示
+        crate::modules::tray::update_tray_menus(&app);
+    }
 
-    Ok(account)
+    Ok(report)
 }
 
 #[tauri::command]
 #[allow(dead_code)]
-pub async fn import_custom_db(app: tauri::AppHandle, path: String) -> Result<Account, String> {
+pub async fn import_custom_db(
+    app: tauri::AppHandle,
+    path: String,
+    capture_device: Option<bool>,
+) -> Result<modules::import_conflict::ImportReport, String> {
+    modules::instance_lock::ensure_writable()?;
     // 调用重构后的自定义导入函数
-    let mut account = modules::migration::import_from_custom_db_path(path).await?;
+    let mut report = modules::migration::import_from_custom_db_path(path, capture_device.unwrap_or(false)).await?;
 
-    // 自动设为当前账号
-    let account_id = account.id.clone();
-    modules::account::set_current_account_id(&account_id)?;
+    // 自动设为当前账号 (冲突待处理时不切换)
+    if let Some(account) = report.imported.first_mut() {
+        modules::account::set_current_account_id(&account.id)?;
 
-    // 自动触发刷新额度
-    let _ = internal_refresh_account_quota(&app, &mut account).await;
+        // 自动触发刷新额度
+        let _ = internal_refresh_account_quota(&app, account).await;
 
-    // 刷新托盘图标展示
-    crate::modules::tray::update_tray_menus(&app);
+        // 刷新托盘图标展示
+        crate::modules::tray::update_tray_menus(&app);
+    }
+
+    Ok(report)
+}
+
+/// 解决导入冲突：保留已有 token 或使用导入的 token
+#[tauri::command]
+pub async fn resolve_import_conflict(
+    app: tauri::AppHandle,
+    email: String,
+    choice: modules::import_conflict::ConflictChoice,
+) -> Result<Account, String> {
+    modules::instance_lock::ensure_writable()?;
+    let mut account = modules::import_conflict::resolve_conflict(&email, choice)?;
+
+    if choice == modules::import_conflict::ConflictChoice::UseImported {
+        let _ = internal_refresh_account_quota(&app, &mut account).await;
+        let _ = crate::commands::proxy::reload_proxy_accounts(
+            app.state::<crate::commands::proxy::ProxyServiceState>(),
+        )
+        .await;
+    }
 
     Ok(account)
 }
 
+/// 列出等待用户处理的导入冲突
+#[tauri::command]
+pub async fn list_import_conflicts() -> Result<Vec<modules::import_conflict::ImportConflict>, String> {
+    Ok(modules::import_conflict::pending_conflicts())
+}
+
 #[tauri::command]
 pub async fn sync_account_from_db(app: tauri::AppHandle) -> Result<Option<Account>, String> {
     // 1. 获取 DB 中的 Refresh Token
@@ -547,6 +892,11 @@ pub async fn sync_account_from_db(app: tauri::AppHandle) -> Result<Option<Accoun
         }
     };
 
+    // 该 token 已登记为待处理冲突，等待用户选择，不再重复导入
+    if modules::import_conflict::is_pending_refresh_token(&db_refresh_token) {
+        return Ok(None);
+    }
+
     // 2. 获取 Manager 当前账号
     let curr_account = modules::account::get_current_account()?;
 
@@ -565,9 +915,9 @@ pub async fn sync_account_from_db(app: tauri::AppHandle) -> Result<Option<Accoun
         modules::logger::log_info("检测到新登录账号，正在自动同步...");
     }
 
-    // 4. 执行完整导入
-    let account = import_from_db(app).await?;
-    Ok(Some(account))
+    // 4. 执行完整导入 (与已有 token 冲突时等待用户处理)
+    let report = import_from_db(app, None).await?;
+    Ok(report.imported.into_iter().next())
 }
 
 /// 保存文本文件 (绕过前端 Scope 限制)
@@ -627,6 +977,30 @@ pub async fn show_main_window(window: tauri::Window) -> Result<(), String> {
     window.show().map_err(|e| e.to_string())
 }
 
+/// 立即重新渲染托盘菜单并返回渲染结果 (调试用，不刷新配额也不切换账号)
+#[tauri::command]
+pub async fn refresh_tray(app: tauri::AppHandle) -> Result<crate::modules::tray::TraySnapshot, String> {
+    crate::modules::tray::refresh_tray_now(&app).await
+}
+
+/// 获取数据目录租约状态
+#[tauri::command]
+pub async fn get_instance_lock_status() -> Result<modules::instance_lock::InstanceLockStatus, String> {
+    Ok(modules::instance_lock::status())
+}
+
+/// 强制接管数据目录租约 (需前端确认，会使其他实例转为只读)
+#[tauri::command]
+pub async fn force_take_ownership(
+    app: tauri::AppHandle,
+    confirm: bool,
+) -> Result<modules::instance_lock::InstanceLockStatus, String> {
+    if !confirm {
+        return Err("Taking ownership requires explicit confirmation".to_string());
+    }
+    modules::instance_lock::force_take_ownership(&app)
+}
+
 /// 获取 Antigravity 可执行文件路径
 #[tauri::command]
 pub async fn get_antigravity_path(bypass_config: Option<bool>) -> Result<String, String> {
@@ -648,6 +1022,15 @@ pub async fn get_antigravity_path(bypass_config: Option<bool>) -> Result<String,
     }
 }
 
+/// 列出本机所有 Antigravity 安装 (用于多版本并存时选择)
+#[tauri::command]
+pub async fn list_antigravity_installations(
+) -> Result<Vec<crate::modules::process::AntigravityInstallation>, String> {
+    tokio::task::spawn_blocking(crate::modules::process::list_antigravity_installations)
+        .await
+        .map_err(|e| format!("扫描 Antigravity 安装失败: {}", e))
+}
+
 /// 获取 Antigravity 启动参数
 #[tauri::command]
 pub async fn get_antigravity_args() -> Result<Vec<String>, String> {
@@ -657,14 +1040,51 @@ pub async fn get_antigravity_args() -> Result<Vec<String>, String> {
     }
 }
 
+/// 重新检测 Antigravity 启动参数并写入配置 (保留原有的 --user-data-dir)
+#[tauri::command]
+pub async fn sync_antigravity_args(app: tauri::AppHandle) -> Result<Vec<String>, String> {
+    let detected = tokio::task::spawn_blocking(crate::modules::process::get_args_from_running_process)
+        .await
+        .map_err(|e| format!("检测 Antigravity 进程失败: {}", e))?
+        .ok_or("未找到正在运行的 Antigravity 进程，请先启动 IDE 后再同步启动参数")?;
+    let args = modules::config::sync_antigravity_args(detected)?;
+    modules::logger::log_info(&format!("已同步 Antigravity 启动参数: {:?}", args));
+    let _ = app.emit("config://updated", ());
+    Ok(args)
+}
+
+/// 校验 IDE 数据库中注入的 Token 是否属于当前账号 (切换账号后确认是否生效)
+#[tauri::command]
+pub async fn verify_injected_token() -> Result<modules::db::InjectedTokenStatus, String> {
+    let db_path = modules::db::get_db_path()?;
+    if !db_path.exists() {
+        return Err(format!("找不到数据库文件: {:?}", db_path));
+    }
+
+    let accounts = modules::list_accounts()?;
+    let current_id = modules::get_current_account_id()?;
+    let ide_running = modules::process::is_antigravity_running();
+
+    let status = modules::db::verify_injected_token_at(&db_path, &accounts, current_id.as_deref(), ide_running)?;
+    if status.db_locked {
+        modules::logger::log_warn("IDE 数据库被锁定，暂时无法校验注入的 Token");
+    }
+    Ok(status)
+}
+
 /// 检测更新响应结构
 pub use crate::modules::update_checker::UpdateInfo;
 
 /// 检测 GitHub releases 更新
+///
+/// `force` 由设置页的手动检查传入，跳过缓存
 #[tauri::command]
-pub async fn check_for_updates() -> Result<UpdateInfo, String> {
+pub async fn check_for_updates(app: tauri::AppHandle, force: Option<bool>) -> Result<UpdateInfo, String> {
     modules::logger::log_info("收到前端触发的更新检查请求");
-    crate::modules::update_checker::check_for_updates().await
+    let info = crate::modules::update_checker::check_for_updates(force.unwrap_or(false)).await?;
+    // 开启自动下载时在后台拉取安装包
+    crate::modules::update_installer::maybe_auto_download(&app, &info);
+    Ok(info)
 }
 
 #[tauri::command]
@@ -693,7 +1113,63 @@ pub async fn save_update_settings(
     crate::modules::update_checker::save_update_settings(&settings)
 }
 
+/// 下载当前平台的最新安装包 (进度通过 update://download-progress 事件推送)
+#[tauri::command]
+pub async fn download_update(
+    app: tauri::AppHandle,
+) -> Result<crate::modules::update_installer::DownloadedUpdate, crate::modules::update_installer::UpdateError> {
+    crate::modules::update_installer::download_update(app).await
+}
+
+/// 下载指定地址的安装包到 `dest_dir`，返回文件路径 (进度通过 update://progress 事件推送)
+#[tauri::command]
+pub async fn download_update_asset(
+    app: tauri::AppHandle,
+    download_url: String,
+    dest_dir: String,
+    expected_size: Option<u64>,
+    sha256: Option<String>,
+) -> Result<String, String> {
+    crate::modules::update_installer::download_update_from_url(
+        app,
+        &download_url,
+        std::path::Path::new(&dest_dir),
+        expected_size,
+        sha256.as_deref(),
+    )
+    .await
+    .map(|path| path.to_string_lossy().to_string())
+    .map_err(|e| e.to_string())
+}
 
+/// 取消正在进行的更新下载
+#[tauri::command]
+pub async fn cancel_update_download() -> Result<bool, String> {
+    Ok(crate::modules::update_installer::cancel_download())
+}
+
+/// 停止反代服务、落盘状态后启动安装程序并退出；安装程序启动失败时恢复之前运行的反代服务
+#[tauri::command]
+pub async fn install_update(
+    app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+) -> Result<(), crate::modules::update_installer::UpdateError> {
+    if crate::modules::update_installer::downloaded_update().is_none() {
+        return Err(crate::modules::update_installer::UpdateError::NotDownloaded);
+    }
+
+    let proxy_stopped = proxy_state.stop().await;
+
+    if let Err(e) = crate::modules::update_installer::install_update(&app) {
+        if proxy_stopped {
+            if let Err(restart_err) = proxy_state.start_with_saved_config(Some(app.clone())).await {
+                modules::logger::log_error(&format!("安装程序启动失败后重新启动反代服务失败: {}", restart_err));
+            }
+        }
+        return Err(e);
+    }
+    Ok(())
+}
 
 /// 切换账号的反代禁用状态
 #[tauri::command]
@@ -704,6 +1180,7 @@ pub async fn toggle_proxy_status(
     enable: bool,
     reason: Option<String>,
 ) -> Result<(), String> {
+    modules::instance_lock::ensure_writable()?;
     modules::logger::log_info(&format!(
         "切换账号反代状态: {} -> {}",
         account_id,
@@ -727,22 +1204,22 @@ pub async fn toggle_proxy_status(
     // 2. 更新 proxy_disabled 字段
     if enable {
         // 启用反代
-        account_json["proxy_disabled"] = serde_json::Value::Bool(false);
-        account_json["proxy_disabled_reason"] = serde_json::Value::Null;
-        account_json["proxy_disabled_at"] = serde_json::Value::Null;
+        modules::proxy_disable::enable_json(&mut account_json);
     } else {
         // 禁用反代
-        let now = chrono::Utc::now().timestamp();
-        account_json["proxy_disabled"] = serde_json::Value::Bool(true);
-        account_json["proxy_disabled_at"] = serde_json::Value::Number(now.into());
-        account_json["proxy_disabled_reason"] = serde_json::Value::String(
-            reason.unwrap_or_else(|| "用户手动禁用".to_string())
+        modules::proxy_disable::disable_json(
+            &mut account_json,
+            crate::models::ProxyDisableCategory::Manual,
+            reason.unwrap_or_else(|| "用户手动禁用".to_string()),
+            chrono::Utc::now().timestamp(),
         );
     }
 
+    // 手动切换后不再自动恢复，清除临时停用
+    modules::account_snooze::clear_snooze(&mut account_json);
+
     // 3. 保存到磁盘
-    std::fs::write(&account_path, serde_json::to_string_pretty(&account_json).unwrap())
-        .map_err(|e| format!("写入账号文件失败: {}", e))?;
+    modules::account::write_account_json(&account_path, &account_json)?;
 
     modules::logger::log_info(&format!(
         "账号反代状态已更新: {} ({})",
@@ -759,6 +1236,296 @@ pub async fn toggle_proxy_status(
     Ok(())
 }
 
+/// 临时停用账号的反代 `minutes` 分钟，到期后自动恢复；已在停用中时延长停用时间
+///
+/// 返回新的停用截止时间戳
+#[tauri::command]
+pub async fn snooze_account(
+    app: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    account_id: String,
+    minutes: u64,
+    reason: Option<String>,
+) -> Result<i64, String> {
+    modules::instance_lock::ensure_writable()?;
+    let data_dir = modules::account::get_data_dir()?;
+    let account_path = data_dir.join("accounts").join(format!("{}.json", account_id));
+
+    if !account_path.exists() {
+        return Err(format!("账号文件不存在: {}", account_id));
+    }
+
+    let until = modules::account_snooze::snooze_account_file(&account_path, minutes, reason)?;
+    modules::logger::log_info(&format!("账号 {} 临时停用反代至 {}", account_id, until));
+
+    let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
+    crate::modules::tray::update_tray_menus(&app);
+
+    Ok(until)
+}
+
+/// 账号可靠性报告：最近的刷新/禁用事件、7 天刷新成功率与平均故障间隔
+#[tauri::command]
+pub async fn get_account_reliability(
+    account_id: String,
+) -> Result<modules::account_reliability::ReliabilityReport, String> {
+    modules::account_reliability::get_report(&account_id)
+}
+
+/// OAuth 刷新耗时基准：并发刷新所有未禁用账号并返回每个账号的耗时
+#[tauri::command]
+pub async fn benchmark_token_refresh() -> Result<Vec<modules::refresh_benchmark::RefreshTiming>, String> {
+    modules::instance_lock::ensure_writable()?;
+    modules::refresh_benchmark::benchmark_token_refresh().await
+}
+
+/// 切换账号是否参与预热 (enable=false 时手动/定时/内部预热都会跳过该账号)
+#[tauri::command]
+pub async fn toggle_account_warmup(
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    account_id: String,
+    enable: bool,
+) -> Result<(), String> {
+    modules::instance_lock::ensure_writable()?;
+    let data_dir = modules::account::get_data_dir()?;
+    let account_path = data_dir.join("accounts").join(format!("{}.json", account_id));
+
+    if !account_path.exists() {
+        return Err(format!("账号文件不存在: {}", account_id));
+    }
+
+    let content = std::fs::read_to_string(&account_path)
+        .map_err(|e| format!("读取账号文件失败: {}", e))?;
+
+    let mut account_json: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("解析账号文件失败: {}", e))?;
+
+    account_json["no_warmup"] = serde_json::Value::Bool(!enable);
+
+    modules::account::write_account_json(&account_path, &account_json)?;
+
+    modules::logger::log_info(&format!(
+        "账号预热状态已更新: {} ({})",
+        account_id,
+        if enable { "允许预热" } else { "不预热" }
+    ));
+
+    // 重新加载账号池，使内部预热接口立即生效
+    let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
+
+    Ok(())
+}
+
+/// 设置账号是否为备用账号 (备用账号只在其他账号全部限流/禁用/失败时才会被反代使用)
+#[tauri::command]
+pub async fn set_account_standby(
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    account_id: String,
+    standby: bool,
+) -> Result<(), String> {
+    modules::instance_lock::ensure_writable()?;
+    let data_dir = modules::account::get_data_dir()?;
+    let account_path = data_dir.join("accounts").join(format!("{}.json", account_id));
+
+    if !account_path.exists() {
+        return Err(format!("账号文件不存在: {}", account_id));
+    }
+
+    let content = std::fs::read_to_string(&account_path)
+        .map_err(|e| format!("读取账号文件失败: {}", e))?;
+
+    let mut account_json: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("解析账号文件失败: {}", e))?;
+
+    account_json["standby"] = serde_json::Value::Bool(standby);
+
+    modules::account::write_account_json(&account_path, &account_json)?;
+
+    modules::logger::log_info(&format!(
+        "账号备用状态已更新: {} ({})",
+        account_id,
+        if standby { "备用" } else { "常规" }
+    ));
+
+    // 重新加载账号池，使调度立即生效
+    let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
+
+    Ok(())
+}
+
+/// 设置账号允许服务的配额分组 (传入空列表表示不限)
+#[tauri::command]
+pub async fn set_account_allowed_groups(
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    account_id: String,
+    groups: Vec<crate::models::QuotaGroup>,
+) -> Result<(), String> {
+    modules::instance_lock::ensure_writable()?;
+    let data_dir = modules::account::get_data_dir()?;
+    let account_path = data_dir.join("accounts").join(format!("{}.json", account_id));
+
+    if !account_path.exists() {
+        return Err(format!("账号文件不存在: {}", account_id));
+    }
+
+    let content = std::fs::read_to_string(&account_path)
+        .map_err(|e| format!("读取账号文件失败: {}", e))?;
+
+    let mut account_json: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("解析账号文件失败: {}", e))?;
+
+    let mut groups = groups;
+    groups.sort_by_key(|g| g.index());
+    groups.dedup();
+    if groups.len() == crate::models::QuotaGroup::ALL.len() {
+        groups.clear();
+    }
+    if groups.is_empty() {
+        if let Some(obj) = account_json.as_object_mut() {
+            obj.remove("allowed_groups");
+        }
+    } else {
+        account_json["allowed_groups"] = serde_json::to_value(&groups).map_err(|e| e.to_string())?;
+    }
+
+    modules::account::write_account_json(&account_path, &account_json)?;
+
+    modules::logger::log_info(&format!(
+        "账号配额分组已更新: {} ({})",
+        account_id,
+        if groups.is_empty() {
+            "不限".to_string()
+        } else {
+            groups.iter().map(|g| g.as_str()).collect::<Vec<_>>().join(", ")
+        }
+    ));
+
+    // 重新加载账号池，使调度立即生效
+    let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
+
+    Ok(())
+}
+
+/// 设置账号标签 (日志标识模式为 label 时代替邮箱显示，传入空值则清除)
+#[tauri::command]
+pub async fn set_account_label(
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    account_id: String,
+    label: Option<String>,
+) -> Result<(), String> {
+    modules::instance_lock::ensure_writable()?;
+    let data_dir = modules::account::get_data_dir()?;
+    let account_path = data_dir.join("accounts").join(format!("{}.json", account_id));
+
+    if !account_path.exists() {
+        return Err(format!("账号文件不存在: {}", account_id));
+    }
+
+    let content = std::fs::read_to_string(&account_path)
+        .map_err(|e| format!("读取账号文件失败: {}", e))?;
+
+    let mut account_json: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("解析账号文件失败: {}", e))?;
+
+    match label.map(|l| l.trim().to_string()).filter(|l| !l.is_empty()) {
+        Some(l) => account_json["label"] = serde_json::Value::String(l),
+        None => {
+            if let Some(obj) = account_json.as_object_mut() {
+                obj.remove("label");
+            }
+        }
+    }
+
+    modules::account::write_account_json(&account_path, &account_json)?;
+
+    // 如果反代服务正在运行,重新加载账号池以使标签生效
+    let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
+
+    Ok(())
+}
+
+/// 提供 token 解密口令 (账号文件已加密时需要)，并重新加载账号池
+#[tauri::command]
+pub async fn unlock_token_encryption(
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    passphrase: String,
+) -> Result<(), String> {
+    if passphrase.is_empty() {
+        return Err("口令不能为空".to_string());
+    }
+    modules::token_crypto::set_passphrase(Some(passphrase));
+    modules::logger::log_info("已设置 token 解密口令");
+
+    let _ = crate::commands::proxy::reload_proxy_accounts(proxy_state).await;
+    Ok(())
+}
+
+/// 将所有明文账号文件迁移为加密格式 (单向)，并在配置中开启静态加密，返回迁移的文件数
+#[tauri::command]
+pub async fn migrate_tokens_to_encrypted(
+    passphrase: Option<String>,
+) -> Result<usize, String> {
+    modules::instance_lock::ensure_writable()?;
+    let accounts_dir = modules::account::get_accounts_dir()?;
+
+    // 先用已加密的文件校验新口令，口令不一致时不替换当前口令
+    if let Some(p) = passphrase.filter(|p| !p.is_empty()) {
+        modules::token_crypto::verify_passphrase(&accounts_dir, &p)?;
+        modules::token_crypto::set_passphrase(Some(p));
+    }
+
+    if !modules::token_crypto::has_passphrase() {
+        return Err("迁移前请先提供加密口令".to_string());
+    }
+
+    // 迁移前开启保存时加密，避免迁移期间 token 持久化写回明文；迁移失败时恢复原设置
+    let previous = modules::token_crypto::encrypt_on_save();
+    modules::token_crypto::set_encrypt_on_save(true);
+    let migrated = match modules::token_crypto::migrate_accounts_dir(&accounts_dir) {
+        Ok(migrated) => migrated,
+        Err(e) => {
+            modules::token_crypto::set_encrypt_on_save(previous);
+            return Err(e);
+        }
+    };
+
+    let mut config = modules::load_app_config()?;
+    config.encrypt_tokens_at_rest = true;
+    modules::save_app_config(&config)?;
+
+    modules::logger::log_info(&format!("已将 {} 个账号文件的 token 迁移为加密存储", migrated));
+    Ok(migrated)
+}
+
+/// 立即创建一次全量备份，返回归档路径 (path 为空时使用配置的备份目录)
+#[tauri::command]
+pub async fn create_backup(
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    path: Option<String>,
+) -> Result<String, String> {
+    let archive = modules::backup::create_backup(&proxy_state, path.as_deref()).await?;
+    Ok(archive.to_string_lossy().to_string())
+}
+
+/// 列出备份目录中的归档 (新到旧)
+#[tauri::command]
+pub async fn list_backups() -> Result<Vec<modules::backup::BackupEntry>, String> {
+    let config = modules::load_app_config()?;
+    let dir = modules::backup::resolve_backup_dir(None, &config.backup)?;
+    Ok(modules::backup::list_backups_in(&dir))
+}
+
+/// 从归档恢复指定分区 (为空表示全部)，恢复期间停止反代服务，完成后重新启动
+#[tauri::command]
+pub async fn restore_backup(
+    app_handle: tauri::AppHandle,
+    proxy_state: tauri::State<'_, crate::commands::proxy::ProxyServiceState>,
+    archive_path: String,
+    sections: Vec<String>,
+) -> Result<modules::backup::RestoreReport, String> {
+    modules::backup::restore_backup(&proxy_state, Some(app_handle), &archive_path, sections).await
+}
+
 /// 预热所有可用账号
 #[tauri::command]
 pub async fn warm_up_all_accounts() -> Result<String, String> {
@@ -5,8 +5,26 @@ use serde::{Serialize, Deserialize};
 use crate::proxy::{ProxyConfig, TokenManager};
 use tokio::time::Duration;
 use crate::proxy::monitor::{ProxyMonitor, ProxyRequestLog, ProxyStats};
+use crate::modules::model_mapping_store::{self, MappingChangeSource, MappingHistoryEntry};
+use crate::proxy::common::model_capabilities::MappingWarning;
 
 
+/// 反代服务生命周期状态
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyLifecycle {
+    Stopped,
+    Starting,
+    Running,
+    Stopping,
+}
+
+impl Default for ProxyLifecycle {
+    fn default() -> Self {
+        Self::Stopped
+    }
+}
+
 /// 反代服务状态
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyStatus {
@@ -14,17 +32,43 @@ pub struct ProxyStatus {
     pub port: u16,
     pub base_url: String,
     pub active_accounts: usize,
+    /// 是否运行在模拟上游模式 (响应为模拟数据)
+    #[serde(default)]
+    pub mock_upstream: bool,
+    #[serde(default)]
+    pub state: ProxyLifecycle,
+    /// 启动请求时服务已在运行 (未重复启动，返回的是现有实例的状态)
+    #[serde(default)]
+    pub already_running: bool,
+    /// 配置端口被占用而自动改用其他端口时，原配置的端口
+    #[serde(default)]
+    pub requested_port: Option<u16>,
+    /// 逐 part 的流式调试日志是否开启
+    #[serde(default)]
+    pub stream_debug: bool,
+    /// HTTPS 监听 (端口与证书指纹，客户端可据此固定证书)
+    #[serde(default)]
+    pub tls: Option<crate::proxy::tls::TlsStatus>,
+    /// 各路由分组生效的鉴权模式 (服务未运行时为 None)
+    #[serde(default)]
+    pub auth_modes: Option<crate::proxy::security::EffectiveAuthModes>,
 }
 
 /// 反代服务全局状态
 pub struct ProxyServiceState {
     pub instance: Arc<RwLock<Option<ProxyServiceInstance>>>,
     pub monitor: Arc<RwLock<Option<Arc<ProxyMonitor>>>>,
+    pub lifecycle: Arc<RwLock<ProxyLifecycle>>,
+    /// 串行化启动/停止流程，避免重复点击或自动启动与手动启动竞争时创建两个实例
+    transition: Arc<tokio::sync::Mutex<()>>,
 }
 
 /// 反代服务实例
 pub struct ProxyServiceInstance {
+    /// 实际生效的配置 (port 为实际监听端口)
     pub config: ProxyConfig,
+    /// 自动改用其他端口时原配置的端口
+    pub requested_port: Option<u16>,
     pub token_manager: Arc<TokenManager>,
     pub axum_server: crate::proxy::AxumServer,
     pub server_handle: tokio::task::JoinHandle<()>,
@@ -35,101 +79,290 @@ impl ProxyServiceState {
         Self {
             instance: Arc::new(RwLock::new(None)),
             monitor: Arc::new(RwLock::new(None)),
+            lifecycle: Arc::new(RwLock::new(ProxyLifecycle::Stopped)),
+            transition: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /// 当前服务状态
+    pub async fn status(&self) -> ProxyStatus {
+        let state = *self.lifecycle.read().await;
+        let instance_lock = self.instance.read().await;
+        match instance_lock.as_ref() {
+            Some(instance) => {
+                let tls = instance.axum_server.tls_status();
+                // 主端口只接受 HTTPS 时 base_url 使用 https
+                let scheme = match &tls {
+                    Some(tls) if tls.https_port == instance.config.port => "https",
+                    _ => "http",
+                };
+                let auth_modes = instance.axum_server.effective_auth_modes().await;
+                ProxyStatus {
+                    running: true,
+                    port: instance.config.port,
+                    base_url: format!("{}://127.0.0.1:{}", scheme, instance.config.port),
+                    active_accounts: instance.token_manager.len(),
+                    mock_upstream: instance.config.mock_upstream.enabled,
+                    state,
+                    already_running: false,
+                    requested_port: instance.requested_port,
+                    stream_debug: instance.axum_server.stream_debug(),
+                    tls,
+                    auth_modes: Some(auth_modes),
+                }
+            }
+            None => ProxyStatus {
+                running: false,
+                port: 0,
+                base_url: String::new(),
+                active_accounts: 0,
+                mock_upstream: false,
+                state,
+                already_running: false,
+                requested_port: None,
+                stream_debug: false,
+                tls: None,
+                auth_modes: None,
+            },
+        }
+    }
+
+    /// 启动服务；已在运行时直接返回现有实例的状态 (`already_running = true`)
+    pub async fn start(
+        &self,
+        config: ProxyConfig,
+        data_dir: std::path::PathBuf,
+        app_handle: Option<tauri::AppHandle>,
+    ) -> Result<ProxyStatus, String> {
+        let _transition = self.transition.lock().await;
+
+        if self.instance.read().await.is_some() {
+            let mut status = self.status().await;
+            status.already_running = true;
+            return Ok(status);
+        }
+
+        *self.lifecycle.write().await = ProxyLifecycle::Starting;
+        match self.launch(config, data_dir, app_handle).await {
+            Ok(instance) => {
+                *self.instance.write().await = Some(instance);
+                *self.lifecycle.write().await = ProxyLifecycle::Running;
+                Ok(self.status().await)
+            }
+            Err(e) => {
+                *self.lifecycle.write().await = ProxyLifecycle::Stopped;
+                Err(e)
+            }
         }
     }
+
+    /// 按磁盘上保存的配置启动服务 (恢复备份、更新安装失败等场景下恢复之前停止的服务)
+    pub async fn start_with_saved_config(&self, app_handle: Option<tauri::AppHandle>) -> Result<ProxyStatus, String> {
+        let config = crate::modules::config::load_app_config()?.proxy;
+        let data_dir = crate::modules::account::get_data_dir()?;
+        self.start(config, data_dir, app_handle).await
+    }
+
+    /// 停止服务，等待监听完全关闭后才清空实例；未运行时返回 false
+    pub async fn stop(&self) -> bool {
+        let _transition = self.transition.lock().await;
+
+        // 只在取出实例时短暂持有写锁，后续等待期间状态查询等读操作不被阻塞 (生命周期显示为 Stopping)
+        let instance = {
+            let mut instance_lock = self.instance.write().await;
+            let Some(instance) = instance_lock.take() else {
+                return false;
+            };
+            *self.lifecycle.write().await = ProxyLifecycle::Stopping;
+            instance
+        };
+
+        crate::proxy::token_manager::clear_active();
+        // 确保刷新后的 token 全部落盘
+        instance.token_manager.flush_token_state().await;
+        instance.axum_server.stop();
+        // 等待服务器任务完成 (端口释放后才标记为已停止；启动/停止由 transition 锁串行化)
+        instance.server_handle.await.ok();
+
+        *self.lifecycle.write().await = ProxyLifecycle::Stopped;
+        true
+    }
+
+    async fn launch(
+        &self,
+        mut config: ProxyConfig,
+        data_dir: std::path::PathBuf,
+        app_handle: Option<tauri::AppHandle>,
+    ) -> Result<ProxyServiceInstance, String> {
+        // Ensure monitor exists
+        {
+            let mut monitor_lock = self.monitor.write().await;
+            if monitor_lock.is_none() {
+                *monitor_lock = Some(Arc::new(ProxyMonitor::new(1000, app_handle.clone())));
+            }
+            // Sync enabled state from config
+            if let Some(monitor) = monitor_lock.as_ref() {
+                monitor.set_enabled(config.enable_logging);
+                monitor.set_hash_end_users(config.hash_end_user_labels);
+                monitor.set_redact_content(config.redact_logged_content);
+                sync_monitor_pricing(monitor, &crate::modules::config::load_app_config().unwrap_or_default());
+            }
+        }
+
+        let monitor = self.monitor.read().await.as_ref().unwrap().clone();
+
+        // 2. 初始化 Token 管理器
+        let token_manager = Arc::new(TokenManager::new(data_dir.clone()));
+        // 同步 UI 传递的调度配置
+        token_manager.update_sticky_config(config.scheduling.clone()).await;
+        token_manager.set_account_identifier_mode(config.log_account_identifier);
+
+        // 3. 加载账号 (模拟上游模式下使用合成账号)
+        let active_accounts = if config.mock_upstream.enabled {
+            crate::modules::logger::log_warn("反代服务以模拟上游模式启动，所有响应均为模拟数据");
+            token_manager.add_mock_account()
+        } else {
+            token_manager.load_accounts().await
+                .map_err(|e| format!("加载账号失败: {}", e))?
+        };
+
+        // 应用从备份恢复的会话绑定与限流记录
+        if let Some(snapshot) = crate::modules::backup::take_pending_proxy_state(&data_dir) {
+            token_manager.restore_state(&snapshot);
+        }
+
+        if active_accounts == 0 {
+            let zai_enabled = config.zai.enabled
+                && !matches!(config.zai.dispatch_mode, crate::proxy::ZaiDispatchMode::Off);
+            if !zai_enabled {
+                return Err("没有可用账号，请先添加账号".to_string());
+            }
+        }
+
+        // 绑定前检查端口占用
+        let requested_port = config.port;
+        config.port = crate::proxy::port_check::resolve_listen_port(
+            config.get_bind_address(),
+            config.port,
+            config.auto_pick_port,
+        )?;
+        let requested_port = (config.port != requested_port).then_some(requested_port);
+        if let Some(requested) = requested_port {
+            crate::modules::logger::log_warn(&format!(
+                "端口 {} 已被占用，反代服务改用端口 {}",
+                requested, config.port
+            ));
+        }
+
+        crate::proxy::upstream::client::validate_upstream_tls(&config)?;
+        let listener_tls = crate::proxy::tls::listener_tls(&config.tls, &data_dir)
+            .map_err(|e| format!("加载反代 TLS 证书失败: {}", e))?;
+
+        // 启动 Axum 服务器
+        let (axum_server, server_handle) =
+            match crate::proxy::AxumServer::start(
+                config.get_bind_address().to_string(),
+                config.port,
+                token_manager.clone(),
+                config.custom_mapping.clone(),
+                config.request_timeout,
+                config.upstream_proxy.clone(),
+                crate::proxy::ProxySecurityConfig::from_proxy_config(&config),
+                config.zai.clone(),
+                monitor.clone(),
+                config.experimental.clone(),
+                config.sampling_limits.clone(),
+                config.max_history_messages,
+                config.mock_upstream.clone(),
+                crate::proxy::upstream::client::UpstreamPoolConfig::from_proxy_config(&config),
+                config.audio.clone(),
+                listener_tls,
+            ).await {
+                Ok((server, handle)) => (server, handle),
+                Err(e) => return Err(format!("启动 Axum 服务器失败: {}", e)),
+            };
+
+        spawn_snooze_watcher(Arc::downgrade(&token_manager), app_handle);
+        crate::proxy::token_manager::set_active(&token_manager);
+
+        // 每次启动时提示一次会静默丢失能力的映射
+        for warning in crate::proxy::common::model_capabilities::check_mapping(&config.custom_mapping) {
+            crate::modules::logger::log_warn(&format!("[Model-Mapping] {}", warning.message));
+        }
+
+        Ok(ProxyServiceInstance {
+            config,
+            requested_port,
+            token_manager,
+            axum_server,
+            server_handle,
+        })
+    }
 }
 
-/// 启动反代服务
+/// 启动反代服务 (已在运行时返回现有状态，并标记 already_running)
 #[tauri::command]
 pub async fn start_proxy_service(
-    config: ProxyConfig,
+    mut config: ProxyConfig,
     state: State<'_, ProxyServiceState>,
     app_handle: tauri::AppHandle,
 ) -> Result<ProxyStatus, String> {
-    let mut instance_lock = state.instance.write().await;
-    
-    // 防止重复启动
-    if instance_lock.is_some() {
-        return Err("服务已在运行中".to_string());
-    }
-
-    // Ensure monitor exists
-    {
-        let mut monitor_lock = state.monitor.write().await;
-        if monitor_lock.is_none() {
-            *monitor_lock = Some(Arc::new(ProxyMonitor::new(1000, Some(app_handle.clone()))));
-        }
-        // Sync enabled state from config
-        if let Some(monitor) = monitor_lock.as_ref() {
-            monitor.set_enabled(config.enable_logging);
-        }
-    }
-    
-    let monitor = state.monitor.read().await.as_ref().unwrap().clone();
-    
-    // 2. 初始化 Token 管理器
+    crate::modules::config::keep_disk_client_keys(&mut config)?;
     let app_data_dir = crate::modules::account::get_data_dir()?;
     // Ensure accounts dir exists even if the user will only use non-Google providers (e.g. z.ai).
     let _ = crate::modules::account::get_accounts_dir()?;
-    let accounts_dir = app_data_dir.clone();
-    
-    let token_manager = Arc::new(TokenManager::new(accounts_dir));
-    // 同步 UI 传递的调度配置
-    token_manager.update_sticky_config(config.scheduling.clone()).await;
-    
-    // 3. 加载账号
-    let active_accounts = token_manager.load_accounts().await
-        .map_err(|e| format!("加载账号失败: {}", e))?;
-    
-    if active_accounts == 0 {
-        let zai_enabled = config.zai.enabled
-            && !matches!(config.zai.dispatch_mode, crate::proxy::ZaiDispatchMode::Off);
-        if !zai_enabled {
-            return Err("没有可用账号，请先添加账号".to_string());
-        }
+
+    let status = state.start(config.clone(), app_data_dir, Some(app_handle)).await?;
+    if status.already_running {
+        crate::modules::logger::log_info("反代服务已在运行中，忽略重复启动");
+        return Ok(status);
     }
-    
-    // 启动 Axum 服务器
-    let (axum_server, server_handle) =
-        match crate::proxy::AxumServer::start(
-            config.get_bind_address().to_string(),
-            config.port,
-            token_manager.clone(),
-            config.custom_mapping.clone(),
-            config.request_timeout,
-            config.upstream_proxy.clone(),
-            crate::proxy::ProxySecurityConfig::from_proxy_config(&config),
-            config.zai.clone(),
-            monitor.clone(),
-            config.experimental.clone(),
-
-        ).await {
-            Ok((server, handle)) => (server, handle),
-            Err(e) => return Err(format!("启动 Axum 服务器失败: {}", e)),
-        };
-    
-    // 创建服务实例
-    let instance = ProxyServiceInstance {
-        config: config.clone(),
-        token_manager: token_manager.clone(), // Clone for ProxyServiceInstance
-        axum_server,
-        server_handle,
-    };
-    
-    *instance_lock = Some(instance);
-    
 
     // 保存配置到全局 AppConfig
     let mut app_config = crate::modules::config::load_app_config().map_err(|e| e)?;
-    app_config.proxy = config.clone();
+    app_config.proxy = config;
     crate::modules::config::save_app_config(&app_config).map_err(|e| e)?;
-    
-    Ok(ProxyStatus {
-        running: true,
-        port: config.port,
-        base_url: format!("http://127.0.0.1:{}", config.port),
-        active_accounts,
-    })
+
+    Ok(status)
+}
+
+/// 定期恢复临时停用已到期的账号 (无需手动重载)，并顺带落盘账号的反代使用时间；
+/// 服务停止、TokenManager 释放后自动退出
+fn spawn_snooze_watcher(token_manager: std::sync::Weak<TokenManager>, app_handle: Option<tauri::AppHandle>) {
+    use tauri::Emitter;
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(30));
+        loop {
+            interval.tick().await;
+            let Some(token_manager) = token_manager.upgrade() else {
+                break;
+            };
+            token_manager.flush_proxy_usage();
+            for account_id in token_manager.release_expired_snoozes().await {
+                crate::modules::logger::log_info(&format!("账号 {} 临时停用已到期，已恢复反代", account_id));
+                if let Some(app_handle) = &app_handle {
+                    let _ = app_handle.emit(crate::modules::account_snooze::SNOOZE_EXPIRED_EVENT, &account_id);
+                }
+            }
+        }
+    });
+}
+
+/// 按当前配置重新加载 HTTPS 证书 (证书轮换)，不重启服务、不丢失号池状态
+#[tauri::command]
+pub async fn reload_proxy_tls(
+    state: State<'_, ProxyServiceState>,
+) -> Result<crate::proxy::tls::TlsStatus, String> {
+    let instance_lock = state.instance.read().await;
+    let Some(instance) = instance_lock.as_ref() else {
+        return Err("服务未运行".to_string());
+    };
+    let config = crate::modules::config::load_app_config()?;
+    let data_dir = crate::modules::account::get_data_dir()?;
+    let cert = crate::proxy::tls::load_cert(&config.proxy.tls, &data_dir)
+        .map_err(|e| format!("加载反代 TLS 证书失败: {}", e))?;
+    instance.axum_server.reload_tls(cert)
 }
 
 /// 停止反代服务
@@ -137,19 +370,9 @@ pub async fn start_proxy_service(
 pub async fn stop_proxy_service(
     state: State<'_, ProxyServiceState>,
 ) -> Result<(), String> {
-    let mut instance_lock = state.instance.write().await;
-    
-    if instance_lock.is_none() {
+    if !state.stop().await {
         return Err("服务未运行".to_string());
     }
-    
-    // 停止 Axum 服务器
-    if let Some(instance) = instance_lock.take() {
-        instance.axum_server.stop();
-        // 等待服务器任务完成
-        instance.server_handle.await.ok();
-    }
-    
     Ok(())
 }
 
@@ -158,22 +381,16 @@ pub async fn stop_proxy_service(
 pub async fn get_proxy_status(
     state: State<'_, ProxyServiceState>,
 ) -> Result<ProxyStatus, String> {
-    let instance_lock = state.instance.read().await;
-    
-    match instance_lock.as_ref() {
-        Some(instance) => Ok(ProxyStatus {
-            running: true,
-            port: instance.config.port,
-            base_url: format!("http://127.0.0.1:{}", instance.config.port),
-            active_accounts: instance.token_manager.len(),
-        }),
-        None => Ok(ProxyStatus {
-            running: false,
-            port: 0,
-            base_url: String::new(),
-            active_accounts: 0,
-        }),
-    }
+    Ok(state.status().await)
+}
+
+/// 将配置中的费用估算单价同步到监控 (单价无效时不计价)
+pub fn sync_monitor_pricing(monitor: &ProxyMonitor, config: &crate::models::AppConfig) {
+    let pricing = crate::proxy::pricing::PricingTable::from_config(&config.pricing).unwrap_or_else(|e| {
+        tracing::warn!("费用估算单价无效，已停用计价: {}", e);
+        Default::default()
+    });
+    monitor.set_pricing(pricing, config.tray_show_daily_cost);
 }
 
 /// 获取反代服务统计
@@ -181,12 +398,35 @@ pub async fn get_proxy_status(
 pub async fn get_proxy_stats(
     state: State<'_, ProxyServiceState>,
 ) -> Result<ProxyStats, String> {
-    let monitor_lock = state.monitor.read().await;
-    if let Some(monitor) = monitor_lock.as_ref() {
-        Ok(monitor.get_stats().await)
-    } else {
-        Ok(ProxyStats::default())
+    let mut stats = {
+        let monitor_lock = state.monitor.read().await;
+        match monitor_lock.as_ref() {
+            Some(monitor) => monitor.get_stats().await,
+            None => ProxyStats::default(),
+        }
+    };
+    if let Some(instance) = state.instance.read().await.as_ref() {
+        stats.zai_keys = instance.axum_server.zai_key_stats().await;
+        stats.standby_activations = instance.token_manager.standby_activations();
+        stats.group_selections = instance.token_manager.group_selections();
     }
+    Ok(stats)
+}
+
+/// 计算 Claude 请求体对应的会话指纹 (与调度使用的一致：均在净化与历史截断之前计算，不产生副作用)
+#[tauri::command]
+pub fn compute_session_id(body: serde_json::Value) -> Result<String, String> {
+    crate::proxy::session_manager::SessionManager::session_id_for_body(body)
+}
+
+/// 按配置单价估算的用量费用 (range: today / week / month / all)
+#[tauri::command]
+pub async fn get_usage_costs(
+    range: crate::proxy::pricing::UsageRange,
+) -> Result<crate::proxy::pricing::UsageCostReport, String> {
+    tokio::task::spawn_blocking(move || crate::modules::proxy_db::get_usage_costs(range))
+        .await
+        .map_err(|e| format!("统计用量费用失败: {}", e))?
 }
 
 /// 获取反代请求日志
@@ -216,6 +456,21 @@ pub async fn set_proxy_monitor_enabled(
     Ok(())
 }
 
+/// 开关逐 part 的流式调试日志 (无需重启服务)
+#[tauri::command]
+pub async fn set_stream_debug(
+    state: State<'_, ProxyServiceState>,
+    enabled: bool,
+) -> Result<(), String> {
+    match state.instance.read().await.as_ref() {
+        Some(instance) => {
+            instance.axum_server.set_stream_debug(enabled);
+            Ok(())
+        }
+        None => Err("服务未运行".to_string()),
+    }
+}
+
 /// 清除反代请求日志
 #[tauri::command]
 pub async fn clear_proxy_logs(
@@ -248,6 +503,168 @@ pub async fn get_proxy_log_detail(
     crate::modules::proxy_db::get_log_detail(&log_id)
 }
 
+/// 重放一条已记录的请求
+///
+/// - `dry_run = true`: 仅使用当前映射与转换逻辑生成 Gemini 请求体，并与按原映射模型转换的结果对比
+/// - `dry_run = false`: 通过本地反代服务完整走一遍处理流程，重放日志会被标记且不计入统计
+/// - 原始请求较大时需要 `confirm_large = true`
+#[tauri::command]
+pub async fn replay_trace(
+    state: State<'_, ProxyServiceState>,
+    trace_id: String,
+    dry_run: bool,
+    confirm_large: Option<bool>,
+) -> Result<crate::proxy::replay::ReplayTraceResult, String> {
+    use crate::proxy::replay::{self, ReplayOutcome, ReplayProtocol, ReplayTraceResult};
+
+    let log = crate::modules::proxy_db::get_log_detail(&trace_id)
+        .map_err(|e| format!("未找到请求记录 {}: {}", trace_id, e))?;
+    let body = replay::check_replayable(log.request_body.as_deref())?;
+
+    let body_len = log.request_body.as_ref().map(|b| b.len()).unwrap_or(0);
+    if body_len > replay::LARGE_REPLAY_THRESHOLD && !confirm_large.unwrap_or(false) {
+        return Err(format!(
+            "原始请求体较大 ({} bytes)，请确认后重放 (confirm_large = true)",
+            body_len
+        ));
+    }
+
+    let original = ReplayOutcome {
+        status: log.status,
+        mapped_model: log.mapped_model.clone(),
+        account_email: log.account_email.clone(),
+        error: log.error.clone(),
+        duration: log.duration,
+    };
+
+    let instance_lock = state.instance.read().await;
+
+    if dry_run {
+        let protocol = ReplayProtocol::from_url(&log.url)
+            .ok_or_else(|| format!("不支持对 {} 进行 dry-run 转换", log.url))?;
+        let custom_mapping = match instance_lock.as_ref() {
+            Some(instance) => instance.axum_server.get_mapping().await,
+            None => crate::modules::config::load_app_config()?.proxy.custom_mapping,
+        };
+
+        let (mapped_model_now, gemini_body) =
+            replay::transform_for_replay(protocol, &log.url, &body, &custom_mapping, None)?;
+
+        // 未保存当时的上游请求体，以“原映射模型 + 当前转换逻辑”作为对比基线
+        let gemini_body_diff = match log.mapped_model.as_deref() {
+            Some(old_model) if old_model != mapped_model_now => {
+                let (_, old_body) = replay::transform_for_replay(
+                    protocol,
+                    &log.url,
+                    &body,
+                    &custom_mapping,
+                    Some(old_model),
+                )?;
+                replay::diff_json(&old_body, &gemini_body)
+            }
+            _ => Vec::new(),
+        };
+
+        return Ok(ReplayTraceResult {
+            trace_id,
+            dry_run: true,
+            url: log.url,
+            model: log.model,
+            original,
+            replayed: None,
+            mapped_model_now: Some(mapped_model_now),
+            gemini_body_diff,
+            gemini_body: Some(gemini_body),
+        });
+    }
+
+    let instance = instance_lock.as_ref().ok_or("服务未运行，无法重放请求")?;
+    let endpoint = instance.axum_server.loopback_endpoint(instance.config.port);
+    let url = format!("{}{}", endpoint.base_url, log.url);
+    let api_key = instance.config.api_key.clone();
+    drop(instance_lock);
+
+    let client = endpoint.client(Duration::from_secs(300))?;
+
+    let start = std::time::Instant::now();
+    let resp = client
+        .post(&url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header(replay::REPLAY_HEADER, &trace_id)
+        .header(replay::INTERNAL_TOKEN_HEADER, replay::internal_token())
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("重放请求失败: {}", e))?;
+
+    let status = resp.status().as_u16();
+    let header_value = |name: &str| {
+        resp.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string())
+    };
+    let mapped_model_now = header_value("X-Mapped-Model");
+    let account_email = header_value("X-Account-Email").or_else(|| header_value("X-Account-Id"));
+    let text = resp.text().await.unwrap_or_default();
+    let duration = start.elapsed().as_millis() as u64;
+
+    let error = if status >= 400 {
+        let preview: String = text.chars().take(2000).collect();
+        Some(preview)
+    } else {
+        None
+    };
+
+    tracing::info!(
+        "[Replay] {} -> HTTP {} (original HTTP {})",
+        trace_id, status, original.status
+    );
+
+    Ok(ReplayTraceResult {
+        trace_id,
+        dry_run: false,
+        url: log.url,
+        model: log.model,
+        original,
+        replayed: Some(ReplayOutcome {
+            status,
+            mapped_model: mapped_model_now.clone(),
+            account_email,
+            error,
+            duration,
+        }),
+        mapped_model_now,
+        gemini_body_diff: Vec::new(),
+        gemini_body: None,
+    })
+}
+
+/// 吞吐自测：通过本地回环向运行中的反代并发发送 `requests` 个最小请求 (会消耗真实配额)
+///
+/// 需在实验性配置中开启 enable_self_test；自测请求带有自测标记，不计入使用统计
+#[tauri::command]
+pub async fn proxy_self_test(
+    state: State<'_, ProxyServiceState>,
+    concurrency: usize,
+    requests: usize,
+    prompt: Option<String>,
+) -> Result<crate::proxy::self_test::SelfTestReport, String> {
+    let (endpoint, api_key) = {
+        let instance_lock = state.instance.read().await;
+        let instance = instance_lock.as_ref().ok_or("服务未运行，无法自测")?;
+        if !instance.config.experimental.enable_self_test {
+            return Err("吞吐自测未启用，请先在实验性设置中开启 (会消耗真实配额)".to_string());
+        }
+        (
+            instance.axum_server.loopback_endpoint(instance.config.port),
+            instance.config.api_key.clone(),
+        )
+    };
+    let prompt = prompt.filter(|p| !p.trim().is_empty()).unwrap_or_else(|| "Say hi".to_string());
+    crate::proxy::self_test::run_self_test(&endpoint, &api_key, concurrency, requests, &prompt).await
+}
+
 /// 生成 API Key
 #[tauri::command]
 pub fn generate_api_key() -> String {
@@ -271,12 +688,27 @@ pub async fn reload_proxy_accounts(
     }
 }
 
-/// 更新模型映射表 (热更新)
+/// 更新模型映射表 (热更新)，返回能力不匹配的警告 (不阻止保存)
 #[tauri::command]
 pub async fn update_model_mapping(
     config: ProxyConfig,
     state: State<'_, ProxyServiceState>,
-) -> Result<(), String> {
+) -> Result<Vec<MappingWarning>, String> {
+    apply_model_mapping(config, &state, MappingChangeSource::Ui).await
+}
+
+async fn apply_model_mapping(
+    config: ProxyConfig,
+    state: &ProxyServiceState,
+    source: MappingChangeSource,
+) -> Result<Vec<MappingWarning>, String> {
+    for (alias, target) in &config.custom_mapping {
+        target
+            .validate()
+            .map_err(|e| format!("模型映射 {} 无效: {}", alias, e))?;
+    }
+    let warnings = crate::proxy::common::model_capabilities::check_mapping(&config.custom_mapping);
+
     let instance_lock = state.instance.read().await;
     
     // 1. 如果服务正在运行，立即更新内存中的映射 (这里目前只更新了 anthropic_mapping 的 RwLock, 
@@ -289,11 +721,185 @@ pub async fn update_model_mapping(
     // 2. 无论是否运行，都保存到全局配置持久化
     let mut app_config = crate::modules::config::load_app_config().map_err(|e| e)?;
     app_config.proxy.custom_mapping = config.custom_mapping;
-    crate::modules::config::save_app_config(&app_config).map_err(|e| e)?;
+    crate::modules::config::save_app_config_with_mapping(&app_config, source)?;
     
+    Ok(warnings)
+}
+
+/// 列出 /setup 页面生成的客户端 Key (只含哈希)
+#[tauri::command]
+pub async fn list_client_keys() -> Result<Vec<crate::proxy::config::ClientApiKey>, String> {
+    Ok(crate::modules::config::load_app_config()?.proxy.client_keys)
+}
+
+/// 吊销客户端 Key：写入配置后立即在运行中的服务生效
+#[tauri::command]
+pub async fn revoke_client_key(
+    key_id: String,
+    state: State<'_, ProxyServiceState>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    use tauri::Emitter;
+    let data_dir = crate::modules::account::get_data_dir()?;
+    if !crate::modules::config::remove_client_key_in(&data_dir, &key_id)? {
+        return Err(format!("客户端 Key 不存在: {}", key_id));
+    }
+    let config = crate::modules::config::load_app_config()?;
+    if let Some(instance) = state.instance.read().await.as_ref() {
+        instance.axum_server.update_security(&config.proxy).await;
+    }
+    crate::modules::logger::log_info(&format!("已吊销客户端 Key {}", key_id));
+    let _ = app_handle.emit("config://updated", ());
     Ok(())
 }
 
+/// 获取模型映射变更历史
+#[tauri::command]
+pub async fn get_model_mapping_history() -> Result<Vec<MappingHistoryEntry>, String> {
+    let data_dir = crate::modules::account::get_data_dir()?;
+    model_mapping_store::load_history(&data_dir)
+}
+
+/// 回滚模型映射到指定版本 (热更新)
+#[tauri::command]
+pub async fn revert_model_mapping(
+    version_index: u64,
+    state: State<'_, ProxyServiceState>,
+) -> Result<(), String> {
+    crate::modules::instance_lock::ensure_writable()?;
+    let data_dir = crate::modules::account::get_data_dir()?;
+    let limit = crate::modules::config::load_app_config()?.model_mapping_history_limit;
+    model_mapping_store::revert_to(&data_dir, version_index, limit)?;
+
+    let config = crate::modules::config::load_app_config()?.proxy;
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        instance.axum_server.update_mapping(&config).await;
+    }
+    crate::modules::logger::log_info(&format!("模型映射已回滚到版本 {}", version_index));
+    Ok(())
+}
+
+/// 导出当前模型映射为 JSON 模板 (服务运行时取实时映射，否则取已保存配置)
+#[tauri::command]
+pub async fn export_model_mapping(
+    state: State<'_, ProxyServiceState>,
+) -> Result<String, String> {
+    let mapping = {
+        let instance_lock = state.instance.read().await;
+        match instance_lock.as_ref() {
+            Some(instance) => instance.axum_server.get_mapping().await,
+            None => crate::modules::config::load_app_config()?.proxy.custom_mapping,
+        }
+    };
+    crate::proxy::common::model_mapping::export_mapping_template(&mapping)
+}
+
+/// 导入模型映射模板，经 update_model_mapping 热更新并持久化，返回能力不匹配的警告
+#[tauri::command]
+pub async fn import_model_mapping(
+    json: String,
+    merge: bool,
+    state: State<'_, ProxyServiceState>,
+) -> Result<Vec<MappingWarning>, String> {
+    let mut config = crate::modules::config::load_app_config()?.proxy;
+    {
+        let instance_lock = state.instance.read().await;
+        if let Some(instance) = instance_lock.as_ref() {
+            config.custom_mapping = instance.axum_server.get_mapping().await;
+        }
+    }
+    config.custom_mapping =
+        crate::proxy::common::model_mapping::apply_mapping_template(&config.custom_mapping, &json, merge)?;
+    apply_model_mapping(config, &state, MappingChangeSource::Import).await
+}
+
+/// 各账号的容量评分 (仪表盘使用)；服务未运行时按磁盘上的账号与已保存的等级权重计算
+#[tauri::command]
+pub async fn get_account_capacity(
+    proxy_state: State<'_, ProxyServiceState>,
+) -> Result<Vec<crate::proxy::token_manager::AccountCapacity>, String> {
+    if let Some(instance) = proxy_state.instance.read().await.as_ref() {
+        return Ok(instance.token_manager.capacity_scores().await);
+    }
+
+    let weights = crate::modules::config::load_app_config()?.proxy.scheduling.tier_weights;
+    let mut scores: Vec<_> = crate::modules::account::list_accounts()?
+        .into_iter()
+        .filter(|a| !a.disabled && !a.proxy_disabled)
+        .map(|a| {
+            let tier = a.quota.as_ref().and_then(|q| q.subscription_tier.clone());
+            let quota_percent = a
+                .quota
+                .as_ref()
+                .filter(|q| !q.models.is_empty())
+                .map(|q| q.models.iter().map(|m| m.percentage).sum::<i32>() / q.models.len() as i32);
+            crate::proxy::token_manager::AccountCapacity {
+                capacity_score: crate::proxy::sticky_config::capacity_score(&weights, tier.as_deref(), quota_percent),
+                account_id: a.id,
+                email: a.email,
+                subscription_tier: tier,
+                quota_percent,
+            }
+        })
+        .collect();
+    scores.sort_by(|a, b| b.capacity_score.cmp(&a.capacity_score).then_with(|| a.email.cmp(&b.email)));
+    Ok(scores)
+}
+
+/// 导出反代运行时状态快照 (号池概要 / 限流记录 / 会话绑定 / 调度配置，不含任何密钥)
+#[tauri::command]
+pub async fn snapshot_proxy_state(
+    proxy_state: State<'_, ProxyServiceState>,
+) -> Result<crate::proxy::token_manager::ProxyStateSnapshot, String> {
+    let instance_lock = proxy_state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        Ok(instance.token_manager.snapshot_state().await)
+    } else {
+        Err("服务未运行".to_string())
+    }
+}
+
+/// 从快照恢复限流记录与会话绑定，用于复现调度问题
+#[tauri::command]
+pub async fn restore_proxy_state(
+    snapshot: crate::proxy::token_manager::ProxyStateSnapshot,
+    proxy_state: State<'_, ProxyServiceState>,
+) -> Result<crate::proxy::token_manager::ProxyStateRestoreSummary, String> {
+    let instance_lock = proxy_state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        Ok(instance.token_manager.restore_state(&snapshot))
+    } else {
+        Err("服务未运行".to_string())
+    }
+}
+
+/// 获取加权模型映射的配置占比与实际分流统计
+#[tauri::command]
+pub async fn get_model_route_split_stats(
+    state: State<'_, ProxyServiceState>,
+) -> Result<Vec<crate::proxy::common::model_mapping::WeightedRouteSplit>, String> {
+    let instance_lock = state.instance.read().await;
+    let custom_mapping = match instance_lock.as_ref() {
+        Some(instance) => instance.axum_server.get_mapping().await,
+        None => crate::modules::config::load_app_config()?.proxy.custom_mapping,
+    };
+    Ok(crate::proxy::common::model_mapping::weighted_route_stats(&custom_mapping))
+}
+
+/// 当前模型映射的健康状况：列出会静默丢失 Thinking / 工具调用能力的映射
+#[tauri::command]
+pub async fn get_model_mapping_health(
+    state: State<'_, ProxyServiceState>,
+) -> Result<Vec<MappingWarning>, String> {
+    let instance_lock = state.instance.read().await;
+    let custom_mapping = match instance_lock.as_ref() {
+        Some(instance) => instance.axum_server.get_mapping().await,
+        None => crate::modules::config::load_app_config()?.proxy.custom_mapping,
+    };
+    Ok(crate::proxy::common::model_capabilities::check_mapping(&custom_mapping))
+}
+
 fn join_base_url(base: &str, path: &str) -> String {
     let base = base.trim_end_matches('/');
     let path = if path.starts_with('/') {
@@ -362,9 +968,8 @@ pub async fn fetch_zai_models(
     if zai.base_url.trim().is_empty() {
         return Err("z.ai base_url is empty".to_string());
     }
-    if zai.api_key.trim().is_empty() {
-        return Err("z.ai api_key is not set".to_string());
-    }
+    // 模型列表与 Key 无关，使用任意一个可用 Key 即可
+    let api_key = zai.first_key().ok_or_else(|| "z.ai api_key is not set".to_string())?;
 
     let url = join_base_url(&zai.base_url, "/v1/models");
 
@@ -380,8 +985,8 @@ pub async fn fetch_zai_models(
 
     let resp = client
         .get(&url)
-        .header("Authorization", format!("Bearer {}", zai.api_key))
-        .header("x-api-key", zai.api_key)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("x-api-key", api_key)
         .header("anthropic-version", "2023-06-01")
         .header("accept", "application/json")
         .send()
@@ -433,6 +1038,19 @@ pub async fn update_proxy_scheduling_config(
     }
 }
 
+/// 获取账号限流与不健康停用状态
+#[tauri::command]
+pub async fn get_rate_limit_status(
+    state: State<'_, ProxyServiceState>,
+) -> Result<Vec<crate::proxy::rate_limit::RateLimitStatusEntry>, String> {
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        Ok(instance.token_manager.get_rate_limit_status())
+    } else {
+        Ok(Vec::new())
+    }
+}
+
 /// 清除所有会话粘性绑定
 #[tauri::command]
 pub async fn clear_proxy_session_bindings(
@@ -447,3 +1065,62 @@ pub async fn clear_proxy_session_bindings(
     }
 }
 
+/// 按策略重新平衡会话粘性绑定 (drop_all / move_lowest_quota)
+#[tauri::command]
+pub async fn rebalance_proxy_sessions(
+    state: State<'_, ProxyServiceState>,
+    strategy: String,
+) -> Result<crate::proxy::token_manager::SessionRebalanceSummary, String> {
+    let strategy: crate::proxy::token_manager::SessionRebalanceStrategy = strategy.parse()?;
+    let instance_lock = state.instance.read().await;
+    if let Some(instance) = instance_lock.as_ref() {
+        Ok(instance.token_manager.rebalance_sessions(strategy))
+    } else {
+        Err("服务未运行".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_concurrent_start_creates_single_listener() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut config = ProxyConfig::default();
+        config.port = port;
+        config.mock_upstream.enabled = true;
+        let data_dir = std::env::temp_dir().join(format!("ag_proxy_start_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(data_dir.join("accounts")).unwrap();
+
+        let state = Arc::new(ProxyServiceState::new());
+        let starts = (0..8).map(|_| {
+            let state = state.clone();
+            let config = config.clone();
+            let data_dir = data_dir.clone();
+            tokio::spawn(async move { state.start(config, data_dir, None).await })
+        });
+        let statuses: Vec<ProxyStatus> = futures::future::join_all(starts)
+            .await
+            .into_iter()
+            .map(|r| r.unwrap().expect("start should not fail"))
+            .collect();
+
+        assert_eq!(statuses.iter().filter(|s| !s.already_running).count(), 1);
+        assert!(statuses.iter().all(|s| s.running && s.state == ProxyLifecycle::Running && s.port == port));
+        // 端口由唯一的实例占用
+        assert!(std::net::TcpListener::bind(("127.0.0.1", port)).is_err());
+
+        assert!(state.stop().await);
+        assert!(!state.stop().await);
+        assert_eq!(state.status().await.state, ProxyLifecycle::Stopped);
+        // 停止后监听已完全释放，没有遗留的监听
+        assert!(std::net::TcpListener::bind(("127.0.0.1", port)).is_ok());
+
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+}
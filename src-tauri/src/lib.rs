@@ -24,6 +24,7 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_autostart::init(
             tauri_plugin_autostart::MacosLauncher::LaunchAgent,
             Some(vec!["--minimized"]),
@@ -40,14 +41,17 @@ pub fn run() {
         .manage(commands::proxy::ProxyServiceState::new())
         .setup(|app| {
             info!("Setup starting...");
+            modules::instance_lock::start_instance_lock(app.handle().clone());
             modules::tray::create_tray(app.handle())?;
             info!("Tray created");
+            modules::account_notifier::init(app.handle().clone());
             
             // 自动启动反代服务
             let handle = app.handle().clone();
             tauri::async_runtime::spawn(async move {
                 // 加载配置
                 if let Ok(config) = modules::config::load_app_config() {
+                    modules::token_crypto::set_encrypt_on_save(config.encrypt_tokens_at_rest);
                     if config.proxy.auto_start {
                         let state = handle.state::<commands::proxy::ProxyServiceState>();
                         // 尝试启动服务
@@ -66,6 +70,8 @@ pub fn run() {
             
             // 启动智能调度器
             modules::scheduler::start_scheduler(app.handle().clone());
+            modules::backup::start_backup_scheduler(app.handle().clone());
+            modules::account_trash::start_trash_purger();
             
             Ok(())
         })
@@ -87,11 +93,24 @@ pub fn run() {
             commands::add_account,
             commands::delete_account,
             commands::delete_accounts,
+            commands::list_trashed_accounts,
+            commands::restore_trashed_account,
+            commands::purge_trash,
             commands::reorder_accounts,
+            commands::reorder_accounts_by,
+            commands::export_accounts_report,
+            commands::create_diagnostic_bundle,
+            commands::find_idle_accounts,
+            commands::get_token_expiries,
+            commands::list_stale_disables,
             commands::switch_account,
+            commands::switch_account_dry_run,
+            commands::switch_to_next_account,
+            commands::switch_to_previous_account,
             // 设备指纹
             commands::get_device_profiles,
             commands::bind_device_profile,
+            commands::validate_device_profile,
             commands::bind_device_profile_with_profile,
             commands::preview_generate_profile,
             commands::apply_device_profile,
@@ -99,53 +118,102 @@ pub fn run() {
             commands::list_device_versions,
             commands::restore_device_version,
             commands::delete_device_version,
+            commands::prune_device_history,
             commands::open_device_folder,
             commands::get_current_account,
             // 配额命令
             commands::fetch_account_quota,
             commands::refresh_all_quotas,
+            commands::get_quota_reset_times,
             // 配置命令
             commands::load_config,
             commands::save_config,
+            commands::reload_config_from_disk,
+            commands::diff_config_from_defaults,
             // 新增命令
             commands::prepare_oauth_url,
             commands::start_oauth_login,
             commands::complete_oauth_login,
+            commands::reauthorize_account,
             commands::cancel_oauth_login,
             commands::import_v1_accounts,
             commands::import_from_db,
             commands::import_custom_db,
+            commands::resolve_import_conflict,
+            commands::list_import_conflicts,
             commands::sync_account_from_db,
             commands::save_text_file,
             commands::clear_log_cache,
             commands::open_data_folder,
             commands::get_data_dir_path,
             commands::show_main_window,
+            commands::refresh_tray,
+            commands::get_instance_lock_status,
+            commands::force_take_ownership,
             commands::get_antigravity_path,
+            commands::list_antigravity_installations,
             commands::get_antigravity_args,
+            commands::sync_antigravity_args,
+            commands::verify_injected_token,
             commands::check_for_updates,
             commands::get_update_settings,
             commands::save_update_settings,
+            commands::download_update,
+            commands::download_update_asset,
+            commands::cancel_update_download,
+            commands::install_update,
             commands::should_check_updates,
             commands::update_last_check_time,
             commands::toggle_proxy_status,
+            commands::snooze_account,
+            commands::get_account_reliability,
+            commands::benchmark_token_refresh,
+            commands::set_account_label,
+            commands::toggle_account_warmup,
+            commands::set_account_standby,
+            commands::set_account_allowed_groups,
+            commands::unlock_token_encryption,
+            commands::migrate_tokens_to_encrypted,
+            // 备份与恢复
+            commands::create_backup,
+            commands::list_backups,
+            commands::restore_backup,
             // 反代服务命令
             commands::proxy::start_proxy_service,
             commands::proxy::stop_proxy_service,
+            commands::proxy::reload_proxy_tls,
             commands::proxy::get_proxy_status,
             commands::proxy::get_proxy_stats,
+            commands::proxy::get_usage_costs,
+            commands::proxy::compute_session_id,
             commands::proxy::get_proxy_logs,
             commands::proxy::get_proxy_logs_paginated,
             commands::proxy::get_proxy_log_detail,
+            commands::proxy::replay_trace,
+            commands::proxy::proxy_self_test,
             commands::proxy::set_proxy_monitor_enabled,
+            commands::proxy::set_stream_debug,
             commands::proxy::clear_proxy_logs,
             commands::proxy::generate_api_key,
             commands::proxy::reload_proxy_accounts,
             commands::proxy::update_model_mapping,
+            commands::proxy::list_client_keys,
+            commands::proxy::revoke_client_key,
+            commands::proxy::get_model_mapping_history,
+            commands::proxy::revert_model_mapping,
+            commands::proxy::export_model_mapping,
+            commands::proxy::import_model_mapping,
+            commands::proxy::get_model_route_split_stats,
+            commands::proxy::get_model_mapping_health,
+            commands::proxy::get_account_capacity,
+            commands::proxy::snapshot_proxy_state,
+            commands::proxy::restore_proxy_state,
             commands::proxy::fetch_zai_models,
             commands::proxy::get_proxy_scheduling_config,
             commands::proxy::update_proxy_scheduling_config,
+            commands::proxy::get_rate_limit_status,
             commands::proxy::clear_proxy_session_bindings,
+            commands::proxy::rebalance_proxy_sessions,
             // Autostart 命令
             commands::autostart::toggle_auto_launch,
             commands::autostart::is_auto_launch_enabled,
@@ -156,6 +224,17 @@ pub fn run() {
         .build(tauri::generate_context!())
         .expect("error while building tauri application")
         .run(|app_handle, event| {
+            // 退出前将待落盘的 token 写入磁盘
+            if let tauri::RunEvent::Exit = event {
+                let state = app_handle.state::<commands::proxy::ProxyServiceState>();
+                tauri::async_runtime::block_on(async {
+                    if let Some(instance) = state.instance.read().await.as_ref() {
+                        instance.token_manager.flush_token_state().await;
+                    }
+                });
+                modules::instance_lock::release();
+            }
+
             // Handle macOS dock icon click to reopen window
             #[cfg(target_os = "macos")]
             if let tauri::RunEvent::Reopen { .. } = event {
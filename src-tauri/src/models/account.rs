@@ -1,12 +1,72 @@
 use serde::{Deserialize, Serialize};
 use super::{token::TokenData, quota::QuotaData};
 
+/// 账号文件的当前 schema 版本 (字段变化时递增，并在 account_schema 中添加升级函数)
+pub const ACCOUNT_SCHEMA_VERSION: u32 = 2;
+
+/// 反代停用原因的类别
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyDisableCategory {
+    /// 用户手动停用
+    Manual,
+    QuotaProtection,
+    InvalidGrant,
+    /// 临时停用，到期自动恢复
+    Snooze,
+    Other,
+}
+
+/// 反代调度的配额分组：各分组独立轮询，图片生成的突发流量不会打乱文本流量的粘性调度
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaGroup {
+    /// 文本类请求 (agent / web_search 等)，参与会话绑定与 60s 粘性窗口
+    Text,
+    ImageGen,
+}
+
+impl QuotaGroup {
+    pub const ALL: [QuotaGroup; 2] = [Self::Text, Self::ImageGen];
+
+    /// 由请求类型 (get_token 的 quota_group 参数) 确定分组，image_gen 以外都归入文本
+    pub fn from_request_type(request_type: &str) -> Self {
+        if request_type == "image_gen" {
+            Self::ImageGen
+        } else {
+            Self::Text
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::ImageGen => "image_gen",
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// 是否参与会话绑定与 60s 粘性窗口
+    pub fn is_sticky(self) -> bool {
+        self == Self::Text
+    }
+}
+
 /// 账号数据结构
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Account {
+    /// 账号文件 schema 版本 (旧文件没有该字段，视为 0)
+    #[serde(default)]
+    pub schema_version: u32,
     pub id: String,
     pub email: String,
     pub name: Option<String>,
+    /// 可选的账号标签，日志标识模式为 label 时代替邮箱显示
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
     pub token: TokenData,
     /// 可选的设备指纹，用于切换账号时固定机器信息
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -30,20 +90,52 @@ pub struct Account {
     /// Optional human-readable reason for proxy disabling.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub proxy_disabled_reason: Option<String>,
+    /// 停用原因的类别 (由 proxy_disable 统一写入)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_disabled_category: Option<ProxyDisableCategory>,
     /// Unix timestamp when the proxy was disabled.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub proxy_disabled_at: Option<i64>,
+    /// 临时停用 (snooze) 的截止时间戳，到期后反代服务自动恢复该账号
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_disabled_until: Option<i64>,
+    /// 剩余停用秒数 (仅由 list_accounts 填充，不读取也不应落盘)
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub snooze_remaining_seconds: Option<i64>,
+    /// 可靠性等级 (A-D/F/N/A，仅由 list_accounts 填充，便于前端排序)
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub reliability_grade: Option<String>,
+    /// 不参与任何预热 (手动/定时/内部预热接口)，用于严格控制用量的账号
+    #[serde(default)]
+    pub no_warmup: bool,
+    /// 备用账号：反代只在其他账号全部限流/禁用/失败时才会使用
+    #[serde(default)]
+    pub standby: bool,
+    /// 允许服务的配额分组，为空时不限
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_groups: Vec<QuotaGroup>,
+    /// 最近一次被反代选中的时间 (由反代延迟落盘；旧账号文件没有该字段，视为从未使用)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_proxy_used_at: Option<i64>,
+    /// 最近一次切换到 IDE 的时间 (last_used 还会因 token 更新等操作变化，不能用于闲置判断)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_switched_at: Option<i64>,
     pub created_at: i64,
     pub last_used: i64,
+    /// 当前版本不认识的字段 (更新版本写入)，保存时原样写回
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 impl Account {
     pub fn new(id: String, email: String, token: TokenData) -> Self {
         let now = chrono::Utc::now().timestamp();
         Self {
+            schema_version: ACCOUNT_SCHEMA_VERSION,
             id,
             email,
             name: None,
+            label: None,
             token,
             device_profile: None,
             device_history: Vec::new(),
@@ -53,9 +145,19 @@ impl Account {
             disabled_at: None,
             proxy_disabled: false,
             proxy_disabled_reason: None,
+            proxy_disabled_category: None,
             proxy_disabled_at: None,
+            proxy_disabled_until: None,
+            snooze_remaining_seconds: None,
+            reliability_grade: None,
+            no_warmup: false,
+            standby: false,
+            allowed_groups: Vec::new(),
+            last_proxy_used_at: None,
+            last_switched_at: None,
             created_at: now,
             last_used: now,
+            extra: serde_json::Map::new(),
         }
     }
 
@@ -103,7 +205,7 @@ impl Default for AccountIndex {
 }
 
 /// 设备指纹（storage.json 中 telemetry 相关字段）
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct DeviceProfile {
     pub machine_id: String,
     pub mac_machine_id: String,
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use crate::proxy::ProxyConfig;
 
 /// 应用配置
@@ -21,6 +22,147 @@ pub struct AppConfig {
     pub scheduled_warmup: ScheduledWarmupConfig, // [NEW] 定时预热配置
     #[serde(default)]
     pub quota_protection: QuotaProtectionConfig, // [NEW] 配额保护配置
+    #[serde(default)]
+    pub quiet_hours: QuietHoursConfig, // [NEW] 静默时段配置
+    #[serde(default)]
+    pub encrypt_tokens_at_rest: bool, // 账号 token 静态加密 (需提供口令)
+    #[serde(default)]
+    pub backup: BackupConfig, // 定时全量备份配置
+    #[serde(default)]
+    pub instance_lock: InstanceLockConfig, // 数据目录多实例租约
+    #[serde(default = "default_model_mapping_history_limit")]
+    pub model_mapping_history_limit: usize, // 模型映射变更历史保留条数
+    #[serde(default)]
+    pub import_conflict_policy: ImportConflictPolicy, // 导入账号与已有 token 冲突时的处理策略
+    #[serde(default)]
+    pub auto_expire_manual_disables_days: Option<u32>, // 手动停用超过 N 天时提醒复查 (不会自动恢复)
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u32, // 已删除账号在回收站中的保留天数
+    #[serde(default)]
+    pub pricing: HashMap<String, ModelPrice>, // 用量费用估算单价，键为模型名或以 * 结尾的前缀
+    #[serde(default)]
+    pub tray_show_daily_cost: bool, // 托盘提示中显示当天的估算费用
+    #[serde(default = "default_max_device_history")]
+    pub max_device_history: usize, // 每个账号保留的历史指纹条数 (0 表示不限制)
+}
+
+/// 模型单价 (美元 / 百万 token)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    /// 命中缓存的输入单价，未设置时按 input 计
+    #[serde(default)]
+    pub cached_input: Option<f64>,
+}
+
+fn default_model_mapping_history_limit() -> usize {
+    50
+}
+
+pub(crate) fn default_max_device_history() -> usize {
+    20
+}
+
+pub(crate) fn default_trash_retention_days() -> u32 {
+    7
+}
+
+/// 导入的账号已存在且 refresh_token 不同时的处理策略
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportConflictPolicy {
+    /// 记录为待处理冲突，由用户选择
+    #[default]
+    Ask,
+    /// 保留已有 token (适合批量导入可能过期的备份)
+    KeepExisting,
+    /// 使用导入的 token
+    UseImported,
+}
+
+/// 数据目录租约配置 (同步盘多机共享数据目录时启用)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceLockConfig {
+    /// 是否启用租约 (关闭时不写锁文件也不启动心跳)
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 心跳间隔 (秒)
+    #[serde(default = "default_lock_heartbeat_secs")]
+    pub heartbeat_secs: u64,
+
+    /// 超过该时长未更新心跳的租约视为过期 (秒)
+    #[serde(default = "default_lock_stale_after_secs")]
+    pub stale_after_secs: u64,
+}
+
+fn default_lock_heartbeat_secs() -> u64 {
+    30
+}
+
+fn default_lock_stale_after_secs() -> u64 {
+    120
+}
+
+impl Default for InstanceLockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            heartbeat_secs: default_lock_heartbeat_secs(),
+            stale_after_secs: default_lock_stale_after_secs(),
+        }
+    }
+}
+
+/// 定时全量备份配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupConfig {
+    /// 是否启用定时备份
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 备份目录 (可为同步盘目录)，为空时使用数据目录下的 backups
+    #[serde(default)]
+    pub directory: Option<String>,
+
+    /// 备份间隔 (小时)
+    #[serde(default = "default_backup_interval_hours")]
+    pub interval_hours: u32,
+
+    /// 保留最近的备份数量
+    #[serde(default = "default_backup_keep_last")]
+    pub keep_last: u32,
+
+    /// 是否使用 token 加密口令加密备份内容
+    #[serde(default)]
+    pub encrypt: bool,
+}
+
+fn default_backup_interval_hours() -> u32 {
+    24
+}
+
+fn default_backup_keep_last() -> u32 {
+    7
+}
+
+impl BackupConfig {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            directory: None,
+            interval_hours: default_backup_interval_hours(),
+            keep_last: default_backup_keep_last(),
+            encrypt: false,
+        }
+    }
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// 定时预热配置
@@ -92,6 +234,111 @@ impl Default for QuotaProtectionConfig {
     }
 }
 
+/// 静默时段配置
+/// 在该时段内暂停所有后台调度 (配额刷新、预热等)，用户手动操作不受影响
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuietHoursConfig {
+    /// 是否启用静默时段
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 开始时间 (HH:MM)
+    #[serde(default = "default_quiet_start")]
+    pub start: String,
+
+    /// 结束时间 (HH:MM)，早于开始时间表示跨越午夜
+    #[serde(default = "default_quiet_end")]
+    pub end: String,
+
+    /// 时区: "local" (系统时区)、"UTC" 或固定偏移如 "+08:00"
+    #[serde(default = "default_quiet_timezone")]
+    pub timezone: String,
+}
+
+fn default_quiet_start() -> String {
+    "00:00".to_string()
+}
+
+fn default_quiet_end() -> String {
+    "07:00".to_string()
+}
+
+fn default_quiet_timezone() -> String {
+    "local".to_string()
+}
+
+impl QuietHoursConfig {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            start: default_quiet_start(),
+            end: default_quiet_end(),
+            timezone: default_quiet_timezone(),
+        }
+    }
+
+    /// 判断给定时间是否处于静默时段内
+    /// 配置非法 (时间或时区无法解析) 时视为不在静默时段，避免后台任务被意外永久暂停
+    pub fn contains(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        use chrono::Timelike;
+
+        if !self.enabled {
+            return false;
+        }
+
+        let (Some(start), Some(end)) = (parse_hhmm(&self.start), parse_hhmm(&self.end)) else {
+            return false;
+        };
+
+        let local_time = match self.timezone.trim() {
+            "" | "local" => now.with_timezone(&chrono::Local).time(),
+            tz if tz.eq_ignore_ascii_case("utc") => now.time(),
+            tz => match parse_utc_offset(tz) {
+                Some(offset) => now.with_timezone(&offset).time(),
+                None => return false,
+            },
+        };
+        let minute = local_time.hour() * 60 + local_time.minute();
+
+        if start == end {
+            false
+        } else if start < end {
+            minute >= start && minute < end
+        } else {
+            // 跨越午夜，例如 22:00 - 07:00
+            minute >= start || minute < end
+        }
+    }
+}
+
+impl Default for QuietHoursConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 解析 "HH:MM" 为当天的分钟数
+fn parse_hhmm(s: &str) -> Option<u32> {
+    let (h, m) = s.trim().split_once(':')?;
+    let h: u32 = h.parse().ok()?;
+    let m: u32 = m.parse().ok()?;
+    if h > 23 || m > 59 {
+        return None;
+    }
+    Some(h * 60 + m)
+}
+
+/// 解析 "+08:00" / "-05:30" 形式的固定时区偏移
+fn parse_utc_offset(s: &str) -> Option<chrono::FixedOffset> {
+    let (sign, rest) = match s.chars().next()? {
+        '+' => (1, &s[1..]),
+        '-' => (-1, &s[1..]),
+        _ => return None,
+    };
+    let minutes = parse_hhmm(rest)? as i32;
+    chrono::FixedOffset::east_opt(sign * minutes * 60)
+}
+
 impl AppConfig {
     pub fn new() -> Self {
         Self {
@@ -108,6 +355,17 @@ impl AppConfig {
             auto_launch: false,
             scheduled_warmup: ScheduledWarmupConfig::default(),
             quota_protection: QuotaProtectionConfig::default(),
+            quiet_hours: QuietHoursConfig::default(),
+            encrypt_tokens_at_rest: false,
+            backup: BackupConfig::default(),
+            instance_lock: InstanceLockConfig::default(),
+            model_mapping_history_limit: default_model_mapping_history_limit(),
+            import_conflict_policy: ImportConflictPolicy::default(),
+            auto_expire_manual_disables_days: None,
+            trash_retention_days: default_trash_retention_days(),
+            pricing: HashMap::new(),
+            tray_show_daily_cost: false,
+            max_device_history: default_max_device_history(),
         }
     }
 }
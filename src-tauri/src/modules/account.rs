@@ -1,11 +1,14 @@
+use std::collections::HashSet;
 use std::fs;
 use std::path::PathBuf;
 use serde_json;
 use uuid::Uuid;
 use serde::Serialize;
 
-use crate::models::{Account, AccountIndex, AccountSummary, TokenData, QuotaData, DeviceProfile, DeviceProfileVersion,};
+use crate::models::account::ACCOUNT_SCHEMA_VERSION;
+use crate::models::{Account, AccountIndex, AccountSummary, TokenData, QuotaData, DeviceProfile, DeviceProfileVersion, ProxyDisableCategory};
 use crate::modules;
+use crate::modules::account_reliability::{self, ReliabilityEventKind};
 use once_cell::sync::Lazy;
 use std::sync::Mutex;
 
@@ -20,6 +23,11 @@ const ACCOUNTS_DIR: &str = "accounts";
 // ... existing functions get_data_dir, get_accounts_dir, load_account_index, save_account_index ...
 /// 获取数据目录路径
 pub fn get_data_dir() -> Result<PathBuf, String> {
+    #[cfg(test)]
+    if let Some(dir) = TEST_DATA_DIR.with(|d| d.borrow().clone()) {
+        return Ok(dir);
+    }
+
     let home = dirs::home_dir().ok_or("无法获取用户主目录")?;
     let data_dir = home.join(DATA_DIR);
     
@@ -32,6 +40,41 @@ pub fn get_data_dir() -> Result<PathBuf, String> {
     Ok(data_dir)
 }
 
+/// 校验账号 ID 可以安全地拼接为文件名 (来自前端的 ID 拒绝路径分隔符与 `..`)
+pub fn validate_account_id(account_id: &str) -> Result<(), String> {
+    if account_id.is_empty()
+        || account_id.contains(|c: char| matches!(c, '/' | '\\' | '\0'))
+        || account_id.contains("..")
+    {
+        return Err(format!("无效的账号 ID: {}", account_id));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+thread_local! {
+    static TEST_DATA_DIR: std::cell::RefCell<Option<PathBuf>> = const { std::cell::RefCell::new(None) };
+}
+
+/// 测试用：将当前线程的数据目录重定向到临时目录，guard 释放时恢复
+/// (只对当前线程生效，`#[tokio::test]` 默认的单线程 runtime 下整个测试都能看到)
+#[cfg(test)]
+pub fn use_test_data_dir(dir: &std::path::Path) -> TestDataDirGuard {
+    let previous = TEST_DATA_DIR.with(|d| d.borrow_mut().replace(dir.to_path_buf()));
+    TestDataDirGuard(previous)
+}
+
+#[cfg(test)]
+pub struct TestDataDirGuard(Option<PathBuf>);
+
+#[cfg(test)]
+impl Drop for TestDataDirGuard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        TEST_DATA_DIR.with(|d| *d.borrow_mut() = previous);
+    }
+}
+
 /// 获取账号目录路径
 pub fn get_accounts_dir() -> Result<PathBuf, String> {
     let data_dir = get_data_dir()?;
@@ -68,6 +111,7 @@ pub fn load_account_index() -> Result<AccountIndex, String> {
 
 /// 保存账号索引 (原子化写入)
 pub fn save_account_index(index: &AccountIndex) -> Result<(), String> {
+    modules::instance_lock::ensure_writable()?;
     let data_dir = get_data_dir()?;
     let index_path = data_dir.join(ACCOUNTS_INDEX);
     let temp_path = data_dir.join(format!("{}.tmp", ACCOUNTS_INDEX));
@@ -93,10 +137,14 @@ pub fn load_account(account_id: &str) -> Result<Account, String> {
         return Err(format!("账号不存在: {}", account_id));
     }
     
-    let content = fs::read_to_string(&account_path)
-        .map_err(|e| format!("读取账号数据失败: {}", e))?;
-    
-    serde_json::from_str(&content)
+    load_account_from_path(&account_path)
+}
+
+/// 从指定文件加载账号 (兼容明文与加密的 token)
+pub fn load_account_from_path(account_path: &std::path::Path) -> Result<Account, String> {
+    let mut account_json = modules::token_crypto::read_account_json(account_path)?;
+    modules::account_schema::upgrade(&mut account_json)?;
+    serde_json::from_value(account_json)
         .map_err(|e| format!("解析账号数据失败: {}", e))
 }
 
@@ -104,19 +152,59 @@ pub fn load_account(account_id: &str) -> Result<Account, String> {
 pub fn save_account(account: &Account) -> Result<(), String> {
     let accounts_dir = get_accounts_dir()?;
     let account_path = accounts_dir.join(format!("{}.json", account.id));
-    
-    let content = serde_json::to_string_pretty(account)
+    save_account_to_path(account, &account_path)
+}
+
+/// 保存账号到指定文件 (已启用加密或原文件已加密时加密 token)
+pub fn save_account_to_path(account: &Account, account_path: &std::path::Path) -> Result<(), String> {
+    modules::instance_lock::ensure_writable()?;
+    // 更新版本的应用写入的账号只读，避免丢失本版本不认识的字段
+    modules::account_schema::ensure_version_writable(account.schema_version)?;
+    let existing = fs::read_to_string(account_path)
+        .ok()
+        .and_then(|c| serde_json::from_str::<serde_json::Value>(&c).ok());
+    if let Some(existing) = &existing {
+        modules::account_schema::ensure_version_writable(modules::account_schema::schema_version(existing))?;
+    }
+
+    let mut account_json = serde_json::to_value(account)
+        .map_err(|e| format!("序列化账号数据失败: {}", e))?;
+    if let Some(fields) = account_json.as_object_mut() {
+        modules::account_schema::strip_transient_fields(fields);
+        fields.insert("schema_version".to_string(), ACCOUNT_SCHEMA_VERSION.into());
+    }
+
+    // 原文件已加密时保持加密，避免静默降级为明文
+    let existing_encrypted = existing
+        .as_ref()
+        .and_then(|v| v.get("token").map(modules::token_crypto::is_encrypted))
+        .unwrap_or(false);
+    if existing_encrypted || modules::token_crypto::encrypt_on_save() {
+        modules::token_crypto::encrypt_account_json(&mut account_json)?;
+    }
+
+    let content = serde_json::to_string_pretty(&account_json)
         .map_err(|e| format!("序列化账号数据失败: {}", e))?;
     
-    fs::write(&account_path, content)
+    fs::write(account_path, content)
         .map_err(|e| format!("保存账号数据失败: {}", e))
 }
 
+/// 写回以 JSON 形式局部修改的账号文件 (保留本版本不认识的字段与加密的 token)
+pub fn write_account_json(account_path: &std::path::Path, account: &serde_json::Value) -> Result<(), String> {
+    modules::instance_lock::ensure_writable()?;
+    modules::account_schema::ensure_version_writable(modules::account_schema::schema_version(account))?;
+    let content = serde_json::to_string_pretty(account)
+        .map_err(|e| format!("序列化账号数据失败: {}", e))?;
+    fs::write(account_path, content)
+        .map_err(|e| format!("写入账号文件失败: {}", e))
+}
+
 /// 列出所有账号
 /// 列出所有账号
 pub fn list_accounts() -> Result<Vec<Account>, String> {
     crate::modules::logger::log_info("已开始列出账号...");
-    let mut index = load_account_index()?;
+    let index = load_account_index()?;
     let mut accounts = Vec::new();
     let mut invalid_ids = Vec::new();
     
@@ -137,17 +225,25 @@ pub fn list_accounts() -> Result<Vec<Account>, String> {
     // 自动修复索引：移除无效的账号 ID
     if !invalid_ids.is_empty() {
         crate::modules::logger::log_warn(&format!("发现 {} 个无效的账号索引，正在自动清理...", invalid_ids.len()));
-        
-        index.accounts.retain(|s| !invalid_ids.contains(&s.id));
-        
-        // 如果当前选中的账号也是无效的，重置为第一个可用账号
-        if let Some(current_id) = &index.current_account_id {
-            if invalid_ids.contains(current_id) {
-                index.current_account_id = index.accounts.first().map(|s| s.id.clone());
-            }
-        }
-        
-        if let Err(e) = save_account_index(&index) {
+
+        // 仅清理时短暂持锁，并基于最新索引修改，避免覆盖同时进行的切换写入的当前账号
+        let result = ACCOUNT_INDEX_LOCK
+            .lock()
+            .map_err(|e| format!("获取锁失败: {}", e))
+            .and_then(|_lock| {
+                let mut index = load_account_index()?;
+                index.accounts.retain(|s| !invalid_ids.contains(&s.id));
+
+                // 如果当前选中的账号也是无效的，重置为第一个可用账号
+                if let Some(current_id) = &index.current_account_id {
+                    if invalid_ids.contains(current_id) {
+                        index.current_account_id = index.accounts.first().map(|s| s.id.clone());
+                    }
+                }
+                save_account_index(&index)
+            });
+
+        if let Err(e) = result {
             crate::modules::logger::log_error(&format!("自动清理索引失败: {}", e));
         } else {
             crate::modules::logger::log_info("索引自动清理完成");
@@ -222,6 +318,9 @@ pub fn upsert_account(email: String, name: Option<String>, token: TokenData) ->
                     account.disabled = false;
                     account.disabled_reason = None;
                     account.disabled_at = None;
+                    if modules::proxy_disable::category_of(&account) == Some(ProxyDisableCategory::InvalidGrant) {
+                        modules::proxy_disable::enable(&mut account);
+                    }
                 }
                 account.update_last_used();
                 save_account(&account)?;
@@ -261,18 +360,16 @@ pub fn upsert_account(email: String, name: Option<String>, token: TokenData) ->
     add_account(email, name, token)
 }
 
-/// 删除账号
+/// 删除账号 (移入回收站，保留期内可恢复)
 pub fn delete_account(account_id: &str) -> Result<(), String> {
     let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
     let mut index = load_account_index()?;
     
     // 从索引中移除
-    let original_len = index.accounts.len();
-    index.accounts.retain(|s| s.id != account_id);
-    
-    if index.accounts.len() == original_len {
+    let Some(pos) = index.accounts.iter().position(|s| s.id == account_id) else {
         return Err(format!("找不到账号 ID: {}", account_id));
-    }
+    };
+    let summary = index.accounts.remove(pos);
     
     // 如果是当前账号，清除当前账号
     if index.current_account_id.as_deref() == Some(account_id) {
@@ -281,16 +378,9 @@ pub fn delete_account(account_id: &str) -> Result<(), String> {
     
     save_account_index(&index)?;
     
-    // 删除账号文件
-    let accounts_dir = get_accounts_dir()?;
-    let account_path = accounts_dir.join(format!("{}.json", account_id));
-    
-    if account_path.exists() {
-        fs::remove_file(&account_path)
-            .map_err(|e| format!("删除账号文件失败: {}", e))?;
-    }
-    
-    Ok(())
+    // 账号文件移入回收站 (可靠性历史保留到彻底清除时再删除)
+    let trash_dir = modules::account_trash::trash_dir(&get_data_dir()?);
+    modules::account_trash::move_to_trash(&get_accounts_dir()?, &trash_dir, &summary, chrono::Utc::now().timestamp())
 }
 
 /// 批量删除账号 (原子性操作索引)
@@ -299,20 +389,24 @@ pub fn delete_accounts(account_ids: &[String]) -> Result<(), String> {
     let mut index = load_account_index()?;
     
     let accounts_dir = get_accounts_dir()?;
+    let trash_dir = modules::account_trash::trash_dir(&get_data_dir()?);
+    let now = chrono::Utc::now().timestamp();
     
     for account_id in account_ids {
         // 从索引中移除
-        index.accounts.retain(|s| &s.id != account_id);
+        let Some(pos) = index.accounts.iter().position(|s| &s.id == account_id) else {
+            continue;
+        };
+        let summary = index.accounts.remove(pos);
         
         // 如果是当前账号，清除当前账号
         if index.current_account_id.as_deref() == Some(account_id) {
             index.current_account_id = None;
         }
         
-        // 删除账号文件
-        let account_path = accounts_dir.join(format!("{}.json", account_id));
-        if account_path.exists() {
-            let _ = fs::remove_file(&account_path);
+        // 账号文件移入回收站
+        if let Err(e) = modules::account_trash::move_to_trash(&accounts_dir, &trash_dir, &summary, now) {
+            crate::modules::logger::log_warn(&format!("账号 {} 移入回收站失败: {}", account_id, e));
         }
     }
     
@@ -324,6 +418,51 @@ pub fn delete_accounts(account_ids: &[String]) -> Result<(), String> {
     save_account_index(&index)
 }
 
+/// 回收站恢复结果
+pub enum TrashRestore {
+    /// 已恢复 (同邮箱账号凭证相同时为现有账号，回收站副本被丢弃)
+    Restored(Account),
+    /// 删除后已添加了同邮箱的账号，回收站条目保持不动
+    EmailTaken { existing: Account, trashed: Account },
+}
+
+/// 将回收站中的账号移回账号目录并重新加入索引
+pub fn restore_from_trash(account_id: &str) -> Result<TrashRestore, String> {
+    let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    let mut index = load_account_index()?;
+    let trash_dir = modules::account_trash::trash_dir(&get_data_dir()?);
+    let entry = modules::account_trash::read_entry(&trash_dir, account_id)?;
+
+    if index.accounts.iter().any(|s| s.id == account_id) {
+        return Err(format!("账号 {} 已在账号列表中", account_id));
+    }
+    if let Some(existing) = index.accounts.iter().find(|s| s.email == entry.email) {
+        let existing = load_account(&existing.id)?;
+        let trashed = load_account_from_path(&modules::account_trash::trashed_account_path(&trash_dir, account_id))?;
+        if trashed.token.refresh_token == existing.token.refresh_token {
+            // 凭证相同，没有需要用户选择的内容：丢弃回收站中的副本
+            modules::account_trash::remove_entry(&trash_dir, account_id);
+            account_reliability::remove_history(&get_data_dir()?, account_id);
+            return Ok(TrashRestore::Restored(existing));
+        }
+        return Ok(TrashRestore::EmailTaken { existing, trashed });
+    }
+
+    let entry = modules::account_trash::take_back(&trash_dir, &get_accounts_dir()?, account_id)?;
+    index.accounts.push(AccountSummary {
+        id: entry.account_id,
+        email: entry.email,
+        name: entry.name,
+        created_at: entry.created_at,
+        last_used: entry.last_used,
+    });
+    if index.current_account_id.is_none() {
+        index.current_account_id = Some(account_id.to_string());
+    }
+    save_account_index(&index)?;
+    load_account(account_id).map(TrashRestore::Restored)
+}
+
 /// 重新排序账号列表
 /// 根据传入的账号ID顺序更新索引文件中的账号排列顺序
 pub fn reorder_accounts(account_ids: &[String]) -> Result<(), String> {
@@ -358,9 +497,139 @@ pub fn reorder_accounts(account_ids: &[String]) -> Result<(), String> {
     save_account_index(&index)
 }
 
+/// 账号列表一键排序的依据
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccountSortMetric {
+    /// 各模型剩余配额百分比的平均值
+    Quota,
+    /// 订阅等级 (ULTRA > PRO > FREE)
+    Tier,
+    LastUsed,
+    Email,
+}
+
+impl AccountSortMetric {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "quota" => Ok(Self::Quota),
+            "tier" => Ok(Self::Tier),
+            "last_used" => Ok(Self::LastUsed),
+            "email" => Ok(Self::Email),
+            other => Err(format!("不支持的排序依据: {}", other)),
+        }
+    }
+}
+
+/// 排序键 (None 表示缺少数据，无论升降序都排在最后)
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum SortKey {
+    Number(i64),
+    Text(String),
+}
+
+fn sort_key(account: &Account, metric: AccountSortMetric) -> Option<SortKey> {
+    match metric {
+        AccountSortMetric::Quota => account
+            .quota
+            .as_ref()
+            .filter(|q| !q.models.is_empty())
+            .map(|q| SortKey::Number(q.models.iter().map(|m| i64::from(m.percentage)).sum::<i64>() / q.models.len() as i64)),
+        AccountSortMetric::Tier => account
+            .quota
+            .as_ref()
+            .and_then(|q| q.subscription_tier.as_deref())
+            .and_then(|tier| match tier.to_ascii_uppercase().as_str() {
+                "ULTRA" => Some(SortKey::Number(3)),
+                "PRO" => Some(SortKey::Number(2)),
+                "FREE" => Some(SortKey::Number(1)),
+                _ => None,
+            }),
+        AccountSortMetric::LastUsed => Some(SortKey::Number(account.last_used)),
+        AccountSortMetric::Email => Some(SortKey::Text(account.email.to_lowercase())),
+    }
+}
+
+/// 按指标计算账号顺序 (稳定排序，相同值保持原顺序)
+pub fn sorted_account_ids(accounts: &[Account], metric: AccountSortMetric, descending: bool) -> Vec<String> {
+    let mut keyed: Vec<(Option<SortKey>, &str)> = accounts
+        .iter()
+        .map(|a| (sort_key(a, metric), a.id.as_str()))
+        .collect();
+    keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) if descending => b.cmp(a),
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+    keyed.into_iter().map(|(_, id)| id.to_string()).collect()
+}
+
+/// 按指标重排账号列表并保存，返回新的账号ID顺序
+pub fn reorder_accounts_by(metric: AccountSortMetric, descending: bool) -> Result<Vec<String>, String> {
+    let accounts = list_accounts()?;
+    let ids = sorted_account_ids(&accounts, metric, descending);
+    reorder_accounts(&ids)?;
+    Ok(ids)
+}
+
+/// 在有序账号列表中查找当前账号的相邻可用账号 (循环，跳过已禁用账号)
+///
+/// `accounts` 为 (账号ID, 是否禁用) 按索引顺序排列；当前账号不在列表中时从头/尾开始查找
+pub fn adjacent_account_id(accounts: &[(String, bool)], current: Option<&str>, forward: bool) -> Option<String> {
+    let len = accounts.len();
+    if len == 0 {
+        return None;
+    }
+    let start = current.and_then(|id| accounts.iter().position(|(a, _)| a == id));
+    (1..=len)
+        .map(|step| match (start, forward) {
+            (Some(pos), true) => (pos + step) % len,
+            (Some(pos), false) => (pos + len - step % len) % len,
+            (None, true) => step - 1,
+            (None, false) => len - step,
+        })
+        .map(|i| &accounts[i])
+        .find(|(id, disabled)| !*disabled && Some(id.as_str()) != current)
+        .map(|(id, _)| id.clone())
+}
+
+/// 按账号顺序切换到下一个 (`forward`) 或上一个可用账号，返回新的当前账号
+pub async fn switch_to_adjacent_account(forward: bool) -> Result<Account, String> {
+    let index = {
+        let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+        load_account_index()?
+    };
+    let accounts: Vec<(String, bool)> = index
+        .accounts
+        .iter()
+        .map(|s| {
+            let disabled = load_account(&s.id).map(|a| a.disabled).unwrap_or(true);
+            (s.id.clone(), disabled)
+        })
+        .collect();
+
+    let target = adjacent_account_id(&accounts, index.current_account_id.as_deref(), forward)
+        .ok_or_else(|| "没有其他可切换的账号".to_string())?;
+    switch_account(&target).await?;
+    load_account(&target)
+}
+
 /// 切换当前账号
 pub async fn switch_account(account_id: &str) -> Result<(), String> {
+    switch_account_with(account_id, std::sync::Arc::new(crate::modules::process::SystemIdeProcess)).await
+}
+
+/// 切换账号 (IDE 进程操作可注入)
+async fn switch_account_with(
+    account_id: &str,
+    ide: std::sync::Arc<dyn crate::modules::process::IdeProcess>,
+) -> Result<(), String> {
     use crate::modules::{oauth, process, db, device};
+    use crate::modules::switch_plan::SwitchProfileSource;
+
+    // 切换期间禁止备份/恢复同时读写账号与设备文件
+    let _backup_guard = crate::modules::backup::STATE_LOCK.lock().await;
     
     let index = {
         let _lock = ACCOUNT_INDEX_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
@@ -386,22 +655,21 @@ pub async fn switch_account(account_id: &str) -> Result<(), String> {
     }
     
     // 3. 关闭 Antigravity (增加超时时间到 20 秒)
-    if process::is_antigravity_running() {
-        process::close_antigravity(20)?;
-    }
+    // 等待在阻塞线程池中进行且不持有索引锁，期间列表/额度查询与托盘操作不受影响
+    process::close_antigravity_if_running(ide.clone(), 20).await?;
 
     // 4. 写入设备指纹（缺失则生成并绑定），仅在切换时改 storage
     let storage_path = device::get_storage_path()?;
-    let profile_to_apply = {
-        // 优先账户绑定，其次全局原始，否则现采集/生成
-        if let Some(p) = account.device_profile.clone() {
-            p
-        } else if let Some(global) = device::load_global_original() {
-            global
-        } else {
+    // 优先账户绑定，其次全局原始，否则现采集/生成 (与 switch_account_dry_run 共用同一规则)
+    let profile_to_apply = match crate::modules::switch_plan::resolve_switch_profile(
+        &account,
+        Some(&storage_path),
+        device::load_global_original(),
+    ) {
+        (Some(p), SwitchProfileSource::Bound | SwitchProfileSource::GlobalOriginal) => p,
+        (current, _) => {
             // 捕获当前 storage 为原始指纹
-            let current =
-                device::read_profile(&storage_path).unwrap_or_else(|_| device::generate_profile());
+            let current = current.unwrap_or_else(device::generate_profile);
             let _ = device::save_global_original(&current);
             current
         }
@@ -443,10 +711,11 @@ pub async fn switch_account(account_id: &str) -> Result<(), String> {
     }
     
     account.update_last_used();
+    account.last_switched_at = Some(account.last_used);
     save_account(&account)?;
 
     // 8. 重启 Antigravity
-    process::start_antigravity()?;
+    process::start_antigravity_async(ide).await?;
     crate::modules::logger::log_info(&format!("账号切换完成: {}", account.email));
 
     Ok(())
@@ -482,6 +751,7 @@ pub fn bind_device_profile(account_id: &str, mode: &str) -> Result<DeviceProfile
         "generate" => device::generate_profile(),
         _ => return Err("mode 只能是 capture 或 generate".to_string()),
     };
+    ensure_valid_profile(&profile)?;
 
     let mut account = load_account(account_id)?;
     let _ = device::save_global_original(&profile);
@@ -492,6 +762,7 @@ pub fn bind_device_profile(account_id: &str, mode: &str) -> Result<DeviceProfile
 
 /// 直接使用提供的 profile 进行绑定
 pub fn bind_device_profile_with_profile(account_id: &str, profile: DeviceProfile, label: Option<String>) -> Result<DeviceProfile, String> {
+    ensure_valid_profile(&profile)?;
     let mut account = load_account(account_id)?;
     let _ = crate::modules::device::save_global_original(&profile);
     apply_profile_to_account(&mut account, profile.clone(), label, true)?;
@@ -499,6 +770,12 @@ pub fn bind_device_profile_with_profile(account_id: &str, profile: DeviceProfile
     Ok(profile)
 }
 
+/// 绑定前校验指纹格式，字段错误合并为一条消息
+fn ensure_valid_profile(profile: &DeviceProfile) -> Result<(), String> {
+    crate::modules::device::validate_device_profile(profile)
+        .map_err(|errors| format!("设备指纹格式无效: {}", errors.join("; ")))
+}
+
 fn apply_profile_to_account(account: &mut Account, profile: DeviceProfile, label: Option<String>, add_history: bool) -> Result<(), String> {
     account.device_profile = Some(profile.clone());
     if add_history {
@@ -513,11 +790,61 @@ fn apply_profile_to_account(account: &mut Account, profile: DeviceProfile, label
             profile: profile.clone(),
             is_current: true,
         });
+        let baseline = crate::modules::device::load_global_original();
+        prune_history(&mut account.device_history, max_device_history(), baseline.as_ref());
     }
     save_account(account)?;
     Ok(())
 }
 
+fn max_device_history() -> usize {
+    crate::modules::config::load_app_config()
+        .map(|c| c.max_device_history)
+        .unwrap_or_else(|_| crate::models::config::default_max_device_history())
+}
+
+/// 历史指纹超过上限时从最旧的开始移除，当前指纹与基线指纹不会被移除 (max 为 0 时不限制)，返回移除条数
+fn prune_history(history: &mut Vec<DeviceProfileVersion>, max: usize, baseline: Option<&DeviceProfile>) -> usize {
+    if max == 0 || history.len() <= max {
+        return 0;
+    }
+    let mut candidates: Vec<(i64, usize)> = history
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| !v.is_current && baseline.map_or(true, |b| &v.profile != b))
+        .map(|(i, v)| (v.created_at, i))
+        .collect();
+    candidates.sort();
+    let evict: HashSet<usize> = candidates
+        .into_iter()
+        .take(history.len() - max)
+        .map(|(_, i)| i)
+        .collect();
+
+    let mut index = 0;
+    history.retain(|_| {
+        let keep = !evict.contains(&index);
+        index += 1;
+        keep
+    });
+    evict.len()
+}
+
+/// 按上限手动清理指定账号的历史指纹，返回移除条数
+pub fn prune_device_history(account_id: &str) -> Result<usize, String> {
+    let mut account = load_account(account_id)?;
+    let baseline = crate::modules::device::load_global_original();
+    let removed = prune_history(&mut account.device_history, max_device_history(), baseline.as_ref());
+    if removed > 0 {
+        save_account(&account)?;
+        crate::modules::logger::log_info(&format!(
+            "已清理账号 {} 的 {} 条历史指纹",
+            account.email, removed
+        ));
+    }
+    Ok(removed)
+}
+
 /// 列出指定账号的可用指纹版本（含基线）
 pub fn list_device_versions(account_id: &str) -> Result<DeviceProfiles, String> {
     get_device_profiles(account_id)
@@ -648,8 +975,8 @@ pub fn update_account_quota(account_id: &str, quota: QuotaData) -> Result<(), St
                 
                 if min_percentage <= threshold {
                     // 触发保护
-                    let is_already_protected = account.proxy_disabled && 
-                        account.proxy_disabled_reason.as_ref().map_or(false, |r| r.contains("quota_protection"));
+                    let is_already_protected = modules::proxy_disable::category_of(&account)
+                        == Some(ProxyDisableCategory::QuotaProtection);
                     
                     if !account.proxy_disabled || is_already_protected {
                         if !account.proxy_disabled {
@@ -658,26 +985,24 @@ pub fn update_account_quota(account_id: &str, quota: QuotaData) -> Result<(), St
                                 account.email, min_percentage, threshold
                             ));
                         }
-                        account.proxy_disabled = true;
-                        account.proxy_disabled_at = Some(chrono::Utc::now().timestamp());
-                        account.proxy_disabled_reason = Some(format!(
-                            "quota_protection: {}% (阈值: {}%)",
-                            min_percentage, threshold
-                        ));
+                        modules::proxy_disable::disable(
+                            &mut account,
+                            ProxyDisableCategory::QuotaProtection,
+                            format!("quota_protection: {}% (阈值: {}%)", min_percentage, threshold),
+                            chrono::Utc::now().timestamp(),
+                        );
                     }
                 } else {
                     // 检查是否需要自动恢复
-                    let is_protected = account.proxy_disabled && 
-                        account.proxy_disabled_reason.as_ref().map_or(false, |r| r.contains("quota_protection"));
+                    let is_protected = modules::proxy_disable::category_of(&account)
+                        == Some(ProxyDisableCategory::QuotaProtection);
                         
                     if is_protected {
                         crate::modules::logger::log_info(&format!(
                             "[Quota] 自动恢复: {} (监控模型最低额度已恢复至 {}%)",
                             account.email, min_percentage
                         ));
-                        account.proxy_disabled = false;
-                        account.proxy_disabled_reason = None;
-                        account.proxy_disabled_at = None;
+                        modules::proxy_disable::enable(&mut account);
                     }
                 }
             }
@@ -688,6 +1013,18 @@ pub fn update_account_quota(account_id: &str, quota: QuotaData) -> Result<(), St
     save_account(&account)
 }
 
+/// 重新授权时校验授权返回的邮箱与原账号一致 (忽略大小写)，防止误用其他 Google 账号覆盖凭据
+pub fn ensure_reauthorized_email(expected: &str, authorized: &str) -> Result<(), String> {
+    if expected.trim().eq_ignore_ascii_case(authorized.trim()) {
+        Ok(())
+    } else {
+        Err(format!(
+            "授权的 Google 账号 ({}) 与需要重新授权的账号 ({}) 不一致，已取消更新。请使用 {} 重新授权",
+            authorized, expected, expected
+        ))
+    }
+}
+
 /// 导出所有账号的 refresh_token
 #[allow(dead_code)]
 pub fn export_accounts() -> Result<Vec<(String, String)>, String> {
@@ -701,6 +1038,82 @@ pub fn export_accounts() -> Result<Vec<(String, String)>, String> {
     Ok(exports)
 }
 
+/// 闲置账号 (窗口期内既未被反代使用，也未切换到 IDE)
+#[derive(Debug, Clone, Serialize)]
+pub struct IdleAccount {
+    pub id: String,
+    pub email: String,
+    pub name: Option<String>,
+    pub disabled: bool,
+    pub proxy_disabled: bool,
+    /// 从未使用时为 None (前端显示为"从未")
+    pub last_proxy_used_at: Option<i64>,
+    pub last_switched_at: Option<i64>,
+}
+
+/// 找出最近 `days` 天内未被反代使用、也未切换到 IDE 的账号 (窗口期内新添加的账号不计入)
+/// 按最近活动时间升序排列，从未使用的排在最前
+pub fn find_idle_accounts(accounts: &[Account], days: u32, now: i64) -> Vec<IdleAccount> {
+    let cutoff = now - i64::from(days) * 86_400;
+    let mut idle: Vec<IdleAccount> = accounts
+        .iter()
+        .filter(|a| a.created_at < cutoff)
+        .filter(|a| a.last_proxy_used_at.max(a.last_switched_at).map_or(true, |t| t < cutoff))
+        .map(|a| IdleAccount {
+            id: a.id.clone(),
+            email: a.email.clone(),
+            name: a.name.clone(),
+            disabled: a.disabled,
+            proxy_disabled: a.proxy_disabled,
+            last_proxy_used_at: a.last_proxy_used_at,
+            last_switched_at: a.last_switched_at,
+        })
+        .collect();
+    idle.sort_by_key(|a| (a.last_proxy_used_at.max(a.last_switched_at), a.email.clone()));
+    idle
+}
+
+/// 账号 access_token 的过期情况
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenExpiry {
+    pub email: String,
+    pub expiry_ts: i64,
+    /// 已过期时为负数
+    pub seconds_until_expiry: i64,
+    /// 是否已进入号池的提前刷新窗口
+    pub needs_refresh_soon: bool,
+}
+
+/// 各账号的 token 过期快照，按剩余时间升序排列
+pub fn token_expiries(accounts: &[Account], now: i64) -> Vec<TokenExpiry> {
+    let lead = crate::proxy::token_manager::TOKEN_REFRESH_LEAD_SECS;
+    let mut expiries: Vec<TokenExpiry> = accounts
+        .iter()
+        .map(|a| {
+            let seconds_until_expiry = a.token.expiry_timestamp - now;
+            TokenExpiry {
+                email: a.email.clone(),
+                expiry_ts: a.token.expiry_timestamp,
+                seconds_until_expiry,
+                needs_refresh_soon: seconds_until_expiry <= lead,
+            }
+        })
+        .collect();
+    expiries.sort_by_key(|e| (e.seconds_until_expiry, e.email.clone()));
+    expiries
+}
+
+/// 配额查询使用的 Token：反代运行且账号在号池中时复用号池的 Token 与刷新逻辑，否则独立刷新
+/// 第二项表示是否由号池提供 (号池已自行记录刷新事件并落盘)
+async fn quota_token(account: &Account) -> (Result<TokenData, String>, bool) {
+    if let Some(manager) = crate::proxy::token_manager::active() {
+        if let Some(result) = manager.fresh_token_data(&account.email, &account.token).await {
+            return (result, true);
+        }
+    }
+    (modules::oauth::ensure_fresh_token(&account.token).await, false)
+}
+
 /// 带有重试机制的配额查询 (从 commands 移动到 modules 以便共享)
 pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppResult<QuotaData> {
     use crate::modules::oauth;
@@ -708,9 +1121,13 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
     use reqwest::StatusCode;
     
     // 1. 基于时间的检查 (Time-based check) - 先确保 Token 有效
-    let token = match oauth::ensure_fresh_token(&account.token).await {
+    let (token_result, from_pool) = quota_token(account).await;
+    let token = match token_result {
         Ok(t) => t,
         Err(e) => {
+            if !from_pool {
+                account_reliability::record(&account.id, ReliabilityEventKind::Refresh, false, Some(e.clone()));
+            }
             if e.contains("invalid_grant") {
                 modules::logger::log_error(&format!(
                     "Disabling account {} due to invalid_grant during token refresh (quota check)",
@@ -720,13 +1137,20 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
                 account.disabled_at = Some(chrono::Utc::now().timestamp());
                 account.disabled_reason = Some(format!("invalid_grant: {}", e));
                 let _ = save_account(account);
+                account_reliability::record(&account.id, ReliabilityEventKind::Disabled, false, account.disabled_reason.clone());
+                modules::account_notifier::notify_account_disabled(&account.id, &account.email, account.disabled_reason.as_deref().unwrap_or_default());
             }
             return Err(AppError::OAuth(e));
         }
     };
     
     if token.access_token != account.token.access_token {
-        modules::logger::log_info(&format!("基于时间的 Token 刷新: {}", account.email));
+        if from_pool {
+            modules::logger::log_info(&format!("从反代号池同步 Token: {}", account.email));
+        } else {
+            modules::logger::log_info(&format!("基于时间的 Token 刷新: {}", account.email));
+            account_reliability::record(&account.id, ReliabilityEventKind::Refresh, true, None);
+        }
         account.token = token.clone();
         
         // 重新获取用户名 (Token 刷新后顺便获取)
@@ -785,8 +1209,12 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
                 
                 // 强制刷新
                 let token_res = match oauth::refresh_access_token(&account.token.refresh_token).await {
-                    Ok(t) => t,
+                    Ok(t) => {
+                        account_reliability::record(&account.id, ReliabilityEventKind::ForcedRefresh, true, None);
+                        t
+                    }
                     Err(e) => {
+                        account_reliability::record(&account.id, ReliabilityEventKind::ForcedRefresh, false, Some(e.clone()));
                         if e.contains("invalid_grant") {
                             modules::logger::log_error(&format!(
                                 "Disabling account {} due to invalid_grant during forced refresh (quota check)",
@@ -796,6 +1224,8 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
                             account.disabled_at = Some(chrono::Utc::now().timestamp());
                             account.disabled_reason = Some(format!("invalid_grant: {}", e));
                             let _ = save_account(account);
+                            account_reliability::record(&account.id, ReliabilityEventKind::Disabled, false, account.disabled_reason.clone());
+                            modules::account_notifier::notify_account_disabled(&account.id, &account.email, account.disabled_reason.as_deref().unwrap_or_default());
                         }
                         return Err(AppError::OAuth(e));
                     }
@@ -823,6 +1253,15 @@ pub async fn fetch_quota_with_retry(account: &mut Account) -> crate::error::AppR
                 account.token = new_token.clone();
                 account.name = name.clone();
                 upsert_account(account.email.clone(), name, new_token.clone()).map_err(AppError::Account)?;
+
+                // 号池仍持有旧 Token，同步强制刷新后的结果
+                if from_pool {
+                    if let Some(manager) = crate::proxy::token_manager::active() {
+                        if let Err(e) = manager.reload_account(&account.id).await {
+                            modules::logger::log_warn(&format!("同步号池 Token 失败: {}", e));
+                        }
+                    }
+                }
                 
                 // 重试查询
                 let retry_result: crate::error::AppResult<(QuotaData, Option<String>)> = modules::fetch_quota(&new_token.access_token, &account.email).await;
@@ -859,11 +1298,22 @@ pub struct RefreshStats {
     pub total: usize,
     pub success: usize,
     pub failed: usize,
+    /// 因处于上游限流锁定期而推迟刷新的账号数
+    pub deferred: usize,
     pub details: Vec<String>,
 }
 
 /// 批量刷新所有账号配额的核心逻辑 (不依赖 Tauri 状态)
 pub async fn refresh_all_quotas_logic() -> Result<RefreshStats, String> {
+    refresh_all_quotas_with_lockouts(&std::collections::HashMap::new()).await
+}
+
+/// 批量刷新配额，跳过处于限流锁定期的账号，避免额外请求延长锁定
+///
+/// `lockouts` 为反代限流跟踪器的快照 (账号 ID 或邮箱 -> 剩余锁定秒数)
+pub async fn refresh_all_quotas_with_lockouts(
+    lockouts: &std::collections::HashMap<String, u64>,
+) -> Result<RefreshStats, String> {
     use futures::future::join_all;
     use std::sync::Arc;
     use tokio::sync::Semaphore;
@@ -878,10 +1328,26 @@ pub async fn refresh_all_quotas_logic() -> Result<RefreshStats, String> {
     let accounts = list_accounts()?;
 
     let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT));
+    let mut deferred_details = Vec::new();
 
     let tasks: Vec<_> = accounts
         .into_iter()
         .filter(|account| {
+            let locked = lockouts
+                .get(&account.id)
+                .or_else(|| lockouts.get(&account.email))
+                .copied();
+            if let Some(remaining) = locked {
+                crate::modules::logger::log_info(&format!(
+                    "  - Deferring {} (rate-limited, {}s remaining)",
+                    account.email, remaining
+                ));
+                deferred_details.push(format!(
+                    "Account {}: Deferred - rate-limited ({}s remaining)",
+                    account.email, remaining
+                ));
+                return false;
+            }
             if account.disabled {
                 crate::modules::logger::log_info(&format!("  - Skipping {} (Disabled)", account.email));
                 return false;
@@ -922,12 +1388,13 @@ pub async fn refresh_all_quotas_logic() -> Result<RefreshStats, String> {
         })
         .collect();
 
-    let total = tasks.len();
+    let deferred = deferred_details.len();
+    let total = tasks.len() + deferred;
     let results = join_all(tasks).await;
 
     let mut success = 0;
     let mut failed = 0;
-    let mut details = Vec::new();
+    let mut details = deferred_details;
 
     for result in results {
         match result {
@@ -941,9 +1408,10 @@ pub async fn refresh_all_quotas_logic() -> Result<RefreshStats, String> {
 
     let elapsed = start.elapsed();
     crate::modules::logger::log_info(&format!(
-        "批量刷新完成: {} 成功, {} 失败, 耗时: {}ms",
+        "批量刷新完成: {} 成功, {} 失败, {} 推迟 (限流中), 耗时: {}ms",
         success,
         failed,
+        deferred,
         elapsed.as_millis()
     ));
 
@@ -951,6 +1419,303 @@ pub async fn refresh_all_quotas_logic() -> Result<RefreshStats, String> {
         total,
         success,
         failed,
+        deferred,
         details,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_history_evicts_oldest_but_keeps_current_and_baseline() {
+        let profile = |n: u32| DeviceProfile {
+            machine_id: format!("m{}", n),
+            mac_machine_id: format!("mac{}", n),
+            dev_device_id: format!("d{}", n),
+            sqm_id: format!("s{}", n),
+        };
+        let version = |n: u32, is_current: bool| DeviceProfileVersion {
+            id: format!("v{}", n),
+            created_at: 1_700_000_000 + i64::from(n),
+            label: "generated".to_string(),
+            profile: profile(n),
+            is_current,
+        };
+        let baseline = profile(0);
+        // v0 为基线，v1 为当前 (两者都比其余条目旧)
+        let mut history = vec![version(0, false), version(1, true), version(2, false), version(3, false)];
+        history.push(version(4, false));
+
+        assert_eq!(prune_history(&mut history, 3, Some(&baseline)), 2);
+        let ids: Vec<&str> = history.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(ids, vec!["v0", "v1", "v4"]);
+
+        // 未超过上限或不限制时不移除
+        assert_eq!(prune_history(&mut history, 3, Some(&baseline)), 0);
+        history.push(version(5, false));
+        assert_eq!(prune_history(&mut history, 0, Some(&baseline)), 0);
+        assert_eq!(history.len(), 4);
+    }
+
+    #[test]
+    fn reauthorized_email_must_match_existing_account() {
+        assert!(ensure_reauthorized_email("User@Example.com", "user@example.com").is_ok());
+
+        let err = ensure_reauthorized_email("user@example.com", "other@example.com").unwrap_err();
+        assert!(err.contains("other@example.com"));
+        assert!(err.contains("user@example.com"));
+    }
+
+    #[test]
+    fn adjacent_account_wraps_and_skips_disabled() {
+        let accounts: Vec<(String, bool)> = [("a", true), ("b", false), ("c", true), ("d", false)]
+            .iter()
+            .map(|(id, disabled)| (id.to_string(), *disabled))
+            .collect();
+
+        // 从最后一个账号向后循环，跳过已禁用的 a
+        assert_eq!(adjacent_account_id(&accounts, Some("d"), true).as_deref(), Some("b"));
+        assert_eq!(adjacent_account_id(&accounts, Some("b"), true).as_deref(), Some("d"));
+        assert_eq!(adjacent_account_id(&accounts, Some("b"), false).as_deref(), Some("d"));
+        assert_eq!(adjacent_account_id(&accounts, None, true).as_deref(), Some("b"));
+        assert_eq!(adjacent_account_id(&accounts, None, false).as_deref(), Some("d"));
+
+        // 只剩当前账号可用时无可切换目标
+        let single = vec![("a".to_string(), false), ("b".to_string(), true)];
+        assert_eq!(adjacent_account_id(&single, Some("a"), true), None);
+    }
+
+    #[test]
+    fn sort_by_quota_descending_puts_missing_quota_last() {
+        let make = |id: &str, percentages: &[i32]| {
+            let token = TokenData::new("a".to_string(), "r".to_string(), 3600, None, None, None);
+            let mut account = Account::new(id.to_string(), format!("{}@example.com", id), token);
+            if !percentages.is_empty() {
+                let mut quota = QuotaData::new();
+                for (i, p) in percentages.iter().enumerate() {
+                    quota.add_model(format!("model-{}", i), *p, String::new());
+                }
+                account.quota = Some(quota);
+            }
+            account
+        };
+        let accounts = vec![
+            make("low", &[10, 30]),
+            make("unknown", &[]),
+            make("high", &[90, 100]),
+            make("mid", &[50]),
+            make("mid2", &[40, 60]),
+        ];
+
+        assert_eq!(
+            sorted_account_ids(&accounts, AccountSortMetric::Quota, true),
+            vec!["high", "mid", "mid2", "low", "unknown"]
+        );
+        assert_eq!(
+            sorted_account_ids(&accounts, AccountSortMetric::Quota, false),
+            vec!["low", "mid", "mid2", "high", "unknown"]
+        );
+        assert_eq!(
+            sorted_account_ids(&accounts, AccountSortMetric::Email, false),
+            vec!["high", "low", "mid", "mid2", "unknown"]
+        );
+        assert!(AccountSortMetric::parse("size").is_err());
+        assert_eq!(AccountSortMetric::parse("Last_Used").unwrap(), AccountSortMetric::LastUsed);
+    }
+
+    #[test]
+    fn idle_accounts_respect_window_and_never_used() {
+        let now = 1_700_000_000;
+        let day = 86_400;
+        let make = |id: &str, proxy: Option<i64>, switched: Option<i64>| {
+            let token = TokenData::new("a".to_string(), "r".to_string(), 3600, None, None, None);
+            let mut account = Account::new(id.to_string(), format!("{}@example.com", id), token);
+            account.created_at = now - 90 * day;
+            account.last_used = now; // last_used 不参与判断
+            account.last_proxy_used_at = proxy;
+            account.last_switched_at = switched;
+            account
+        };
+        let mut fresh = make("fresh", None, None);
+        fresh.created_at = now - day;
+        let accounts = vec![
+            make("never", None, None),
+            make("proxied", Some(now - 2 * day), None),
+            make("switched", None, Some(now - 3 * day)),
+            make("stale", Some(now - 40 * day), Some(now - 35 * day)),
+            fresh,
+        ];
+
+        let idle = find_idle_accounts(&accounts, 30, now);
+        let ids: Vec<&str> = idle.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, vec!["never", "stale"]);
+        assert_eq!(idle[0].last_proxy_used_at, None);
+        assert_eq!(idle[1].last_proxy_used_at, Some(now - 40 * day));
+
+        // 窗口缩短后最近使用过的账号也算闲置
+        assert_eq!(find_idle_accounts(&accounts, 1, now).len(), 4);
+    }
+
+    #[test]
+    fn test_token_expiries_flag_refresh_window() {
+        let now = 1_700_000_000;
+        let make = |id: &str, expires_in: i64| {
+            let mut token = TokenData::new("a".to_string(), "r".to_string(), 3600, None, None, None);
+            token.expiry_timestamp = now + expires_in;
+            Account::new(id.to_string(), format!("{}@example.com", id), token)
+        };
+        let accounts = vec![make("later", 2 * 3600), make("soon", 200)];
+
+        let expiries = token_expiries(&accounts, now);
+        assert_eq!(expiries[0].email, "soon@example.com");
+        assert_eq!(expiries[0].seconds_until_expiry, 200);
+        assert!(expiries[0].needs_refresh_soon);
+        assert_eq!(expiries[1].email, "later@example.com");
+        assert_eq!(expiries[1].expiry_ts, now + 7200);
+        assert!(!expiries[1].needs_refresh_soon);
+    }
+
+    #[test]
+    fn accounts_without_usage_fields_load_as_never_used() {
+        let json = serde_json::json!({
+            "id": "legacy",
+            "email": "legacy@example.com",
+            "name": null,
+            "token": {
+                "access_token": "a",
+                "refresh_token": "r",
+                "expires_in": 3600,
+                "expiry_timestamp": 0,
+                "token_type": "Bearer",
+                "email": null
+            },
+            "quota": null,
+            "created_at": 1,
+            "last_used": 1
+        });
+        let account: Account = serde_json::from_value(json).unwrap();
+        assert_eq!(account.last_proxy_used_at, None);
+        assert_eq!(account.last_switched_at, None);
+    }
+
+    #[test]
+    fn raw_account_write_refuses_newer_schema() {
+        let dir = std::env::temp_dir().join(format!("ag_raw_write_test_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("acc1.json");
+        let newer = serde_json::json!({
+            "id": "acc1",
+            "schema_version": crate::models::account::ACCOUNT_SCHEMA_VERSION + 1
+        });
+        std::fs::write(&path, newer.to_string()).unwrap();
+
+        let mut updated = newer.clone();
+        updated["proxy_disabled"] = serde_json::Value::Bool(true);
+        let err = write_account_json(&path, &updated).unwrap_err();
+        assert!(err.contains("更新版本"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), newer.to_string());
+
+        let current = serde_json::json!({
+            "id": "acc1",
+            "schema_version": crate::models::account::ACCOUNT_SCHEMA_VERSION
+        });
+        write_account_json(&path, &current).unwrap();
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn quota_token_reuses_running_proxy_token() {
+        let dir = std::env::temp_dir().join(format!("ag_quota_token_test_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("accounts")).unwrap();
+        let expiry = chrono::Utc::now().timestamp() + 3600;
+        let file = serde_json::json!({
+            "id": "acc1",
+            "email": "acc1@example.com",
+            "token": {
+                "access_token": "pool-access",
+                "refresh_token": "refresh",
+                "expires_in": 3600,
+                "expiry_timestamp": expiry
+            }
+        });
+        std::fs::write(dir.join("accounts").join("acc1.json"), file.to_string()).unwrap();
+
+        let manager = std::sync::Arc::new(crate::proxy::TokenManager::new(dir.clone()));
+        assert_eq!(manager.load_accounts().await.unwrap(), 1);
+        crate::proxy::token_manager::set_active(&manager);
+
+        // 本地副本已过期：独立路径会发起刷新，反代运行时应直接复用号池中的有效 Token
+        let mut token = TokenData::new("stale-access".to_string(), "refresh".to_string(), 3600, None, None, None);
+        token.expiry_timestamp = 1_000;
+        let account = Account::new("acc1".to_string(), "acc1@example.com".to_string(), token);
+        let (result, from_pool) = quota_token(&account).await;
+        crate::proxy::token_manager::clear_active();
+
+        assert!(from_pool);
+        let token = result.unwrap();
+        assert_eq!(token.access_token, "pool-access");
+        assert_eq!(token.expiry_timestamp, expiry);
+        assert!(account_reliability::load_history(&dir, "acc1").is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn slow_ide_close_does_not_block_account_listing() {
+        use crate::modules::process::IdeProcess;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::time::{Duration, Instant};
+
+        // 模拟迟迟不退出的 IDE：关闭等待一段时间后失败，切换在写入设备/数据库之前中止
+        struct SlowIde {
+            closing: AtomicBool,
+        }
+        impl IdeProcess for SlowIde {
+            fn close_if_running(&self, _timeout_secs: u64) -> Result<(), String> {
+                self.closing.store(true, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(1500));
+                Err("模拟: IDE 未退出".to_string())
+            }
+            fn start(&self) -> Result<(), String> {
+                Ok(())
+            }
+        }
+
+        let dir = std::env::temp_dir().join(format!("ag_switch_slow_close_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let _guard = use_test_data_dir(&dir);
+        let token = |refresh: &str| TokenData::new("access".to_string(), refresh.to_string(), 3600, None, None, None);
+        let current = add_account("current@example.com".to_string(), None, token("refresh-a")).unwrap();
+        let account = add_account("slow@example.com".to_string(), None, token("refresh-b")).unwrap();
+
+        // 单线程运行时：若切换在运行时线程上阻塞等待，本任务在关闭结束前无法继续执行
+        let ide = std::sync::Arc::new(SlowIde { closing: AtomicBool::new(false) });
+        let switch = tokio::spawn({
+            let ide = ide.clone();
+            let id = account.id.clone();
+            async move { switch_account_with(&id, ide).await }
+        });
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !ide.closing.load(Ordering::SeqCst) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("switch never reached the IDE close");
+
+        let started = Instant::now();
+        let listed = list_accounts().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(listed.len(), 2);
+        assert!(!switch.is_finished(), "list_accounts waited for the IDE close");
+
+        let err = switch.await.unwrap().unwrap_err();
+        assert!(err.contains("模拟"));
+        assert_eq!(load_account_index().unwrap().current_account_id, Some(current.id));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
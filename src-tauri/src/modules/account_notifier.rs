@@ -0,0 +1,69 @@
+// 账号状态通知：账号因 invalid_grant 被自动禁用、或长期手动停用时，向前端发送事件并弹出系统通知
+use serde::Serialize;
+use std::sync::OnceLock;
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_notification::NotificationExt;
+
+pub const ACCOUNT_DISABLED_EVENT: &str = "account://disabled";
+
+static APP_HANDLE: OnceLock<AppHandle> = OnceLock::new();
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountDisabledEvent {
+    pub account_id: String,
+    pub email: String,
+    pub reason: String,
+}
+
+/// 在 setup 阶段注册 AppHandle；未注册时 (如单元测试) 通知为空操作
+pub fn init(app_handle: AppHandle) {
+    let _ = APP_HANDLE.set(app_handle);
+}
+
+/// 通知账号已被禁用。前端收到事件后提供「重新授权」入口 (调用 reauthorize_account)
+pub fn notify_account_disabled(account_id: &str, email: &str, reason: &str) {
+    let Some(app) = APP_HANDLE.get() else {
+        return;
+    };
+
+    let payload = AccountDisabledEvent {
+        account_id: account_id.to_string(),
+        email: email.to_string(),
+        reason: reason.to_string(),
+    };
+    if let Err(e) = app.emit(ACCOUNT_DISABLED_EVENT, &payload) {
+        crate::modules::logger::log_warn(&format!("发送账号禁用事件失败: {}", e));
+    }
+
+    // 桌面端通知不支持操作按钮，点击通知会唤起主窗口，由界面上的重新授权按钮完成操作
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title("账号已被禁用")
+        .body(format!("{} 授权已失效，请在账号列表中重新授权", email))
+        .show()
+    {
+        crate::modules::logger::log_warn(&format!("显示系统通知失败: {}", e));
+    }
+}
+
+/// 提醒长期手动停用的账号需要复查。只发送提醒，从不自动恢复
+pub fn notify_stale_disables(stale: &[crate::modules::proxy_disable::StaleDisable]) {
+    let Some(app) = APP_HANDLE.get() else {
+        return;
+    };
+
+    if let Err(e) = app.emit(crate::modules::proxy_disable::STALE_DISABLES_EVENT, stale) {
+        crate::modules::logger::log_warn(&format!("发送长期停用提醒事件失败: {}", e));
+    }
+
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title("账号长期停用")
+        .body(format!("有 {} 个账号已手动停用较长时间，请在账号列表中复查", stale.len()))
+        .show()
+    {
+        crate::modules::logger::log_warn(&format!("显示系统通知失败: {}", e));
+    }
+}
@@ -0,0 +1,250 @@
+// 账号可靠性记录
+// 按账号记录最近的 token 刷新、401 强制刷新与禁用事件，存放在独立的 sidecar 文件中 (不写入账号 JSON)，
+// 并据此计算 7 天刷新成功率、平均故障间隔与简单的可靠性等级。
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// sidecar 文件目录 (位于数据目录下，与 accounts/ 平级，避免被账号扫描误读)
+const RELIABILITY_DIR: &str = "reliability";
+
+/// 每个账号保留的事件数
+pub const MAX_EVENTS: usize = 100;
+
+/// 成功率的统计窗口
+const SCORE_WINDOW_SECS: i64 = 7 * 24 * 3600;
+
+/// 每个账号一把锁，串行化同一账号 sidecar 文件的读改写
+static FILE_LOCKS: Lazy<DashMap<String, Arc<Mutex<()>>>> = Lazy::new(DashMap::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReliabilityEventKind {
+    /// 常规 token 刷新 (到期前刷新)
+    Refresh,
+    /// 上游返回 401 后的强制刷新
+    ForcedRefresh,
+    /// 账号被自动禁用 (如 invalid_grant)
+    Disabled,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReliabilityEvent {
+    pub timestamp: i64,
+    pub kind: ReliabilityEventKind,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl ReliabilityEvent {
+    pub fn new(kind: ReliabilityEventKind, success: bool, detail: Option<String>) -> Self {
+        Self {
+            timestamp: chrono::Utc::now().timestamp(),
+            kind,
+            success,
+            // 错误信息只保留开头，避免 sidecar 膨胀
+            detail: detail.map(|d| d.chars().take(300).collect()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReliabilityReport {
+    pub account_id: String,
+    pub events: Vec<ReliabilityEvent>,
+    /// 最近 7 天刷新 (含强制刷新) 的成功率，无刷新记录时为 None
+    pub refresh_success_rate_7d: Option<f64>,
+    /// 平均故障间隔 (秒)，少于两次故障时为 None
+    pub mean_time_between_failures_secs: Option<u64>,
+    pub grade: String,
+}
+
+fn history_path(data_dir: &Path, account_id: &str) -> Result<PathBuf, String> {
+    crate::modules::account::validate_account_id(account_id)?;
+    Ok(data_dir.join(RELIABILITY_DIR).join(format!("{}.json", account_id)))
+}
+
+fn account_lock(account_id: &str) -> Arc<Mutex<()>> {
+    FILE_LOCKS
+        .entry(account_id.to_string())
+        .or_insert_with(|| Arc::new(Mutex::new(())))
+        .clone()
+}
+
+fn read_events(path: &Path) -> Vec<ReliabilityEvent> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+/// 读取账号的事件历史 (按时间先后)
+pub fn load_history(data_dir: &Path, account_id: &str) -> Vec<ReliabilityEvent> {
+    let Ok(path) = history_path(data_dir, account_id) else {
+        return Vec::new();
+    };
+    let lock = account_lock(account_id);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+    read_events(&path)
+}
+
+/// 追加一条事件，并裁剪到 MAX_EVENTS
+pub fn append_event(data_dir: &Path, account_id: &str, event: ReliabilityEvent) -> Result<(), String> {
+    crate::modules::instance_lock::ensure_writable()?;
+    let path = history_path(data_dir, account_id)?;
+    let lock = account_lock(account_id);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut events = read_events(&path);
+    events.push(event);
+    if events.len() > MAX_EVENTS {
+        let excess = events.len() - MAX_EVENTS;
+        events.drain(..excess);
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("创建目录失败: {}", e))?;
+    }
+    let content = serde_json::to_string(&events).map_err(|e| format!("序列化失败: {}", e))?;
+    std::fs::write(&path, content).map_err(|e| format!("写入可靠性记录失败: {}", e))
+}
+
+/// 记录事件 (使用默认数据目录)，失败只记日志，不影响调用方
+pub fn record(account_id: &str, kind: ReliabilityEventKind, success: bool, detail: Option<String>) {
+    match crate::modules::account::get_data_dir() {
+        Ok(data_dir) => record_in(&data_dir, account_id, kind, success, detail),
+        Err(e) => crate::modules::logger::log_warn(&format!("记录账号可靠性事件失败: {}", e)),
+    }
+}
+
+/// 在指定数据目录下记录事件，失败只记日志
+pub fn record_in(data_dir: &Path, account_id: &str, kind: ReliabilityEventKind, success: bool, detail: Option<String>) {
+    if let Err(e) = append_event(data_dir, account_id, ReliabilityEvent::new(kind, success, detail)) {
+        crate::modules::logger::log_warn(&format!("记录账号 {} 可靠性事件失败: {}", account_id, e));
+    }
+}
+
+/// 删除账号的可靠性记录
+pub fn remove_history(data_dir: &Path, account_id: &str) {
+    let Ok(path) = history_path(data_dir, account_id) else {
+        return;
+    };
+    let lock = account_lock(account_id);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = std::fs::remove_file(path);
+}
+
+fn refresh_success_rate(events: &[ReliabilityEvent], now: i64) -> Option<f64> {
+    let refreshes: Vec<&ReliabilityEvent> = events
+        .iter()
+        .filter(|e| e.kind != ReliabilityEventKind::Disabled && now - e.timestamp <= SCORE_WINDOW_SECS)
+        .collect();
+    if refreshes.is_empty() {
+        return None;
+    }
+    Some(refreshes.iter().filter(|e| e.success).count() as f64 / refreshes.len() as f64)
+}
+
+fn mean_time_between_failures(events: &[ReliabilityEvent]) -> Option<u64> {
+    let failures: Vec<i64> = events.iter().filter(|e| !e.success).map(|e| e.timestamp).collect();
+    if failures.len() < 2 {
+        return None;
+    }
+    let span = failures.last()? - failures.first()?;
+    Some((span.max(0) / (failures.len() as i64 - 1)) as u64)
+}
+
+/// 可靠性等级：7 天内被禁用为 F，否则按刷新成功率 A (≥99%) / B (≥95%) / C (≥80%) / D；无记录为 N/A
+fn grade(events: &[ReliabilityEvent], success_rate: Option<f64>, now: i64) -> &'static str {
+    let disabled_recently = events
+        .iter()
+        .any(|e| e.kind == ReliabilityEventKind::Disabled && now - e.timestamp <= SCORE_WINDOW_SECS);
+    if disabled_recently {
+        return "F";
+    }
+    match success_rate {
+        None => "N/A",
+        Some(r) if r >= 0.99 => "A",
+        Some(r) if r >= 0.95 => "B",
+        Some(r) if r >= 0.80 => "C",
+        Some(_) => "D",
+    }
+}
+
+pub fn build_report(account_id: &str, events: Vec<ReliabilityEvent>, now: i64) -> ReliabilityReport {
+    let refresh_success_rate_7d = refresh_success_rate(&events, now);
+    ReliabilityReport {
+        account_id: account_id.to_string(),
+        mean_time_between_failures_secs: mean_time_between_failures(&events),
+        grade: grade(&events, refresh_success_rate_7d, now).to_string(),
+        refresh_success_rate_7d,
+        events,
+    }
+}
+
+/// 账号可靠性报告 (使用默认数据目录)
+pub fn get_report(account_id: &str) -> Result<ReliabilityReport, String> {
+    crate::modules::account::validate_account_id(account_id)?;
+    let data_dir = crate::modules::account::get_data_dir()?;
+    let events = load_history(&data_dir, account_id);
+    Ok(build_report(account_id, events, chrono::Utc::now().timestamp()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(timestamp: i64, kind: ReliabilityEventKind, success: bool) -> ReliabilityEvent {
+        ReliabilityEvent { timestamp, kind, success, detail: None }
+    }
+
+    #[test]
+    fn test_append_prunes_to_cap() {
+        let dir = std::env::temp_dir().join(format!("ag_reliability_test_{}", uuid::Uuid::new_v4()));
+        for i in 0..(MAX_EVENTS + 5) {
+            append_event(&dir, "acc", event(i as i64, ReliabilityEventKind::Refresh, true)).unwrap();
+        }
+        let events = load_history(&dir, "acc");
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert_eq!(events[0].timestamp, 5);
+
+        remove_history(&dir, "acc");
+        assert!(load_history(&dir, "acc").is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_account_id_cannot_escape_sidecar_dir() {
+        let dir = std::env::temp_dir().join(format!("ag_reliability_test_{}", uuid::Uuid::new_v4()));
+        for bad in ["../escape", "..\\escape", "a/b", ""] {
+            assert!(append_event(&dir, bad, event(1, ReliabilityEventKind::Refresh, true)).is_err());
+            assert!(get_report(bad).is_err());
+            assert!(load_history(&dir, bad).is_empty());
+        }
+        assert!(!dir.join("escape.json").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_scores_and_grade() {
+        let now = 1_700_000_000;
+        let old = now - SCORE_WINDOW_SECS - 10;
+        let mut events = vec![event(old, ReliabilityEventKind::Refresh, false)];
+        events.extend((0..9).map(|i| event(now - 1000 + i, ReliabilityEventKind::Refresh, true)));
+        events.push(event(now - 100, ReliabilityEventKind::ForcedRefresh, false));
+
+        let report = build_report("acc", events.clone(), now);
+        // 窗口外的失败不计入成功率，但计入故障间隔
+        assert_eq!(report.refresh_success_rate_7d, Some(0.9));
+        assert_eq!(report.mean_time_between_failures_secs, Some((now - 100 - old) as u64));
+        assert_eq!(report.grade, "C");
+
+        events.push(event(now - 50, ReliabilityEventKind::Disabled, false));
+        assert_eq!(build_report("acc", events, now).grade, "F");
+        assert_eq!(build_report("acc", Vec::new(), now).grade, "N/A");
+    }
+}
@@ -0,0 +1,233 @@
+// 账号报表导出 (CSV / JSON)
+// 仅读取账号文件，不包含任何 token；用于在表格中按周跟踪账号健康度。
+
+use crate::models::Account;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// 固定列 (模型列按名称排序追加在其后)
+const BASE_COLUMNS: [&str; 10] = [
+    "email",
+    "name",
+    "label",
+    "tier",
+    "disabled",
+    "disabled_reason",
+    "proxy_disabled",
+    "proxy_disabled_reason",
+    "last_used",
+    "created_at",
+];
+
+/// 每个模型输出的列
+const MODEL_COLUMNS: [&str; 2] = ["percentage", "reset_time"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+}
+
+impl ReportFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            other => Err(format!("不支持的导出格式: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ModelQuotaRow {
+    percentage: i32,
+    reset_time: String,
+}
+
+#[derive(Debug, Serialize)]
+struct AccountReportRow {
+    email: String,
+    name: Option<String>,
+    label: Option<String>,
+    tier: Option<String>,
+    disabled: bool,
+    disabled_reason: Option<String>,
+    proxy_disabled: bool,
+    proxy_disabled_reason: Option<String>,
+    last_used: String,
+    created_at: String,
+    /// 模型名 -> 配额 (仅包含该账号实际拥有的模型)
+    models: std::collections::BTreeMap<String, ModelQuotaRow>,
+}
+
+fn format_timestamp(ts: i64) -> String {
+    chrono::DateTime::from_timestamp(ts, 0)
+        .map(|dt| dt.to_rfc3339())
+        .unwrap_or_default()
+}
+
+fn to_row(account: &Account) -> AccountReportRow {
+    let models = account
+        .quota
+        .as_ref()
+        .map(|q| {
+            q.models
+                .iter()
+                .map(|m| {
+                    (
+                        m.name.clone(),
+                        ModelQuotaRow { percentage: m.percentage, reset_time: m.reset_time.clone() },
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    AccountReportRow {
+        email: account.email.clone(),
+        name: account.name.clone(),
+        label: account.label.clone(),
+        tier: account.quota.as_ref().and_then(|q| q.subscription_tier.clone()),
+        disabled: account.disabled,
+        disabled_reason: account.disabled_reason.clone(),
+        proxy_disabled: account.proxy_disabled,
+        proxy_disabled_reason: account.proxy_disabled_reason.clone(),
+        last_used: format_timestamp(account.last_used),
+        created_at: format_timestamp(account.created_at),
+        models,
+    }
+}
+
+/// 所有账号出现过的模型 (按名称排序，保证列顺序稳定)
+fn model_union(rows: &[AccountReportRow]) -> Vec<String> {
+    rows.iter()
+        .flat_map(|r| r.models.keys().cloned())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// CSV 字段转义 (包含逗号、引号或换行时加引号，引号加倍)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn build_csv(accounts: &[Account]) -> String {
+    let rows: Vec<AccountReportRow> = accounts.iter().map(to_row).collect();
+    let models = model_union(&rows);
+
+    let mut header: Vec<String> = BASE_COLUMNS.iter().map(|c| c.to_string()).collect();
+    for model in &models {
+        for col in MODEL_COLUMNS {
+            header.push(format!("{} {}", model, col));
+        }
+    }
+
+    let mut lines = vec![header.iter().map(|h| csv_field(h)).collect::<Vec<_>>().join(",")];
+    for row in &rows {
+        let mut fields = vec![
+            row.email.clone(),
+            row.name.clone().unwrap_or_default(),
+            row.label.clone().unwrap_or_default(),
+            row.tier.clone().unwrap_or_default(),
+            row.disabled.to_string(),
+            row.disabled_reason.clone().unwrap_or_default(),
+            row.proxy_disabled.to_string(),
+            row.proxy_disabled_reason.clone().unwrap_or_default(),
+            row.last_used.clone(),
+            row.created_at.clone(),
+        ];
+        for model in &models {
+            match row.models.get(model) {
+                Some(q) => {
+                    fields.push(q.percentage.to_string());
+                    fields.push(q.reset_time.clone());
+                }
+                None => fields.extend([String::new(), String::new()]),
+            }
+        }
+        lines.push(fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(","));
+    }
+
+    let mut csv = lines.join("\r\n");
+    csv.push_str("\r\n");
+    csv
+}
+
+pub fn build_json(accounts: &[Account]) -> Result<String, String> {
+    let rows: Vec<AccountReportRow> = accounts.iter().map(to_row).collect();
+    serde_json::to_string_pretty(&rows).map_err(|e| format!("序列化报表失败: {}", e))
+}
+
+/// 导出账号报表到指定文件，返回导出的账号数
+pub fn export_accounts_report(path: &Path, format: ReportFormat) -> Result<usize, String> {
+    let accounts = crate::modules::account::list_accounts()?;
+    let content = match format {
+        ReportFormat::Csv => build_csv(&accounts),
+        ReportFormat::Json => build_json(&accounts)?,
+    };
+    std::fs::write(path, content).map_err(|e| format!("写入报表失败: {}", e))?;
+    Ok(accounts.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{QuotaData, TokenData};
+
+    fn account(email: &str, models: &[(&str, i32, &str)]) -> Account {
+        let token = TokenData::new("secret-access".to_string(), "secret-refresh".to_string(), 3600, None, None, None);
+        let mut account = Account::new(format!("id-{}", email), email.to_string(), token);
+        account.created_at = 1_700_000_000;
+        account.last_used = 1_700_086_400;
+        let mut quota = QuotaData::new();
+        quota.subscription_tier = Some("PRO".to_string());
+        for (name, pct, reset) in models {
+            quota.add_model(name.to_string(), *pct, reset.to_string());
+        }
+        account.quota = Some(quota);
+        account
+    }
+
+    #[test]
+    fn test_csv_layout_is_stable() {
+        let mut a = account("a@example.com", &[("gemini-3-pro-high", 80, "2025-01-01T00:00:00Z")]);
+        a.label = Some("team, \"alpha\"".to_string());
+        let mut b = account("b@example.com", &[("claude-sonnet-4-5", 10, "2025-01-02T00:00:00Z")]);
+        b.disabled = true;
+        b.disabled_reason = Some("invalid_grant: token revoked".to_string());
+
+        let csv = build_csv(&[a, b]);
+        let lines: Vec<&str> = csv.split("\r\n").collect();
+
+        assert_eq!(
+            lines[0],
+            "email,name,label,tier,disabled,disabled_reason,proxy_disabled,proxy_disabled_reason,last_used,created_at,\
+             claude-sonnet-4-5 percentage,claude-sonnet-4-5 reset_time,gemini-3-pro-high percentage,gemini-3-pro-high reset_time"
+        );
+        assert_eq!(
+            lines[1],
+            "a@example.com,,\"team, \"\"alpha\"\"\",PRO,false,,false,,2023-11-15T22:13:20+00:00,2023-11-14T22:13:20+00:00,,,80,2025-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            lines[2],
+            "b@example.com,,,PRO,true,invalid_grant: token revoked,false,,2023-11-15T22:13:20+00:00,2023-11-14T22:13:20+00:00,10,2025-01-02T00:00:00Z,,"
+        );
+        assert!(!csv.contains("secret"));
+    }
+
+    #[test]
+    fn test_json_report_omits_tokens() {
+        let json = build_json(&[account("a@example.com", &[("gemini-3-pro-high", 80, "")])]).unwrap();
+        assert!(!json.contains("secret"));
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value[0]["models"]["gemini-3-pro-high"]["percentage"], 80);
+        assert_eq!(ReportFormat::parse("CSV").unwrap(), ReportFormat::Csv);
+        assert!(ReportFormat::parse("xlsx").is_err());
+    }
+}
@@ -0,0 +1,183 @@
+// 账号文件 schema 版本管理
+// 账号 JSON 会被不同版本的应用读写 (例如用户回滚到旧版本)。当前版本不认识的字段由 Account::extra 保留并写回；
+// schema_version 高于当前应用支持版本的文件只读，避免按较窄的结构重写后丢失字段。
+
+use serde_json::{Map, Value};
+
+use crate::models::account::ACCOUNT_SCHEMA_VERSION;
+
+/// 只由 list_accounts 填充的运行时字段，不应出现在账号文件中
+const TRANSIENT_FIELDS: [&str; 2] = ["snooze_remaining_seconds", "reliability_grade"];
+
+/// 升级函数：UPGRADES[n] 把 v{n} 的账号 JSON 升级到 v{n+1}
+const UPGRADES: [fn(&mut Map<String, Value>); ACCOUNT_SCHEMA_VERSION as usize] = [upgrade_v0_to_v1, upgrade_v1_to_v2];
+
+/// v0 (无版本号)：早期版本可能把运行时字段写入了文件
+fn upgrade_v0_to_v1(account: &mut Map<String, Value>) {
+    strip_transient_fields(account);
+}
+
+/// v1 -> v2：根据原因文本补全反代停用类别
+fn upgrade_v1_to_v2(account: &mut Map<String, Value>) {
+    let has_category = account.get("proxy_disabled_category").is_some_and(|v| !v.is_null());
+    if has_category {
+        return;
+    }
+    if let Some(category) = crate::modules::proxy_disable::category_of_json(&Value::Object(account.clone())) {
+        account.insert("proxy_disabled_category".to_string(), serde_json::to_value(category).unwrap_or(Value::Null));
+    }
+}
+
+/// 移除运行时字段
+pub fn strip_transient_fields(account: &mut Map<String, Value>) {
+    for field in TRANSIENT_FIELDS {
+        account.remove(field);
+    }
+}
+
+/// 账号 JSON 的 schema 版本 (缺失视为 0)
+pub fn schema_version(account: &Value) -> u32 {
+    account
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .map(|v| u32::try_from(v).unwrap_or(u32::MAX))
+        .unwrap_or(0)
+}
+
+/// 将账号 JSON 逐版本升级到当前版本；版本更新的文件保持原样 (只读加载)
+pub fn upgrade(account: &mut Value) -> Result<(), String> {
+    let mut version = schema_version(account);
+    if version >= ACCOUNT_SCHEMA_VERSION {
+        return Ok(());
+    }
+    let fields = account.as_object_mut().ok_or("账号数据不是 JSON 对象")?;
+    while version < ACCOUNT_SCHEMA_VERSION {
+        UPGRADES[version as usize](fields);
+        version += 1;
+    }
+    fields.insert("schema_version".to_string(), Value::from(version));
+    Ok(())
+}
+
+/// 拒绝写入由更新版本应用创建的账号
+pub fn ensure_version_writable(version: u32) -> Result<(), String> {
+    if version > ACCOUNT_SCHEMA_VERSION {
+        return Err(format!(
+            "账号文件由更新版本的应用写入 (schema v{}，当前支持 v{})，为避免丢失数据已拒绝修改，请升级应用",
+            version, ACCOUNT_SCHEMA_VERSION
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::account::{load_account_from_path, save_account_to_path};
+    use serde_json::json;
+    use std::path::Path;
+
+    fn fixture(schema_version: Option<u32>) -> Value {
+        let mut account = json!({
+            "id": "future",
+            "email": "future@example.com",
+            "name": null,
+            "token": {
+                "access_token": "a",
+                "refresh_token": "r",
+                "expires_in": 3600,
+                "expiry_timestamp": 0,
+                "token_type": "Bearer",
+                "email": null
+            },
+            "quota": null,
+            "created_at": 1,
+            "last_used": 1,
+            "quota_override": { "claude": 80, "gemini": null },
+            "tags": ["team-a", "backup"]
+        });
+        if let Some(v) = schema_version {
+            account["schema_version"] = json!(v);
+        }
+        account
+    }
+
+    fn temp_file(account: &Value) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("ag_account_schema_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("future.json");
+        std::fs::write(&path, serde_json::to_string_pretty(account).unwrap()).unwrap();
+        path
+    }
+
+    fn read(path: &Path) -> Value {
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_unknown_fields_survive_load_modify_save() {
+        let path = temp_file(&fixture(Some(ACCOUNT_SCHEMA_VERSION)));
+
+        let mut account = load_account_from_path(&path).unwrap();
+        account.label = Some("renamed".to_string());
+        save_account_to_path(&account, &path).unwrap();
+
+        let saved = read(&path);
+        let original = fixture(None);
+        assert_eq!(saved["label"], "renamed");
+        assert_eq!(saved["quota_override"], original["quota_override"]);
+        assert_eq!(saved["tags"], original["tags"]);
+        assert_eq!(saved["schema_version"], ACCOUNT_SCHEMA_VERSION);
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_newer_schema_is_read_only() {
+        let newer = fixture(Some(ACCOUNT_SCHEMA_VERSION + 1));
+        let path = temp_file(&newer);
+
+        let mut account = load_account_from_path(&path).unwrap();
+        assert_eq!(account.email, "future@example.com");
+        account.label = Some("renamed".to_string());
+        let err = save_account_to_path(&account, &path).unwrap_err();
+        assert!(err.contains(&format!("v{}", ACCOUNT_SCHEMA_VERSION + 1)));
+        assert_eq!(read(&path), newer);
+
+        // 即使用当前版本的结构覆盖同一文件也会被拒绝
+        account.schema_version = ACCOUNT_SCHEMA_VERSION;
+        assert!(save_account_to_path(&account, &path).is_err());
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_unversioned_file_is_upgraded() {
+        let mut legacy = fixture(None);
+        legacy["reliability_grade"] = json!("B");
+        upgrade(&mut legacy).unwrap();
+        assert_eq!(schema_version(&legacy), ACCOUNT_SCHEMA_VERSION);
+        assert!(legacy.get("reliability_grade").is_none());
+        assert_eq!(legacy["tags"], json!(["team-a", "backup"]));
+    }
+
+    #[test]
+    fn test_upgrade_categorizes_existing_proxy_disables() {
+        let mut legacy = fixture(Some(1));
+        legacy["proxy_disabled"] = json!(true);
+        legacy["proxy_disabled_reason"] = json!("quota_protection: 3/100 (阈值: 10)");
+        upgrade(&mut legacy).unwrap();
+        assert_eq!(legacy["proxy_disabled_category"], "quota_protection");
+
+        let mut manual = fixture(Some(1));
+        manual["proxy_disabled"] = json!(true);
+        manual["proxy_disabled_reason"] = json!("testing");
+        upgrade(&mut manual).unwrap();
+        assert_eq!(manual["proxy_disabled_category"], "manual");
+
+        // 未停用的账号不写入类别
+        let mut enabled = fixture(Some(1));
+        upgrade(&mut enabled).unwrap();
+        assert!(enabled.get("proxy_disabled_category").is_none());
+    }
+}
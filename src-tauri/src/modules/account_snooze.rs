@@ -0,0 +1,140 @@
+// 账号临时停用 (snooze)
+// 在 proxy_disabled 的基础上记录 proxy_disabled_until，到期后由反代服务自动恢复。
+// 直接操作账号 JSON 的顶层字段，不触碰 (可能加密的) token。
+
+use serde_json::Value;
+use std::path::Path;
+
+use crate::models::ProxyDisableCategory;
+use crate::modules::proxy_disable;
+
+/// 停用到期自动恢复时发出的事件 (payload 为账号 ID)
+pub const SNOOZE_EXPIRED_EVENT: &str = "account://snooze_expired";
+
+/// 仍在生效的停用截止时间
+fn active_until(account: &Value, now: i64) -> Option<i64> {
+    let disabled = account.get("proxy_disabled").and_then(|v| v.as_bool()).unwrap_or(false);
+    account
+        .get("proxy_disabled_until")
+        .and_then(|v| v.as_i64())
+        .filter(|until| disabled && *until > now)
+}
+
+/// 剩余停用秒数，未停用或已到期时返回 None
+pub fn remaining_seconds(account: &Value, now: i64) -> Option<i64> {
+    active_until(account, now).map(|until| until - now)
+}
+
+/// 停用 `minutes` 分钟；已在停用中时在原截止时间上延长。返回新的截止时间
+pub fn apply_snooze(account: &mut Value, minutes: u64, reason: Option<String>, now: i64) -> i64 {
+    let current = active_until(account, now);
+    let until = current.unwrap_or(now) + minutes as i64 * 60;
+
+    // 延长停用时保留最初的原因，除非给出了新原因
+    let reason = reason.filter(|r| !r.trim().is_empty());
+    if current.is_none() || reason.is_some() {
+        let reason = reason.unwrap_or_else(|| format!("临时停用 {} 分钟", minutes));
+        proxy_disable::disable_json(account, ProxyDisableCategory::Snooze, reason, now);
+    }
+    account["proxy_disabled_until"] = Value::Number(until.into());
+    until
+}
+
+/// 移除停用截止时间 (手动启用/禁用时调用，使状态不再自动变化)
+pub fn clear_snooze(account: &mut Value) {
+    if let Some(obj) = account.as_object_mut() {
+        obj.remove("proxy_disabled_until");
+    }
+}
+
+/// 停用已到期时恢复反代并返回 true
+pub fn clear_expired(account: &mut Value, now: i64) -> bool {
+    let Some(until) = account.get("proxy_disabled_until").and_then(|v| v.as_i64()) else {
+        return false;
+    };
+    if until > now {
+        return false;
+    }
+    let was_disabled = account.get("proxy_disabled").and_then(|v| v.as_bool()).unwrap_or(false);
+    clear_snooze(account);
+    if was_disabled {
+        proxy_disable::enable_json(account);
+    }
+    was_disabled
+}
+
+fn read_account(path: &Path) -> Result<Value, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("读取账号文件失败: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("解析账号文件失败: {}", e))
+}
+
+/// 停用账号文件对应的账号，返回新的截止时间
+pub fn snooze_account_file(path: &Path, minutes: u64, reason: Option<String>) -> Result<i64, String> {
+    if minutes == 0 {
+        return Err("停用时长必须大于 0 分钟".to_string());
+    }
+    let mut account = read_account(path)?;
+    let until = apply_snooze(&mut account, minutes, reason, chrono::Utc::now().timestamp());
+    crate::modules::account::write_account_json(path, &account)?;
+    Ok(until)
+}
+
+/// 账号文件中的停用已到期时恢复并写回，返回是否恢复
+pub fn release_if_expired(path: &Path, now: i64) -> Result<bool, String> {
+    let mut account = read_account(path)?;
+    match account.get("proxy_disabled_until").and_then(|v| v.as_i64()) {
+        Some(until) if until <= now => {}
+        _ => return Ok(false),
+    }
+    let released = clear_expired(&mut account, now);
+    crate::modules::account::write_account_json(path, &account)?;
+    Ok(released)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_snooze_extends_and_expires() {
+        let now = 1_700_000_000;
+        let mut account = json!({ "id": "a", "proxy_disabled": false });
+        assert_eq!(remaining_seconds(&account, now), None);
+
+        let until = apply_snooze(&mut account, 120, None, now);
+        assert_eq!(until, now + 7200);
+        assert_eq!(account["proxy_disabled_at"], now);
+        assert_eq!(remaining_seconds(&account, now + 600), Some(6600));
+
+        // 再次停用在原截止时间上延长，保留最初的停用时间与原因
+        let extended = apply_snooze(&mut account, 30, None, now + 600);
+        assert_eq!(extended, now + 7200 + 1800);
+        assert_eq!(account["proxy_disabled_at"], now);
+        assert_eq!(account["proxy_disabled_reason"], "临时停用 120 分钟");
+
+        assert!(!clear_expired(&mut account, now + 7200));
+        assert!(clear_expired(&mut account, extended));
+        assert_eq!(account["proxy_disabled"], false);
+        assert!(account.get("proxy_disabled_until").is_none());
+    }
+
+    #[test]
+    fn test_release_if_expired_rewrites_file() {
+        let dir = std::env::temp_dir().join(format!("ag_snooze_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.json");
+        std::fs::write(&path, json!({ "id": "a", "proxy_disabled": false }).to_string()).unwrap();
+
+        assert!(snooze_account_file(&path, 0, None).is_err());
+        let until = snooze_account_file(&path, 5, Some("maintenance".to_string())).unwrap();
+        assert!(!release_if_expired(&path, until - 1).unwrap());
+        assert!(release_if_expired(&path, until).unwrap());
+
+        let on_disk = read_account(&path).unwrap();
+        assert_eq!(on_disk["proxy_disabled"], false);
+        assert!(on_disk["proxy_disabled_reason"].is_null());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
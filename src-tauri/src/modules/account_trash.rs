@@ -0,0 +1,336 @@
+// 账号回收站
+// 删除账号时将账号文件移入 trash/ 并记录删除时间，账号随即离开索引与反代号池；
+// 保留期内可恢复 (同邮箱账号已重新添加时按导入冲突处理)，超过 trash_retention_days 的条目由后台任务清除。
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::time::{self, Duration};
+
+use crate::models::AccountSummary;
+use crate::modules::account::{self, TrashRestore};
+use crate::modules::import_conflict::{self, ImportConflict, ImportedDevice};
+use crate::modules::{account_reliability, config, logger};
+
+const TRASH_DIR: &str = "trash";
+
+/// 回收站条目 (trash/{id}.meta.json)，账号文件原样保存在 trash/{id}.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashedAccount {
+    pub account_id: String,
+    pub email: String,
+    pub name: Option<String>,
+    pub created_at: i64,
+    pub last_used: i64,
+    pub deleted_at: i64,
+}
+
+/// 恢复结果：直接恢复，或因同邮箱账号已存在而等待用户处理 (resolve_import_conflict)
+#[derive(Debug, Clone, Serialize)]
+pub struct TrashRestoreResult {
+    pub restored: Option<crate::models::Account>,
+    pub conflict: Option<ImportConflict>,
+}
+
+pub fn trash_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join(TRASH_DIR)
+}
+
+/// 回收站中账号文件的路径
+pub fn trashed_account_path(trash_dir: &Path, account_id: &str) -> PathBuf {
+    trash_dir.join(format!("{}.json", account_id))
+}
+
+fn meta_path(trash_dir: &Path, account_id: &str) -> PathBuf {
+    trash_dir.join(format!("{}.meta.json", account_id))
+}
+
+/// 将账号文件移入回收站；账号文件不存在时无可恢复内容，直接跳过
+pub fn move_to_trash(accounts_dir: &Path, trash_dir: &Path, summary: &AccountSummary, now: i64) -> Result<(), String> {
+    let source = accounts_dir.join(format!("{}.json", summary.id));
+    if !source.exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(trash_dir).map_err(|e| format!("创建回收站目录失败: {}", e))?;
+
+    let entry = TrashedAccount {
+        account_id: summary.id.clone(),
+        email: summary.email.clone(),
+        name: summary.name.clone(),
+        created_at: summary.created_at,
+        last_used: summary.last_used,
+        deleted_at: now,
+    };
+    let meta = meta_path(trash_dir, &summary.id);
+    let content = serde_json::to_string_pretty(&entry).map_err(|e| format!("序列化回收站条目失败: {}", e))?;
+    fs::write(&meta, content).map_err(|e| format!("写入回收站条目失败: {}", e))?;
+    if let Err(e) = fs::rename(&source, trashed_account_path(trash_dir, &summary.id)) {
+        let _ = fs::remove_file(&meta);
+        return Err(format!("移动账号文件到回收站失败: {}", e));
+    }
+    Ok(())
+}
+
+/// 读取回收站条目
+pub fn read_entry(trash_dir: &Path, account_id: &str) -> Result<TrashedAccount, String> {
+    account::validate_account_id(account_id)?;
+    if !trashed_account_path(trash_dir, account_id).exists() {
+        return Err(format!("回收站中没有账号: {}", account_id));
+    }
+    let content = fs::read_to_string(meta_path(trash_dir, account_id))
+        .map_err(|e| format!("读取回收站条目失败: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("解析回收站条目失败: {}", e))
+}
+
+/// 回收站中的账号，最近删除的排在最前
+pub fn list(trash_dir: &Path) -> Vec<TrashedAccount> {
+    let Ok(entries) = fs::read_dir(trash_dir) else {
+        return Vec::new();
+    };
+    let mut trashed: Vec<TrashedAccount> = entries
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            name.strip_suffix(".meta.json").map(str::to_string)
+        })
+        .filter_map(|id| read_entry(trash_dir, &id).ok())
+        .collect();
+    trashed.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at).then_with(|| a.email.cmp(&b.email)));
+    trashed
+}
+
+/// 将账号文件移回账号目录并删除回收站条目
+pub fn take_back(trash_dir: &Path, accounts_dir: &Path, account_id: &str) -> Result<TrashedAccount, String> {
+    let entry = read_entry(trash_dir, account_id)?;
+    fs::rename(
+        trashed_account_path(trash_dir, account_id),
+        accounts_dir.join(format!("{}.json", account_id)),
+    )
+    .map_err(|e| format!("从回收站恢复账号文件失败: {}", e))?;
+    let _ = fs::remove_file(meta_path(trash_dir, account_id));
+    Ok(entry)
+}
+
+/// 删除单个回收站条目
+pub fn remove_entry(trash_dir: &Path, account_id: &str) {
+    let _ = fs::remove_file(trashed_account_path(trash_dir, account_id));
+    let _ = fs::remove_file(meta_path(trash_dir, account_id));
+}
+
+/// 清除删除时间早于 `older_than_secs` 的条目 (None 时清空回收站)，返回被清除的账号 ID
+pub fn purge(trash_dir: &Path, older_than_secs: Option<i64>, now: i64) -> Vec<String> {
+    list(trash_dir)
+        .into_iter()
+        .filter(|e| older_than_secs.map_or(true, |secs| now - e.deleted_at >= secs))
+        .map(|e| {
+            remove_entry(trash_dir, &e.account_id);
+            e.account_id
+        })
+        .collect()
+}
+
+/// 列出回收站中的账号
+pub fn list_trashed_accounts() -> Result<Vec<TrashedAccount>, String> {
+    Ok(list(&trash_dir(&account::get_data_dir()?)))
+}
+
+/// 恢复回收站中的账号
+pub async fn restore_trashed_account(account_id: &str) -> Result<TrashRestoreResult, String> {
+    account::validate_account_id(account_id)?;
+    match account::restore_from_trash(account_id)? {
+        TrashRestore::Restored(restored) => {
+            logger::log_info(&format!("已从回收站恢复账号: {}", restored.email));
+            Ok(TrashRestoreResult { restored: Some(restored), conflict: None })
+        }
+        TrashRestore::EmailTaken { existing, trashed } => {
+            // 删除后又添加了同邮箱的账号：不覆盖，按导入冲突由用户选择保留哪个 token
+            let device = ImportedDevice::from_account(&trashed);
+            let conflict = import_conflict::register_conflict(
+                existing,
+                trashed.email.clone(),
+                trashed.name.clone(),
+                trashed.token,
+                device,
+                Some(trashed.id),
+            )
+            .await?;
+            Ok(TrashRestoreResult { restored: None, conflict: Some(conflict) })
+        }
+    }
+}
+
+/// 清除回收站条目 (older_than_days 为空时清空)，返回清除数量
+pub fn purge_trash(older_than_days: Option<u32>) -> Result<usize, String> {
+    crate::modules::instance_lock::ensure_writable()?;
+    let data_dir = account::get_data_dir()?;
+    let older_than_secs = older_than_days.map(|days| i64::from(days) * 86_400);
+    let purged = purge(&trash_dir(&data_dir), older_than_secs, chrono::Utc::now().timestamp());
+    for account_id in &purged {
+        account_reliability::remove_history(&data_dir, account_id);
+    }
+    if !purged.is_empty() {
+        logger::log_info(&format!("已从回收站永久删除 {} 个账号", purged.len()));
+    }
+    Ok(purged.len())
+}
+
+/// 后台定期清除超过保留期的回收站条目
+pub fn start_trash_purger() {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            // 只读实例不清理回收站，由持有租约的实例负责
+            if crate::modules::instance_lock::is_read_only() {
+                continue;
+            }
+            let retention_days = config::load_app_config()
+                .map(|c| c.trash_retention_days)
+                .unwrap_or_else(|_| crate::models::config::default_trash_retention_days());
+            if let Err(e) = purge_trash(Some(retention_days)) {
+                logger::log_warn(&format!("[Trash] 清理回收站失败: {}", e));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (PathBuf, PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!("ag_trash_test_{}", uuid::Uuid::new_v4()));
+        let accounts = root.join("accounts");
+        fs::create_dir_all(&accounts).unwrap();
+        let trash = trash_dir(&root);
+        (root, accounts, trash)
+    }
+
+    fn summary(id: &str) -> AccountSummary {
+        AccountSummary {
+            id: id.to_string(),
+            email: format!("{}@example.com", id),
+            name: None,
+            created_at: 1,
+            last_used: 2,
+        }
+    }
+
+    #[test]
+    fn test_trash_roundtrip_keeps_file_contents() {
+        let (root, accounts, trash) = setup();
+        fs::write(accounts.join("a.json"), r#"{"id":"a","token":"secret"}"#).unwrap();
+
+        move_to_trash(&accounts, &trash, &summary("a"), 100).unwrap();
+        assert!(!accounts.join("a.json").exists());
+        let listed = list(&trash);
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].email, "a@example.com");
+        assert_eq!(listed[0].deleted_at, 100);
+
+        let entry = take_back(&trash, &accounts, "a").unwrap();
+        assert_eq!(entry.account_id, "a");
+        assert_eq!(
+            fs::read_to_string(accounts.join("a.json")).unwrap(),
+            r#"{"id":"a","token":"secret"}"#
+        );
+        assert!(list(&trash).is_empty());
+        assert!(take_back(&trash, &accounts, "a").is_err());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_read_entry_rejects_path_traversal() {
+        let (root, _, trash) = setup();
+        fs::create_dir_all(&trash).unwrap();
+        fs::write(root.join("outside.json"), "{}").unwrap();
+        fs::write(root.join("outside.meta.json"), "{}").unwrap();
+
+        let err = read_entry(&trash, "../outside").unwrap_err();
+        assert!(err.contains("无效的账号 ID"));
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_purge_respects_retention() {
+        let (root, accounts, trash) = setup();
+        for (id, deleted_at) in [("old", 0), ("new", 9 * 86_400)] {
+            fs::write(accounts.join(format!("{}.json", id)), "{}").unwrap();
+            move_to_trash(&accounts, &trash, &summary(id), deleted_at).unwrap();
+        }
+        let now = 10 * 86_400;
+
+        assert_eq!(purge(&trash, Some(7 * 86_400), now), vec!["old".to_string()]);
+        let remaining: Vec<String> = list(&trash).into_iter().map(|e| e.account_id).collect();
+        assert_eq!(remaining, vec!["new".to_string()]);
+
+        assert_eq!(purge(&trash, None, now), vec!["new".to_string()]);
+        assert!(list(&trash).is_empty());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    fn token(refresh_token: &str) -> crate::models::TokenData {
+        crate::models::TokenData::new("access".to_string(), refresh_token.to_string(), 3600, None, None, None)
+    }
+
+    /// 在临时数据目录中添加并删除一个账号，返回其 ID
+    fn trashed_account(email: &str, refresh_token: &str) -> String {
+        let added = account::upsert_account(email.to_string(), None, token(refresh_token)).unwrap();
+        account::delete_account(&added.id).unwrap();
+        added.id
+    }
+
+    #[tokio::test]
+    async fn test_restore_returns_account_to_index_and_proxy_pool() {
+        let (root, _, trash) = setup();
+        let _data_dir = account::use_test_data_dir(&root);
+        let id = trashed_account("restore@example.com", "refresh-1");
+        let manager = crate::proxy::TokenManager::new(root.clone());
+        assert_eq!(manager.load_accounts().await.unwrap(), 0);
+
+        match account::restore_from_trash(&id).unwrap() {
+            TrashRestore::Restored(restored) => assert_eq!(restored.id, id),
+            TrashRestore::EmailTaken { .. } => panic!("no account with this email exists"),
+        }
+        assert!(list(&trash).is_empty());
+        assert!(account::list_accounts().unwrap().iter().any(|a| a.id == id));
+        // 号池重新加载后账号回到反代
+        assert_eq!(manager.load_accounts().await.unwrap(), 1);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_restore_with_readded_email_reports_conflict() {
+        let (root, _, trash) = setup();
+        let _data_dir = account::use_test_data_dir(&root);
+        let id = trashed_account("taken@example.com", "old-refresh");
+        let readded = account::upsert_account("taken@example.com".to_string(), None, token("new-refresh")).unwrap();
+
+        match account::restore_from_trash(&id).unwrap() {
+            TrashRestore::EmailTaken { existing, trashed } => {
+                assert_eq!(existing.id, readded.id);
+                assert_eq!(trashed.token.refresh_token, "old-refresh");
+            }
+            TrashRestore::Restored(_) => panic!("different tokens must be resolved by the user"),
+        }
+        // 冲突处理前回收站条目保持不动
+        assert_eq!(list(&trash).len(), 1);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_restore_with_identical_token_drops_trashed_copy() {
+        let (root, _, trash) = setup();
+        let _data_dir = account::use_test_data_dir(&root);
+        let id = trashed_account("same@example.com", "same-refresh");
+        let readded = account::upsert_account("same@example.com".to_string(), None, token("same-refresh")).unwrap();
+
+        match account::restore_from_trash(&id).unwrap() {
+            TrashRestore::Restored(existing) => assert_eq!(existing.id, readded.id),
+            TrashRestore::EmailTaken { .. } => panic!("identical tokens leave nothing to choose"),
+        }
+        assert!(list(&trash).is_empty());
+        assert_eq!(account::list_accounts().unwrap().len(), 1);
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,781 @@
+// 全量备份与恢复
+// 将账号、索引、配置、设备指纹、会话绑定与用量统计打包为单个带时间戳的归档 (gzip 压缩的 JSON)，
+// 归档头部的 manifest 始终为明文，便于在解密/写入任何文件之前完成格式与版本校验。
+
+use base64::{engine::general_purpose, Engine as _};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+use tokio::time::{self, Duration};
+
+use crate::models::BackupConfig;
+use crate::modules::model_mapping_store::MODEL_MAPPING_FILE;
+use crate::modules::{config, logger, token_crypto};
+
+pub const BACKUP_FORMAT: &str = "antigravity-manager-backup";
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+const BACKUP_PREFIX: &str = "antigravity-backup-";
+const BACKUP_EXTENSION: &str = "agbak";
+
+pub const SECTION_ACCOUNTS: &str = "accounts";
+pub const SECTION_INDEX: &str = "index";
+pub const SECTION_CONFIG: &str = "config";
+pub const SECTION_DEVICE: &str = "device";
+pub const SECTION_SESSIONS: &str = "sessions";
+pub const SECTION_USAGE: &str = "usage";
+pub const ALL_SECTIONS: [&str; 6] = [
+    SECTION_ACCOUNTS,
+    SECTION_INDEX,
+    SECTION_CONFIG,
+    SECTION_DEVICE,
+    SECTION_SESSIONS,
+    SECTION_USAGE,
+];
+
+/// 设备分区中代表 Antigravity 当前 storage.json 的条目
+const DEVICE_STORAGE_ENTRY: &str = "storage.json";
+const DEVICE_BASELINE_FILE: &str = "device_original.json";
+const INDEX_FILE: &str = "accounts.json";
+const CONFIG_FILE: &str = "gui_config.json";
+const USAGE_DB_FILE: &str = "proxy_logs.db";
+/// 恢复的会话绑定在下次启动反代时应用
+pub const PENDING_PROXY_STATE_FILE: &str = "proxy_state.pending.json";
+
+/// 备份与账号切换共用的互斥锁：两者都会读写账号与设备文件，不能并发执行
+pub static STATE_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+/// 备份/恢复涉及的路径
+#[derive(Debug, Clone)]
+pub struct BackupPaths {
+    pub data_dir: PathBuf,
+    /// Antigravity 的 storage.json (无法定位时跳过)
+    pub device_storage: Option<PathBuf>,
+}
+
+impl BackupPaths {
+    pub fn current() -> Result<Self, String> {
+        Ok(Self {
+            data_dir: crate::modules::account::get_data_dir()?,
+            device_storage: crate::modules::device::get_storage_path().ok(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupSectionInfo {
+    pub name: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format: String,
+    pub version: u32,
+    pub app_version: String,
+    pub created_at: i64,
+    pub encrypted: bool,
+    pub sections: Vec<BackupSectionInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupEntry {
+    pub path: String,
+    pub file_name: String,
+    pub size: u64,
+    pub manifest: Option<BackupManifest>,
+    /// manifest 无法读取时的原因
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SectionRestoreReport {
+    pub section: String,
+    /// restored / skipped / failed
+    pub status: String,
+    pub files: usize,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreReport {
+    pub archive: String,
+    pub proxy_stopped: bool,
+    /// 恢复前在运行的反代是否已按恢复后的配置重新启动
+    pub proxy_restarted: bool,
+    pub sections: Vec<SectionRestoreReport>,
+}
+
+/// 分区名 -> (相对路径 -> 文件内容)
+type SectionFiles = BTreeMap<String, BTreeMap<String, Vec<u8>>>;
+
+/// 已校验的归档内容
+pub struct ParsedBackup {
+    pub manifest: BackupManifest,
+    pub sections: SectionFiles,
+}
+
+/// 文件存在时加入分区
+fn add_file(files: &mut BTreeMap<String, Vec<u8>>, name: &str, path: &Path) -> Result<(), String> {
+    if !path.is_file() {
+        return Ok(());
+    }
+    let bytes = fs::read(path).map_err(|e| format!("读取 {:?} 失败: {}", path, e))?;
+    files.insert(name.to_string(), bytes);
+    Ok(())
+}
+
+/// 收集单个分区的文件，`sessions_snapshot` 为运行中反代导出的调度状态
+fn collect_section(
+    paths: &BackupPaths,
+    section: &str,
+    sessions_snapshot: Option<&Value>,
+) -> Result<BTreeMap<String, Vec<u8>>, String> {
+    let mut files = BTreeMap::new();
+
+    match section {
+        SECTION_ACCOUNTS => {
+            let dir = paths.data_dir.join("accounts");
+            if let Ok(entries) = fs::read_dir(&dir) {
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    if path.extension().and_then(|s| s.to_str()) != Some("json") {
+                        continue;
+                    }
+                    if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+                        add_file(&mut files, name, &path)?;
+                    }
+                }
+            }
+        }
+        SECTION_INDEX => add_file(&mut files, INDEX_FILE, &paths.data_dir.join(INDEX_FILE))?,
+        SECTION_CONFIG => {
+            add_file(&mut files, CONFIG_FILE, &paths.data_dir.join(CONFIG_FILE))?;
+            add_file(&mut files, MODEL_MAPPING_FILE, &paths.data_dir.join(MODEL_MAPPING_FILE))?;
+        }
+        SECTION_DEVICE => {
+            add_file(&mut files, DEVICE_BASELINE_FILE, &paths.data_dir.join(DEVICE_BASELINE_FILE))?;
+            if let Some(storage) = &paths.device_storage {
+                add_file(&mut files, DEVICE_STORAGE_ENTRY, storage)?;
+            }
+        }
+        SECTION_SESSIONS => {
+            if let Some(snapshot) = sessions_snapshot {
+                let bytes = serde_json::to_vec_pretty(snapshot).map_err(|e| format!("序列化会话绑定失败: {}", e))?;
+                files.insert(PENDING_PROXY_STATE_FILE.to_string(), bytes);
+            }
+        }
+        SECTION_USAGE => add_file(&mut files, USAGE_DB_FILE, &paths.data_dir.join(USAGE_DB_FILE))?,
+        other => return Err(format!("未知的备份分区: {}", other)),
+    }
+    Ok(files)
+}
+
+/// 生成归档字节 (gzip 压缩的 JSON)，传入 passphrase 时加密分区内容
+pub fn build_archive(
+    paths: &BackupPaths,
+    sessions_snapshot: Option<&Value>,
+    passphrase: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    let mut payload = serde_json::Map::new();
+    let mut infos = Vec::new();
+    for section in ALL_SECTIONS {
+        let files = collect_section(paths, section, sessions_snapshot)?;
+        infos.push(BackupSectionInfo {
+            name: section.to_string(),
+            files: files.len(),
+            bytes: files.values().map(|b| b.len() as u64).sum(),
+        });
+        let encoded: serde_json::Map<String, Value> = files
+            .into_iter()
+            .map(|(name, bytes)| (name, Value::String(general_purpose::STANDARD.encode(bytes))))
+            .collect();
+        payload.insert(section.to_string(), Value::Object(encoded));
+    }
+
+    let payload = Value::Object(payload);
+    let payload = match passphrase {
+        Some(p) => token_crypto::encrypt_token_with(&payload, p, uuid::Uuid::new_v4().as_bytes())?,
+        None => payload,
+    };
+
+    let manifest = BackupManifest {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: chrono::Utc::now().timestamp(),
+        encrypted: passphrase.is_some(),
+        sections: infos,
+    };
+    let archive = json!({ "manifest": manifest, "payload": payload });
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&serde_json::to_vec(&archive).map_err(|e| format!("序列化备份失败: {}", e))?)
+        .map_err(|e| format!("压缩备份失败: {}", e))?;
+    encoder.finish().map_err(|e| format!("压缩备份失败: {}", e))
+}
+
+fn decode_archive(bytes: &[u8]) -> Result<Value, String> {
+    let mut decoder = GzDecoder::new(bytes);
+    let mut raw = Vec::new();
+    decoder
+        .read_to_end(&mut raw)
+        .map_err(|e| format!("备份文件不是有效的归档: {}", e))?;
+    serde_json::from_slice(&raw).map_err(|e| format!("备份文件内容无效: {}", e))
+}
+
+fn parse_manifest(archive: &Value) -> Result<BackupManifest, String> {
+    let manifest: BackupManifest = archive
+        .get("manifest")
+        .cloned()
+        .ok_or_else(|| "备份文件缺少 manifest".to_string())
+        .and_then(|m| serde_json::from_value(m).map_err(|e| format!("备份 manifest 无效: {}", e)))?;
+    if manifest.format != BACKUP_FORMAT {
+        return Err(format!("不支持的备份格式: {}", manifest.format));
+    }
+    if manifest.version == 0 || manifest.version > BACKUP_FORMAT_VERSION {
+        return Err(format!(
+            "备份版本 {} 不受支持 (当前支持 {})，请升级应用后再恢复",
+            manifest.version, BACKUP_FORMAT_VERSION
+        ));
+    }
+    Ok(manifest)
+}
+
+/// 仅读取归档的 manifest (不解密)
+pub fn read_manifest(bytes: &[u8]) -> Result<BackupManifest, String> {
+    parse_manifest(&decode_archive(bytes)?)
+}
+
+/// 解析并完整校验归档 (manifest、版本、解密、分区文件数)，不触碰任何磁盘文件
+pub fn parse_archive(bytes: &[u8], passphrase: Option<&str>) -> Result<ParsedBackup, String> {
+    let archive = decode_archive(bytes)?;
+    let manifest = parse_manifest(&archive)?;
+
+    let payload = archive.get("payload").ok_or_else(|| "备份文件缺少数据内容".to_string())?;
+    let payload = if manifest.encrypted {
+        let passphrase = passphrase.ok_or_else(|| "备份已加密，请先提供口令".to_string())?;
+        token_crypto::decrypt_token_with(payload, passphrase)?
+    } else {
+        payload.clone()
+    };
+
+    let mut sections = SectionFiles::new();
+    for info in &manifest.sections {
+        let entries = payload
+            .get(&info.name)
+            .and_then(|v| v.as_object())
+            .ok_or_else(|| format!("备份缺少分区 {}", info.name))?;
+        if entries.len() != info.files {
+            return Err(format!(
+                "分区 {} 文件数与 manifest 不一致 ({} != {})",
+                info.name,
+                entries.len(),
+                info.files
+            ));
+        }
+        let mut files = BTreeMap::new();
+        for (name, data) in entries {
+            if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+                return Err(format!("分区 {} 包含非法文件名: {}", info.name, name));
+            }
+            let bytes = data
+                .as_str()
+                .and_then(|s| general_purpose::STANDARD.decode(s).ok())
+                .ok_or_else(|| format!("分区 {} 的文件 {} 已损坏", info.name, name))?;
+            files.insert(name.clone(), bytes);
+        }
+        sections.insert(info.name.clone(), files);
+    }
+
+    Ok(ParsedBackup { manifest, sections })
+}
+
+/// 将分区内容写入临时目录后原子替换到目标位置
+fn swap_section(
+    paths: &BackupPaths,
+    staging: &Path,
+    section: &str,
+    files: &BTreeMap<String, Vec<u8>>,
+) -> Result<(), String> {
+    let section_dir = staging.join(section);
+    fs::create_dir_all(&section_dir).map_err(|e| format!("创建临时目录失败: {}", e))?;
+    for (name, bytes) in files {
+        fs::write(section_dir.join(name), bytes).map_err(|e| format!("写入临时文件 {} 失败: {}", name, e))?;
+    }
+
+    // 单文件分区：同目录临时文件 + rename
+    let replace_file = |name: &str, target: &Path| -> Result<(), String> {
+        let Some(bytes) = files.get(name) else {
+            return Ok(());
+        };
+        let parent = target.parent().ok_or_else(|| format!("无法获取 {:?} 的父目录", target))?;
+        fs::create_dir_all(parent).map_err(|e| format!("创建目录失败: {}", e))?;
+        let tmp = parent.join(format!(".{}.restore", name));
+        fs::write(&tmp, bytes).map_err(|e| format!("写入 {:?} 失败: {}", tmp, e))?;
+        fs::rename(&tmp, target).map_err(|e| format!("替换 {:?} 失败: {}", target, e))
+    };
+
+    match section {
+        SECTION_ACCOUNTS => {
+            // 整个账号目录交换：旧目录先挪开，新目录就位后再删除旧目录，失败时回滚
+            let target = paths.data_dir.join("accounts");
+            let previous = paths.data_dir.join(format!(".accounts.{}.old", uuid::Uuid::new_v4()));
+            let had_previous = target.exists();
+            if had_previous {
+                fs::rename(&target, &previous).map_err(|e| format!("移动原账号目录失败: {}", e))?;
+            }
+            if let Err(e) = fs::rename(&section_dir, &target) {
+                if had_previous {
+                    let _ = fs::rename(&previous, &target);
+                }
+                return Err(format!("替换账号目录失败: {}", e));
+            }
+            if had_previous {
+                let _ = fs::remove_dir_all(&previous);
+            }
+            Ok(())
+        }
+        SECTION_INDEX => replace_file(INDEX_FILE, &paths.data_dir.join(INDEX_FILE)),
+        SECTION_CONFIG => {
+            replace_file(CONFIG_FILE, &paths.data_dir.join(CONFIG_FILE))?;
+            replace_file(MODEL_MAPPING_FILE, &paths.data_dir.join(MODEL_MAPPING_FILE))
+        }
+        SECTION_DEVICE => {
+            replace_file(DEVICE_BASELINE_FILE, &paths.data_dir.join(DEVICE_BASELINE_FILE))?;
+            match (&paths.device_storage, files.contains_key(DEVICE_STORAGE_ENTRY)) {
+                (Some(storage), true) => replace_file(DEVICE_STORAGE_ENTRY, storage),
+                (None, true) => Err("无法定位 Antigravity 的 storage.json，设备指纹未恢复".to_string()),
+                _ => Ok(()),
+            }
+        }
+        SECTION_SESSIONS => replace_file(
+            PENDING_PROXY_STATE_FILE,
+            &paths.data_dir.join(PENDING_PROXY_STATE_FILE),
+        ),
+        SECTION_USAGE => replace_file(USAGE_DB_FILE, &paths.data_dir.join(USAGE_DB_FILE)),
+        other => Err(format!("未知的备份分区: {}", other)),
+    }
+}
+
+/// 按分区恢复已校验的归档，`sections` 为空表示恢复全部分区
+pub fn restore_parsed(
+    paths: &BackupPaths,
+    parsed: &ParsedBackup,
+    sections: &[String],
+) -> Result<Vec<SectionRestoreReport>, String> {
+    for section in sections {
+        if !ALL_SECTIONS.contains(&section.as_str()) {
+            return Err(format!("未知的备份分区: {}", section));
+        }
+    }
+
+    let staging = paths.data_dir.join(format!(".restore-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&staging).map_err(|e| format!("创建临时目录失败: {}", e))?;
+
+    let mut reports = Vec::new();
+    for section in ALL_SECTIONS {
+        let requested = sections.is_empty() || sections.iter().any(|s| s == section);
+        let report = |status: &str, files: usize, message: Option<String>| SectionRestoreReport {
+            section: section.to_string(),
+            status: status.to_string(),
+            files,
+            message,
+        };
+
+        if !requested {
+            reports.push(report("skipped", 0, Some("未选择".to_string())));
+            continue;
+        }
+        let Some(files) = parsed.sections.get(section) else {
+            reports.push(report("skipped", 0, Some("备份中不包含该分区".to_string())));
+            continue;
+        };
+        if files.is_empty() && section != SECTION_ACCOUNTS {
+            reports.push(report("skipped", 0, Some("备份中该分区为空".to_string())));
+            continue;
+        }
+
+        match swap_section(paths, &staging, section, files) {
+            Ok(()) => reports.push(report("restored", files.len(), None)),
+            Err(e) => {
+                logger::log_error(&format!("[Backup] 恢复分区 {} 失败: {}", section, e));
+                reports.push(report("failed", 0, Some(e)));
+            }
+        }
+    }
+
+    let _ = fs::remove_dir_all(&staging);
+    Ok(reports)
+}
+
+/// 备份文件名 (按时间排序即为创建顺序)
+fn backup_file_name(now: chrono::DateTime<chrono::Local>) -> String {
+    format!("{}{}.{}", BACKUP_PREFIX, now.format("%Y%m%d_%H%M%S"), BACKUP_EXTENSION)
+}
+
+fn is_backup_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| n.starts_with(BACKUP_PREFIX) && n.ends_with(&format!(".{}", BACKUP_EXTENSION)))
+        .unwrap_or(false)
+}
+
+/// 目录下的备份文件 (新到旧)
+fn backup_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| entries.flatten().map(|e| e.path()).filter(|p| is_backup_file(p)).collect())
+        .unwrap_or_default();
+    files.sort();
+    files.reverse();
+    files
+}
+
+/// 仅保留最近 keep_last 个备份，返回删除的数量
+pub fn apply_retention(dir: &Path, keep_last: usize) -> usize {
+    let mut removed = 0;
+    for path in backup_files(dir).into_iter().skip(keep_last.max(1)) {
+        match fs::remove_file(&path) {
+            Ok(()) => removed += 1,
+            Err(e) => logger::log_warn(&format!("[Backup] 删除过期备份 {:?} 失败: {}", path, e)),
+        }
+    }
+    removed
+}
+
+/// 解析备份目录：显式路径 > 配置目录 > 数据目录下的 backups
+pub fn resolve_backup_dir(path: Option<&str>, backup_config: &BackupConfig) -> Result<PathBuf, String> {
+    let dir = match path.or(backup_config.directory.as_deref()).filter(|p| !p.trim().is_empty()) {
+        Some(p) => PathBuf::from(p),
+        None => crate::modules::account::get_data_dir()?.join("backups"),
+    };
+    fs::create_dir_all(&dir).map_err(|e| format!("创建备份目录失败: {}", e))?;
+    Ok(dir)
+}
+
+/// 写出一个备份归档并执行保留策略，调用方需持有 STATE_LOCK
+pub fn write_backup(
+    paths: &BackupPaths,
+    dir: &Path,
+    backup_config: &BackupConfig,
+    sessions_snapshot: Option<&Value>,
+) -> Result<PathBuf, String> {
+    let passphrase = if backup_config.encrypt {
+        Some(token_crypto::passphrase().ok_or_else(|| "已启用备份加密，但未提供加密口令".to_string())?)
+    } else {
+        None
+    };
+    let bytes = build_archive(paths, sessions_snapshot, passphrase.as_deref())?;
+
+    let target = dir.join(backup_file_name(chrono::Local::now()));
+    let tmp = dir.join(format!(".{}.tmp", uuid::Uuid::new_v4()));
+    fs::write(&tmp, &bytes).map_err(|e| format!("写入备份失败: {}", e))?;
+    fs::rename(&tmp, &target).map_err(|e| format!("写入备份失败: {}", e))?;
+
+    let removed = apply_retention(dir, backup_config.keep_last as usize);
+    logger::log_info(&format!(
+        "[Backup] 已创建备份 {:?} ({} 字节)，清理旧备份 {} 个",
+        target,
+        bytes.len(),
+        removed
+    ));
+    Ok(target)
+}
+
+/// 列出目录下的备份 (新到旧)
+pub fn list_backups_in(dir: &Path) -> Vec<BackupEntry> {
+    backup_files(dir)
+        .into_iter()
+        .map(|path| {
+            let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            let (manifest, error) = match fs::read(&path).map_err(|e| e.to_string()).and_then(|b| read_manifest(&b)) {
+                Ok(m) => (Some(m), None),
+                Err(e) => (None, Some(e)),
+            };
+            BackupEntry {
+                path: path.to_string_lossy().to_string(),
+                file_name: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+                size,
+                manifest,
+                error,
+            }
+        })
+        .collect()
+}
+
+/// 距离上次备份是否已超过配置的间隔
+fn backup_due(dir: &Path, interval_hours: u32) -> bool {
+    let Some(latest) = backup_files(dir).into_iter().next() else {
+        return true;
+    };
+    let age = fs::metadata(&latest)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.elapsed().ok());
+    match age {
+        Some(age) => age.as_secs() >= interval_hours.max(1) as u64 * 3600,
+        None => true,
+    }
+}
+
+/// 定时备份任务
+pub fn start_backup_scheduler(app_handle: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        // 每 10 分钟检查一次是否到期
+        let mut interval = time::interval(Duration::from_secs(600));
+        loop {
+            interval.tick().await;
+            // 只读实例不做定时备份，由持有租约的实例负责
+            if crate::modules::instance_lock::is_read_only() {
+                continue;
+            }
+
+            let Ok(app_config) = config::load_app_config() else {
+                continue;
+            };
+            if !app_config.backup.enabled {
+                continue;
+            }
+            let dir = match resolve_backup_dir(None, &app_config.backup) {
+                Ok(dir) => dir,
+                Err(e) => {
+                    logger::log_error(&format!("[Backup] 定时备份目录不可用: {}", e));
+                    continue;
+                }
+            };
+            if !backup_due(&dir, app_config.backup.interval_hours) {
+                continue;
+            }
+
+            let state = app_handle.state::<crate::commands::proxy::ProxyServiceState>();
+            if let Err(e) = create_backup(&state, Some(dir.to_string_lossy().as_ref())).await {
+                logger::log_error(&format!("[Backup] 定时备份失败: {}", e));
+            }
+        }
+    });
+}
+
+/// 创建一次全量备份 (与其他备份或账号切换互斥)
+pub async fn create_backup(
+    proxy_state: &crate::commands::proxy::ProxyServiceState,
+    path: Option<&str>,
+) -> Result<PathBuf, String> {
+    let _guard = STATE_LOCK
+        .try_lock()
+        .map_err(|_| "另一个备份或账号切换正在进行中，请稍后再试".to_string())?;
+
+    let app_config = config::load_app_config()?;
+    let dir = resolve_backup_dir(path, &app_config.backup)?;
+    let sessions_snapshot = {
+        let instance_lock = proxy_state.instance.read().await;
+        match instance_lock.as_ref() {
+            Some(instance) => serde_json::to_value(instance.token_manager.snapshot_state().await).ok(),
+            None => None,
+        }
+    };
+    write_backup(&BackupPaths::current()?, &dir, &app_config.backup, sessions_snapshot.as_ref())
+}
+
+/// 从归档恢复：先校验，再停止反代，最后按分区原子替换；恢复前在运行的反代按恢复后的配置重新启动
+pub async fn restore_backup(
+    proxy_state: &crate::commands::proxy::ProxyServiceState,
+    app_handle: Option<tauri::AppHandle>,
+    archive_path: &str,
+    sections: Vec<String>,
+) -> Result<RestoreReport, String> {
+    crate::modules::instance_lock::ensure_writable()?;
+    let _guard = STATE_LOCK
+        .try_lock()
+        .map_err(|_| "另一个备份或账号切换正在进行中，请稍后再试".to_string())?;
+
+    let bytes = fs::read(archive_path).map_err(|e| format!("读取备份文件失败: {}", e))?;
+    let parsed = parse_archive(&bytes, token_crypto::passphrase().as_deref())?;
+    let paths = BackupPaths::current()?;
+
+    let proxy_stopped = proxy_state.stop().await;
+    let restored = restore_parsed(&paths, &parsed, &sections);
+
+    // 无论恢复成功与否，都不让用户停留在被停止的反代上
+    let proxy_restarted = if proxy_stopped {
+        match proxy_state.start_with_saved_config(app_handle).await {
+            Ok(_) => true,
+            Err(e) => {
+                logger::log_error(&format!("[Backup] 恢复后重新启动反代服务失败: {}", e));
+                false
+            }
+        }
+    } else {
+        false
+    };
+
+    let reports = restored?;
+    logger::log_info(&format!(
+        "[Backup] 已从 {} 恢复: {}",
+        archive_path,
+        reports
+            .iter()
+            .map(|r| format!("{}={}", r.section, r.status))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+
+    Ok(RestoreReport {
+        archive: archive_path.to_string(),
+        proxy_stopped,
+        proxy_restarted,
+        sections: reports,
+    })
+}
+
+/// 反代启动时应用恢复得到的会话绑定 (应用后删除)
+pub fn take_pending_proxy_state(data_dir: &Path) -> Option<crate::proxy::token_manager::ProxyStateSnapshot> {
+    let path = data_dir.join(PENDING_PROXY_STATE_FILE);
+    let content = fs::read_to_string(&path).ok()?;
+    let _ = fs::remove_file(&path);
+    match serde_json::from_str(&content) {
+        Ok(snapshot) => Some(snapshot),
+        Err(e) => {
+            logger::log_warn(&format!("[Backup] 忽略无效的待恢复会话绑定: {}", e));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ag-backup-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn seed_paths() -> BackupPaths {
+        let data_dir = temp_dir("data");
+        fs::create_dir_all(data_dir.join("accounts")).unwrap();
+        fs::write(data_dir.join("accounts/a1.json"), br#"{"id":"a1"}"#).unwrap();
+        fs::write(data_dir.join("accounts/a2.json"), br#"{"id":"a2"}"#).unwrap();
+        fs::write(data_dir.join(INDEX_FILE), br#"{"accounts":["a1","a2"]}"#).unwrap();
+        fs::write(data_dir.join(CONFIG_FILE), br#"{"language":"en"}"#).unwrap();
+        fs::write(data_dir.join(USAGE_DB_FILE), b"sqlite-bytes").unwrap();
+        let storage = temp_dir("storage").join("storage.json");
+        fs::write(&storage, br#"{"telemetry.machineId":"m1"}"#).unwrap();
+        BackupPaths { data_dir, device_storage: Some(storage) }
+    }
+
+    fn empty_target(source: &BackupPaths) -> BackupPaths {
+        BackupPaths {
+            data_dir: temp_dir("restore"),
+            device_storage: source.device_storage.as_ref().map(|_| temp_dir("restore-storage").join("storage.json")),
+        }
+    }
+
+    #[test]
+    fn test_backup_round_trip_restores_selected_sections() {
+        let source = seed_paths();
+        let sessions = json!({ "session_bindings": { "s1": "a1" } });
+        let bytes = build_archive(&source, Some(&sessions), None).unwrap();
+        let parsed = parse_archive(&bytes, None).unwrap();
+        assert_eq!(parsed.manifest.version, BACKUP_FORMAT_VERSION);
+        assert_eq!(parsed.sections[SECTION_ACCOUNTS].len(), 2);
+
+        let target = empty_target(&source);
+        fs::create_dir_all(target.data_dir.join("accounts")).unwrap();
+        fs::write(target.data_dir.join("accounts/stale.json"), b"{}").unwrap();
+        fs::write(target.data_dir.join(CONFIG_FILE), br#"{"language":"zh"}"#).unwrap();
+
+        let reports = restore_parsed(
+            &target,
+            &parsed,
+            &[SECTION_ACCOUNTS.to_string(), SECTION_DEVICE.to_string(), SECTION_SESSIONS.to_string()],
+        )
+        .unwrap();
+        let status = |s: &str| reports.iter().find(|r| r.section == s).unwrap().status.clone();
+        assert_eq!(status(SECTION_ACCOUNTS), "restored");
+        assert_eq!(status(SECTION_DEVICE), "restored");
+        assert_eq!(status(SECTION_SESSIONS), "restored");
+        assert_eq!(status(SECTION_CONFIG), "skipped");
+
+        // 账号目录被整体替换，未选择的配置保持不变，临时目录已清理
+        assert!(!target.data_dir.join("accounts/stale.json").exists());
+        assert_eq!(fs::read(target.data_dir.join("accounts/a2.json")).unwrap(), br#"{"id":"a2"}"#);
+        assert_eq!(fs::read(target.data_dir.join(CONFIG_FILE)).unwrap(), br#"{"language":"zh"}"#);
+        assert_eq!(
+            fs::read(target.device_storage.as_ref().unwrap()).unwrap(),
+            br#"{"telemetry.machineId":"m1"}"#
+        );
+        assert!(target.data_dir.join(PENDING_PROXY_STATE_FILE).exists());
+        let leftovers: Vec<_> = fs::read_dir(&target.data_dir)
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().starts_with('.'))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[test]
+    fn test_encrypted_backup_requires_passphrase() {
+        let source = seed_paths();
+        let bytes = build_archive(&source, None, Some("correct horse")).unwrap();
+
+        let manifest = read_manifest(&bytes).unwrap();
+        assert!(manifest.encrypted);
+        assert!(parse_archive(&bytes, None).is_err());
+        assert!(parse_archive(&bytes, Some("wrong horse")).is_err());
+        let parsed = parse_archive(&bytes, Some("correct horse")).unwrap();
+        assert_eq!(parsed.sections[SECTION_USAGE][USAGE_DB_FILE], b"sqlite-bytes");
+    }
+
+    #[test]
+    fn test_newer_or_foreign_archive_is_rejected_before_restore() {
+        let source = seed_paths();
+        let bytes = build_archive(&source, None, None).unwrap();
+        let mut archive = decode_archive(&bytes).unwrap();
+        archive["manifest"]["version"] = json!(BACKUP_FORMAT_VERSION + 1);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(archive.to_string().as_bytes()).unwrap();
+        let newer = encoder.finish().unwrap();
+
+        assert!(parse_archive(&newer, None).unwrap_err().contains("版本"));
+        assert!(parse_archive(b"not an archive", None).is_err());
+    }
+
+    #[test]
+    fn test_retention_keeps_newest_backups() {
+        let dir = temp_dir("retention");
+        for day in 1..=5 {
+            fs::write(dir.join(format!("{}2026010{}_000000.{}", BACKUP_PREFIX, day, BACKUP_EXTENSION)), b"x").unwrap();
+        }
+        fs::write(dir.join("unrelated.txt"), b"x").unwrap();
+
+        assert_eq!(apply_retention(&dir, 2), 3);
+        let remaining: Vec<String> = backup_files(&dir)
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(
+            remaining,
+            vec![
+                format!("{}20260105_000000.{}", BACKUP_PREFIX, BACKUP_EXTENSION),
+                format!("{}20260104_000000.{}", BACKUP_PREFIX, BACKUP_EXTENSION),
+            ]
+        );
+        assert!(dir.join("unrelated.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_backup_is_exclusive_with_account_switch() {
+        let _switch = STATE_LOCK.lock().await;
+        let state = crate::commands::proxy::ProxyServiceState::new();
+        let err = create_backup(&state, None).await.unwrap_err();
+        assert!(err.contains("正在进行中"));
+    }
+}
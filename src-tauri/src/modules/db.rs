@@ -1,8 +1,13 @@
+use crate::models::Account;
 use crate::utils::protobuf;
 use base64::{engine::general_purpose, Engine as _};
-use rusqlite::Connection;
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
 use std::path::PathBuf;
 
+/// 读取 IDE 数据库时等待锁释放的最长时间
+const DB_BUSY_TIMEOUT_MS: u64 = 500;
+
 fn get_antigravity_path() -> Option<PathBuf> {
     if let Ok(config) = crate::modules::config::load_app_config() {
         if let Some(path_str) = config.antigravity_executable {
@@ -113,3 +118,212 @@ pub fn inject_token(
 
     Ok(format!("Token 注入成功！\n数据库: {:?}", db_path))
 }
+
+/// IDE 数据库中当前注入的 OAuth Token
+#[derive(Debug, Clone, PartialEq)]
+pub struct InjectedToken {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expiry: Option<i64>,
+}
+
+/// 注入校验结果
+#[derive(Debug, Clone, Serialize)]
+pub struct InjectedTokenStatus {
+    /// IDE 数据库中的 Token 是否属于当前账号
+    pub matches_current_account: bool,
+    /// 注入 Token 对应的账号邮箱 (无法识别时为 None)
+    pub injected_email: Option<String>,
+    /// IDE 是否正在运行
+    pub ide_running: bool,
+    /// 数据库被 IDE 锁定，暂时无法读取
+    pub db_locked: bool,
+}
+
+enum ReadError {
+    Locked,
+    Other(String),
+}
+
+fn is_locked_error(e: &rusqlite::Error) -> bool {
+    matches!(
+        e.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseBusy) | Some(rusqlite::ErrorCode::DatabaseLocked)
+    )
+}
+
+/// 从数据库只读地取出注入的 OAuth Token (Field 6)
+fn read_injected_token(db_path: &PathBuf) -> Result<Option<InjectedToken>, ReadError> {
+    let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| ReadError::Other(format!("打开数据库失败: {}", e)))?;
+    let _ = conn.busy_timeout(std::time::Duration::from_millis(DB_BUSY_TIMEOUT_MS));
+
+    let current_data: String = match conn.query_row(
+        "SELECT value FROM ItemTable WHERE key = ?",
+        ["jetskiStateSync.agentManagerInitState"],
+        |row| row.get(0),
+    ) {
+        Ok(v) => v,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+        Err(e) if is_locked_error(&e) => return Err(ReadError::Locked),
+        Err(e) => return Err(ReadError::Other(format!("读取数据失败: {}", e))),
+    };
+
+    let blob = general_purpose::STANDARD
+        .decode(&current_data)
+        .map_err(|e| ReadError::Other(format!("Base64 解码失败: {}", e)))?;
+
+    let oauth_data = match protobuf::find_field(&blob, 6).map_err(ReadError::Other)? {
+        Some(d) => d,
+        None => return Ok(None),
+    };
+
+    let read_string = |field: u32| -> Result<Option<String>, ReadError> {
+        Ok(protobuf::find_field(&oauth_data, field)
+            .map_err(ReadError::Other)?
+            .and_then(|b| String::from_utf8(b).ok()))
+    };
+
+    let access_token = match read_string(1)? {
+        Some(t) => t,
+        None => return Ok(None),
+    };
+    let refresh_token = read_string(3)?;
+
+    // Field 4: Timestamp { seconds = 1 (varint) }
+    let expiry = protobuf::find_field(&oauth_data, 4)
+        .ok()
+        .flatten()
+        .and_then(|ts| {
+            let (tag, offset) = protobuf::read_varint(&ts, 0).ok()?;
+            if tag == (1 << 3) {
+                protobuf::read_varint(&ts, offset).ok().map(|(v, _)| v as i64)
+            } else {
+                None
+            }
+        });
+
+    Ok(Some(InjectedToken { access_token, refresh_token, expiry }))
+}
+
+/// 校验 IDE 数据库中注入的 Token 是否属于当前账号
+///
+/// 通过 refresh_token / access_token 与本地账号比对识别邮箱；数据库被锁定时不报错，
+/// 返回 `db_locked = true`
+pub fn verify_injected_token_at(
+    db_path: &PathBuf,
+    accounts: &[Account],
+    current_account_id: Option<&str>,
+    ide_running: bool,
+) -> Result<InjectedTokenStatus, String> {
+    let injected = match read_injected_token(db_path) {
+        Ok(t) => t,
+        Err(ReadError::Locked) => {
+            return Ok(InjectedTokenStatus {
+                matches_current_account: false,
+                injected_email: None,
+                ide_running,
+                db_locked: true,
+            });
+        }
+        Err(ReadError::Other(e)) => return Err(e),
+    };
+
+    let owner = injected.as_ref().and_then(|t| {
+        accounts.iter().find(|a| {
+            t.refresh_token.as_deref() == Some(a.token.refresh_token.as_str())
+                || t.access_token == a.token.access_token
+        })
+    });
+
+    Ok(InjectedTokenStatus {
+        matches_current_account: match (owner, current_account_id) {
+            (Some(a), Some(current)) => a.id == current,
+            _ => false,
+        },
+        injected_email: owner.map(|a| a.email.clone()),
+        ide_running,
+        db_locked: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TokenData;
+
+    fn fixture_db(access_token: &str, refresh_token: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ag_state_{}.vscdb", uuid::Uuid::new_v4()));
+        let conn = Connection::open(&path).unwrap();
+        conn.execute("CREATE TABLE ItemTable (key TEXT UNIQUE ON CONFLICT REPLACE, value BLOB)", [])
+            .unwrap();
+
+        // 其他字段 + Field 6 (OAuthTokenInfo)
+        let mut blob = protobuf::encode_varint((1 << 3) | 2);
+        blob.extend(protobuf::encode_varint(3));
+        blob.extend(b"abc");
+        blob.extend(protobuf::create_oauth_field(access_token, refresh_token, 1_900_000_000));
+
+        conn.execute(
+            "INSERT INTO ItemTable (key, value) VALUES (?, ?)",
+            ["jetskiStateSync.agentManagerInitState", &general_purpose::STANDARD.encode(&blob)],
+        )
+        .unwrap();
+        path
+    }
+
+    fn account(id: &str, email: &str, access: &str, refresh: &str) -> Account {
+        Account::new(
+            id.to_string(),
+            email.to_string(),
+            TokenData::new(access.to_string(), refresh.to_string(), 3600, None, None, None),
+        )
+    }
+
+    #[test]
+    fn test_read_injected_token() {
+        let path = fixture_db("ya29.known", "1//refresh-known");
+        let token = match read_injected_token(&path) {
+            Ok(Some(t)) => t,
+            _ => panic!("expected injected token"),
+        };
+        assert_eq!(token.access_token, "ya29.known");
+        assert_eq!(token.refresh_token.as_deref(), Some("1//refresh-known"));
+        assert_eq!(token.expiry, Some(1_900_000_000));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_verify_injected_token_matches_current_account() {
+        let path = fixture_db("ya29.stale-access", "1//refresh-b");
+        let accounts = vec![
+            account("a", "a@example.com", "ya29.a", "1//refresh-a"),
+            account("b", "b@example.com", "ya29.b", "1//refresh-b"),
+        ];
+
+        let status = verify_injected_token_at(&path, &accounts, Some("b"), false).unwrap();
+        assert!(status.matches_current_account);
+        assert_eq!(status.injected_email.as_deref(), Some("b@example.com"));
+        assert!(!status.db_locked);
+
+        let status = verify_injected_token_at(&path, &accounts, Some("a"), false).unwrap();
+        assert!(!status.matches_current_account);
+        assert_eq!(status.injected_email.as_deref(), Some("b@example.com"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_verify_injected_token_locked_db() {
+        let path = fixture_db("ya29.known", "1//refresh-known");
+        let locker = Connection::open(&path).unwrap();
+        locker.execute_batch("BEGIN EXCLUSIVE;").unwrap();
+
+        let status = verify_injected_token_at(&path, &[], None, true).unwrap();
+        assert!(status.db_locked);
+        assert!(status.ide_running);
+        assert!(!status.matches_current_account);
+
+        locker.execute_batch("ROLLBACK;").unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+}
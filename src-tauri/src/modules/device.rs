@@ -421,3 +421,87 @@ fn new_standard_machine_id() -> String {
     }
     id
 }
+
+/// 标准带连字符的 UUID (36 位，大小写均可)
+fn is_hyphenated_uuid(value: &str) -> bool {
+    value.len() == 36 && value.chars().filter(|c| *c == '-').count() == 4 && Uuid::parse_str(value).is_ok()
+}
+
+fn is_hex_of_len(value: &str, len: usize) -> bool {
+    value.len() == len && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// machineId：`auth0|user_` + 32 位小写字母数字 (本工具生成)，或 64 位十六进制 (IDE 原生)
+fn is_valid_machine_id(value: &str) -> bool {
+    if let Some(rest) = value.strip_prefix("auth0|user_") {
+        return rest.len() == 32 && rest.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+    }
+    is_hex_of_len(value, 64)
+}
+
+/// 校验设备指纹格式，返回逐字段的错误
+///
+/// 写入 storage.json 前调用，避免手工编辑的畸形指纹导致 IDE 无法启动
+pub fn validate_device_profile(profile: &DeviceProfile) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+
+    if !is_valid_machine_id(&profile.machine_id) {
+        errors.push("machine_id: 应为 auth0|user_ 加 32 位小写字母数字，或 64 位十六进制".to_string());
+    }
+    if !is_hyphenated_uuid(&profile.mac_machine_id) && !is_hex_of_len(&profile.mac_machine_id, 64) {
+        errors.push("mac_machine_id: 应为 UUID (xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx) 或 64 位十六进制".to_string());
+    }
+    if !is_hyphenated_uuid(&profile.dev_device_id) {
+        errors.push("dev_device_id: 应为 UUID (xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx)".to_string());
+    }
+    // 非 Windows 平台 IDE 写入的 sqmId 为空
+    let sqm_ok = profile.sqm_id.is_empty()
+        || profile
+            .sqm_id
+            .strip_prefix('{')
+            .and_then(|s| s.strip_suffix('}'))
+            .map(is_hyphenated_uuid)
+            .unwrap_or(false);
+    if !sqm_ok {
+        errors.push("sqm_id: 应为花括号包裹的 UUID ({XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX}) 或留空".to_string());
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_profile_is_valid() {
+        for _ in 0..20 {
+            assert_eq!(validate_device_profile(&generate_profile()), Ok(()));
+        }
+
+        // IDE 原生格式 (64 位十六进制 machineId，空 sqmId)
+        let native = DeviceProfile {
+            machine_id: "a".repeat(64),
+            mac_machine_id: "b".repeat(64),
+            dev_device_id: Uuid::new_v4().to_string(),
+            sqm_id: String::new(),
+        };
+        assert_eq!(validate_device_profile(&native), Ok(()));
+    }
+
+    #[test]
+    fn test_malformed_uuid_is_rejected() {
+        let mut profile = generate_profile();
+        profile.dev_device_id = "1234-not-a-uuid".to_string();
+        profile.sqm_id = Uuid::new_v4().to_string(); // 缺少花括号
+
+        let errors = validate_device_profile(&profile).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("dev_device_id:"));
+        assert!(errors[1].starts_with("sqm_id:"));
+    }
+}
@@ -0,0 +1,318 @@
+// 诊断包导出
+// 将脱敏后的配置、近期日志、反代统计、账号概况、限流快照与版本信息打包为 zip，便于提交问题反馈。
+// 所有条目写入前都经过同一个脱敏器：已知账号的邮箱替换为 account-N，token 与配置中的密钥一律替换为 [REDACTED]。
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::models::{Account, AppConfig};
+
+/// 最多打包的日志文件数 (按修改时间取最新)
+const MAX_LOG_FILES: usize = 3;
+/// 每个日志文件只保留末尾这么多字节
+const MAX_LOG_BYTES: usize = 512 * 1024;
+
+const REDACTED: &str = "[REDACTED]";
+
+pub const ENTRY_MANIFEST: &str = "manifest.json";
+pub const ENTRY_CONFIG: &str = "config.json";
+pub const ENTRY_ACCOUNTS: &str = "accounts_summary.json";
+pub const ENTRY_STATS: &str = "proxy_stats.json";
+pub const ENTRY_RATE_LIMITS: &str = "rate_limits.json";
+pub const ENTRY_VERSIONS: &str = "versions.json";
+const LOG_ENTRY_PREFIX: &str = "logs/";
+
+/// 日志中可能出现的邮箱与凭据 (账号列表之外的兜底规则)
+static SECRET_PATTERNS: Lazy<Vec<(Regex, &'static str)>> = Lazy::new(|| {
+    vec![
+        (Regex::new(r"ya29\.[A-Za-z0-9._\-]+").unwrap(), REDACTED),
+        (Regex::new(r"1//[A-Za-z0-9._\-]+").unwrap(), REDACTED),
+        (Regex::new(r"sk-[A-Za-z0-9_\-]{8,}").unwrap(), REDACTED),
+        (Regex::new(r"(?i)bearer\s+[A-Za-z0-9._\-]+").unwrap(), "Bearer [REDACTED]"),
+        (Regex::new(r"[A-Za-z0-9._%+\-]+@[A-Za-z0-9.\-]+\.[A-Za-z]{2,}").unwrap(), "[EMAIL]"),
+    ]
+});
+
+/// 诊断包的原始数据 (由 `collect_inputs` 从磁盘与运行中的反代收集)
+#[derive(Debug, Clone)]
+pub struct DiagnosticInputs {
+    pub config: AppConfig,
+    pub accounts: Vec<Account>,
+    /// 日志目录 (不存在时跳过日志)
+    pub log_dir: Option<PathBuf>,
+    pub proxy_stats: Option<crate::proxy::monitor::ProxyStats>,
+    pub rate_limits: Vec<crate::proxy::rate_limit::RateLimitStatusEntry>,
+    /// 本机 Antigravity 安装的版本号
+    pub ide_versions: Vec<Option<String>>,
+}
+
+/// 账号概况 (不含邮箱与 token)
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct AccountSummary {
+    pub total: usize,
+    pub disabled: usize,
+    pub proxy_disabled: usize,
+    pub standby: usize,
+    /// 订阅等级 -> 账号数 (未知等级记为 UNKNOWN)
+    pub tiers: BTreeMap<String, usize>,
+}
+
+pub fn summarize_accounts(accounts: &[Account]) -> AccountSummary {
+    let mut tiers = BTreeMap::new();
+    for account in accounts {
+        let tier = account
+            .quota
+            .as_ref()
+            .and_then(|q| q.subscription_tier.clone())
+            .unwrap_or_else(|| "UNKNOWN".to_string());
+        *tiers.entry(tier).or_insert(0) += 1;
+    }
+    AccountSummary {
+        total: accounts.len(),
+        disabled: accounts.iter().filter(|a| a.disabled).count(),
+        proxy_disabled: accounts.iter().filter(|a| a.proxy_disabled).count(),
+        standby: accounts.iter().filter(|a| a.standby).count(),
+        tiers,
+    }
+}
+
+/// 对诊断包中的所有文本做脱敏
+struct Redactor {
+    /// 已知的敏感字符串 -> 替换值 (按长度降序，避免短串先替换破坏长串)
+    known: Vec<(String, String)>,
+}
+
+impl Redactor {
+    fn new(accounts: &[Account], config: &AppConfig) -> Self {
+        let mut known = Vec::new();
+        for (i, account) in accounts.iter().enumerate() {
+            let alias = format!("account-{}", i + 1);
+            known.push((account.email.clone(), alias.clone()));
+            if let Some(email) = &account.token.email {
+                known.push((email.clone(), alias));
+            }
+            known.push((account.token.access_token.clone(), REDACTED.to_string()));
+            known.push((account.token.refresh_token.clone(), REDACTED.to_string()));
+        }
+        known.push((config.proxy.api_key.clone(), REDACTED.to_string()));
+        known.push((config.proxy.zai.api_key.clone(), REDACTED.to_string()));
+        known.extend(config.proxy.zai.api_keys.iter().map(|k| (k.key.clone(), REDACTED.to_string())));
+        known.push((config.proxy.upstream_proxy.url.clone(), REDACTED.to_string()));
+
+        known.retain(|(secret, _)| !secret.is_empty());
+        known.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        known.dedup_by(|a, b| a.0 == b.0);
+        Self { known }
+    }
+
+    fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (secret, replacement) in &self.known {
+            if text.contains(secret.as_str()) {
+                text = text.replace(secret.as_str(), replacement);
+            }
+        }
+        for (pattern, replacement) in SECRET_PATTERNS.iter() {
+            text = pattern.replace_all(&text, *replacement).into_owned();
+        }
+        text
+    }
+}
+
+/// 最近修改的日志文件 (仅保留末尾 MAX_LOG_BYTES 字节)
+fn recent_logs(log_dir: &Path) -> Vec<(String, String)> {
+    let Ok(entries) = std::fs::read_dir(log_dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .flatten()
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .collect();
+    files.sort_by(|a, b| b.0.cmp(&a.0));
+
+    files
+        .into_iter()
+        .take(MAX_LOG_FILES)
+        .filter_map(|(_, path)| {
+            let bytes = std::fs::read(&path).ok()?;
+            let tail = &bytes[bytes.len().saturating_sub(MAX_LOG_BYTES)..];
+            let name = path.file_name()?.to_string_lossy().to_string();
+            Some((name, String::from_utf8_lossy(tail).into_owned()))
+        })
+        .collect()
+}
+
+fn to_pretty(value: &impl Serialize) -> Result<String, String> {
+    serde_json::to_string_pretty(value).map_err(|e| format!("序列化诊断数据失败: {}", e))
+}
+
+/// 生成诊断包各条目 (条目名 -> 脱敏后的内容)
+pub fn build_entries(inputs: &DiagnosticInputs) -> Result<Vec<(String, String)>, String> {
+    let redactor = Redactor::new(&inputs.accounts, &inputs.config);
+    let mut entries = Vec::new();
+
+    entries.push((ENTRY_CONFIG.to_string(), to_pretty(&crate::modules::config::redacted_config(&inputs.config))?));
+    entries.push((ENTRY_ACCOUNTS.to_string(), to_pretty(&summarize_accounts(&inputs.accounts))?));
+
+    // 终端用户标识可能是客户自己的用户名，不进入诊断包
+    let mut stats = serde_json::to_value(inputs.proxy_stats.clone().unwrap_or_default()).unwrap_or(Value::Null);
+    if let Some(obj) = stats.as_object_mut() {
+        obj.remove("top_end_users");
+    }
+    entries.push((ENTRY_STATS.to_string(), to_pretty(&stats)?));
+    entries.push((ENTRY_RATE_LIMITS.to_string(), to_pretty(&inputs.rate_limits)?));
+    entries.push((
+        ENTRY_VERSIONS.to_string(),
+        to_pretty(&json!({
+            "app_version": env!("CARGO_PKG_VERSION"),
+            "os": std::env::consts::OS,
+            "arch": std::env::consts::ARCH,
+            "antigravity_versions": inputs.ide_versions,
+        }))?,
+    ));
+    if let Some(log_dir) = &inputs.log_dir {
+        for (name, content) in recent_logs(log_dir) {
+            entries.push((format!("{}{}", LOG_ENTRY_PREFIX, name), content));
+        }
+    }
+
+    let mut entries: Vec<(String, String)> = entries
+        .into_iter()
+        .map(|(name, content)| (name, redactor.redact(&content)))
+        .collect();
+    let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+    let manifest = to_pretty(&json!({
+        "generated_at": chrono::Utc::now().to_rfc3339(),
+        "entries": names,
+    }))?;
+    entries.insert(0, (ENTRY_MANIFEST.to_string(), manifest));
+    Ok(entries)
+}
+
+/// 将诊断包写入 zip 文件，返回写入的条目名
+pub fn write_bundle(path: &Path, inputs: &DiagnosticInputs) -> Result<Vec<String>, String> {
+    let entries = build_entries(inputs)?;
+    let file = std::fs::File::create(path).map_err(|e| format!("创建诊断包失败: {}", e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (name, content) in &entries {
+        zip.start_file(name.as_str(), options)
+            .map_err(|e| format!("写入诊断包失败: {}", e))?;
+        zip.write_all(content.as_bytes())
+            .map_err(|e| format!("写入诊断包失败: {}", e))?;
+    }
+    zip.finish().map_err(|e| format!("写入诊断包失败: {}", e))?;
+    Ok(entries.into_iter().map(|(name, _)| name).collect())
+}
+
+/// 从磁盘与运行中的反代收集诊断数据 (`proxy_stats` 由调用方从监控器获取)
+pub fn collect_inputs(proxy_stats: Option<crate::proxy::monitor::ProxyStats>) -> Result<DiagnosticInputs, String> {
+    let proxy_stats = proxy_stats.or_else(|| crate::modules::proxy_db::get_stats().ok());
+    Ok(DiagnosticInputs {
+        config: crate::modules::config::load_app_config()?,
+        accounts: crate::modules::account::list_accounts()?,
+        log_dir: crate::modules::logger::get_log_dir().ok(),
+        proxy_stats,
+        // 诊断包不含邮箱，只保留账号 ID
+        rate_limits: crate::proxy::token_manager::active()
+            .map(|manager| manager.get_rate_limit_status())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| crate::proxy::rate_limit::RateLimitStatusEntry { email: None, ..entry })
+            .collect(),
+        ide_versions: crate::modules::process::list_antigravity_installations()
+            .into_iter()
+            .map(|i| i.version)
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{QuotaData, TokenData};
+    use std::io::Read;
+
+    fn account(email: &str, tier: &str) -> Account {
+        let token = TokenData::new(
+            format!("ya29.access-{}", email),
+            format!("1//refresh-{}", email),
+            3600,
+            Some(email.to_string()),
+            None,
+            None,
+        );
+        let mut account = Account::new(format!("id-{}", email), email.to_string(), token);
+        let mut quota = QuotaData::new();
+        quota.subscription_tier = Some(tier.to_string());
+        account.quota = Some(quota);
+        account
+    }
+
+    #[test]
+    fn test_bundle_contains_expected_entries_without_secrets() {
+        let dir = std::env::temp_dir().join(format!("ag_diagnostics_{}", uuid::Uuid::new_v4()));
+        let log_dir = dir.join("logs");
+        std::fs::create_dir_all(&log_dir).unwrap();
+
+        let accounts = vec![account("alice@example.com", "PRO"), account("bob@example.com", "FREE")];
+        std::fs::write(
+            log_dir.join("app.log.2026-10-16"),
+            format!(
+                "INFO selected alice@example.com token={}\nWARN refresh failed for {}: {}\n",
+                accounts[0].token.access_token, accounts[1].email, accounts[1].token.refresh_token
+            ),
+        )
+        .unwrap();
+
+        let mut config = AppConfig::new();
+        config.proxy.api_key = "sk-local-proxy-secret".to_string();
+        let inputs = DiagnosticInputs {
+            config,
+            accounts: accounts.clone(),
+            log_dir: Some(log_dir),
+            proxy_stats: None,
+            rate_limits: Vec::new(),
+            ide_versions: vec![Some("1.2.3".to_string())],
+        };
+
+        let bundle = dir.join("bundle.zip");
+        let names = write_bundle(&bundle, &inputs).unwrap();
+        for expected in [ENTRY_MANIFEST, ENTRY_CONFIG, ENTRY_ACCOUNTS, ENTRY_STATS, ENTRY_RATE_LIMITS, ENTRY_VERSIONS, "logs/app.log.2026-10-16"] {
+            assert!(names.iter().any(|n| n == expected), "missing {}", expected);
+        }
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&bundle).unwrap()).unwrap();
+        assert_eq!(archive.len(), names.len());
+        let mut log = String::new();
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).unwrap();
+            let mut content = String::new();
+            file.read_to_string(&mut content).unwrap();
+            for account in &accounts {
+                assert!(!content.contains(&account.token.access_token), "access token leaked in {}", file.name());
+                assert!(!content.contains(&account.token.refresh_token), "refresh token leaked in {}", file.name());
+                assert!(!content.contains(&account.email), "email leaked in {}", file.name());
+            }
+            assert!(!content.contains("sk-local-proxy-secret"), "api key leaked in {}", file.name());
+            if file.name().starts_with(LOG_ENTRY_PREFIX) {
+                log = content;
+            }
+        }
+        // 已知账号的邮箱替换为稳定别名，保留日志的可读性
+        assert!(log.contains("selected account-1"));
+        assert!(log.contains("refresh failed for account-2"));
+
+        let summary = summarize_accounts(&accounts);
+        assert_eq!(summary.total, 2);
+        assert_eq!(summary.tiers.get("PRO"), Some(&1));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
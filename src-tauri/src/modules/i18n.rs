@@ -13,6 +13,7 @@ pub struct TrayTexts {
     pub no_account: String,
     pub unknown_quota: String,
     pub forbidden: String,
+    pub today_cost: String,
 }
 
 /// 从 JSON 加载翻译
@@ -52,5 +53,6 @@ pub fn get_tray_texts(lang: &str) -> TrayTexts {
         no_account: t.get("no_account").cloned().unwrap_or_else(|| "No Account".to_string()),
         unknown_quota: t.get("unknown_quota").cloned().unwrap_or_else(|| "Unknown".to_string()),
         forbidden: t.get("forbidden").cloned().unwrap_or_else(|| "Account Forbidden".to_string()),
+        today_cost: t.get("today_cost").cloned().unwrap_or_else(|| "Estimated cost today".to_string()),
     }
 }
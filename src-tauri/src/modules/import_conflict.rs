@@ -0,0 +1,613 @@
+// 账号导入冲突处理
+// 导入的邮箱已存在且 refresh_token 不同时不直接覆盖 (导入的可能是过期备份，而本地 token 仍然可用)：
+// 按 import_conflict_policy 自动处理，或记为待处理冲突，由用户通过 resolve_import_conflict 决定保留哪一个。
+// 导入源携带的设备指纹 (device_profile / device_history) 随账号一并写入，保持跨机器的指纹连续性。
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+
+use crate::models::{Account, DeviceProfile, DeviceProfileVersion, ImportConflictPolicy, TokenData};
+use crate::modules::oauth::{self, TokenResponse};
+use crate::modules::{account, config, device, logger};
+
+/// 待处理冲突 (按邮箱)，应用重启后丢失，重新导入即可
+static PENDING: Lazy<Mutex<HashMap<String, PendingImport>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 冲突的解决方式
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictChoice {
+    KeepExisting,
+    UseImported,
+}
+
+/// 冲突一方的 token 元数据 (不含完整 token)
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenSide {
+    pub refresh_token_hint: String,
+    pub expiry_timestamp: i64,
+    /// 轻量刷新是否成功
+    pub refreshes: bool,
+    pub error: Option<String>,
+}
+
+/// 导入冲突
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportConflict {
+    pub email: String,
+    pub account_id: String,
+    pub existing: TokenSide,
+    pub imported: TokenSide,
+}
+
+/// 导入报告
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    /// 新增或已更新的账号
+    pub imported: Vec<Account>,
+    /// 按策略保留了已有 token 的账号邮箱
+    pub kept_existing: Vec<String>,
+    /// 等待用户处理的冲突
+    pub conflicts: Vec<ImportConflict>,
+}
+
+/// 导入源携带的设备指纹
+#[derive(Debug, Clone, Default)]
+pub struct ImportedDevice {
+    pub profile: Option<DeviceProfile>,
+    pub history: Vec<DeviceProfileVersion>,
+}
+
+impl ImportedDevice {
+    /// 从账号 JSON 中读取 device_profile / device_history，格式无效的指纹会被丢弃
+    pub fn from_json(source: &Value) -> Self {
+        let profile = source
+            .get("device_profile")
+            .and_then(|v| serde_json::from_value::<DeviceProfile>(v.clone()).ok())
+            .filter(|p| device::validate_device_profile(p).is_ok());
+        let history = source
+            .get("device_history")
+            .and_then(|v| v.as_array())
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|v| serde_json::from_value::<DeviceProfileVersion>(v.clone()).ok())
+                    .filter(|v| device::validate_device_profile(&v.profile).is_ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { profile, history }
+    }
+
+    /// 账号自身绑定的指纹 (回收站恢复冲突时沿用)
+    pub fn from_account(account: &Account) -> Self {
+        Self {
+            profile: account.device_profile.clone(),
+            history: account.device_history.clone(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profile.is_none() && self.history.is_empty()
+    }
+}
+
+/// 将导入的指纹并入账号，返回账号是否有变化：
+/// 账号尚未绑定指纹时采用导入的指纹 (已绑定时保留本机指纹)，历史版本按 ID 去重追加
+pub fn merge_imported_device(account: &mut Account, device: ImportedDevice) -> bool {
+    let ImportedDevice { profile, history } = device;
+    let adopted = match profile {
+        Some(profile) if account.device_profile.is_none() => {
+            for h in account.device_history.iter_mut() {
+                h.is_current = false;
+            }
+            account.device_profile = Some(profile.clone());
+            Some(profile)
+        }
+        _ => None,
+    };
+
+    let mut changed = adopted.is_some();
+    for mut version in history {
+        if account.device_history.iter().any(|h| h.id == version.id) {
+            continue;
+        }
+        if adopted.is_none() {
+            version.is_current = false;
+        }
+        account.device_history.push(version);
+        changed = true;
+    }
+
+    // 导入源只有指纹没有历史 (如从 storage.json 采集) 时补一条当前版本
+    if let Some(profile) = adopted {
+        if !account.device_history.iter().any(|h| h.is_current) {
+            account.device_history.push(DeviceProfileVersion {
+                id: uuid::Uuid::new_v4().to_string(),
+                created_at: chrono::Utc::now().timestamp(),
+                label: "imported".to_string(),
+                profile,
+                is_current: true,
+            });
+        }
+    }
+    changed
+}
+
+struct PendingImport {
+    /// 登记时的冲突信息 (同一 token 再次导入时直接返回，不重复刷新)
+    conflict: ImportConflict,
+    name: Option<String>,
+    token: TokenData,
+    device: ImportedDevice,
+    /// 冲突来自回收站恢复时的回收站账号 ID (选择导入的 token 后从回收站移除)
+    trashed_account_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ImportAction {
+    Upsert,
+    KeepExisting,
+    Conflict,
+}
+
+/// 只有邮箱已存在且 refresh_token 不同时才算冲突
+fn classify(existing: Option<&Account>, imported: &TokenData, policy: ImportConflictPolicy) -> ImportAction {
+    match existing {
+        Some(acc) if acc.token.refresh_token != imported.refresh_token => match policy {
+            ImportConflictPolicy::Ask => ImportAction::Conflict,
+            ImportConflictPolicy::KeepExisting => ImportAction::KeepExisting,
+            ImportConflictPolicy::UseImported => ImportAction::Upsert,
+        },
+        _ => ImportAction::Upsert,
+    }
+}
+
+/// 配置中的冲突策略 (读取失败时为 Ask)
+pub fn configured_policy() -> ImportConflictPolicy {
+    config::load_app_config()
+        .map(|c| c.import_conflict_policy)
+        .unwrap_or_default()
+}
+
+fn refresh_token_hint(token: &str) -> String {
+    let chars: Vec<char> = token.chars().collect();
+    if chars.len() <= 12 {
+        return "***".to_string();
+    }
+    let head: String = chars[..6].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}...{}", head, tail)
+}
+
+async fn token_side<F, Fut>(token: &TokenData, check: &F) -> TokenSide
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<TokenResponse, String>>,
+{
+    let result = check(token.refresh_token.clone()).await;
+    TokenSide {
+        refresh_token_hint: refresh_token_hint(&token.refresh_token),
+        expiry_timestamp: token.expiry_timestamp,
+        refreshes: result.is_ok(),
+        error: result.err(),
+    }
+}
+
+fn find_by_email(email: &str) -> Result<Option<Account>, String> {
+    let index = account::load_account_index()?;
+    Ok(index
+        .accounts
+        .iter()
+        .find(|s| s.email == email)
+        .and_then(|s| account::load_account(&s.id).ok()))
+}
+
+/// 写入导入的账号并合并其设备指纹
+fn upsert_with_device(email: String, name: Option<String>, token: TokenData, device: ImportedDevice) -> Result<Account, String> {
+    let mut account = account::upsert_account(email, name, token)?;
+    if merge_imported_device(&mut account, device) {
+        account::save_account(&account)?;
+    }
+    Ok(account)
+}
+
+/// 导入单个账号：无冲突时直接写入，冲突时按策略处理或记入报告
+pub async fn import_account(
+    email: String,
+    name: Option<String>,
+    token: TokenData,
+    device: ImportedDevice,
+    policy: ImportConflictPolicy,
+    report: &mut ImportReport,
+) -> Result<(), String> {
+    import_account_with(email, name, token, device, policy, report, &oauth_check).await
+}
+
+async fn oauth_check(refresh_token: String) -> Result<TokenResponse, String> {
+    oauth::refresh_access_token(&refresh_token).await
+}
+
+async fn import_account_with<F, Fut>(
+    email: String,
+    name: Option<String>,
+    token: TokenData,
+    device: ImportedDevice,
+    policy: ImportConflictPolicy,
+    report: &mut ImportReport,
+    check: &F,
+) -> Result<(), String>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<TokenResponse, String>>,
+{
+    let existing = find_by_email(&email)?;
+    match (classify(existing.as_ref(), &token, policy), existing) {
+        (ImportAction::KeepExisting, Some(_)) => {
+            logger::log_info(&format!("导入的 {} 与已有 token 不同，按策略保留已有 token", email));
+            report.kept_existing.push(email);
+        }
+        (ImportAction::Conflict, Some(existing)) => {
+            report
+                .conflicts
+                .push(register_conflict_with(existing, email, name, token, device, None, check).await?);
+        }
+        _ => report.imported.push(upsert_with_device(email, name, token, device)?),
+    }
+    Ok(())
+}
+
+/// 记录待处理冲突，由用户通过 resolve_import_conflict 决定保留哪个 token
+pub async fn register_conflict(
+    existing: Account,
+    email: String,
+    name: Option<String>,
+    token: TokenData,
+    device: ImportedDevice,
+    trashed_account_id: Option<String>,
+) -> Result<ImportConflict, String> {
+    register_conflict_with(existing, email, name, token, device, trashed_account_id, &oauth_check).await
+}
+
+async fn register_conflict_with<F, Fut>(
+    existing: Account,
+    email: String,
+    name: Option<String>,
+    token: TokenData,
+    device: ImportedDevice,
+    trashed_account_id: Option<String>,
+    check: &F,
+) -> Result<ImportConflict, String>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<TokenResponse, String>>,
+{
+    // 同一 token 已在等待处理 (如周期同步重复导入)，直接返回已登记的冲突
+    if let Some(conflict) = pending_conflict(&email, &token.refresh_token)? {
+        return Ok(conflict);
+    }
+
+    let (existing_side, imported_side) =
+        futures::join!(token_side(&existing.token, check), token_side(&token, check));
+    logger::log_warn(&format!("导入的 {} 与已有 token 不同，等待用户选择", email));
+    let conflict = ImportConflict {
+        email: email.clone(),
+        account_id: existing.id,
+        existing: existing_side,
+        imported: imported_side,
+    };
+    PENDING.lock().map_err(|e| format!("获取锁失败: {}", e))?.insert(
+        email,
+        PendingImport { conflict: conflict.clone(), name, token, device, trashed_account_id },
+    );
+    Ok(conflict)
+}
+
+fn pending_conflict(email: &str, refresh_token: &str) -> Result<Option<ImportConflict>, String> {
+    let pending = PENDING.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    Ok(pending
+        .get(email)
+        .filter(|p| p.token.refresh_token == refresh_token)
+        .map(|p| p.conflict.clone()))
+}
+
+/// 该 refresh_token 是否已作为导入的一方在等待用户处理
+pub fn is_pending_refresh_token(refresh_token: &str) -> bool {
+    PENDING
+        .lock()
+        .map(|pending| pending.values().any(|p| p.token.refresh_token == refresh_token))
+        .unwrap_or(false)
+}
+
+/// 所有待处理冲突 (按邮箱排序)
+pub fn pending_conflicts() -> Vec<ImportConflict> {
+    let Ok(pending) = PENDING.lock() else {
+        return Vec::new();
+    };
+    let mut conflicts: Vec<ImportConflict> = pending.values().map(|p| p.conflict.clone()).collect();
+    conflicts.sort_by(|a, b| a.email.cmp(&b.email));
+    conflicts
+}
+
+/// 取出待处理冲突：UseImported 返回待写入的导入数据，KeepExisting 直接丢弃
+fn take_pending(
+    pending: &mut HashMap<String, PendingImport>,
+    email: &str,
+    choice: ConflictChoice,
+) -> Result<Option<PendingImport>, String> {
+    let entry = pending
+        .remove(email)
+        .ok_or_else(|| format!("没有账号 {} 的待处理导入冲突", email))?;
+    Ok(match choice {
+        ConflictChoice::KeepExisting => None,
+        ConflictChoice::UseImported => Some(entry),
+    })
+}
+
+/// 解决导入冲突，返回处理后的账号
+pub fn resolve_conflict(email: &str, choice: ConflictChoice) -> Result<Account, String> {
+    let entry = {
+        let mut pending = PENDING.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+        take_pending(&mut pending, email, choice)?
+    };
+    match entry {
+        Some(PendingImport { name, token, device, trashed_account_id }) => {
+            logger::log_info(&format!("导入冲突已解决: {} 使用导入的 token", email));
+            let account = upsert_with_device(email.to_string(), name, token, device)?;
+            // token 已并入现有账号，回收站中的副本不再需要
+            if let Some(trashed_id) = trashed_account_id {
+                let trash_dir = crate::modules::account_trash::trash_dir(&account::get_data_dir()?);
+                crate::modules::account_trash::remove_entry(&trash_dir, &trashed_id);
+            }
+            Ok(account)
+        }
+        None => {
+            logger::log_info(&format!("导入冲突已解决: {} 保留已有 token", email));
+            find_by_email(email)?.ok_or_else(|| format!("账号 {} 不存在", email))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(refresh_token: &str) -> TokenData {
+        TokenData::new("access".to_string(), refresh_token.to_string(), 3600, None, None, None)
+    }
+
+    fn side(hint: &str) -> TokenSide {
+        TokenSide { refresh_token_hint: hint.to_string(), expiry_timestamp: 0, refreshes: true, error: None }
+    }
+
+    fn pending(refresh_token: &str) -> HashMap<String, PendingImport> {
+        let mut pending = HashMap::new();
+        pending.insert(
+            "a@example.com".to_string(),
+            PendingImport {
+                conflict: ImportConflict {
+                    email: "a@example.com".to_string(),
+                    account_id: "id".to_string(),
+                    existing: side("***"),
+                    imported: side("***"),
+                },
+                name: None,
+                token: token(refresh_token),
+                device: ImportedDevice::default(),
+                trashed_account_id: None,
+            },
+        );
+        pending
+    }
+
+    #[test]
+    fn test_classify_applies_auto_policy_only_on_conflict() {
+        let existing = Account::new("id".to_string(), "a@example.com".to_string(), token("old"));
+
+        for policy in [ImportConflictPolicy::Ask, ImportConflictPolicy::KeepExisting, ImportConflictPolicy::UseImported] {
+            assert_eq!(classify(None, &token("new"), policy), ImportAction::Upsert);
+            assert_eq!(classify(Some(&existing), &token("old"), policy), ImportAction::Upsert);
+        }
+        assert_eq!(classify(Some(&existing), &token("new"), ImportConflictPolicy::Ask), ImportAction::Conflict);
+        assert_eq!(
+            classify(Some(&existing), &token("new"), ImportConflictPolicy::KeepExisting),
+            ImportAction::KeepExisting
+        );
+        assert_eq!(
+            classify(Some(&existing), &token("new"), ImportConflictPolicy::UseImported),
+            ImportAction::Upsert
+        );
+    }
+
+    #[test]
+    fn test_resolve_keep_existing_discards_import() {
+        let mut pending = pending("imported");
+        assert!(take_pending(&mut pending, "a@example.com", ConflictChoice::KeepExisting)
+            .unwrap()
+            .is_none());
+        assert!(pending.is_empty());
+        // 已处理的冲突不能再次处理
+        assert!(take_pending(&mut pending, "a@example.com", ConflictChoice::UseImported).is_err());
+    }
+
+    #[test]
+    fn test_resolve_use_imported_returns_imported_token() {
+        let mut pending = pending("imported");
+        let entry = take_pending(&mut pending, "a@example.com", ConflictChoice::UseImported)
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.token.refresh_token, "imported");
+        assert!(pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_imported_device_profile_is_kept_in_saved_account_file() {
+        let dir = std::env::temp_dir().join(format!("ag_import_device_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let _data_dir = account::use_test_data_dir(&dir);
+
+        let bound = device::generate_profile();
+        let older = device::generate_profile();
+        let suffix = uuid::Uuid::new_v4().simple().to_string();
+        let email = format!("device-{}@example.com", suffix);
+        let refresh = format!("refresh-{}", suffix);
+        let source = serde_json::json!({
+            "email": email,
+            "token": { "refresh_token": refresh },
+            "device_profile": bound,
+            "device_history": [
+                { "id": "v1", "created_at": 1, "label": "generate", "profile": older, "is_current": false },
+                { "id": "v2", "created_at": 2, "label": "capture", "profile": bound, "is_current": true },
+                { "id": "bad", "created_at": 3, "label": "broken", "profile": { "machine_id": "x" } }
+            ]
+        });
+        let imported = ImportedDevice::from_json(&source);
+        assert_eq!(imported.history.len(), 2);
+
+        // 新账号：走真实的导入路径 (无冲突，不触发刷新检查)
+        let check = |_: String| async { Err::<TokenResponse, String>("unexpected refresh check".to_string()) };
+        let mut report = ImportReport::default();
+        import_account_with(
+            email.clone(),
+            None,
+            token(&refresh),
+            imported.clone(),
+            ImportConflictPolicy::Ask,
+            &mut report,
+            &check,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.imported.len(), 1);
+        assert!(report.conflicts.is_empty());
+
+        let saved = account::load_account(&report.imported[0].id).unwrap();
+        assert_eq!(saved.device_profile.unwrap().machine_id, bound.machine_id);
+        let ids: Vec<&str> = saved.device_history.iter().map(|h| h.id.as_str()).collect();
+        assert_eq!(ids, vec!["v1", "v2"]);
+        assert!(saved.device_history[1].is_current);
+
+        // 已绑定本机指纹的账号重新导入时保留原指纹，导入的版本仅追加到历史且不标记为当前
+        let other_email = format!("device-local-{}@example.com", suffix);
+        let other_refresh = format!("refresh-local-{}", suffix);
+        let mut existing = account::upsert_account(other_email.clone(), None, token(&other_refresh)).unwrap();
+        let local = device::generate_profile();
+        existing.device_profile = Some(local.clone());
+        account::save_account(&existing).unwrap();
+
+        for _ in 0..2 {
+            let mut report = ImportReport::default();
+            import_account_with(
+                other_email.clone(),
+                None,
+                token(&other_refresh),
+                imported.clone(),
+                ImportConflictPolicy::Ask,
+                &mut report,
+                &check,
+            )
+            .await
+            .unwrap();
+            assert_eq!(report.imported.len(), 1);
+        }
+        let saved = account::load_account(&existing.id).unwrap();
+        assert_eq!(saved.device_profile.as_ref().unwrap().machine_id, local.machine_id);
+        assert!(saved.device_history.iter().all(|h| !h.is_current));
+        // 重复导入不产生重复版本
+        let ids: Vec<&str> = saved.device_history.iter().map(|h| h.id.as_str()).collect();
+        assert_eq!(ids, vec!["v1", "v2"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_token_side_reports_refresh_result_without_full_token() {
+        let check = |refresh_token: String| async move {
+            if refresh_token.starts_with("good") {
+                Ok(TokenResponse {
+                    access_token: "a".to_string(),
+                    expires_in: 3600,
+                    token_type: "Bearer".to_string(),
+                    refresh_token: None,
+                })
+            } else {
+                Err("invalid_grant".to_string())
+            }
+        };
+
+        let good = token_side(&token("good-refresh-token-0001"), &check).await;
+        assert!(good.refreshes);
+        assert_eq!(good.refresh_token_hint, "good-r...0001");
+
+        let bad = token_side(&token("stale"), &check).await;
+        assert!(!bad.refreshes);
+        assert_eq!(bad.error.as_deref(), Some("invalid_grant"));
+        assert_eq!(bad.refresh_token_hint, "***");
+    }
+
+    #[tokio::test]
+    async fn test_import_conflict_is_resolved_end_to_end() {
+        let dir = std::env::temp_dir().join(format!("ag_import_conflict_e2e_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let _data_dir = account::use_test_data_dir(&dir);
+
+        let suffix = uuid::Uuid::new_v4().simple().to_string();
+        let email = format!("conflict-{}@example.com", suffix);
+        let local_refresh = format!("local-{}", suffix);
+        let imported_refresh = format!("imported-{}", suffix);
+        let existing = account::upsert_account(email.clone(), None, token(&local_refresh)).unwrap();
+
+        let checks = std::sync::atomic::AtomicUsize::new(0);
+        let check = |refresh_token: String| {
+            checks.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async move { Err::<TokenResponse, String>(format!("invalid_grant ({})", refresh_token.len())) }
+        };
+
+        let mut report = ImportReport::default();
+        import_account_with(
+            email.clone(),
+            None,
+            token(&imported_refresh),
+            ImportedDevice::default(),
+            ImportConflictPolicy::Ask,
+            &mut report,
+            &check,
+        )
+        .await
+        .unwrap();
+        assert!(report.imported.is_empty());
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].account_id, existing.id);
+        assert_eq!(checks.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert!(is_pending_refresh_token(&imported_refresh));
+        // 冲突未处理前不写入导入的 token
+        assert_eq!(account::load_account(&existing.id).unwrap().token.refresh_token, local_refresh);
+
+        // 再次导入同一 token (如周期同步) 直接返回已登记的冲突，不重复刷新
+        let mut again = ImportReport::default();
+        import_account_with(
+            email.clone(),
+            None,
+            token(&imported_refresh),
+            ImportedDevice::default(),
+            ImportConflictPolicy::Ask,
+            &mut again,
+            &check,
+        )
+        .await
+        .unwrap();
+        assert_eq!(again.conflicts.len(), 1);
+        assert_eq!(checks.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(pending_conflicts().iter().filter(|c| c.email == email).count(), 1);
+
+        let resolved = resolve_conflict(&email, ConflictChoice::UseImported).unwrap();
+        assert_eq!(resolved.id, existing.id);
+        assert_eq!(account::load_account(&existing.id).unwrap().token.refresh_token, imported_refresh);
+        assert!(!is_pending_refresh_token(&imported_refresh));
+        assert!(resolve_conflict(&email, ConflictChoice::KeepExisting).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
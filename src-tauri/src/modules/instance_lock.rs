@@ -0,0 +1,279 @@
+// 数据目录租约锁
+// 多台机器通过同步盘 (Dropbox / Syncthing) 共享同一数据目录时，只有持有租约的实例可以写入；
+// 其他实例发现新鲜的外部租约后以只读模式运行，避免双方同时写账号索引导致损坏。
+
+use crate::models::InstanceLockConfig;
+use crate::modules::{account, config, logger};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
+use tauri::Emitter;
+
+pub const LOCK_FILE: &str = "manager.lock";
+
+/// 租约变化事件 (载荷为 `InstanceLockStatus`)
+pub const LOCK_CHANGED_EVENT: &str = "instance://lock-changed";
+
+/// 租约文件内容
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LockInfo {
+    pub instance_id: String,
+    pub hostname: String,
+    /// 最近一次心跳 (Unix 秒)
+    pub heartbeat: i64,
+}
+
+/// 当前实例的租约状态 (供前端展示)
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct InstanceLockStatus {
+    pub enabled: bool,
+    pub read_only: bool,
+    pub instance_id: String,
+    /// 只读模式下持有租约的实例
+    pub owner: Option<LockInfo>,
+}
+
+static INSTANCE_ID: Lazy<String> = Lazy::new(|| uuid::Uuid::new_v4().to_string());
+
+#[derive(Default)]
+struct LockState {
+    enabled: bool,
+    owner: Option<LockInfo>,
+}
+
+static STATE: Lazy<RwLock<LockState>> = Lazy::new(|| RwLock::new(LockState::default()));
+
+/// 本机主机名 (无法获取时为 unknown)
+pub fn hostname() -> String {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn lock_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(LOCK_FILE)
+}
+
+/// 读取租约文件 (不存在或损坏时视为无租约)
+pub fn read_lock(data_dir: &Path) -> Option<LockInfo> {
+    let content = fs::read_to_string(lock_path(data_dir)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn write_lock(data_dir: &Path, info: &LockInfo) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(info).map_err(|e| format!("序列化租约失败: {}", e))?;
+    let temp_path = data_dir.join(format!("{}.tmp", LOCK_FILE));
+    fs::write(&temp_path, content).map_err(|e| format!("写入租约文件失败: {}", e))?;
+    fs::rename(&temp_path, lock_path(data_dir)).map_err(|e| format!("替换租约文件失败: {}", e))
+}
+
+/// 租约是否属于其他实例且仍在有效期内
+pub fn is_foreign_and_fresh(lock: &LockInfo, instance_id: &str, now: i64, stale_after_secs: u64) -> bool {
+    lock.instance_id != instance_id && now.saturating_sub(lock.heartbeat) < stale_after_secs as i64
+}
+
+/// 尝试获取 (或续期) 租约
+///
+/// 返回 `Ok(None)` 表示本实例持有租约；`Ok(Some(owner))` 表示其他实例持有新鲜租约，本实例应只读
+pub fn try_acquire(
+    data_dir: &Path,
+    instance_id: &str,
+    hostname: &str,
+    now: i64,
+    stale_after_secs: u64,
+) -> Result<Option<LockInfo>, String> {
+    if let Some(existing) = read_lock(data_dir) {
+        if is_foreign_and_fresh(&existing, instance_id, now, stale_after_secs) {
+            return Ok(Some(existing));
+        }
+    }
+    write_lock(
+        data_dir,
+        &LockInfo {
+            instance_id: instance_id.to_string(),
+            hostname: hostname.to_string(),
+            heartbeat: now,
+        },
+    )?;
+    Ok(None)
+}
+
+/// 当前租约状态
+pub fn status() -> InstanceLockStatus {
+    let state = STATE.read().unwrap_or_else(|e| e.into_inner());
+    InstanceLockStatus {
+        enabled: state.enabled,
+        read_only: state.owner.is_some(),
+        instance_id: INSTANCE_ID.clone(),
+        owner: state.owner.clone(),
+    }
+}
+
+/// 当前实例是否以只读模式运行 (后台定时任务据此静默跳过)
+pub fn is_read_only() -> bool {
+    STATE.read().unwrap_or_else(|e| e.into_inner()).owner.is_some()
+}
+
+/// 写操作前调用：只读模式下返回说明持有者的错误
+pub fn ensure_writable() -> Result<(), String> {
+    let state = STATE.read().unwrap_or_else(|e| e.into_inner());
+    match &state.owner {
+        Some(owner) => Err(format!(
+            "数据目录由其他实例持有 (主机: {})，当前以只读模式运行；如需写入请先“接管”数据目录",
+            owner.hostname
+        )),
+        None => Ok(()),
+    }
+}
+
+/// 更新内存状态，状态变化时通知前端
+fn set_state(app: Option<&tauri::AppHandle>, enabled: bool, owner: Option<LockInfo>) {
+    let changed = {
+        let mut state = STATE.write().unwrap_or_else(|e| e.into_inner());
+        let changed = state.enabled != enabled
+            || state.owner.as_ref().map(|o| &o.instance_id) != owner.as_ref().map(|o| &o.instance_id);
+        state.enabled = enabled;
+        state.owner = owner;
+        changed
+    };
+    if changed {
+        let current = status();
+        match &current.owner {
+            Some(owner) => logger::log_warn(&format!(
+                "[InstanceLock] 数据目录由 {} ({}) 持有，当前以只读模式运行",
+                owner.hostname, owner.instance_id
+            )),
+            None => logger::log_info("[InstanceLock] 已持有数据目录租约"),
+        }
+        if let Some(app) = app {
+            let _ = app.emit(LOCK_CHANGED_EVENT, &current);
+        }
+    }
+}
+
+/// 一次租约检查：持有者续期心跳，只读实例在外部租约过期后接管
+fn refresh(app: Option<&tauri::AppHandle>, cfg: &InstanceLockConfig) -> Result<(), String> {
+    let data_dir = account::get_data_dir()?;
+    let now = chrono::Utc::now().timestamp();
+    let owner = try_acquire(&data_dir, &INSTANCE_ID, &hostname(), now, cfg.stale_after_secs)?;
+    set_state(app, true, owner);
+    Ok(())
+}
+
+/// 启动租约检查 (功能关闭时不产生任何文件与后台任务)
+pub fn start_instance_lock(app_handle: tauri::AppHandle) {
+    let cfg = config::load_app_config().unwrap_or_default().instance_lock;
+    if !cfg.enabled {
+        return;
+    }
+    if let Err(e) = refresh(Some(&app_handle), &cfg) {
+        logger::log_error(&format!("[InstanceLock] 获取租约失败: {}", e));
+    }
+
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(cfg.heartbeat_secs.max(1)));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = refresh(Some(&app_handle), &cfg) {
+                logger::log_error(&format!("[InstanceLock] 租约心跳失败: {}", e));
+            }
+        }
+    });
+}
+
+/// 强制接管数据目录 (覆盖其他实例的租约，需前端二次确认)
+pub fn force_take_ownership(app: &tauri::AppHandle) -> Result<InstanceLockStatus, String> {
+    let data_dir = account::get_data_dir()?;
+    let previous = read_lock(&data_dir);
+    write_lock(
+        &data_dir,
+        &LockInfo {
+            instance_id: INSTANCE_ID.clone(),
+            hostname: hostname(),
+            heartbeat: chrono::Utc::now().timestamp(),
+        },
+    )?;
+    if let Some(prev) = previous.filter(|p| p.instance_id != *INSTANCE_ID) {
+        logger::log_warn(&format!(
+            "[InstanceLock] 已强制接管数据目录 (原持有者: {} / {})",
+            prev.hostname, prev.instance_id
+        ));
+    }
+    let enabled = STATE.read().unwrap_or_else(|e| e.into_inner()).enabled;
+    set_state(Some(app), enabled, None);
+    Ok(status())
+}
+
+/// 退出时释放本实例持有的租约
+pub fn release() {
+    if !status().enabled || status().read_only {
+        return;
+    }
+    if let Ok(data_dir) = account::get_data_dir() {
+        if read_lock(&data_dir).map(|l| l.instance_id == *INSTANCE_ID).unwrap_or(false) {
+            let _ = fs::remove_file(lock_path(&data_dir));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ag_lock_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_fresh_foreign_lock_forces_read_only() {
+        let dir = temp_dir();
+        assert_eq!(try_acquire(&dir, "a", "host-a", 1_000, 120).unwrap(), None);
+
+        // 另一实例在有效期内启动：只读，且不覆盖租约
+        let owner = try_acquire(&dir, "b", "host-b", 1_060, 120).unwrap().expect("should be read-only");
+        assert_eq!(owner.hostname, "host-a");
+        assert_eq!(read_lock(&dir).unwrap().instance_id, "a");
+
+        // 持有者续期
+        assert_eq!(try_acquire(&dir, "a", "host-a", 1_090, 120).unwrap(), None);
+        assert_eq!(read_lock(&dir).unwrap().heartbeat, 1_090);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_stale_foreign_lock_is_taken_over() {
+        let dir = temp_dir();
+        try_acquire(&dir, "a", "host-a", 1_000, 120).unwrap();
+
+        assert_eq!(try_acquire(&dir, "b", "host-b", 1_120, 120).unwrap(), None);
+        let lock = read_lock(&dir).unwrap();
+        assert_eq!(lock.instance_id, "b");
+        assert_eq!(lock.hostname, "host-b");
+
+        // 原持有者随后发现租约被接管
+        let owner = try_acquire(&dir, "a", "host-a", 1_130, 120).unwrap();
+        assert_eq!(owner.map(|o| o.instance_id), Some("b".to_string()));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_corrupt_lock_is_ignored() {
+        let dir = temp_dir();
+        fs::write(dir.join(LOCK_FILE), "not json").unwrap();
+        assert_eq!(try_acquire(&dir, "a", "host-a", 1_000, 120).unwrap(), None);
+        assert_eq!(read_lock(&dir).unwrap().instance_id, "a");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
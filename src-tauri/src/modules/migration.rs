@@ -1,13 +1,14 @@
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use serde_json::Value;
 use base64::{Engine as _, engine::general_purpose};
-use crate::models::{TokenData, Account};
-use crate::modules::{account, db};
+use crate::models::TokenData;
+use crate::modules::db;
+use crate::modules::import_conflict::{self, ImportReport, ImportedDevice};
 use crate::utils::protobuf;
 
 /// 扫描并导入 V1 数据
-pub async fn import_from_v1() -> Result<Vec<Account>, String> {
+pub async fn import_from_v1() -> Result<ImportReport, String> {
     use crate::modules::oauth;
 
     let home = dirs::home_dir().ok_or("无法获取主目录")?;
@@ -15,7 +16,8 @@ pub async fn import_from_v1() -> Result<Vec<Account>, String> {
     // V1 数据目录 (根据 utils.py 确认全平台统一)
     let v1_dir = home.join(".antigravity-agent");
     
-    let mut imported_accounts = Vec::new();
+    let mut report = ImportReport::default();
+    let policy = import_conflict::configured_policy();
     
     // 尝试多个可能的文件名
     let index_files = vec![
@@ -166,11 +168,10 @@ pub async fn import_from_v1() -> Result<Vec<Account>, String> {
                     );
                         
                         // 在第153行的get_user_info中已经获取name，但这里是在match语句外，我们巴安全起见使用None
-                        match account::upsert_account(email.clone(), None, token_data) {
-                            Ok(acc) => {
-                                crate::modules::logger::log_info(&format!("导入成功: {}", email));
-                                imported_accounts.push(acc);
-                            },
+                        // V2/脚本导出的账号数据可能带有设备指纹，一并导入
+                        let device = ImportedDevice::from_json(&backup_json);
+                        match import_conflict::import_account(email.clone(), None, token_data, device, policy, &mut report).await {
+                            Ok(()) => crate::modules::logger::log_info(&format!("导入完成: {}", email)),
                             Err(e) => crate::modules::logger::log_error(&format!("导入保存失败 {}: {}", email, e)),
                         }
 
@@ -186,11 +187,23 @@ pub async fn import_from_v1() -> Result<Vec<Account>, String> {
         return Err("未找到 V1 版本账号数据文件".to_string());
     }
     
-    Ok(imported_accounts)
+    Ok(report)
+}
+
+/// 读取数据库同目录下 storage.json 中的设备指纹，作为导入账号的指纹基线
+fn capture_storage_device(db_path: &Path) -> ImportedDevice {
+    let storage_path = db_path.with_file_name("storage.json");
+    match crate::modules::device::read_profile(&storage_path) {
+        Ok(profile) => ImportedDevice { profile: Some(profile), history: Vec::new() },
+        Err(e) => {
+            crate::modules::logger::log_warn(&format!("未能采集设备指纹，导入时不绑定: {}", e));
+            ImportedDevice::default()
+        }
+    }
 }
 
-/// 从自定义数据库路径导入账号
-pub async fn import_from_custom_db_path(path_str: String) -> Result<Account, String> {
+/// 从自定义数据库路径导入账号；capture_device 时将同目录 storage.json 的指纹绑定到导入的账号
+pub async fn import_from_custom_db_path(path_str: String, capture_device: bool) -> Result<ImportReport, String> {
     use crate::modules::oauth;
 
     let path = PathBuf::from(path_str);
@@ -218,14 +231,17 @@ pub async fn import_from_custom_db_path(path_str: String) -> Result<Account, Str
         None, // session_id 将在 token_manager 中生成
     );
     
-    // 4. 添加或更新账号
-    account::upsert_account(email.clone(), user_info.name, token_data)
+    // 4. 添加或更新账号 (与已有 token 冲突时按策略处理)
+    let device = if capture_device { capture_storage_device(&path) } else { ImportedDevice::default() };
+    let mut report = ImportReport::default();
+    import_conflict::import_account(email, user_info.name, token_data, device, import_conflict::configured_policy(), &mut report).await?;
+    Ok(report)
 }
 
 /// 从默认 IDE 数据库导入当前登录账号
-pub async fn import_from_db() -> Result<Account, String> {
+pub async fn import_from_db(capture_device: bool) -> Result<ImportReport, String> {
     let db_path = db::get_db_path()?;
-    import_from_custom_db_path(db_path.to_string_lossy().to_string()).await
+    import_from_custom_db_path(db_path.to_string_lossy().to_string(), capture_device).await
 }
 
 /// 从数据库获取当前 Refresh Token (通用逻辑)
@@ -1,4 +1,5 @@
 pub mod account;
+pub mod account_notifier;
 pub mod quota;
 pub mod config;
 pub mod logger;
@@ -12,7 +13,22 @@ pub mod i18n;
 pub mod proxy_db;
 pub mod device;
 pub mod update_checker;
+pub mod update_installer;
 pub mod scheduler;
+pub mod token_crypto;
+pub mod backup;
+pub mod instance_lock;
+pub mod account_report;
+pub mod account_snooze;
+pub mod account_reliability;
+pub mod account_schema;
+pub mod refresh_benchmark;
+pub mod model_mapping_store;
+pub mod import_conflict;
+pub mod proxy_disable;
+pub mod switch_plan;
+pub mod account_trash;
+pub mod diagnostics;
 
 use crate::models;
 
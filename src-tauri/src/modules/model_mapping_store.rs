@@ -0,0 +1,257 @@
+// 模型映射独立存储
+// 自定义映射保存在数据目录的 model_mapping.json 中，不再随 gui_config.json 整体重写；
+// 每次变更追加一条历史 (时间、来源、各别名的旧值/新值)，可按版本回滚。
+// 旧版本写在 gui_config.json 的 proxy.custom_mapping 会在加载配置时迁移到这里。
+
+use crate::proxy::config::ModelMappingTarget;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub const MODEL_MAPPING_FILE: &str = "model_mapping.json";
+
+/// 串行化映射文件的读改写
+static STORE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+pub type ModelMapping = HashMap<String, ModelMappingTarget>;
+
+/// 映射变更的来源
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MappingChangeSource {
+    /// 界面编辑 (保存配置 / 更新映射)
+    Ui,
+    /// 导入映射模板
+    Import,
+    /// 自动迁移 (旧配置文件中的映射)
+    Auto,
+    /// 回滚到历史版本
+    Revert,
+}
+
+/// 单个别名的变更，`old` / `new` 为 None 分别表示新增 / 删除
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MappingChange {
+    pub alias: String,
+    pub old: Option<ModelMappingTarget>,
+    pub new: Option<ModelMappingTarget>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MappingHistoryEntry {
+    /// 变更后的版本号
+    pub version: u64,
+    pub timestamp: i64,
+    pub source: MappingChangeSource,
+    pub changes: Vec<MappingChange>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct MappingStore {
+    #[serde(default)]
+    version: u64,
+    #[serde(default)]
+    mapping: ModelMapping,
+    #[serde(default)]
+    history: Vec<MappingHistoryEntry>,
+}
+
+fn store_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(MODEL_MAPPING_FILE)
+}
+
+fn read_store(data_dir: &Path) -> Result<Option<MappingStore>, String> {
+    let path = store_path(data_dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(&path).map_err(|e| format!("读取模型映射失败: {}", e))?;
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| format!("解析模型映射失败: {}", e))
+}
+
+fn write_store(data_dir: &Path, store: &MappingStore) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(store).map_err(|e| format!("序列化模型映射失败: {}", e))?;
+    std::fs::write(store_path(data_dir), content).map_err(|e| format!("保存模型映射失败: {}", e))
+}
+
+/// 两份映射的差异 (按别名排序)
+fn diff(old: &ModelMapping, new: &ModelMapping) -> Vec<MappingChange> {
+    let mut aliases: Vec<&String> = old.keys().chain(new.keys()).collect();
+    aliases.sort();
+    aliases.dedup();
+    aliases
+        .into_iter()
+        .filter(|alias| old.get(*alias) != new.get(*alias))
+        .map(|alias| MappingChange {
+            alias: alias.clone(),
+            old: old.get(alias).cloned(),
+            new: new.get(alias).cloned(),
+        })
+        .collect()
+}
+
+fn apply_change(store: &mut MappingStore, mapping: &ModelMapping, source: MappingChangeSource, history_limit: usize) -> bool {
+    let changes = diff(&store.mapping, mapping);
+    if changes.is_empty() {
+        return false;
+    }
+    store.version += 1;
+    store.mapping = mapping.clone();
+    store.history.push(MappingHistoryEntry {
+        version: store.version,
+        timestamp: chrono::Utc::now().timestamp(),
+        source,
+        changes,
+    });
+    if store.history.len() > history_limit {
+        let excess = store.history.len() - history_limit;
+        store.history.drain(..excess);
+    }
+    true
+}
+
+/// 读取当前映射，映射文件不存在时返回 None
+pub fn load_mapping(data_dir: &Path) -> Result<Option<ModelMapping>, String> {
+    let _lock = STORE_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    Ok(read_store(data_dir)?.map(|s| s.mapping))
+}
+
+/// 保存映射；有变化时追加一条历史并裁剪到 `history_limit` 条，返回是否有变化
+pub fn save_mapping(
+    data_dir: &Path,
+    mapping: &ModelMapping,
+    source: MappingChangeSource,
+    history_limit: usize,
+) -> Result<bool, String> {
+    let _lock = STORE_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    let mut store = read_store(data_dir)?.unwrap_or_default();
+    if !apply_change(&mut store, mapping, source, history_limit) {
+        return Ok(false);
+    }
+    write_store(data_dir, &store)?;
+    Ok(true)
+}
+
+/// 变更历史 (按时间先后)
+pub fn load_history(data_dir: &Path) -> Result<Vec<MappingHistoryEntry>, String> {
+    let _lock = STORE_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    Ok(read_store(data_dir)?.map(|s| s.history).unwrap_or_default())
+}
+
+/// 撤销 `version` 之后的所有变更，得到该版本时的映射
+fn mapping_at(store: &MappingStore, version: u64) -> Result<ModelMapping, String> {
+    if version > store.version {
+        return Err(format!("版本 {} 不存在 (当前版本 {})", version, store.version));
+    }
+    let oldest = store.history.first().map(|e| e.version).unwrap_or(store.version + 1);
+    if version + 1 < oldest {
+        return Err(format!("版本 {} 已超出保留的历史范围 (最早可回滚到 {})", version, oldest - 1));
+    }
+
+    let mut mapping = store.mapping.clone();
+    for entry in store.history.iter().rev().take_while(|e| e.version > version) {
+        for change in &entry.changes {
+            match &change.old {
+                Some(target) => {
+                    mapping.insert(change.alias.clone(), target.clone());
+                }
+                None => {
+                    mapping.remove(&change.alias);
+                }
+            }
+        }
+    }
+    Ok(mapping)
+}
+
+/// 回滚到 `version` 时的映射 (回滚本身也记为一次变更)，返回回滚后的映射
+pub fn revert_to(data_dir: &Path, version: u64, history_limit: usize) -> Result<ModelMapping, String> {
+    let _lock = STORE_LOCK.lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    let mut store = read_store(data_dir)?.ok_or_else(|| "尚无模型映射历史".to_string())?;
+    let mapping = mapping_at(&store, version)?;
+    if apply_change(&mut store, &mapping, MappingChangeSource::Revert, history_limit) {
+        write_store(data_dir, &store)?;
+    }
+    Ok(mapping)
+}
+
+/// 诊断导出用的历史摘要：只保留版本、时间、来源与变更的别名，不含映射目标
+pub fn history_summary(history: &[MappingHistoryEntry]) -> Value {
+    Value::Array(
+        history
+            .iter()
+            .map(|e| {
+                json!({
+                    "version": e.version,
+                    "timestamp": e.timestamp,
+                    "source": e.source,
+                    "aliases": e.changes.iter().map(|c| c.alias.as_str()).collect::<Vec<_>>(),
+                })
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(pairs: &[(&str, &str)]) -> ModelMapping {
+        pairs.iter().map(|(k, v)| (k.to_string(), (*v).into())).collect()
+    }
+
+    #[test]
+    fn test_history_records_changes_and_reverts() {
+        let dir = std::env::temp_dir().join(format!("ag_mapping_store_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let v1 = mapping(&[("gpt-4o", "gemini-3-flash"), ("o1", "gemini-3-pro-high")]);
+        assert!(save_mapping(&dir, &v1, MappingChangeSource::Ui, 10).unwrap());
+        assert!(!save_mapping(&dir, &v1, MappingChangeSource::Ui, 10).unwrap());
+
+        // 误操作清空了映射
+        assert!(save_mapping(&dir, &ModelMapping::new(), MappingChangeSource::Ui, 10).unwrap());
+        let history = load_history(&dir).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].changes.len(), 2);
+        assert_eq!(history[1].changes[0].alias, "gpt-4o");
+        assert!(history[1].changes[0].new.is_none());
+
+        assert_eq!(revert_to(&dir, 1, 10).unwrap(), v1);
+        assert_eq!(load_mapping(&dir).unwrap().unwrap(), v1);
+        let history = load_history(&dir).unwrap();
+        assert_eq!(history.last().unwrap().source, MappingChangeSource::Revert);
+        assert_eq!(history.last().unwrap().version, 3);
+
+        assert!(revert_to(&dir, 9, 10).is_err());
+        let summary = history_summary(&history);
+        assert_eq!(summary[0]["aliases"], json!(["gpt-4o", "o1"]));
+        assert!(summary[0].get("changes").is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_history_is_capped() {
+        let dir = std::env::temp_dir().join(format!("ag_mapping_cap_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for i in 0..5 {
+            let target = format!("gemini-{}", i);
+            save_mapping(&dir, &mapping(&[("gpt-4o", target.as_str())]), MappingChangeSource::Ui, 3).unwrap();
+        }
+        let history = load_history(&dir).unwrap();
+        assert_eq!(history.iter().map(|e| e.version).collect::<Vec<_>>(), vec![3, 4, 5]);
+
+        // 最早可回滚到版本 2 (最早保留的变更之前)
+        assert_eq!(revert_to(&dir, 2, 3).unwrap(), mapping(&[("gpt-4o", "gemini-1")]));
+        assert!(revert_to(&dir, 1, 3).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
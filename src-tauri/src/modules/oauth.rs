@@ -49,7 +49,8 @@ impl UserInfo {
 
 
 /// 生成 OAuth 授权 URL
-pub fn get_auth_url(redirect_uri: &str) -> String {
+/// `login_hint` 用于重新授权指定账号时预选 Google 账号
+pub fn get_auth_url(redirect_uri: &str, login_hint: Option<&str>) -> String {
     let scopes = vec![
         "https://www.googleapis.com/auth/cloud-platform",
         "https://www.googleapis.com/auth/userinfo.email",
@@ -58,7 +59,7 @@ pub fn get_auth_url(redirect_uri: &str) -> String {
         "https://www.googleapis.com/auth/experimentsandconfigs"
     ].join(" ");
 
-    let params = vec![
+    let mut params = vec![
         ("client_id", CLIENT_ID),
         ("redirect_uri", redirect_uri),
         ("response_type", "code"),
@@ -67,6 +68,9 @@ pub fn get_auth_url(redirect_uri: &str) -> String {
         ("prompt", "consent"),
         ("include_granted_scopes", "true"),
     ];
+    if let Some(hint) = login_hint {
+        params.push(("login_hint", hint));
+    }
     
     let url = url::Url::parse_with_params(AUTH_URL, &params).expect("无效的 Auth URL");
     url.to_string()
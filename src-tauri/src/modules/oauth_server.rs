@@ -40,7 +40,10 @@ fn oauth_fail_html() -> &'static str {
     </html>"
 }
 
-async fn ensure_oauth_flow_prepared(app_handle: &tauri::AppHandle) -> Result<String, String> {
+async fn ensure_oauth_flow_prepared(
+    app_handle: &tauri::AppHandle,
+    login_hint: Option<&str>,
+) -> Result<String, String> {
     use tauri::Emitter;
 
     // 如果已有 flow，直接返回 URL
@@ -111,7 +114,7 @@ async fn ensure_oauth_flow_prepared(app_handle: &tauri::AppHandle) -> Result<Str
         format!("http://[::1]:{}/oauth-callback", port)
     };
 
-    let auth_url = oauth::get_auth_url(&redirect_uri);
+    let auth_url = oauth::get_auth_url(&redirect_uri, login_hint);
 
     // 取消信号（支持多消费者）
     let (cancel_tx, cancel_rx) = watch::channel(false);
@@ -219,7 +222,7 @@ async fn ensure_oauth_flow_prepared(app_handle: &tauri::AppHandle) -> Result<Str
 
 /// 预生成 OAuth URL (不打开浏览器、不阻塞等待回调)
 pub async fn prepare_oauth_url(app_handle: tauri::AppHandle) -> Result<String, String> {
-    ensure_oauth_flow_prepared(&app_handle).await
+    ensure_oauth_flow_prepared(&app_handle, None).await
 }
 
 /// 取消当前的 OAuth 流程
@@ -234,8 +237,24 @@ pub fn cancel_oauth_flow() {
 
 /// 启动 OAuth 流程并等待回调，再交换 token
 pub async fn start_oauth_flow(app_handle: tauri::AppHandle) -> Result<oauth::TokenResponse, String> {
+    run_oauth_flow(app_handle, None).await
+}
+
+/// 为指定账号重新授权：丢弃已预生成的 flow，以 login_hint 预选该账号后启动授权
+pub async fn start_oauth_flow_for_account(
+    app_handle: tauri::AppHandle,
+    email: &str,
+) -> Result<oauth::TokenResponse, String> {
+    cancel_oauth_flow();
+    run_oauth_flow(app_handle, Some(email)).await
+}
+
+async fn run_oauth_flow(
+    app_handle: tauri::AppHandle,
+    login_hint: Option<&str>,
+) -> Result<oauth::TokenResponse, String> {
     // 确保已准备好 URL + listener（这样即使用户先授权，也不会卡住）
-    let auth_url = ensure_oauth_flow_prepared(&app_handle).await?;
+    let auth_url = ensure_oauth_flow_prepared(&app_handle, login_hint).await?;
 
     // 打开默认浏览器
     use tauri_plugin_opener::OpenerExt;
@@ -279,7 +298,7 @@ pub async fn start_oauth_flow(app_handle: tauri::AppHandle) -> Result<oauth::Tok
 /// а мы только ждём callback и обмениваем code на token.
 pub async fn complete_oauth_flow(app_handle: tauri::AppHandle) -> Result<oauth::TokenResponse, String> {
     // Ensure URL + listeners exist
-    let _ = ensure_oauth_flow_prepared(&app_handle).await?;
+    let _ = ensure_oauth_flow_prepared(&app_handle, None).await?;
 
     // Take receiver to wait for code
     let (code_rx, redirect_uri) = {
@@ -701,6 +701,53 @@ pub fn close_antigravity(timeout_secs: u64) -> Result<(), String> {
     Ok(())
 }
 
+/// 切换账号时对 IDE 进程的操作 (测试中注入慢速的假实现，不触碰真实进程)
+pub trait IdeProcess: Send + Sync + 'static {
+    /// 若在运行则关闭
+    fn close_if_running(&self, timeout_secs: u64) -> Result<(), String>;
+    /// 启动
+    fn start(&self) -> Result<(), String>;
+}
+
+/// 操作真实的 Antigravity 进程
+pub struct SystemIdeProcess;
+
+impl IdeProcess for SystemIdeProcess {
+    fn close_if_running(&self, timeout_secs: u64) -> Result<(), String> {
+        if is_antigravity_running() {
+            close_antigravity(timeout_secs)?;
+        }
+        Ok(())
+    }
+
+    fn start(&self) -> Result<(), String> {
+        start_antigravity()
+    }
+}
+
+/// 在阻塞线程池中执行进程操作，长时间等待不占用异步运行时线程
+async fn run_process_op<F>(op: F) -> Result<(), String>
+where
+    F: FnOnce() -> Result<(), String> + Send + 'static,
+{
+    tokio::task::spawn_blocking(op)
+        .await
+        .map_err(|e| format!("进程操作任务异常退出: {}", e))?
+}
+
+/// 若 Antigravity 在运行则关闭 (异步版本，切换账号时使用)
+pub async fn close_antigravity_if_running(
+    ide: std::sync::Arc<dyn IdeProcess>,
+    timeout_secs: u64,
+) -> Result<(), String> {
+    run_process_op(move || ide.close_if_running(timeout_secs)).await
+}
+
+/// 启动 Antigravity (异步版本，切换账号时使用)
+pub async fn start_antigravity_async(ide: std::sync::Arc<dyn IdeProcess>) -> Result<(), String> {
+    run_process_op(move || ide.start()).await
+}
+
 /// 启动 Antigravity
 pub fn start_antigravity() -> Result<(), String> {
     crate::modules::logger::log_info("正在启动 Antigravity...");
@@ -862,6 +909,15 @@ pub fn start_antigravity() -> Result<(), String> {
 ///
 /// 这是最可靠的方法，可以找到任意位置的安装和启动参数
 fn get_process_info() -> (Option<std::path::PathBuf>, Option<Vec<String>>) {
+    match collect_running_processes().into_iter().next() {
+        Some((path, args)) => (Some(path), Some(args)),
+        None => (None, None),
+    }
+}
+
+/// 收集所有运行中的 Antigravity 主进程 (可执行文件路径, 启动参数)
+fn collect_running_processes() -> Vec<(std::path::PathBuf, Vec<String>)> {
+    let mut found = Vec::new();
     let mut system = System::new_all();
     system.refresh_all();
 
@@ -893,12 +949,12 @@ fn get_process_info() -> (Option<std::path::PathBuf>, Option<Vec<String>>) {
                 .map_or(exe.to_string_lossy(), |arg| arg.to_string_lossy())
                 .to_lowercase();
 
-            // 从命令行参数中提取真正的参数（跳过可执行文件路径）
+            // 从命令行参数中提取真正的参数（跳过可执行文件路径），保留原始大小写以便持久化
             let args = args
-                .map(|arg| arg.to_string_lossy().to_lowercase())
+                .map(|arg| arg.to_string_lossy().to_string())
                 .collect::<Vec<String>>();
 
-            let args_str = args.join(" ");
+            let args_str = args.join(" ").to_lowercase();
 
             // 通用的辅助进程排除逻辑
             let is_helper = args_str.contains("--type=")
@@ -916,8 +972,7 @@ fn get_process_info() -> (Option<std::path::PathBuf>, Option<Vec<String>>) {
                 || name.contains("sandbox")
                 || exe_path.contains("crashpad");
 
-            let path = Some(exe.to_path_buf());
-            let args = Some(args);
+            let path = exe.to_path_buf();
             #[cfg(target_os = "macos")]
             {
                 // macOS: 排除辅助进程,只匹配主程序,并检查 Frameworks
@@ -928,10 +983,11 @@ fn get_process_info() -> (Option<std::path::PathBuf>, Option<Vec<String>>) {
                     // 尝试提取 .app 路径以便更好地支持 open 命令
                     if let Some(app_idx) = exe_path.find(".app") {
                         let app_path_str = &exe.to_string_lossy()[..app_idx + 4];
-                        let path = Some(std::path::PathBuf::from(app_path_str));
-                        return (path, args);
+                        found.push((std::path::PathBuf::from(app_path_str), args));
+                        continue;
                     }
-                    return (path, args);
+                    found.push((path, args));
+                    continue;
                 }
             }
 
@@ -939,7 +995,8 @@ fn get_process_info() -> (Option<std::path::PathBuf>, Option<Vec<String>>) {
             {
                 // Windows: 严格匹配进程名且排除辅助进程
                 if name == "antigravity.exe" && !is_helper {
-                    return (path, args);
+                    found.push((path, args));
+                    continue;
                 }
             }
 
@@ -950,12 +1007,13 @@ fn get_process_info() -> (Option<std::path::PathBuf>, Option<Vec<String>>) {
                     && !name.contains("tools")
                     && !is_helper
                 {
-                    return (path, args);
+                    found.push((path, args));
+                    continue;
                 }
             }
         }
     }
-    (None, None)
+    found
 }
 
 /// 从运行中的进程获取 Antigravity 可执行文件路径
@@ -972,6 +1030,31 @@ pub fn get_args_from_running_process() -> Option<Vec<String>> {
     args
 }
 
+/// 参数列表中的 --user-data-dir 参数 (`--user-data-dir=路径` 或 `--user-data-dir 路径` 两种形式)
+fn user_data_dir_arg(args: &[String]) -> Option<Vec<String>> {
+    let idx = args
+        .iter()
+        .position(|a| a == "--user-data-dir" || a.starts_with("--user-data-dir="))?;
+    if args[idx] == "--user-data-dir" {
+        args.get(idx + 1).map(|value| vec![args[idx].clone(), value.clone()])
+    } else {
+        Some(vec![args[idx].clone()])
+    }
+}
+
+/// 合并检测到的启动参数与配置中已有的参数：
+/// 检测结果自带 --user-data-dir 时以检测结果为准，否则保留配置中原有的数据目录
+pub fn merge_launch_args(detected: Vec<String>, existing: Option<&[String]>) -> Vec<String> {
+    if user_data_dir_arg(&detected).is_some() {
+        return detected;
+    }
+    let mut merged = detected;
+    if let Some(user_data_dir) = existing.and_then(user_data_dir_arg) {
+        merged.extend(user_data_dir);
+    }
+    merged
+}
+
 /// 获取 --user-data-dir 参数值（如果存在）
 pub fn get_user_data_dir_from_process() -> Option<std::path::PathBuf> {
     // 优先从配置中获取启动参数
@@ -1044,11 +1127,21 @@ pub fn get_antigravity_executable_path() -> Option<std::path::PathBuf> {
 
 /// 检查标准安装位置
 fn check_standard_locations() -> Option<std::path::PathBuf> {
+    standard_location_candidates()
+        .into_iter()
+        .find(|path| path.exists())
+}
+
+/// 当前平台的标准安装位置 (按优先级排序，不检查是否存在)
+fn standard_location_candidates() -> Vec<std::path::PathBuf> {
+    #[allow(unused_mut)]
+    let mut possible_paths: Vec<std::path::PathBuf> = Vec::new();
+
     #[cfg(target_os = "macos")]
     {
-        let path = std::path::PathBuf::from("/Applications/Antigravity.app");
-        if path.exists() {
-            return Some(path);
+        possible_paths.push(std::path::PathBuf::from("/Applications/Antigravity.app"));
+        if let Some(home) = dirs::home_dir() {
+            possible_paths.push(home.join("Applications/Antigravity.app"));
         }
     }
 
@@ -1063,8 +1156,6 @@ fn check_standard_locations() -> Option<std::path::PathBuf> {
         let program_files_x86 =
             env::var("ProgramFiles(x86)").unwrap_or_else(|_| "C:\\Program Files (x86)".to_string());
 
-        let mut possible_paths = Vec::new();
-
         // 用户安装位置（优先）
         if let Some(local) = local_appdata {
             possible_paths.push(
@@ -1088,37 +1179,146 @@ fn check_standard_locations() -> Option<std::path::PathBuf> {
                 .join("Antigravity")
                 .join("Antigravity.exe"),
         );
-
-        // 返回第一个存在的路径
-        for path in possible_paths {
-            if path.exists() {
-                return Some(path);
-            }
-        }
     }
 
     #[cfg(target_os = "linux")]
     {
-        let possible_paths = vec![
-            std::path::PathBuf::from("/usr/bin/antigravity"),
-            std::path::PathBuf::from("/opt/Antigravity/antigravity"),
-            std::path::PathBuf::from("/usr/share/antigravity/antigravity"),
-        ];
-
-        // 用户本地安装
+        // 用户本地安装（优先）
         if let Some(home) = dirs::home_dir() {
-            let user_local = home.join(".local/bin/antigravity");
-            if user_local.exists() {
-                return Some(user_local);
-            }
+            possible_paths.push(home.join(".local/bin/antigravity"));
         }
 
-        for path in possible_paths {
-            if path.exists() {
-                return Some(path);
-            }
+        possible_paths.push(std::path::PathBuf::from("/usr/bin/antigravity"));
+        possible_paths.push(std::path::PathBuf::from("/opt/Antigravity/antigravity"));
+        possible_paths.push(std::path::PathBuf::from("/usr/share/antigravity/antigravity"));
+    }
+
+    possible_paths
+}
+
+/// Antigravity 安装的发现来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallationSource {
+    /// 当前配置中手动指定的路径
+    Configured,
+    /// 运行中的进程
+    RunningProcess,
+    /// 标准安装位置
+    StandardLocation,
+}
+
+/// 一个 Antigravity 安装
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AntigravityInstallation {
+    pub path: String,
+    pub version: Option<String>,
+    pub source: InstallationSource,
+}
+
+/// 列出本机所有 Antigravity 安装 (运行中进程 / 配置 / 标准位置)，供 UI 选择写入 `antigravity_executable`
+pub fn list_antigravity_installations() -> Vec<AntigravityInstallation> {
+    let mut candidates: Vec<(std::path::PathBuf, InstallationSource)> = collect_running_processes()
+        .into_iter()
+        .map(|(path, _)| (path, InstallationSource::RunningProcess))
+        .collect();
+
+    if let Some(configured) = crate::modules::config::load_app_config()
+        .ok()
+        .and_then(|c| c.antigravity_executable)
+    {
+        candidates.push((std::path::PathBuf::from(configured), InstallationSource::Configured));
+    }
+
+    candidates.extend(
+        standard_location_candidates()
+            .into_iter()
+            .map(|path| (path, InstallationSource::StandardLocation)),
+    );
+
+    build_installation_list(candidates)
+}
+
+/// 过滤不存在的候选路径并按规范化路径去重，保留首次出现的来源
+fn build_installation_list(
+    candidates: Vec<(std::path::PathBuf, InstallationSource)>,
+) -> Vec<AntigravityInstallation> {
+    let mut seen = std::collections::HashSet::new();
+    let mut installations = Vec::new();
+
+    for (path, source) in candidates {
+        if !path.exists() {
+            continue;
         }
+        // 符号链接 (如 /usr/bin/antigravity) 与其目标视为同一安装
+        let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if !seen.insert(key) {
+            continue;
+        }
+        installations.push(AntigravityInstallation {
+            path: path.to_string_lossy().to_string(),
+            version: detect_installation_version(&path),
+            source,
+        });
     }
 
-    None
+    installations
+}
+
+/// 从安装目录中的 package.json 读取版本号 (读取失败时返回 None)
+fn detect_installation_version(path: &std::path::Path) -> Option<String> {
+    let resolved = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+    let package_json = if resolved.extension().and_then(|e| e.to_str()) == Some("app") {
+        // macOS: /Applications/Antigravity.app/Contents/Resources/app/package.json
+        resolved.join("Contents/Resources/app/package.json")
+    } else {
+        // Windows / Linux: <安装目录>/resources/app/package.json
+        resolved.parent()?.join("resources/app/package.json")
+    };
+
+    let content = std::fs::read_to_string(package_json).ok()?;
+    let value: serde_json::Value = serde_json::from_str(&content).ok()?;
+    value
+        .get("version")
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_installation_list_dedups_and_labels_sources() {
+        let root = std::env::temp_dir().join(format!("ag_install_test_{}", uuid::Uuid::new_v4()));
+        let stable = root.join("stable");
+        let insiders = root.join("insiders");
+        for dir in [&stable, &insiders] {
+            std::fs::create_dir_all(dir.join("resources/app")).unwrap();
+            std::fs::write(dir.join("antigravity"), "").unwrap();
+        }
+        std::fs::write(
+            stable.join("resources/app/package.json"),
+            r#"{"name":"antigravity","version":"1.2.3"}"#,
+        )
+        .unwrap();
+
+        let list = build_installation_list(vec![
+            (stable.join("antigravity"), InstallationSource::RunningProcess),
+            (insiders.join("antigravity"), InstallationSource::Configured),
+            // 同一安装经不同路径写法再次出现，应被去重
+            (stable.join("resources/../antigravity"), InstallationSource::StandardLocation),
+            (root.join("missing/antigravity"), InstallationSource::StandardLocation),
+        ]);
+
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].source, InstallationSource::RunningProcess);
+        assert_eq!(list[0].version.as_deref(), Some("1.2.3"));
+        assert_eq!(list[1].source, InstallationSource::Configured);
+        assert_eq!(list[1].version, None);
+        assert!(list[1].path.ends_with("antigravity"));
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
 }
@@ -1,16 +1,23 @@
 use rusqlite::{params, Connection};
 use std::path::PathBuf;
 use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::pricing::{pico_to_usd, UnpricedUsage, UsageCostReport, UsageCostRow, UsageRange};
 
 pub fn get_proxy_db_path() -> Result<PathBuf, String> {
     let data_dir = crate::modules::account::get_data_dir()?;
     Ok(data_dir.join("proxy_logs.db"))
 }
 
+/// 终端用户统计返回的用户数
+const TOP_END_USERS: usize = 10;
+
 pub fn init_db() -> Result<(), String> {
     let db_path = get_proxy_db_path()?;
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-    
+    init_schema(&conn)
+}
+
+fn init_schema(conn: &Connection) -> Result<(), String> {
     conn.execute(
         "CREATE TABLE IF NOT EXISTS request_logs (
             id TEXT PRIMARY KEY,
@@ -32,6 +39,14 @@ pub fn init_db() -> Result<(), String> {
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN output_tokens INTEGER", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN account_email TEXT", []);
     let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN mapped_model TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN replay_of TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN priority TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN account_tier TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN locally_answered INTEGER DEFAULT 0", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN end_user TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN upstream_region TEXT", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN cached_tokens INTEGER", []);
+    let _ = conn.execute("ALTER TABLE request_logs ADD COLUMN cost_pico INTEGER", []);
 
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_timestamp ON request_logs (timestamp DESC)",
@@ -44,16 +59,66 @@ pub fn init_db() -> Result<(), String> {
         [],
     ).map_err(|e| e.to_string())?;
 
+    init_usage_schema(conn)
+}
+
+/// 费用汇总表：按天 / 账号 / 模型累加，不受日志开关与清空日志影响
+/// (priced = 0 的行记录未匹配单价的 token 用量)
+fn init_usage_schema(conn: &Connection) -> Result<(), String> {
+    let exists: bool = conn
+        .query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'usage_daily'",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+        .map_err(|e| e.to_string())?
+        > 0;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS usage_daily (
+            day TEXT NOT NULL,
+            account_email TEXT NOT NULL,
+            model TEXT NOT NULL,
+            priced INTEGER NOT NULL,
+            requests INTEGER NOT NULL DEFAULT 0,
+            input_tokens INTEGER NOT NULL DEFAULT 0,
+            output_tokens INTEGER NOT NULL DEFAULT 0,
+            cached_tokens INTEGER NOT NULL DEFAULT 0,
+            cost_pico INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (day, account_email, model, priced)
+        )",
+        [],
+    ).map_err(|e| e.to_string())?;
+    if exists {
+        return Ok(());
+    }
+
+    // 首次创建时从已有日志回填，升级前的费用历史不丢失
+    conn.execute(
+        "INSERT INTO usage_daily
+         SELECT strftime('%Y-%m-%d', timestamp / 1000, 'unixepoch', 'localtime'),
+                COALESCE(account_email, ''), COALESCE(mapped_model, model, ''),
+                cost_pico IS NOT NULL, COUNT(*),
+                SUM(COALESCE(input_tokens, 0)), SUM(COALESCE(output_tokens, 0)), SUM(COALESCE(cached_tokens, 0)),
+                SUM(COALESCE(cost_pico, 0))
+         FROM request_logs
+         WHERE replay_of IS NULL AND COALESCE(locally_answered, 0) = 0
+           AND (input_tokens IS NOT NULL OR output_tokens IS NOT NULL)
+         GROUP BY 1, 2, 3, 4",
+        [],
+    ).map_err(|e| e.to_string())?;
     Ok(())
 }
 
 pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
     let db_path = get_proxy_db_path()?;
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    insert_log(&conn, log)
+}
 
+fn insert_log(conn: &Connection, log: &ProxyRequestLog) -> Result<(), String> {
     conn.execute(
-        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        "INSERT INTO request_logs (id, timestamp, method, url, status, duration, model, error, request_body, response_body, input_tokens, output_tokens, account_email, mapped_model, replay_of, priority, account_tier, locally_answered, end_user, upstream_region, cached_tokens, cost_pico)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)",
         params![
             log.id,
             log.timestamp,
@@ -69,6 +134,14 @@ pub fn save_log(log: &ProxyRequestLog) -> Result<(), String> {
             log.output_tokens,
             log.account_email,
             log.mapped_model,
+            log.replay_of,
+            log.priority,
+            log.account_tier,
+            log.locally_answered,
+            log.end_user,
+            log.upstream_region,
+            log.cached_tokens,
+            log.cost_pico,
         ],
     ).map_err(|e| e.to_string())?;
 
@@ -83,7 +156,8 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
     let mut stmt = conn.prepare(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 NULL as request_body, NULL as response_body,
-                input_tokens, output_tokens, account_email, mapped_model
+                input_tokens, output_tokens, account_email, mapped_model, replay_of,
+                priority, account_tier, locally_answered, end_user, upstream_region, cached_tokens, cost_pico
          FROM request_logs 
          ORDER BY timestamp DESC 
          LIMIT ?1 OFFSET ?2"
@@ -105,6 +179,14 @@ pub fn get_logs_summary(limit: usize, offset: usize) -> Result<Vec<ProxyRequestL
             response_body: None, // Don't query large fields for list view
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
+            replay_of: row.get(14).unwrap_or(None),
+            priority: row.get(15).unwrap_or(None),
+            account_tier: row.get(16).unwrap_or(None),
+            cached_tokens: row.get(20).unwrap_or(None),
+            cost_pico: row.get(21).unwrap_or(None),
+            locally_answered: row.get::<_, Option<bool>>(17).ok().flatten().unwrap_or(false),
+            end_user: row.get(18).unwrap_or(None),
+            upstream_region: row.get(19).unwrap_or(None),
         })
     }).map_err(|e| e.to_string())?;
 
@@ -130,18 +212,178 @@ pub fn get_stats() -> Result<crate::proxy::monitor::ProxyStats, String> {
             COUNT(*) as total,
             SUM(CASE WHEN status >= 200 AND status < 400 THEN 1 ELSE 0 END) as success,
             SUM(CASE WHEN status < 200 OR status >= 400 THEN 1 ELSE 0 END) as error
-         FROM request_logs",
+         FROM request_logs
+         WHERE replay_of IS NULL AND COALESCE(locally_answered, 0) = 0",
         [],
         |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
     ).map_err(|e| e.to_string())?;
 
+    let locally_answered: u64 = conn.query_row(
+        "SELECT COUNT(*) FROM request_logs WHERE locally_answered = 1",
+        [],
+        |row| row.get(0),
+    ).map_err(|e| e.to_string())?;
+
+    let mut priority_breakdown: std::collections::BTreeMap<String, std::collections::BTreeMap<String, u64>> =
+        std::collections::BTreeMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT priority, COALESCE(account_tier, 'UNKNOWN'), COUNT(*)
+         FROM request_logs
+         WHERE replay_of IS NULL AND COALESCE(locally_answered, 0) = 0 AND priority IS NOT NULL
+         GROUP BY priority, account_tier"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, u64>(2)?))
+    }).map_err(|e| e.to_string())?;
+    for row in rows {
+        let (priority, tier, count) = row.map_err(|e| e.to_string())?;
+        *priority_breakdown.entry(priority).or_default().entry(tier).or_insert(0) += count;
+    }
+
     Ok(crate::proxy::monitor::ProxyStats {
         total_requests,
         success_count,
         error_count,
+        priority_breakdown,
+        locally_answered,
+        zai_keys: Vec::new(),
+        recitations: Default::default(),
+        top_end_users: top_end_users(&conn, TOP_END_USERS)?,
+        last_resort_probes: Default::default(),
+        standby_activations: 0,
+        group_selections: Default::default(),
     })
 }
 
+/// 请求数最多的终端用户 (同时汇总 token 用量)
+fn top_end_users(conn: &Connection, limit: usize) -> Result<Vec<crate::proxy::monitor::EndUserStats>, String> {
+    let mut stmt = conn.prepare(
+        "SELECT end_user, COUNT(*), SUM(COALESCE(input_tokens, 0)), SUM(COALESCE(output_tokens, 0))
+         FROM request_logs
+         WHERE end_user IS NOT NULL AND replay_of IS NULL AND COALESCE(locally_answered, 0) = 0
+         GROUP BY end_user
+         ORDER BY COUNT(*) DESC, SUM(COALESCE(input_tokens, 0) + COALESCE(output_tokens, 0)) DESC
+         LIMIT ?1"
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([limit], |row| {
+        Ok(crate::proxy::monitor::EndUserStats {
+            end_user: row.get(0)?,
+            requests: row.get(1)?,
+            input_tokens: row.get(2)?,
+            output_tokens: row.get(3)?,
+        })
+    }).map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())
+}
+
+/// 按天 / 账号 / 模型汇总估算费用 (未匹配单价的请求单独按模型汇总 token 数)
+pub fn get_usage_costs(range: UsageRange) -> Result<UsageCostReport, String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    usage_costs(&conn, range, range.since_ms(chrono::Local::now()))
+}
+
+/// 参与费用统计的请求：非重放、非本地应答、有 token 用量
+pub fn counts_toward_usage(log: &ProxyRequestLog) -> bool {
+    log.replay_of.is_none()
+        && !log.locally_answered
+        && (log.input_tokens.is_some() || log.output_tokens.is_some())
+}
+
+/// 将一次请求的用量累加进费用汇总表 (与请求日志开关无关)
+pub fn record_usage(log: &ProxyRequestLog) -> Result<(), String> {
+    let db_path = get_proxy_db_path()?;
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    insert_usage(&conn, log)
+}
+
+/// 毫秒时间戳对应的本地日期 (与费用汇总表的 day 列一致)
+fn local_day(timestamp_ms: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(timestamp_ms)
+        .unwrap_or_default()
+        .with_timezone(&chrono::Local)
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+fn insert_usage(conn: &Connection, log: &ProxyRequestLog) -> Result<(), String> {
+    if !counts_toward_usage(log) {
+        return Ok(());
+    }
+    let day = local_day(log.timestamp);
+    let model = log.mapped_model.as_deref().or(log.model.as_deref()).unwrap_or("");
+    conn.execute(
+        "INSERT INTO usage_daily (day, account_email, model, priced, requests, input_tokens, output_tokens, cached_tokens, cost_pico)
+         VALUES (?1, ?2, ?3, ?4, 1, ?5, ?6, ?7, ?8)
+         ON CONFLICT (day, account_email, model, priced) DO UPDATE SET
+            requests = requests + 1,
+            input_tokens = input_tokens + excluded.input_tokens,
+            output_tokens = output_tokens + excluded.output_tokens,
+            cached_tokens = cached_tokens + excluded.cached_tokens,
+            cost_pico = cost_pico + excluded.cost_pico",
+        params![
+            day,
+            log.account_email.as_deref().unwrap_or(""),
+            model,
+            log.cost_pico.is_some(),
+            log.input_tokens.unwrap_or(0),
+            log.output_tokens.unwrap_or(0),
+            log.cached_tokens.unwrap_or(0),
+            log.cost_pico.unwrap_or(0),
+        ],
+    ).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn usage_costs(conn: &Connection, range: UsageRange, since_ms: i64) -> Result<UsageCostReport, String> {
+    let since_day = local_day(since_ms);
+    let mut stmt = conn.prepare(
+        "SELECT day, account_email, model, SUM(requests),
+                SUM(input_tokens), SUM(output_tokens), SUM(cached_tokens), SUM(cost_pico)
+         FROM usage_daily
+         WHERE day >= ?1 AND priced = 1
+         GROUP BY 1, 2, 3
+         ORDER BY 1 DESC, SUM(cost_pico) DESC",
+    ).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([&since_day], |row| {
+        let cost_pico: i64 = row.get(7)?;
+        Ok(UsageCostRow {
+            day: row.get(0)?,
+            account_email: row.get(1)?,
+            model: row.get(2)?,
+            requests: row.get(3)?,
+            input_tokens: row.get(4)?,
+            output_tokens: row.get(5)?,
+            cached_tokens: row.get(6)?,
+            cost_usd: pico_to_usd(i128::from(cost_pico)),
+            cost_pico,
+        })
+    }).map_err(|e| e.to_string())?
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|e| e.to_string())?;
+
+    let mut stmt = conn.prepare(
+        "SELECT model, SUM(requests), SUM(input_tokens), SUM(output_tokens), SUM(cached_tokens)
+         FROM usage_daily
+         WHERE day >= ?1 AND priced = 0
+         GROUP BY 1
+         ORDER BY 2 DESC",
+    ).map_err(|e| e.to_string())?;
+    let unpriced = stmt.query_map([&since_day], |row| {
+        Ok(UnpricedUsage {
+            model: row.get(0)?,
+            requests: row.get(1)?,
+            input_tokens: row.get(2)?,
+            output_tokens: row.get(3)?,
+            cached_tokens: row.get(4)?,
+        })
+    }).map_err(|e| e.to_string())?
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|e| e.to_string())?;
+
+    Ok(UsageCostReport::new(range, since_ms, rows, unpriced))
+}
+
 /// Get single log detail (with request_body and response_body)
 pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
     let db_path = get_proxy_db_path()?;
@@ -150,7 +392,8 @@ pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
     let mut stmt = conn.prepare(
         "SELECT id, timestamp, method, url, status, duration, model, error, 
                 request_body, response_body, input_tokens, output_tokens, 
-                account_email, mapped_model
+                account_email, mapped_model, replay_of, priority, account_tier, locally_answered, end_user, upstream_region,
+                cached_tokens, cost_pico
          FROM request_logs 
          WHERE id = ?1"
     ).map_err(|e| e.to_string())?;
@@ -171,6 +414,14 @@ pub fn get_log_detail(log_id: &str) -> Result<ProxyRequestLog, String> {
             response_body: row.get(9).unwrap_or(None),
             input_tokens: row.get(10).unwrap_or(None),
             output_tokens: row.get(11).unwrap_or(None),
+            replay_of: row.get(14).unwrap_or(None),
+            priority: row.get(15).unwrap_or(None),
+            account_tier: row.get(16).unwrap_or(None),
+            cached_tokens: row.get(20).unwrap_or(None),
+            cost_pico: row.get(21).unwrap_or(None),
+            locally_answered: row.get::<_, Option<bool>>(17).ok().flatten().unwrap_or(false),
+            end_user: row.get(18).unwrap_or(None),
+            upstream_region: row.get(19).unwrap_or(None),
         })
     }).map_err(|e| e.to_string())
 }
@@ -210,9 +461,144 @@ pub fn limit_max_logs(max_count: usize) -> Result<usize, String> {
     Ok(deleted)
 }
 
+/// 清空请求日志 (费用汇总表保留)
 pub fn clear_logs() -> Result<(), String> {
     let db_path = get_proxy_db_path()?;
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    clear_request_logs(&conn)
+}
+
+fn clear_request_logs(conn: &Connection) -> Result<(), String> {
     conn.execute("DELETE FROM request_logs", []).map_err(|e| e.to_string())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(id: &str, end_user: Option<&str>, input: u32, output: u32) -> ProxyRequestLog {
+        ProxyRequestLog {
+            id: id.to_string(),
+            timestamp: 0,
+            method: "POST".to_string(),
+            url: "/v1/chat/completions".to_string(),
+            status: 200,
+            duration: 10,
+            model: Some("gpt-4".to_string()),
+            mapped_model: None,
+            account_email: None,
+            error: None,
+            request_body: None,
+            response_body: None,
+            input_tokens: Some(input),
+            output_tokens: Some(output),
+            replay_of: None,
+            priority: None,
+            account_tier: None,
+            cached_tokens: None,
+            cost_pico: None,
+            locally_answered: false,
+            end_user: end_user.map(str::to_string),
+            upstream_region: None,
+        }
+    }
+
+    #[test]
+    fn test_top_end_users_by_requests() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        insert_log(&conn, &log("1", Some("emp-1"), 10, 5)).unwrap();
+        insert_log(&conn, &log("2", Some("emp-2"), 100, 50)).unwrap();
+        insert_log(&conn, &log("3", Some("emp-2"), 1, 1)).unwrap();
+        insert_log(&conn, &log("4", None, 1000, 1000)).unwrap();
+
+        let top = top_end_users(&conn, 10).unwrap();
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].end_user, "emp-2");
+        assert_eq!(top[0].requests, 2);
+        assert_eq!(top[0].input_tokens, 101);
+        assert_eq!(top[0].output_tokens, 51);
+        assert_eq!(top[1].end_user, "emp-1");
+
+        assert_eq!(top_end_users(&conn, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_usage_costs_group_priced_and_unpriced() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        let now = chrono::Local::now().timestamp_millis();
+        let priced = |id: &str, email: &str, cost: i64| ProxyRequestLog {
+            timestamp: now,
+            account_email: Some(email.to_string()),
+            cached_tokens: Some(2),
+            cost_pico: Some(cost),
+            ..log(id, None, 10, 5)
+        };
+        insert_usage(&conn, &priced("1", "a@example.com", 100)).unwrap();
+        insert_usage(&conn, &priced("2", "a@example.com", 250)).unwrap();
+        insert_usage(&conn, &priced("3", "b@example.com", 1_000_000_000_000)).unwrap();
+        insert_usage(&conn, &ProxyRequestLog { timestamp: now, ..log("4", None, 7, 3) }).unwrap();
+        // 重放请求、本地应答与范围之外的请求不计入
+        insert_usage(&conn, &ProxyRequestLog { replay_of: Some("1".to_string()), ..priced("5", "a@example.com", 999) }).unwrap();
+        insert_usage(&conn, &ProxyRequestLog { locally_answered: true, ..priced("7", "a@example.com", 999) }).unwrap();
+        insert_usage(&conn, &ProxyRequestLog { timestamp: now - 86_400_000 * 40, ..priced("6", "a@example.com", 999) }).unwrap();
+
+        let report = usage_costs(&conn, UsageRange::Month, now - 86_400_000 * 30).unwrap();
+        assert_eq!(report.rows.len(), 2);
+        assert_eq!(report.rows[0].account_email, "b@example.com");
+        assert_eq!(report.rows[1].requests, 2);
+        assert_eq!(report.rows[1].cost_pico, 350);
+        assert_eq!(report.rows[1].cached_tokens, 4);
+        assert_eq!(report.total_usd, pico_to_usd(1_000_000_000_350));
+        assert_eq!(report.unpriced.len(), 1);
+        assert_eq!(report.unpriced[0].model, "gpt-4");
+        assert_eq!(report.unpriced[0].input_tokens, 7);
+        assert_eq!(report.unpriced[0].output_tokens, 3);
+    }
+
+    #[test]
+    fn test_usage_costs_survive_clearing_logs() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        let now = chrono::Local::now().timestamp_millis();
+        let entry = ProxyRequestLog {
+            timestamp: now,
+            account_email: Some("a@example.com".to_string()),
+            cost_pico: Some(500),
+            ..log("1", None, 10, 5)
+        };
+        insert_log(&conn, &entry).unwrap();
+        insert_usage(&conn, &entry).unwrap();
+        // 日志关闭时只累加费用，不写请求日志
+        insert_usage(&conn, &ProxyRequestLog { id: "2".to_string(), ..entry.clone() }).unwrap();
+
+        clear_request_logs(&conn).unwrap();
+        let report = usage_costs(&conn, UsageRange::Today, now - 1000).unwrap();
+        assert_eq!(report.rows.len(), 1);
+        assert_eq!(report.rows[0].requests, 2);
+        assert_eq!(report.rows[0].cost_pico, 1000);
+    }
+
+    #[test]
+    fn test_usage_table_backfills_from_existing_logs() {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        conn.execute("DROP TABLE usage_daily", []).unwrap();
+        let now = chrono::Local::now().timestamp_millis();
+        insert_log(&conn, &ProxyRequestLog { timestamp: now, cost_pico: Some(42), ..log("1", None, 10, 5) }).unwrap();
+        insert_log(&conn, &ProxyRequestLog { timestamp: now, ..log("2", None, 7, 3) }).unwrap();
+
+        init_schema(&conn).unwrap();
+        let report = usage_costs(&conn, UsageRange::Today, now - 1000).unwrap();
+        assert_eq!(report.rows.len(), 1);
+        assert_eq!(report.rows[0].cost_pico, 42);
+        assert_eq!(report.unpriced.len(), 1);
+        assert_eq!(report.unpriced[0].input_tokens, 7);
+
+        // 再次初始化不会重复回填
+        init_schema(&conn).unwrap();
+        assert_eq!(usage_costs(&conn, UsageRange::Today, now - 1000).unwrap().rows[0].cost_pico, 42);
+    }
+}
@@ -0,0 +1,186 @@
+// 反代停用 (proxy_disabled) 的统一读写入口
+// 所有停用路径都经由这里同时写入原因与类别，避免再出现无法归类的自由文本原因；
+// 旧账号文件的类别在 schema 升级时由 categorize 根据原因文本推断。
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::models::{Account, ProxyDisableCategory};
+
+/// 长期手动停用的账号提醒事件 (payload 为 Vec<StaleDisable>)
+pub const STALE_DISABLES_EVENT: &str = "account://stale_disables";
+
+/// 根据原因文本推断类别 (仅用于没有类别字段的旧数据)
+pub fn categorize(reason: Option<&str>, snoozed: bool) -> ProxyDisableCategory {
+    let reason = reason.unwrap_or("").trim();
+    if reason.contains("quota_protection") {
+        ProxyDisableCategory::QuotaProtection
+    } else if reason.contains("invalid_grant") {
+        ProxyDisableCategory::InvalidGrant
+    } else if snoozed || reason.starts_with("临时停用") {
+        ProxyDisableCategory::Snooze
+    } else if reason.is_empty() {
+        ProxyDisableCategory::Other
+    } else {
+        // 其余自由文本只可能来自手动停用
+        ProxyDisableCategory::Manual
+    }
+}
+
+/// 账号 JSON 的停用类别，未停用时为 None
+pub fn category_of_json(account: &Value) -> Option<ProxyDisableCategory> {
+    if !account.get("proxy_disabled").and_then(|v| v.as_bool()).unwrap_or(false) {
+        return None;
+    }
+    account
+        .get("proxy_disabled_category")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .or_else(|| {
+            Some(categorize(
+                account.get("proxy_disabled_reason").and_then(|v| v.as_str()),
+                account.get("proxy_disabled_until").is_some_and(|v| !v.is_null()),
+            ))
+        })
+}
+
+/// 账号的停用类别，未停用时为 None
+pub fn category_of(account: &Account) -> Option<ProxyDisableCategory> {
+    if !account.proxy_disabled {
+        return None;
+    }
+    account.proxy_disabled_category.or_else(|| {
+        Some(categorize(
+            account.proxy_disabled_reason.as_deref(),
+            account.proxy_disabled_until.is_some(),
+        ))
+    })
+}
+
+/// 停用账号 JSON；已停用时保留最初的停用时间
+pub fn disable_json(account: &mut Value, category: ProxyDisableCategory, reason: String, now: i64) {
+    if category_of_json(account).is_none() {
+        account["proxy_disabled_at"] = Value::Number(now.into());
+    }
+    account["proxy_disabled"] = Value::Bool(true);
+    account["proxy_disabled_reason"] = Value::String(reason);
+    account["proxy_disabled_category"] = serde_json::to_value(category).unwrap_or(Value::Null);
+}
+
+/// 恢复账号 JSON 的反代
+pub fn enable_json(account: &mut Value) {
+    account["proxy_disabled"] = Value::Bool(false);
+    account["proxy_disabled_reason"] = Value::Null;
+    account["proxy_disabled_category"] = Value::Null;
+    account["proxy_disabled_at"] = Value::Null;
+}
+
+/// 停用账号；已停用时保留最初的停用时间
+pub fn disable(account: &mut Account, category: ProxyDisableCategory, reason: String, now: i64) {
+    if !account.proxy_disabled {
+        account.proxy_disabled_at = Some(now);
+    }
+    account.proxy_disabled = true;
+    account.proxy_disabled_reason = Some(reason);
+    account.proxy_disabled_category = Some(category);
+}
+
+/// 恢复账号的反代
+pub fn enable(account: &mut Account) {
+    account.proxy_disabled = false;
+    account.proxy_disabled_reason = None;
+    account.proxy_disabled_category = None;
+    account.proxy_disabled_at = None;
+}
+
+/// 停用时间过长、建议复查的手动停用账号
+#[derive(Debug, Clone, Serialize)]
+pub struct StaleDisable {
+    pub account_id: String,
+    pub email: String,
+    pub reason: Option<String>,
+    pub disabled_at: i64,
+    pub days_disabled: i64,
+}
+
+/// 找出手动停用超过 `days` 天的账号 (只提示，从不自动恢复)，停用最久的排在最前
+pub fn find_stale_disables(accounts: &[Account], days: u32, now: i64) -> Vec<StaleDisable> {
+    let cutoff = now - i64::from(days) * 86_400;
+    let mut stale: Vec<StaleDisable> = accounts
+        .iter()
+        .filter(|a| category_of(a) == Some(ProxyDisableCategory::Manual))
+        .filter_map(|a| {
+            let disabled_at = a.proxy_disabled_at.filter(|at| *at < cutoff)?;
+            Some(StaleDisable {
+                account_id: a.id.clone(),
+                email: a.email.clone(),
+                reason: a.proxy_disabled_reason.clone(),
+                disabled_at,
+                days_disabled: (now - disabled_at) / 86_400,
+            })
+        })
+        .collect();
+    stale.sort_by_key(|s| (s.disabled_at, s.email.clone()));
+    stale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TokenData;
+    use serde_json::json;
+
+    #[test]
+    fn test_categorize_legacy_reasons() {
+        assert_eq!(categorize(Some("quota_protection: 3% (阈值: 10%)"), false), ProxyDisableCategory::QuotaProtection);
+        assert_eq!(categorize(Some("invalid_grant: revoked"), false), ProxyDisableCategory::InvalidGrant);
+        assert_eq!(categorize(Some("临时停用 30 分钟"), false), ProxyDisableCategory::Snooze);
+        assert_eq!(categorize(Some("maintenance"), true), ProxyDisableCategory::Snooze);
+        assert_eq!(categorize(Some("用户手动禁用"), false), ProxyDisableCategory::Manual);
+        assert_eq!(categorize(Some("testing"), false), ProxyDisableCategory::Manual);
+        assert_eq!(categorize(None, false), ProxyDisableCategory::Other);
+    }
+
+    #[test]
+    fn test_json_disable_keeps_first_timestamp_and_enable_clears() {
+        let mut account = json!({ "id": "a", "proxy_disabled": false });
+        disable_json(&mut account, ProxyDisableCategory::QuotaProtection, "quota_protection: 5%".to_string(), 100);
+        disable_json(&mut account, ProxyDisableCategory::QuotaProtection, "quota_protection: 3%".to_string(), 200);
+        assert_eq!(account["proxy_disabled_at"], 100);
+        assert_eq!(account["proxy_disabled_category"], "quota_protection");
+        assert_eq!(category_of_json(&account), Some(ProxyDisableCategory::QuotaProtection));
+
+        enable_json(&mut account);
+        assert_eq!(account["proxy_disabled"], false);
+        assert!(account["proxy_disabled_category"].is_null());
+        assert_eq!(category_of_json(&account), None);
+    }
+
+    #[test]
+    fn test_find_stale_disables_only_lists_old_manual_disables() {
+        let now = 1_700_000_000;
+        let day = 86_400;
+        let make = |id: &str, category: ProxyDisableCategory, days_ago: i64| {
+            let token = TokenData::new("a".to_string(), "r".to_string(), 3600, None, None, None);
+            let mut account = Account::new(id.to_string(), format!("{}@example.com", id), token);
+            disable(&mut account, category, "testing".to_string(), now - days_ago * day);
+            account
+        };
+        let accounts = vec![
+            make("old-manual", ProxyDisableCategory::Manual, 90),
+            make("recent-manual", ProxyDisableCategory::Manual, 3),
+            make("old-quota", ProxyDisableCategory::QuotaProtection, 90),
+            Account::new(
+                "enabled".to_string(),
+                "enabled@example.com".to_string(),
+                TokenData::new("a".to_string(), "r".to_string(), 3600, None, None, None),
+            ),
+        ];
+
+        let stale = find_stale_disables(&accounts, 30, now);
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].account_id, "old-manual");
+        assert_eq!(stale[0].days_disabled, 90);
+        // 列出后账号仍保持停用
+        assert!(accounts[0].proxy_disabled);
+    }
+}
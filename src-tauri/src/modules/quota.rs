@@ -242,6 +242,11 @@ pub async fn fetch_all_quotas(accounts: Vec<(String, String)>) -> Vec<(String, c
     results
 }
 
+/// 过滤出允许预热的账号 (排除设置了 no_warmup 的账号)
+pub fn warmup_targets(accounts: Vec<crate::models::account::Account>) -> Vec<crate::models::account::Account> {
+    accounts.into_iter().filter(|a| !a.no_warmup).collect()
+}
+
 /// 获取有效 token（自动刷新过期的）
 pub async fn get_valid_token_for_warmup(account: &crate::models::account::Account) -> Result<(String, String), String> {
     let mut account = account.clone();
@@ -316,10 +321,17 @@ pub async fn warmup_model_directly(
 
 /// 智能预热所有账号
 pub async fn warm_up_all_accounts() -> Result<String, String> {
+    warm_up_accounts_from(|| crate::modules::account::list_accounts().unwrap_or_default()).await
+}
+
+async fn warm_up_accounts_from<F>(load_accounts: F) -> Result<String, String>
+where
+    F: Fn() -> Vec<crate::models::account::Account>,
+{
     let mut retry_count = 0;
     
     loop {
-        let target_accounts = crate::modules::account::list_accounts().unwrap_or_default();
+        let target_accounts = warmup_targets(load_accounts());
 
         if target_accounts.is_empty() {
             return Ok("没有可用账号".to_string());
@@ -399,6 +411,9 @@ pub async fn warm_up_all_accounts() -> Result<String, String> {
 pub async fn warm_up_account(account_id: &str) -> Result<String, String> {
     let accounts = crate::modules::account::list_accounts().unwrap_or_default();
     let account_owned = accounts.iter().find(|a| a.id == account_id).cloned().ok_or_else(|| "账号未找到".to_string())?;
+    if account_owned.no_warmup {
+        return Err("该账号已设置为不预热".to_string());
+    }
     
     let email = account_owned.email.clone();
     let (token, pid) = get_valid_token_for_warmup(&account_owned).await?;
@@ -441,3 +456,103 @@ pub async fn warm_up_account(account_id: &str) -> Result<String, String> {
 
     Ok(format!("成功触发 {} 个系列的模型预热", warmed_count))
 }
+
+/// 单个模型距配额刷新的时间
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaResetTime {
+    pub email: String,
+    pub model: String,
+    pub reset_time: String,
+    /// 距刷新的秒数 (已过刷新时间为 0)
+    pub seconds_until: i64,
+}
+
+/// 解析配额中的 reset_time (ISO 8601，如 "2026-01-08T17:00:00Z")，返回 Unix 时间戳
+pub fn parse_reset_time(reset_time: &str) -> Option<i64> {
+    chrono::DateTime::parse_from_rfc3339(reset_time.trim())
+        .ok()
+        .map(|dt| dt.timestamp())
+}
+
+/// 各账号各模型的配额刷新倒计时；reset_time 为空或无法解析的模型跳过
+pub fn quota_reset_times(accounts: &[crate::models::account::Account], now: i64) -> Vec<QuotaResetTime> {
+    let mut result = Vec::new();
+    for account in accounts {
+        let Some(quota) = &account.quota else {
+            continue;
+        };
+        for model in &quota.models {
+            if model.reset_time.trim().is_empty() {
+                continue;
+            }
+            let Some(reset_at) = parse_reset_time(&model.reset_time) else {
+                crate::modules::logger::log_warn(&format!(
+                    "无法解析账号 {} 模型 {} 的配额刷新时间: {}",
+                    account.email, model.name, model.reset_time
+                ));
+                continue;
+            };
+            result.push(QuotaResetTime {
+                email: account.email.clone(),
+                model: model.name.clone(),
+                reset_time: model.reset_time.clone(),
+                seconds_until: (reset_at - now).max(0),
+            });
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{account::Account, TokenData};
+
+    fn account(id: &str, no_warmup: bool) -> Account {
+        let token = TokenData::new(
+            "access".to_string(),
+            "refresh".to_string(),
+            3600,
+            Some(format!("{}@example.com", id)),
+            Some("project".to_string()),
+            None,
+        );
+        let mut account = Account::new(id.to_string(), format!("{}@example.com", id), token);
+        account.no_warmup = no_warmup;
+        account
+    }
+
+    #[test]
+    fn test_warmup_targets_skip_no_warmup_accounts() {
+        let targets = warmup_targets(vec![account("a", false), account("b", true), account("c", false)]);
+        let ids: Vec<&str> = targets.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_warm_up_all_accounts_skips_no_warmup_account() {
+        // 唯一账号设置了 no_warmup，不应发起任何 token 刷新或配额查询
+        let result = warm_up_accounts_from(|| vec![account("budget", true)]).await;
+        assert_eq!(result.unwrap(), "没有可用账号");
+    }
+
+    #[test]
+    fn test_quota_reset_times_seconds_until() {
+        let now = parse_reset_time("2026-01-08T14:00:00Z").unwrap();
+        let mut quota = QuotaData::new();
+        quota.add_model("gemini-3-pro-high".to_string(), 20, "2026-01-08T17:00:00Z".to_string());
+        quota.add_model("claude-sonnet-4-5".to_string(), 100, String::new());
+        quota.add_model("gemini-3-flash".to_string(), 0, "2026-01-08T13:00:00+00:00".to_string());
+        let mut with_quota = account("a", false);
+        with_quota.quota = Some(quota);
+
+        let resets = quota_reset_times(&[with_quota, account("b", false)], now);
+        assert_eq!(resets.len(), 2);
+        assert_eq!(resets[0].email, "a@example.com");
+        assert_eq!(resets[0].model, "gemini-3-pro-high");
+        assert_eq!(resets[0].seconds_until, 3 * 3600);
+        // 已过刷新时间
+        assert_eq!(resets[1].seconds_until, 0);
+        assert_eq!(parse_reset_time("not a time"), None);
+    }
+}
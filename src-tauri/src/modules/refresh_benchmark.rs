@@ -0,0 +1,194 @@
+// OAuth 刷新耗时基准
+// 并发 (有上限) 刷新每个账号的 access_token 并计时，便于找出刷新持续偏慢 (可能被 Google 限速或标记) 的账号。
+// 反代运行时以号池内存中的 token 为准 (号池可能已刷新过、磁盘尚未落盘)，新 token 也交给号池更新与落盘；
+// 反代未运行时才读取磁盘上的账号。新 token 只在当前 token 即将过期时才保存，避免无意义的写入。
+
+use futures::StreamExt;
+use serde::Serialize;
+use std::future::Future;
+use std::time::Instant;
+
+use crate::models::Account;
+use crate::modules::account_reliability::{self, ReliabilityEventKind};
+use crate::modules::oauth::TokenResponse;
+
+/// 同时进行的刷新请求上限
+const MAX_CONCURRENT_REFRESHES: usize = 5;
+
+/// 本地 token 剩余有效期低于该值时才保存新 token (秒)
+const PERSIST_IF_EXPIRING_WITHIN_SECS: i64 = 600;
+
+/// 单个账号的刷新耗时
+#[derive(Debug, Clone, Serialize)]
+pub struct RefreshTiming {
+    pub email: String,
+    pub refresh_ms: u64,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+/// 待刷新的账号
+#[derive(Debug, Clone)]
+pub struct RefreshTarget {
+    pub account_id: String,
+    pub email: String,
+    pub refresh_token: String,
+    pub expiry_timestamp: i64,
+}
+
+impl From<&Account> for RefreshTarget {
+    fn from(account: &Account) -> Self {
+        Self {
+            account_id: account.id.clone(),
+            email: account.email.clone(),
+            refresh_token: account.token.refresh_token.clone(),
+            expiry_timestamp: account.token.expiry_timestamp,
+        }
+    }
+}
+
+/// 并发刷新并计时，结果按账号原顺序返回 (附带成功时的新 token)
+async fn time_refreshes<F, Fut>(
+    targets: &[RefreshTarget],
+    concurrency: usize,
+    refresh: F,
+) -> Vec<(RefreshTiming, Option<TokenResponse>)>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<TokenResponse, String>>,
+{
+    futures::stream::iter(targets.iter().map(|target| {
+        let pending = refresh(target.refresh_token.clone());
+        async move {
+            let start = Instant::now();
+            let result = pending.await;
+            let refresh_ms = start.elapsed().as_millis() as u64;
+            let (ok, error, token) = match result {
+                Ok(token) => (true, None, Some(token)),
+                Err(e) => (false, Some(e), None),
+            };
+            (RefreshTiming { email: target.email.clone(), refresh_ms, ok, error }, token)
+        }
+    }))
+    .buffered(concurrency.max(1))
+    .collect()
+    .await
+}
+
+/// 当前 token 即将过期时才值得保存新 token
+fn should_persist(target: &RefreshTarget, now: i64) -> bool {
+    target.expiry_timestamp - now < PERSIST_IF_EXPIRING_WITHIN_SECS
+}
+
+/// 反代未运行时把新 token 写回磁盘上的账号文件
+fn save_to_disk(target: &RefreshTarget, token: &TokenResponse, now: i64) -> Result<(), String> {
+    let mut account = crate::modules::account::load_account(&target.account_id)?;
+    account.token.access_token = token.access_token.clone();
+    account.token.expires_in = token.expires_in;
+    account.token.expiry_timestamp = now + token.expires_in;
+    crate::modules::account::save_account(&account)
+}
+
+/// 对所有未禁用账号执行刷新基准测试 (反代运行时使用号池中的账号)
+pub async fn benchmark_token_refresh() -> Result<Vec<RefreshTiming>, String> {
+    let manager = crate::proxy::token_manager::active();
+    let targets: Vec<RefreshTarget> = match &manager {
+        Some(manager) => manager.refresh_targets(),
+        None => crate::modules::account::list_accounts()?
+            .iter()
+            .filter(|a| !a.disabled)
+            .map(RefreshTarget::from)
+            .collect(),
+    };
+
+    let results = time_refreshes(&targets, MAX_CONCURRENT_REFRESHES, |refresh_token| async move {
+        crate::modules::oauth::refresh_access_token(&refresh_token).await
+    })
+    .await;
+
+    let now = chrono::Utc::now().timestamp();
+    let mut timings = Vec::with_capacity(results.len());
+    for (target, (timing, token)) in targets.iter().zip(results) {
+        account_reliability::record(&target.account_id, ReliabilityEventKind::Refresh, timing.ok, timing.error.clone());
+        if let Some(token) = token.filter(|_| should_persist(target, now)) {
+            match &manager {
+                Some(manager) => manager.apply_refreshed_token(
+                    &target.account_id,
+                    &token.access_token,
+                    token.expires_in,
+                    now + token.expires_in,
+                ),
+                None => {
+                    if let Err(e) = save_to_disk(target, &token, now) {
+                        crate::modules::logger::log_warn(&format!("保存刷新后的 token 失败 ({}): {}", target.email, e));
+                    }
+                }
+            }
+        }
+        timings.push(timing);
+    }
+
+    crate::modules::logger::log_info(&format!(
+        "Token 刷新基准完成: {} 个账号，最慢 {}ms",
+        timings.len(),
+        timings.iter().map(|t| t.refresh_ms).max().unwrap_or(0)
+    ));
+    Ok(timings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TokenData;
+    use std::time::Duration;
+
+    fn account(email: &str, refresh_token: &str, expiry_timestamp: i64) -> RefreshTarget {
+        let mut token = TokenData::new("a".to_string(), refresh_token.to_string(), 3600, None, None, None);
+        token.expiry_timestamp = expiry_timestamp;
+        RefreshTarget::from(&Account::new(email.to_string(), email.to_string(), token))
+    }
+
+    #[tokio::test]
+    async fn test_timings_captured_per_account() {
+        let accounts = vec![
+            account("slow@example.com", "slow", 0),
+            account("fast@example.com", "fast", 0),
+            account("broken@example.com", "broken", 0),
+        ];
+
+        let results = time_refreshes(&accounts, 2, |refresh_token| async move {
+            let delay = if refresh_token == "slow" { 120 } else { 5 };
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            if refresh_token == "broken" {
+                return Err("invalid_grant".to_string());
+            }
+            Ok(TokenResponse {
+                access_token: format!("new-{}", refresh_token),
+                expires_in: 3600,
+                token_type: "Bearer".to_string(),
+                refresh_token: None,
+            })
+        })
+        .await;
+
+        let timings: Vec<&RefreshTiming> = results.iter().map(|(t, _)| t).collect();
+        assert_eq!(
+            timings.iter().map(|t| t.email.as_str()).collect::<Vec<_>>(),
+            vec!["slow@example.com", "fast@example.com", "broken@example.com"]
+        );
+        assert!(timings[0].refresh_ms >= 120);
+        assert!(timings[1].refresh_ms < 120);
+        assert!(timings[0].ok && timings[1].ok);
+        assert!(!timings[2].ok);
+        assert_eq!(timings[2].error.as_deref(), Some("invalid_grant"));
+        assert_eq!(results[1].1.as_ref().unwrap().access_token, "new-fast");
+        assert!(results[2].1.is_none());
+    }
+
+    #[test]
+    fn test_only_expiring_tokens_are_persisted() {
+        let now = 1_700_000_000;
+        assert!(should_persist(&account("a@example.com", "r", now + 60), now));
+        assert!(!should_persist(&account("b@example.com", "r", now + 3000), now));
+    }
+}
@@ -5,14 +5,18 @@ use std::sync::Mutex;
 use tokio::time::{self, Duration};
 use tauri::Manager;
 use crate::modules::{config, logger, quota, account};
-use crate::models::Account;
+use crate::models::{Account, AppConfig};
 
 // 预热历史记录：key = "email:model_name:100", value = 预热时间戳
 static WARMUP_HISTORY: Lazy<Mutex<HashMap<String, i64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
+// 上次发送长期停用提醒的时间戳 (每 24 小时最多提醒一次)
+static LAST_STALE_NOTICE: Mutex<Option<i64>> = Mutex::new(None);
+
 pub fn start_scheduler(app_handle: tauri::AppHandle) {
     tauri::async_runtime::spawn(async move {
         logger::log_info("Smart Warmup Scheduler started. Monitoring quota at 100%...");
+        let work = AppTickWork { app_handle };
         
         // 每 10 分钟扫描一次
         let mut interval = time::interval(Duration::from_secs(600));
@@ -25,152 +29,240 @@ pub fn start_scheduler(app_handle: tauri::AppHandle) {
                 continue;
             };
 
-            if !app_config.scheduled_warmup.enabled {
-                continue;
-            }
-            
-            // 获取所有账号（不再过滤等级）
-            let Ok(accounts) = account::list_accounts() else {
+            run_tick(&app_config, Utc::now(), &work).await;
+        }
+    });
+}
+
+/// 一次调度的结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TickOutcome {
+    /// 静默时段内，未做任何工作
+    QuietHours,
+    /// 只检查了长期停用账号 (未开启定时预热)
+    WarmupDisabled,
+    /// 完成了预热扫描与配额刷新
+    Scanned,
+}
+
+/// 一次调度实际执行的工作 (测试中替换为只记录调用的实现)
+trait TickWork {
+    fn check_stale_disables(&self, app_config: &AppConfig);
+    /// 扫描满额度模型、触发预热并刷新配额
+    async fn warmup_scan(&self, app_config: &AppConfig);
+}
+
+/// 执行一次调度：静默时段内暂停所有后台 API 活动
+async fn run_tick(app_config: &AppConfig, now: chrono::DateTime<Utc>, work: &impl TickWork) -> TickOutcome {
+    if is_quiet_hours(app_config, now) {
+        logger::log_info("[Scheduler] Skipping scheduled tick: within quiet hours");
+        return TickOutcome::QuietHours;
+    }
+
+    work.check_stale_disables(app_config);
+
+    if !app_config.scheduled_warmup.enabled {
+        return TickOutcome::WarmupDisabled;
+    }
+    work.warmup_scan(app_config).await;
+    TickOutcome::Scanned
+}
+
+struct AppTickWork {
+    app_handle: tauri::AppHandle,
+}
+
+impl TickWork for AppTickWork {
+    fn check_stale_disables(&self, app_config: &AppConfig) {
+        check_stale_disables(app_config);
+    }
+
+    async fn warmup_scan(&self, app_config: &AppConfig) {
+        // 获取所有账号（不再过滤等级，仅排除设置了不预热的账号）
+        let Ok(accounts) = account::list_accounts().map(quota::warmup_targets) else {
+            return;
+        };
+
+        if accounts.is_empty() {
+            return;
+        }
+
+        logger::log_info(&format!(
+            "[Scheduler] Scanning {} accounts for 100% quota models...",
+            accounts.len()
+        ));
+
+        let mut warmup_tasks = Vec::new();
+
+        // 扫描每个账号的每个模型
+        for account in &accounts {
+            // 获取有效 token
+            let Ok((token, pid)) = quota::get_valid_token_for_warmup(account).await else {
                 continue;
             };
 
-            if accounts.is_empty() {
+            // 获取实时配额
+            let Ok((fresh_quota, _)) = quota::fetch_quota_with_cache(&token, &account.email, Some(&pid)).await else {
                 continue;
-            }
+            };
 
-            logger::log_info(&format!(
-                "[Scheduler] Scanning {} accounts for 100% quota models...",
-                accounts.len()
-            ));
+            let now_ts = Utc::now().timestamp();
 
-            let mut warmup_tasks = Vec::new();
+            for model in fresh_quota.models {
+                let history_key = format!("{}:{}:100", account.email, model.name);
+            
+                // 核心逻辑：检测 100% 额度
+                if model.percentage == 100 {
+                    // 检查是否已经在本周期预热过
+                    let mut history = WARMUP_HISTORY.lock().unwrap();
+                    if history.contains_key(&history_key) {
+                        // 已经预热过这个 100% 周期，跳过
+                        continue;
+                    }
 
-            // 扫描每个账号的每个模型
-            for account in &accounts {
-                // 获取有效 token
-                let Ok((token, pid)) = quota::get_valid_token_for_warmup(account).await else {
-                    continue;
-                };
+                    // 记录到历史
+                    history.insert(history_key.clone(), now_ts);
+                    drop(history);
+
+                    // 模型名称映射
+                    let model_to_ping = if model.name == "gemini-2.5-flash" {
+                        "gemini-3-flash".to_string()
+                    } else {
+                        model.name.clone()
+                    };
+
+                    // 仅对用户配置的模型进行预热
+                    if app_config.scheduled_warmup.monitored_models.contains(&model_to_ping) {
+                        warmup_tasks.push((
+                            account.email.clone(),
+                            model_to_ping.clone(),
+                            token.clone(),
+                            pid.clone(),
+                            model.percentage,
+                        ));
 
-                // 获取实时配额
-                let Ok((fresh_quota, _)) = quota::fetch_quota_with_cache(&token, &account.email, Some(&pid)).await else {
-                    continue;
-                };
-
-                let now_ts = Utc::now().timestamp();
-
-                for model in fresh_quota.models {
-                    let history_key = format!("{}:{}:100", account.email, model.name);
-                    
-                    // 核心逻辑：检测 100% 额度
-                    if model.percentage == 100 {
-                        // 检查是否已经在本周期预热过
-                        let mut history = WARMUP_HISTORY.lock().unwrap();
-                        if history.contains_key(&history_key) {
-                            // 已经预热过这个 100% 周期，跳过
-                            continue;
-                        }
-
-                        // 记录到历史
-                        history.insert(history_key.clone(), now_ts);
-                        drop(history);
-
-                        // 模型名称映射
-                        let model_to_ping = if model.name == "gemini-2.5-flash" {
-                            "gemini-3-flash".to_string()
-                        } else {
-                            model.name.clone()
-                        };
-
-                        // 仅对用户配置的模型进行预热
-                        if app_config.scheduled_warmup.monitored_models.contains(&model_to_ping) {
-                            warmup_tasks.push((
-                                account.email.clone(),
-                                model_to_ping.clone(),
-                                token.clone(),
-                                pid.clone(),
-                                model.percentage,
-                            ));
-
-                            logger::log_info(&format!(
-                                "[Scheduler] ✓ Scheduled warmup: {} @ {} (quota at 100%)",
-                                model_to_ping, account.email
-                            ));
-                        }
-                    } else if model.percentage < 100 {
-                        // 额度未满，清除历史记录，允许下次 100% 时再预热
-                        let mut history = WARMUP_HISTORY.lock().unwrap();
-                        if history.remove(&history_key).is_some() {
-                            logger::log_info(&format!(
-                                "[Scheduler] Cleared history for {} @ {} (quota: {}%)",
-                                model.name, account.email, model.percentage
-                            ));
-                        }
+                        logger::log_info(&format!(
+                            "[Scheduler] ✓ Scheduled warmup: {} @ {} (quota at 100%)",
+                            model_to_ping, account.email
+                        ));
+                    }
+                } else if model.percentage < 100 {
+                    // 额度未满，清除历史记录，允许下次 100% 时再预热
+                    let mut history = WARMUP_HISTORY.lock().unwrap();
+                    if history.remove(&history_key).is_some() {
+                        logger::log_info(&format!(
+                            "[Scheduler] Cleared history for {} @ {} (quota: {}%)",
+                            model.name, account.email, model.percentage
+                        ));
                     }
                 }
             }
+        }
 
-            // 执行预热任务
-            if !warmup_tasks.is_empty() {
-                let total = warmup_tasks.len();
-                logger::log_info(&format!(
-                    "[Scheduler] 🔥 Triggering {} warmup tasks...",
-                    total
-                ));
-
-                let handle_for_warmup = app_handle.clone();
-                tokio::spawn(async move {
-                    let mut success = 0;
-                    for (idx, (email, model, token, pid, pct)) in warmup_tasks.into_iter().enumerate() {
-                        logger::log_info(&format!(
-                            "[Warmup {}/{}] {} @ {} ({}%)",
-                            idx + 1, total, model, email, pct
-                        ));
+        // 执行预热任务
+        if !warmup_tasks.is_empty() {
+            let total = warmup_tasks.len();
+            logger::log_info(&format!(
+                "[Scheduler] 🔥 Triggering {} warmup tasks...",
+                total
+            ));
 
-                        if quota::warmup_model_directly(&token, &model, &pid, &email, pct).await {
-                            success += 1;
-                        }
+            let handle_for_warmup = self.app_handle.clone();
+            tokio::spawn(async move {
+                let mut success = 0;
+                for (idx, (email, model, token, pid, pct)) in warmup_tasks.into_iter().enumerate() {
+                    logger::log_info(&format!(
+                        "[Warmup {}/{}] {} @ {} ({}%)",
+                        idx + 1, total, model, email, pct
+                    ));
 
-                        // 间隔 2 秒，避免请求过快
-                        if idx < total - 1 {
-                            tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-                        }
+                    if quota::warmup_model_directly(&token, &model, &pid, &email, pct).await {
+                        success += 1;
                     }
 
-                    logger::log_info(&format!(
-                        "[Scheduler] ✅ Warmup completed: {}/{} successful",
-                        success, total
-                    ));
+                    // 间隔 2 秒，避免请求过快
+                    if idx < total - 1 {
+                        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+                    }
+                }
 
-                    // 刷新配额，同步到前端
-                    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-                    let state = handle_for_warmup.state::<crate::commands::proxy::ProxyServiceState>();
-                    let _ = crate::commands::refresh_all_quotas(state).await;
-                });
-            }
+                logger::log_info(&format!(
+                    "[Scheduler] ✅ Warmup completed: {}/{} successful",
+                    success, total
+                ));
 
-            // 扫描完成后刷新前端显示（确保调度器获取的最新数据同步到 UI）
-            let handle_inner = app_handle.clone();
-            tokio::spawn(async move {
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                let state = handle_inner.state::<crate::commands::proxy::ProxyServiceState>();
-                let _ = crate::commands::refresh_all_quotas(state).await;
-                logger::log_info("[Scheduler] Quota data synced to frontend");
+                // 刷新配额，同步到前端
+                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+                let state = handle_for_warmup.state::<crate::commands::proxy::ProxyServiceState>();
+                let _ = crate::commands::refresh_all_quotas(state, false, Some(true)).await;
             });
+        }
 
-            // 定期清理历史记录（保留最近 24 小时）
-            {
-                let now_ts = Utc::now().timestamp();
-                let mut history = WARMUP_HISTORY.lock().unwrap();
-                let cutoff = now_ts - 86400; // 24 小时前
-                history.retain(|_, &mut ts| ts > cutoff);
-            }
+        // 扫描完成后刷新前端显示（确保调度器获取的最新数据同步到 UI）
+        let handle_inner = self.app_handle.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            let state = handle_inner.state::<crate::commands::proxy::ProxyServiceState>();
+            let _ = crate::commands::refresh_all_quotas(state, false, Some(true)).await;
+            logger::log_info("[Scheduler] Quota data synced to frontend");
+        });
+
+        // 定期清理历史记录（保留最近 24 小时）
+        {
+            let now_ts = Utc::now().timestamp();
+            let mut history = WARMUP_HISTORY.lock().unwrap();
+            let cutoff = now_ts - 86400; // 24 小时前
+            history.retain(|_, &mut ts| ts > cutoff);
         }
-    });
+    }
+}
+
+/// 检查长期手动停用的账号，需要时提醒用户复查
+fn check_stale_disables(app_config: &AppConfig) {
+    let Some(days) = app_config.auto_expire_manual_disables_days else {
+        return;
+    };
+    let now = Utc::now().timestamp();
+    let Ok(mut last) = LAST_STALE_NOTICE.lock() else {
+        return;
+    };
+    if last.is_some_and(|at| now - at < 86_400) {
+        return;
+    }
+    let Ok(accounts) = account::list_accounts() else {
+        return;
+    };
+    let stale = crate::modules::proxy_disable::find_stale_disables(&accounts, days, now);
+    if stale.is_empty() {
+        return;
+    }
+    logger::log_info(&format!("[Scheduler] {} accounts manually disabled for over {} days", stale.len(), days));
+    crate::modules::account_notifier::notify_stale_disables(&stale);
+    *last = Some(now);
+}
+
+/// 判断当前是否处于静默时段 (后台调度应跳过本次执行)
+pub fn is_quiet_hours(app_config: &AppConfig, now: chrono::DateTime<Utc>) -> bool {
+    app_config.quiet_hours.contains(now)
+}
+
+/// 后台任务 (定时配额刷新等) 是否因静默时段暂停；跳过时记录日志
+pub fn background_paused(task: &str) -> bool {
+    let paused = config::load_app_config()
+        .map(|c| is_quiet_hours(&c, Utc::now()))
+        .unwrap_or(false);
+    if paused {
+        logger::log_info(&format!("[Scheduler] Skipping {}: within quiet hours", task));
+    }
+    paused
 }
 
 /// 为单个账号触发即时智能预热检查
 pub async fn trigger_warmup_for_account(account: &Account) {
+    if account.no_warmup {
+        return;
+    }
+
     // 获取有效 token
     let Ok((token, pid)) = quota::get_valid_token_for_warmup(account).await else {
         return;
@@ -229,3 +321,97 @@ pub async fn trigger_warmup_for_account(account: &Account) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn config_with_quiet_hours(start: &str, end: &str) -> AppConfig {
+        let mut config = AppConfig::new();
+        config.quiet_hours.enabled = true;
+        config.quiet_hours.start = start.to_string();
+        config.quiet_hours.end = end.to_string();
+        config.quiet_hours.timezone = "UTC".to_string();
+        config
+    }
+
+    #[test]
+    fn test_is_quiet_hours_across_midnight() {
+        let config = config_with_quiet_hours("22:00", "07:00");
+        let late_night = Utc.with_ymd_and_hms(2026, 1, 1, 23, 30, 0).unwrap();
+        let early_morning = Utc.with_ymd_and_hms(2026, 1, 2, 6, 59, 0).unwrap();
+        assert!(is_quiet_hours(&config, late_night));
+        assert!(is_quiet_hours(&config, early_morning));
+    }
+
+    #[test]
+    fn test_is_quiet_hours_outside_window_or_disabled() {
+        let config = config_with_quiet_hours("22:00", "07:00");
+        let morning = Utc.with_ymd_and_hms(2026, 1, 2, 7, 0, 0).unwrap();
+        let afternoon = Utc.with_ymd_and_hms(2026, 1, 2, 15, 0, 0).unwrap();
+        assert!(!is_quiet_hours(&config, morning));
+        assert!(!is_quiet_hours(&config, afternoon));
+
+        let mut disabled = config.clone();
+        disabled.quiet_hours.enabled = false;
+        let late_night = Utc.with_ymd_and_hms(2026, 1, 1, 23, 30, 0).unwrap();
+        assert!(!is_quiet_hours(&disabled, late_night));
+    }
+
+    /// 只记录调用的调度工作
+    #[derive(Default)]
+    struct RecordingWork {
+        calls: Mutex<Vec<&'static str>>,
+    }
+
+    impl TickWork for RecordingWork {
+        fn check_stale_disables(&self, _app_config: &AppConfig) {
+            self.calls.lock().unwrap().push("stale_check");
+        }
+
+        async fn warmup_scan(&self, _app_config: &AppConfig) {
+            self.calls.lock().unwrap().push("warmup_scan");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tick_inside_quiet_hours_does_no_work() {
+        let mut config = config_with_quiet_hours("22:00", "07:00");
+        config.scheduled_warmup.enabled = true;
+        config.auto_expire_manual_disables_days = Some(30);
+        let work = RecordingWork::default();
+
+        let late_night = Utc.with_ymd_and_hms(2026, 1, 1, 23, 30, 0).unwrap();
+        assert_eq!(run_tick(&config, late_night, &work).await, TickOutcome::QuietHours);
+        assert!(work.calls.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tick_outside_quiet_hours_scans_and_refreshes() {
+        let mut config = config_with_quiet_hours("22:00", "07:00");
+        config.scheduled_warmup.enabled = true;
+        let work = RecordingWork::default();
+
+        let afternoon = Utc.with_ymd_and_hms(2026, 1, 2, 15, 0, 0).unwrap();
+        assert_eq!(run_tick(&config, afternoon, &work).await, TickOutcome::Scanned);
+        assert_eq!(*work.calls.lock().unwrap(), vec!["stale_check", "warmup_scan"]);
+
+        // 未开启定时预热时只检查长期停用账号
+        config.scheduled_warmup.enabled = false;
+        let work = RecordingWork::default();
+        assert_eq!(run_tick(&config, afternoon, &work).await, TickOutcome::WarmupDisabled);
+        assert_eq!(*work.calls.lock().unwrap(), vec!["stale_check"]);
+    }
+
+    #[test]
+    fn test_quiet_hours_with_fixed_offset() {
+        let mut config = config_with_quiet_hours("01:00", "05:00");
+        config.quiet_hours.timezone = "+08:00".to_string();
+        // 18:00 UTC == 02:00 (+08:00)
+        let inside = Utc.with_ymd_and_hms(2026, 1, 1, 18, 0, 0).unwrap();
+        let outside = Utc.with_ymd_and_hms(2026, 1, 1, 2, 0, 0).unwrap();
+        assert!(is_quiet_hours(&config, inside));
+        assert!(!is_quiet_hours(&config, outside));
+    }
+}
@@ -0,0 +1,160 @@
+// 切换账号预演 (dry-run)
+// 按 switch_account 的同一套规则解析 storage.json / 数据库路径与将写入的设备指纹，
+// 但不刷新 token、不写任何文件、不关闭或启动进程，用于排查切换问题。
+
+use serde::Serialize;
+use std::path::PathBuf;
+
+use crate::models::{Account, DeviceProfile};
+use crate::modules::{account, db, device, process};
+
+/// 切换时写入 storage.json 的设备指纹来源 (按优先级)
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SwitchProfileSource {
+    /// 账号绑定的指纹
+    Bound,
+    /// 全局原始指纹
+    GlobalOriginal,
+    /// 当前 storage.json 中的指纹 (同时保存为全局原始指纹)
+    CurrentStorage,
+    /// 新生成的指纹 (storage.json 不可读时)
+    Generated,
+}
+
+/// 切换账号将执行的操作
+#[derive(Debug, Clone, Serialize)]
+pub struct SwitchPlan {
+    pub account_id: String,
+    pub email: String,
+    /// 将写入的 storage.json (无法定位时见 storage_error)
+    pub storage_path: Option<String>,
+    pub storage_error: Option<String>,
+    /// 将写入的设备指纹；来源为 Generated 时切换时才随机生成，这里为空
+    pub device_profile: Option<DeviceProfile>,
+    pub profile_source: SwitchProfileSource,
+    /// 将注入 token 的 state.vscdb
+    pub db_path: Option<String>,
+    pub db_exists: bool,
+    pub db_error: Option<String>,
+    /// Antigravity 正在运行，切换时会先将其关闭
+    pub ide_running: bool,
+    /// 切换前需要先刷新 token
+    pub token_refresh_needed: bool,
+}
+
+/// 按优先级解析切换时使用的设备指纹：账号绑定 > 全局原始 > 当前 storage.json > 新生成 (None)
+pub fn resolve_switch_profile(
+    account: &Account,
+    storage_path: Option<&std::path::Path>,
+    global_original: Option<DeviceProfile>,
+) -> (Option<DeviceProfile>, SwitchProfileSource) {
+    if let Some(profile) = account.device_profile.clone() {
+        return (Some(profile), SwitchProfileSource::Bound);
+    }
+    if let Some(profile) = global_original {
+        return (Some(profile), SwitchProfileSource::GlobalOriginal);
+    }
+    match storage_path.and_then(|p| device::read_profile(p).ok()) {
+        Some(profile) => (Some(profile), SwitchProfileSource::CurrentStorage),
+        None => (None, SwitchProfileSource::Generated),
+    }
+}
+
+/// 由已解析的环境生成切换计划 (纯读取)
+fn plan_switch(
+    account: &Account,
+    storage_path: Result<PathBuf, String>,
+    db_path: Result<PathBuf, String>,
+    global_original: Option<DeviceProfile>,
+    ide_running: bool,
+    now: i64,
+) -> SwitchPlan {
+    let (device_profile, profile_source) =
+        resolve_switch_profile(account, storage_path.as_deref().ok(), global_original);
+    SwitchPlan {
+        account_id: account.id.clone(),
+        email: account.email.clone(),
+        storage_path: storage_path.as_ref().ok().map(|p| p.display().to_string()),
+        storage_error: storage_path.err(),
+        device_profile,
+        profile_source,
+        db_path: db_path.as_ref().ok().map(|p| p.display().to_string()),
+        db_exists: db_path.as_ref().is_ok_and(|p| p.exists()),
+        db_error: db_path.err(),
+        ide_running,
+        // 与 oauth::ensure_fresh_token 的判断一致
+        token_refresh_needed: account.token.expiry_timestamp <= now + 300,
+    }
+}
+
+/// 预演切换到指定账号
+pub async fn dry_run(account_id: &str) -> Result<SwitchPlan, String> {
+    let account = account::load_account(account_id)?;
+    // 进程检测会遍历进程列表，放到阻塞线程池中
+    let ide_running = tokio::task::spawn_blocking(process::is_antigravity_running)
+        .await
+        .map_err(|e| format!("进程检测任务异常退出: {}", e))?;
+    Ok(plan_switch(
+        &account,
+        device::get_storage_path(),
+        db::get_db_path(),
+        device::load_global_original(),
+        ide_running,
+        chrono::Utc::now().timestamp(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TokenData;
+
+    #[test]
+    fn test_plan_reflects_bound_profile_and_existing_db_without_writes() {
+        let dir = std::env::temp_dir().join(format!("ag_switch_plan_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let storage_path = dir.join("storage.json");
+        let db_path = dir.join("state.vscdb");
+        let storage = serde_json::json!({
+            "telemetry": {
+                "machineId": "current-machine",
+                "macMachineId": "current-mac",
+                "devDeviceId": "current-dev",
+                "sqmId": "current-sqm",
+            }
+        })
+        .to_string();
+        std::fs::write(&storage_path, &storage).unwrap();
+        std::fs::write(&db_path, b"db").unwrap();
+
+        let now = 1_700_000_000;
+        let token = TokenData::new("a".to_string(), "r".to_string(), 3600, None, None, None);
+        let mut account = Account::new("id".to_string(), "a@example.com".to_string(), token);
+        account.token.expiry_timestamp = now + 60;
+        let bound = device::generate_profile();
+        account.device_profile = Some(bound.clone());
+
+        let plan = plan_switch(&account, Ok(storage_path.clone()), Ok(db_path.clone()), None, true, now);
+        assert_eq!(plan.profile_source, SwitchProfileSource::Bound);
+        assert_eq!(plan.device_profile.unwrap().machine_id, bound.machine_id);
+        assert_eq!(plan.db_path.as_deref(), Some(db_path.display().to_string().as_str()));
+        assert!(plan.db_exists);
+        assert!(plan.ide_running);
+        assert!(plan.token_refresh_needed);
+
+        // 未绑定时读取当前 storage.json；数据库不存在时如实报告
+        account.device_profile = None;
+        let plan = plan_switch(&account, Ok(storage_path.clone()), Ok(dir.join("missing.vscdb")), None, false, now);
+        assert_eq!(plan.profile_source, SwitchProfileSource::CurrentStorage);
+        assert_eq!(plan.device_profile.unwrap().machine_id, "current-machine");
+        assert!(!plan.db_exists);
+
+        // 预演不修改任何文件
+        assert_eq!(std::fs::read_to_string(&storage_path).unwrap(), storage);
+        assert_eq!(std::fs::read(&db_path).unwrap(), b"db");
+        assert!(!db_path.with_extension("vscdb.backup").exists());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
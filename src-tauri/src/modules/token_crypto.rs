@@ -0,0 +1,351 @@
+// 账号 token 静态加密
+// 使用用户口令派生密钥 (PBKDF2-HMAC-SHA256) 对账号文件中的 token 对象做 AES-256-GCM 加密，
+// 加密后的 token 对象带有 `encrypted: true` 标记，未加密的旧文件保持原样可读。
+
+use base64::{engine::general_purpose, Engine as _};
+use once_cell::sync::Lazy;
+use ring::{aead, pbkdf2, rand::SecureRandom, rand::SystemRandom};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+
+/// 提供解密口令的环境变量
+pub const PASSPHRASE_ENV: &str = "ANTIGRAVITY_TOKEN_PASSPHRASE";
+
+const FORMAT_VERSION: u64 = 1;
+const PBKDF2_ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const AAD: &[u8] = b"antigravity-token-v1";
+
+static PASSPHRASE: Lazy<RwLock<Option<String>>> = Lazy::new(|| {
+    RwLock::new(std::env::var(PASSPHRASE_ENV).ok().filter(|p| !p.is_empty()))
+});
+
+/// 保存账号时是否加密 token (由配置 `encrypt_tokens_at_rest` 同步)
+static ENCRYPT_ON_SAVE: AtomicBool = AtomicBool::new(false);
+
+/// 派生密钥缓存 (salt -> key)，避免每次加载账号都执行 PBKDF2
+static KEY_CACHE: Lazy<Mutex<HashMap<Vec<u8>, [u8; 32]>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 本进程新加密文件使用的 salt (同一批文件共用，配合密钥缓存)
+static PROCESS_SALT: Lazy<[u8; SALT_LEN]> = Lazy::new(|| {
+    let mut salt = [0u8; SALT_LEN];
+    let _ = SystemRandom::new().fill(&mut salt);
+    salt
+});
+
+/// 设置 (或清除) 当前进程使用的解密口令
+pub fn set_passphrase(passphrase: Option<String>) {
+    if let Ok(mut p) = PASSPHRASE.write() {
+        *p = passphrase.filter(|p| !p.is_empty());
+    }
+    if let Ok(mut cache) = KEY_CACHE.lock() {
+        cache.clear();
+    }
+}
+
+/// 是否已提供解密口令
+pub fn has_passphrase() -> bool {
+    PASSPHRASE.read().map(|p| p.is_some()).unwrap_or(false)
+}
+
+pub fn set_encrypt_on_save(enabled: bool) {
+    ENCRYPT_ON_SAVE.store(enabled, Ordering::SeqCst);
+}
+
+pub fn encrypt_on_save() -> bool {
+    ENCRYPT_ON_SAVE.load(Ordering::SeqCst)
+}
+
+/// 当前口令 (备份加密等复用同一口令)
+pub fn passphrase() -> Option<String> {
+    PASSPHRASE.read().ok().and_then(|p| p.clone())
+}
+
+fn current_passphrase() -> Result<String, String> {
+    PASSPHRASE
+        .read()
+        .ok()
+        .and_then(|p| p.clone())
+        .ok_or_else(|| format!("账号 token 已加密，但未提供解密口令 (请设置环境变量 {} 或在应用中解锁)", PASSPHRASE_ENV))
+}
+
+/// token 对象是否为加密格式
+pub fn is_encrypted(token: &Value) -> bool {
+    token.get("encrypted").and_then(|v| v.as_bool()).unwrap_or(false)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> [u8; 32] {
+    let cache_key = [salt, passphrase.as_bytes()].concat();
+    if let Some(key) = KEY_CACHE.lock().ok().and_then(|c| c.get(&cache_key).copied()) {
+        return key;
+    }
+
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).expect("non-zero iterations"),
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    if let Ok(mut cache) = KEY_CACHE.lock() {
+        cache.insert(cache_key, key);
+    }
+    key
+}
+
+fn aead_key(passphrase: &str, salt: &[u8]) -> Result<aead::LessSafeKey, String> {
+    let key = derive_key(passphrase, salt);
+    let unbound = aead::UnboundKey::new(&aead::AES_256_GCM, &key).map_err(|_| "初始化加密密钥失败".to_string())?;
+    Ok(aead::LessSafeKey::new(unbound))
+}
+
+/// 使用指定口令加密 token 对象
+pub fn encrypt_token_with(token: &Value, passphrase: &str, salt: &[u8]) -> Result<Value, String> {
+    if is_encrypted(token) {
+        return Ok(token.clone());
+    }
+
+    let key = aead_key(passphrase, salt)?;
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce_bytes)
+        .map_err(|_| "生成随机数失败".to_string())?;
+
+    let mut in_out = serde_json::to_vec(token).map_err(|e| format!("序列化 token 失败: {}", e))?;
+    key.seal_in_place_append_tag(
+        aead::Nonce::assume_unique_for_key(nonce_bytes),
+        aead::Aad::from(AAD),
+        &mut in_out,
+    )
+    .map_err(|_| "加密 token 失败".to_string())?;
+
+    Ok(json!({
+        "encrypted": true,
+        "version": FORMAT_VERSION,
+        "salt": general_purpose::STANDARD.encode(salt),
+        "nonce": general_purpose::STANDARD.encode(nonce_bytes),
+        "ciphertext": general_purpose::STANDARD.encode(&in_out),
+    }))
+}
+
+/// 使用指定口令解密 token 对象 (未加密的对象原样返回)
+pub fn decrypt_token_with(token: &Value, passphrase: &str) -> Result<Value, String> {
+    if !is_encrypted(token) {
+        return Ok(token.clone());
+    }
+
+    let field = |name: &str| -> Result<Vec<u8>, String> {
+        let raw = token
+            .get(name)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| format!("加密 token 缺少 {} 字段", name))?;
+        general_purpose::STANDARD
+            .decode(raw)
+            .map_err(|e| format!("加密 token 的 {} 字段无效: {}", name, e))
+    };
+    let salt = field("salt")?;
+    let nonce: [u8; NONCE_LEN] = field("nonce")?
+        .try_into()
+        .map_err(|_| "加密 token 的 nonce 长度无效".to_string())?;
+    let mut in_out = field("ciphertext")?;
+
+    let key = aead_key(passphrase, &salt)?;
+    let plaintext = key
+        .open_in_place(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::from(AAD), &mut in_out)
+        .map_err(|_| "解密 token 失败：口令错误或文件已损坏".to_string())?;
+
+    serde_json::from_slice(plaintext).map_err(|e| format!("解析解密后的 token 失败: {}", e))
+}
+
+/// 就地解密账号 JSON 中的 token 对象，返回原文件是否为加密格式
+pub fn decrypt_account_json(account: &mut Value) -> Result<bool, String> {
+    let Some(token) = account.get("token") else {
+        return Ok(false);
+    };
+    if !is_encrypted(token) {
+        return Ok(false);
+    }
+    let plain = decrypt_token_with(token, &current_passphrase()?)?;
+    account["token"] = plain;
+    Ok(true)
+}
+
+/// 就地加密账号 JSON 中的 token 对象
+pub fn encrypt_account_json(account: &mut Value) -> Result<(), String> {
+    let Some(token) = account.get("token") else {
+        return Ok(());
+    };
+    if is_encrypted(token) {
+        return Ok(());
+    }
+    let passphrase = current_passphrase().map_err(|_| "已启用 token 加密，但未提供加密口令".to_string())?;
+    account["token"] = encrypt_token_with(token, &passphrase, PROCESS_SALT.as_slice())?;
+    Ok(())
+}
+
+/// 修改账号 JSON 中的 token 字段，保持原有的加密状态
+pub fn update_token_fields<F: FnOnce(&mut Value)>(account: &mut Value, f: F) -> Result<(), String> {
+    let was_encrypted = decrypt_account_json(account)?;
+    f(&mut account["token"]);
+    if was_encrypted || encrypt_on_save() {
+        encrypt_account_json(account)?;
+    }
+    Ok(())
+}
+
+/// 读取账号 JSON 文件 (自动解密 token)
+pub fn read_account_json(path: &std::path::Path) -> Result<Value, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("读取账号数据失败: {}", e))?;
+    let mut account: Value = serde_json::from_str(&content).map_err(|e| format!("解析账号数据失败: {}", e))?;
+    decrypt_account_json(&mut account)?;
+    Ok(account)
+}
+
+/// 目录下的账号 JSON 文件 (无法解析的文件记录警告后跳过)
+fn account_files(accounts_dir: &std::path::Path) -> Result<Vec<(std::path::PathBuf, Value)>, String> {
+    let entries = std::fs::read_dir(accounts_dir).map_err(|e| format!("读取账号目录失败: {}", e))?;
+    let mut files = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("json") {
+            continue;
+        }
+        let content = std::fs::read_to_string(&path).map_err(|e| format!("读取 {:?} 失败: {}", path, e))?;
+        match serde_json::from_str(&content) {
+            Ok(account) => files.push((path, account)),
+            Err(e) => crate::modules::logger::log_warn(&format!("跳过无法解析的账号文件 {:?}: {}", path, e)),
+        }
+    }
+    Ok(files)
+}
+
+/// 用已加密的账号文件校验口令；目录中没有加密文件时任何口令都可用
+pub fn verify_passphrase(accounts_dir: &std::path::Path, passphrase: &str) -> Result<(), String> {
+    let encrypted = account_files(accounts_dir)?
+        .into_iter()
+        .find_map(|(_, account)| account.get("token").filter(|t| is_encrypted(t)).cloned());
+    match encrypted {
+        Some(token) => decrypt_token_with(&token, passphrase)
+            .map(|_| ())
+            .map_err(|_| "口令与已加密的账号文件不一致，已中止迁移 (避免账号文件使用不同口令加密)".to_string()),
+        None => Ok(()),
+    }
+}
+
+/// 将目录下所有明文账号文件迁移为加密格式 (单向)，返回迁移的文件数
+///
+/// 迁移前用已加密的文件校验当前口令，口令不一致时不写入任何文件
+pub fn migrate_accounts_dir(accounts_dir: &std::path::Path) -> Result<usize, String> {
+    let passphrase = current_passphrase().map_err(|_| "迁移前请先提供加密口令".to_string())?;
+    verify_passphrase(accounts_dir, &passphrase)?;
+
+    let mut migrated = 0;
+    for (path, mut account) in account_files(accounts_dir)? {
+        if account.get("token").map_or(true, is_encrypted) {
+            continue;
+        }
+        encrypt_account_json(&mut account)?;
+        crate::modules::account::write_account_json(&path, &account)?;
+        migrated += 1;
+    }
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_token() -> Value {
+        json!({
+            "access_token": "ya29.access",
+            "refresh_token": "1//refresh-secret",
+            "expires_in": 3600,
+            "expiry_timestamp": 1_900_000_000,
+            "project_id": "test-project"
+        })
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_round_trip() {
+        let token = sample_token();
+        let encrypted = encrypt_token_with(&token, "correct horse", b"0123456789abcdef").unwrap();
+
+        assert!(is_encrypted(&encrypted));
+        let serialized = encrypted.to_string();
+        assert!(!serialized.contains("1//refresh-secret"));
+        assert!(!serialized.contains("ya29.access"));
+
+        assert_eq!(decrypt_token_with(&encrypted, "correct horse").unwrap(), token);
+        assert!(decrypt_token_with(&encrypted, "wrong horse").is_err());
+    }
+
+    #[test]
+    fn test_legacy_plaintext_passes_through() {
+        let token = sample_token();
+        assert!(!is_encrypted(&token));
+        assert_eq!(decrypt_token_with(&token, "any").unwrap(), token);
+
+        let mut account = json!({ "id": "a", "email": "a@example.com", "token": token.clone() });
+        assert!(!decrypt_account_json(&mut account).unwrap());
+        assert_eq!(account["token"], token);
+    }
+
+    #[test]
+    fn test_migrate_dir_and_update_keeps_encryption() {
+        let dir = std::env::temp_dir().join(format!("ag_token_crypto_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("acc.json");
+        let account = json!({ "id": "acc", "email": "a@example.com", "token": sample_token() });
+        std::fs::write(&path, serde_json::to_string_pretty(&account).unwrap()).unwrap();
+
+        set_passphrase(Some("migration pass".to_string()));
+        assert_eq!(migrate_accounts_dir(&dir).unwrap(), 1);
+        // 已加密的文件不会被重复迁移
+        assert_eq!(migrate_accounts_dir(&dir).unwrap(), 0);
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("1//refresh-secret"));
+        assert_eq!(read_account_json(&path).unwrap()["token"], sample_token());
+
+        let mut on_disk: Value = serde_json::from_str(&raw).unwrap();
+        update_token_fields(&mut on_disk, |token| {
+            token["access_token"] = Value::String("ya29.refreshed".to_string());
+        })
+        .unwrap();
+        assert!(is_encrypted(&on_disk["token"]));
+        decrypt_account_json(&mut on_disk).unwrap();
+        assert_eq!(on_disk["token"]["access_token"], "ya29.refreshed");
+        assert_eq!(on_disk["token"]["refresh_token"], "1//refresh-secret");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_verify_passphrase_against_encrypted_files() {
+        let dir = std::env::temp_dir().join(format!("ag_token_crypto_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // 没有加密文件时任何口令都可用
+        std::fs::write(
+            dir.join("plain.json"),
+            serde_json::to_string_pretty(&json!({ "id": "plain", "token": sample_token() })).unwrap(),
+        )
+        .unwrap();
+        assert!(verify_passphrase(&dir, "anything").is_ok());
+
+        let encrypted = encrypt_token_with(&sample_token(), "right pass", b"0123456789abcdef").unwrap();
+        std::fs::write(
+            dir.join("encrypted.json"),
+            serde_json::to_string_pretty(&json!({ "id": "encrypted", "token": encrypted })).unwrap(),
+        )
+        .unwrap();
+        assert!(verify_passphrase(&dir, "right pass").is_ok());
+        assert!(verify_passphrase(&dir, "wrong pass").is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
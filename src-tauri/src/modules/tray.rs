@@ -5,6 +5,11 @@ use tauri::{
     Manager, Runtime, Emitter, Listener,
 };
 use crate::modules;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
 
 pub fn create_tray<R: Runtime>(app: &tauri::AppHandle<R>) -> tauri::Result<()> {
     // 1. 加载配置获取语言设置
@@ -19,37 +24,11 @@ pub fn create_tray<R: Runtime>(app: &tauri::AppHandle<R>) -> tauri::Result<()> {
     let (width, height) = img.dimensions();
     let icon = Image::new_owned(img.into_raw(), width, height);
 
-    // 3. 定义菜单项（使用翻译文本）
-    // 状态区
-    let loading_text = format!("{}: ...", texts.current);
-    let quota_text = format!("{}: --", texts.quota);
-    let info_user = MenuItem::with_id(app, "info_user", &loading_text, false, None::<&str>)?;
-    let info_quota = MenuItem::with_id(app, "info_quota", &quota_text, false, None::<&str>)?;
-
-    // 快捷操作区
-    let switch_next = MenuItem::with_id(app, "switch_next", &texts.switch_next, true, None::<&str>)?;
-    let refresh_curr = MenuItem::with_id(app, "refresh_curr", &texts.refresh_current, true, None::<&str>)?;
-    
-    // 系统功能
-    let show_i = MenuItem::with_id(app, "show", &texts.show_window, true, None::<&str>)?;
-    let quit_i = MenuItem::with_id(app, "quit", &texts.quit, true, None::<&str>)?;
-    
-    let sep1 = PredefinedMenuItem::separator(app)?;
-    let sep2 = PredefinedMenuItem::separator(app)?;
-    let sep3 = PredefinedMenuItem::separator(app)?;
-
-    // 4. 构建菜单
-    let menu = Menu::with_items(app, &[
-        &info_user,
-        &info_quota,
-        &sep1,
-        &switch_next,
-        &refresh_curr,
-        &sep2,
-        &show_i,
-        &sep3,
-        &quit_i,
-    ])?;
+    // 3. 构建菜单 (保留菜单项句柄，后续增量更新)
+    let model = TrayModel::placeholder(&texts);
+    let rendered = build_menu(app, model)?;
+    let menu = rendered.menu.clone();
+    app.manage(TrayMenuState::<R> { rendered: Mutex::new(Some(rendered)) });
 
     // 4. 构建托盘
     let _ = TrayIconBuilder::with_id("main")
@@ -157,98 +136,526 @@ pub fn create_tray<R: Runtime>(app: &tauri::AppHandle<R>) -> tauri::Result<()> {
     Ok(())
 }
 
+/// 连续更新请求的合并窗口
+const TRAY_UPDATE_DEBOUNCE_MS: u64 = 300;
+
+static TRAY_DEBOUNCER: Lazy<UpdateDebouncer> =
+    Lazy::new(|| UpdateDebouncer::new(Duration::from_millis(TRAY_UPDATE_DEBOUNCE_MS)));
+
 /// 更新托盘菜单的辅助函数
+///
+/// 短时间内的多次调用 (如批量刷新配额) 只在最后一次调用后渲染一次，且只修改文本有变化的菜单项
 pub fn update_tray_menus<R: Runtime>(app: &tauri::AppHandle<R>) {
     let app_clone = app.clone();
+    let ticket = TRAY_DEBOUNCER.bump();
     tauri::async_runtime::spawn(async move {
-         // 读取配置获取语言
-         let config = modules::load_app_config().unwrap_or_default();
-         let texts = modules::i18n::get_tray_texts(&config.language);
-         
-         // 获取当前账号信息
-         let current = modules::get_current_account_id().unwrap_or(None);
-         
-         let mut menu_lines = Vec::new();
-         let mut user_text = format!("{}: {}", texts.current, texts.no_account);
-
-         if let Some(id) = current {
-             if let Ok(account) = modules::load_account(&id) {
-                 user_text = format!("{}: {}", texts.current, account.email);
-                 
-                 if let Some(q) = account.quota {
-                     if q.is_forbidden {
-                         menu_lines.push(format!("🚫 {}", texts.forbidden));
-                     } else {
-                         // 提取 3 个指定模型
-                         let mut gemini_high = 0;
-                         let mut gemini_image = 0;
-                         let mut claude = 0;
-                         
-                         // 使用严格匹配，与前端一致
-                         for m in q.models {
-                             let name = m.name.to_lowercase();
-                             if name == "gemini-3-pro-high" { gemini_high = m.percentage; }
-                             if name == "gemini-3-pro-image" { gemini_image = m.percentage; }
-                             if name == "claude-sonnet-4-5" { claude = m.percentage; }
-                         }
-                         
-                         menu_lines.push(format!("Gemini High: {}%", gemini_high));
-                         menu_lines.push(format!("Gemini Image: {}%", gemini_image));
-                         menu_lines.push(format!("Claude 4.5: {}%", claude));
-                     }
-                 } else {
-                     menu_lines.push(texts.unknown_quota.clone());
-                 }
-             } else {
-                 user_text = format!("{}: Error", texts.current);
-                 menu_lines.push(format!("{}: --", texts.quota));
-             }
-         } else {
-             menu_lines.push(texts.unknown_quota.clone());
-         };
-
-         // 重新构建菜单项
-         let info_user = MenuItem::with_id(&app_clone, "info_user", &user_text, false, None::<&str>);
-         
-         // 动态创建额度项
-         let mut quota_items = Vec::new();
-         for (i, line) in menu_lines.iter().enumerate() {
-             let item = MenuItem::with_id(&app_clone, format!("info_quota_{}", i), line, false, None::<&str>);
-             if let Ok(item) = item {
-                 quota_items.push(item);
-             }
-         }
-         
-         let switch_next = MenuItem::with_id(&app_clone, "switch_next", &texts.switch_next, true, None::<&str>);
-         let refresh_curr = MenuItem::with_id(&app_clone, "refresh_curr", &texts.refresh_current, true, None::<&str>);
-         
-         let show_i = MenuItem::with_id(&app_clone, "show", &texts.show_window, true, None::<&str>);
-         let quit_i = MenuItem::with_id(&app_clone, "quit", &texts.quit, true, None::<&str>);
-         
-         if let (Ok(i_u), Ok(s_n), Ok(r_c), Ok(s), Ok(q)) = (info_user, switch_next, refresh_curr, show_i, quit_i) {
-             let sep1 = PredefinedMenuItem::separator(&app_clone).ok();
-             let sep2 = PredefinedMenuItem::separator(&app_clone).ok();
-             let sep3 = PredefinedMenuItem::separator(&app_clone).ok();
-             
-             let mut items: Vec<&dyn tauri::menu::IsMenuItem<R>> = vec![&i_u];
-             // 添加动态的额度项
-             for item in &quota_items {
-                 items.push(item);
-             }
-             
-             if let Some(ref s) = sep1 { items.push(s); }
-             items.push(&s_n);
-             items.push(&r_c);
-             if let Some(ref s) = sep2 { items.push(s); }
-             items.push(&s);
-             if let Some(ref s) = sep3 { items.push(s); }
-             items.push(&q);
-             
-             if let Ok(menu) = Menu::with_items(&app_clone, &items) {
-                 if let Some(tray) = app_clone.tray_by_id("main") {
-                     let _ = tray.set_menu(Some(menu));
-                 }
-             }
-         }
+        if !TRAY_DEBOUNCER.settle(ticket).await {
+            return;
+        }
+
+        // 读取账号与拼接文本较慢，放在后台线程完成
+        let model = match tokio::task::spawn_blocking(load_tray_model).await {
+            Ok(model) => model,
+            Err(e) => {
+                modules::logger::log_error(&format!("生成托盘菜单数据失败: {}", e));
+                return;
+            }
+        };
+        apply_tray_model(&app_clone, model);
     });
 }
+
+/// 立即 (不经合并窗口) 重新渲染托盘菜单并返回渲染结果
+///
+/// 仅读取本地账号与配置，不刷新配额也不切换账号，用于排查托盘显示错误
+pub async fn refresh_tray_now<R: Runtime>(app: &tauri::AppHandle<R>) -> Result<TraySnapshot, String> {
+    // 让尚在合并窗口中的更新作废，避免随后用旧数据覆盖
+    TRAY_DEBOUNCER.bump();
+
+    let model = tokio::task::spawn_blocking(load_tray_model)
+        .await
+        .map_err(|e| format!("生成托盘菜单数据失败: {}", e))?;
+    let snapshot = TraySnapshot::from_model(&model);
+    apply_tray_model(app, model);
+    Ok(snapshot)
+}
+
+/// 合并短时间内的连续更新请求 (只有窗口期内的最后一次请求会执行)
+pub(crate) struct UpdateDebouncer {
+    generation: AtomicU64,
+    delay: Duration,
+}
+
+impl UpdateDebouncer {
+    pub(crate) fn new(delay: Duration) -> Self {
+        Self { generation: AtomicU64::new(0), delay }
+    }
+
+    /// 登记一次更新请求，返回本次请求的代号
+    pub(crate) fn bump(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// 等待合并窗口结束，若期间没有更新的请求则返回 true
+    pub(crate) async fn settle(&self, ticket: u64) -> bool {
+        tokio::time::sleep(self.delay).await;
+        self.generation.load(Ordering::SeqCst) == ticket
+    }
+}
+
+/// 托盘菜单的展示内容 (与上一次渲染结果比较以决定需要修改的菜单项)
+#[derive(Debug, Clone, PartialEq)]
+struct TrayModel {
+    current_account_id: Option<String>,
+    current_account_email: Option<String>,
+    user_text: String,
+    quota_lines: Vec<String>,
+    switch_next: String,
+    refresh_current: String,
+    show_window: String,
+    quit: String,
+    /// 托盘提示 (开启 tray_show_daily_cost 时显示当天估算费用)
+    tooltip: Option<String>,
+}
+
+impl TrayModel {
+    fn placeholder(texts: &modules::i18n::TrayTexts) -> Self {
+        Self {
+            user_text: format!("{}: ...", texts.current),
+            quota_lines: vec![format!("{}: --", texts.quota)],
+            ..Self::with_actions(texts)
+        }
+    }
+
+    fn with_actions(texts: &modules::i18n::TrayTexts) -> Self {
+        Self {
+            current_account_id: None,
+            current_account_email: None,
+            user_text: String::new(),
+            quota_lines: Vec::new(),
+            switch_next: texts.switch_next.clone(),
+            refresh_current: texts.refresh_current.clone(),
+            show_window: texts.show_window.clone(),
+            quit: texts.quit.clone(),
+            tooltip: None,
+        }
+    }
+}
+
+/// 读取配置与当前账号，生成托盘展示内容
+fn load_tray_model() -> TrayModel {
+    // 读取配置获取语言
+    let config = modules::load_app_config().unwrap_or_default();
+    let texts = modules::i18n::get_tray_texts(&config.language);
+
+    // 获取当前账号信息
+    let current = modules::get_current_account_id()
+        .unwrap_or(None)
+        .map(|id| modules::load_account(&id));
+    let mut model = compose_tray_model(&texts, current);
+    if config.tray_show_daily_cost {
+        model.tooltip = modules::proxy_db::get_usage_costs(crate::proxy::pricing::UsageRange::Today)
+            .ok()
+            .map(|report| format!("{}: ${:.2}", texts.today_cost, report.total_usd));
+    }
+    model
+}
+
+/// 根据当前账号 (None 表示未选择，Err 表示读取失败) 拼接托盘展示内容
+fn compose_tray_model(
+    texts: &modules::i18n::TrayTexts,
+    current: Option<Result<crate::models::Account, String>>,
+) -> TrayModel {
+    let mut model = TrayModel::with_actions(texts);
+    model.user_text = format!("{}: {}", texts.current, texts.no_account);
+
+    match current {
+        Some(Ok(account)) => {
+            model.user_text = format!("{}: {}", texts.current, account.email);
+            model.current_account_id = Some(account.id);
+            model.current_account_email = Some(account.email);
+
+            if let Some(q) = account.quota {
+                if q.is_forbidden {
+                    model.quota_lines.push(format!("🚫 {}", texts.forbidden));
+                } else {
+                    // 提取 3 个指定模型
+                    let mut gemini_high = 0;
+                    let mut gemini_image = 0;
+                    let mut claude = 0;
+
+                    // 使用严格匹配，与前端一致
+                    for m in q.models {
+                        let name = m.name.to_lowercase();
+                        if name == "gemini-3-pro-high" { gemini_high = m.percentage; }
+                        if name == "gemini-3-pro-image" { gemini_image = m.percentage; }
+                        if name == "claude-sonnet-4-5" { claude = m.percentage; }
+                    }
+
+                    model.quota_lines.push(format!("Gemini High: {}%", gemini_high));
+                    model.quota_lines.push(format!("Gemini Image: {}%", gemini_image));
+                    model.quota_lines.push(format!("Claude 4.5: {}%", claude));
+                }
+            } else {
+                model.quota_lines.push(texts.unknown_quota.clone());
+            }
+        }
+        Some(Err(_)) => {
+            model.user_text = format!("{}: Error", texts.current);
+            model.quota_lines.push(format!("{}: --", texts.quota));
+        }
+        None => {
+            model.quota_lines.push(texts.unknown_quota.clone());
+        }
+    }
+
+    model
+}
+
+/// 托盘菜单中的一项 (分隔线的 id 为 `separator`)
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TrayMenuEntry {
+    pub id: String,
+    pub label: String,
+    pub enabled: bool,
+}
+
+/// 托盘菜单的渲染结果 (供前端与测试断言)
+#[derive(Debug, Clone, Serialize)]
+pub struct TraySnapshot {
+    /// 托盘标记为当前的账号
+    pub current_account_id: Option<String>,
+    pub current_account_email: Option<String>,
+    pub quota_summary: Vec<String>,
+    /// 按显示顺序排列的菜单项，与 `build_menu` 一致
+    pub items: Vec<TrayMenuEntry>,
+}
+
+impl TraySnapshot {
+    fn from_model(model: &TrayModel) -> Self {
+        let entry = |id: &str, label: &str, enabled: bool| TrayMenuEntry {
+            id: id.to_string(),
+            label: label.to_string(),
+            enabled,
+        };
+        let separator = || entry("separator", "", false);
+
+        let mut items = vec![entry("info_user", &model.user_text, false)];
+        for (i, line) in model.quota_lines.iter().enumerate() {
+            items.push(entry(&format!("info_quota_{}", i), line, false));
+        }
+        items.push(separator());
+        items.push(entry("switch_next", &model.switch_next, true));
+        items.push(entry("refresh_curr", &model.refresh_current, true));
+        items.push(separator());
+        items.push(entry("show", &model.show_window, true));
+        items.push(separator());
+        items.push(entry("quit", &model.quit, true));
+
+        Self {
+            current_account_id: model.current_account_id.clone(),
+            current_account_email: model.current_account_email.clone(),
+            quota_summary: model.quota_lines.clone(),
+            items,
+        }
+    }
+}
+
+/// 菜单项标识
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TrayItem {
+    User,
+    Quota(usize),
+    SwitchNext,
+    RefreshCurrent,
+    ShowWindow,
+    Quit,
+}
+
+/// 一次更新需要执行的操作
+#[derive(Debug, PartialEq)]
+enum TrayUpdate {
+    Unchanged,
+    /// 只修改这些菜单项的文本
+    Patch(Vec<TrayItem>),
+    /// 菜单结构变化 (额度行数不同)，需要重建
+    Rebuild,
+}
+
+fn plan_update(prev: Option<&TrayModel>, next: &TrayModel) -> TrayUpdate {
+    let Some(prev) = prev else {
+        return TrayUpdate::Rebuild;
+    };
+    if prev.quota_lines.len() != next.quota_lines.len() {
+        return TrayUpdate::Rebuild;
+    }
+
+    let mut changed = Vec::new();
+    if prev.user_text != next.user_text {
+        changed.push(TrayItem::User);
+    }
+    for (i, (old, new)) in prev.quota_lines.iter().zip(&next.quota_lines).enumerate() {
+        if old != new {
+            changed.push(TrayItem::Quota(i));
+        }
+    }
+    if prev.switch_next != next.switch_next {
+        changed.push(TrayItem::SwitchNext);
+    }
+    if prev.refresh_current != next.refresh_current {
+        changed.push(TrayItem::RefreshCurrent);
+    }
+    if prev.show_window != next.show_window {
+        changed.push(TrayItem::ShowWindow);
+    }
+    if prev.quit != next.quit {
+        changed.push(TrayItem::Quit);
+    }
+
+    if changed.is_empty() {
+        TrayUpdate::Unchanged
+    } else {
+        TrayUpdate::Patch(changed)
+    }
+}
+
+/// 已渲染的托盘菜单及其菜单项句柄
+struct RenderedTray<R: Runtime> {
+    model: TrayModel,
+    menu: Menu<R>,
+    info_user: MenuItem<R>,
+    quota_items: Vec<MenuItem<R>>,
+    switch_next: MenuItem<R>,
+    refresh_curr: MenuItem<R>,
+    show: MenuItem<R>,
+    quit: MenuItem<R>,
+}
+
+impl<R: Runtime> RenderedTray<R> {
+    fn item(&self, key: TrayItem) -> Option<(&MenuItem<R>, &str)> {
+        match key {
+            TrayItem::User => Some((&self.info_user, self.model.user_text.as_str())),
+            TrayItem::Quota(i) => self
+                .quota_items
+                .get(i)
+                .zip(self.model.quota_lines.get(i).map(String::as_str)),
+            TrayItem::SwitchNext => Some((&self.switch_next, self.model.switch_next.as_str())),
+            TrayItem::RefreshCurrent => Some((&self.refresh_curr, self.model.refresh_current.as_str())),
+            TrayItem::ShowWindow => Some((&self.show, self.model.show_window.as_str())),
+            TrayItem::Quit => Some((&self.quit, self.model.quit.as_str())),
+        }
+    }
+}
+
+/// 托盘菜单状态 (由 Tauri 托管)
+struct TrayMenuState<R: Runtime> {
+    rendered: Mutex<Option<RenderedTray<R>>>,
+}
+
+fn build_menu<R: Runtime, M: Manager<R>>(app: &M, model: TrayModel) -> tauri::Result<RenderedTray<R>> {
+    // 状态区
+    let info_user = MenuItem::with_id(app, "info_user", &model.user_text, false, None::<&str>)?;
+    let quota_items = model
+        .quota_lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| MenuItem::with_id(app, format!("info_quota_{}", i), line, false, None::<&str>))
+        .collect::<tauri::Result<Vec<_>>>()?;
+
+    // 快捷操作区
+    let switch_next = MenuItem::with_id(app, "switch_next", &model.switch_next, true, None::<&str>)?;
+    let refresh_curr = MenuItem::with_id(app, "refresh_curr", &model.refresh_current, true, None::<&str>)?;
+
+    // 系统功能
+    let show = MenuItem::with_id(app, "show", &model.show_window, true, None::<&str>)?;
+    let quit = MenuItem::with_id(app, "quit", &model.quit, true, None::<&str>)?;
+
+    let sep1 = PredefinedMenuItem::separator(app)?;
+    let sep2 = PredefinedMenuItem::separator(app)?;
+    let sep3 = PredefinedMenuItem::separator(app)?;
+
+    let mut items: Vec<&dyn tauri::menu::IsMenuItem<R>> = vec![&info_user];
+    for item in &quota_items {
+        items.push(item);
+    }
+    items.push(&sep1);
+    items.push(&switch_next);
+    items.push(&refresh_curr);
+    items.push(&sep2);
+    items.push(&show);
+    items.push(&sep3);
+    items.push(&quit);
+    let menu = Menu::with_items(app, &items)?;
+
+    Ok(RenderedTray {
+        model,
+        menu,
+        info_user,
+        quota_items,
+        switch_next,
+        refresh_curr,
+        show,
+        quit,
+    })
+}
+
+/// 将展示内容应用到托盘：无变化则跳过，结构不变时只改文本，否则重建菜单
+fn apply_tray_model<R: Runtime>(app: &tauri::AppHandle<R>, model: TrayModel) {
+    let Some(state) = app.try_state::<TrayMenuState<R>>() else {
+        return;
+    };
+    let Ok(mut guard) = state.rendered.lock() else {
+        return;
+    };
+
+    // 提示文本不在菜单中，单独比较
+    if guard.as_ref().map_or(true, |r| r.model.tooltip != model.tooltip) {
+        if let Some(tray) = app.tray_by_id("main") {
+            let _ = tray.set_tooltip(model.tooltip.as_deref());
+        }
+    }
+
+    match plan_update(guard.as_ref().map(|r| &r.model), &model) {
+        TrayUpdate::Unchanged => {
+            if let Some(rendered) = guard.as_mut() {
+                rendered.model.tooltip = model.tooltip;
+            }
+        }
+        TrayUpdate::Patch(keys) => {
+            if let Some(rendered) = guard.as_mut() {
+                rendered.model = model;
+                for key in keys {
+                    if let Some((item, text)) = rendered.item(key) {
+                        let _ = item.set_text(text);
+                    }
+                }
+            }
+        }
+        TrayUpdate::Rebuild => match build_menu(app, model) {
+            Ok(rendered) => {
+                if let Some(tray) = app.tray_by_id("main") {
+                    let _ = tray.set_menu(Some(rendered.menu.clone()));
+                }
+                *guard = Some(rendered);
+            }
+            Err(e) => modules::logger::log_error(&format!("重建托盘菜单失败: {}", e)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    fn model(user: &str, lines: &[&str]) -> TrayModel {
+        TrayModel {
+            current_account_id: None,
+            current_account_email: None,
+            user_text: user.to_string(),
+            quota_lines: lines.iter().map(|s| s.to_string()).collect(),
+            switch_next: "Switch".to_string(),
+            refresh_current: "Refresh".to_string(),
+            show_window: "Show".to_string(),
+            quit: "Quit".to_string(),
+            tooltip: None,
+        }
+    }
+
+    #[test]
+    fn test_plan_update_only_patches_changed_items() {
+        let prev = model("Current: a@example.com", &["Gemini High: 80%", "Gemini Image: 50%", "Claude 4.5: 20%"]);
+
+        assert_eq!(plan_update(None, &prev), TrayUpdate::Rebuild);
+        assert_eq!(plan_update(Some(&prev), &prev.clone()), TrayUpdate::Unchanged);
+
+        let next = model("Current: a@example.com", &["Gemini High: 75%", "Gemini Image: 50%", "Claude 4.5: 20%"]);
+        assert_eq!(plan_update(Some(&prev), &next), TrayUpdate::Patch(vec![TrayItem::Quota(0)]));
+
+        let switched = model("Current: b@example.com", &["Gemini High: 75%", "Gemini Image: 50%", "Claude 4.5: 10%"]);
+        assert_eq!(
+            plan_update(Some(&next), &switched),
+            TrayUpdate::Patch(vec![TrayItem::User, TrayItem::Quota(2)])
+        );
+
+        // 额度行数变化 (例如账号被禁用) 需要重建菜单
+        let forbidden = model("Current: b@example.com", &["🚫 Forbidden"]);
+        assert_eq!(plan_update(Some(&switched), &forbidden), TrayUpdate::Rebuild);
+    }
+
+    #[tokio::test]
+    async fn test_debounce_collapses_rapid_updates() {
+        let debouncer = Arc::new(UpdateDebouncer::new(Duration::from_millis(50)));
+        let renders = Arc::new(AtomicUsize::new(0));
+
+        // 模拟批量刷新期间的连续更新请求
+        let mut tasks = Vec::new();
+        for _ in 0..100 {
+            let ticket = debouncer.bump();
+            let debouncer = debouncer.clone();
+            let renders = renders.clone();
+            tasks.push(tokio::spawn(async move {
+                if debouncer.settle(ticket).await {
+                    renders.fetch_add(1, Ordering::SeqCst);
+                }
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(renders.load(Ordering::SeqCst), 1);
+
+        // 窗口结束后的新请求会再次执行
+        let ticket = debouncer.bump();
+        assert!(debouncer.settle(ticket).await);
+    }
+
+    fn texts() -> modules::i18n::TrayTexts {
+        modules::i18n::TrayTexts {
+            current: "Current".to_string(),
+            quota: "Quota".to_string(),
+            switch_next: "Switch".to_string(),
+            refresh_current: "Refresh".to_string(),
+            show_window: "Show".to_string(),
+            quit: "Quit".to_string(),
+            no_account: "None".to_string(),
+            unknown_quota: "Unknown".to_string(),
+            forbidden: "Forbidden".to_string(),
+            today_cost: "Today".to_string(),
+        }
+    }
+
+    fn account(id: &str, email: &str, claude_pct: i32) -> crate::models::Account {
+        let token = crate::models::TokenData::new("at".to_string(), "rt".to_string(), 3600, None, None, None);
+        let mut account = crate::models::Account::new(id.to_string(), email.to_string(), token);
+        let mut quota = crate::models::QuotaData::new();
+        quota.add_model("claude-sonnet-4-5".to_string(), claude_pct, String::new());
+        account.quota = Some(quota);
+        account
+    }
+
+    #[test]
+    fn test_snapshot_marks_switched_account_as_current() {
+        let texts = texts();
+        let a = account("acc-a", "a@example.com", 80);
+        let b = account("acc-b", "b@example.com", 30);
+
+        let before = TraySnapshot::from_model(&compose_tray_model(&texts, Some(Ok(a))));
+        assert_eq!(before.current_account_id.as_deref(), Some("acc-a"));
+
+        // 切换当前账号后重新渲染
+        let after = TraySnapshot::from_model(&compose_tray_model(&texts, Some(Ok(b))));
+        assert_eq!(after.current_account_id.as_deref(), Some("acc-b"));
+        assert_eq!(after.current_account_email.as_deref(), Some("b@example.com"));
+        assert_eq!(after.items[0].id, "info_user");
+        assert_eq!(after.items[0].label, "Current: b@example.com");
+        assert!(after.quota_summary.contains(&"Claude 4.5: 30%".to_string()));
+        assert!(after.items.iter().any(|i| i.id == "info_quota_2" && i.label == "Claude 4.5: 30%"));
+        assert_eq!(after.items.last().map(|i| i.id.as_str()), Some("quit"));
+
+        let none = TraySnapshot::from_model(&compose_tray_model(&texts, None));
+        assert_eq!(none.current_account_id, None);
+        assert_eq!(none.items[0].label, "Current: None");
+    }
+}
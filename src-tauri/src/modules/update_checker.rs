@@ -1,10 +1,18 @@
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use crate::modules::logger;
 
 const GITHUB_API_URL: &str = "https://api.github.com/repos/lbjlaq/Antigravity-Manager/releases/latest";
 const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
 const DEFAULT_CHECK_INTERVAL_HOURS: u64 = 24;
+const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+const MAX_RETRY_ATTEMPTS: u32 = 5;
+const RETRY_BASE_DELAY_MS: u64 = 500;
+
+/// 最近一次成功的检查结果，在检查间隔内直接复用，避免频繁请求 GitHub API
+static LAST_CHECK_RESULT: Lazy<Mutex<Option<(Instant, UpdateInfo)>>> = Lazy::new(|| Mutex::new(None));
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UpdateInfo {
@@ -14,6 +22,12 @@ pub struct UpdateInfo {
     pub download_url: String, // 原为 release_url
     pub release_notes: String,
     pub published_at: String,
+    /// GitHub API 触发限流，本次未能获取版本信息，稍后再试
+    #[serde(default)]
+    pub rate_limited: bool,
+    /// 限流重置时间 (Unix 秒，来自 X-RateLimit-Reset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,62 +36,222 @@ pub struct UpdateSettings {
     pub last_check_time: u64,
     #[serde(default = "default_check_interval")]
     pub check_interval_hours: u64,
+    /// 检测到新版本后自动在后台下载安装包 (需用户主动开启)
+    #[serde(default)]
+    pub auto_download: bool,
+    /// 网络瞬时故障 (DNS 解析、连接超时、5xx) 时的最大尝试次数
+    #[serde(default = "default_retry_attempts")]
+    pub retry_attempts: u32,
 }
 
 fn default_check_interval() -> u64 {
     DEFAULT_CHECK_INTERVAL_HOURS
 }
 
+fn default_retry_attempts() -> u32 {
+    DEFAULT_RETRY_ATTEMPTS
+}
+
 impl Default for UpdateSettings {
     fn default() -> Self {
         Self {
             auto_check: true,
             last_check_time: 0,
             check_interval_hours: DEFAULT_CHECK_INTERVAL_HOURS,
+            auto_download: false,
+            retry_attempts: DEFAULT_RETRY_ATTEMPTS,
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct GitHubRelease {
-    tag_name: String,
-    html_url: String,
-    body: String,
-    published_at: String,
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitHubRelease {
+    pub tag_name: String,
+    pub html_url: String,
+    #[serde(default)]
+    pub body: String,
+    pub published_at: String,
+    #[serde(default)]
+    pub assets: Vec<GitHubAsset>,
 }
 
-/// Check for updates from GitHub releases
-pub async fn check_for_updates() -> Result<UpdateInfo, String> {
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitHubAsset {
+    pub name: String,
+    pub browser_download_url: String,
+    #[serde(default)]
+    pub size: u64,
+}
+
+/// 单次获取 release 的失败类型
+#[derive(Debug)]
+enum FetchError {
+    /// 网络瞬时故障或服务端 5xx，可重试
+    Transient(String),
+    /// GitHub API 限流 (403 + X-RateLimit-Remaining: 0)，重试无意义
+    RateLimited { reset_at: Option<u64> },
+    Fatal(String),
+}
+
+impl FetchError {
+    fn into_message(self) -> String {
+        match self {
+            FetchError::Transient(e) | FetchError::Fatal(e) => e,
+            FetchError::RateLimited { .. } => "GitHub API rate limit exceeded, please try again later".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RetryPolicy {
+    attempts: u32,
+    base_delay: Duration,
+}
+
+impl RetryPolicy {
+    fn from_settings(settings: &UpdateSettings) -> Self {
+        Self {
+            attempts: settings.retry_attempts.clamp(1, MAX_RETRY_ATTEMPTS),
+            base_delay: Duration::from_millis(RETRY_BASE_DELAY_MS),
+        }
+    }
+
+    fn load() -> Self {
+        Self::from_settings(&load_update_settings().unwrap_or_default())
+    }
+}
+
+async fn fetch_release_once(client: &reqwest::Client, url: &str) -> Result<GitHubRelease, FetchError> {
+    let response = client.get(url).send().await.map_err(|e| {
+        let err_msg = format!("Failed to fetch release info: {}", e);
+        if e.is_connect() || e.is_timeout() || e.is_request() {
+            FetchError::Transient(err_msg)
+        } else {
+            FetchError::Fatal(err_msg)
+        }
+    })?;
+
+    let status = response.status();
+    if status == reqwest::StatusCode::FORBIDDEN || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        let header_u64 = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+        };
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS || header_u64("x-ratelimit-remaining") == Some(0) {
+            return Err(FetchError::RateLimited {
+                reset_at: header_u64("x-ratelimit-reset"),
+            });
+        }
+    }
+
+    if status.is_server_error() {
+        return Err(FetchError::Transient(format!("GitHub API returned status: {}", status)));
+    }
+    if !status.is_success() {
+        return Err(FetchError::Fatal(format!("GitHub API returned status: {}", status)));
+    }
+
+    response
+        .json()
+        .await
+        .map_err(|e| FetchError::Fatal(format!("Failed to parse release info: {}", e)))
+}
+
+/// 带指数退避的获取，仅对瞬时故障重试
+async fn fetch_release_with_retry(url: &str, policy: RetryPolicy) -> Result<GitHubRelease, FetchError> {
     let client = reqwest::Client::builder()
         .user_agent("Antigravity-Manager")
-        .timeout(std::time::Duration::from_secs(10))
+        .timeout(Duration::from_secs(10))
         .build()
-        .map_err(|e| {
-            let err_msg = format!("Failed to create HTTP client: {}", e);
-            logger::log_error(&err_msg);
-            err_msg
-        })?;
+        .map_err(|e| FetchError::Fatal(format!("Failed to create HTTP client: {}", e)))?;
+
+    let mut attempt = 1;
+    loop {
+        match fetch_release_once(&client, url).await {
+            Err(FetchError::Transient(e)) if attempt < policy.attempts => {
+                let delay = policy.base_delay * 2u32.pow(attempt - 1);
+                logger::log_warn(&format!(
+                    "检查更新失败 (第 {}/{} 次)，{}ms 后重试: {}",
+                    attempt,
+                    policy.attempts,
+                    delay.as_millis(),
+                    e
+                ));
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
 
+/// Fetch the latest release metadata from GitHub
+pub async fn fetch_latest_release() -> Result<GitHubRelease, String> {
     logger::log_info("正在从 GitHub 检查新版本...");
 
-    let response = client
-        .get(GITHUB_API_URL)
-        .send()
+    fetch_release_with_retry(GITHUB_API_URL, RetryPolicy::load())
         .await
         .map_err(|e| {
-            let err_msg = format!("Failed to fetch release info: {}", e);
+            let err_msg = e.into_message();
             logger::log_error(&err_msg);
             err_msg
-        })?;
+        })
+}
+
+/// Check for updates from GitHub releases
+///
+/// `force` 为 true 时 (用户在设置页手动检查) 跳过缓存，直接请求 GitHub
+pub async fn check_for_updates(force: bool) -> Result<UpdateInfo, String> {
+    let settings = load_update_settings().unwrap_or_default();
+    let cache_ttl = Duration::from_secs(effective_interval_hours(&settings) * 3600);
+    check_with_cache(GITHUB_API_URL, RetryPolicy::from_settings(&settings), cache_ttl, force).await
+}
 
-    if !response.status().is_success() {
-        return Err(format!("GitHub API returned status: {}", response.status()));
+async fn check_with_cache(url: &str, policy: RetryPolicy, cache_ttl: Duration, force: bool) -> Result<UpdateInfo, String> {
+    let cached = LAST_CHECK_RESULT
+        .lock()
+        .unwrap()
+        .as_ref()
+        .filter(|(checked_at, _)| !force && checked_at.elapsed() < cache_ttl)
+        .map(|(_, info)| info.clone());
+    if let Some(info) = cached {
+        return Ok(info);
     }
 
-    let release: GitHubRelease = response
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse release info: {}", e))?;
+    logger::log_info("正在从 GitHub 检查新版本...");
+    let info = check_for_updates_from(url, policy).await?;
+
+    if !info.rate_limited {
+        *LAST_CHECK_RESULT.lock().unwrap() = Some((Instant::now(), info.clone()));
+    }
+    Ok(info)
+}
+
+async fn check_for_updates_from(url: &str, policy: RetryPolicy) -> Result<UpdateInfo, String> {
+    let release = match fetch_release_with_retry(url, policy).await {
+        Ok(release) => release,
+        Err(FetchError::RateLimited { reset_at }) => {
+            logger::log_warn("GitHub API 已限流，稍后再检查更新");
+            return Ok(UpdateInfo {
+                current_version: CURRENT_VERSION.to_string(),
+                latest_version: CURRENT_VERSION.to_string(),
+                has_update: false,
+                download_url: String::new(),
+                release_notes: String::new(),
+                published_at: String::new(),
+                rate_limited: true,
+                retry_after: reset_at,
+            });
+        }
+        Err(e) => {
+            let err_msg = e.into_message();
+            logger::log_error(&err_msg);
+            return Err(err_msg);
+        }
+    };
 
     // Remove 'v' prefix if present
     let latest_version = release.tag_name.trim_start_matches('v').to_string();
@@ -98,11 +272,13 @@ pub async fn check_for_updates() -> Result<UpdateInfo, String> {
         download_url: release.html_url,
         release_notes: release.body,
         published_at: release.published_at,
+        rate_limited: false,
+        retry_after: None,
     })
 }
 
 /// Compare two semantic versions (e.g., "3.3.24" vs "3.3.23")
-fn compare_versions(latest: &str, current: &str) -> bool {
+pub fn compare_versions(latest: &str, current: &str) -> bool {
     let parse_version = |v: &str| -> Vec<u32> {
         v.split('.')
             .filter_map(|s| s.parse::<u32>().ok())
@@ -138,12 +314,15 @@ pub fn should_check_for_updates(settings: &UpdateSettings) -> bool {
         .as_secs();
 
     let elapsed_hours = (now - settings.last_check_time) / 3600;
-    let interval = if settings.check_interval_hours > 0 {
+    elapsed_hours >= effective_interval_hours(settings)
+}
+
+fn effective_interval_hours(settings: &UpdateSettings) -> u64 {
+    if settings.check_interval_hours > 0 {
         settings.check_interval_hours
     } else {
         DEFAULT_CHECK_INTERVAL_HOURS
-    };
-    elapsed_hours >= interval
+    }
 }
 
 /// Load update settings from config file
@@ -213,4 +392,127 @@ mod tests {
         settings.auto_check = false;
         assert!(!should_check_for_updates(&settings));
     }
+
+    /// 按顺序返回预设原始 HTTP 响应的本地服务，返回 (url, 已处理请求数)
+    async fn scripted_server(responses: Vec<String>) -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/releases/latest", listener.local_addr().unwrap());
+        let hits = std::sync::Arc::new(AtomicUsize::new(0));
+        let hits_clone = hits.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let Ok((mut socket, _)) = listener.accept().await else { return };
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                hits_clone.fetch_add(1, Ordering::SeqCst);
+                let _ = socket.write_all(response.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+        (url, hits)
+    }
+
+    fn http_response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+        let mut out = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+        for (k, v) in headers {
+            out.push_str(&format!("{}: {}\r\n", k, v));
+        }
+        out.push_str("\r\n");
+        out.push_str(body);
+        out
+    }
+
+    fn fast_policy(attempts: u32) -> RetryPolicy {
+        RetryPolicy { attempts, base_delay: Duration::from_millis(1) }
+    }
+
+    const RELEASE_JSON: &str = r#"{"tag_name":"v999.0.0","html_url":"https://example.com/r","body":"notes","published_at":"2026-01-01T00:00:00Z"}"#;
+
+    #[tokio::test]
+    async fn test_transient_failures_are_retried() {
+        use std::sync::atomic::Ordering;
+        let (url, hits) = scripted_server(vec![
+            http_response("503 Service Unavailable", &[], ""),
+            http_response("502 Bad Gateway", &[], ""),
+            http_response("200 OK", &[("Content-Type", "application/json")], RELEASE_JSON),
+        ])
+        .await;
+
+        let info = check_for_updates_from(&url, fast_policy(3)).await.unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert!(info.has_update);
+        assert_eq!(info.latest_version, "999.0.0");
+        assert!(!info.rate_limited);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up_after_configured_attempts() {
+        use std::sync::atomic::Ordering;
+        let (url, hits) = scripted_server(vec![
+            http_response("503 Service Unavailable", &[], ""),
+            http_response("503 Service Unavailable", &[], ""),
+            http_response("200 OK", &[("Content-Type", "application/json")], RELEASE_JSON),
+        ])
+        .await;
+
+        let err = check_for_updates_from(&url, fast_policy(2)).await.unwrap_err();
+        assert!(err.contains("503"));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_returns_friendly_result_without_retry() {
+        use std::sync::atomic::Ordering;
+        let (url, hits) = scripted_server(vec![
+            http_response(
+                "403 Forbidden",
+                &[("X-RateLimit-Remaining", "0"), ("X-RateLimit-Reset", "1900000000")],
+                r#"{"message":"API rate limit exceeded"}"#,
+            ),
+            http_response("200 OK", &[("Content-Type", "application/json")], RELEASE_JSON),
+        ])
+        .await;
+
+        let info = check_for_updates_from(&url, fast_policy(3)).await.unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        assert!(info.rate_limited);
+        assert!(!info.has_update);
+        assert_eq!(info.retry_after, Some(1_900_000_000));
+    }
+
+    #[tokio::test]
+    async fn test_forced_check_bypasses_cache() {
+        use std::sync::atomic::Ordering;
+        let newer = RELEASE_JSON.replace("v999.0.0", "v999.1.0");
+        let (url, hits) = scripted_server(vec![
+            http_response("200 OK", &[("Content-Type", "application/json")], RELEASE_JSON),
+            http_response("200 OK", &[("Content-Type", "application/json")], &newer),
+        ])
+        .await;
+        let ttl = Duration::from_secs(3600);
+
+        let first = check_with_cache(&url, fast_policy(1), ttl, false).await.unwrap();
+        assert_eq!(first.latest_version, "999.0.0");
+        // 后台检查命中缓存
+        let cached = check_with_cache(&url, fast_policy(1), ttl, false).await.unwrap();
+        assert_eq!(cached.latest_version, "999.0.0");
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // 手动检查跳过缓存并刷新缓存
+        let forced = check_with_cache(&url, fast_policy(1), ttl, true).await.unwrap();
+        assert_eq!(forced.latest_version, "999.1.0");
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        let cached = check_with_cache(&url, fast_policy(1), ttl, false).await.unwrap();
+        assert_eq!(cached.latest_version, "999.1.0");
+    }
+
+    #[tokio::test]
+    async fn test_plain_forbidden_is_an_error() {
+        let (url, _) = scripted_server(vec![http_response("403 Forbidden", &[("X-RateLimit-Remaining", "42")], "")]).await;
+        let err = check_for_updates_from(&url, fast_policy(3)).await.unwrap_err();
+        assert!(err.contains("403"));
+    }
 }
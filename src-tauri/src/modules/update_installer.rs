@@ -0,0 +1,801 @@
+// 更新下载与安装
+// 从 GitHub Release 下载当前平台的安装包，校验 sha256 后交给系统安装器 / 替换 AppImage
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use futures::StreamExt;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::Emitter;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+
+use crate::modules::logger;
+use crate::modules::update_checker::{self, GitHubAsset, GitHubRelease};
+
+const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+const UPDATES_DIR: &str = "updates";
+
+/// 进度事件最小间隔字节数，避免事件风暴
+const PROGRESS_EMIT_STEP: u64 = 256 * 1024;
+
+/// 按地址下载时的重试次数 (每次重试从已下载部分断点续传)
+const ASSET_DOWNLOAD_ATTEMPTS: u32 = 3;
+const ASSET_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// 更新下载 / 安装失败原因，按类型序列化给前端区分展示
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum UpdateError {
+    #[error("Already on the latest version")]
+    NoUpdate,
+
+    #[error("No release asset available for this platform ({0})")]
+    NoAssetForPlatform(String),
+
+    #[error("No published sha256 checksum found for {0}")]
+    ChecksumMissing(String),
+
+    #[error("Checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+
+    #[error("Partial download: received {received} of {expected} bytes")]
+    PartialDownload { received: u64, expected: u64 },
+
+    #[error("A download is already in progress")]
+    AlreadyDownloading,
+
+    #[error("No downloaded update is ready to install")]
+    NotDownloaded,
+
+    #[error("Update cancelled")]
+    Cancelled,
+
+    #[error("Network error: {0}")]
+    Network(String),
+
+    #[error("IO error: {0}")]
+    Io(String),
+
+    #[error("Install error: {0}")]
+    Install(String),
+}
+
+impl UpdateError {
+    /// 供前端区分失败类型的稳定标识
+    pub fn kind(&self) -> &'static str {
+        match self {
+            UpdateError::NoUpdate => "no_update",
+            UpdateError::NoAssetForPlatform(_) => "no_asset_for_platform",
+            UpdateError::ChecksumMissing(_) => "checksum_missing",
+            UpdateError::ChecksumMismatch { .. } => "checksum_mismatch",
+            UpdateError::PartialDownload { .. } => "partial_download",
+            UpdateError::AlreadyDownloading => "already_downloading",
+            UpdateError::NotDownloaded => "not_downloaded",
+            UpdateError::Cancelled => "cancelled",
+            UpdateError::Network(_) => "network",
+            UpdateError::Io(_) => "io",
+            UpdateError::Install(_) => "install",
+        }
+    }
+}
+
+// 序列化为 { kind, message }，便于前端按类型展示
+impl Serialize for UpdateError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+        let mut st = serializer.serialize_struct("UpdateError", 2)?;
+        st.serialize_field("kind", self.kind())?;
+        st.serialize_field("message", &self.to_string())?;
+        st.end()
+    }
+}
+
+/// 下载进度事件负载 (`update://download-progress`)
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress {
+    pub version: String,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+}
+
+/// 按地址下载的进度事件负载 (`update://progress`)
+#[derive(Debug, Clone, Serialize)]
+pub struct AssetProgress {
+    pub file_name: String,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+}
+
+/// 已下载并通过校验的安装包
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadedUpdate {
+    pub version: String,
+    pub asset_name: String,
+    pub path: PathBuf,
+    pub sha256: String,
+}
+
+/// 当前下载任务的取消信号
+static CANCEL_TX: Lazy<Mutex<Option<watch::Sender<bool>>>> = Lazy::new(|| Mutex::new(None));
+
+/// 占用下载槽位；任务结束 (含出错、panic 或被调用方丢弃) 时释放，之后才能开始新的下载
+struct DownloadSlot;
+
+impl DownloadSlot {
+    fn acquire() -> Result<(Self, watch::Receiver<bool>), UpdateError> {
+        let mut slot = CANCEL_TX.lock().unwrap_or_else(|e| e.into_inner());
+        if slot.is_some() {
+            return Err(UpdateError::AlreadyDownloading);
+        }
+        let (cancel_tx, cancel_rx) = watch::channel(false);
+        *slot = Some(cancel_tx);
+        Ok((DownloadSlot, cancel_rx))
+    }
+}
+
+impl Drop for DownloadSlot {
+    fn drop(&mut self) {
+        *CANCEL_TX.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+/// 最近一次下载完成的安装包
+static DOWNLOADED: Lazy<Mutex<Option<DownloadedUpdate>>> = Lazy::new(|| Mutex::new(None));
+
+/// 在取消信号到达时立即放弃正在进行的网络步骤
+async fn cancellable<T, F>(cancel: &mut watch::Receiver<bool>, fut: F) -> Result<T, UpdateError>
+where
+    F: std::future::Future<Output = Result<T, UpdateError>>,
+{
+    if *cancel.borrow() {
+        return Err(UpdateError::Cancelled);
+    }
+    tokio::select! {
+        res = fut => res,
+        _ = cancel.wait_for(|c| *c) => Err(UpdateError::Cancelled),
+    }
+}
+
+/// 为当前平台挑选安装包
+pub fn select_asset<'a>(assets: &'a [GitHubAsset], os: &str, arch: &str) -> Option<&'a GitHubAsset> {
+    let arch_tokens: &[&str] = match arch {
+        "x86_64" => &["x64", "x86_64", "amd64"],
+        "aarch64" => &["aarch64", "arm64"],
+        _ => &[],
+    };
+    let matches_arch = |name: &str| arch_tokens.iter().any(|t| name.contains(t));
+
+    let candidates: Vec<&GitHubAsset> = assets
+        .iter()
+        .filter(|a| {
+            let name = a.name.to_lowercase();
+            match os {
+                "windows" => name.ends_with(".msi") || name.ends_with("-setup.exe"),
+                "macos" => name.ends_with(".dmg"),
+                "linux" => name.ends_with(".appimage"),
+                _ => false,
+            }
+        })
+        .collect();
+
+    // 优先精确匹配架构，其次 universal 包
+    candidates
+        .iter()
+        .find(|a| matches_arch(&a.name.to_lowercase()))
+        .or_else(|| candidates.iter().find(|a| a.name.to_lowercase().contains("universal")))
+        .copied()
+}
+
+/// 从 release 正文或校验文件中查找指定文件的 sha256
+///
+/// 支持 `sha256sum` 输出格式 (`<hash>  <file>`) 以及正文中同一行出现文件名和哈希的写法
+pub fn find_sha256(text: &str, asset_name: &str) -> Option<String> {
+    text.lines()
+        .filter(|line| line.contains(asset_name))
+        .find_map(|line| {
+            line.split(|c: char| !c.is_ascii_hexdigit())
+                .find(|tok| tok.len() == 64)
+                .map(|tok| tok.to_lowercase())
+        })
+}
+
+/// 查找安装包对应的校验文件 (`<asset>.sha256` / `SHA256SUMS` / `checksums.txt`)
+fn find_checksum_asset<'a>(assets: &'a [GitHubAsset], asset_name: &str) -> Option<&'a GitHubAsset> {
+    let per_file = format!("{}.sha256", asset_name);
+    assets.iter().find(|a| a.name == per_file).or_else(|| {
+        assets.iter().find(|a| {
+            let name = a.name.to_lowercase();
+            name == "sha256sums" || name == "sha256sums.txt" || name == "checksums.txt"
+        })
+    })
+}
+
+fn build_client() -> Result<reqwest::Client, UpdateError> {
+    reqwest::Client::builder()
+        .user_agent("Antigravity-Manager")
+        .connect_timeout(std::time::Duration::from_secs(15))
+        .build()
+        .map_err(|e| UpdateError::Network(e.to_string()))
+}
+
+async fn resolve_expected_sha256(
+    client: &reqwest::Client,
+    release: &GitHubRelease,
+    asset: &GitHubAsset,
+) -> Result<String, UpdateError> {
+    if let Some(checksum_asset) = find_checksum_asset(&release.assets, &asset.name) {
+        let text = client
+            .get(&checksum_asset.browser_download_url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| UpdateError::Network(e.to_string()))?
+            .text()
+            .await
+            .map_err(|e| UpdateError::Network(e.to_string()))?;
+        // 单文件校验文件通常只有哈希本身，不含文件名
+        let found = find_sha256(&text, &asset.name).or_else(|| {
+            if checksum_asset.name.ends_with(".sha256") {
+                text.split_whitespace()
+                    .next()
+                    .filter(|tok| tok.len() == 64 && tok.chars().all(|c| c.is_ascii_hexdigit()))
+                    .map(|tok| tok.to_lowercase())
+            } else {
+                None
+            }
+        });
+        if let Some(hash) = found {
+            return Ok(hash);
+        }
+    }
+
+    find_sha256(&release.body, &asset.name).ok_or_else(|| UpdateError::ChecksumMissing(asset.name.clone()))
+}
+
+fn updates_dir() -> Result<PathBuf, UpdateError> {
+    let dir = crate::modules::account::get_data_dir()
+        .map_err(UpdateError::Io)?
+        .join(UPDATES_DIR);
+    std::fs::create_dir_all(&dir).map_err(|e| UpdateError::Io(e.to_string()))?;
+    Ok(dir)
+}
+
+/// 下载最新版本安装包并校验，完成后记录在内存中等待安装
+pub async fn download_update(app: tauri::AppHandle) -> Result<DownloadedUpdate, UpdateError> {
+    let (slot, mut cancel_rx) = DownloadSlot::acquire()?;
+    let result = run_download(&app, &mut cancel_rx).await;
+    drop(slot);
+
+    match &result {
+        Ok(update) => {
+            logger::log_info(&format!("更新包下载并校验完成: {}", update.path.display()));
+            *DOWNLOADED.lock().unwrap() = Some(update.clone());
+            let _ = app.emit("update://download-finished", update);
+        }
+        Err(e) => {
+            logger::log_error(&format!("更新包下载失败: {}", e));
+            let _ = app.emit("update://download-failed", e);
+        }
+    }
+    result
+}
+
+async fn run_download(
+    app: &tauri::AppHandle,
+    cancel: &mut watch::Receiver<bool>,
+) -> Result<DownloadedUpdate, UpdateError> {
+    let release = cancellable(cancel, async {
+        update_checker::fetch_latest_release().await.map_err(UpdateError::Network)
+    })
+    .await?;
+
+    let version = release.tag_name.trim_start_matches('v').to_string();
+    if !update_checker::compare_versions(&version, CURRENT_VERSION) {
+        return Err(UpdateError::NoUpdate);
+    }
+
+    let platform = format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH);
+    let asset = select_asset(&release.assets, std::env::consts::OS, std::env::consts::ARCH)
+        .ok_or(UpdateError::NoAssetForPlatform(platform))?
+        .clone();
+
+    let client = build_client()?;
+    let expected_sha256 = cancellable(cancel, resolve_expected_sha256(&client, &release, &asset)).await?;
+
+    let dir = updates_dir()?;
+    let expected_size = if asset.size > 0 { Some(asset.size) } else { None };
+
+    logger::log_info(&format!("开始下载更新包: {} ({})", asset.name, version));
+
+    let path = download_asset_to(
+        &asset.browser_download_url,
+        &dir,
+        &asset.name,
+        expected_size,
+        Some(&expected_sha256),
+        cancel,
+        |progress| {
+            let _ = app.emit(
+                "update://download-progress",
+                DownloadProgress { version: version.clone(), downloaded: progress.downloaded, total: progress.total },
+            );
+        },
+    )
+    .await?;
+
+    Ok(DownloadedUpdate {
+        version,
+        asset_name: asset.name,
+        path,
+        sha256: expected_sha256,
+    })
+}
+
+/// 从下载地址推导保存的文件名 (取路径最后一段)
+fn asset_file_name(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.path_segments().and_then(|s| s.last().map(|n| n.to_string())))
+        .filter(|n| !n.is_empty() && n != "." && n != "..")
+        .unwrap_or_else(|| "update.bin".to_string())
+}
+
+fn sha256_of_file(path: &Path) -> Result<String, UpdateError> {
+    use std::io::Read;
+    let mut file = std::fs::File::open(path).map_err(|e| UpdateError::Io(e.to_string()))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).map_err(|e| UpdateError::Io(e.to_string()))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// 下载到 `.part` 文件，已有部分时通过 Range 续传；返回总大小 (已知时)
+async fn fetch_into_part<P: FnMut(AssetProgress)>(
+    client: &reqwest::Client,
+    url: &str,
+    part_path: &Path,
+    file_name: &str,
+    expected_size: Option<u64>,
+    cancel: &mut watch::Receiver<bool>,
+    on_progress: &mut P,
+) -> Result<Option<u64>, UpdateError> {
+    let offset = tokio::fs::metadata(part_path).await.map(|m| m.len()).unwrap_or(0);
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+    }
+    let response = cancellable(cancel, async {
+        request.send().await.map_err(|e| UpdateError::Network(e.to_string()))
+    })
+    .await?;
+
+    if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // 残留的 .part 与服务端文件不符，下次从头下载
+        let _ = tokio::fs::remove_file(part_path).await;
+        return Err(UpdateError::Network("range not satisfiable, restarting download".to_string()));
+    }
+    let response = response.error_for_status().map_err(|e| UpdateError::Network(e.to_string()))?;
+
+    // 服务端不支持 Range 时返回 200 全量内容，需从头写
+    let start = if offset > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT { offset } else { 0 };
+    let total = expected_size.or_else(|| response.content_length().map(|len| len + start));
+    let mut file = if start > 0 {
+        tokio::fs::OpenOptions::new().append(true).open(part_path).await
+    } else {
+        tokio::fs::File::create(part_path).await
+    }
+    .map_err(|e| UpdateError::Io(e.to_string()))?;
+
+    let mut downloaded = start;
+    let mut last_emitted = start;
+    let mut stream = response.bytes_stream();
+    loop {
+        let next = cancellable(cancel, async { Ok(stream.next().await) }).await?;
+        let Some(chunk) = next else { break };
+        let chunk = chunk.map_err(|e| UpdateError::Network(e.to_string()))?;
+        file.write_all(&chunk).await.map_err(|e| UpdateError::Io(e.to_string()))?;
+        downloaded += chunk.len() as u64;
+
+        if downloaded - last_emitted >= PROGRESS_EMIT_STEP {
+            last_emitted = downloaded;
+            on_progress(AssetProgress { file_name: file_name.to_string(), downloaded, total });
+        }
+    }
+    file.flush().await.map_err(|e| UpdateError::Io(e.to_string()))?;
+    on_progress(AssetProgress { file_name: file_name.to_string(), downloaded, total });
+    Ok(total)
+}
+
+/// 下载安装包到 `dest_dir/file_name` 并校验大小 / sha256 (可选)，返回文件路径
+///
+/// download_update 与 download_update_from_url 共用：网络中断时自动重试并从已下载部分续传，
+/// 重定向由 reqwest 跟随，取消信号随时中止
+async fn download_asset_to<P: FnMut(AssetProgress)>(
+    url: &str,
+    dest_dir: &Path,
+    file_name: &str,
+    expected_size: Option<u64>,
+    expected_sha256: Option<&str>,
+    cancel: &mut watch::Receiver<bool>,
+    mut on_progress: P,
+) -> Result<PathBuf, UpdateError> {
+    tokio::fs::create_dir_all(dest_dir)
+        .await
+        .map_err(|e| UpdateError::Io(e.to_string()))?;
+    let final_path = dest_dir.join(file_name);
+    let part_path = dest_dir.join(format!("{}.part", file_name));
+    let client = build_client()?;
+
+    let mut attempt = 1;
+    let total = loop {
+        match fetch_into_part(&client, url, &part_path, file_name, expected_size, cancel, &mut on_progress).await {
+            Ok(total) => break total,
+            Err(UpdateError::Network(e)) if attempt < ASSET_DOWNLOAD_ATTEMPTS => {
+                logger::log_warn(&format!("更新包下载中断 (第 {} 次)，稍后续传: {}", attempt, e));
+                attempt += 1;
+                cancellable(cancel, async {
+                    tokio::time::sleep(ASSET_RETRY_DELAY).await;
+                    Ok(())
+                })
+                .await?;
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    let received = tokio::fs::metadata(&part_path)
+        .await
+        .map(|m| m.len())
+        .map_err(|e| UpdateError::Io(e.to_string()))?;
+    if let Some(expected) = total {
+        if received != expected {
+            let _ = tokio::fs::remove_file(&part_path).await;
+            return Err(UpdateError::PartialDownload { received, expected });
+        }
+    }
+
+    if let Some(expected) = expected_sha256.map(|h| h.trim().to_lowercase()).filter(|h| !h.is_empty()) {
+        let actual = sha256_of_file(&part_path)?;
+        if actual != expected {
+            let _ = tokio::fs::remove_file(&part_path).await;
+            return Err(UpdateError::ChecksumMismatch { expected, actual });
+        }
+    }
+
+    tokio::fs::rename(&part_path, &final_path)
+        .await
+        .map_err(|e| UpdateError::Io(e.to_string()))?;
+    Ok(final_path)
+}
+
+/// 下载 `check_for_updates` 返回的安装包地址到指定目录，交由用户自行安装
+pub async fn download_update_from_url(
+    app: tauri::AppHandle,
+    url: &str,
+    dest_dir: &Path,
+    expected_size: Option<u64>,
+    expected_sha256: Option<&str>,
+) -> Result<PathBuf, UpdateError> {
+    let (slot, mut cancel_rx) = DownloadSlot::acquire()?;
+
+    logger::log_info(&format!("开始下载更新包: {}", url));
+    let file_name = asset_file_name(url);
+    let result = download_asset_to(
+        url,
+        dest_dir,
+        &file_name,
+        expected_size,
+        expected_sha256,
+        &mut cancel_rx,
+        |progress| {
+            let _ = app.emit("update://progress", progress);
+        },
+    )
+    .await;
+    drop(slot);
+
+    match &result {
+        Ok(path) => logger::log_info(&format!("更新包已下载: {}", path.display())),
+        Err(e) => logger::log_error(&format!("更新包下载失败: {}", e)),
+    }
+    result
+}
+
+/// 取消正在进行的下载，没有下载任务时返回 false
+pub fn cancel_download() -> bool {
+    match CANCEL_TX.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        Some(tx) => {
+            let _ = tx.send(true);
+            logger::log_info("已请求取消更新下载");
+            true
+        }
+        None => false,
+    }
+}
+
+/// 获取已下载待安装的更新
+pub fn downloaded_update() -> Option<DownloadedUpdate> {
+    DOWNLOADED.lock().unwrap().clone()
+}
+
+/// 启动安装程序并退出应用
+///
+/// 调用前需确保反代服务已停止、待落盘状态已写入
+pub fn install_update(app: &tauri::AppHandle) -> Result<(), UpdateError> {
+    let update = downloaded_update().ok_or(UpdateError::NotDownloaded)?;
+    if !update.path.exists() {
+        return Err(UpdateError::NotDownloaded);
+    }
+
+    logger::log_info(&format!("开始安装更新: {} ({})", update.version, update.path.display()));
+    launch_installer(&update.path)?;
+
+    // 退出应用，RunEvent::Exit 中会再次刷新待落盘的 token
+    app.exit(0);
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn launch_installer(path: &Path) -> Result<(), UpdateError> {
+    let is_msi = path
+        .extension()
+        .map(|e| e.eq_ignore_ascii_case("msi"))
+        .unwrap_or(false);
+    let result = if is_msi {
+        std::process::Command::new("msiexec").arg("/i").arg(path).spawn()
+    } else {
+        std::process::Command::new(path).spawn()
+    };
+    result.map(|_| ()).map_err(|e| UpdateError::Install(e.to_string()))
+}
+
+#[cfg(target_os = "macos")]
+fn launch_installer(path: &Path) -> Result<(), UpdateError> {
+    std::process::Command::new("open")
+        .arg(path)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| UpdateError::Install(e.to_string()))
+}
+
+#[cfg(target_os = "linux")]
+fn launch_installer(path: &Path) -> Result<(), UpdateError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    // 仅支持以 AppImage 方式运行时原地替换
+    let target = std::env::var_os("APPIMAGE")
+        .map(PathBuf::from)
+        .ok_or_else(|| UpdateError::Install("Not running from an AppImage, please update via your package manager".to_string()))?;
+
+    let staged = target.with_extension("new");
+    std::fs::copy(path, &staged).map_err(|e| UpdateError::Io(e.to_string()))?;
+    std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755))
+        .map_err(|e| UpdateError::Io(e.to_string()))?;
+    std::fs::rename(&staged, &target).map_err(|e| UpdateError::Io(e.to_string()))?;
+
+    std::process::Command::new(&target)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| UpdateError::Install(e.to_string()))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+fn launch_installer(_path: &Path) -> Result<(), UpdateError> {
+    Err(UpdateError::NoAssetForPlatform(std::env::consts::OS.to_string()))
+}
+
+/// 若用户开启了自动下载，在检测到新版本后后台下载
+pub fn maybe_auto_download(app: &tauri::AppHandle, info: &update_checker::UpdateInfo) {
+    if !info.has_update {
+        return;
+    }
+    let auto_download = update_checker::load_update_settings()
+        .map(|s| s.auto_download)
+        .unwrap_or(false);
+    if !auto_download {
+        return;
+    }
+    if downloaded_update().map_or(false, |d| d.version == info.latest_version) {
+        return;
+    }
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let _ = download_update(app).await;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_download_slot_released_when_task_is_dropped() {
+        // 下载任务被中途丢弃 (如前端关闭、任务被取消) 后槽位仍会释放
+        let task = tokio::spawn(async {
+            let (_slot, mut cancel_rx) = DownloadSlot::acquire().unwrap();
+            let _ = cancel_rx.wait_for(|c| *c).await;
+            std::future::pending::<()>().await;
+        });
+        while CANCEL_TX.lock().unwrap().is_none() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(DownloadSlot::acquire().err(), Some(UpdateError::AlreadyDownloading));
+
+        task.abort();
+        let _ = task.await;
+        assert!(!cancel_download());
+        let (slot, _) = DownloadSlot::acquire().unwrap();
+        drop(slot);
+        assert!(CANCEL_TX.lock().unwrap().is_none());
+    }
+
+    fn asset(name: &str) -> GitHubAsset {
+        GitHubAsset {
+            name: name.to_string(),
+            browser_download_url: format!("https://example.com/{}", name),
+            size: 0,
+        }
+    }
+
+    #[test]
+    fn test_select_asset_per_platform() {
+        let assets = vec![
+            asset("Antigravity.Tools_3.4.0_x64-setup.exe"),
+            asset("Antigravity.Tools_3.4.0_x64_en-US.msi"),
+            asset("Antigravity.Tools_3.4.0_aarch64.dmg"),
+            asset("Antigravity.Tools_3.4.0_universal.dmg"),
+            asset("Antigravity.Tools_3.4.0_amd64.AppImage"),
+            asset("SHA256SUMS"),
+        ];
+
+        assert!(select_asset(&assets, "windows", "x86_64").unwrap().name.contains("x64"));
+        assert_eq!(
+            select_asset(&assets, "macos", "aarch64").unwrap().name,
+            "Antigravity.Tools_3.4.0_aarch64.dmg"
+        );
+        assert_eq!(
+            select_asset(&assets, "macos", "x86_64").unwrap().name,
+            "Antigravity.Tools_3.4.0_universal.dmg"
+        );
+        assert_eq!(
+            select_asset(&assets, "linux", "x86_64").unwrap().name,
+            "Antigravity.Tools_3.4.0_amd64.AppImage"
+        );
+        assert!(select_asset(&assets, "linux", "aarch64").is_none());
+    }
+
+    #[test]
+    fn test_find_sha256() {
+        let hash = "a".repeat(64);
+        let sums = format!("{}  Antigravity.Tools_3.4.0_amd64.AppImage\n{}  other.dmg", hash, "b".repeat(64));
+        assert_eq!(find_sha256(&sums, "Antigravity.Tools_3.4.0_amd64.AppImage"), Some(hash.clone()));
+
+        let body = format!("- `Antigravity.Tools_3.4.0_amd64.AppImage`: sha256 `{}`", hash.to_uppercase());
+        assert_eq!(find_sha256(&body, "Antigravity.Tools_3.4.0_amd64.AppImage"), Some(hash));
+
+        assert_eq!(find_sha256(&sums, "missing.msi"), None);
+    }
+
+    /// 按顺序返回预设原始响应的本地服务，记录每个请求的原始报文
+    async fn scripted_server(responses: Vec<Vec<u8>>) -> (String, std::sync::Arc<Mutex<Vec<String>>>) {
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let requests_clone = requests.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let Ok((mut socket, _)) = listener.accept().await else { return };
+                let mut buf = [0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                requests_clone.lock().unwrap().push(String::from_utf8_lossy(&buf[..n]).to_string());
+                let _ = socket.write_all(&response).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+        (base, requests)
+    }
+
+    fn raw_response(head: &str, body: &[u8]) -> Vec<u8> {
+        let mut out = format!("{}\r\nConnection: close\r\n\r\n", head).into_bytes();
+        out.extend_from_slice(body);
+        out
+    }
+
+    #[tokio::test]
+    async fn test_download_asset_follows_redirect_and_resumes() {
+        let payload: Vec<u8> = (0..(2 * PROGRESS_EMIT_STEP as usize + 1000)).map(|i| (i % 251) as u8).collect();
+        let split = 100_000;
+        let sha256 = format!("{:x}", Sha256::digest(&payload));
+        let (base, requests) = scripted_server(vec![
+            raw_response("HTTP/1.1 302 Found\r\nLocation: /assets/app.AppImage\r\nContent-Length: 0", b""),
+            // 声明完整长度但中途断开，触发续传
+            raw_response(&format!("HTTP/1.1 200 OK\r\nContent-Length: {}", payload.len()), &payload[..split]),
+            raw_response(
+                &format!(
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}",
+                    split,
+                    payload.len() - 1,
+                    payload.len(),
+                    payload.len() - split
+                ),
+                &payload[split..],
+            ),
+        ])
+        .await;
+
+        let dir = std::env::temp_dir().join(format!("ag_update_download_{}", uuid::Uuid::new_v4()));
+        let (_tx, mut cancel) = watch::channel(false);
+        let mut events = Vec::new();
+        let path = download_asset_to(
+            &format!("{}/releases/download/app.AppImage", base),
+            &dir,
+            "app.AppImage",
+            Some(payload.len() as u64),
+            Some(&sha256),
+            &mut cancel,
+            |p| events.push(p),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(path, dir.join("app.AppImage"));
+        assert_eq!(std::fs::read(&path).unwrap(), payload);
+        assert!(!dir.join("app.AppImage.part").exists());
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].starts_with("GET /assets/app.AppImage"));
+        assert!(requests[2].to_lowercase().contains(&format!("range: bytes={}-", split)));
+
+        let last = events.last().unwrap();
+        assert_eq!(last.file_name, "app.AppImage");
+        assert_eq!(last.downloaded, payload.len() as u64);
+        assert_eq!(last.total, Some(payload.len() as u64));
+        assert!(events.windows(2).all(|w| w[0].downloaded <= w[1].downloaded));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_download_asset_rejects_wrong_checksum() {
+        let (base, _) = scripted_server(vec![raw_response("HTTP/1.1 200 OK\r\nContent-Length: 5", b"hello")]).await;
+        let dir = std::env::temp_dir().join(format!("ag_update_checksum_{}", uuid::Uuid::new_v4()));
+        let (_tx, mut cancel) = watch::channel(false);
+
+        let err = download_asset_to(&format!("{}/a.msi", base), &dir, "a.msi", None, Some(&"0".repeat(64)), &mut cancel, |_| {})
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), "checksum_mismatch");
+        assert!(!dir.join("a.msi").exists());
+        assert!(!dir.join("a.msi.part").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_error_kinds_are_distinct() {
+        let json = serde_json::to_value(UpdateError::ChecksumMismatch {
+            expected: "a".to_string(),
+            actual: "b".to_string(),
+        })
+        .unwrap();
+        assert_eq!(json["kind"], "checksum_mismatch");
+        assert_eq!(UpdateError::PartialDownload { received: 1, expected: 2 }.kind(), "partial_download");
+        assert_eq!(UpdateError::NoAssetForPlatform("x".to_string()).kind(), "no_asset_for_platform");
+    }
+}
@@ -1,6 +1,9 @@
 use base64::{engine::general_purpose, Engine as _};
 use std::path::Path;
 
+pub mod segment;   // 长音频分段与转录拼接
+pub mod spool;     // 上传暂存 (超过阈值写入临时文件)
+
 pub struct AudioProcessor;
 
 impl AudioProcessor {
@@ -22,15 +25,49 @@ impl AudioProcessor {
         }
     }
 
-    /// 将音频数据编码为 Base64
-    pub fn encode_to_base64(audio_data: &[u8]) -> String {
-        general_purpose::STANDARD.encode(audio_data)
+    /// 判断文件是否超过大小限制
+    pub fn exceeds_size_limit(size_bytes: u64, max_bytes: u64) -> bool {
+        size_bytes > max_bytes
     }
+}
 
-    /// 判断文件是否超过大小限制
-    pub fn exceeds_size_limit(size_bytes: usize) -> bool {
-        const MAX_SIZE: usize = 15 * 1024 * 1024; // 15MB
-        size_bytes > MAX_SIZE
+/// 分块 Base64 编码器：逐块追加原始数据，不需要一次持有完整的原始音频
+#[derive(Default)]
+pub struct StreamingBase64 {
+    /// 不足 3 字节、需与下一块拼接的尾部
+    pending: Vec<u8>,
+    out: String,
+}
+
+impl StreamingBase64 {
+    pub fn with_capacity(raw_len: usize) -> Self {
+        Self {
+            pending: Vec::with_capacity(3),
+            out: String::with_capacity(raw_len.div_ceil(3) * 4),
+        }
+    }
+
+    pub fn push(&mut self, mut data: &[u8]) {
+        if !self.pending.is_empty() {
+            let n = (3 - self.pending.len()).min(data.len());
+            self.pending.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.pending.len() < 3 {
+                return;
+            }
+            general_purpose::STANDARD.encode_string(&self.pending, &mut self.out);
+            self.pending.clear();
+        }
+        let whole = data.len() / 3 * 3;
+        general_purpose::STANDARD.encode_string(&data[..whole], &mut self.out);
+        self.pending.extend_from_slice(&data[whole..]);
+    }
+
+    pub fn finish(mut self) -> String {
+        if !self.pending.is_empty() {
+            general_purpose::STANDARD.encode_string(&self.pending, &mut self.out);
+        }
+        self.out
     }
 }
 
@@ -53,16 +90,25 @@ mod tests {
 
     #[test]
     fn test_exceeds_size_limit() {
-        assert!(!AudioProcessor::exceeds_size_limit(10 * 1024 * 1024)); // 10MB
-        assert!(AudioProcessor::exceeds_size_limit(20 * 1024 * 1024)); // 20MB
-        assert!(AudioProcessor::exceeds_size_limit(15 * 1024 * 1024 + 1)); // 刚好超过
-        assert!(!AudioProcessor::exceeds_size_limit(15 * 1024 * 1024)); // 刚好等于限制
+        const MAX: u64 = 15 * 1024 * 1024;
+        assert!(!AudioProcessor::exceeds_size_limit(10 * 1024 * 1024, MAX)); // 10MB
+        assert!(AudioProcessor::exceeds_size_limit(20 * 1024 * 1024, MAX)); // 20MB
+        assert!(AudioProcessor::exceeds_size_limit(MAX + 1, MAX)); // 刚好超过
+        assert!(!AudioProcessor::exceeds_size_limit(MAX, MAX)); // 刚好等于限制
     }
 
     #[test]
     fn test_base64_encoding() {
-        let data = b"test audio data";
-        let encoded = AudioProcessor::encode_to_base64(data);
-        assert!(!encoded.is_empty());
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let expected = general_purpose::STANDARD.encode(&data);
+
+        // 任意分块方式的结果都与一次性编码一致
+        for chunk_size in [1, 2, 3, 4, 7, 64, 999, 1000] {
+            let mut encoder = StreamingBase64::with_capacity(data.len());
+            for chunk in data.chunks(chunk_size) {
+                encoder.push(chunk);
+            }
+            assert_eq!(encoder.finish(), expected, "chunk_size={}", chunk_size);
+        }
     }
 }
@@ -0,0 +1,494 @@
+// 长音频分段
+// 超过单次请求大小的音频按字节区间切成相互重叠的片段依次转录，再拼接结果。
+// 仅 WAV (PCM，按采样块对齐并为每段重写文件头) 与 MP3 (对齐到帧头) 可以直接按字节切分；
+// m4a / ogg / flac 等容器格式无法切分，超过单段大小时由调用方拒绝。
+
+use std::ops::Range;
+
+use super::spool::{AudioSpool, SpoolError};
+
+/// 探测文件头时读取的字节数
+const PROBE_WINDOW: usize = 64 * 1024;
+
+/// MP3 片段起点向后查找帧头的范围
+const FRAME_SEARCH_WINDOW: usize = 8 * 1024;
+
+/// 拼接时比较的最大重叠词数
+const MAX_OVERLAP_WORDS: usize = 40;
+
+/// 至少连续重合这么多个词才认定为重叠，避免误删
+const MIN_OVERLAP_WORDS: usize = 2;
+
+/// WAVE_FORMAT_EXTENSIBLE 格式码
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// 标准子格式 GUID ({XXXXXXXX-0000-0010-8000-00AA00389B71}) 在格式码之后的字节
+const SUBFORMAT_GUID_TAIL: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+/// WAV 文件的格式与数据区
+#[derive(Debug, Clone, PartialEq)]
+pub struct WavInfo {
+    pub format_tag: u16,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub byte_rate: u32,
+    pub block_align: u16,
+    pub bits_per_sample: u16,
+    pub data_start: u64,
+    pub data_len: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioLayout {
+    Wav(WavInfo),
+    /// byte_rate 由首帧比特率估算 (VBR 文件为近似值)
+    Mp3 { audio_start: u64, byte_rate: u32 },
+    /// 无法按字节切分的格式
+    Opaque,
+}
+
+/// 一个待转录片段：原始字节区间与 (可估算时的) 时间范围
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub range: Range<u64>,
+    /// 需要拼在片段前的数据 (WAV 重写的文件头)
+    pub prefix: Vec<u8>,
+    pub start_secs: Option<f64>,
+    pub end_secs: Option<f64>,
+}
+
+fn u16_le(b: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([b[at], b[at + 1]])
+}
+
+fn u32_le(b: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]])
+}
+
+/// WAVE_FORMAT_EXTENSIBLE 的实际编码 (子格式 GUID 中的格式码)，非标准 GUID 时返回 None
+fn extensible_subformat(header: &[u8], fmt_body: usize, fmt_size: usize) -> Option<u16> {
+    let guid = fmt_body + 24;
+    if fmt_size < 40 || guid + 16 > header.len() || header[guid + 2..guid + 16] != SUBFORMAT_GUID_TAIL {
+        return None;
+    }
+    Some(u16_le(header, guid))
+}
+
+/// 解析 WAV 文件头 (RIFF / fmt / data)，data 长度超出文件时按文件实际长度截断
+///
+/// WAVE_FORMAT_EXTENSIBLE 按子格式 GUID 还原实际编码 (PCM / IEEE float 等)，无法识别时不作为可切分的 WAV
+pub fn parse_wav_header(header: &[u8], total_len: u64) -> Option<WavInfo> {
+    if header.len() < 12 || &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return None;
+    }
+
+    let mut fmt: Option<(u16, u16, u32, u32, u16, u16)> = None;
+    let mut pos = 12;
+    while pos + 8 <= header.len() {
+        let id = &header[pos..pos + 4];
+        let size = u32_le(header, pos + 4) as usize;
+        let body = pos + 8;
+        if id == b"fmt " && body + 16 <= header.len() {
+            let format_tag = match u16_le(header, body) {
+                WAVE_FORMAT_EXTENSIBLE => extensible_subformat(header, body, size)?,
+                tag => tag,
+            };
+            fmt = Some((
+                format_tag,
+                u16_le(header, body + 2),
+                u32_le(header, body + 4),
+                u32_le(header, body + 8),
+                u16_le(header, body + 12),
+                u16_le(header, body + 14),
+            ));
+        } else if id == b"data" {
+            let (format_tag, channels, sample_rate, byte_rate, block_align, bits_per_sample) = fmt?;
+            if byte_rate == 0 || block_align == 0 {
+                return None;
+            }
+            let data_start = body as u64;
+            let data_len = (size as u64).min(total_len.saturating_sub(data_start));
+            return Some(WavInfo {
+                format_tag,
+                channels,
+                sample_rate,
+                byte_rate,
+                block_align,
+                bits_per_sample,
+                data_start,
+                data_len,
+            });
+        }
+        // 块按偶数字节对齐
+        pos = body + size + (size & 1);
+    }
+    None
+}
+
+/// 为一个 WAV 片段生成 44 字节的标准文件头
+pub fn wav_header_for(info: &WavInfo, data_len: u64) -> Vec<u8> {
+    let data_len = data_len.min(u32::MAX as u64 - 36) as u32;
+    // format_tag 已是实际编码 (WAVE_FORMAT_EXTENSIBLE 在解析时按子格式还原)，扩展字段不保留
+    let format_tag = info.format_tag;
+
+    let mut h = Vec::with_capacity(44);
+    h.extend_from_slice(b"RIFF");
+    h.extend_from_slice(&(36 + data_len).to_le_bytes());
+    h.extend_from_slice(b"WAVEfmt ");
+    h.extend_from_slice(&16u32.to_le_bytes());
+    h.extend_from_slice(&format_tag.to_le_bytes());
+    h.extend_from_slice(&info.channels.to_le_bytes());
+    h.extend_from_slice(&info.sample_rate.to_le_bytes());
+    h.extend_from_slice(&info.byte_rate.to_le_bytes());
+    h.extend_from_slice(&info.block_align.to_le_bytes());
+    h.extend_from_slice(&info.bits_per_sample.to_le_bytes());
+    h.extend_from_slice(b"data");
+    h.extend_from_slice(&data_len.to_le_bytes());
+    h
+}
+
+/// ID3v2 标签长度 (无标签时为 0)
+pub fn id3v2_len(header: &[u8]) -> u64 {
+    if header.len() < 10 || &header[0..3] != b"ID3" {
+        return 0;
+    }
+    let size = header[6..10]
+        .iter()
+        .fold(0u64, |acc, b| (acc << 7) | (*b as u64 & 0x7F));
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    10 + size + footer
+}
+
+/// 解析 MPEG 音频帧头，返回比特率 (kbps)
+fn mp3_frame_bitrate(h: &[u8]) -> Option<u32> {
+    if h.len() < 4 || h[0] != 0xFF || h[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+    let version = (h[1] >> 3) & 0x03; // 3 = MPEG1, 2 = MPEG2, 0 = MPEG2.5
+    let layer = (h[1] >> 1) & 0x03; // 1 = Layer III, 2 = Layer II, 3 = Layer I
+    let bitrate_idx = (h[2] >> 4) as usize;
+    let sample_rate_idx = (h[2] >> 2) & 0x03;
+    if version == 1 || layer == 0 || bitrate_idx == 0 || bitrate_idx == 15 || sample_rate_idx == 3 {
+        return None;
+    }
+
+    const V1_L1: [u32; 14] = [32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448];
+    const V1_L2: [u32; 14] = [32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384];
+    const V1_L3: [u32; 14] = [32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+    const V2_L1: [u32; 14] = [32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256];
+    const V2_L23: [u32; 14] = [8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+    let table = match (version, layer) {
+        (3, 3) => &V1_L1,
+        (3, 2) => &V1_L2,
+        (3, 1) => &V1_L3,
+        (_, 3) => &V2_L1,
+        _ => &V2_L23,
+    };
+    Some(table[bitrate_idx - 1])
+}
+
+/// 在缓冲区中查找第一个有效的 MPEG 帧头
+pub fn find_mp3_frame(buf: &[u8]) -> Option<(usize, u32)> {
+    (0..buf.len().saturating_sub(3)).find_map(|i| mp3_frame_bitrate(&buf[i..i + 4]).map(|kbps| (i, kbps)))
+}
+
+/// 探测音频布局 (只读取文件头附近的少量数据)
+pub async fn probe_layout(spool: &mut AudioSpool, mime_type: &str) -> Result<AudioLayout, SpoolError> {
+    let header = spool.read_range(0, PROBE_WINDOW).await?;
+    let layout = match mime_type {
+        "audio/wav" => parse_wav_header(&header, spool.len()).map(AudioLayout::Wav),
+        "audio/mp3" => {
+            let tag_len = id3v2_len(&header);
+            let window = spool.read_range(tag_len, PROBE_WINDOW).await?;
+            find_mp3_frame(&window).map(|(offset, kbps)| AudioLayout::Mp3 {
+                audio_start: tag_len + offset as u64,
+                byte_rate: kbps * 1000 / 8,
+            })
+        }
+        _ => None,
+    };
+    Ok(layout.unwrap_or(AudioLayout::Opaque))
+}
+
+/// 将数据区切成长度不超过 `segment_len`、相邻片段重叠 `overlap` 字节的区间
+///
+/// 片段长度与重叠均按 `align` 向下对齐 (WAV 采样块)，重叠最多为片段长度的一半
+pub fn plan_ranges(data: Range<u64>, segment_len: u64, overlap: u64, align: u64) -> Vec<Range<u64>> {
+    let align = align.max(1);
+    let segment_len = (segment_len / align * align).max(align);
+    let overlap = overlap.min(segment_len / 2) / align * align;
+
+    let mut ranges = Vec::new();
+    let mut start = data.start;
+    loop {
+        let end = (start + segment_len).min(data.end);
+        ranges.push(start..end);
+        if end >= data.end {
+            break;
+        }
+        start = end - overlap;
+    }
+    ranges
+}
+
+fn secs(offset: u64, byte_rate: u32) -> f64 {
+    offset as f64 / byte_rate as f64
+}
+
+/// 按布局规划片段；不超过 `segment_len` 的文件整体作为一个片段
+///
+/// 返回 None 表示文件需要切分但格式不支持
+pub async fn plan_segments(
+    spool: &mut AudioSpool,
+    layout: &AudioLayout,
+    segment_len: u64,
+    overlap_secs: u64,
+) -> Result<Option<Vec<Segment>>, SpoolError> {
+    let total = spool.len();
+    if total <= segment_len {
+        let duration = match layout {
+            AudioLayout::Wav(info) => Some(secs(info.data_len, info.byte_rate)),
+            AudioLayout::Mp3 { audio_start, byte_rate } => Some(secs(total - audio_start, *byte_rate)),
+            AudioLayout::Opaque => None,
+        };
+        return Ok(Some(vec![Segment {
+            range: 0..total,
+            prefix: Vec::new(),
+            start_secs: duration.map(|_| 0.0),
+            end_secs: duration,
+        }]));
+    }
+
+    let segments = match layout {
+        AudioLayout::Wav(info) => {
+            let data = info.data_start..info.data_start + info.data_len;
+            // 为每段的 44 字节文件头预留空间
+            let ranges = plan_ranges(
+                data,
+                segment_len.saturating_sub(44),
+                overlap_secs * info.byte_rate as u64,
+                info.block_align as u64,
+            );
+            ranges
+                .into_iter()
+                .map(|r| Segment {
+                    prefix: wav_header_for(info, r.end - r.start),
+                    start_secs: Some(secs(r.start - info.data_start, info.byte_rate)),
+                    end_secs: Some(secs(r.end - info.data_start, info.byte_rate)),
+                    range: r,
+                })
+                .collect()
+        }
+        AudioLayout::Mp3 { audio_start, byte_rate } => {
+            let ranges = plan_ranges(*audio_start..total, segment_len, overlap_secs * *byte_rate as u64, 1);
+            let mut segments = Vec::with_capacity(ranges.len());
+            for (i, mut r) in ranges.into_iter().enumerate() {
+                // 后续片段的起点对齐到下一个帧头，保证每段都能独立解码
+                if i > 0 {
+                    let window = spool.read_range(r.start, FRAME_SEARCH_WINDOW).await?;
+                    if let Some((offset, _)) = find_mp3_frame(&window) {
+                        r.start = (r.start + offset as u64).min(r.end);
+                    }
+                }
+                segments.push(Segment {
+                    prefix: Vec::new(),
+                    start_secs: Some(secs(r.start - audio_start, *byte_rate)),
+                    end_secs: Some(secs(r.end - audio_start, *byte_rate)),
+                    range: r,
+                });
+            }
+            segments
+        }
+        AudioLayout::Opaque => return Ok(None),
+    };
+    Ok(Some(segments))
+}
+
+/// 归一化单词用于比较重叠 (忽略大小写与标点)
+fn normalize_word(word: &str) -> String {
+    word.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+/// 拼接各片段的转录文本，去掉相邻片段因音频重叠而重复的词
+///
+/// 按空白分词比较，前一段末尾与后一段开头最长的相同词序列视为重叠。
+/// 不以空格分词的语言 (如中文) 找不到重叠时直接拼接。
+pub fn stitch_transcripts(parts: &[String]) -> String {
+    let mut out = String::new();
+    for part in parts {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+        if out.is_empty() {
+            out.push_str(part);
+            continue;
+        }
+
+        let tail: Vec<String> = out.split_whitespace().rev().take(MAX_OVERLAP_WORDS).map(normalize_word).collect();
+        let head: Vec<(usize, &str)> = part
+            .split_whitespace()
+            .take(MAX_OVERLAP_WORDS)
+            .map(|w| (w.as_ptr() as usize - part.as_ptr() as usize + w.len(), w))
+            .collect();
+
+        let overlap = (MIN_OVERLAP_WORDS..=head.len().min(tail.len()))
+            .rev()
+            .find(|&k| {
+                (0..k).all(|i| {
+                    let a = &tail[k - 1 - i];
+                    !a.is_empty() && *a == normalize_word(head[i].1)
+                })
+            })
+            .unwrap_or(0);
+
+        let rest = if overlap > 0 { part[head[overlap - 1].0..].trim_start() } else { part };
+        if !rest.is_empty() {
+            out.push(' ');
+            out.push_str(rest);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav_bytes(data_len: usize) -> Vec<u8> {
+        let info = WavInfo {
+            format_tag: 1,
+            channels: 1,
+            sample_rate: 16_000,
+            byte_rate: 32_000,
+            block_align: 2,
+            bits_per_sample: 16,
+            data_start: 44,
+            data_len: data_len as u64,
+        };
+        let mut bytes = wav_header_for(&info, data_len as u64);
+        bytes.extend((0..data_len).map(|i| (i % 256) as u8));
+        bytes
+    }
+
+    #[test]
+    fn test_parse_wav_header_round_trip() {
+        let bytes = wav_bytes(64_000);
+        let info = parse_wav_header(&bytes, bytes.len() as u64).unwrap();
+        assert_eq!(info.byte_rate, 32_000);
+        assert_eq!(info.data_start, 44);
+        assert_eq!(info.data_len, 64_000);
+        assert_eq!(wav_header_for(&info, 64_000), bytes[..44].to_vec());
+        assert!(parse_wav_header(b"not a wav file", 14).is_none());
+    }
+
+    /// 带 WAVE_FORMAT_EXTENSIBLE fmt 块 (40 字节) 的 WAV 文件头
+    fn extensible_wav_header(subformat: [u8; 16]) -> Vec<u8> {
+        let mut h = Vec::new();
+        h.extend_from_slice(b"RIFF");
+        h.extend_from_slice(&(4 + 48 + 8 + 800u32).to_le_bytes());
+        h.extend_from_slice(b"WAVEfmt ");
+        h.extend_from_slice(&40u32.to_le_bytes());
+        h.extend_from_slice(&0xFFFEu16.to_le_bytes());
+        h.extend_from_slice(&2u16.to_le_bytes());
+        h.extend_from_slice(&48_000u32.to_le_bytes());
+        h.extend_from_slice(&384_000u32.to_le_bytes());
+        h.extend_from_slice(&8u16.to_le_bytes());
+        h.extend_from_slice(&32u16.to_le_bytes());
+        h.extend_from_slice(&22u16.to_le_bytes());
+        h.extend_from_slice(&32u16.to_le_bytes());
+        h.extend_from_slice(&3u32.to_le_bytes());
+        h.extend_from_slice(&subformat);
+        h.extend_from_slice(b"data");
+        h.extend_from_slice(&800u32.to_le_bytes());
+        h
+    }
+
+    #[test]
+    fn test_extensible_wav_keeps_float_subformat() {
+        let guid = |code: u8| {
+            let mut g = [0u8; 16];
+            g[0] = code;
+            g[2..].copy_from_slice(&SUBFORMAT_GUID_TAIL);
+            g
+        };
+
+        // IEEE float 不能被改写成 PCM
+        let header = extensible_wav_header(guid(3));
+        let info = parse_wav_header(&header, header.len() as u64 + 800).unwrap();
+        assert_eq!(info.format_tag, 3);
+        assert_eq!(info.data_start, header.len() as u64);
+        assert_eq!(u16_le(&wav_header_for(&info, 800), 20), 3);
+
+        let header = extensible_wav_header(guid(1));
+        let info = parse_wav_header(&header, header.len() as u64 + 800).unwrap();
+        assert_eq!(u16_le(&wav_header_for(&info, 800), 20), 1);
+
+        // 非标准子格式无法用普通文件头描述，不按 WAV 切分
+        let header = extensible_wav_header([0x5A; 16]);
+        assert!(parse_wav_header(&header, header.len() as u64 + 800).is_none());
+    }
+
+    #[test]
+    fn test_plan_ranges_overlap_and_alignment() {
+        let ranges = plan_ranges(44..10_044, 4_001, 1_001, 2);
+        assert_eq!(ranges, vec![44..4_044, 3_044..7_044, 6_044..10_044]);
+        for r in &ranges {
+            assert_eq!((r.start - 44) % 2, 0);
+        }
+        // 不足一段时只有一个区间
+        assert_eq!(plan_ranges(0..100, 1_000, 10, 1), vec![0..100]);
+    }
+
+    #[test]
+    fn test_mp3_frame_detection() {
+        // ID3v2 标签 (内容 20 字节) + 垃圾数据 + MPEG1 Layer III 128kbps 帧头
+        let mut bytes = b"ID3\x04\x00\x00\x00\x00\x00\x14".to_vec();
+        bytes.extend_from_slice(&[0u8; 20]);
+        assert_eq!(id3v2_len(&bytes), 30);
+        bytes.extend_from_slice(&[0x12, 0x34, 0xFF, 0xFB, 0x90, 0x64]);
+        assert_eq!(find_mp3_frame(&bytes[30..]), Some((2, 128)));
+    }
+
+    #[tokio::test]
+    async fn test_wav_segments_get_their_own_header() {
+        let bytes = wav_bytes(10 * 32_000); // 10 秒
+        let mut spool = AudioSpool::new(u64::MAX);
+        spool.write(&bytes).await.unwrap();
+
+        let layout = probe_layout(&mut spool, "audio/wav").await.unwrap();
+        let segments = plan_segments(&mut spool, &layout, 4 * 32_000 + 44, 1).await.unwrap().unwrap();
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0].start_secs, Some(0.0));
+        assert_eq!(segments[1].start_secs, Some(3.0)); // 与上一段重叠 1 秒
+        assert_eq!(segments[2].end_secs, Some(10.0));
+        for seg in &segments {
+            let info = parse_wav_header(&seg.prefix, u64::MAX).unwrap();
+            assert_eq!(info.data_len, seg.range.end - seg.range.start);
+        }
+
+        // 容器格式无法切分
+        let segments = plan_segments(&mut spool, &AudioLayout::Opaque, 32_000, 1).await.unwrap();
+        assert!(segments.is_none());
+    }
+
+    #[test]
+    fn test_stitch_removes_overlapping_words() {
+        let parts = vec![
+            "So the plan for the next quarter is to ship".to_string(),
+            "quarter is to ship the new dashboard, then".to_string(),
+            "".to_string(),
+            "dashboard, then we review.".to_string(),
+        ];
+        assert_eq!(
+            stitch_transcripts(&parts),
+            "So the plan for the next quarter is to ship the new dashboard, then we review."
+        );
+
+        // 没有重叠时直接拼接
+        let parts = vec!["第一段。".to_string(), "第二段。".to_string()];
+        assert_eq!(stitch_transcripts(&parts), "第一段。 第二段。");
+    }
+}
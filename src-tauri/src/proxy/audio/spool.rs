@@ -0,0 +1,245 @@
+// 音频上传暂存
+// 上传内容按块写入：小文件留在内存，超过阈值后转存到临时文件，
+// 之后按字节区间读取与分块编码。临时文件随 AudioSpool 释放而删除，
+// 请求中途取消 (处理 future 被丢弃) 时同样会清理。
+
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom};
+
+use super::{AudioProcessor, StreamingBase64};
+
+/// 超过该大小后转存到临时文件
+pub const SPILL_THRESHOLD: usize = 1024 * 1024;
+
+/// 临时文件所在目录 (系统临时目录下)
+const SPOOL_DIR: &str = "antigravity_audio";
+
+/// 分块读取大小 (3 的倍数，编码时不产生中间填充)
+const READ_CHUNK: usize = 3 * 64 * 1024;
+
+/// 启动时清理超过该时长的残留临时文件 (进程异常退出时留下)
+const STALE_AFTER: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, PartialEq)]
+pub enum SpoolError {
+    /// 超过大小上限 (字节)
+    TooLarge { limit: u64 },
+    Io(String),
+}
+
+impl std::fmt::Display for SpoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpoolError::TooLarge { limit } => write!(f, "音频文件超过大小上限 ({} MB)", limit / (1024 * 1024)),
+            SpoolError::Io(e) => write!(f, "音频暂存失败: {}", e),
+        }
+    }
+}
+
+fn io_err(e: std::io::Error) -> SpoolError {
+    SpoolError::Io(e.to_string())
+}
+
+fn spool_dir() -> PathBuf {
+    std::env::temp_dir().join(SPOOL_DIR)
+}
+
+/// 临时文件守卫：释放时删除文件
+struct TempFileGuard {
+    path: PathBuf,
+}
+
+impl Drop for TempFileGuard {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("删除音频临时文件失败 {:?}: {}", self.path, e);
+            }
+        }
+    }
+}
+
+enum Storage {
+    Memory(Vec<u8>),
+    // 字段按声明顺序释放：先关闭文件句柄再删除文件 (Windows 下无法删除打开中的文件)
+    File { file: tokio::fs::File, _guard: TempFileGuard },
+}
+
+pub struct AudioSpool {
+    storage: Storage,
+    len: u64,
+    max_len: u64,
+    spill_threshold: usize,
+}
+
+impl AudioSpool {
+    pub fn new(max_len: u64) -> Self {
+        Self::with_threshold(max_len, SPILL_THRESHOLD)
+    }
+
+    pub fn with_threshold(max_len: u64, spill_threshold: usize) -> Self {
+        Self {
+            storage: Storage::Memory(Vec::new()),
+            len: 0,
+            max_len,
+            spill_threshold,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 是否已转存到临时文件
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.storage, Storage::File { .. })
+    }
+
+    /// 追加一块上传数据，超过上限时立即返回 TooLarge
+    pub async fn write(&mut self, chunk: &[u8]) -> Result<(), SpoolError> {
+        let new_len = self.len + chunk.len() as u64;
+        if AudioProcessor::exceeds_size_limit(new_len, self.max_len) {
+            return Err(SpoolError::TooLarge { limit: self.max_len });
+        }
+
+        let spill = matches!(&self.storage, Storage::Memory(buf) if buf.len() + chunk.len() > self.spill_threshold);
+        if spill {
+            let (mut file, guard) = create_spool_file().await?;
+            if let Storage::Memory(buf) = &self.storage {
+                file.write_all(buf).await.map_err(io_err)?;
+            }
+            tracing::debug!("音频上传超过 {} 字节，转存到临时文件 {:?}", self.spill_threshold, guard.path);
+            self.storage = Storage::File { file, _guard: guard };
+        }
+
+        match &mut self.storage {
+            Storage::Memory(buf) => buf.extend_from_slice(chunk),
+            Storage::File { file, .. } => file.write_all(chunk).await.map_err(io_err)?,
+        }
+        self.len = new_len;
+        Ok(())
+    }
+
+    /// 读取 [start, start + len) 区间 (超出末尾的部分截断)
+    pub async fn read_range(&mut self, start: u64, len: usize) -> Result<Vec<u8>, SpoolError> {
+        let start = start.min(self.len);
+        let end = (start + len as u64).min(self.len);
+        match &mut self.storage {
+            Storage::Memory(buf) => Ok(buf[start as usize..end as usize].to_vec()),
+            Storage::File { file, .. } => {
+                file.flush().await.map_err(io_err)?;
+                file.seek(SeekFrom::Start(start)).await.map_err(io_err)?;
+                let mut out = vec![0u8; (end - start) as usize];
+                file.read_exact(&mut out).await.map_err(io_err)?;
+                Ok(out)
+            }
+        }
+    }
+
+    /// 将 `prefix` 与 `range` 区间的数据编码为 Base64，按块读取，不额外持有原始数据副本
+    pub async fn encode_base64(&mut self, prefix: &[u8], range: Range<u64>) -> Result<String, SpoolError> {
+        let end = range.end.min(self.len);
+        let mut encoder = StreamingBase64::with_capacity(prefix.len() + end.saturating_sub(range.start) as usize);
+        encoder.push(prefix);
+
+        let mut pos = range.start;
+        while pos < end {
+            let chunk = self.read_range(pos, READ_CHUNK.min((end - pos) as usize)).await?;
+            if chunk.is_empty() {
+                break;
+            }
+            encoder.push(&chunk);
+            pos += chunk.len() as u64;
+        }
+        Ok(encoder.finish())
+    }
+}
+
+async fn create_spool_file() -> Result<(tokio::fs::File, TempFileGuard), SpoolError> {
+    let dir = spool_dir();
+    tokio::fs::create_dir_all(&dir).await.map_err(io_err)?;
+    let path = dir.join(format!("{}.part", uuid::Uuid::new_v4()));
+    let file = tokio::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .await
+        .map_err(io_err)?;
+    Ok((file, TempFileGuard { path }))
+}
+
+/// 清理进程异常退出时残留的临时文件
+pub fn cleanup_stale_files() -> usize {
+    let Ok(entries) = std::fs::read_dir(spool_dir()) else {
+        return 0;
+    };
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let stale = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.elapsed().ok())
+            .map(|age| age > STALE_AFTER)
+            .unwrap_or(false);
+        if stale && std::fs::remove_file(entry.path()).is_ok() {
+            removed += 1;
+        }
+    }
+    if removed > 0 {
+        tracing::info!("清理了 {} 个残留的音频临时文件", removed);
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose, Engine as _};
+
+    fn spool_file_path(spool: &AudioSpool) -> Option<PathBuf> {
+        match &spool.storage {
+            Storage::File { _guard, .. } => Some(_guard.path.clone()),
+            Storage::Memory(_) => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spills_to_file_and_reads_back() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 253) as u8).collect();
+        let mut spool = AudioSpool::with_threshold(1_000_000, 4096);
+        for chunk in data.chunks(1500) {
+            spool.write(chunk).await.unwrap();
+        }
+        assert!(spool.is_spilled());
+        assert_eq!(spool.len(), data.len() as u64);
+
+        assert_eq!(spool.read_range(5000, 100).await.unwrap(), data[5000..5100].to_vec());
+        let encoded = spool.encode_base64(b"RIFF", 2000..9000).await.unwrap();
+        let mut expected = b"RIFF".to_vec();
+        expected.extend_from_slice(&data[2000..9000]);
+        assert_eq!(encoded, general_purpose::STANDARD.encode(&expected));
+    }
+
+    #[tokio::test]
+    async fn test_size_limit_and_cleanup_on_drop() {
+        let mut spool = AudioSpool::with_threshold(8192, 1024);
+        spool.write(&[0u8; 4096]).await.unwrap();
+        let path = spool_file_path(&spool).expect("spilled");
+        assert!(path.exists());
+
+        let err = spool.write(&[0u8; 5000]).await.unwrap_err();
+        assert_eq!(err, SpoolError::TooLarge { limit: 8192 });
+
+        // 请求中途取消时 spool 被丢弃，临时文件随之删除
+        drop(spool);
+        assert!(!path.exists());
+    }
+}
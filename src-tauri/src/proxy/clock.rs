@@ -0,0 +1,63 @@
+// 时钟抽象
+// 限流跟踪与账号选择的缓冲延迟统一从这里取时间，生产环境使用系统时间，
+// 测试中注入 FakeClock 手动推进，覆盖锁定到期、缓冲延迟等时序相关逻辑。
+
+use futures::future::BoxFuture;
+use std::time::{Duration, SystemTime};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    /// 等待指定时长 (FakeClock 直接推进时间并立即返回)
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// 系统时钟 (默认)
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// 手动推进的时钟 (测试用)
+#[cfg(test)]
+#[derive(Debug)]
+pub struct FakeClock {
+    now: std::sync::Mutex<SystemTime>,
+}
+
+#[cfg(test)]
+impl FakeClock {
+    pub fn new(start: SystemTime) -> Self {
+        Self { now: std::sync::Mutex::new(start) }
+    }
+
+    /// 从 Unix 秒构建，便于与 ISO 时间字符串对照
+    pub fn at_unix(secs: u64) -> Self {
+        Self::new(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap_or_else(|e| e.into_inner());
+        *now += duration;
+    }
+}
+
+#[cfg(test)]
+impl Clock for FakeClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self.advance(duration);
+        Box::pin(std::future::ready(()))
+    }
+}
@@ -0,0 +1,60 @@
+// 账号标识 - 控制日志与响应头中账号的展示方式 (邮箱 / 标签 / 哈希)
+
+use crate::proxy::config::AccountIdentifierMode;
+use sha2::{Digest, Sha256};
+
+/// 展示完整邮箱时使用的响应头
+pub const ACCOUNT_EMAIL_HEADER: &str = "X-Account-Email";
+/// 展示标签或哈希时使用的通用响应头
+pub const ACCOUNT_ID_HEADER: &str = "X-Account-Id";
+
+/// 计算邮箱的稳定哈希 (同一邮箱始终得到同一结果，便于排查问题而不泄露邮箱)
+pub fn hash_email(email: &str) -> String {
+    let digest = Sha256::digest(email.trim().to_lowercase().as_bytes());
+    let short: String = digest.iter().take(6).map(|b| format!("{:02x}", b)).collect();
+    format!("acct-{}", short)
+}
+
+/// 按配置的模式生成账号标识
+pub fn account_identifier(mode: AccountIdentifierMode, email: &str, label: Option<&str>) -> String {
+    match mode {
+        AccountIdentifierMode::Email => email.to_string(),
+        AccountIdentifierMode::Label => match label.map(str::trim).filter(|l| !l.is_empty()) {
+            Some(label) => label.to_string(),
+            // 未设置标签时退化为哈希，避免泄露邮箱
+            None => hash_email(email),
+        },
+        AccountIdentifierMode::Hash => hash_email(email),
+    }
+}
+
+/// 按配置的模式选择响应头名称
+pub fn header_name(mode: AccountIdentifierMode) -> &'static str {
+    match mode {
+        AccountIdentifierMode::Email => ACCOUNT_EMAIL_HEADER,
+        AccountIdentifierMode::Label | AccountIdentifierMode::Hash => ACCOUNT_ID_HEADER,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_is_stable_and_hides_email() {
+        let a = hash_email("alice@example.com");
+        assert_eq!(a, hash_email("Alice@Example.com "));
+        assert_ne!(a, hash_email("bob@example.com"));
+        assert!(a.starts_with("acct-"));
+        assert!(!a.contains("alice"));
+    }
+
+    #[test]
+    fn test_label_mode_falls_back_to_hash() {
+        let email = "alice@example.com";
+        assert_eq!(account_identifier(AccountIdentifierMode::Label, email, Some("work")), "work");
+        assert_eq!(account_identifier(AccountIdentifierMode::Label, email, Some("  ")), hash_email(email));
+        assert_eq!(account_identifier(AccountIdentifierMode::Email, email, Some("work")), email);
+        assert_eq!(header_name(AccountIdentifierMode::Hash), ACCOUNT_ID_HEADER);
+    }
+}
@@ -0,0 +1,132 @@
+// 终端用户标识 - 记录客户端传入的 OpenAI `user` / Claude `metadata.user_id`，用于按用户统计
+// 仅用于监控与日志，不会转发到上游
+
+use base64::{engine::general_purpose, Engine as _};
+use once_cell::sync::Lazy;
+use ring::{hmac, rand::SecureRandom, rand::SystemRandom};
+use serde_json::Value;
+use std::path::Path;
+
+/// 用户标识的最大长度 (字符)，超出部分截断，避免异常值撑大日志
+pub const MAX_END_USER_LEN: usize = 128;
+
+/// 本机哈希密钥文件 (位于数据目录，首次使用时随机生成)
+const SALT_FILE: &str = "end_user_salt";
+const SALT_LEN: usize = 32;
+
+/// 哈希结果保留的字节数
+const HASH_BYTES: usize = 16;
+
+/// 本机的 HMAC 密钥：不同安装之间同一用户的哈希不同，也无法用常见用户名字典反推
+static INSTALL_KEY: Lazy<hmac::Key> = Lazy::new(|| {
+    let salt = crate::modules::account::get_data_dir()
+        .map(|dir| load_or_create_salt(&dir))
+        .unwrap_or_else(|_| random_salt());
+    hmac::Key::new(hmac::HMAC_SHA256, &salt)
+});
+
+fn random_salt() -> Vec<u8> {
+    let mut salt = vec![0u8; SALT_LEN];
+    let _ = SystemRandom::new().fill(&mut salt);
+    salt
+}
+
+/// 读取数据目录中的密钥，不存在或损坏时生成新密钥 (只读模式下不写入，仅本次运行有效)
+fn load_or_create_salt(data_dir: &Path) -> Vec<u8> {
+    let path = data_dir.join(SALT_FILE);
+    if let Some(salt) = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| general_purpose::STANDARD.decode(s.trim()).ok())
+        .filter(|s| s.len() == SALT_LEN)
+    {
+        return salt;
+    }
+
+    let salt = random_salt();
+    if crate::modules::instance_lock::ensure_writable().is_ok() {
+        if let Err(e) = std::fs::write(&path, general_purpose::STANDARD.encode(&salt)) {
+            tracing::warn!("Failed to persist end-user hash key: {}", e);
+        }
+    }
+    salt
+}
+
+fn hash_with_key(key: &hmac::Key, label: &str) -> String {
+    let tag = hmac::sign(key, label.as_bytes());
+    let short: String = tag.as_ref().iter().take(HASH_BYTES).map(|b| format!("{:02x}", b)).collect();
+    format!("user-{}", short)
+}
+
+/// 从请求体中提取用户标识 (OpenAI `user` 优先，其次 Claude `metadata.user_id`)
+pub fn end_user_from_body(body: &Value) -> Option<String> {
+    body.get("user")
+        .or_else(|| body.pointer("/metadata/user_id"))
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.chars().take(MAX_END_USER_LEN).collect())
+}
+
+/// 用户标识的稳定哈希 (开启隐私选项时代替原值记录，以本机密钥做 HMAC-SHA256)
+pub fn hash_end_user(label: &str) -> String {
+    hash_with_key(&INSTALL_KEY, label)
+}
+
+/// 按隐私选项生成记录用的标识
+pub fn end_user_label(body: &Value, hashed: bool) -> Option<String> {
+    end_user_from_body(body).map(|label| if hashed { hash_end_user(&label) } else { label })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_extracts_openai_and_claude_fields() {
+        assert_eq!(end_user_from_body(&json!({ "user": " emp-42 " })).as_deref(), Some("emp-42"));
+        assert_eq!(
+            end_user_from_body(&json!({ "metadata": { "user_id": "emp-7" } })).as_deref(),
+            Some("emp-7")
+        );
+        assert_eq!(end_user_from_body(&json!({ "user": "" })), None);
+        assert_eq!(end_user_from_body(&json!({ "user": 42 })), None);
+    }
+
+    #[test]
+    fn test_caps_length_and_hashes() {
+        let long = "x".repeat(10_000);
+        let label = end_user_from_body(&json!({ "user": long })).unwrap();
+        assert_eq!(label.chars().count(), MAX_END_USER_LEN);
+
+        let dir = std::env::temp_dir().join(format!("ag_end_user_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let _guard = crate::modules::account::use_test_data_dir(&dir);
+        let hashed = end_user_label(&json!({ "user": "emp-42" }), true).unwrap();
+        assert_eq!(hashed, hash_end_user("emp-42"));
+        assert!(!hashed.contains("emp-42"));
+        assert_eq!(hashed.len(), "user-".len() + HASH_BYTES * 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_hash_key_is_per_install_and_persisted() {
+        let dir_a = std::env::temp_dir().join(format!("ag_end_user_salt_{}", uuid::Uuid::new_v4()));
+        let dir_b = std::env::temp_dir().join(format!("ag_end_user_salt_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir_a).unwrap();
+        std::fs::create_dir_all(&dir_b).unwrap();
+
+        let salt_a = load_or_create_salt(&dir_a);
+        assert_eq!(load_or_create_salt(&dir_a), salt_a);
+        let salt_b = load_or_create_salt(&dir_b);
+        assert_ne!(salt_a, salt_b);
+
+        // 同一标识在不同安装下的哈希不同
+        let key = |salt: &[u8]| hmac::Key::new(hmac::HMAC_SHA256, salt);
+        assert_eq!(hash_with_key(&key(&salt_a), "emp-42"), hash_with_key(&key(&salt_a), "emp-42"));
+        assert_ne!(hash_with_key(&key(&salt_a), "emp-42"), hash_with_key(&key(&salt_b), "emp-42"));
+
+        let _ = std::fs::remove_dir_all(&dir_a);
+        let _ = std::fs::remove_dir_all(&dir_b);
+    }
+}
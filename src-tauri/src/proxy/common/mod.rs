@@ -3,5 +3,15 @@
 // pub mod error;
 // pub mod rate_limiter;
 pub mod model_mapping;
+pub mod model_capabilities;
 pub mod utils;
+pub mod account_identity;
+pub mod request_body;
+pub mod sampling;
 pub mod json_schema;
+pub mod request_priority;
+pub mod request_deadline;
+pub mod request_type;
+pub mod sse_parser;
+pub mod sse_heartbeat;
+pub mod end_user;
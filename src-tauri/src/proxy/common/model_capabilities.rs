@@ -0,0 +1,142 @@
+// 模型能力元数据：用于校验自定义映射是否会静默丢失 Thinking / 工具调用能力
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::proxy::config::ModelMappingTarget;
+
+/// Thinking 被丢弃时附加的响应头
+pub const THINKING_DROPPED_HEADER: &str = "X-Thinking-Dropped";
+
+/// 上游模型是否支持 Thinking (仅 `-thinking` 后缀模型与 Claude 模型)
+pub fn supports_thinking(model: &str) -> bool {
+    model.contains("-thinking") || model.starts_with("claude-")
+}
+
+/// 上游模型是否支持工具调用 (图像生成模型不支持)
+pub fn supports_tools(model: &str) -> bool {
+    !model.starts_with("gemini-3-pro-image")
+}
+
+/// 上游模型是否支持 candidateCount (Claude 与图像生成模型每次只返回一个候选)
+pub fn supports_candidate_count(model: &str) -> bool {
+    !model.starts_with("claude-") && !model.starts_with("gemini-3-pro-image")
+}
+
+/// 别名隐含的能力：带 thinking 的别名期望推理输出，非图像别名期望可用工具
+fn alias_expects_thinking(alias: &str) -> bool {
+    alias.contains("thinking")
+}
+
+fn alias_expects_tools(alias: &str) -> bool {
+    !alias.contains("image")
+}
+
+/// 映射能力不匹配的类型
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MappingWarningKind {
+    ThinkingUnsupported,
+    ToolsUnsupported,
+}
+
+/// 映射能力校验警告 (不阻止保存)
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct MappingWarning {
+    pub alias: String,
+    pub target: String,
+    pub kind: MappingWarningKind,
+    pub message: String,
+}
+
+/// 检查映射表中每个别名隐含的能力是否被目标模型支持 (加权映射逐个检查权重大于 0 的目标)
+pub fn check_mapping(mapping: &HashMap<String, ModelMappingTarget>) -> Vec<MappingWarning> {
+    let mut warnings = Vec::new();
+    for (alias, target) in mapping {
+        let targets: Vec<&str> = match target {
+            ModelMappingTarget::Single(model) => vec![model.as_str()],
+            ModelMappingTarget::Weighted(targets) => targets
+                .iter()
+                .filter(|t| t.weight > 0.0)
+                .map(|t| t.model.as_str())
+                .collect(),
+        };
+        for model in targets {
+            if alias_expects_thinking(alias) && !supports_thinking(model) {
+                warnings.push(MappingWarning {
+                    alias: alias.clone(),
+                    target: model.to_string(),
+                    kind: MappingWarningKind::ThinkingUnsupported,
+                    message: format!("{} 不支持 Thinking，映射 {} 的推理内容会被丢弃", model, alias),
+                });
+            }
+            if alias_expects_tools(alias) && !supports_tools(model) {
+                warnings.push(MappingWarning {
+                    alias: alias.clone(),
+                    target: model.to_string(),
+                    kind: MappingWarningKind::ToolsUnsupported,
+                    message: format!("{} 不支持工具调用，映射 {} 的工具请求会失败", model, alias),
+                });
+            }
+        }
+    }
+    warnings.sort_by(|a, b| a.alias.cmp(&b.alias).then_with(|| a.target.cmp(&b.target)));
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(entries: &[(&str, &str)]) -> HashMap<String, ModelMappingTarget> {
+        entries
+            .iter()
+            .map(|(alias, target)| (alias.to_string(), ModelMappingTarget::from(*target)))
+            .collect()
+    }
+
+    #[test]
+    fn test_thinking_alias_to_flash_lite_warns() {
+        let warnings = check_mapping(&mapping(&[("claude-sonnet-4-5-thinking", "gemini-2.5-flash-lite")]));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].alias, "claude-sonnet-4-5-thinking");
+        assert_eq!(warnings[0].target, "gemini-2.5-flash-lite");
+        assert_eq!(warnings[0].kind, MappingWarningKind::ThinkingUnsupported);
+    }
+
+    #[test]
+    fn test_clean_mapping_has_no_warnings() {
+        let clean = mapping(&[
+            ("claude-sonnet-4-5-thinking", "claude-sonnet-4-5-thinking"),
+            ("claude-opus-4-5-thinking", "gemini-2.5-flash-thinking"),
+            ("gpt-4o", "gemini-2.5-pro"),
+            ("my-image", "gemini-3-pro-image"),
+        ]);
+        assert!(check_mapping(&clean).is_empty());
+    }
+
+    #[test]
+    fn test_weighted_and_tool_mismatches() {
+        let mut weighted = mapping(&[("gpt-4o", "gemini-3-pro-image")]);
+        weighted.insert(
+            "claude-opus-4-5-thinking".to_string(),
+            serde_json::from_value(serde_json::json!([
+                { "model": "claude-opus-4-5-thinking", "weight": 0.9 },
+                { "model": "gemini-3-flash", "weight": 0.1 },
+                { "model": "gemini-2.5-flash-lite", "weight": 0.0 }
+            ]))
+            .unwrap(),
+        );
+        let kinds: Vec<(String, MappingWarningKind)> = check_mapping(&weighted)
+            .into_iter()
+            .map(|w| (w.target, w.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("gemini-3-flash".to_string(), MappingWarningKind::ThinkingUnsupported),
+                ("gemini-3-pro-image".to_string(), MappingWarningKind::ToolsUnsupported),
+            ]
+        );
+    }
+}
@@ -1,6 +1,11 @@
 // 模型名称映射
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use once_cell::sync::Lazy;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use crate::proxy::config::{ModelMappingTarget, WeightedModelTarget};
 
 static CLAUDE_TO_GEMINI: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
     let mut m = HashMap::new();
@@ -77,7 +82,7 @@ pub fn get_supported_models() -> Vec<String> {
 
 /// 动态获取所有可用模型列表 (包含内置与用户自定义)
 pub async fn get_all_dynamic_models(
-    custom_mapping: &tokio::sync::RwLock<std::collections::HashMap<String, String>>,
+    custom_mapping: &tokio::sync::RwLock<HashMap<String, ModelMappingTarget>>,
 ) -> Vec<String> {
     use std::collections::HashSet;
     let mut model_ids = HashSet::new();
@@ -142,9 +147,25 @@ fn wildcard_match(pattern: &str, text: &str) -> bool {
     }
 }
 
+/// 在自定义映射表中查找规则：精确匹配 > 通配符匹配，返回 (规则键, 目标)
+fn lookup_custom_mapping<'a>(
+    original_model: &str,
+    custom_mapping: &'a HashMap<String, ModelMappingTarget>,
+) -> Option<(&'a str, &'a ModelMappingTarget)> {
+    if let Some((key, target)) = custom_mapping.get_key_value(original_model) {
+        return Some((key.as_str(), target));
+    }
+    custom_mapping
+        .iter()
+        .find(|(pattern, _)| pattern.contains('*') && wildcard_match(pattern, original_model))
+        .map(|(pattern, target)| (pattern.as_str(), target))
+}
+
 /// 核心模型路由解析引擎
 /// 优先级：精确匹配 > 通配符匹配 > 系统默认映射
 /// 
+/// 加权映射在此处不做随机分流，固定取权重最高的目标 (用于 dry-run、能力探测等无请求上下文的场景)
+/// 
 /// # 参数
 /// - `original_model`: 原始模型名称
 /// - `custom_mapping`: 用户自定义映射表
@@ -153,23 +174,50 @@ fn wildcard_match(pattern: &str, text: &str) -> bool {
 /// 映射后的目标模型名称
 pub fn resolve_model_route(
     original_model: &str,
-    custom_mapping: &std::collections::HashMap<String, String>,
+    custom_mapping: &HashMap<String, ModelMappingTarget>,
 ) -> String {
-    // 1. 精确匹配 (最高优先级)
-    if let Some(target) = custom_mapping.get(original_model) {
-        crate::modules::logger::log_info(&format!("[Router] 精确映射: {} -> {}", original_model, target));
-        return target.clone();
-    }
-    
-    // 2. 通配符匹配
-    for (pattern, target) in custom_mapping.iter() {
-        if pattern.contains('*') && wildcard_match(pattern, original_model) {
-            crate::modules::logger::log_info(&format!("[Router] 通配符映射: {} -> {} (规则: {})", original_model, target, pattern));
-            return target.clone();
+    resolve_route(original_model, custom_mapping, None)
+}
+
+/// 针对实际请求的模型路由解析
+/// 
+/// 与 `resolve_model_route` 相同，但命中加权映射时按权重随机选择目标：
+/// - 以 `seed` (通常为 trace_id) 作为随机种子，便于根据日志复现选择结果
+/// - 同一 `session_id` 在会话存活期间固定使用首次选中的目标，避免对话中途切换模型
+/// - 每次选择都会计入分流统计，见 `weighted_route_stats`
+pub fn resolve_model_route_for_request(
+    original_model: &str,
+    custom_mapping: &HashMap<String, ModelMappingTarget>,
+    session_id: Option<&str>,
+    seed: &str,
+) -> String {
+    resolve_route(original_model, custom_mapping, Some((session_id, seed)))
+}
+
+fn resolve_route(
+    original_model: &str,
+    custom_mapping: &HashMap<String, ModelMappingTarget>,
+    request_ctx: Option<(Option<&str>, &str)>,
+) -> String {
+    // 1. 自定义映射 (精确 / 通配符)
+    if let Some((rule, target)) = lookup_custom_mapping(original_model, custom_mapping) {
+        let resolved = match (target, request_ctx) {
+            (ModelMappingTarget::Weighted(targets), Some((session_id, seed))) => {
+                pick_weighted_target(rule, targets, session_id, seed)
+            }
+            _ => None,
         }
+        .unwrap_or_else(|| target.primary().to_string());
+
+        if rule == original_model {
+            crate::modules::logger::log_info(&format!("[Router] 精确映射: {} -> {}", original_model, resolved));
+        } else {
+            crate::modules::logger::log_info(&format!("[Router] 通配符映射: {} -> {} (规则: {})", original_model, resolved, rule));
+        }
+        return resolved;
     }
     
-    // 3. 系统默认映射
+    // 2. 系统默认映射
     let result = map_claude_model_to_gemini(original_model);
     if result != original_model {
         crate::modules::logger::log_info(&format!("[Router] 系统默认映射: {} -> {}", original_model, result));
@@ -177,6 +225,227 @@ pub fn resolve_model_route(
     result
 }
 
+/// 会话粘性选择的空闲过期时间
+const WEIGHTED_STICKY_TTL: Duration = Duration::from_secs(2 * 60 * 60);
+/// 粘性选择表超过该大小时清理过期条目
+const WEIGHTED_STICKY_PRUNE_THRESHOLD: usize = 10_000;
+
+struct StickyChoice {
+    model: String,
+    last_used: Instant,
+}
+
+#[derive(Default)]
+struct WeightedRouteState {
+    /// (session_id, 规则键) -> 已选目标
+    sticky: HashMap<(String, String), StickyChoice>,
+    /// 规则键 -> (目标模型 -> 实际命中次数)
+    realized: HashMap<String, BTreeMap<String, u64>>,
+}
+
+static WEIGHTED_ROUTES: Lazy<Mutex<WeightedRouteState>> =
+    Lazy::new(|| Mutex::new(WeightedRouteState::default()));
+
+/// 由种子计算 [0, 1) 区间内的稳定随机数 (与进程、平台无关，日志中的 trace_id 可复现)
+fn seeded_unit(seed: &str, rule: &str) -> f64 {
+    let mut hasher = Sha256::new();
+    hasher.update(seed.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(rule.as_bytes());
+    let digest = hasher.finalize();
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// 按权重选择目标，权重无效 (总和不为正) 时返回 None
+fn choose_by_weight<'a>(targets: &'a [WeightedModelTarget], unit: f64) -> Option<&'a str> {
+    let total: f64 = targets.iter().filter(|t| t.weight > 0.0).map(|t| t.weight).sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let point = unit * total;
+    let mut acc = 0.0;
+    let mut last = None;
+    for target in targets.iter().filter(|t| t.weight > 0.0) {
+        acc += target.weight;
+        last = Some(target.model.as_str());
+        if point < acc {
+            return last;
+        }
+    }
+    // 浮点误差兜底：落到最后一个有效目标
+    last
+}
+
+fn pick_weighted_target(
+    rule: &str,
+    targets: &[WeightedModelTarget],
+    session_id: Option<&str>,
+    seed: &str,
+) -> Option<String> {
+    let mut state = WEIGHTED_ROUTES.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Instant::now();
+
+    // 1. 会话粘性：沿用仍然有效 (权重 > 0) 的历史选择
+    let sticky_key = session_id.map(|sid| (sid.to_string(), rule.to_string()));
+    let sticky_model = sticky_key
+        .as_ref()
+        .and_then(|key| state.sticky.get_mut(key))
+        .filter(|choice| now.duration_since(choice.last_used) < WEIGHTED_STICKY_TTL)
+        .filter(|choice| targets.iter().any(|t| t.model == choice.model && t.weight > 0.0))
+        .map(|choice| {
+            choice.last_used = now;
+            choice.model.clone()
+        });
+
+    let chosen = match sticky_model {
+        Some(model) => model,
+        None => {
+            let model = choose_by_weight(targets, seeded_unit(seed, rule))?.to_string();
+            tracing::info!(
+                "[Router] 加权映射 {} 选中 {} (seed: {}, session: {})",
+                rule,
+                model,
+                seed,
+                session_id.unwrap_or("-")
+            );
+            if let Some(key) = sticky_key {
+                if state.sticky.len() >= WEIGHTED_STICKY_PRUNE_THRESHOLD {
+                    state
+                        .sticky
+                        .retain(|_, c| now.duration_since(c.last_used) < WEIGHTED_STICKY_TTL);
+                }
+                state.sticky.insert(key, StickyChoice { model: model.clone(), last_used: now });
+            }
+            model
+        }
+    };
+
+    *state
+        .realized
+        .entry(rule.to_string())
+        .or_default()
+        .entry(chosen.clone())
+        .or_insert(0) += 1;
+
+    Some(chosen)
+}
+
+/// 单个目标的配置权重与实际分流
+#[derive(Debug, Clone, Serialize)]
+pub struct WeightedRouteShare {
+    pub model: String,
+    /// 配置的权重 (已从配置中移除的目标为 0)
+    pub weight: f64,
+    /// 配置期望的占比 (0.0 - 1.0)
+    pub expected_ratio: f64,
+    /// 实际命中次数
+    pub count: u64,
+    /// 实际占比 (0.0 - 1.0)
+    pub realized_ratio: f64,
+}
+
+/// 单个加权映射规则的分流统计
+#[derive(Debug, Clone, Serialize)]
+pub struct WeightedRouteSplit {
+    pub alias: String,
+    pub total: u64,
+    pub targets: Vec<WeightedRouteShare>,
+}
+
+/// 汇总当前所有加权映射的配置占比与实际占比，用于验证灰度比例是否生效
+pub fn weighted_route_stats(
+    custom_mapping: &HashMap<String, ModelMappingTarget>,
+) -> Vec<WeightedRouteSplit> {
+    let state = WEIGHTED_ROUTES.lock().unwrap_or_else(|e| e.into_inner());
+    let empty = BTreeMap::new();
+
+    let mut result: Vec<WeightedRouteSplit> = custom_mapping
+        .iter()
+        .filter_map(|(alias, target)| match target {
+            ModelMappingTarget::Weighted(targets) => Some((alias, targets)),
+            ModelMappingTarget::Single(_) => None,
+        })
+        .map(|(alias, targets)| {
+            let counts = state.realized.get(alias).unwrap_or(&empty);
+            let total: u64 = counts.values().sum();
+            let weight_sum: f64 = targets.iter().filter(|t| t.weight > 0.0).map(|t| t.weight).sum();
+
+            let mut shares: Vec<WeightedRouteShare> = targets
+                .iter()
+                .map(|t| (t.model.clone(), t.weight.max(0.0)))
+                .chain(
+                    counts
+                        .keys()
+                        .filter(|m| !targets.iter().any(|t| &t.model == *m))
+                        .map(|m| (m.clone(), 0.0)),
+                )
+                .map(|(model, weight)| {
+                    let count = counts.get(&model).copied().unwrap_or(0);
+                    WeightedRouteShare {
+                        expected_ratio: if weight_sum > 0.0 { weight / weight_sum } else { 0.0 },
+                        realized_ratio: if total > 0 { count as f64 / total as f64 } else { 0.0 },
+                        model,
+                        weight,
+                        count,
+                    }
+                })
+                .collect();
+            shares.sort_by(|a, b| b.weight.total_cmp(&a.weight).then_with(|| a.model.cmp(&b.model)));
+
+            WeightedRouteSplit { alias: alias.clone(), total, targets: shares }
+        })
+        .collect();
+    result.sort_by(|a, b| a.alias.cmp(&b.alias));
+    result
+}
+
+/// 映射表热更新时调用：权重发生变化的规则重新开始统计实际占比 (粘性选择保留，失效目标会在下次请求时重选)
+pub fn reset_changed_route_stats(
+    old_mapping: &HashMap<String, ModelMappingTarget>,
+    new_mapping: &HashMap<String, ModelMappingTarget>,
+) {
+    let mut state = WEIGHTED_ROUTES.lock().unwrap_or_else(|e| e.into_inner());
+    state
+        .realized
+        .retain(|alias, _| old_mapping.get(alias) == new_mapping.get(alias));
+}
+
+/// 将映射表导出为格式化 JSON 模板 (按别名排序，便于分享与比对)
+pub fn export_mapping_template(mapping: &HashMap<String, ModelMappingTarget>) -> Result<String, String> {
+    let sorted: BTreeMap<&String, &ModelMappingTarget> = mapping.iter().collect();
+    serde_json::to_string_pretty(&sorted).map_err(|e| format!("导出模型映射失败: {}", e))
+}
+
+/// 解析并校验映射模板，`merge` 为 true 时保留现有映射中模板未涉及的条目
+pub fn apply_mapping_template(
+    current: &HashMap<String, ModelMappingTarget>,
+    template_json: &str,
+    merge: bool,
+) -> Result<HashMap<String, ModelMappingTarget>, String> {
+    let incoming: HashMap<String, ModelMappingTarget> = serde_json::from_str(template_json)
+        .map_err(|e| format!("模型映射模板格式无效: {}", e))?;
+
+    for (alias, target) in &incoming {
+        if alias.trim().is_empty() {
+            return Err("模型映射的源模型名不能为空".to_string());
+        }
+        if let ModelMappingTarget::Single(model) = target {
+            if model.trim().is_empty() {
+                return Err(format!("模型映射 {} 的目标模型名不能为空", alias));
+            }
+        }
+        target
+            .validate()
+            .map_err(|e| format!("模型映射 {} 无效: {}", alias, e))?;
+    }
+
+    let mut result = if merge { current.clone() } else { HashMap::new() };
+    result.extend(incoming);
+    Ok(result)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -201,4 +470,102 @@ mod tests {
             "claude-sonnet-4-5"
         );
     }
+
+    fn weighted(pairs: &[(&str, f64)]) -> ModelMappingTarget {
+        ModelMappingTarget::Weighted(
+            pairs
+                .iter()
+                .map(|(model, weight)| WeightedModelTarget { model: model.to_string(), weight: *weight })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_single_target_mapping_still_deserializes() {
+        let mapping: HashMap<String, ModelMappingTarget> = serde_json::from_str(
+            r#"{"gpt-4*": "gemini-2.5-pro", "claude-sonnet-4-5": [{"model": "gemini-2.5-pro", "weight": 80}, {"model": "gemini-3-pro", "weight": 20}]}"#,
+        )
+        .unwrap();
+        assert_eq!(resolve_model_route("gpt-4o", &mapping), "gemini-2.5-pro");
+        // 无请求上下文时固定取权重最高的目标
+        assert_eq!(resolve_model_route("claude-sonnet-4-5", &mapping), "gemini-2.5-pro");
+    }
+
+    #[test]
+    fn test_weighted_choice_is_reproducible_by_seed() {
+        let mut mapping = HashMap::new();
+        mapping.insert("ab-repro".to_string(), weighted(&[("model-a", 50.0), ("model-b", 50.0)]));
+        let first = resolve_model_route_for_request("ab-repro", &mapping, None, "trace-123");
+        for _ in 0..5 {
+            assert_eq!(resolve_model_route_for_request("ab-repro", &mapping, None, "trace-123"), first);
+        }
+    }
+
+    #[test]
+    fn test_weighted_choice_is_sticky_per_session() {
+        let mut mapping = HashMap::new();
+        mapping.insert("ab-sticky".to_string(), weighted(&[("model-a", 50.0), ("model-b", 50.0)]));
+        let first = resolve_model_route_for_request("ab-sticky", &mapping, Some("session-1"), "seed-0");
+        for i in 1..20 {
+            let seed = format!("seed-{}", i);
+            assert_eq!(resolve_model_route_for_request("ab-sticky", &mapping, Some("session-1"), &seed), first);
+        }
+    }
+
+    #[test]
+    fn test_weighted_split_roughly_honors_weights() {
+        let mut mapping = HashMap::new();
+        mapping.insert("ab-ramp".to_string(), weighted(&[("model-old", 80.0), ("model-new", 20.0)]));
+        for i in 0..2000 {
+            resolve_model_route_for_request("ab-ramp", &mapping, None, &format!("trace-{}", i));
+        }
+        let stats = weighted_route_stats(&mapping);
+        let split = stats.iter().find(|s| s.alias == "ab-ramp").unwrap();
+        assert_eq!(split.total, 2000);
+        let new_share = split.targets.iter().find(|t| t.model == "model-new").unwrap();
+        assert!((new_share.expected_ratio - 0.2).abs() < 1e-9);
+        assert!((new_share.realized_ratio - 0.2).abs() < 0.05, "realized {}", new_share.realized_ratio);
+    }
+
+    #[test]
+    fn test_weight_validation() {
+        assert!(weighted(&[("a", 1.0)]).validate().is_ok());
+        assert!(weighted(&[("a", 0.0), ("b", 0.0)]).validate().is_err());
+        assert!(weighted(&[("a", -1.0), ("b", 2.0)]).validate().is_err());
+        assert!(weighted(&[]).validate().is_err());
+        assert!(ModelMappingTarget::from("gemini-2.5-pro").validate().is_ok());
+    }
+
+    #[test]
+    fn test_mapping_template_roundtrip_and_merge() {
+        let mut live = HashMap::new();
+        live.insert("gpt-4*".to_string(), ModelMappingTarget::from("gemini-2.5-pro"));
+        live.insert("ab-ramp".to_string(), weighted(&[("model-old", 80.0), ("model-new", 20.0)]));
+
+        // 导出后再以合并方式导入，实时映射保持等价
+        let template = export_mapping_template(&live).unwrap();
+        assert_eq!(apply_mapping_template(&live, &template, true).unwrap(), live);
+        assert_eq!(apply_mapping_template(&HashMap::new(), &template, false).unwrap(), live);
+
+        // 合并保留模板未涉及的现有条目，同名条目以模板为准
+        let mut existing = HashMap::new();
+        existing.insert("claude-haiku-*".to_string(), ModelMappingTarget::from("gemini-2.5-flash"));
+        existing.insert("gpt-4*".to_string(), ModelMappingTarget::from("gemini-3-pro"));
+        let merged = apply_mapping_template(&existing, &template, true).unwrap();
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged["claude-haiku-*"], ModelMappingTarget::from("gemini-2.5-flash"));
+        assert_eq!(merged["gpt-4*"], ModelMappingTarget::from("gemini-2.5-pro"));
+
+        // 覆盖模式只保留模板内容
+        assert_eq!(apply_mapping_template(&existing, &template, false).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_mapping_template_rejects_empty_names() {
+        let current = HashMap::new();
+        assert!(apply_mapping_template(&current, r#"{"": "gemini-2.5-pro"}"#, true).is_err());
+        assert!(apply_mapping_template(&current, r#"{"gpt-4": "  "}"#, true).is_err());
+        assert!(apply_mapping_template(&current, r#"{"gpt-4": 42}"#, true).is_err());
+        assert!(apply_mapping_template(&current, "not json", true).is_err());
+    }
 }
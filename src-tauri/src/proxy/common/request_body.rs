@@ -0,0 +1,311 @@
+// 宽松的 JSON 请求体解析
+// 兼容老旧 HTTP 客户端：带参数的 Content-Type、UTF-8 BOM、gzip/deflate 压缩请求体
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::io::Read;
+
+/// 解压后请求体的最大字节数 (与路由的 DefaultBodyLimit 保持一致)
+pub const MAX_DECOMPRESSED_BODY_BYTES: usize = 100 * 1024 * 1024;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+
+/// 请求体解析失败的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum BodyError {
+    UnsupportedContentType(String),
+    UnsupportedEncoding(String),
+    Decompress(String),
+    TooLarge,
+    InvalidUtf8,
+    InvalidJson(String),
+}
+
+impl BodyError {
+    fn status(&self) -> StatusCode {
+        match self {
+            BodyError::UnsupportedContentType(_) | BodyError::UnsupportedEncoding(_) => {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            }
+            BodyError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            BodyError::UnsupportedContentType(ct) => {
+                format!("Unsupported Content-Type '{}': expected application/json", ct)
+            }
+            BodyError::UnsupportedEncoding(enc) => {
+                format!("Unsupported Content-Encoding '{}': expected gzip, deflate or identity", enc)
+            }
+            BodyError::Decompress(e) => format!("Failed to decompress request body: {}", e),
+            BodyError::TooLarge => format!(
+                "Request body exceeds {} bytes after decompression",
+                MAX_DECOMPRESSED_BODY_BYTES
+            ),
+            BodyError::InvalidUtf8 => "Request body is not valid UTF-8".to_string(),
+            BodyError::InvalidJson(e) => format!("Failed to parse the request body as JSON: {}", e),
+        }
+    }
+}
+
+/// 错误响应的协议格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ErrorEnvelope {
+    OpenAI,
+    Claude,
+}
+
+/// 请求体解析失败时的响应 (按协议返回对应的错误结构)
+#[derive(Debug)]
+pub struct BodyRejection {
+    error: BodyError,
+    envelope: ErrorEnvelope,
+}
+
+impl IntoResponse for BodyRejection {
+    fn into_response(self) -> Response {
+        let status = self.error.status();
+        let message = self.error.message();
+        tracing::warn!("[Request-Body] Rejected request body: {}", message);
+
+        let body = match self.envelope {
+            ErrorEnvelope::Claude => json!({
+                "type": "error",
+                "error": {
+                    "type": "invalid_request_error",
+                    "message": message
+                }
+            }),
+            ErrorEnvelope::OpenAI => json!({
+                "error": {
+                    "message": message,
+                    "type": "invalid_request_error",
+                    "param": null,
+                    "code": null
+                }
+            }),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+/// 宽松的 JSON 提取器，替代 OpenAI / Claude 路由上的 `Json<Value>`
+pub struct TolerantJson<T = Value>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for TolerantJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let envelope = if req.uri().path().starts_with("/v1/messages") {
+            ErrorEnvelope::Claude
+        } else {
+            ErrorEnvelope::OpenAI
+        };
+        let headers = req.headers().clone();
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        decode_json_body(&headers, &bytes)
+            .map(TolerantJson)
+            .map_err(|error| BodyRejection { error, envelope }.into_response())
+    }
+}
+
+/// 按 Content-Type / Content-Encoding 解码请求体并解析为 JSON
+pub fn decode_json_body<T: DeserializeOwned>(headers: &HeaderMap, body: &[u8]) -> Result<T, BodyError> {
+    check_content_type(headers)?;
+
+    let decoded = decompress(headers, body)?;
+    let bytes = decoded.strip_prefix(UTF8_BOM).unwrap_or(&decoded);
+    let text = std::str::from_utf8(bytes).map_err(|_| BodyError::InvalidUtf8)?;
+
+    serde_json::from_str(text).map_err(|e| BodyError::InvalidJson(e.to_string()))
+}
+
+/// 接受 application/json 及其带参数的变体 (如 charset=UTF-8)、`+json` 后缀与缺省 Content-Type
+fn check_content_type(headers: &HeaderMap) -> Result<(), BodyError> {
+    let Some(raw) = headers.get(header::CONTENT_TYPE) else {
+        return Ok(());
+    };
+    let value = raw.to_str().unwrap_or_default();
+    let mime = value.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+
+    let accepted = mime.is_empty()
+        || mime == "application/json"
+        || (mime.starts_with("application/") && mime.ends_with("+json"))
+        || mime == "text/plain";
+    if accepted {
+        Ok(())
+    } else {
+        Err(BodyError::UnsupportedContentType(value.to_string()))
+    }
+}
+
+fn decompress(headers: &HeaderMap, body: &[u8]) -> Result<Vec<u8>, BodyError> {
+    let encoding = headers
+        .get(header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match encoding.as_str() {
+        "" | "identity" => Ok(body.to_vec()),
+        "gzip" | "x-gzip" => read_capped(flate2::read::GzDecoder::new(body), MAX_DECOMPRESSED_BODY_BYTES),
+        // HTTP 规范中的 deflate 为 zlib 封装，但不少客户端直接发送裸 deflate 流
+        "deflate" => read_capped(flate2::read::ZlibDecoder::new(body), MAX_DECOMPRESSED_BODY_BYTES)
+            .or_else(|_| read_capped(flate2::read::DeflateDecoder::new(body), MAX_DECOMPRESSED_BODY_BYTES)),
+        other => Err(BodyError::UnsupportedEncoding(other.to_string())),
+    }
+}
+
+fn read_capped<R: Read>(reader: R, limit: usize) -> Result<Vec<u8>, BodyError> {
+    let mut out = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| BodyError::Decompress(e.to_string()))?;
+    if out.len() > limit {
+        return Err(BodyError::TooLarge);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use std::io::Write;
+
+    const BODY: &str = r#"{"model":"gpt-4o","messages":[{"role":"user","content":"hi"}]}"#;
+
+    fn headers(content_type: Option<&str>, encoding: Option<&str>) -> HeaderMap {
+        let mut h = HeaderMap::new();
+        if let Some(ct) = content_type {
+            h.insert(header::CONTENT_TYPE, HeaderValue::from_str(ct).unwrap());
+        }
+        if let Some(enc) = encoding {
+            h.insert(header::CONTENT_ENCODING, HeaderValue::from_str(enc).unwrap());
+        }
+        h
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(data).unwrap();
+        enc.finish().unwrap()
+    }
+
+    fn decode(h: &HeaderMap, body: &[u8]) -> Result<Value, BodyError> {
+        decode_json_body(h, body)
+    }
+
+    #[test]
+    fn test_content_type_with_charset() {
+        let v = decode(&headers(Some("application/json; charset=UTF-8"), None), BODY.as_bytes()).unwrap();
+        assert_eq!(v["model"], "gpt-4o");
+    }
+
+    #[test]
+    fn test_missing_content_type() {
+        assert!(decode(&HeaderMap::new(), BODY.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_utf8_bom_is_stripped() {
+        let mut body = UTF8_BOM.to_vec();
+        body.extend_from_slice(BODY.as_bytes());
+        let v = decode(&headers(Some("application/json"), None), &body).unwrap();
+        assert_eq!(v["messages"][0]["content"], "hi");
+    }
+
+    #[test]
+    fn test_gzip_body_with_bom() {
+        let mut raw = UTF8_BOM.to_vec();
+        raw.extend_from_slice(BODY.as_bytes());
+        let v = decode(&headers(Some("application/json; charset=utf-8"), Some("gzip")), &gzip(&raw)).unwrap();
+        assert_eq!(v["model"], "gpt-4o");
+    }
+
+    #[test]
+    fn test_deflate_zlib_and_raw() {
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(BODY.as_bytes()).unwrap();
+        let zlib = zlib.finish().unwrap();
+        assert!(decode(&headers(None, Some("deflate")), &zlib).is_ok());
+
+        let mut raw = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        raw.write_all(BODY.as_bytes()).unwrap();
+        let raw = raw.finish().unwrap();
+        assert!(decode(&headers(None, Some("deflate")), &raw).is_ok());
+    }
+
+    #[test]
+    fn test_decompression_size_cap() {
+        // 压缩炸弹：极小的压缩体展开后超过上限
+        let bomb = gzip(&vec![b' '; 64 * 1024]);
+        let err = read_capped(flate2::read::GzDecoder::new(bomb.as_slice()), 1024).unwrap_err();
+        assert_eq!(err, BodyError::TooLarge);
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let ok = read_capped(flate2::read::GzDecoder::new(bomb.as_slice()), 64 * 1024).unwrap();
+        assert_eq!(ok.len(), 64 * 1024);
+    }
+
+    #[test]
+    fn test_invalid_inputs_name_the_problem() {
+        let err = decode(&headers(Some("application/json"), None), b"{\"model\": ").unwrap_err();
+        assert!(matches!(err, BodyError::InvalidJson(_)));
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+
+        let err = decode(&headers(Some("application/json"), Some("gzip")), BODY.as_bytes()).unwrap_err();
+        assert!(matches!(err, BodyError::Decompress(_)));
+
+        let err = decode(&headers(Some("application/xml"), None), BODY.as_bytes()).unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert!(err.message().contains("application/xml"));
+
+        let err = decode(&headers(None, Some("br")), BODY.as_bytes()).unwrap_err();
+        assert_eq!(err, BodyError::UnsupportedEncoding("br".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_rejection_uses_protocol_envelope() {
+        for (path, claude) in [("/v1/messages", true), ("/v1/chat/completions", false)] {
+            let req = Request::builder()
+                .method("POST")
+                .uri(path)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from("not json"))
+                .unwrap();
+            let resp = match TolerantJson::<Value>::from_request(req, &()).await {
+                Ok(_) => panic!("expected rejection"),
+                Err(resp) => resp,
+            };
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+            let v: Value = serde_json::from_slice(&bytes).unwrap();
+            if claude {
+                assert_eq!(v["type"], "error");
+            }
+            assert_eq!(v["error"]["type"], "invalid_request_error");
+            assert!(v["error"]["message"].as_str().unwrap().contains("JSON"));
+        }
+    }
+}
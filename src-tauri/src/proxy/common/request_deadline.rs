@@ -0,0 +1,64 @@
+// 请求截止时间 - 客户端通过 X-Max-Duration-Ms 请求头限制代理在重试上花费的总时间
+
+use axum::http::HeaderMap;
+use std::time::{Duration, Instant};
+
+pub const MAX_DURATION_HEADER: &str = "x-max-duration-ms";
+
+#[derive(Debug, Clone, Copy)]
+pub struct RequestDeadline {
+    budget_ms: Option<u64>,
+    deadline: Option<Instant>,
+}
+
+impl RequestDeadline {
+    /// 解析 X-Max-Duration-Ms 请求头 (从调用时刻开始计时)，缺失、为 0 或无法解析时不限制
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let budget_ms = headers
+            .get(MAX_DURATION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .filter(|ms| *ms > 0);
+        Self {
+            budget_ms,
+            deadline: budget_ms.map(|ms| Instant::now() + Duration::from_millis(ms)),
+        }
+    }
+
+    /// 客户端指定的时长 (毫秒)
+    pub fn budget_ms(&self) -> Option<u64> {
+        self.budget_ms
+    }
+
+    pub fn is_exceeded(&self) -> bool {
+        self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    /// 等待 `wait` 之后是否仍在截止时间之内
+    pub fn allows_wait(&self, wait: Duration) -> bool {
+        self.deadline.map_or(true, |d| Instant::now() + wait < d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_deadline_header() {
+        let mut headers = HeaderMap::new();
+        let unbounded = RequestDeadline::from_headers(&headers);
+        assert_eq!(unbounded.budget_ms(), None);
+        assert!(unbounded.allows_wait(Duration::from_secs(3600)));
+
+        headers.insert("X-Max-Duration-Ms", "abc".parse().unwrap());
+        assert_eq!(RequestDeadline::from_headers(&headers).budget_ms(), None);
+
+        headers.insert("X-Max-Duration-Ms", " 500 ".parse().unwrap());
+        let bounded = RequestDeadline::from_headers(&headers);
+        assert_eq!(bounded.budget_ms(), Some(500));
+        assert!(!bounded.is_exceeded());
+        assert!(bounded.allows_wait(Duration::from_millis(10)));
+        assert!(!bounded.allows_wait(Duration::from_secs(1)));
+    }
+}
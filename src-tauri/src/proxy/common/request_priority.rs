@@ -0,0 +1,58 @@
+// 请求优先级提示 - 客户端通过 X-Priority 请求头区分交互式与批量流量
+
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+pub const PRIORITY_HEADER: &str = "x-priority";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestPriority {
+    /// 延迟敏感流量，按默认调度 (ULTRA > PRO > FREE，参与 60s 粘性窗口)
+    #[default]
+    Interactive,
+    /// 批量流量，优先 FREE 账号且不占用交互式会话的热账号
+    Bulk,
+}
+
+impl RequestPriority {
+    /// 解析 X-Priority 请求头，缺失或无法识别时视为 interactive
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(PRIORITY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(Self::parse)
+            .unwrap_or_default()
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "bulk" => Self::Bulk,
+            _ => Self::Interactive,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Interactive => "interactive",
+            Self::Bulk => "bulk",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_priority_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(RequestPriority::from_headers(&headers), RequestPriority::Interactive);
+
+        headers.insert("X-Priority", " Bulk ".parse().unwrap());
+        assert_eq!(RequestPriority::from_headers(&headers), RequestPriority::Bulk);
+
+        headers.insert("X-Priority", "urgent".parse().unwrap());
+        assert_eq!(RequestPriority::from_headers(&headers), RequestPriority::Interactive);
+    }
+}
@@ -0,0 +1,46 @@
+// 请求类型覆盖 - 客户端通过 X-Request-Type 请求头指定账号调度使用的配额分组
+// 自动推断 (resolve_request_config) 对自定义映射的模型可能不准确，此时可手动指定
+
+use axum::http::HeaderMap;
+
+pub const REQUEST_TYPE_HEADER: &str = "x-request-type";
+
+/// 已知的请求类型 (与 resolve_request_config 的推断结果一致)
+pub const KNOWN_REQUEST_TYPES: [&str; 3] = ["agent", "web_search", "image_gen"];
+
+/// 校验请求类型，未知值返回 None
+pub fn parse_request_type(value: &str) -> Option<&'static str> {
+    let value = value.trim().to_ascii_lowercase();
+    KNOWN_REQUEST_TYPES.iter().copied().find(|t| *t == value)
+}
+
+/// 解析 X-Request-Type 请求头，缺失或无法识别时返回 None (无法识别时记录警告)
+pub fn request_type_from_headers(headers: &HeaderMap) -> Option<&'static str> {
+    let raw = headers.get(REQUEST_TYPE_HEADER)?.to_str().ok()?;
+    let parsed = parse_request_type(raw);
+    if parsed.is_none() {
+        tracing::warn!(
+            "Ignoring unknown X-Request-Type '{}' (expected one of {:?})",
+            raw,
+            KNOWN_REQUEST_TYPES
+        );
+    }
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request_type_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(request_type_from_headers(&headers), None);
+
+        headers.insert("X-Request-Type", " Image_Gen ".parse().unwrap());
+        assert_eq!(request_type_from_headers(&headers), Some("image_gen"));
+
+        headers.insert("X-Request-Type", "claude".parse().unwrap());
+        assert_eq!(request_type_from_headers(&headers), None);
+    }
+}
@@ -0,0 +1,136 @@
+// 采样参数钳制
+// 客户端常按 Claude / OpenAI 的取值范围传参，超出 Gemini 模型有效范围时上游直接返回 400，
+// 这里在转换后的请求体上按模型把 temperature / topP / topK 修正到有效范围内。
+
+use serde_json::{json, Value};
+
+use crate::proxy::config::SamplingLimitsConfig;
+
+/// 钳制转换后请求体 (`{ model, request: { generationConfig } }`) 中的采样参数
+pub fn clamp_sampling_params(body: &mut Value, config: &SamplingLimitsConfig) {
+    if !config.enabled {
+        return;
+    }
+    let model = body
+        .get("model")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let Some(gen_config) = body
+        .get_mut("request")
+        .and_then(|r| r.get_mut("generationConfig"))
+        .and_then(|g| g.as_object_mut())
+    else {
+        return;
+    };
+    let limits = config.limits_for(&model);
+
+    for (key, min, max) in [
+        ("temperature", limits.temperature_min, limits.temperature_max),
+        ("topP", limits.top_p_min, limits.top_p_max),
+    ] {
+        if let Some(value) = gen_config.get(key).and_then(|v| v.as_f64()) {
+            let clamped = value.clamp(min, max);
+            if clamped != value {
+                tracing::debug!(
+                    "[Sampling-Clamp] {} {} -> {} for model {} (valid range {}..={})",
+                    key, value, clamped, model, min, max
+                );
+                gen_config.insert(key.to_string(), json!(clamped));
+            }
+        }
+    }
+
+    if let Some(value) = gen_config.get("topK").and_then(|v| v.as_f64()) {
+        let clamped = value
+            .round()
+            .clamp(limits.top_k_min as f64, limits.top_k_max as f64) as u32;
+        if clamped as f64 != value {
+            tracing::debug!(
+                "[Sampling-Clamp] topK {} -> {} for model {} (valid range {}..={})",
+                value, clamped, model, limits.top_k_min, limits.top_k_max
+            );
+            gen_config.insert("topK".to_string(), json!(clamped));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::config::SamplingLimits;
+    use crate::proxy::mappers::openai::OpenAIRequest;
+
+    fn body(model: &str, gen_config: Value) -> Value {
+        json!({
+            "model": model,
+            "request": { "generationConfig": gen_config }
+        })
+    }
+
+    #[test]
+    fn test_out_of_range_temperature_is_clamped_in_transformed_body() {
+        let req: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{ "role": "user", "content": "hi" }],
+            "temperature": 3.5
+        }))
+        .unwrap();
+
+        let mut transformed =
+            crate::proxy::mappers::openai::transform_openai_request(&req, "test-project", "gemini-3-flash");
+        assert_eq!(transformed["request"]["generationConfig"]["temperature"], 3.5);
+
+        clamp_sampling_params(&mut transformed, &SamplingLimitsConfig::default());
+        assert_eq!(transformed["request"]["generationConfig"]["temperature"], 2.0);
+    }
+
+    #[test]
+    fn test_top_k_and_top_p_clamped_to_range() {
+        let mut b = body("gemini-3-flash", json!({ "temperature": -1.0, "topP": 1.5, "topK": 500 }));
+        clamp_sampling_params(&mut b, &SamplingLimitsConfig::default());
+        let g = &b["request"]["generationConfig"];
+        assert_eq!(g["temperature"], 0.0);
+        assert_eq!(g["topP"], 1.0);
+        assert_eq!(g["topK"], 64);
+    }
+
+    #[test]
+    fn test_in_range_values_untouched_and_disabled_is_noop() {
+        let original = body("gemini-3-flash", json!({ "temperature": 0.7, "topK": 40 }));
+        let mut b = original.clone();
+        clamp_sampling_params(&mut b, &SamplingLimitsConfig::default());
+        assert_eq!(b, original);
+
+        let mut b = body("gemini-3-flash", json!({ "temperature": 9.0 }));
+        let config = SamplingLimitsConfig { enabled: false, ..Default::default() };
+        clamp_sampling_params(&mut b, &config);
+        assert_eq!(b["request"]["generationConfig"]["temperature"], 9.0);
+    }
+
+    #[test]
+    fn test_per_model_override_prefers_exact_then_longest_prefix() {
+        let mut config = SamplingLimitsConfig::default();
+        config.per_model.insert(
+            "claude-*".to_string(),
+            SamplingLimits { temperature_max: 1.0, ..Default::default() },
+        );
+        config.per_model.insert(
+            "claude-opus-*".to_string(),
+            SamplingLimits { temperature_max: 0.8, ..Default::default() },
+        );
+        config.per_model.insert(
+            "claude-opus-4-5-thinking".to_string(),
+            SamplingLimits { temperature_max: 0.5, ..Default::default() },
+        );
+
+        assert_eq!(config.limits_for("claude-sonnet-4-5").temperature_max, 1.0);
+        assert_eq!(config.limits_for("claude-opus-4-5").temperature_max, 0.8);
+        assert_eq!(config.limits_for("claude-opus-4-5-thinking").temperature_max, 0.5);
+        assert_eq!(config.limits_for("gemini-3-flash").temperature_max, 2.0);
+
+        let mut b = body("claude-sonnet-4-5", json!({ "temperature": 1.7 }));
+        clamp_sampling_params(&mut b, &config);
+        assert_eq!(b["request"]["generationConfig"]["temperature"], 1.0);
+    }
+}
@@ -0,0 +1,130 @@
+// SSE 心跳
+// Thinking 类模型在首个内容块之前可能长时间没有输出，部分客户端与中间代理会因连接空闲而断开。
+// 在首个内容事件出现之前，上游每静默一个间隔就插入一次心跳；内容开始流动后不再插入。
+// 处理器预读首块时若拿到的是心跳，会立即开始响应 (放弃该次尝试的换号重试)，保证心跳确实发到客户端。
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::time::Duration;
+
+/// Anthropic 协议的 ping 事件
+pub const CLAUDE_HEARTBEAT: &[u8] = b"event: ping\ndata: {\"type\":\"ping\"}\n\n";
+
+/// OpenAI 协议使用 SSE 注释行 (客户端会忽略)
+pub const OPENAI_HEARTBEAT: &[u8] = b": keepalive\n\n";
+
+type SseStream<E> = Pin<Box<dyn Stream<Item = Result<Bytes, E>> + Send>>;
+
+/// 是否为心跳
+#[cfg(test)]
+pub fn is_heartbeat(chunk: &[u8]) -> bool {
+    chunk == CLAUDE_HEARTBEAT || chunk == OPENAI_HEARTBEAT
+}
+
+/// Claude 流中 message_start 之后的事件才算内容
+fn is_claude_content(chunk: &[u8]) -> bool {
+    !chunk.is_empty() && !chunk.starts_with(b"event: message_start")
+}
+
+/// OpenAI 流的每个数据块都携带内容
+fn is_openai_content(chunk: &[u8]) -> bool {
+    !chunk.is_empty()
+}
+
+/// 为 Claude SSE 流加上 ping 心跳 (`interval` 为 0 时原样返回)
+pub fn claude<E: Send + 'static>(stream: SseStream<E>, interval: Duration) -> SseStream<E> {
+    with_heartbeat(stream, interval, CLAUDE_HEARTBEAT, is_claude_content)
+}
+
+/// 为 OpenAI SSE 流加上注释行心跳 (`interval` 为 0 时原样返回)
+pub fn openai<E: Send + 'static>(stream: SseStream<E>, interval: Duration) -> SseStream<E> {
+    with_heartbeat(stream, interval, OPENAI_HEARTBEAT, is_openai_content)
+}
+
+fn with_heartbeat<E: Send + 'static>(
+    mut inner: SseStream<E>,
+    interval: Duration,
+    heartbeat: &'static [u8],
+    is_content: fn(&[u8]) -> bool,
+) -> SseStream<E> {
+    if interval.is_zero() {
+        return inner;
+    }
+    Box::pin(async_stream::stream! {
+        loop {
+            match tokio::time::timeout(interval, inner.next()).await {
+                Ok(Some(item)) => {
+                    // 错误也视为流已进入正常阶段，不再插入心跳
+                    let content_started = match &item {
+                        Ok(chunk) => is_content(chunk),
+                        Err(_) => true,
+                    };
+                    yield item;
+                    if content_started {
+                        break;
+                    }
+                }
+                Ok(None) => return,
+                Err(_) => yield Ok(Bytes::from_static(heartbeat)),
+            }
+        }
+        while let Some(item) = inner.next().await {
+            yield item;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delayed(events: Vec<(u64, &'static str)>) -> SseStream<String> {
+        Box::pin(async_stream::stream! {
+            for (delay_ms, event) in events {
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                yield Ok(Bytes::from_static(event.as_bytes()));
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_heartbeats_only_until_first_content() {
+        let upstream = delayed(vec![
+            (0, "event: message_start\ndata: {}\n\n"),
+            // 长时间思考，期间没有任何输出
+            (250, "event: content_block_start\ndata: {}\n\n"),
+            (250, "event: content_block_delta\ndata: {}\n\n"),
+        ]);
+        let chunks: Vec<Bytes> = claude(upstream, Duration::from_millis(50))
+            .map(|c| c.unwrap())
+            .collect()
+            .await;
+
+        let first_content = chunks
+            .iter()
+            .position(|c| c.starts_with(b"event: content_block_start"))
+            .unwrap();
+        let heartbeats_before = chunks[..first_content].iter().filter(|c| is_heartbeat(c)).count();
+        assert!(heartbeats_before >= 2, "expected heartbeats while waiting, got {:?}", chunks);
+        assert!(chunks[first_content..].iter().all(|c| !is_heartbeat(c)));
+        assert!(chunks[0].starts_with(b"event: message_start"));
+        assert!(chunks.last().unwrap().starts_with(b"event: content_block_delta"));
+    }
+
+    #[tokio::test]
+    async fn test_openai_uses_comment_lines_and_zero_disables() {
+        let with_heartbeat: Vec<Bytes> = openai(delayed(vec![(150, "data: {}\n\n")]), Duration::from_millis(40))
+            .map(|c| c.unwrap())
+            .collect()
+            .await;
+        assert!(with_heartbeat.len() >= 3);
+        assert!(with_heartbeat[..with_heartbeat.len() - 1].iter().all(|c| c.as_ref() == OPENAI_HEARTBEAT));
+
+        let disabled: Vec<Bytes> = openai(delayed(vec![(150, "data: {}\n\n")]), Duration::ZERO)
+            .map(|c| c.unwrap())
+            .collect()
+            .await;
+        assert_eq!(disabled, vec![Bytes::from_static(b"data: {}\n\n")]);
+    }
+}
@@ -0,0 +1,127 @@
+// 上游 SSE 事件解析
+// 按 SSE 规范逐行解析：兼容 \r\n 行尾、多行 data 拼接、注释行以及 event/id/retry 字段。
+// 上游 (或中间代理) 未用空行分隔事件时，data 一旦构成完整 JSON 即立即派发，保持逐行处理的行为。
+
+use bytes::BytesMut;
+use serde::de::IgnoredAny;
+
+#[derive(Default)]
+pub struct SseEventParser {
+    buffer: BytesMut,
+    /// 当前事件已累积的 data (多行以 \n 连接)
+    data: Option<String>,
+}
+
+/// data 是否已构成可独立派发的载荷 (完整 JSON 或 [DONE])
+fn is_complete(data: &str) -> bool {
+    let data = data.trim();
+    data == "[DONE]" || serde_json::from_str::<IgnoredAny>(data).is_ok()
+}
+
+impl SseEventParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 追加上游字节，返回所有已完整的事件 data
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line_raw = self.buffer.split_to(pos + 1);
+            let Ok(line) = std::str::from_utf8(&line_raw) else {
+                continue;
+            };
+            self.process_line(line.trim_end_matches(['\n', '\r']), &mut events);
+        }
+        events
+    }
+
+    fn process_line(&mut self, line: &str, events: &mut Vec<String>) {
+        // 空行：事件结束
+        if line.is_empty() {
+            if let Some(data) = self.data.take() {
+                events.push(data);
+            }
+            return;
+        }
+        // 注释 (如 keep-alive)
+        if line.starts_with(':') {
+            return;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        // event / id / retry 等字段对转换无意义
+        if field != "data" {
+            return;
+        }
+
+        match self.data.take() {
+            // 未完成的 data 后出现一条独立的完整 JSON：前者已损坏，丢弃
+            Some(pending) if value.trim_start().starts_with('{') && is_complete(value) => {
+                tracing::debug!("[SSE] Dropping incomplete event data ({} bytes)", pending.len());
+                self.data = Some(value.to_string());
+            }
+            Some(mut pending) => {
+                pending.push('\n');
+                pending.push_str(value);
+                self.data = Some(pending);
+            }
+            None => self.data = Some(value.to_string()),
+        }
+
+        if self.data.as_deref().is_some_and(is_complete) {
+            events.extend(self.data.take());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_in_chunks(input: &str, chunk_size: usize) -> Vec<String> {
+        let mut parser = SseEventParser::new();
+        input
+            .as_bytes()
+            .chunks(chunk_size)
+            .flat_map(|c| parser.push(c))
+            .collect()
+    }
+
+    #[test]
+    fn test_crlf_multiline_and_comments() {
+        let input = concat!(
+            ": keep-alive\r\n",
+            "event: message\r\n",
+            "id: 1\r\n",
+            "data: {\"text\":\r\n",
+            "data: \"Hello\"}\r\n",
+            "\r\n",
+            "retry: 1000\r\n",
+            "data:{\"text\":\"world\"}\r\n",
+            "\r\n",
+            "data: [DONE]\r\n",
+            "\r\n",
+        );
+        for chunk_size in [1, 3, 7, input.len()] {
+            let events = parse_in_chunks(input, chunk_size);
+            assert_eq!(events, vec!["{\"text\":\n\"Hello\"}", "{\"text\":\"world\"}", "[DONE]"]);
+        }
+    }
+
+    #[test]
+    fn test_line_delimited_and_broken_events() {
+        // 无空行分隔的逐行 data，中间夹杂截断的事件
+        let input = concat!(
+            "data: {\"a\":1}\n",
+            "data: {\"broken\":\n",
+            "garbage line\n",
+            "data: {\"b\":2}\n",
+        );
+        assert_eq!(parse_in_chunks(input, 5), vec!["{\"a\":1}", "{\"b\":2}"]);
+    }
+}
@@ -9,6 +9,17 @@ pub fn generate_random_id() -> String {
         .collect()
 }
 
+/// 生成请求级 Trace ID，用于日志追踪，也作为加权模型路由的随机种子 (可据日志复现选择结果)
+pub fn generate_trace_id() -> String {
+    use rand::Rng;
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(6)
+        .map(char::from)
+        .collect::<String>()
+        .to_lowercase()
+}
+
 /// 根据模型名称推测功能类型
 // 注意：此函数已弃用，请改用 mappers::common_utils::resolve_request_config
 pub fn _deprecated_infer_quota_group(model: &str) -> String {
@@ -1,8 +1,8 @@
 use serde::{Deserialize, Serialize};
 // use std::path::PathBuf;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum ProxyAuthMode {
     Off,
@@ -17,6 +17,133 @@ impl Default for ProxyAuthMode {
     }
 }
 
+/// 可单独设置鉴权模式的路由分组
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteGroup {
+    /// /v1/messages、/v1/messages/count_tokens、/v1/models/claude
+    Claude,
+    /// /v1/models、/v1/chat/completions、/v1/completions、/v1/responses、/v1/images/*、/v1/audio/*
+    Openai,
+    /// /v1beta/models/*
+    GeminiNative,
+    /// /healthz (/metrics 与 /admin/ 仍使用各自的访问策略)
+    Status,
+}
+
+impl RouteGroup {
+    pub const ALL: [RouteGroup; 4] = [Self::Claude, Self::Openai, Self::GeminiNative, Self::Status];
+
+    /// 按请求路径匹配分组；未归入任何分组的路径返回 None
+    pub fn for_path(path: &str) -> Option<Self> {
+        match path {
+            "/healthz" => Some(Self::Status),
+            "/v1/messages" | "/v1/messages/count_tokens" | "/v1/models/claude" => Some(Self::Claude),
+            "/v1/models" | "/v1/chat/completions" | "/v1/completions" | "/v1/responses" => Some(Self::Openai),
+            p if p.starts_with("/v1/images/") || p.starts_with("/v1/audio/") => Some(Self::Openai),
+            p if p == "/v1beta/models" || p.starts_with("/v1beta/models/") => Some(Self::GeminiNative),
+            _ => None,
+        }
+    }
+}
+
+/// 附加的客户端 Key：明文只在生成时展示一次，配置中只保存 SHA-256
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ClientApiKey {
+    pub id: String,
+    #[serde(default)]
+    pub label: String,
+    pub key_sha256: String,
+    /// 允许访问的路由分组，为空时不限
+    #[serde(default)]
+    pub scopes: Vec<RouteGroup>,
+    pub created_at: i64,
+}
+
+/// 按路由分组覆盖的鉴权模式，未设置的分组使用全局 auth_mode
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RouteAuthConfig {
+    #[serde(default)]
+    pub claude: Option<ProxyAuthMode>,
+    #[serde(default)]
+    pub openai: Option<ProxyAuthMode>,
+    #[serde(default)]
+    pub gemini_native: Option<ProxyAuthMode>,
+    #[serde(default)]
+    pub status: Option<ProxyAuthMode>,
+}
+
+impl RouteAuthConfig {
+    pub fn get(&self, group: RouteGroup) -> Option<&ProxyAuthMode> {
+        match group {
+            RouteGroup::Claude => self.claude.as_ref(),
+            RouteGroup::Openai => self.openai.as_ref(),
+            RouteGroup::GeminiNative => self.gemini_native.as_ref(),
+            RouteGroup::Status => self.status.as_ref(),
+        }
+    }
+}
+
+/// 响应头中账号与模型信息的对外暴露策略
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseHeaderPolicy {
+    /// 原样返回 X-Account-Email / X-Account-Id / X-Mapped-Model
+    Full,
+    /// 账号邮箱部分打码，其他头保留
+    Masked,
+    /// 不返回任何账号与模型相关的响应头
+    Minimal,
+}
+
+impl Default for ResponseHeaderPolicy {
+    fn default() -> Self {
+        Self::Full
+    }
+}
+
+/// 诊断响应头开关与自定义响应头 (在暴露策略之后应用，对流式、非流式与错误响应一致生效)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ResponseHeadersConfig {
+    /// 返回 X-Account-Email / X-Account-Id
+    #[serde(default = "default_true")]
+    pub expose_account: bool,
+    /// 返回 X-Mapped-Model
+    #[serde(default = "default_true")]
+    pub expose_model: bool,
+    /// 附加到每个响应的自定义头 (名称或值不合法、或为 Content-Type 等保留头的条目会被忽略)
+    #[serde(default)]
+    pub extra: HashMap<String, String>,
+}
+
+impl Default for ResponseHeadersConfig {
+    fn default() -> Self {
+        Self {
+            expose_account: true,
+            expose_model: true,
+            extra: HashMap::new(),
+        }
+    }
+}
+
+/// 日志与响应头中账号的展示方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountIdentifierMode {
+    /// 显示完整邮箱
+    Email,
+    /// 显示账号标签 (未设置标签时退化为哈希)
+    Label,
+    /// 显示邮箱的稳定哈希
+    Hash,
+}
+
+impl Default for AccountIdentifierMode {
+    fn default() -> Self {
+        Self::Email
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum ZaiDispatchMode {
@@ -82,14 +209,28 @@ impl Default for ZaiMcpConfig {
     }
 }
 
+/// z.ai 号池中的单个 API Key
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ZaiApiKey {
+    pub key: String,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ZaiConfig {
     #[serde(default)]
     pub enabled: bool,
     #[serde(default = "default_zai_base_url")]
     pub base_url: String,
+    /// 单个 API Key (旧版字段，非空时作为号池中的第一个 Key)
     #[serde(default)]
     pub api_key: String,
+    /// API Key 号池 (按请求轮换，各 Key 独立限流)
+    #[serde(default)]
+    pub api_keys: Vec<ZaiApiKey>,
     #[serde(default)]
     pub dispatch_mode: ZaiDispatchMode,
     /// Optional per-model mapping overrides for Anthropic/Claude model ids.
@@ -100,6 +241,9 @@ pub struct ZaiConfig {
     pub models: ZaiModelDefaults,
     #[serde(default)]
     pub mcp: ZaiMcpConfig,
+    /// z.ai 转发失败 (网络错误 / 5xx) 时改用 Google 号池重试 (仅 Pooled 模式，需号池有账号)
+    #[serde(default)]
+    pub failure_fallback_to_google: bool,
 }
 
 impl Default for ZaiConfig {
@@ -108,14 +252,106 @@ impl Default for ZaiConfig {
             enabled: false,
             base_url: default_zai_base_url(),
             api_key: String::new(),
+            api_keys: Vec::new(),
             dispatch_mode: ZaiDispatchMode::Off,
             model_mapping: HashMap::new(),
             models: ZaiModelDefaults::default(),
             mcp: ZaiMcpConfig::default(),
+            failure_fallback_to_google: false,
         }
     }
 }
 
+impl ZaiConfig {
+    /// 生效的 Key 列表：旧版 `api_key` 在前，随后为号池中已启用的 Key (去空、去重)
+    pub fn effective_keys(&self) -> Vec<ZaiApiKey> {
+        let legacy = (!self.api_key.trim().is_empty()).then(|| ZaiApiKey {
+            key: self.api_key.trim().to_string(),
+            label: None,
+            enabled: true,
+        });
+        let mut keys: Vec<ZaiApiKey> = Vec::new();
+        for entry in legacy.into_iter().chain(self.api_keys.iter().cloned()) {
+            let key = entry.key.trim().to_string();
+            if entry.enabled && !key.is_empty() && !keys.iter().any(|k| k.key == key) {
+                keys.push(ZaiApiKey { key, ..entry });
+            }
+        }
+        keys
+    }
+
+    /// 第一个可用 Key (供 MCP 等不参与轮换的调用使用)
+    pub fn first_key(&self) -> Option<String> {
+        self.effective_keys().into_iter().next().map(|k| k.key)
+    }
+}
+
+/// 工具循环恢复模式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolLoopRecoveryMode {
+    /// 不做任何处理
+    Off,
+    /// 注入合成的 assistant/user 消息闭合工具循环 (默认)
+    Synthetic,
+    /// 仅移除最后一条 assistant 消息中未签名的 thinking 块，不注入任何消息
+    Strip,
+}
+
+impl Default for ToolLoopRecoveryMode {
+    fn default() -> Self {
+        Self::Synthetic
+    }
+}
+
+/// assistant 消息在过滤无效 thinking 块后变为空时的处理方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmptyAssistantMode {
+    /// 插入一个空文本块保持消息结构 (默认)
+    EmptyText,
+    /// 移除整条消息 (会破坏 tool_use / tool_result 配对或 user / assistant 交替时回退为插入空文本)
+    Drop,
+}
+
+impl Default for EmptyAssistantMode {
+    fn default() -> Self {
+        Self::EmptyText
+    }
+}
+
+/// 请求 logprobs 而目标模型不支持时的处理方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogprobsMode {
+    /// 忽略 logprobs 参数，照常返回 (响应中 logprobs 为 null)
+    Ignore,
+    /// 返回 400 invalid_request_error
+    Error,
+}
+
+impl Default for LogprobsMode {
+    fn default() -> Self {
+        Self::Ignore
+    }
+}
+
+/// 上游以 RECITATION 截断输出时的处理方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecitationPolicy {
+    /// 原样返回，stop_reason 置为 refusal 并附说明文本 (非流式响应另附 X-Finish-Reason 响应头)
+    PassThrough,
+    /// 在同一账号上提高温度并附加改写提示重试一次，返回输出更多的一次
+    Retry,
+}
+
+impl Default for RecitationPolicy {
+    fn default() -> Self {
+        Self::Retry
+    }
+}
+
 /// 实验性功能配置 (Feature Flags)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExperimentalConfig {
@@ -126,10 +362,66 @@ pub struct ExperimentalConfig {
     /// 启用工具循环自动恢复 (Tool Loop Recovery)
     #[serde(default = "default_true")]
     pub enable_tool_loop_recovery: bool,
+
+    /// 工具循环恢复方式 (仅在 enable_tool_loop_recovery 开启时生效)
+    #[serde(default)]
+    pub tool_loop_recovery_mode: ToolLoopRecoveryMode,
+
+    /// 过滤 thinking 块后变为空的 assistant 消息：插入空文本 / 移除 (部分上游拒绝空内容的 assistant 消息)
+    #[serde(default)]
+    pub empty_assistant_mode: EmptyAssistantMode,
     
     /// 启用跨模型兼容性检查 (Cross-Model Checks)
     #[serde(default = "default_true")]
     pub enable_cross_model_checks: bool,
+
+    /// 遇到 MALFORMED_FUNCTION_CALL 时轮换账号重试 (关闭则直接返回并附带诊断说明)
+    #[serde(default = "default_true")]
+    pub retry_malformed_function_call: bool,
+
+    /// 遇到 RECITATION 时的处理方式 (重试一次 / 原样返回并标记)
+    #[serde(default)]
+    pub recitation_policy: RecitationPolicy,
+
+    /// 流式响应中途收到 usageMetadata 时发送增量 message_delta (默认关闭以保持严格兼容)
+    #[serde(default)]
+    pub emit_incremental_usage: bool,
+
+    /// 流式响应在首个内容块之前的心跳间隔 (秒，0 为关闭)
+    #[serde(default = "default_stream_heartbeat_seconds")]
+    pub stream_heartbeat_seconds: u64,
+
+    /// OpenAI `n` (候选数量) 上限，超出时拒绝请求以限制配额消耗
+    #[serde(default = "default_max_completions")]
+    pub max_completions: u32,
+
+    /// 上游报上下文超长时裁剪最早的历史消息后重试一次 (默认关闭)
+    #[serde(default)]
+    pub trim_context_on_overflow: bool,
+
+    /// 识别上下文超长错误的关键字 (不区分大小写)
+    #[serde(default = "default_context_exceeded_patterns")]
+    pub context_exceeded_patterns: Vec<String>,
+
+    /// 本地应答 Claude Code 的小模型工具调用 (默认关闭)
+    #[serde(default)]
+    pub local_responder: LocalResponderConfig,
+
+    /// OpenAI 请求的 logprobs / top_logprobs 在模型不支持时忽略还是报错
+    #[serde(default)]
+    pub logprobs_mode: LogprobsMode,
+
+    /// 号池耗尽前对最快解锁的账号做一次探测 (默认关闭)
+    #[serde(default)]
+    pub last_resort_probe: LastResortProbeConfig,
+
+    /// 允许客户端通过 X-Upstream-Region 头指定上游端点 (默认关闭)
+    #[serde(default)]
+    pub upstream_regions: UpstreamRegionsConfig,
+
+    /// 允许运行吞吐自测 (会消耗真实配额，默认关闭)
+    #[serde(default)]
+    pub enable_self_test: bool,
 }
 
 impl Default for ExperimentalConfig {
@@ -137,13 +429,140 @@ impl Default for ExperimentalConfig {
         Self {
             enable_signature_cache: true,
             enable_tool_loop_recovery: true,
+            tool_loop_recovery_mode: ToolLoopRecoveryMode::Synthetic,
+            empty_assistant_mode: EmptyAssistantMode::EmptyText,
             enable_cross_model_checks: true,
+            retry_malformed_function_call: true,
+            recitation_policy: RecitationPolicy::Retry,
+            emit_incremental_usage: false,
+            stream_heartbeat_seconds: default_stream_heartbeat_seconds(),
+            max_completions: default_max_completions(),
+            trim_context_on_overflow: false,
+            context_exceeded_patterns: default_context_exceeded_patterns(),
+            local_responder: LocalResponderConfig::default(),
+            logprobs_mode: LogprobsMode::Ignore,
+            last_resort_probe: LastResortProbeConfig::default(),
+            upstream_regions: UpstreamRegionsConfig::default(),
+            enable_self_test: false,
+        }
+    }
+}
+
+impl ExperimentalConfig {
+    /// 实际生效的工具循环恢复模式 (总开关关闭时视为 Off)
+    pub fn effective_tool_loop_recovery_mode(&self) -> ToolLoopRecoveryMode {
+        if self.enable_tool_loop_recovery {
+            self.tool_loop_recovery_mode
+        } else {
+            ToolLoopRecoveryMode::Off
         }
     }
 }
 
 fn default_true() -> bool { true }
 
+fn default_stream_heartbeat_seconds() -> u64 { 15 }
+
+fn default_max_completions() -> u32 { 4 }
+
+fn default_context_exceeded_patterns() -> Vec<String> {
+    vec![
+        "exceeds the maximum number of tokens".to_string(),
+        "input token count".to_string(),
+        "context_length_exceeded".to_string(),
+        "context length".to_string(),
+        "prompt is too long".to_string(),
+    ]
+}
+
+/// 最后探测
+/// 所有账号都处于锁定状态时，部分锁定可能是 5xx 突发导致的误判。开启后在返回"无可用账号"前，
+/// 用一个 1 token 的廉价请求测试锁定最快到期的账号，成功则解除锁定并用它处理当前请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastResortProbeConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 全局两次探测的最小间隔 (秒)，避免大量失败请求同时触发探测
+    #[serde(default = "default_probe_min_interval_seconds")]
+    pub min_interval_seconds: u64,
+
+    /// 探测使用的模型
+    #[serde(default = "default_probe_model")]
+    pub model: String,
+}
+
+impl Default for LastResortProbeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_interval_seconds: default_probe_min_interval_seconds(),
+            model: default_probe_model(),
+        }
+    }
+}
+
+/// 上游区域表
+/// 客户端通过 `X-Upstream-Region: <name>` 将单个请求固定到指定的上游端点，便于对比各区域的延迟。
+/// 指定区域的请求不会回退到其他端点，区域不可用时直接返回错误
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpstreamRegionsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 区域名 -> v1internal 基础 URL (如 https://daily-cloudcode-pa.googleapis.com/v1internal)
+    #[serde(default)]
+    pub regions: BTreeMap<String, String>,
+}
+
+fn default_probe_min_interval_seconds() -> u64 { 30 }
+
+fn default_probe_model() -> String { "gemini-2.5-flash".to_string() }
+
+/// 本地应答规则
+/// Claude Code 会把话题检测等琐碎请求发给 haiku 级模型；命中规则的请求直接在本地返回确定性结果，不消耗上游配额。
+/// 匹配阈值可配置，因为 Claude Code 的提示词会随版本变化
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalResponderConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 模型名需包含的关键字 (不区分大小写)
+    #[serde(default = "default_local_responder_model_keyword")]
+    pub model_keyword: String,
+
+    /// system + 消息文本的总字符数上限，超过则透传
+    #[serde(default = "default_local_responder_max_prompt_chars")]
+    pub max_prompt_chars: usize,
+
+    /// 识别话题检测请求的关键字 (出现在 system 或消息中)
+    #[serde(default = "default_topic_detection_markers")]
+    pub topic_detection_markers: Vec<String>,
+}
+
+impl Default for LocalResponderConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model_keyword: default_local_responder_model_keyword(),
+            max_prompt_chars: default_local_responder_max_prompt_chars(),
+            topic_detection_markers: default_topic_detection_markers(),
+        }
+    }
+}
+
+fn default_local_responder_model_keyword() -> String {
+    "haiku".to_string()
+}
+
+fn default_local_responder_max_prompt_chars() -> usize {
+    4000
+}
+
+fn default_topic_detection_markers() -> Vec<String> {
+    vec!["isNewTopic".to_string()]
+}
+
 /// 反代服务配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyConfig {
@@ -159,13 +578,21 @@ pub struct ProxyConfig {
     /// Authorization policy for the proxy.
     /// - off: no auth required
     /// - strict: auth required for all routes
-    /// - all_except_health: auth required for all routes except `/healthz`
+    /// - all_except_health: auth required for all routes except `/healthz` (`/metrics` keeps its own access policy)
     /// - auto: recommended defaults (currently: allow_lan_access => all_except_health, else off)
     #[serde(default)]
     pub auth_mode: ProxyAuthMode,
+
+    /// 按路由分组覆盖 auth_mode (未归入分组的路径使用所有分组中最严格的模式)
+    #[serde(default)]
+    pub route_auth: RouteAuthConfig,
     
     /// 监听端口
     pub port: u16,
+
+    /// 端口被占用时自动改用附近的空闲端口 (不修改已保存的端口)
+    #[serde(default)]
+    pub auto_pick_port: bool,
     
     /// API 密钥
     pub api_key: String,
@@ -174,9 +601,9 @@ pub struct ProxyConfig {
     /// 是否自动启动
     pub auto_start: bool,
 
-    /// 自定义精确模型映射表 (key: 原始模型名, value: 目标模型名)
+    /// 自定义精确模型映射表 (key: 原始模型名, value: 目标模型名或带权重的目标列表)
     #[serde(default)]
-    pub custom_mapping: std::collections::HashMap<String, String>,
+    pub custom_mapping: std::collections::HashMap<String, ModelMappingTarget>,
 
     /// API 请求超时时间(秒)
     #[serde(default = "default_request_timeout")]
@@ -190,6 +617,22 @@ pub struct ProxyConfig {
     #[serde(default)]
     pub upstream_proxy: UpstreamProxyConfig,
 
+    /// 上游连接池：每主机保留的空闲连接数 (默认 16，高并发时可调大到 64~128 以提高连接复用)
+    #[serde(default = "default_upstream_pool_max_idle_per_host")]
+    pub upstream_pool_max_idle_per_host: usize,
+
+    /// 上游连接池：空闲连接保留时间(秒，默认 90)
+    #[serde(default = "default_upstream_pool_idle_timeout_secs")]
+    pub upstream_pool_idle_timeout_secs: u64,
+
+    /// 上游连接的最低 TLS 版本 ("1.2" / "1.3")，未设置时使用 TLS 库默认值
+    #[serde(default)]
+    pub upstream_min_tls: Option<String>,
+
+    /// 监听端的 TLS (HTTPS) 配置
+    #[serde(default)]
+    pub tls: ProxyTlsConfig,
+
     /// z.ai provider configuration (Anthropic-compatible).
     #[serde(default)]
     pub zai: ZaiConfig,
@@ -201,6 +644,381 @@ pub struct ProxyConfig {
     /// 实验性功能配置
     #[serde(default)]
     pub experimental: ExperimentalConfig,
+
+    /// 模拟上游 (集成测试 / 演示用，开启后不访问网络也不使用真实账号)
+    #[serde(default)]
+    pub mock_upstream: MockUpstreamConfig,
+
+    /// 日志与响应头中的账号标识方式 (email / label / hash)，用于截图分享时隐藏邮箱
+    #[serde(default)]
+    pub log_account_identifier: AccountIdentifierMode,
+
+    /// 响应头暴露策略 (为他人提供服务时可隐藏账号信息，监控日志不受影响)
+    #[serde(default)]
+    pub response_header_policy: ResponseHeaderPolicy,
+
+    /// 诊断响应头开关与自定义响应头
+    #[serde(default)]
+    pub response_headers: ResponseHeadersConfig,
+
+    /// 监控日志中以哈希代替客户端传入的终端用户标识 (OpenAI user / Claude metadata.user_id)
+    #[serde(default)]
+    pub hash_end_user_labels: bool,
+
+    /// 监控日志中的消息内容以占位符保存 (保留模型与参数，被脱敏的记录不可重放)
+    #[serde(default)]
+    pub redact_logged_content: bool,
+
+    /// 采样参数钳制 (temperature / topP / topK 超出模型有效范围时自动修正，避免上游 400)
+    #[serde(default)]
+    pub sampling_limits: SamplingLimitsConfig,
+
+    /// Prometheus 指标端点 (`GET /metrics`)
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+
+    /// 配置引导页 (`GET /setup`)：展示接入地址与模型别名，可生成新的客户端 Key 与二维码
+    #[serde(default)]
+    pub enable_setup_page: bool,
+
+    /// 除 api_key 外可用的客户端 Key (只保存哈希)
+    #[serde(default)]
+    pub client_keys: Vec<ClientApiKey>,
+
+    /// 消息历史上限：超出时丢弃最早的消息，仅保留最近 N 条 (None 表示不限制)
+    #[serde(default)]
+    pub max_history_messages: Option<usize>,
+
+    /// 转发给上游的工具白名单 / 黑名单 (全局及按 API Key)
+    #[serde(default)]
+    pub tool_filter: ToolFilterConfig,
+
+    /// 音频转录：上传上限与长音频分段
+    #[serde(default)]
+    pub audio: AudioConfig,
+}
+
+/// 工具过滤规则 (按工具名精确匹配)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ToolFilterRule {
+    /// 非空时只转发列表中的工具
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// 始终剔除的工具
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl ToolFilterRule {
+    pub fn permits(&self, name: &str) -> bool {
+        !self.deny.iter().any(|d| d == name)
+            && (self.allow.is_empty() || self.allow.iter().any(|a| a == name))
+    }
+}
+
+/// 工具过滤策略：全局规则与请求所用 API Key 的规则同时生效
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ToolFilterConfig {
+    #[serde(default)]
+    pub global: ToolFilterRule,
+    /// API Key -> 该 Key 额外的规则
+    #[serde(default)]
+    pub per_key: std::collections::HashMap<String, ToolFilterRule>,
+}
+
+impl ToolFilterConfig {
+    pub fn permits(&self, name: &str, api_key: Option<&str>) -> bool {
+        self.global.permits(name)
+            && api_key
+                .and_then(|k| self.per_key.get(k))
+                .map_or(true, |rule| rule.permits(name))
+    }
+}
+
+/// `/metrics` 的访问方式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsAccess {
+    /// 仅允许本机 (回环地址) 访问，无需 API Key
+    Localhost,
+    /// 需要 API Key (与鉴权模式无关)
+    Auth,
+}
+
+impl Default for MetricsAccess {
+    fn default() -> Self {
+        Self::Localhost
+    }
+}
+
+/// Prometheus 指标配置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// 是否开放 `/metrics` (关闭时返回 404)
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default)]
+    pub access: MetricsAccess,
+
+    /// 额外导出按账号统计的请求数 (会在指标中出现账号标识，默认关闭)
+    #[serde(default)]
+    pub per_account_labels: bool,
+}
+
+/// 单个模型的采样参数有效范围
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SamplingLimits {
+    #[serde(default = "default_temperature_min")]
+    pub temperature_min: f64,
+    #[serde(default = "default_temperature_max")]
+    pub temperature_max: f64,
+    #[serde(default = "default_top_p_min")]
+    pub top_p_min: f64,
+    #[serde(default = "default_top_p_max")]
+    pub top_p_max: f64,
+    #[serde(default = "default_top_k_min")]
+    pub top_k_min: u32,
+    #[serde(default = "default_top_k_max")]
+    pub top_k_max: u32,
+}
+
+impl Default for SamplingLimits {
+    fn default() -> Self {
+        Self {
+            temperature_min: default_temperature_min(),
+            temperature_max: default_temperature_max(),
+            top_p_min: default_top_p_min(),
+            top_p_max: default_top_p_max(),
+            top_k_min: default_top_k_min(),
+            top_k_max: default_top_k_max(),
+        }
+    }
+}
+
+// Gemini 全系列通用的有效范围
+fn default_temperature_min() -> f64 { 0.0 }
+fn default_temperature_max() -> f64 { 2.0 }
+fn default_top_p_min() -> f64 { 0.0 }
+fn default_top_p_max() -> f64 { 1.0 }
+fn default_top_k_min() -> u32 { 1 }
+fn default_top_k_max() -> u32 { 64 }
+
+/// 采样参数钳制配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SamplingLimitsConfig {
+    /// 是否启用钳制
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 未单独配置的模型使用的范围
+    #[serde(default)]
+    pub default: SamplingLimits,
+    /// 按模型覆盖 (键为模型名，或以 `*` 结尾的前缀，如 `gemini-2.5-*`)
+    #[serde(default)]
+    pub per_model: HashMap<String, SamplingLimits>,
+}
+
+impl Default for SamplingLimitsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            default: SamplingLimits::default(),
+            per_model: HashMap::new(),
+        }
+    }
+}
+
+impl SamplingLimitsConfig {
+    /// 查找模型对应的范围：精确匹配优先，其次最长的前缀匹配，最后使用默认值
+    pub fn limits_for(&self, model: &str) -> &SamplingLimits {
+        if let Some(limits) = self.per_model.get(model) {
+            return limits;
+        }
+        self.per_model
+            .iter()
+            .filter_map(|(pattern, limits)| {
+                pattern
+                    .strip_suffix('*')
+                    .filter(|prefix| model.starts_with(prefix))
+                    .map(|prefix| (prefix.len(), limits))
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, limits)| limits)
+            .unwrap_or(&self.default)
+    }
+}
+
+/// 加权映射中的单个目标模型
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeightedModelTarget {
+    pub model: String,
+    pub weight: f64,
+}
+
+/// 自定义映射的目标：单个模型 (原有格式) 或按权重分流的模型列表 (用于灰度切换)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ModelMappingTarget {
+    Single(String),
+    Weighted(Vec<WeightedModelTarget>),
+}
+
+impl From<&str> for ModelMappingTarget {
+    fn from(model: &str) -> Self {
+        Self::Single(model.to_string())
+    }
+}
+
+impl From<String> for ModelMappingTarget {
+    fn from(model: String) -> Self {
+        Self::Single(model)
+    }
+}
+
+impl ModelMappingTarget {
+    /// 权重最高的目标 (无法按请求分流时使用，如 dry-run 与能力探测)
+    pub fn primary(&self) -> &str {
+        match self {
+            Self::Single(model) => model,
+            Self::Weighted(targets) => targets
+                .iter()
+                .filter(|t| t.weight > 0.0)
+                .max_by(|a, b| a.weight.total_cmp(&b.weight))
+                .map(|t| t.model.as_str())
+                .unwrap_or(""),
+        }
+    }
+
+    /// 校验权重：每项非负且为有限数，总和必须为正
+    pub fn validate(&self) -> Result<(), String> {
+        let Self::Weighted(targets) = self else {
+            return Ok(());
+        };
+        if targets.is_empty() {
+            return Err("加权映射至少需要一个目标模型".to_string());
+        }
+        let mut total = 0.0;
+        for target in targets {
+            if target.model.trim().is_empty() {
+                return Err("加权映射的目标模型名不能为空".to_string());
+            }
+            if !target.weight.is_finite() || target.weight < 0.0 {
+                return Err(format!("目标 {} 的权重无效: {}", target.model, target.weight));
+            }
+            total += target.weight;
+        }
+        if total <= 0.0 {
+            return Err("加权映射的权重总和必须大于 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// 音频转录 (/v1/audio/transcriptions) 配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AudioConfig {
+    /// 上传大小上限 (MB)
+    #[serde(default = "default_audio_max_upload_mb")]
+    pub max_upload_mb: u64,
+    /// 单次上游请求的音频大小 (MB)，超过时分段转录 (仅 WAV / MP3)
+    #[serde(default = "default_audio_segment_mb")]
+    pub segment_mb: u64,
+    /// 相邻片段的重叠时长 (秒)，避免切点处丢词
+    #[serde(default = "default_audio_segment_overlap_secs")]
+    pub segment_overlap_secs: u64,
+}
+
+impl AudioConfig {
+    pub fn max_upload_bytes(&self) -> u64 {
+        self.max_upload_mb.saturating_mul(1024 * 1024)
+    }
+
+    pub fn segment_bytes(&self) -> u64 {
+        self.segment_mb.max(1).saturating_mul(1024 * 1024)
+    }
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            max_upload_mb: default_audio_max_upload_mb(),
+            segment_mb: default_audio_segment_mb(),
+            segment_overlap_secs: default_audio_segment_overlap_secs(),
+        }
+    }
+}
+
+fn default_audio_max_upload_mb() -> u64 {
+    500 // 约 8 小时 128kbps MP3
+}
+
+fn default_audio_segment_mb() -> u64 {
+    12 // Base64 后约 16MB，低于上游内联数据上限
+}
+
+fn default_audio_segment_overlap_secs() -> u64 {
+    3
+}
+
+/// 模拟上游配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockUpstreamConfig {
+    /// 是否启用模拟上游
+    #[serde(default)]
+    pub enabled: bool,
+    /// 固定回复内容
+    #[serde(default = "default_mock_reply_text")]
+    pub reply_text: String,
+    /// 每次响应前的人工延迟 (毫秒)
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// 按调用顺序返回的状态码脚本，例如 [429, 500] 表示前两次调用依次失败，之后正常返回
+    #[serde(default)]
+    pub status_script: Vec<u16>,
+    /// 流式响应在发出首个分片前的额外延迟 (毫秒)，模拟长时间思考
+    #[serde(default)]
+    pub first_chunk_delay_ms: u64,
+}
+
+impl Default for MockUpstreamConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            reply_text: default_mock_reply_text(),
+            latency_ms: 0,
+            status_script: Vec::new(),
+            first_chunk_delay_ms: 0,
+        }
+    }
+}
+
+fn default_mock_reply_text() -> String {
+    "This is a mock response from Antigravity Tools.".to_string()
+}
+
+/// 反代监听的 TLS 配置
+///
+/// - `enabled = false`：仅 HTTP (默认)
+/// - `enabled = true` 且未设置 `https_port`：`port` 只接受 HTTPS
+/// - `enabled = true` 且设置了 `https_port`：`port` 继续提供 HTTP，`https_port` 提供 HTTPS (迁移期间并存)
+///
+/// 证书：`self_signed = true` 时使用数据目录下自动生成的自签名证书 (指纹见 get_proxy_status)，
+/// 否则读取 `cert_path` / `key_path` 指定的 PEM 文件
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ProxyTlsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub self_signed: bool,
+    /// 证书链 PEM 路径
+    #[serde(default)]
+    pub cert_path: Option<String>,
+    /// 私钥 PEM 路径 (PKCS#8 / PKCS#1 / SEC1)
+    #[serde(default)]
+    pub key_path: Option<String>,
+    /// 单独的 HTTPS 端口
+    #[serde(default)]
+    pub https_port: Option<u16>,
 }
 
 /// 上游代理配置
@@ -218,16 +1036,35 @@ impl Default for ProxyConfig {
             enabled: false,
             allow_lan_access: false, // 默认仅本机访问，隐私优先
             auth_mode: ProxyAuthMode::default(),
+            route_auth: RouteAuthConfig::default(),
             port: 8045,
+            auto_pick_port: false,
             api_key: format!("sk-{}", uuid::Uuid::new_v4().simple()),
             auto_start: false,
             custom_mapping: std::collections::HashMap::new(),
             request_timeout: default_request_timeout(),
             enable_logging: false, // 默认关闭，节省性能
             upstream_proxy: UpstreamProxyConfig::default(),
+            upstream_pool_max_idle_per_host: default_upstream_pool_max_idle_per_host(),
+            upstream_pool_idle_timeout_secs: default_upstream_pool_idle_timeout_secs(),
+            upstream_min_tls: None,
+            tls: ProxyTlsConfig::default(),
             zai: ZaiConfig::default(),
             scheduling: crate::proxy::sticky_config::StickySessionConfig::default(),
             experimental: ExperimentalConfig::default(),
+            mock_upstream: MockUpstreamConfig::default(),
+            log_account_identifier: AccountIdentifierMode::default(),
+            response_header_policy: ResponseHeaderPolicy::default(),
+            response_headers: ResponseHeadersConfig::default(),
+            hash_end_user_labels: false,
+            redact_logged_content: false,
+            sampling_limits: SamplingLimitsConfig::default(),
+            metrics: MetricsConfig::default(),
+            enable_setup_page: false,
+            client_keys: Vec::new(),
+            max_history_messages: None,
+            tool_filter: ToolFilterConfig::default(),
+            audio: AudioConfig::default(),
         }
     }
 }
@@ -236,6 +1073,14 @@ fn default_request_timeout() -> u64 {
     120  // 默认 120 秒,原来 60 秒太短
 }
 
+fn default_upstream_pool_max_idle_per_host() -> usize {
+    16
+}
+
+fn default_upstream_pool_idle_timeout_secs() -> u64 {
+    90
+}
+
 fn default_zai_base_url() -> String {
     "https://api.z.ai/api/anthropic".to_string()
 }
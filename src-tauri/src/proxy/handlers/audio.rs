@@ -1,5 +1,5 @@
 use axum::{
-    extract::{Multipart, State},
+    extract::{multipart::Field, Multipart, State},
     http::StatusCode,
     response::IntoResponse,
     Json,
@@ -9,22 +9,77 @@ use tracing::{debug, info};
 use uuid::Uuid;
 
 use crate::proxy::{
-    audio::AudioProcessor,
+    audio::{
+        segment,
+        spool::{AudioSpool, SpoolError},
+        AudioProcessor,
+    },
     server::AppState,
 };
 
+/// 暂存错误转换为 HTTP 错误
+fn spool_error(e: SpoolError) -> (StatusCode, String) {
+    match e {
+        SpoolError::TooLarge { limit } => (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "音频文件过大，超过上限 {} MB。可在反代设置中调大音频上传上限，或压缩音频后重试",
+                limit / (1024 * 1024)
+            ),
+        ),
+        SpoolError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+/// 整个表单 (文件与其余字段合计) 的上传字节预算；路由不设 body 上限，由此保证不超过 max_upload_mb
+struct UploadBudget {
+    limit: u64,
+    used: u64,
+}
+
+impl UploadBudget {
+    fn charge(&mut self, len: usize) -> Result<(), (StatusCode, String)> {
+        self.used += len as u64;
+        if self.used > self.limit {
+            return Err(spool_error(SpoolError::TooLarge { limit: self.limit }));
+        }
+        Ok(())
+    }
+}
+
+/// 读取一个文本字段 (计入上传预算)
+async fn read_text_field(field: &mut Field<'_>, budget: &mut UploadBudget) -> Result<String, (StatusCode, String)> {
+    let mut buf = Vec::new();
+    while let Some(chunk) = field.chunk().await.map_err(|e| {
+        (StatusCode::BAD_REQUEST, format!("读取表单字段失败: {}", e))
+    })? {
+        budget.charge(chunk.len())?;
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
 /// 处理音频转录请求 (OpenAI Whisper API 兼容)
+///
+/// 上传内容边读边写入暂存 (超过阈值转存临时文件)；超过单段大小的 WAV / MP3
+/// 切成重叠片段依次转录后拼接。暂存随请求结束或取消自动清理。
 pub async fn handle_audio_transcription(
     State(state): State<AppState>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    let mut audio_data: Option<Vec<u8>> = None;
+    let audio_config = state.audio.read().await.clone();
+    let mut spool: Option<AudioSpool> = None;
     let mut filename: Option<String> = None;
     let mut model = "gemini-2.0-flash-exp".to_string();
     let mut prompt = "Generate a transcript of the speech.".to_string();
+    let mut response_format = "json".to_string();
+    let mut budget = UploadBudget {
+        limit: audio_config.max_upload_bytes(),
+        used: 0,
+    };
 
-    // 1. 解析 multipart/form-data
-    while let Some(field) = multipart.next_field().await.map_err(|e| {
+    // 1. 解析 multipart/form-data (文件按块写入暂存，不整体缓冲)
+    while let Some(mut field) = multipart.next_field().await.map_err(|e| {
         (StatusCode::BAD_REQUEST, format!("解析表单失败: {}", e))
     })? {
         let name = field.name().unwrap_or("").to_string();
@@ -32,21 +87,32 @@ pub async fn handle_audio_transcription(
         match name.as_str() {
             "file" => {
                 filename = field.file_name().map(|s| s.to_string());
-                audio_data = Some(field.bytes().await.map_err(|e| {
+                let mut file_spool = AudioSpool::new(audio_config.max_upload_bytes());
+                while let Some(chunk) = field.chunk().await.map_err(|e| {
                     (StatusCode::BAD_REQUEST, format!("读取文件失败: {}", e))
-                })?.to_vec());
+                })? {
+                    budget.charge(chunk.len())?;
+                    file_spool.write(&chunk).await.map_err(spool_error)?;
+                }
+                spool = Some(file_spool);
             }
             "model" => {
-                model = field.text().await.unwrap_or(model);
+                model = read_text_field(&mut field, &mut budget).await?;
             }
             "prompt" => {
-                prompt = field.text().await.unwrap_or(prompt);
+                prompt = read_text_field(&mut field, &mut budget).await?;
+            }
+            "response_format" => {
+                response_format = read_text_field(&mut field, &mut budget).await?;
+            }
+            _ => {
+                // 未使用的字段同样计入预算后丢弃
+                read_text_field(&mut field, &mut budget).await?;
             }
-            _ => {}
         }
     }
 
-    let audio_bytes = audio_data.ok_or((
+    let mut spool = spool.filter(|s| !s.is_empty()).ok_or((
         StatusCode::BAD_REQUEST,
         "缺少音频文件".to_string(),
     ))?;
@@ -57,9 +123,10 @@ pub async fn handle_audio_transcription(
     ))?;
 
     info!(
-        "收到音频转录请求: 文件={}, 大小={} bytes, 模型={}",
+        "收到音频转录请求: 文件={}, 大小={} bytes ({}), 模型={}",
         file_name,
-        audio_bytes.len(),
+        spool.len(),
+        if spool.is_spilled() { "临时文件" } else { "内存" },
         model
     );
 
@@ -67,23 +134,105 @@ pub async fn handle_audio_transcription(
     let mime_type = AudioProcessor::detect_mime_type(&file_name)
         .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
 
-    // 3. 验证文件大小
-    if AudioProcessor::exceeds_size_limit(audio_bytes.len()) {
-        let size_mb = audio_bytes.len() as f64 / (1024.0 * 1024.0);
-        return Err((
+    // 3. 规划分段 (不超过单段大小时整体发送)
+    let layout = segment::probe_layout(&mut spool, &mime_type).await.map_err(spool_error)?;
+    let segments = segment::plan_segments(
+        &mut spool,
+        &layout,
+        audio_config.segment_bytes(),
+        audio_config.segment_overlap_secs,
+    )
+    .await
+    .map_err(spool_error)?
+    .ok_or_else(|| {
+        let size_mb = spool.len() as f64 / (1024.0 * 1024.0);
+        (
             StatusCode::PAYLOAD_TOO_LARGE,
             format!(
-                "音频文件过大 ({:.1} MB)。最大支持 15 MB (约 16 分钟 MP3)。建议: 1) 压缩音频质量 2) 分段上传",
-                size_mb
+                "音频文件 ({:.1} MB) 超过单段上限 {} MB，且 {} 格式无法分段。建议转换为 MP3 或 WAV 后重试",
+                size_mb, audio_config.segment_mb, mime_type
             ),
-        ));
+        )
+    })?;
+    if segments.len() > 1 {
+        info!(
+            "音频分为 {} 段依次转录 (每段不超过 {} MB，重叠 {} 秒)",
+            segments.len(),
+            audio_config.segment_mb,
+            audio_config.segment_overlap_secs
+        );
     }
 
-    // 4. 使用 Inline Data 方式
-    debug!("使用 Inline Data 方式处理");
-    let base64_audio = AudioProcessor::encode_to_base64(&audio_bytes);
+    // 4. 依次转录各片段 (每段单独编码，上一段的 Base64 在下一段编码前释放)
+    let total = segments.len();
+    let mut texts = Vec::with_capacity(total);
+    for (i, seg) in segments.iter().enumerate() {
+        let base64_audio = spool
+            .encode_base64(&seg.prefix, seg.range.clone())
+            .await
+            .map_err(spool_error)?;
+        let segment_prompt = if total > 1 {
+            format!(
+                "{}\n\n(This is part {} of {} of a longer recording. Transcribe only the speech in this part.)",
+                prompt,
+                i + 1,
+                total
+            )
+        } else {
+            prompt.clone()
+        };
+        let text = transcribe_segment(&state, &model, &segment_prompt, &mime_type, base64_audio).await?;
+        debug!(
+            "音频片段 {}/{} 转录完成: bytes {}..{}, {} 字符",
+            i + 1,
+            total,
+            seg.range.start,
+            seg.range.end,
+            text.len()
+        );
+        texts.push(text);
+    }
 
-    // 5. 构建 Gemini 请求
+    let text = segment::stitch_transcripts(&texts);
+    info!("音频转录完成，返回 {} 字符", text.len());
+
+    // 5. 返回标准格式响应 (verbose_json 附带分段边界)
+    if response_format == "verbose_json" {
+        let verbose_segments: Vec<Value> = segments
+            .iter()
+            .zip(&texts)
+            .enumerate()
+            .map(|(i, (seg, t))| {
+                json!({
+                    "id": i,
+                    "start": seg.start_secs,
+                    "end": seg.end_secs,
+                    "text": t.trim()
+                })
+            })
+            .collect();
+        return Ok(Json(json!({
+            "task": "transcribe",
+            "duration": segments.last().and_then(|s| s.end_secs),
+            "text": text,
+            "segments": verbose_segments
+        })));
+    }
+
+    Ok(Json(json!({
+        "text": text
+    })))
+}
+
+/// 转录单个片段
+async fn transcribe_segment(
+    state: &AppState,
+    model: &str,
+    prompt: &str,
+    mime_type: &str,
+    base64_audio: String,
+) -> Result<String, (StatusCode, String)> {
+    // 构建 Gemini 请求 (Inline Data 方式)
     let gemini_request = json!({
         "contents": [{
             "parts": [
@@ -98,16 +247,16 @@ pub async fn handle_audio_transcription(
         }]
     });
 
-    // 6. 获取 Token 和上游客户端
-    let token_manager = state.token_manager;
-    let (access_token, project_id, email) = token_manager
+    // 获取 Token (逐段获取，长音频转录期间可随限流切换账号)
+    let (access_token, project_id, email) = state
+        .token_manager
         .get_token("text", false, None)
         .await
         .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e))?;
 
     info!("使用账号: {}", email);
 
-    // 7. 包装请求为 v1internal 格式
+    // 包装请求为 v1internal 格式
     let wrapped_body = json!({
         "project": project_id,
         "requestId": format!("audio-{}", Uuid::new_v4()),
@@ -117,9 +266,9 @@ pub async fn handle_audio_transcription(
         "requestType": "text"
     });
 
-    // 8. 发送请求到 Gemini
-    let upstream = state.upstream.clone();
-    let response = upstream
+    // 发送请求到 Gemini
+    let response = state
+        .upstream
         .call_v1_internal("generateContent", &access_token, wrapped_body, None)
         .await
         .map_err(|e| (StatusCode::BAD_GATEWAY, format!("上游请求失败: {}", e)))?;
@@ -137,22 +286,62 @@ pub async fn handle_audio_transcription(
         .await
         .map_err(|e| (StatusCode::BAD_GATEWAY, format!("解析响应失败: {}", e)))?;
 
-    // 9. 提取文本响应（解包 v1internal 响应）
+    // 提取文本响应（解包 v1internal 响应，合并全部文本 part）
     let inner_response = result.get("response").unwrap_or(&result);
     let text = inner_response
         .get("candidates")
         .and_then(|c| c.get(0))
         .and_then(|c| c.get("content"))
         .and_then(|c| c.get("parts"))
-        .and_then(|p| p.get(0))
-        .and_then(|p| p.get("text"))
-        .and_then(|t| t.as_str())
-        .unwrap_or("");
+        .and_then(|p| p.as_array())
+        .map(|parts| {
+            parts
+                .iter()
+                .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                .collect::<String>()
+        })
+        .unwrap_or_default();
 
-    info!("音频转录完成，返回 {} 字符", text.len());
+    Ok(text)
+}
 
-    // 10. 返回标准格式响应
-    Ok(Json(json!({
-        "text": text
-    })))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::FromRequest;
+
+    async fn multipart_of(fields: &[(&str, Vec<u8>)]) -> Multipart {
+        let boundary = "ag-test-boundary";
+        let mut body = Vec::new();
+        for (name, value) in fields {
+            body.extend_from_slice(
+                format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n", boundary, name).as_bytes(),
+            );
+            body.extend_from_slice(value);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+        let request = axum::http::Request::builder()
+            .method("POST")
+            .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+            .body(axum::body::Body::from(body))
+            .unwrap();
+        Multipart::from_request(request, &()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_text_fields_count_toward_upload_limit() {
+        let mut multipart = multipart_of(&[("model", vec![b'm'; 600]), ("extra", vec![b'x'; 600])]).await;
+        let mut budget = UploadBudget { limit: 1024, used: 0 };
+
+        let mut field = multipart.next_field().await.unwrap().unwrap();
+        assert_eq!(read_text_field(&mut field, &mut budget).await.unwrap().len(), 600);
+        drop(field);
+
+        // 第二个字段使合计超过上限
+        let mut field = multipart.next_field().await.unwrap().unwrap();
+        let (status, _) = read_text_field(&mut field, &mut budget).await.unwrap_err();
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
 }
@@ -0,0 +1,527 @@
+// 单次上游尝试：转换请求、调用上游并把结果整理为响应或交给重试循环
+
+use axum::{
+    body::Body,
+    extract::Json,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::proxy::common::{model_capabilities, sse_heartbeat};
+use crate::proxy::mappers::claude::{
+    create_claude_sse_stream, perturb_for_recitation, recitation, tool_filter, transform_claude_request_in,
+    transform_response, ClaudeRequest, ClaudeResponse, RECITATION,
+};
+use crate::proxy::server::AppState;
+use crate::proxy::upstream::region::UpstreamRegion;
+
+/// 本次尝试使用的账号
+pub(crate) struct AttemptAccount {
+    pub access_token: String,
+    pub project_id: String,
+    pub email: String,
+    /// 响应头中展示的账号标识 (邮箱或标签)
+    pub account_tag: String,
+}
+
+/// RECITATION 重试状态 (跨尝试保留)
+///
+/// RECITATION 重试有独立的预算 (每个请求一次)，在同一账号上立即执行，不占用常规尝试次数
+#[derive(Default)]
+pub(crate) struct RecitationState {
+    /// 是否已因 RECITATION 重试过 (只重试一次)
+    pub retried: bool,
+    /// 本次尝试结束后需要在同一账号上扰动重试
+    pending: bool,
+    /// 被 RECITATION 截断的首次结果 (非流式客户端)，重试后与新结果比较取输出更多者
+    pub fallback: Option<RecitationFallback>,
+}
+
+/// 被 RECITATION 截断的首次结果及其来源 (重试都失败时原样返回)
+pub(crate) struct RecitationFallback {
+    pub response: ClaudeResponse,
+    pub account_tag: String,
+    pub email: String,
+    pub model: String,
+}
+
+impl RecitationState {
+    fn retry_on_same_account(&mut self) {
+        self.retried = true;
+        self.pending = true;
+    }
+
+    /// 取出待执行的 RECITATION 重试
+    pub fn take_pending_retry(&mut self) -> bool {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// 单次尝试的结果
+pub(crate) enum AttemptOutcome {
+    /// 已得到要返回给客户端的响应
+    Respond(Response),
+    /// 本次尝试失败，继续下一次；`None` 表示沿用上一次的错误信息
+    Retry(Option<String>),
+    /// 上游返回非 2xx 状态，由重试循环决定如何处理
+    UpstreamError {
+        status: StatusCode,
+        error_text: String,
+        retry_after: Option<String>,
+    },
+}
+
+/// 单次上游尝试的执行器 (请求级参数在重试间保持不变)
+pub(crate) struct AttemptExecutor<'a> {
+    pub state: &'a AppState,
+    pub trace_id: &'a str,
+    pub client_wants_stream: bool,
+    /// 被代理策略剔除的工具 (模型仍调用时以说明文本代替)
+    pub blocked_tools: &'a [String],
+    pub stream_debug: bool,
+    /// 客户端指定的上游区域 (重试期间不切换)
+    pub region: Option<&'a UpstreamRegion>,
+}
+
+impl AttemptExecutor<'_> {
+    /// 用 `account` 发送一次请求；`request.model` 应为最终的上游模型
+    pub async fn run(
+        &self,
+        account: &AttemptAccount,
+        request: &ClaudeRequest,
+        recitation: &mut RecitationState,
+        context_trimmed: Option<usize>,
+        attempt: usize,
+        max_attempts: usize,
+    ) -> AttemptOutcome {
+        let trace_id = self.trace_id;
+        let token_manager = &self.state.token_manager;
+
+        let mut gemini_body = match transform_claude_request_in(request, &account.project_id) {
+            Ok(b) => {
+                debug!("[{}] Transformed Gemini Body: {}", trace_id, serde_json::to_string_pretty(&b).unwrap_or_default());
+                b
+            },
+            Err(e) => {
+                return AttemptOutcome::Respond((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({
+                        "type": "error",
+                        "error": {
+                            "type": "api_error",
+                            "message": format!("Transform error: {}", e)
+                        }
+                    }))
+                ).into_response());
+            }
+        };
+        crate::proxy::common::sampling::clamp_sampling_params(&mut gemini_body, &*self.state.sampling_limits.read().await);
+        if recitation.retried {
+            info!("[{}] Retrying after RECITATION with perturbed request", trace_id);
+            perturb_for_recitation(&mut gemini_body, &crate::proxy::common::utils::generate_random_id());
+        }
+
+        // 上游调用 - 自动转换逻辑
+        // [AUTO-CONVERSION] 非 Stream 请求自动转换为 Stream 以享受更宽松的配额
+        let force_stream_internally = !self.client_wants_stream;
+        let actual_stream = self.client_wants_stream || force_stream_internally;
+
+        if force_stream_internally {
+            info!("[{}] 🔄 Auto-converting non-stream request to stream for better quota", trace_id);
+        }
+
+        let method = if actual_stream { "streamGenerateContent" } else { "generateContent" };
+        let query = if actual_stream { Some("alt=sse") } else { None };
+
+        let response = match self.state.upstream.call_v1_internal_at(
+            method,
+            &account.access_token,
+            gemini_body,
+            query,
+            self.region,
+        ).await {
+            Ok(r) => r,
+            Err(e) => {
+                debug!("Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
+                token_manager.mark_account_failure(&account.email);
+                return AttemptOutcome::Retry(Some(e));
+            }
+        };
+
+        let status = response.status();
+        if !status.is_success() {
+            // 立即提取 headers（防止 response 被 move），再获取错误文本
+            let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
+            let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status));
+            return AttemptOutcome::UpstreamError { status, error_text, retry_after };
+        }
+
+        // [智能限流] 请求成功，重置该账号的连续失败计数
+        token_manager.mark_account_success(&account.email);
+
+        if actual_stream {
+            self.finish_stream(response, account, request, recitation, context_trimmed).await
+        } else {
+            self.finish_json(response, account, request, context_trimmed).await
+        }
+    }
+
+    /// 处理流式响应：预读首个 chunk，空流或首块报错时交给重试循环
+    async fn finish_stream(
+        &self,
+        response: reqwest::Response,
+        account: &AttemptAccount,
+        request: &ClaudeRequest,
+        recitation: &mut RecitationState,
+        context_trimmed: Option<usize>,
+    ) -> AttemptOutcome {
+        let trace_id = self.trace_id;
+        let token_manager = &self.state.token_manager;
+        let account_header = token_manager.account_header_name();
+        let account_tag = &account.account_tag;
+
+        let stream = response.bytes_stream();
+        let gemini_stream = Box::pin(stream);
+        let (retry_malformed, recitation_policy, incremental_usage, heartbeat_secs) = {
+            let experimental = self.state.experimental.read().await;
+            (
+                experimental.retry_malformed_function_call,
+                experimental.recitation_policy,
+                experimental.emit_incremental_usage,
+                experimental.stream_heartbeat_seconds,
+            )
+        };
+        let retry_recitation = recitation::should_retry(recitation_policy, recitation.retried);
+        let claude_stream = create_claude_sse_stream(
+            gemini_stream,
+            trace_id.to_string(),
+            account_tag.clone(),
+            retry_malformed,
+            retry_recitation,
+            incremental_usage,
+            self.blocked_tools.to_vec(),
+            self.stream_debug,
+        );
+        // 心跳只对流式客户端有意义 (非流式响应收集完毕后才返回)
+        let heartbeat = if self.client_wants_stream {
+            Duration::from_secs(heartbeat_secs)
+        } else {
+            Duration::ZERO
+        };
+        let mut claude_stream = sse_heartbeat::claude(claude_stream, heartbeat);
+
+        // [FIX #530/#529] Peek first chunk to detect empty response and allow retry
+        // If the stream is empty or fails immediately, we should retry instead of sending 200 OK + empty body
+        // 首个 chunk 是心跳说明上游已静默一个心跳间隔：立即开始响应把心跳发给客户端，放弃本次的空响应重试
+        let first_chunk = claude_stream.next().await;
+
+        match first_chunk {
+            Some(Ok(bytes)) => {
+                if bytes.is_empty() {
+                    tracing::warn!("[{}] Empty first chunk received, treating as Empty Response and retrying...", trace_id);
+                    return AttemptOutcome::Retry(Some("Empty response stream (0 bytes)".to_string()));
+                }
+
+                // We have data! Construct the combined stream
+                let stream_rest = claude_stream;
+                let monitor = self.state.monitor.clone();
+                let stats_model = request.model.clone();
+                let combined_stream = Box::pin(futures::stream::once(async move { Ok(bytes) })
+                    .chain(stream_rest.map(move |result| -> Result<Bytes, std::io::Error> {
+                        match result {
+                            Ok(b) => {
+                                if recitation::is_recitation_event(&b) {
+                                    monitor.record_recitation(&stats_model);
+                                }
+                                Ok(b)
+                            }
+                            Err(e) => Ok(Bytes::from(format!("data: {{\"error\":\"{}\"}}\n\n", e))),
+                        }
+                    })));
+
+                // 判断客户端期望的格式
+                if self.client_wants_stream {
+                    // 客户端本就要 Stream，直接返回 SSE
+                    let mut resp = Response::builder()
+                        .status(StatusCode::OK)
+                        .header(header::CONTENT_TYPE, "text/event-stream")
+                        .header(header::CACHE_CONTROL, "no-cache")
+                        .header(header::CONNECTION, "keep-alive")
+                        .header(account_header, account_tag)
+                        .header("X-Mapped-Model", &request.model)
+                        .body(Body::from_stream(combined_stream))
+                        .unwrap();
+                    apply_rate_limit_headers(resp.headers_mut(), &token_manager.get_rate_limit_hints(&account.email));
+                    apply_context_trimmed_header(resp.headers_mut(), context_trimmed);
+                    apply_thinking_dropped_header(resp.headers_mut(), request);
+                    AttemptOutcome::Respond(resp)
+                } else {
+                    // 客户端要非 Stream，需要收集完整响应并转换为 JSON
+                    use crate::proxy::mappers::claude::collect_stream_to_json;
+
+                    match collect_stream_to_json(combined_stream).await {
+                        Ok(full_response) => {
+                            info!("[{}] ✓ Stream collected and converted to JSON", trace_id);
+                            let full_response = match recitation.fallback.take() {
+                                Some(first) => recitation::pick_longer(first.response, full_response),
+                                None => full_response,
+                            };
+                            if recitation::is_recitation(&full_response)
+                                && recitation::should_retry(recitation_policy, recitation.retried)
+                            {
+                                info!("[{}] Output cut off by RECITATION, retrying once on the same account", trace_id);
+                                recitation.retry_on_same_account();
+                                recitation.fallback = Some(RecitationFallback {
+                                    response: full_response,
+                                    account_tag: account_tag.clone(),
+                                    email: account.email.clone(),
+                                    model: request.model.clone(),
+                                });
+                                return AttemptOutcome::Retry(None);
+                            }
+                            let mut resp = Response::builder()
+                                .status(StatusCode::OK)
+                                .header(header::CONTENT_TYPE, "application/json")
+                                .header(account_header, account_tag)
+                                .header("X-Mapped-Model", &request.model)
+                                .body(Body::from(serde_json::to_string(&full_response).unwrap()))
+                                .unwrap();
+                            apply_rate_limit_headers(resp.headers_mut(), &token_manager.get_rate_limit_hints(&account.email));
+                            apply_context_trimmed_header(resp.headers_mut(), context_trimmed);
+                            apply_thinking_dropped_header(resp.headers_mut(), request);
+                            apply_finish_reason_header(resp.headers_mut(), &full_response);
+                            AttemptOutcome::Respond(resp)
+                        }
+                        Err(e) => {
+                            AttemptOutcome::Respond((StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)).into_response())
+                        }
+                    }
+                }
+            },
+            Some(Err(e)) => {
+                // RECITATION 仅重试一次，下次请求在同一账号上追加扰动
+                if e.contains(RECITATION) {
+                    self.state.monitor.record_recitation(&request.model);
+                    recitation.retry_on_same_account();
+                }
+                tracing::warn!("[{}] Stream error on first chunk: {}, retrying...", trace_id, e);
+                AttemptOutcome::Retry(Some(format!("Stream error: {}", e)))
+            },
+            None => {
+                tracing::warn!("[{}] Stream ended immediately (Empty Response), retrying...", trace_id);
+                AttemptOutcome::Retry(Some("Empty response stream (None)".to_string()))
+            }
+        }
+    }
+
+    /// 处理非流式响应
+    async fn finish_json(
+        &self,
+        response: reqwest::Response,
+        account: &AttemptAccount,
+        request: &ClaudeRequest,
+        context_trimmed: Option<usize>,
+    ) -> AttemptOutcome {
+        let trace_id = self.trace_id;
+        let token_manager = &self.state.token_manager;
+        let account_header = token_manager.account_header_name();
+
+        let bytes = match response.bytes().await {
+            Ok(b) => b,
+            Err(e) => return AttemptOutcome::Respond((StatusCode::BAD_GATEWAY, format!("Failed to read body: {}", e)).into_response()),
+        };
+
+        // Debug print
+        if let Ok(text) = String::from_utf8(bytes.to_vec()) {
+            debug!("Upstream Response for Claude request: {}", text);
+        }
+
+        let gemini_resp: Value = match serde_json::from_slice(&bytes) {
+            Ok(v) => v,
+            Err(e) => return AttemptOutcome::Respond((StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)).into_response()),
+        };
+
+        // 解包 response 字段（v1internal 格式）
+        let raw = gemini_resp.get("response").unwrap_or(&gemini_resp);
+
+        // 转换为 Gemini Response 结构
+        let gemini_response: crate::proxy::mappers::claude::models::GeminiResponse = match serde_json::from_value(raw.clone()) {
+            Ok(r) => r,
+            Err(e) => return AttemptOutcome::Respond((StatusCode::INTERNAL_SERVER_ERROR, format!("Convert error: {}", e)).into_response()),
+        };
+
+        // 转换
+        let mut claude_response = match transform_response(&gemini_response) {
+            Ok(r) => r,
+            Err(e) => return AttemptOutcome::Respond((StatusCode::INTERNAL_SERVER_ERROR, format!("Transform error: {}", e)).into_response()),
+        };
+        tool_filter::replace_blocked_tool_uses(&mut claude_response, self.blocked_tools);
+
+        // [Optimization] 记录闭环日志：消耗情况
+        let cache_info = if let Some(cached) = claude_response.usage.cache_read_input_tokens {
+            format!(", Cached: {}", cached)
+        } else {
+            String::new()
+        };
+
+        tracing::info!(
+            "[{}] Request finished. Model: {}, Tokens: In {}, Out {}{}",
+            trace_id,
+            request.model,
+            claude_response.usage.input_tokens,
+            claude_response.usage.output_tokens,
+            cache_info
+        );
+
+        if recitation::is_recitation(&claude_response) {
+            self.state.monitor.record_recitation(&request.model);
+        }
+        let mut resp = (StatusCode::OK, [(account_header, account.account_tag.as_str()), ("X-Mapped-Model", request.model.as_str())], Json(&claude_response)).into_response();
+        apply_rate_limit_headers(resp.headers_mut(), &token_manager.get_rate_limit_hints(&account.email));
+        apply_context_trimmed_header(resp.headers_mut(), context_trimmed);
+        apply_thinking_dropped_header(resp.headers_mut(), request);
+        apply_finish_reason_header(resp.headers_mut(), &claude_response);
+        AttemptOutcome::Respond(resp)
+    }
+}
+
+impl AttemptExecutor<'_> {
+    /// RECITATION 重试都未成功时，返回首次被截断的结果 (附带其来源账号的响应头)
+    pub fn fallback_response(&self, fallback: RecitationFallback, context_trimmed: Option<usize>) -> Response {
+        let token_manager = &self.state.token_manager;
+        let mut resp = (
+            StatusCode::OK,
+            [
+                (token_manager.account_header_name(), fallback.account_tag.as_str()),
+                ("X-Mapped-Model", fallback.model.as_str()),
+            ],
+            Json(&fallback.response),
+        )
+            .into_response();
+        apply_rate_limit_headers(resp.headers_mut(), &token_manager.get_rate_limit_hints(&fallback.email));
+        apply_context_trimmed_header(resp.headers_mut(), context_trimmed);
+        apply_finish_reason_header(resp.headers_mut(), &fallback.response);
+        resp
+    }
+}
+
+/// 在成功响应上附加速率限制头
+///
+/// 数值来自 TokenManager 内存中的配额快照，仅为估算；未知的值直接省略，
+/// 并通过 `x-antigravity-estimated: true` 标明这些不是上游的权威数据。
+/// 上游只有剩余百分比，没有请求数，因此不发送 `anthropic-ratelimit-requests-remaining`
+fn apply_rate_limit_headers(headers: &mut HeaderMap, hints: &crate::proxy::token_manager::RateLimitHints) {
+    let mut has_estimate = false;
+
+    if let Some(percent) = hints.remaining_percent {
+        headers.insert("x-antigravity-quota-remaining-percent", percent.into());
+        has_estimate = true;
+    }
+    if let Some(reset) = hints.reset_time.as_deref() {
+        if let Ok(v) = axum::http::HeaderValue::from_str(reset) {
+            headers.insert("anthropic-ratelimit-requests-reset", v);
+            has_estimate = true;
+        }
+    }
+    if let Some(pool_percent) = hints.pool_remaining_percent {
+        headers.insert("x-antigravity-pool-remaining-percent", pool_percent.into());
+        has_estimate = true;
+    }
+
+    if has_estimate {
+        headers.insert("x-antigravity-estimated", axum::http::HeaderValue::from_static("true"));
+    }
+}
+
+/// 历史被裁剪时附加 `X-Context-Trimmed: <省略的消息数>`
+fn apply_context_trimmed_header(headers: &mut HeaderMap, context_trimmed: Option<usize>) {
+    if let Some(dropped) = context_trimmed {
+        headers.insert(
+            crate::proxy::mappers::claude::context_trim::CONTEXT_TRIMMED_HEADER,
+            dropped.into(),
+        );
+    }
+}
+
+/// 请求开启了 Thinking 但上游模型不支持时附加 `X-Thinking-Dropped: true`，便于定位输出质量差异
+fn apply_thinking_dropped_header(headers: &mut HeaderMap, request: &ClaudeRequest) {
+    let thinking_enabled = request.thinking.as_ref().is_some_and(|t| t.type_ == "enabled");
+    if thinking_enabled && !model_capabilities::supports_thinking(&request.model) {
+        headers.insert(
+            model_capabilities::THINKING_DROPPED_HEADER,
+            axum::http::HeaderValue::from_static("true"),
+        );
+    }
+}
+
+pub(crate) fn apply_finish_reason_header(headers: &mut HeaderMap, response: &ClaudeResponse) {
+    if recitation::is_recitation(response) {
+        headers.insert(recitation::FINISH_REASON_HEADER, axum::http::HeaderValue::from_static(RECITATION));
+    }
+}
+
+#[cfg(test)]
+mod rate_limit_header_tests {
+    use super::*;
+    use crate::proxy::token_manager::RateLimitHints;
+
+    #[test]
+    fn test_rate_limit_headers_marked_as_estimates() {
+        let mut headers = HeaderMap::new();
+        apply_rate_limit_headers(&mut headers, &RateLimitHints {
+            remaining_percent: Some(42),
+            reset_time: Some("2026-01-01T00:00:00Z".to_string()),
+            pool_remaining_percent: Some(65),
+        });
+        // 百分比不能冒充剩余请求数
+        assert!(headers.get("anthropic-ratelimit-requests-remaining").is_none());
+        assert_eq!(headers.get("x-antigravity-quota-remaining-percent").unwrap(), "42");
+        assert_eq!(headers.get("anthropic-ratelimit-requests-reset").unwrap(), "2026-01-01T00:00:00Z");
+        assert_eq!(headers.get("x-antigravity-pool-remaining-percent").unwrap(), "65");
+        assert_eq!(headers.get("x-antigravity-estimated").unwrap(), "true");
+    }
+
+    #[test]
+    fn test_unknown_rate_limit_values_are_omitted() {
+        let mut headers = HeaderMap::new();
+        apply_rate_limit_headers(&mut headers, &RateLimitHints::default());
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn test_thinking_dropped_header_only_for_non_thinking_target() {
+        let request = |model: &str| -> ClaudeRequest {
+            serde_json::from_value(serde_json::json!({
+                "model": model,
+                "thinking": { "type": "enabled", "budget_tokens": 1024 },
+                "messages": [{ "role": "user", "content": "hi" }]
+            }))
+            .unwrap()
+        };
+
+        let mut headers = HeaderMap::new();
+        apply_thinking_dropped_header(&mut headers, &request("gemini-2.5-flash-lite"));
+        assert_eq!(headers.get(model_capabilities::THINKING_DROPPED_HEADER).unwrap(), "true");
+
+        let mut headers = HeaderMap::new();
+        apply_thinking_dropped_header(&mut headers, &request("claude-sonnet-4-5-thinking"));
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn test_context_trimmed_header_only_when_trimmed() {
+        let mut headers = HeaderMap::new();
+        apply_context_trimmed_header(&mut headers, None);
+        assert!(headers.is_empty());
+        apply_context_trimmed_header(&mut headers, Some(4));
+        assert_eq!(
+            headers.get(crate::proxy::mappers::claude::context_trim::CONTEXT_TRIMMED_HEADER).unwrap(),
+            "4"
+        );
+    }
+}
@@ -0,0 +1,172 @@
+// 分发决策：请求走 z.ai (Anthropic 透传) 还是 Google 号池
+
+use std::sync::atomic::Ordering;
+
+use axum::http::StatusCode;
+
+use crate::proxy::server::AppState;
+use crate::proxy::ZaiDispatchMode;
+
+/// 请求的处理方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Dispatch {
+    Zai,
+    Google,
+}
+
+/// 分发决策的输入 (均为请求到达时的快照)
+#[derive(Debug, Clone)]
+pub(crate) struct DispatchDecider {
+    pub zai_enabled: bool,
+    pub mode: ZaiDispatchMode,
+    /// Google 号池中的账号数
+    pub google_accounts: usize,
+    /// 未被锁定的 z.ai Key 数 (仅 Pooled 模式会用到)
+    pub zai_slots: usize,
+    /// z.ai 转发失败时是否改用 Google 号池
+    pub fallback_to_google: bool,
+}
+
+impl DispatchDecider {
+    pub async fn from_state(state: &AppState) -> Self {
+        let zai = state.zai.read().await;
+        let zai_slots = if zai.enabled && zai.dispatch_mode == ZaiDispatchMode::Pooled {
+            state.zai_keys.healthy_count(&zai.effective_keys())
+        } else {
+            0
+        };
+        Self {
+            zai_enabled: zai.enabled,
+            mode: zai.dispatch_mode.clone(),
+            google_accounts: state.token_manager.len(),
+            zai_slots,
+            fallback_to_google: zai.failure_fallback_to_google,
+        }
+    }
+
+    /// z.ai 是否参与分发 (已启用且模式不为 Off)
+    pub fn zai_active(&self) -> bool {
+        self.zai_enabled && self.mode != ZaiDispatchMode::Off
+    }
+
+    /// 决定处理方；`next_slot` 仅在 Pooled 模式下被调用，用于轮询号池槽位
+    pub fn decide(&self, next_slot: impl FnOnce() -> usize) -> Dispatch {
+        if !self.zai_active() {
+            return Dispatch::Google;
+        }
+        let use_zai = match self.mode {
+            ZaiDispatchMode::Off => false,
+            ZaiDispatchMode::Exclusive => true,
+            ZaiDispatchMode::Fallback => self.google_accounts == 0,
+            ZaiDispatchMode::Pooled => {
+                // Treat each healthy (not locked) z.ai key as one extra slot in the pool.
+                // No strict guarantees: it may get 0 requests if selection never hits.
+                let total = self.google_accounts.saturating_add(self.zai_slots);
+                if total == 0 {
+                    true
+                } else {
+                    next_slot() % total < self.zai_slots
+                }
+            }
+        };
+        if use_zai {
+            Dispatch::Zai
+        } else {
+            Dispatch::Google
+        }
+    }
+
+    /// z.ai 转发返回 `status` 后是否改用 Google 号池重试
+    /// (仅 Pooled 模式下的网络错误 / 5xx；Exclusive 模式表示只用 z.ai，
+    /// Fallback 模式只在号池为空时才会走 z.ai，没有可回退的账号)
+    pub fn should_fallback_to_google(&self, status: StatusCode, google_accounts: usize) -> bool {
+        self.fallback_to_google
+            && self.mode == ZaiDispatchMode::Pooled
+            && status.is_server_error()
+            && google_accounts > 0
+    }
+
+    /// 使用 AppState 的全局轮询计数器决定处理方
+    pub fn decide_for(&self, state: &AppState) -> Dispatch {
+        self.decide(|| state.provider_rr.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decider(enabled: bool, mode: ZaiDispatchMode, google_accounts: usize, zai_slots: usize) -> DispatchDecider {
+        DispatchDecider { zai_enabled: enabled, mode, google_accounts, zai_slots, fallback_to_google: false }
+    }
+
+    #[test]
+    fn test_disabled_or_off_always_uses_google() {
+        for mode in [ZaiDispatchMode::Off, ZaiDispatchMode::Exclusive, ZaiDispatchMode::Pooled, ZaiDispatchMode::Fallback] {
+            for google_accounts in [0, 3] {
+                let d = decider(false, mode.clone(), google_accounts, 2);
+                assert_eq!(d.decide(|| panic!("slot counter must not be touched")), Dispatch::Google);
+            }
+        }
+        for google_accounts in [0, 3] {
+            let d = decider(true, ZaiDispatchMode::Off, google_accounts, 2);
+            assert!(!d.zai_active());
+            assert_eq!(d.decide(|| panic!("slot counter must not be touched")), Dispatch::Google);
+        }
+    }
+
+    #[test]
+    fn test_exclusive_and_fallback_matrix() {
+        for google_accounts in [0, 1, 5] {
+            let d = decider(true, ZaiDispatchMode::Exclusive, google_accounts, 0);
+            assert_eq!(d.decide(|| panic!("slot counter must not be touched")), Dispatch::Zai);
+        }
+
+        let empty_pool = decider(true, ZaiDispatchMode::Fallback, 0, 0);
+        assert_eq!(empty_pool.decide(|| panic!("slot counter must not be touched")), Dispatch::Zai);
+        let with_accounts = decider(true, ZaiDispatchMode::Fallback, 2, 3);
+        assert_eq!(with_accounts.decide(|| panic!("slot counter must not be touched")), Dispatch::Google);
+    }
+
+    #[test]
+    fn test_pooled_treats_each_zai_key_as_one_slot() {
+        // 2 个 Google 账号 + 1 个 z.ai Key：槽位 0 归 z.ai，槽位 1、2 归 Google
+        let d = decider(true, ZaiDispatchMode::Pooled, 2, 1);
+        let picks: Vec<Dispatch> = (0..6).map(|slot| d.decide(|| slot)).collect();
+        assert_eq!(
+            picks,
+            vec![Dispatch::Zai, Dispatch::Google, Dispatch::Google, Dispatch::Zai, Dispatch::Google, Dispatch::Google]
+        );
+
+        // 所有 z.ai Key 均被锁定时只走 Google
+        let locked = decider(true, ZaiDispatchMode::Pooled, 2, 0);
+        assert!((0..4).all(|slot| locked.decide(|| slot) == Dispatch::Google));
+
+        // 号池与 z.ai 均为空时仍交给 z.ai (由其返回错误)，且不推进轮询计数
+        let empty = decider(true, ZaiDispatchMode::Pooled, 0, 0);
+        assert_eq!(empty.decide(|| panic!("slot counter must not be touched")), Dispatch::Zai);
+
+        // 只有 z.ai 可用
+        let zai_only = decider(true, ZaiDispatchMode::Pooled, 0, 2);
+        assert!((0..4).all(|slot| zai_only.decide(|| slot) == Dispatch::Zai));
+    }
+
+    #[test]
+    fn test_fallback_to_google_only_on_server_errors_when_enabled() {
+        let mut d = decider(true, ZaiDispatchMode::Pooled, 2, 1);
+        assert!(!d.should_fallback_to_google(StatusCode::BAD_GATEWAY, 2));
+
+        d.fallback_to_google = true;
+        assert!(d.should_fallback_to_google(StatusCode::BAD_GATEWAY, 2));
+        assert!(d.should_fallback_to_google(StatusCode::INTERNAL_SERVER_ERROR, 1));
+        // 客户端错误与限流原样返回；号池为空时无处可退
+        assert!(!d.should_fallback_to_google(StatusCode::BAD_REQUEST, 2));
+        assert!(!d.should_fallback_to_google(StatusCode::TOO_MANY_REQUESTS, 2));
+        assert!(!d.should_fallback_to_google(StatusCode::BAD_GATEWAY, 0));
+
+        d.mode = ZaiDispatchMode::Exclusive;
+        assert!(!d.should_fallback_to_google(StatusCode::BAD_GATEWAY, 2));
+        d.mode = ZaiDispatchMode::Fallback;
+        assert!(!d.should_fallback_to_google(StatusCode::BAD_GATEWAY, 2));
+    }
+}
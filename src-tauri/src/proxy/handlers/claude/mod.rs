@@ -0,0 +1,676 @@
+// Claude 协议处理器
+//
+// handle_messages 由以下阶段组成：
+// RequestSanitizer (净化) -> DispatchDecider (z.ai / Google 分发) -> ModelRouter (模型路由)
+// -> 重试循环 [账号选择 -> AttemptExecutor (单次上游尝试) -> 错误恢复与退避]
+
+mod attempt;
+mod dispatch;
+mod retry;
+mod routing;
+mod sanitize;
+#[cfg(test)]
+mod pipeline_golden;
+
+use axum::{
+    body::Body,
+    extract::{Json, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use serde_json::{json, Value};
+use tracing::{debug, error, info};
+
+use crate::proxy::common::request_body::TolerantJson;
+use crate::proxy::common::request_deadline::RequestDeadline;
+use crate::proxy::common::request_priority::RequestPriority;
+use crate::proxy::common::request_type::request_type_from_headers;
+use crate::proxy::handlers::pipeline::{select_token, RetryLoop};
+use crate::proxy::mappers::claude::{tool_filter, ClaudeRequest};
+use crate::proxy::server::AppState;
+use crate::proxy::upstream::region::UpstreamRegion;
+use axum::http::HeaderMap;
+
+use attempt::{AttemptAccount, AttemptExecutor, AttemptOutcome, RecitationState};
+use dispatch::{Dispatch, DispatchDecider};
+use retry::{apply_retry_strategy, determine_retry_strategy, is_thinking_signature_error, model_without_thinking, should_rotate_account};
+use routing::{extract_last_user_message_for_detection, ModelRouter};
+use sanitize::{strip_thinking_blocks, RequestSanitizer};
+
+/// 处理 Claude messages 请求
+/// 
+/// 处理 Chat 消息请求流程
+pub async fn handle_messages(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    region: Option<Extension<UpstreamRegion>>,
+    TolerantJson(body): TolerantJson,
+) -> Response {
+    tracing::debug!("handle_messages called. Body JSON len: {}", body.to_string().len());
+    
+    // 客户端指定的总耗时上限 (从收到请求开始计时)
+    let deadline = RequestDeadline::from_headers(&headers);
+
+    // 生成随机 Trace ID 用户追踪
+    let trace_id = crate::proxy::common::utils::generate_trace_id();
+        
+    // Decide whether this request should be handled by z.ai (Anthropic passthrough) or the existing Google flow.
+    let decider = DispatchDecider::from_state(&state).await;
+    let dispatch = decider.decide_for(&state);
+
+    // [CRITICAL REFACTOR] 优先解析并过滤 Thinking 块，确保 z.ai 也是用修复后的 Body
+    let mut request: ClaudeRequest = match serde_json::from_value(body) {
+        Ok(r) => r,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "type": "error",
+                    "error": {
+                        "type": "invalid_request_error",
+                        "message": format!("Invalid request body: {}", e)
+                    }
+                }))
+            ).into_response();
+        }
+    };
+
+    // 会话指纹取自第一条有效用户消息，必须在净化与截断历史之前计算，
+    // 否则历史超出上限后同一会话的指纹会变化，粘性调度与加权路由随之漂移
+    let session_id_str = crate::proxy::session_manager::SessionManager::extract_session_id(&request);
+
+    // 过滤 Thinking 块签名、恢复断裂的工具循环、按历史上限截断
+    let sanitizer = RequestSanitizer::from_state(&state).await;
+    let truncated = sanitizer.apply(&mut request);
+    if truncated > 0 {
+        tracing::warn!(
+            "[{}] History exceeds max_history_messages ({}), dropped {} oldest message(s)",
+            trace_id, sanitizer.max_history, truncated
+        );
+    }
+
+    // ===== [Issue #467 Fix] 拦截 Claude Code Warmup 请求 =====
+    // Claude Code 会每 10 秒发送一次 warmup 请求来保持连接热身，
+    // 这些请求会消耗大量配额。检测到 warmup 请求后直接返回模拟响应。
+    if is_warmup_request(&request) {
+        tracing::info!(
+            "[{}] 🔥 拦截 Warmup 请求，返回模拟响应（节省配额）",
+            trace_id
+        );
+        return create_warmup_response(&request, request.stream);
+    }
+
+    // 本地应答：Claude Code 的 haiku 工具类小请求 (如话题检测) 命中规则时不转发上游
+    let local_rule = {
+        let experimental = state.experimental.read().await;
+        crate::proxy::mappers::claude::local_responder::match_local_rule(&request, &experimental.local_responder)
+    };
+    if let Some(rule) = local_rule {
+        tracing::info!("[{}] Answered {} request locally ({})", trace_id, request.model, rule.as_str());
+        return create_local_text_response(
+            &request,
+            request.stream,
+            "msg_local_",
+            rule.answer(),
+            (crate::proxy::mappers::claude::local_responder::LOCALLY_ANSWERED_HEADER, "true"),
+        );
+    }
+
+    if dispatch == Dispatch::Zai {
+        // 重新序列化修复后的请求体
+        let new_body = match serde_json::to_value(&request) {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::error!("Failed to serialize fixed request for z.ai: {}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+
+        let response = crate::proxy::providers::zai_anthropic::forward_anthropic_json(
+            &state,
+            axum::http::Method::POST,
+            "/v1/messages",
+            &headers,
+            new_body,
+        )
+        .await;
+        if !decider.should_fallback_to_google(response.status(), state.token_manager.len()) {
+            return response;
+        }
+        tracing::warn!(
+            "[{}] z.ai forward failed with {}, falling back to Google pool",
+            trace_id,
+            response.status()
+        );
+        crate::modules::logger::log_warn(&format!(
+            "[Provider-Fallback] z.ai 转发失败 ({})，改用 Google 号池处理",
+            response.status()
+        ));
+    }
+    
+    // Google Flow 继续使用 request 对象
+    // (后续代码不需要再次净化)
+
+    // 获取最新一条“有意义”的消息内容（用于日志记录）
+    // 策略：反向遍历用户消息，跳过 "Warmup" 或系统预设的 reminder；
+    // 找不到时（例如纯工具调用）回退到最后一条消息的原始展示
+    let latest_msg = extract_last_user_message_for_detection(&request).unwrap_or_else(|| {
+        request.messages.last().map(|m| {
+            match &m.content {
+                crate::proxy::mappers::claude::models::MessageContent::String(s) => s.clone(),
+                crate::proxy::mappers::claude::models::MessageContent::Array(_) => "[Complex/Tool Message]".to_string()
+            }
+        }).unwrap_or_else(|| "[No Messages]".to_string())
+    });
+    
+    
+    // INFO 级别: 简洁的一行摘要
+    info!(
+        "[{}] Claude Request | Model: {} | Stream: {} | Messages: {} | Tools: {}",
+        trace_id,
+        request.model,
+        request.stream,
+        request.messages.len(),
+        request.tools.is_some()
+    );
+    
+    // DEBUG 级别: 详细的调试信息
+    debug!("========== [{}] CLAUDE REQUEST DEBUG START ==========", trace_id);
+    debug!("[{}] Model: {}", trace_id, request.model);
+    debug!("[{}] Stream: {}", trace_id, request.stream);
+    debug!("[{}] Max Tokens: {:?}", trace_id, request.max_tokens);
+    debug!("[{}] Temperature: {:?}", trace_id, request.temperature);
+    debug!("[{}] Message Count: {}", trace_id, request.messages.len());
+    debug!("[{}] Has Tools: {}", trace_id, request.tools.is_some());
+    debug!("[{}] Has Thinking Config: {}", trace_id, request.thinking.is_some());
+    debug!("[{}] Content Preview: {:.100}...", trace_id, latest_msg);
+    
+    // 输出每一条消息的详细信息
+    for (idx, msg) in request.messages.iter().enumerate() {
+        let content_preview = match &msg.content {
+            crate::proxy::mappers::claude::models::MessageContent::String(s) => {
+                let char_count = s.chars().count();
+                if char_count > 200 {
+                    // 【修复】使用 chars().take() 安全截取，避免 UTF-8 字符边界 panic
+                    let preview: String = s.chars().take(200).collect();
+                    format!("{}... (total {} chars)", preview, char_count)
+                } else {
+                    s.clone()
+                }
+            },
+            crate::proxy::mappers::claude::models::MessageContent::Array(arr) => {
+                format!("[Array with {} blocks]", arr.len())
+            }
+        };
+        debug!("[{}] Message[{}] - Role: {}, Content: {}", 
+            trace_id, idx, msg.role, content_preview);
+    }
+    
+    debug!("[{}] Full Claude Request JSON: {}", trace_id, serde_json::to_string_pretty(&request).unwrap_or_default());
+    debug!("========== [{}] CLAUDE REQUEST DEBUG END ==========", trace_id);
+
+    let mut request_for_body = request.clone();
+    let token_manager = state.token_manager.clone();
+
+    // 按工具过滤策略剔除不允许转发的工具，模型仍调用时以说明文本代替
+    let blocked_tools = {
+        let security = state.security.read().await;
+        let api_key = crate::proxy::middleware::auth::client_api_key(&headers);
+        tool_filter::strip_denied_tools(&mut request_for_body, |name| {
+            security.tool_filter.permits(name, api_key)
+        })
+    };
+    if !blocked_tools.is_empty() {
+        info!("[{}] Tools removed by proxy policy: {}", trace_id, blocked_tools.join(", "));
+    }
+    
+    let mut retry = RetryLoop::new(token_manager.len());
+    let max_attempts = retry.max_attempts();
+    let mut retried_without_thinking = false;
+
+    // 模型路由解析 (每个请求只解析一次，重试期间保持同一目标)
+    let router = ModelRouter::resolve(&state, &request_for_body.model, &session_id_str, &trace_id).await;
+
+    let executor = AttemptExecutor {
+        state: &state,
+        trace_id: &trace_id,
+        client_wants_stream: request.stream,
+        blocked_tools: &blocked_tools,
+        stream_debug: sanitizer.verbose,
+        region: region.as_deref(),
+    };
+    let priority = RequestPriority::from_headers(&headers);
+    let request_type_override = request_type_from_headers(&headers);
+
+    // RECITATION 重试状态 (只重试一次，沿用同一账号，不占用常规尝试次数)
+    let mut recitation = RecitationState::default();
+
+    // 因上下文超长裁剪掉的历史消息数 (只裁剪一次)
+    let mut context_trimmed: Option<usize> = None;
+
+    // 是否因超过客户端截止时间而提前结束重试
+    let mut deadline_exceeded = false;
+
+    for attempt in retry.attempts() {
+        if attempt > 0 && deadline.is_exceeded() {
+            deadline_exceeded = true;
+            break;
+        }
+        if attempt > 0 {
+            state.monitor.metrics.record_retry("claude");
+        }
+
+        // 将 Claude 工具转为 Value 数组以便探测联网
+        let tools_val: Option<Vec<Value>> = request_for_body.tools.as_ref().map(|list| {
+            list.iter().map(|t| serde_json::to_value(t).unwrap_or(json!({}))).collect()
+        });
+
+        let config = crate::proxy::mappers::common_utils::resolve_request_config(&request_for_body.model, router.routed_model(), &tools_val);
+
+        // 粘性调度使用请求开始时计算的会话指纹
+        let session_id = Some(session_id_str.as_str());
+
+        let force_rotate_token = attempt > 0;
+        let quota_group = token_manager.resolve_quota_group(&config.request_type, request_type_override).await;
+        let acquired = select_token(&state, &quota_group, force_rotate_token, session_id, priority).await;
+        let (access_token, project_id, email) = match acquired {
+            Ok(t) => t,
+            Err(e) => {
+                let safe_message = if e.contains("invalid_grant") {
+                    "OAuth refresh failed (invalid_grant): refresh_token likely revoked/expired; reauthorize account(s) to restore service.".to_string()
+                } else {
+                    e
+                };
+                 return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    Json(json!({
+                        "type": "error",
+                        "error": {
+                            "type": "overloaded_error",
+                            "message": format!("No available accounts: {}", safe_message)
+                        }
+                    }))
+                ).into_response();
+            }
+        };
+
+        let account_tag = token_manager.account_identifier(&email);
+        let account_header = token_manager.account_header_name();
+        retry.use_account(&account_tag);
+        info!("✓ Using account: {} (type: {})", account_tag, config.request_type);
+
+        // 后台任务降级到 Flash 模型，真实请求保持映射
+        let request_with_mapped = router.route_attempt(&request_for_body, &trace_id);
+        let account = AttemptAccount { access_token, project_id, email, account_tag };
+
+        let mut outcome = executor
+            .run(&account, &request_with_mapped, &mut recitation, context_trimmed, attempt, max_attempts)
+            .await;
+        // RECITATION 重试留在同一账号，只扰动请求
+        if recitation.take_pending_retry() && !deadline.is_exceeded() {
+            outcome = executor
+                .run(&account, &request_with_mapped, &mut recitation, context_trimmed, attempt, max_attempts)
+                .await;
+        }
+        let (status, error_text, retry_after) = match outcome {
+            AttemptOutcome::Respond(resp) => return resp,
+            AttemptOutcome::Retry(error) => {
+                if let Some(error) = error {
+                    retry.fail(error);
+                }
+                continue;
+            }
+            AttemptOutcome::UpstreamError { status, error_text, retry_after } => (status, error_text, retry_after),
+        };
+        let email = &account.email;
+        let account_tag = &account.account_tag;
+
+        let status_code = status.as_u16();
+        retry.fail(format!("HTTP {}: {}", status_code, error_text));
+        debug!("[{}] Upstream Error Response: {}", trace_id, error_text);
+
+        // [健康度] 累计跨错误类型的连续失败，达到阈值后临时停用账号
+        if crate::proxy::rate_limit::is_health_failure_status(status_code) {
+            token_manager.mark_account_failure(email);
+        }
+        
+        // 标记限流状态(用于 UI 显示) - 使用异步版本以支持实时配额刷新
+        // 🆕 传入实际使用的模型,实现模型级别限流,避免不同模型配额互相影响
+        if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 {
+            token_manager.mark_rate_limited_async(email, status_code, retry_after.as_deref(), &error_text, Some(&request_with_mapped.model)).await;
+        }
+
+        // 上下文超长：裁剪最早的历史后重试一次 (需在通用 INVALID_ARGUMENT 判断之前)
+        if status_code == 400 && context_trimmed.is_none() {
+            let experimental = state.experimental.read().await;
+            if experimental.trim_context_on_overflow
+                && crate::proxy::mappers::claude::context_trim::is_context_exceeded_error(
+                    &error_text,
+                    &experimental.context_exceeded_patterns,
+                )
+            {
+                use crate::proxy::mappers::claude::context_trim::{context_limit_for_model, trim_history_to_fit};
+                let budget = context_limit_for_model(&request_with_mapped.model)
+                    .saturating_sub(request_for_body.max_tokens.unwrap_or(0));
+                match trim_history_to_fit(&mut request_for_body, budget) {
+                    Ok(dropped) => {
+                        tracing::warn!(
+                            "[{}] Context length exceeded, trimmed {} earlier message(s) and retrying",
+                            trace_id, dropped
+                        );
+                        context_trimmed = Some(dropped);
+                        continue;
+                    }
+                    Err(e) => {
+                        tracing::warn!("[{}] Context length exceeded but trimming failed: {}", trace_id, e);
+                    }
+                }
+            }
+        }
+
+        // 处理 400 错误 (Thinking 签名失效)
+        // 由于已经主动过滤,这个错误应该很少发生
+        if status_code == 400 && !retried_without_thinking && is_thinking_signature_error(&error_text) {
+            retried_without_thinking = true;
+            
+            // 使用 WARN 级别,因为这不应该经常发生(已经主动过滤过)
+            tracing::warn!(
+                "[{}] Unexpected thinking signature error (should have been filtered). \
+                 Retrying with all thinking blocks removed.",
+                trace_id
+            );
+
+            // 完全移除所有 thinking 相关内容
+            request_for_body.thinking = None;
+            strip_thinking_blocks(&mut request_for_body.messages);
+            request_for_body.model = model_without_thinking(&request_for_body.model);
+            
+            // 使用统一退避策略
+            let strategy = determine_retry_strategy(status_code, &error_text, retried_without_thinking);
+            if apply_retry_strategy(strategy, attempt, status_code, &trace_id, &deadline).await {
+                continue;
+            }
+        }
+
+        // 统一处理所有可重试错误
+        // [REMOVED] 不再特殊处理 QUOTA_EXHAUSTED,允许账号轮换
+        // 原逻辑会在第一个账号配额耗尽时直接返回,导致"平衡"模式无法切换账号
+        let strategy = determine_retry_strategy(status_code, &error_text, retried_without_thinking);
+        let retryable = strategy.delay(attempt).is_some();
+        
+        // 执行退避
+        if apply_retry_strategy(strategy, attempt, status_code, &trace_id, &deadline).await {
+            // 判断是否需要轮换账号
+            if !should_rotate_account(status_code) {
+                debug!("[{}] Keeping same account for status {} (server-side issue)", trace_id, status_code);
+            }
+            continue;
+        } else if retryable {
+            // 可重试但退避会超过客户端截止时间：以 504 结束，而不是把上游错误当作最终结果
+            deadline_exceeded = true;
+            break;
+        } else {
+            // 不可重试的错误，直接返回
+            error!("[{}] Non-retryable error {}: {}", trace_id, status_code, error_text);
+            return (status, [(account_header, account_tag.as_str())], error_text).into_response();
+        }
+    }
+    
+    // RECITATION 重试未能成功时返回首次被截断的结果
+    if let Some(first) = recitation.fallback {
+        return executor.fallback_response(first, context_trimmed);
+    }
+
+    let (status, error_type, message) = if deadline_exceeded {
+        (
+            StatusCode::GATEWAY_TIMEOUT,
+            "api_error",
+            format!(
+                "Request deadline of {}ms exceeded. Last error: {}",
+                deadline.budget_ms().unwrap_or(0),
+                retry.last_error()
+            ),
+        )
+    } else {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            "overloaded_error",
+            format!("All {} attempts failed. Last error: {}", max_attempts, retry.last_error()),
+        )
+    };
+    let body = Json(json!({
+        "type": "error",
+        "error": {
+            "type": error_type,
+            "message": message
+        }
+    }));
+
+    if let Some(account_tag) = retry.last_account_tag() {
+        (status, [(token_manager.account_header_name(), account_tag.to_string())], body).into_response()
+    } else {
+        (status, body).into_response()
+    }
+}
+
+/// 列出可用模型
+pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoResponse {
+    use crate::proxy::common::model_mapping::get_all_dynamic_models;
+
+    let model_ids = get_all_dynamic_models(
+        &state.custom_mapping,
+    ).await;
+
+    let data: Vec<_> = model_ids.into_iter().map(|id| {
+        json!({
+            "id": id,
+            "object": "model",
+            "created": 1706745600,
+            "owned_by": "antigravity"
+        })
+    }).collect();
+
+    Json(json!({
+        "object": "list",
+        "data": data
+    }))
+}
+
+/// 计算 tokens (占位符)
+pub async fn handle_count_tokens(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    TolerantJson(body): TolerantJson,
+) -> Response {
+    if DispatchDecider::from_state(&state).await.zai_active() {
+        return crate::proxy::providers::zai_anthropic::forward_anthropic_json(
+            &state,
+            axum::http::Method::POST,
+            "/v1/messages/count_tokens",
+            &headers,
+            body,
+        )
+        .await;
+    }
+
+    Json(json!({
+        "input_tokens": 0,
+        "output_tokens": 0
+    }))
+    .into_response()
+}
+
+// 移除已失效的简单单元测试，后续将补全完整的集成测试
+/*
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_handle_list_models() {
+        // handle_list_models 现在需要 AppState，此处跳过旧的单元测试
+    }
+}
+*/
+
+// ===== [Issue #467 Fix] Warmup 请求拦截 =====
+
+/// 检测是否为 Warmup 请求
+/// 
+/// Claude Code 每 10 秒发送一次 warmup 请求，特征包括：
+/// 1. 用户消息内容以 "Warmup" 开头或包含 "Warmup"
+/// 2. tool_result 内容为 "Warmup" 错误
+/// 3. 消息循环模式：助手发送工具调用，用户返回 Warmup 错误
+fn is_warmup_request(request: &ClaudeRequest) -> bool {
+    // 检查最近的消息是否包含 Warmup 特征
+    let mut warmup_tool_result_count = 0;
+    let mut total_tool_results = 0;
+    
+    for msg in request.messages.iter().rev().take(10) {
+        match &msg.content {
+            crate::proxy::mappers::claude::models::MessageContent::String(s) => {
+                // 简单文本消息：检查是否以 Warmup 开头
+                if s.trim().starts_with("Warmup") && s.len() < 100 {
+                    return true;
+                }
+            },
+            crate::proxy::mappers::claude::models::MessageContent::Array(arr) => {
+                for block in arr {
+                    match block {
+                        // 检查 text block 是否为 Warmup
+                        crate::proxy::mappers::claude::models::ContentBlock::Text { text } => {
+                            let trimmed = text.trim();
+                            if trimmed == "Warmup" || trimmed.starts_with("Warmup\n") {
+                                return true;
+                            }
+                        },
+                        // 检查 tool_result 是否返回 Warmup 错误
+                        crate::proxy::mappers::claude::models::ContentBlock::ToolResult { 
+                            content, is_error, .. 
+                        } => {
+                            total_tool_results += 1;
+                            // content 是 serde_json::Value，需要转换为字符串检查
+                            let content_str = if let Some(s) = content.as_str() {
+                                s.to_string()
+                            } else {
+                                content.to_string()
+                            };
+                            if content_str.contains("Warmup") {
+                                warmup_tool_result_count += 1;
+                                // 如果是错误且内容为 Warmup，很可能是 warmup 请求
+                                if *is_error == Some(true) && content_str.trim().starts_with("Warmup") {
+                                    // 如果连续多个 tool_result 都是 Warmup 错误，确认为 warmup 请求
+                                    if warmup_tool_result_count >= 2 {
+                                        return true;
+                                    }
+                                }
+                            }
+                        },
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    
+    // 如果大多数 tool_result 都是 Warmup 错误，确认为 warmup 请求
+    if total_tool_results >= 3 && warmup_tool_result_count >= total_tool_results / 2 {
+        return true;
+    }
+    
+    false
+}
+
+/// 创建 Warmup 请求的模拟响应
+/// 
+/// 返回一个简单的响应，不消耗上游配额
+fn create_warmup_response(request: &ClaudeRequest, is_stream: bool) -> Response {
+    create_local_text_response(request, is_stream, "msg_warmup_", "OK", ("X-Warmup-Intercepted", "true"))
+}
+
+/// 创建由代理本地生成的单段文本响应 (流式为标准 SSE 事件序列)
+fn create_local_text_response(
+    request: &ClaudeRequest,
+    is_stream: bool,
+    id_prefix: &str,
+    text: &str,
+    marker_header: (&'static str, &'static str),
+) -> Response {
+    let model = &request.model;
+    let message_id = format!("{}{}", id_prefix, chrono::Utc::now().timestamp_millis());
+
+    if is_stream {
+        // 流式响应：发送标准的 SSE 事件序列
+        let events = [
+            ("message_start", json!({
+                "type": "message_start",
+                "message": {
+                    "id": message_id,
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": model,
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": { "input_tokens": 1, "output_tokens": 0 }
+                }
+            })),
+            ("content_block_start", json!({
+                "type": "content_block_start",
+                "index": 0,
+                "content_block": { "type": "text", "text": "" }
+            })),
+            ("content_block_delta", json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": { "type": "text_delta", "text": text }
+            })),
+            ("content_block_stop", json!({ "type": "content_block_stop", "index": 0 })),
+            ("message_delta", json!({
+                "type": "message_delta",
+                "delta": { "stop_reason": "end_turn", "stop_sequence": null },
+                "usage": { "output_tokens": 1 }
+            })),
+            ("message_stop", json!({ "type": "message_stop" })),
+        ];
+
+        let body: String = events
+            .iter()
+            .map(|(event, data)| format!("event: {}\ndata: {}\n\n", event, data))
+            .collect();
+
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .header(header::CONNECTION, "keep-alive")
+            .header(marker_header.0, marker_header.1)
+            .body(Body::from(body))
+            .unwrap()
+    } else {
+        // 非流式响应
+        let response = json!({
+            "id": message_id,
+            "type": "message",
+            "role": "assistant",
+            "content": [{
+                "type": "text",
+                "text": text
+            }],
+            "model": model,
+            "stop_reason": "end_turn",
+            "stop_sequence": null,
+            "usage": {
+                "input_tokens": 1,
+                "output_tokens": 1
+            }
+        });
+
+        (
+            StatusCode::OK,
+            [marker_header],
+            Json(response)
+        ).into_response()
+    }
+}
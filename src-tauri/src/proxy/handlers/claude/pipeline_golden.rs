@@ -0,0 +1,109 @@
+// Golden 文件测试：请求经过 RequestSanitizer 与 ModelRouter 后发往映射器的 Claude 请求
+//
+// 期望输出取自拆分前的 handlers/claude.rs 的行为 (过滤无效 thinking 块、后台任务降级、
+// 移除尾部无签名 thinking 块)，按序列化后的字节逐一比对，用于保证阶段拆分前后行为一致。
+//
+// 目录：src/proxy/tests/golden/claude_pipeline/
+// - <场景>.request.json  { "routed_model": 映射后的模型, "request": 客户端请求 }
+// - <场景>.golden.json   期望的上游请求 (serde_json::to_string_pretty 输出)
+//
+// 有意修改净化或路由逻辑后，使用 `UPDATE_GOLDENS=1 cargo test pipeline_golden` 重新生成期望输出
+
+use serde_json::Value;
+use std::path::PathBuf;
+
+use super::routing::ModelRouter;
+use super::sanitize::RequestSanitizer;
+use crate::proxy::config::{EmptyAssistantMode, ToolLoopRecoveryMode};
+use crate::proxy::mappers::claude::ClaudeRequest;
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/proxy/tests/golden/claude_pipeline")
+}
+
+fn update_goldens() -> bool {
+    std::env::var("UPDATE_GOLDENS").map(|v| v == "1").unwrap_or(false)
+}
+
+/// 与拆分前一致的默认配置：不恢复工具循环、空 assistant 补空文本、不限制历史
+fn sanitizer() -> RequestSanitizer {
+    RequestSanitizer {
+        verbose: false,
+        recovery_mode: ToolLoopRecoveryMode::Off,
+        empty_assistant: EmptyAssistantMode::EmptyText,
+        max_history: 0,
+    }
+}
+
+fn run_fixture(name: &str) -> String {
+    let path = golden_dir().join(format!("{}.request.json", name));
+    let fixture: Value = serde_json::from_str(
+        &std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("missing fixture {}.request.json: {}", name, e)),
+    )
+    .unwrap();
+    let routed_model = fixture["routed_model"].as_str().expect("routed_model");
+    let mut request: ClaudeRequest = serde_json::from_value(fixture["request"].clone()).unwrap();
+
+    sanitizer().apply(&mut request);
+    let routed = ModelRouter::new(routed_model).route_attempt(&request, "golden");
+    format!("{}\n", serde_json::to_string_pretty(&routed).unwrap())
+}
+
+fn check_golden(name: &str) {
+    let actual = run_fixture(name);
+    let golden_path = golden_dir().join(format!("{}.golden.json", name));
+    if update_goldens() {
+        std::fs::write(&golden_path, &actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&golden_path)
+        .unwrap_or_else(|e| panic!("missing golden {}.golden.json ({}), run with UPDATE_GOLDENS=1", name, e));
+    if actual != expected {
+        let line = actual
+            .lines()
+            .zip(expected.lines())
+            .position(|(a, e)| a != e)
+            .unwrap_or(actual.lines().count().min(expected.lines().count()));
+        panic!(
+            "golden mismatch for '{}' at line {}\n  actual:   {}\n  expected: {}\nrun with UPDATE_GOLDENS=1 if the change is intentional",
+            name,
+            line + 1,
+            actual.lines().nth(line).unwrap_or_default(),
+            expected.lines().nth(line).unwrap_or_default(),
+        );
+    }
+}
+
+#[test]
+fn golden_user_thinking_history() {
+    check_golden("user_thinking_history");
+}
+
+#[test]
+fn golden_background_title() {
+    check_golden("background_title");
+}
+
+#[test]
+fn golden_background_compression() {
+    check_golden("background_compression");
+}
+
+#[test]
+fn golden_tool_loop() {
+    check_golden("tool_loop");
+}
+
+/// 每个 fixture 都必须有对应的测试，避免新增场景后忘记接入
+#[test]
+fn every_fixture_is_wired() {
+    let wired = ["user_thinking_history", "background_title", "background_compression", "tool_loop"];
+    for entry in std::fs::read_dir(golden_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        let file = path.file_name().unwrap().to_str().unwrap().to_string();
+        if let Some(stem) = file.strip_suffix(".request.json") {
+            assert!(wired.contains(&stem), "fixture {} has no golden test", stem);
+        }
+    }
+}
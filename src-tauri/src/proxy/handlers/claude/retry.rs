@@ -0,0 +1,228 @@
+// 统一退避策略：按上游错误决定是否重试、等待多久、是否轮换账号
+
+use tokio::time::{sleep, Duration};
+use tracing::{debug, info};
+
+use crate::proxy::common::request_deadline::RequestDeadline;
+use crate::proxy::handlers::pipeline::MAX_RETRY_ATTEMPTS;
+
+// [REMOVED] apply_jitter function
+// Jitter logic removed to restore stability (v3.3.16 fix)
+
+/// 重试策略枚举
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum RetryStrategy {
+    /// 不重试，直接返回错误
+    NoRetry,
+    /// 固定延迟
+    FixedDelay(Duration),
+    /// 线性退避：base_ms * (attempt + 1)
+    LinearBackoff { base_ms: u64 },
+    /// 指数退避：base_ms * 2^attempt，上限 max_ms
+    ExponentialBackoff { base_ms: u64, max_ms: u64 },
+}
+
+impl RetryStrategy {
+    /// 第 `attempt` 次尝试失败后的等待时长 (NoRetry 返回 None)
+    pub fn delay(&self, attempt: usize) -> Option<Duration> {
+        match self {
+            RetryStrategy::NoRetry => None,
+            RetryStrategy::FixedDelay(duration) => Some(*duration),
+            RetryStrategy::LinearBackoff { base_ms } => {
+                Some(Duration::from_millis(base_ms * (attempt as u64 + 1)))
+            }
+            RetryStrategy::ExponentialBackoff { base_ms, max_ms } => {
+                Some(Duration::from_millis((base_ms * 2_u64.pow(attempt as u32)).min(*max_ms)))
+            }
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            RetryStrategy::NoRetry => "no retry",
+            RetryStrategy::FixedDelay(_) => "fixed delay",
+            RetryStrategy::LinearBackoff { .. } => "linear backoff",
+            RetryStrategy::ExponentialBackoff { .. } => "exponential backoff",
+        }
+    }
+}
+
+/// 根据错误状态码和错误信息确定重试策略
+pub(crate) fn determine_retry_strategy(
+    status_code: u16,
+    error_text: &str,
+    retried_without_thinking: bool,
+) -> RetryStrategy {
+    match status_code {
+        // 400 错误：Thinking 签名失败
+        400 if !retried_without_thinking
+            && (error_text.contains("Invalid `signature`")
+                || error_text.contains("thinking.signature")
+                || error_text.contains("thinking.thinking")) =>
+        {
+            // 固定 200ms 延迟后重试
+            RetryStrategy::FixedDelay(Duration::from_millis(200))
+        }
+
+        // 429 限流错误
+        429 => {
+            // 优先使用服务端返回的 Retry-After
+            if let Some(delay_ms) = crate::proxy::upstream::retry::parse_retry_delay(error_text) {
+                let actual_delay = delay_ms.saturating_add(200).min(10_000);
+                RetryStrategy::FixedDelay(Duration::from_millis(actual_delay))
+            } else {
+                // 否则使用线性退避：1s, 2s, 3s
+                RetryStrategy::LinearBackoff { base_ms: 1000 }
+            }
+        }
+
+        // 503 服务不可用 / 529 服务器过载
+        503 | 529 => {
+            // 指数退避：1s, 2s, 4s, 8s
+            RetryStrategy::ExponentialBackoff {
+                base_ms: 1000,
+                max_ms: 8000,
+            }
+        }
+
+        // 500 服务器内部错误
+        500 => {
+            // 线性退避：500ms, 1s, 1.5s
+            RetryStrategy::LinearBackoff { base_ms: 500 }
+        }
+
+        // 401/403 认证/权限错误：可重试（轮换账号）
+        401 | 403 => RetryStrategy::FixedDelay(Duration::from_millis(100)),
+
+        // 其他错误：不重试
+        _ => RetryStrategy::NoRetry,
+    }
+}
+
+/// 执行退避策略并返回是否应该继续重试
+///
+/// 退避结束时会超过客户端截止时间 (X-Max-Duration-Ms) 则不再等待，直接返回 false (调用方据此返回 504)
+pub(crate) async fn apply_retry_strategy(
+    strategy: RetryStrategy,
+    attempt: usize,
+    status_code: u16,
+    trace_id: &str,
+    deadline: &RequestDeadline,
+) -> bool {
+    let Some(delay) = strategy.delay(attempt) else {
+        debug!("[{}] Non-retryable error {}, stopping", trace_id, status_code);
+        return false;
+    };
+
+    if !deadline.allows_wait(delay) {
+        info!(
+            "[{}] Request deadline ({}ms) would be exceeded by {}ms backoff, giving up",
+            trace_id,
+            deadline.budget_ms().unwrap_or(0),
+            delay.as_millis()
+        );
+        return false;
+    }
+
+    info!(
+        "[{}] ⏱️  Retry with {}: status={}, attempt={}/{}, base={}ms",
+        trace_id,
+        strategy.label(),
+        status_code,
+        attempt + 1,
+        MAX_RETRY_ATTEMPTS,
+        delay.as_millis()
+    );
+    sleep(delay).await;
+    true
+}
+
+/// 判断是否应该轮换账号
+pub(crate) fn should_rotate_account(status_code: u16) -> bool {
+    match status_code {
+        // 这些错误是账号级别的，需要轮换
+        429 | 401 | 403 | 500 => true,
+        // 这些错误是服务端级别的，轮换账号无意义
+        400 | 503 | 529 => false,
+        // 其他错误默认不轮换
+        _ => false,
+    }
+}
+
+/// 400 错误是否由 Thinking 签名 / 结构问题引起 (可移除全部 Thinking 后重试一次)
+pub(crate) fn is_thinking_signature_error(error_text: &str) -> bool {
+    error_text.contains("Invalid `signature`")
+        || error_text.contains("thinking.signature: Field required")
+        || error_text.contains("thinking.thinking: Field required")
+        || error_text.contains("thinking.signature")
+        || error_text.contains("thinking.thinking")
+        || error_text.contains("INVALID_ARGUMENT")  // [New] Catch generic Google 400s
+        || error_text.contains("Corrupted thought signature") // [New] Explicit signature corruption
+        || error_text.contains("failed to deserialise") // [New] JSON structure issues
+}
+
+/// 清理模型名中的 -thinking 后缀 (仅 Claude 模型)
+pub(crate) fn model_without_thinking(model: &str) -> String {
+    if !model.contains("claude-") {
+        return model.to_string();
+    }
+    let m = model.replace("-thinking", "");
+    if m.contains("claude-sonnet-4-5-") {
+        "claude-sonnet-4-5".to_string()
+    } else if m.contains("claude-opus-4-5-") || m.contains("claude-opus-4-") {
+        "claude-opus-4-5".to_string()
+    } else {
+        m
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strategy_by_status() {
+        assert_eq!(
+            determine_retry_strategy(400, "Invalid `signature` in thinking block", false),
+            RetryStrategy::FixedDelay(Duration::from_millis(200))
+        );
+        assert_eq!(determine_retry_strategy(400, "Invalid `signature`", true), RetryStrategy::NoRetry);
+        assert_eq!(determine_retry_strategy(429, "slow down", false), RetryStrategy::LinearBackoff { base_ms: 1000 });
+        assert_eq!(
+            determine_retry_strategy(529, "", false),
+            RetryStrategy::ExponentialBackoff { base_ms: 1000, max_ms: 8000 }
+        );
+        assert_eq!(determine_retry_strategy(500, "", false), RetryStrategy::LinearBackoff { base_ms: 500 });
+        assert_eq!(determine_retry_strategy(403, "", false), RetryStrategy::FixedDelay(Duration::from_millis(100)));
+        assert_eq!(determine_retry_strategy(404, "", false), RetryStrategy::NoRetry);
+    }
+
+    #[test]
+    fn test_backoff_delays() {
+        assert_eq!(RetryStrategy::NoRetry.delay(0), None);
+        assert_eq!(RetryStrategy::LinearBackoff { base_ms: 500 }.delay(2), Some(Duration::from_millis(1500)));
+        let exponential = RetryStrategy::ExponentialBackoff { base_ms: 1000, max_ms: 8000 };
+        assert_eq!(exponential.delay(2), Some(Duration::from_millis(4000)));
+        assert_eq!(exponential.delay(5), Some(Duration::from_millis(8000)));
+    }
+
+    #[test]
+    fn test_account_level_errors_rotate() {
+        for status in [429, 401, 403, 500] {
+            assert!(should_rotate_account(status));
+        }
+        for status in [400, 503, 529, 404] {
+            assert!(!should_rotate_account(status));
+        }
+    }
+
+    #[test]
+    fn test_model_without_thinking() {
+        assert_eq!(model_without_thinking("claude-sonnet-4-5-thinking"), "claude-sonnet-4-5");
+        assert_eq!(model_without_thinking("claude-opus-4-5-thinking"), "claude-opus-4-5");
+        assert_eq!(model_without_thinking("claude-sonnet-4-5-20250929"), "claude-sonnet-4-5");
+        assert_eq!(model_without_thinking("gemini-2.5-pro-thinking"), "gemini-2.5-pro-thinking");
+        assert!(is_thinking_signature_error("400 INVALID_ARGUMENT"));
+        assert!(!is_thinking_signature_error("quota exceeded"));
+    }
+}
@@ -0,0 +1,299 @@
+// 模型路由：请求级的映射解析 + 每次尝试的后台任务降级
+
+use tracing::{debug, info};
+
+use super::sanitize::{remove_trailing_unsigned_thinking_in_history, strip_thinking_blocks};
+use crate::proxy::mappers::claude::models::{ClaudeRequest, ContentBlock, MessageContent};
+use crate::proxy::server::AppState;
+
+// ===== Model Constants for Background Tasks =====
+// These can be adjusted for performance/cost optimization
+const BACKGROUND_MODEL_LITE: &str = "gemini-2.5-flash-lite";  // For simple/lightweight tasks
+const BACKGROUND_MODEL_STANDARD: &str = "gemini-2.5-flash";   // For complex background tasks
+
+/// 模型路由阶段
+#[derive(Debug, Clone)]
+pub(crate) struct ModelRouter {
+    routed_model: String,
+}
+
+impl ModelRouter {
+    pub fn new(routed_model: impl Into<String>) -> Self {
+        Self { routed_model: routed_model.into() }
+    }
+
+    /// 模型路由解析 (每个请求只解析一次，加权映射按 trace_id 取种子并按会话粘性固定，重试期间保持同一目标)
+    ///
+    /// `session_id` 需取自净化与截断之前的原始请求
+    pub async fn resolve(state: &AppState, model: &str, session_id: &str, trace_id: &str) -> Self {
+        Self::new(crate::proxy::common::model_mapping::resolve_model_route_for_request(
+            model,
+            &*state.custom_mapping.read().await,
+            Some(session_id),
+            trace_id,
+        ))
+    }
+
+    pub fn routed_model(&self) -> &str {
+        &self.routed_model
+    }
+
+    /// 生成本次尝试的上游请求 (`model` 替换为最终的上游模型)
+    ///
+    /// 后台任务 (标题、摘要等) 强制降级到 Flash 模型并移除工具与 Thinking；
+    /// 真实用户请求保持映射，只移除尾部无签名的 thinking 块
+    pub fn route_attempt(&self, request: &ClaudeRequest, trace_id: &str) -> ClaudeRequest {
+        // ===== 【优化】后台任务智能检测与降级 =====
+        // 使用新的检测系统，支持 5 大类关键词和多 Flash 模型策略
+        // 传递映射后的模型名
+        let mut routed = request.clone();
+        let mut mapped_model = self.routed_model.clone();
+
+        if let Some(task_type) = detect_background_task_type(request) {
+            // 检测到后台任务,强制降级到 Flash 模型
+            let downgrade_model = select_background_model(task_type);
+
+            info!(
+                "[{}][AUTO] 检测到后台任务 (类型: {:?}),强制降级: {} -> {}",
+                trace_id,
+                task_type,
+                mapped_model,
+                downgrade_model
+            );
+
+            // 覆盖用户自定义映射
+            mapped_model = downgrade_model.to_string();
+
+            // 后台任务净化：
+            // 1. 移除工具定义（后台任务不需要工具）
+            routed.tools = None;
+
+            // 2. 移除 Thinking 配置（Flash 模型不支持）
+            routed.thinking = None;
+
+            // 3. 清理历史消息中的 Thinking Block，防止 Invalid Argument
+            strip_thinking_blocks(&mut routed.messages);
+        } else {
+            // 真实用户请求,保持原映射
+            debug!(
+                "[{}][USER] 用户交互请求,保持映射: {}",
+                trace_id,
+                mapped_model
+            );
+
+            // 对真实请求应用额外的清理:移除尾部无签名的 thinking 块
+            remove_trailing_unsigned_thinking_in_history(&mut routed.messages);
+        }
+
+        routed.model = mapped_model;
+        routed
+    }
+}
+
+// ===== 后台任务检测辅助函数 =====
+
+/// 后台任务类型
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BackgroundTaskType {
+    TitleGeneration,      // 标题生成
+    SimpleSummary,        // 简单摘要
+    ContextCompression,   // 上下文压缩
+    PromptSuggestion,     // 提示建议
+    SystemMessage,        // 系统消息
+    EnvironmentProbe,     // 环境探测
+}
+
+/// 标题生成关键词
+const TITLE_KEYWORDS: &[&str] = &[
+    "write a 5-10 word title",
+    "Please write a 5-10 word title",
+    "Respond with the title",
+    "Generate a title for",
+    "Create a brief title",
+    "title for the conversation",
+    "conversation title",
+    "生成标题",
+    "为对话起个标题",
+];
+
+/// 摘要生成关键词
+const SUMMARY_KEYWORDS: &[&str] = &[
+    "Summarize this coding conversation",
+    "Summarize the conversation",
+    "Concise summary",
+    "in under 50 characters",
+    "compress the context",
+    "Provide a concise summary",
+    "condense the previous messages",
+    "shorten the conversation history",
+    "extract key points from",
+];
+
+/// 建议生成关键词
+const SUGGESTION_KEYWORDS: &[&str] = &[
+    "prompt suggestion generator",
+    "suggest next prompts",
+    "what should I ask next",
+    "generate follow-up questions",
+    "recommend next steps",
+    "possible next actions",
+];
+
+/// 系统消息关键词
+const SYSTEM_KEYWORDS: &[&str] = &[
+    "Warmup",
+    "<system-reminder>",
+    // Removed: "Caveat: The messages below were generated" - this is a normal Claude Desktop system prompt
+    "This is a system message",
+];
+
+/// 环境探测关键词
+const PROBE_KEYWORDS: &[&str] = &[
+    "check current directory",
+    "list available tools",
+    "verify environment",
+    "test connection",
+];
+
+/// 检测后台任务并返回任务类型
+fn detect_background_task_type(request: &ClaudeRequest) -> Option<BackgroundTaskType> {
+    let last_user_msg = extract_last_user_message_for_detection(request)?;
+    let preview = last_user_msg.chars().take(500).collect::<String>();
+
+    // 长度过滤：后台任务通常不超过 800 字符
+    if last_user_msg.len() > 800 {
+        return None;
+    }
+
+    // 按优先级匹配
+    if matches_keywords(&preview, SYSTEM_KEYWORDS) {
+        return Some(BackgroundTaskType::SystemMessage);
+    }
+
+    if matches_keywords(&preview, TITLE_KEYWORDS) {
+        return Some(BackgroundTaskType::TitleGeneration);
+    }
+
+    if matches_keywords(&preview, SUMMARY_KEYWORDS) {
+        if preview.contains("in under 50 characters") {
+            return Some(BackgroundTaskType::SimpleSummary);
+        }
+        return Some(BackgroundTaskType::ContextCompression);
+    }
+
+    if matches_keywords(&preview, SUGGESTION_KEYWORDS) {
+        return Some(BackgroundTaskType::PromptSuggestion);
+    }
+
+    if matches_keywords(&preview, PROBE_KEYWORDS) {
+        return Some(BackgroundTaskType::EnvironmentProbe);
+    }
+
+    None
+}
+
+/// 辅助函数：关键词匹配
+fn matches_keywords(text: &str, keywords: &[&str]) -> bool {
+    keywords.iter().any(|kw| text.contains(kw))
+}
+
+/// 辅助函数：提取最后一条有意义的用户消息（跳过空消息、Warmup 与 system-reminder）
+pub(crate) fn extract_last_user_message_for_detection(request: &ClaudeRequest) -> Option<String> {
+    request.messages.iter().rev()
+        .filter(|m| m.role == "user")
+        .find_map(|m| {
+            let content = match &m.content {
+                MessageContent::String(s) => s.to_string(),
+                MessageContent::Array(arr) => {
+                    arr.iter()
+                        .filter_map(|block| match block {
+                            ContentBlock::Text { text } => Some(text.as_str()),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                }
+            };
+
+            if content.trim().is_empty()
+                || content.starts_with("Warmup")
+                || content.contains("<system-reminder>")
+            {
+                None
+            } else {
+                Some(content)
+            }
+        })
+}
+
+/// 根据后台任务类型选择合适的模型
+fn select_background_model(task_type: BackgroundTaskType) -> &'static str {
+    match task_type {
+        BackgroundTaskType::TitleGeneration => BACKGROUND_MODEL_LITE,     // 极简任务
+        BackgroundTaskType::SimpleSummary => BACKGROUND_MODEL_LITE,       // 简单摘要
+        BackgroundTaskType::SystemMessage => BACKGROUND_MODEL_LITE,       // 系统消息
+        BackgroundTaskType::PromptSuggestion => BACKGROUND_MODEL_LITE,    // 建议生成
+        BackgroundTaskType::EnvironmentProbe => BACKGROUND_MODEL_LITE,    // 环境探测
+        BackgroundTaskType::ContextCompression => BACKGROUND_MODEL_STANDARD, // 复杂压缩
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(user_text: &str) -> ClaudeRequest {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "thinking": { "type": "enabled", "budget_tokens": 1024 },
+            "tools": [{ "name": "Bash", "input_schema": { "type": "object" } }],
+            "messages": [
+                { "role": "assistant", "content": [
+                    { "type": "text", "text": "earlier" },
+                    { "type": "thinking", "thinking": "", "signature": null }
+                ]},
+                { "role": "user", "content": user_text }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_user_request_keeps_routed_model() {
+        let router = ModelRouter::new("claude-sonnet-4-5-thinking");
+        let routed = router.route_attempt(&request("Refactor the parser module"), "t");
+
+        assert_eq!(routed.model, "claude-sonnet-4-5-thinking");
+        assert!(routed.tools.is_some());
+        assert!(routed.thinking.is_some());
+        // 尾部无签名 thinking 块被移除
+        let MessageContent::Array(blocks) = &routed.messages[0].content else { panic!("expected blocks") };
+        assert_eq!(blocks.len(), 1);
+    }
+
+    #[test]
+    fn test_background_task_downgraded_and_stripped() {
+        let router = ModelRouter::new("claude-opus-4-5");
+
+        let title_request = request("Please write a 5-10 word title for this chat");
+        assert_eq!(detect_background_task_type(&title_request), Some(BackgroundTaskType::TitleGeneration));
+        let title = router.route_attempt(&title_request, "t");
+        assert_eq!(title.model, BACKGROUND_MODEL_LITE);
+        assert!(title.tools.is_none());
+        assert!(title.thinking.is_none());
+
+        let compression_request = request("Summarize the conversation so far");
+        assert_eq!(detect_background_task_type(&compression_request), Some(BackgroundTaskType::ContextCompression));
+        assert_eq!(router.route_attempt(&compression_request, "t").model, BACKGROUND_MODEL_STANDARD);
+    }
+
+    #[test]
+    fn test_long_messages_are_never_background_tasks() {
+        let router = ModelRouter::new("claude-opus-4-5");
+        let long_text = format!("Summarize the conversation {}", "x".repeat(900));
+        let long_request = request(&long_text);
+        assert_eq!(detect_background_task_type(&long_request), None);
+        assert_eq!(router.route_attempt(&long_request, "t").model, "claude-opus-4-5");
+    }
+}
@@ -0,0 +1,404 @@
+// 请求净化：过滤无效 Thinking 块、恢复断裂的工具循环、裁剪超长历史
+// 在分发之前执行，z.ai 与 Google 两条路径都使用净化后的请求
+
+use std::collections::HashSet;
+use std::sync::atomic::Ordering;
+use tracing::debug;
+
+use crate::proxy::config::{EmptyAssistantMode, ToolLoopRecoveryMode};
+use crate::proxy::mappers::claude::context_trim::truncate_history;
+use crate::proxy::mappers::claude::models::{ClaudeRequest, ContentBlock, Message, MessageContent};
+use crate::proxy::mappers::claude::recover_tool_loop;
+use crate::proxy::server::AppState;
+
+const MIN_SIGNATURE_LENGTH: usize = 10;  // 最小有效签名长度
+
+/// 请求净化阶段 (配置为请求到达时的快照)
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RequestSanitizer {
+    /// 输出逐块的过滤日志 (对应 AppState.stream_debug)
+    pub verbose: bool,
+    pub recovery_mode: ToolLoopRecoveryMode,
+    /// 过滤后变为空的 assistant 消息的处理方式
+    pub empty_assistant: EmptyAssistantMode,
+    /// 消息历史上限 (0 表示不限制)
+    pub max_history: usize,
+}
+
+impl RequestSanitizer {
+    pub async fn from_state(state: &AppState) -> Self {
+        let experimental = state.experimental.read().await;
+        Self {
+            verbose: state.stream_debug.load(Ordering::Relaxed),
+            recovery_mode: experimental.effective_tool_loop_recovery_mode(),
+            empty_assistant: experimental.empty_assistant_mode,
+            max_history: state.max_history_messages.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 就地净化请求，返回因历史上限被丢弃的消息数
+    pub fn apply(&self, request: &mut ClaudeRequest) -> usize {
+        // [CRITICAL FIX] 过滤并修复 Thinking 块签名
+        filter_invalid_thinking_blocks(&mut request.messages, self.verbose, self.empty_assistant);
+
+        // [New] Recover from broken tool loops (where signatures were stripped)
+        // This prevents "Assistant message must start with thinking" errors by closing the loop
+        // with synthetic messages, or by stripping the unsigned thinking blocks (per config)
+        recover_tool_loop(&mut request.messages, self.recovery_mode);
+
+        // 消息历史上限：防止失控的 Agent 循环无限撑大上下文
+        truncate_history(&mut request.messages, self.max_history)
+    }
+}
+
+/// 检查 thinking 块是否有有效签名
+fn has_valid_signature(block: &ContentBlock) -> bool {
+    match block {
+        ContentBlock::Thinking { signature, thinking, .. } => {
+            // 空 thinking + 任意 signature = 有效 (trailing signature case)
+            if thinking.is_empty() && signature.is_some() {
+                return true;
+            }
+            // 有内容 + 足够长度的 signature = 有效
+            signature.as_ref().map_or(false, |s| s.len() >= MIN_SIGNATURE_LENGTH)
+        }
+        _ => true  // 非 thinking 块默认有效
+    }
+}
+
+/// 清理 thinking 块,只保留必要字段(移除 cache_control 等)
+fn sanitize_thinking_block(block: ContentBlock) -> ContentBlock {
+    match block {
+        ContentBlock::Thinking { thinking, signature, .. } => {
+            // 重建块,移除 cache_control 等额外字段
+            ContentBlock::Thinking {
+                thinking,
+                signature,
+                cache_control: None,
+            }
+        }
+        _ => block
+    }
+}
+
+/// 过滤消息中的无效 thinking 块 (`verbose` 对应 AppState.stream_debug)
+fn filter_invalid_thinking_blocks(messages: &mut Vec<Message>, verbose: bool, empty_assistant: EmptyAssistantMode) {
+    let mut total_filtered = 0;
+    // 过滤后变为空的消息下标 (Drop 模式下尝试移除)
+    let mut emptied = Vec::new();
+
+    for (index, msg) in messages.iter_mut().enumerate() {
+        // 只处理 assistant 消息
+        // [CRITICAL FIX] Handle 'model' role too (Google history usage)
+        if msg.role != "assistant" && msg.role != "model" {
+            continue;
+        }
+        if verbose {
+            tracing::info!("[DEBUG-FILTER] Inspecting msg with role: {}", msg.role);
+        }
+
+        if let MessageContent::Array(blocks) = &mut msg.content {
+            let original_len = blocks.len();
+
+            // 过滤并清理
+            let mut new_blocks = Vec::new();
+            for block in blocks.drain(..) {
+                if matches!(block, ContentBlock::Thinking { .. }) {
+                    if verbose {
+                        if let ContentBlock::Thinking { ref signature, .. } = block {
+                            tracing::info!("[DEBUG-FILTER] Found thinking block. Sig len: {:?}", signature.as_ref().map(|s| s.len()));
+                        }
+                    }
+
+                    // [CRITICAL FIX] Vertex AI 不认可 skip_thought_signature_validator
+                    // 必须直接删除无效的 thinking 块
+                    if has_valid_signature(&block) {
+                        new_blocks.push(sanitize_thinking_block(block));
+                    } else {
+                        // [IMPROVED] 保留内容转换为 text，而不是直接丢弃
+                        if let ContentBlock::Thinking { thinking, .. } = &block {
+                            if !thinking.is_empty() {
+                                tracing::info!(
+                                    "[Claude-Handler] Converting thinking block with invalid signature to text. \
+                                     Content length: {} chars",
+                                    thinking.len()
+                                );
+                                new_blocks.push(ContentBlock::Text { text: thinking.clone() });
+                            } else {
+                                tracing::debug!("[Claude-Handler] Dropping empty thinking block with invalid signature");
+                            }
+                        }
+                    }
+                } else {
+                    new_blocks.push(block);
+                }
+            }
+
+            *blocks = new_blocks;
+            let filtered_count = original_len - blocks.len();
+            total_filtered += filtered_count;
+
+            if blocks.is_empty() {
+                emptied.push(index);
+            }
+        }
+    }
+
+    if total_filtered > 0 {
+        debug!("Filtered {} invalid thinking block(s) from history", total_filtered);
+    }
+
+    // 从后向前处理，移除消息不影响前面的下标
+    for index in emptied.into_iter().rev() {
+        if empty_assistant == EmptyAssistantMode::Drop {
+            let before = (broken_tool_pairs(messages), role_repeats(messages));
+            let removed = messages.remove(index);
+            let after = (broken_tool_pairs(messages), role_repeats(messages));
+            if after.0 <= before.0 && after.1 <= before.1 {
+                debug!("Dropped assistant message #{} emptied by thinking filter", index);
+                continue;
+            }
+            // 移除会破坏工具调用配对或角色交替，放回原处按默认方式处理
+            tracing::warn!(
+                "Keeping emptied assistant message #{}: dropping it would break a tool_use/tool_result pair or role alternation",
+                index
+            );
+            messages.insert(index, removed);
+        }
+        // 添加一个空文本块以保持消息有效
+        if let MessageContent::Array(blocks) = &mut messages[index].content {
+            blocks.push(ContentBlock::Text {
+                text: String::new()
+            });
+        }
+    }
+}
+
+/// 统计相邻且角色相同的消息对 (model 视同 assistant)
+fn role_repeats(messages: &[Message]) -> usize {
+    let role = |m: &Message| if m.role == "model" { "assistant".to_string() } else { m.role.clone() };
+    messages.windows(2).filter(|pair| role(&pair[0]) == role(&pair[1])).count()
+}
+
+/// 统计未配对的工具调用：tool_use 未在紧随的 user 消息中得到 tool_result，
+/// 或 tool_result 找不到紧邻的上一条 assistant 消息中对应的 tool_use
+fn broken_tool_pairs(messages: &[Message]) -> usize {
+    let ids = |msg: Option<&Message>, want_use: bool| -> HashSet<String> {
+        let Some(MessageContent::Array(blocks)) = msg.map(|m| &m.content) else {
+            return HashSet::new();
+        };
+        blocks
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::ToolUse { id, .. } if want_use => Some(id.clone()),
+                ContentBlock::ToolResult { tool_use_id, .. } if !want_use => Some(tool_use_id.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+
+    let mut broken = 0;
+    for (i, msg) in messages.iter().enumerate() {
+        let is_assistant = msg.role == "assistant" || msg.role == "model";
+        if is_assistant {
+            let uses = ids(Some(msg), true);
+            let next = messages.get(i + 1).filter(|m| m.role == "user");
+            let results = ids(next, false);
+            broken += uses.iter().filter(|id| !results.contains(*id)).count();
+        } else {
+            let results = ids(Some(msg), false);
+            if results.is_empty() {
+                continue;
+            }
+            let prev = i
+                .checked_sub(1)
+                .and_then(|p| messages.get(p))
+                .filter(|m| m.role == "assistant" || m.role == "model");
+            let uses = ids(prev, true);
+            broken += results.iter().filter(|id| !uses.contains(*id)).count();
+        }
+    }
+    broken
+}
+
+/// 移除尾部的无签名 thinking 块
+fn remove_trailing_unsigned_thinking(blocks: &mut Vec<ContentBlock>) {
+    if blocks.is_empty() {
+        return;
+    }
+
+    // 从后向前扫描
+    let mut end_index = blocks.len();
+    for i in (0..blocks.len()).rev() {
+        match &blocks[i] {
+            ContentBlock::Thinking { .. } => {
+                if !has_valid_signature(&blocks[i]) {
+                    end_index = i;
+                } else {
+                    break;  // 遇到有效签名的 thinking 块,停止
+                }
+            }
+            _ => break  // 遇到非 thinking 块,停止
+        }
+    }
+
+    if end_index < blocks.len() {
+        let removed = blocks.len() - end_index;
+        blocks.truncate(end_index);
+        debug!("Removed {} trailing unsigned thinking block(s)", removed);
+    }
+}
+
+/// 移除所有 assistant 消息尾部的无签名 thinking 块 (真实用户请求在每次尝试前执行)
+pub(crate) fn remove_trailing_unsigned_thinking_in_history(messages: &mut [Message]) {
+    for msg in messages.iter_mut() {
+        if msg.role == "assistant" || msg.role == "model" {
+            if let MessageContent::Array(blocks) = &mut msg.content {
+                remove_trailing_unsigned_thinking(blocks);
+            }
+        }
+    }
+}
+
+/// 清理历史消息中的所有 Thinking / RedactedThinking 块
+pub(crate) fn strip_thinking_blocks(messages: &mut [Message]) {
+    for msg in messages.iter_mut() {
+        if let MessageContent::Array(blocks) = &mut msg.content {
+            blocks.retain(|b| !matches!(b,
+                ContentBlock::Thinking { .. } |
+                ContentBlock::RedactedThinking { .. }
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(messages: serde_json::Value) -> ClaudeRequest {
+        serde_json::from_value(json!({ "model": "claude-sonnet-4-5", "messages": messages })).unwrap()
+    }
+
+    fn sanitizer(max_history: usize) -> RequestSanitizer {
+        RequestSanitizer {
+            verbose: false,
+            recovery_mode: ToolLoopRecoveryMode::Off,
+            empty_assistant: EmptyAssistantMode::EmptyText,
+            max_history,
+        }
+    }
+
+    #[test]
+    fn test_invalid_thinking_signature_becomes_text() {
+        let mut req = request(json!([
+            { "role": "user", "content": "hi" },
+            { "role": "assistant", "content": [
+                { "type": "thinking", "thinking": "draft", "signature": "short" },
+                { "type": "thinking", "thinking": "kept", "signature": "a-long-enough-signature" },
+                { "type": "text", "text": "answer" }
+            ]}
+        ]));
+
+        assert_eq!(sanitizer(0).apply(&mut req), 0);
+
+        let MessageContent::Array(blocks) = &req.messages[1].content else { panic!("expected blocks") };
+        assert!(matches!(&blocks[0], ContentBlock::Text { text } if text == "draft"));
+        assert!(matches!(&blocks[1], ContentBlock::Thinking { thinking, .. } if thinking == "kept"));
+        assert!(matches!(&blocks[2], ContentBlock::Text { text } if text == "answer"));
+    }
+
+    #[test]
+    fn test_emptied_assistant_message_dropped_without_orphaning_tool_result() {
+        let messages = json!([
+            { "role": "user", "content": "run it" },
+            { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "toolu_1", "name": "bash", "input": {} }
+            ]},
+            { "role": "assistant", "content": [
+                { "type": "thinking", "thinking": "", "signature": null }
+            ]},
+            { "role": "user", "content": [
+                { "type": "tool_result", "tool_use_id": "toolu_1", "content": "ok" }
+            ]}
+        ]);
+
+        // 默认模式：保留消息并插入空文本
+        let mut req = request(messages.clone());
+        sanitizer(0).apply(&mut req);
+        assert_eq!(req.messages.len(), 4);
+        let MessageContent::Array(blocks) = &req.messages[2].content else { panic!("expected blocks") };
+        assert!(matches!(&blocks[..], [ContentBlock::Text { text }] if text.is_empty()));
+
+        // Drop 模式：移除空消息，tool_result 仍紧跟对应的 tool_use
+        let mut req = request(messages);
+        RequestSanitizer { empty_assistant: EmptyAssistantMode::Drop, ..sanitizer(0) }.apply(&mut req);
+        assert_eq!(req.messages.len(), 3);
+        assert_eq!(broken_tool_pairs(&req.messages), 0);
+        let MessageContent::Array(blocks) = &req.messages[1].content else { panic!("expected blocks") };
+        assert!(matches!(&blocks[0], ContentBlock::ToolUse { id, .. } if id == "toolu_1"));
+        assert_eq!(req.messages[2].role, "user");
+    }
+
+    #[test]
+    fn test_emptied_assistant_message_kept_when_drop_breaks_alternation() {
+        let mut req = request(json!([
+            { "role": "user", "content": "first" },
+            { "role": "assistant", "content": [
+                { "type": "thinking", "thinking": "", "signature": null }
+            ]},
+            { "role": "user", "content": "second" }
+        ]));
+
+        // 移除后两条 user 消息相邻，回退为空文本
+        RequestSanitizer { empty_assistant: EmptyAssistantMode::Drop, ..sanitizer(0) }.apply(&mut req);
+        assert_eq!(req.messages.len(), 3);
+        assert_eq!(role_repeats(&req.messages), 0);
+        let MessageContent::Array(blocks) = &req.messages[1].content else { panic!("expected blocks") };
+        assert!(matches!(&blocks[..], [ContentBlock::Text { text }] if text.is_empty()));
+    }
+
+    #[test]
+    fn test_history_limit_reports_dropped_messages() {
+        let mut req = request(json!([
+            { "role": "user", "content": "one" },
+            { "role": "assistant", "content": "two" },
+            { "role": "user", "content": "three" }
+        ]));
+
+        assert_eq!(sanitizer(2).apply(&mut req), 1);
+        assert_eq!(req.messages.len(), 2);
+    }
+
+    #[test]
+    fn test_strip_thinking_blocks_keeps_other_content() {
+        let mut req = request(json!([
+            { "role": "assistant", "content": [
+                { "type": "thinking", "thinking": "x", "signature": "a-long-enough-signature" },
+                { "type": "text", "text": "answer" }
+            ]}
+        ]));
+
+        strip_thinking_blocks(&mut req.messages);
+
+        let MessageContent::Array(blocks) = &req.messages[0].content else { panic!("expected blocks") };
+        assert_eq!(blocks.len(), 1);
+        assert!(matches!(&blocks[0], ContentBlock::Text { .. }));
+    }
+
+    #[test]
+    fn test_trailing_unsigned_thinking_removed() {
+        let mut req = request(json!([
+            { "role": "assistant", "content": [
+                { "type": "text", "text": "answer" },
+                { "type": "thinking", "thinking": "", "signature": null }
+            ]}
+        ]));
+
+        remove_trailing_unsigned_thinking_in_history(&mut req.messages);
+
+        let MessageContent::Array(blocks) = &req.messages[0].content else { panic!("expected blocks") };
+        assert_eq!(blocks.len(), 1);
+    }
+}
@@ -1,21 +1,27 @@
 // Gemini Handler
-use axum::{extract::State, extract::{Json, Path}, http::StatusCode, response::IntoResponse};
+use axum::{extract::State, extract::{Json, Path}, Extension, http::{HeaderMap, StatusCode}, response::IntoResponse};
 use serde_json::{json, Value};
 use tracing::{debug, error, info};
 
 use crate::proxy::mappers::gemini::{wrap_request, unwrap_response};
 use crate::proxy::server::AppState;
+use crate::proxy::upstream::region::UpstreamRegion;
+use crate::proxy::common::request_priority::RequestPriority;
+use crate::proxy::common::request_type::request_type_from_headers;
 use crate::proxy::session_manager::SessionManager;
- 
-const MAX_RETRY_ATTEMPTS: usize = 3;
+use crate::proxy::handlers::pipeline::{select_token, RetryLoop};
  
 /// 处理 generateContent 和 streamGenerateContent
 /// 路径参数: model_name, method (e.g. "gemini-pro", "generateContent")
 pub async fn handle_generate(
     State(state): State<AppState>,
     Path(model_action): Path<String>,
+    headers: HeaderMap,
+    region: Option<Extension<UpstreamRegion>>,
     Json(body): Json<Value>
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let priority = RequestPriority::from_headers(&headers);
+    let request_type_override = request_type_from_headers(&headers);
     // 解析 model:method
     let (model_name, method) = if let Some((m, action)) = model_action.rsplit_once(':') {
         (m.to_string(), action.to_string())
@@ -33,19 +39,30 @@ pub async fn handle_generate(
 
     // 2. 获取 UpstreamClient 和 TokenManager
     let upstream = state.upstream.clone();
-    let token_manager = state.token_manager;
-    let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
-    
-    let mut last_error = String::new();
-    let mut last_email: Option<String> = None;
+    let token_manager = state.token_manager.clone();
+    let mut retry = RetryLoop::new(token_manager.len());
+    let max_attempts = retry.max_attempts();
 
-    for attempt in 0..max_attempts {
-        // 3. 模型路由解析
-        let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
+    // 模型路由解析 (每个请求只解析一次，加权映射按 trace_id 取种子并按会话粘性固定，重试期间保持同一目标)
+    let trace_id = crate::proxy::common::utils::generate_trace_id();
+    let routed_model = {
+        let routing_session_id = SessionManager::extract_gemini_session_id(&body, &model_name);
+        crate::proxy::common::model_mapping::resolve_model_route_for_request(
             &model_name,
             &*state.custom_mapping.read().await,
-        );
+            Some(&routing_session_id),
+            &trace_id,
+        )
+    };
+    debug!("[{}] Gemini request routed: {} -> {}", trace_id, model_name, routed_model);
+
+    for attempt in retry.attempts() {
+        if attempt > 0 {
+            state.monitor.metrics.record_retry("gemini");
+        }
+
+        // 3. 模型路由解析
+        let mapped_model = routed_model.clone();
         // 提取 tools 列表以进行联网探测 (Gemini 风格可能是嵌套的)
         let tools_val: Option<Vec<Value>> = body.get("tools").and_then(|t| t.as_array()).map(|arr| {
             let mut flattened = Vec::new();
@@ -66,36 +83,43 @@ pub async fn handle_generate(
         let session_id = SessionManager::extract_gemini_session_id(&body, &model_name);
 
         // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
-        let (access_token, project_id, email) = match token_manager.get_token(&config.request_type, attempt > 0, Some(&session_id)).await {
+        let quota_group = token_manager.resolve_quota_group(&config.request_type, request_type_override).await;
+        let acquired = select_token(&state, &quota_group, attempt > 0, Some(&session_id), priority).await;
+        let (access_token, project_id, email) = match acquired {
             Ok(t) => t,
             Err(e) => {
                 return Err((StatusCode::SERVICE_UNAVAILABLE, format!("Token error: {}", e)));
             }
         };
 
-        last_email = Some(email.clone());
-        info!("✓ Using account: {} (type: {})", email, config.request_type);
+        let account_tag = token_manager.account_identifier(&email);
+        let account_header = token_manager.account_header_name();
+        retry.use_account(&account_tag);
+        info!("✓ Using account: {} (type: {})", account_tag, config.request_type);
 
         // 5. 包装请求 (project injection)
-        let wrapped_body = wrap_request(&body, &project_id, &mapped_model);
+        let mut wrapped_body = wrap_request(&body, &project_id, &mapped_model);
+        crate::proxy::common::sampling::clamp_sampling_params(&mut wrapped_body, &*state.sampling_limits.read().await);
 
         // 5. 上游调用
         let query_string = if is_stream { Some("alt=sse") } else { None };
         let upstream_method = if is_stream { "streamGenerateContent" } else { "generateContent" };
 
         let response = match upstream
-            .call_v1_internal(upstream_method, &access_token, wrapped_body, query_string)
+            .call_v1_internal_at(upstream_method, &access_token, wrapped_body, query_string, region.as_deref())
             .await {
                 Ok(r) => r,
                 Err(e) => {
-                    last_error = e.clone();
+                    retry.fail(e.clone());
                     debug!("Gemini Request failed on attempt {}/{}: {}", attempt + 1, max_attempts, e);
+                    token_manager.mark_account_failure(&email);
                     continue;
                 }
             };
 
         let status = response.status();
         if status.is_success() {
+            token_manager.mark_account_success(&email);
             // 6. 响应处理
             if is_stream {
                 use axum::body::Body;
@@ -164,7 +188,7 @@ pub async fn handle_generate(
                     .header("Content-Type", "text/event-stream")
                     .header("Cache-Control", "no-cache")
                     .header("Connection", "keep-alive")
-                    .header("X-Account-Email", &email)
+                    .header(account_header, &account_tag)
                     .header("X-Mapped-Model", &mapped_model)
                     .body(body)
                     .unwrap()
@@ -177,14 +201,18 @@ pub async fn handle_generate(
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
 
             let unwrapped = unwrap_response(&gemini_resp);
-            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(unwrapped)).into_response());
+            return Ok((StatusCode::OK, [(account_header, account_tag.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(unwrapped)).into_response());
         }
 
         // 处理错误并重试
         let status_code = status.as_u16();
         let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        retry.fail(format!("HTTP {}: {}", status_code, error_text));
+
+        if crate::proxy::rate_limit::is_health_failure_status(status_code) {
+            token_manager.mark_account_failure(&email);
+        }
  
         // 只有 429 (限流), 529 (过载), 503, 403 (权限) 和 401 (认证失效) 触发账号轮换
         if status_code == 429 || status_code == 529 || status_code == 503 || status_code == 500 || status_code == 403 || status_code == 401 {
@@ -193,23 +221,23 @@ pub async fn handle_generate(
 
             // 只有明确包含 "QUOTA_EXHAUSTED" 才停止，避免误判上游的频率限制提示 (如 "check quota")
             if status_code == 429 && error_text.contains("QUOTA_EXHAUSTED") {
-                error!("Gemini Quota exhausted (429) on account {} attempt {}/{}, stopping to protect pool.", email, attempt + 1, max_attempts);
-                return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
+                error!("Gemini Quota exhausted (429) on account {} attempt {}/{}, stopping to protect pool.", account_tag, attempt + 1, max_attempts);
+                return Ok((status, [(account_header, account_tag.as_str())], error_text).into_response());
             }
 
-            tracing::warn!("Gemini Upstream {} on account {} attempt {}/{}, rotating account", status_code, email, attempt + 1, max_attempts);
+            tracing::warn!("Gemini Upstream {} on account {} attempt {}/{}, rotating account", status_code, account_tag, attempt + 1, max_attempts);
             continue;
         }
  
         // 404 等由于模型配置或路径错误的 HTTP 异常，直接报错，不进行无效轮换
         error!("Gemini Upstream non-retryable error {}: {}", status_code, error_text);
-        return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
+        return Ok((status, [(account_header, account_tag.as_str())], error_text).into_response());
     }
 
-    if let Some(email) = last_email {
-        Ok((StatusCode::TOO_MANY_REQUESTS, [("X-Account-Email", email)], format!("All accounts exhausted. Last error: {}", last_error)).into_response())
+    if let Some(account_tag) = retry.last_account_tag() {
+        Ok((StatusCode::TOO_MANY_REQUESTS, [(token_manager.account_header_name(), account_tag.to_string())], format!("All accounts exhausted. Last error: {}", retry.last_error())).into_response())
     } else {
-        Ok((StatusCode::TOO_MANY_REQUESTS, format!("All accounts exhausted. Last error: {}", last_error)).into_response())
+        Ok((StatusCode::TOO_MANY_REQUESTS, format!("All accounts exhausted. Last error: {}", retry.last_error())).into_response())
     }
 }
 
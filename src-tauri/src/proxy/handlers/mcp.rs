@@ -50,9 +50,9 @@ async fn forward_mcp(
     body: Body,
 ) -> Response {
     let zai = state.zai.read().await.clone();
-    if !zai.enabled || zai.api_key.trim().is_empty() {
+    let Some(api_key) = zai.first_key().filter(|_| zai.enabled) else {
         return (StatusCode::BAD_REQUEST, "z.ai is not configured").into_response();
-    }
+    };
 
     if !zai.mcp.enabled {
         return StatusCode::NOT_FOUND.into_response();
@@ -76,7 +76,7 @@ async fn forward_mcp(
     };
 
     let mut headers = copy_passthrough_headers(&incoming_headers);
-    if let Ok(v) = HeaderValue::from_str(&format!("Bearer {}", zai.api_key)) {
+    if let Ok(v) = HeaderValue::from_str(&format!("Bearer {}", api_key)) {
         headers.insert(header::AUTHORIZATION, v);
     }
 
@@ -380,7 +380,7 @@ pub async fn handle_zai_mcp_server(
     body: Body,
 ) -> Response {
     let zai = state.zai.read().await.clone();
-    if !zai.enabled || zai.api_key.trim().is_empty() {
+    if !zai.enabled || zai.first_key().is_none() {
         return (StatusCode::BAD_REQUEST, "z.ai is not configured").into_response();
     }
     if !zai.mcp.enabled || !zai.mcp.vision_enabled {
@@ -6,6 +6,8 @@ pub mod openai;
 pub mod gemini;
 pub mod mcp;
 pub mod common;
+pub(crate) mod pipeline; // 各处理器共用的账号选择与重试循环
 pub mod audio;  // 音频转录处理器 (PR #311)
 pub mod warmup; // 预热处理器
+pub mod setup;  // 客户端引导页 (/setup)
 
@@ -1,23 +1,49 @@
 // OpenAI Handler
-use axum::{extract::Json, extract::State, http::StatusCode, response::IntoResponse};
+use axum::{body::Body, extract::Json, extract::State, Extension, http::{HeaderMap, StatusCode}, response::{IntoResponse, Response}};
 use base64::Engine as _; 
 use bytes::Bytes;
+use futures::StreamExt;
 use serde_json::{json, Value};
 use tracing::{debug, error, info}; // Import Engine trait for encode method
 
 use crate::proxy::mappers::openai::{
-    transform_openai_request, transform_openai_response, OpenAIRequest,
+    fanout, transform_openai_request, transform_openai_response, OpenAIRequest,
 };
 // use crate::proxy::upstream::client::UpstreamClient; // 通过 state 获取
+use crate::proxy::common::model_capabilities;
+use crate::proxy::common::request_body::TolerantJson;
+use crate::proxy::common::request_priority::RequestPriority;
+use crate::proxy::common::request_type::request_type_from_headers;
+use crate::proxy::common::sse_heartbeat;
+use crate::proxy::handlers::pipeline::{select_token, RetryLoop};
 use crate::proxy::server::AppState;
-
-const MAX_RETRY_ATTEMPTS: usize = 3;
+use crate::proxy::upstream::region::UpstreamRegion;
 use crate::proxy::session_manager::SessionManager;
 
+/// 请求参数不受支持时的响应 (logprobs 不可用、n 超出上限等)
+fn invalid_request_response(param: &str, message: &str) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({
+            "error": {
+                "message": message,
+                "type": "invalid_request_error",
+                "param": param,
+                "code": null
+            }
+        })),
+    )
+        .into_response()
+}
+
 pub async fn handle_chat_completions(
     State(state): State<AppState>,
-    Json(body): Json<Value>,
+    headers: HeaderMap,
+    region: Option<Extension<UpstreamRegion>>,
+    TolerantJson(body): TolerantJson,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let priority = RequestPriority::from_headers(&headers);
+    let request_type_override = request_type_from_headers(&headers);
     let mut openai_req: OpenAIRequest = serde_json::from_value(body)
         .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid request: {}", e)))?;
 
@@ -38,23 +64,121 @@ pub async fn handle_chat_completions(
             });
     }
 
-    debug!("Received OpenAI request for model: {}", openai_req.model);
+    let trace_id = crate::proxy::common::utils::generate_trace_id();
+    debug!("[{}] Received OpenAI request for model: {}", trace_id, openai_req.model);
 
-    // 1. 获取 UpstreamClient (Clone handle)
-    let upstream = state.upstream.clone();
-    let token_manager = state.token_manager;
-    let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    // 模型路由解析 (每个请求只解析一次，加权映射按 trace_id 取种子并按会话粘性固定，重试期间保持同一目标)
+    let routed_model = {
+        let routing_session_id = SessionManager::extract_openai_session_id(&openai_req);
+        crate::proxy::common::model_mapping::resolve_model_route_for_request(
+            &openai_req.model,
+            &*state.custom_mapping.read().await,
+            Some(&routing_session_id),
+            &trace_id,
+        )
+    };
 
-    let mut last_error = String::new();
-    let mut last_email: Option<String> = None;
+    let (logprobs_mode, max_completions) = {
+        let experimental = state.experimental.read().await;
+        (experimental.logprobs_mode, experimental.max_completions)
+    };
+    if let Err(message) =
+        crate::proxy::mappers::openai::logprobs::check_logprobs(&openai_req, &routed_model, logprobs_mode)
+    {
+        return Ok(invalid_request_response("logprobs", &message));
+    }
+
+    let n = openai_req.n.unwrap_or(1);
+    if n == 0 || n > max_completions {
+        return Ok(invalid_request_response(
+            "n",
+            &format!("n must be between 1 and {} (max_completions)", max_completions),
+        ));
+    }
+
+    // 目标模型不支持 candidateCount 时拆成 n 个单候选请求
+    if n > 1 && !model_capabilities::supports_candidate_count(&routed_model) {
+        return fan_out_chat_completions(&state, priority, request_type_override, region.as_deref(), openai_req, &routed_model).await;
+    }
+    chat_completions_once(&state, priority, request_type_override, region.as_deref(), &openai_req, &routed_model).await
+}
+
+/// n > 1 且目标模型不支持 candidateCount：并发发出 n 个单候选请求，合并为 n 个 choices
+async fn fan_out_chat_completions(
+    state: &AppState,
+    priority: RequestPriority,
+    request_type_override: Option<&'static str>,
+    region: Option<&UpstreamRegion>,
+    openai_req: OpenAIRequest,
+    routed_model: &str,
+) -> Result<Response, (StatusCode, String)> {
+    let n = openai_req.n.unwrap_or(1);
+    info!("[OpenAI] {} does not support candidateCount, fanning out n={} requests", routed_model, n);
+    let single = OpenAIRequest { n: None, ..openai_req };
+    let mut responses = futures::future::try_join_all(
+        (0..n).map(|_| chat_completions_once(state, priority, request_type_override, region, &single, routed_model)),
+    )
+    .await?;
+
+    // 任一子请求失败时直接返回该错误
+    if let Some(failed) = responses.iter().position(|r| !r.status().is_success()) {
+        return Ok(responses.swap_remove(failed));
+    }
+
+    let mut head = None;
+    let mut bodies = Vec::with_capacity(responses.len());
+    for response in responses {
+        let (parts, body) = response.into_parts();
+        head.get_or_insert(parts);
+        bodies.push(body);
+    }
+    let Some(mut head) = head else {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, "No completions to merge".to_string()));
+    };
+    head.headers.remove(axum::http::header::CONTENT_LENGTH);
+
+    if single.stream {
+        let streams = bodies
+            .into_iter()
+            .map(|body| Box::pin(body.into_data_stream().map(|r| r.map_err(|e| e.to_string()))) as fanout::SseStream)
+            .collect();
+        return Ok(Response::from_parts(head, Body::from_stream(fanout::merge_streams(streams))));
+    }
+
+    let mut completions = Vec::with_capacity(bodies.len());
+    for body in bodies {
+        let bytes = axum::body::to_bytes(body, usize::MAX)
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Read completion error: {}", e)))?;
+        let completion: Value = serde_json::from_slice(&bytes)
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse completion error: {}", e)))?;
+        completions.push(completion);
+    }
+    Ok(Response::from_parts(head, Body::from(fanout::merge_completions(completions).to_string())))
+}
+
+/// 单次 Chat Completions 请求 (含账号轮换重试)
+async fn chat_completions_once(
+    state: &AppState,
+    priority: RequestPriority,
+    request_type_override: Option<&'static str>,
+    region: Option<&UpstreamRegion>,
+    openai_req: &OpenAIRequest,
+    routed_model: &str,
+) -> Result<Response, (StatusCode, String)> {
+    let upstream = state.upstream.clone();
+    let token_manager = state.token_manager.clone();
+    let mut retry = RetryLoop::new(token_manager.len());
+    let max_attempts = retry.max_attempts();
+    let heartbeat_secs = state.experimental.read().await.stream_heartbeat_seconds;
+
+    for attempt in retry.attempts() {
+        if attempt > 0 {
+            state.monitor.metrics.record_retry("openai_chat");
+        }
 
-    for attempt in 0..max_attempts {
         // 2. 模型路由解析
-        let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
-            &openai_req.model,
-            &*state.custom_mapping.read().await,
-        );
+        let mapped_model = routed_model.to_string();
         // 将 OpenAI 工具转为 Value 数组以便探测联网
         let tools_val: Option<Vec<Value>> = openai_req
             .tools
@@ -67,14 +191,13 @@ pub async fn handle_chat_completions(
         );
 
         // 3. 提取 SessionId (粘性指纹)
-        let session_id = SessionManager::extract_openai_session_id(&openai_req);
+        let session_id = SessionManager::extract_openai_session_id(openai_req);
 
-        // 4. 获取 Token (使用准确的 request_type)
+        // 4. 获取 Token (使用准确的 request_type，允许请求头/配置覆盖)
         // 关键：在重试尝试 (attempt > 0) 时强制轮换账号
-        let (access_token, project_id, email) = match token_manager
-            .get_token(&config.request_type, attempt > 0, Some(&session_id))
-            .await
-        {
+        let quota_group = token_manager.resolve_quota_group(&config.request_type, request_type_override).await;
+        let acquired = select_token(state, &quota_group, attempt > 0, Some(&session_id), priority).await;
+        let (access_token, project_id, email) = match acquired {
             Ok(t) => t,
             Err(e) => {
                 return Err((
@@ -84,11 +207,14 @@ pub async fn handle_chat_completions(
             }
         };
 
-        last_email = Some(email.clone());
-        info!("✓ Using account: {} (type: {})", email, config.request_type);
+        let account_tag = token_manager.account_identifier(&email);
+        let account_header = token_manager.account_header_name();
+        retry.use_account(&account_tag);
+        info!("✓ Using account: {} (type: {})", account_tag, config.request_type);
 
         // 4. 转换请求
-        let gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model);
+        let mut gemini_body = transform_openai_request(openai_req, &project_id, &mapped_model);
+        crate::proxy::common::sampling::clamp_sampling_params(&mut gemini_body, &*state.sampling_limits.read().await);
 
         // [New] 打印转换后的报文 (Gemini Body) 供调试
         if let Ok(body_json) = serde_json::to_string_pretty(&gemini_body) {
@@ -113,33 +239,61 @@ pub async fn handle_chat_completions(
         let query_string = if actual_stream { Some("alt=sse") } else { None };
 
         let response = match upstream
-            .call_v1_internal(method, &access_token, gemini_body, query_string)
+            .call_v1_internal_at(method, &access_token, gemini_body, query_string, region)
             .await
         {
             Ok(r) => r,
             Err(e) => {
-                last_error = e.clone();
+                retry.fail(e.clone());
                 debug!(
                     "OpenAI Request failed on attempt {}/{}: {}",
                     attempt + 1,
                     max_attempts,
                     e
                 );
+                token_manager.mark_account_failure(&email);
                 continue;
             }
         };
 
         let status = response.status();
         if status.is_success() {
+            token_manager.mark_account_success(&email);
             // 5. 处理流式 vs 非流式
             if actual_stream {
                 use crate::proxy::mappers::openai::streaming::create_openai_sse_stream;
-                use axum::body::Body;
-                use axum::response::Response;
 
                 let gemini_stream = response.bytes_stream();
                 let openai_stream =
                     create_openai_sse_stream(Box::pin(gemini_stream), openai_req.model.clone());
+                // 心跳只对流式客户端有意义 (非流式响应收集完毕后才返回)
+                let heartbeat = if client_wants_stream {
+                    std::time::Duration::from_secs(heartbeat_secs)
+                } else {
+                    std::time::Duration::ZERO
+                };
+                let mut openai_stream = sse_heartbeat::openai(openai_stream, heartbeat);
+
+                // 预读首个 chunk：尚未写出任何字节前的流错误可以换号重试，之后的错误由映射器转为错误帧
+                // 首个 chunk 是心跳说明上游已静默一个心跳间隔：立即开始响应把心跳发给客户端，放弃本次的换号重试
+                let first = openai_stream.next().await;
+                let first_chunk = match first {
+                    Some(Ok(bytes)) => bytes,
+                    Some(Err(e)) => {
+                        debug!("OpenAI stream error on first chunk (attempt {}/{}): {}", attempt + 1, max_attempts, e);
+                        retry.fail(format!("Stream error: {}", e));
+                        token_manager.mark_account_failure(&email);
+                        continue;
+                    }
+                    None => {
+                        retry.fail("Empty response stream");
+                        continue;
+                    }
+                };
+                let openai_stream = Box::pin(
+                    futures::stream::once(futures::future::ready(Ok::<Bytes, String>(first_chunk)))
+                        .chain(openai_stream),
+                );
                 
                 // 判断客户端期望的格式
                 if client_wants_stream {
@@ -149,7 +303,7 @@ pub async fn handle_chat_completions(
                         .header("Content-Type", "text/event-stream")
                         .header("Cache-Control", "no-cache")
                         .header("Connection", "keep-alive")
-                        .header("X-Account-Email", &email)
+                        .header(account_header, &account_tag)
                         .header("X-Mapped-Model", &mapped_model)
                         .body(body)
                         .unwrap()
@@ -157,7 +311,6 @@ pub async fn handle_chat_completions(
                 } else {
                     // 客户端要非 Stream，需要收集完整响应并转换为 JSON
                     use crate::proxy::mappers::openai::collect_openai_stream_to_json;
-                    use futures::StreamExt;
                     
                     // 转换为 io::Error stream
                     let sse_stream = openai_stream.map(|result| -> Result<Bytes, std::io::Error> {
@@ -170,7 +323,7 @@ pub async fn handle_chat_completions(
                     match collect_openai_stream_to_json(sse_stream).await {
                         Ok(full_response) => {
                             info!("[OpenAI] ✓ Stream collected and converted to JSON");
-                            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(full_response)).into_response());
+                            return Ok((StatusCode::OK, [(account_header, account_tag.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(full_response)).into_response());
                         }
                         Err(e) => {
                             return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Stream collection error: {}", e)));
@@ -185,14 +338,18 @@ pub async fn handle_chat_completions(
                 .map_err(|e| (StatusCode::BAD_GATEWAY, format!("Parse error: {}", e)))?;
 
             let openai_response = transform_openai_response(&gemini_resp);
-            return Ok((StatusCode::OK, [("X-Account-Email", email.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(openai_response)).into_response());
+            return Ok((StatusCode::OK, [(account_header, account_tag.as_str()), ("X-Mapped-Model", mapped_model.as_str())], Json(openai_response)).into_response());
         }
 
         // 处理特定错误并重试
         let status_code = status.as_u16();
         let retry_after = response.headers().get("Retry-After").and_then(|h| h.to_str().ok()).map(|s| s.to_string());
         let error_text = response.text().await.unwrap_or_else(|_| format!("HTTP {}", status_code));
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        retry.fail(format!("HTTP {}: {}", status_code, error_text));
+
+        if crate::proxy::rate_limit::is_health_failure_status(status_code) {
+            token_manager.mark_account_failure(&email);
+        }
 
         // [New] 打印错误报文日志
         tracing::error!(
@@ -212,7 +369,7 @@ pub async fn handle_chat_completions(
                 tracing::warn!(
                     "OpenAI Upstream {} on {} attempt {}/{}, waiting {}ms then retrying",
                     status_code,
-                    email,
+                    account_tag,
                     attempt + 1,
                     max_attempts,
                     actual_delay
@@ -225,18 +382,18 @@ pub async fn handle_chat_completions(
             if error_text.contains("QUOTA_EXHAUSTED") {
                 error!(
                     "OpenAI Quota exhausted (429) on account {} attempt {}/{}, stopping to protect pool.",
-                    email,
+                    account_tag,
                     attempt + 1,
                     max_attempts
                 );
-                return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
+                return Ok((status, [(account_header, account_tag.as_str())], error_text).into_response());
             }
 
             // 3. 其他限流或服务器过载情况，轮换账号
             tracing::warn!(
                 "OpenAI Upstream {} on {} attempt {}/{}, rotating account",
                 status_code,
-                email,
+                account_tag,
                 attempt + 1,
                 max_attempts
             );
@@ -248,7 +405,7 @@ pub async fn handle_chat_completions(
             tracing::warn!(
                 "OpenAI Upstream {} on account {} attempt {}/{}, rotating account",
                 status_code,
-                email,
+                account_tag,
                 attempt + 1,
                 max_attempts
             );
@@ -258,22 +415,22 @@ pub async fn handle_chat_completions(
         // 404 等由于模型配置或路径错误的 HTTP 异常，直接报错，不进行无效轮换
         error!(
             "OpenAI Upstream non-retryable error {} on account {}: {}",
-            status_code, email, error_text
+            status_code, account_tag, error_text
         );
-        return Ok((status, [("X-Account-Email", email.as_str())], error_text).into_response());
+        return Ok((status, [(account_header, account_tag.as_str())], error_text).into_response());
     }
 
     // 所有尝试均失败
-    if let Some(email) = last_email {
+    if let Some(account_tag) = retry.last_account_tag() {
         Ok((
             StatusCode::TOO_MANY_REQUESTS,
-            [("X-Account-Email", email)],
-            format!("All accounts exhausted. Last error: {}", last_error),
+            [(token_manager.account_header_name(), account_tag.to_string())],
+            format!("All accounts exhausted. Last error: {}", retry.last_error()),
         ).into_response())
     } else {
         Ok((
             StatusCode::TOO_MANY_REQUESTS,
-            format!("All accounts exhausted. Last error: {}", last_error),
+            format!("All accounts exhausted. Last error: {}", retry.last_error()),
         ).into_response())
     }
 }
@@ -282,8 +439,12 @@ pub async fn handle_chat_completions(
 /// 将 Prompt 转换为 Chat Message 格式，复用 handle_chat_completions
 pub async fn handle_completions(
     State(state): State<AppState>,
-    Json(mut body): Json<Value>,
+    headers: HeaderMap,
+    region: Option<Extension<UpstreamRegion>>,
+    TolerantJson(mut body): TolerantJson,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let priority = RequestPriority::from_headers(&headers);
+    let request_type_override = request_type_from_headers(&headers);
     info!(
         "Received /v1/completions or /v1/responses payload: {:?}",
         body
@@ -558,18 +719,32 @@ pub async fn handle_completions(
     }
 
     let upstream = state.upstream.clone();
-    let token_manager = state.token_manager;
-    let pool_size = token_manager.len();
-    let max_attempts = MAX_RETRY_ATTEMPTS.min(pool_size).max(1);
+    let token_manager = state.token_manager.clone();
+    let mut retry = RetryLoop::new(token_manager.len());
+
+    // 模型路由解析 (每个请求只解析一次，加权映射按 trace_id 取种子并按会话粘性固定，重试期间保持同一目标)
+    let trace_id = crate::proxy::common::utils::generate_trace_id();
+    let routed_model = {
+        let routing_session_id = SessionManager::extract_openai_session_id(&openai_req);
+        crate::proxy::common::model_mapping::resolve_model_route_for_request(
+            &openai_req.model,
+            &*state.custom_mapping.read().await,
+            Some(&routing_session_id),
+            &trace_id,
+        )
+    };
+    debug!("[{}] Codex request routed: {} -> {}", trace_id, openai_req.model, routed_model);
 
-    let mut last_error = String::new();
+    let logprobs_mode = state.experimental.read().await.logprobs_mode;
+    if let Err(message) =
+        crate::proxy::mappers::openai::logprobs::check_logprobs(&openai_req, &routed_model, logprobs_mode)
+    {
+        return Ok(invalid_request_response("logprobs", &message));
+    }
 
-    for _attempt in 0..max_attempts {
+    for _attempt in retry.attempts() {
         // 1. 模型路由解析
-        let mapped_model = crate::proxy::common::model_mapping::resolve_model_route(
-            &openai_req.model,
-            &*state.custom_mapping.read().await,
-        );
+        let mapped_model = routed_model.clone();
         // 将 OpenAI 工具转为 Value 数组以便探测联网
         let tools_val: Option<Vec<Value>> = openai_req
             .tools
@@ -581,8 +756,9 @@ pub async fn handle_completions(
             &tools_val,
         );
 
+        let quota_group = token_manager.resolve_quota_group(&config.request_type, request_type_override).await;
         let (access_token, project_id, email) =
-            match token_manager.get_token(&config.request_type, false, None).await {
+            match token_manager.get_token_with_priority(&quota_group, false, None, priority).await {
                 Ok(t) => t,
                 Err(e) => {
                     return Err((
@@ -592,9 +768,12 @@ pub async fn handle_completions(
                 }
             };
 
-        info!("✓ Using account: {} (type: {})", email, config.request_type);
+        let account_tag = token_manager.account_identifier(&email);
+        let account_header = token_manager.account_header_name();
+        info!("✓ Using account: {} (type: {})", account_tag, config.request_type);
 
-        let gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model);
+        let mut gemini_body = transform_openai_request(&openai_req, &project_id, &mapped_model);
+        crate::proxy::common::sampling::clamp_sampling_params(&mut gemini_body, &*state.sampling_limits.read().await);
 
         // [New] 打印转换后的报文 (Gemini Body) 供调试 (Codex 路径)
         if let Ok(body_json) = serde_json::to_string_pretty(&gemini_body) {
@@ -610,12 +789,12 @@ pub async fn handle_completions(
         let query_string = if list_response { Some("alt=sse") } else { None };
 
         let response = match upstream
-            .call_v1_internal(method, &access_token, gemini_body, query_string)
+            .call_v1_internal_at(method, &access_token, gemini_body, query_string, region.as_deref())
             .await
         {
             Ok(r) => r,
             Err(e) => {
-                last_error = e.clone();
+                retry.fail(e);
                 continue;
             }
         };
@@ -623,8 +802,6 @@ pub async fn handle_completions(
         let status = response.status();
         if status.is_success() {
             if list_response {
-                use axum::body::Body;
-                use axum::response::Response;
 
                 let gemini_stream = response.bytes_stream();
                 let body = if is_codex_style {
@@ -643,7 +820,7 @@ pub async fn handle_completions(
                     .header("Content-Type", "text/event-stream")
                     .header("Cache-Control", "no-cache")
                     .header("Connection", "keep-alive")
-                    .header("X-Account-Email", &email)
+                    .header(account_header, &account_tag)
                     .header("X-Mapped-Model", &mapped_model)
                     .body(body)
                     .unwrap()
@@ -684,7 +861,7 @@ pub async fn handle_completions(
         // Handle errors and retry
         let status_code = status.as_u16();
         let error_text = response.text().await.unwrap_or_default();
-        last_error = format!("HTTP {}: {}", status_code, error_text);
+        retry.fail(format!("HTTP {}: {}", status_code, error_text));
 
         if status_code == 429 || status_code == 403 || status_code == 401 {
             continue;
@@ -694,7 +871,7 @@ pub async fn handle_completions(
 
     Err((
         StatusCode::TOO_MANY_REQUESTS,
-        format!("All attempts failed. Last error: {}", last_error),
+        format!("All attempts failed. Last error: {}", retry.last_error()),
     ))
 }
 
@@ -724,7 +901,7 @@ pub async fn handle_list_models(State(state): State<AppState>) -> impl IntoRespo
 /// 处理图像生成请求，转换为 Gemini API 格式
 pub async fn handle_images_generations(
     State(state): State<AppState>,
-    Json(body): Json<Value>,
+    TolerantJson(body): TolerantJson,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
     // 1. 解析请求参数
     let prompt = body.get("prompt").and_then(|v| v.as_str()).ok_or((
@@ -804,7 +981,7 @@ pub async fn handle_images_generations(
         }
     };
 
-    info!("✓ Using account: {} for image generation", email);
+    info!("✓ Using account: {} for image generation", token_manager.account_identifier(&email));
 
     // 4. 并发发送请求 (解决 candidateCount > 1 不支持的问题)
     let mut tasks = Vec::new();
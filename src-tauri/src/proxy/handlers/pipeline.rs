@@ -0,0 +1,99 @@
+// 各协议处理器共用的请求流水线阶段：账号选择与重试循环
+
+use crate::models::QuotaGroup;
+use crate::proxy::common::request_priority::RequestPriority;
+use crate::proxy::server::AppState;
+
+/// 单个请求最多尝试的次数 (不超过号池大小)
+pub(crate) const MAX_RETRY_ATTEMPTS: usize = 3;
+
+/// 按配额组获取账号 (access_token, project_id, email)；号池耗尽时先尝试最后探测 (需在配置中开启)
+pub(crate) async fn select_token(
+    state: &AppState,
+    quota_group: &str,
+    force_rotate: bool,
+    session_id: Option<&str>,
+    priority: RequestPriority,
+) -> Result<(String, String, String), String> {
+    match state
+        .token_manager
+        .get_token_with_priority(quota_group, force_rotate, session_id, priority)
+        .await
+    {
+        Err(e) => crate::proxy::probe::last_resort_token(state, QuotaGroup::from_request_type(quota_group))
+            .await
+            .ok_or(e),
+        ok => ok,
+    }
+}
+
+/// 重试循环的状态：尝试次数上限、最后一次错误与最后使用的账号
+#[derive(Debug, Clone)]
+pub(crate) struct RetryLoop {
+    max_attempts: usize,
+    last_error: String,
+    last_account_tag: Option<String>,
+}
+
+impl RetryLoop {
+    pub fn new(pool_size: usize) -> Self {
+        Self {
+            max_attempts: MAX_RETRY_ATTEMPTS.min(pool_size).max(1),
+            last_error: String::new(),
+            last_account_tag: None,
+        }
+    }
+
+    pub fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    pub fn attempts(&self) -> std::ops::Range<usize> {
+        0..self.max_attempts
+    }
+
+    /// 记录本次尝试使用的账号 (全部失败时写入账号响应头)
+    pub fn use_account(&mut self, account_tag: &str) {
+        self.last_account_tag = Some(account_tag.to_string());
+    }
+
+    pub fn fail(&mut self, error: impl Into<String>) {
+        self.last_error = error.into();
+    }
+
+    pub fn last_error(&self) -> &str {
+        &self.last_error
+    }
+
+    pub fn last_account_tag(&self) -> Option<&str> {
+        self.last_account_tag.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attempts_bounded_by_pool_size() {
+        assert_eq!(RetryLoop::new(0).max_attempts(), 1);
+        assert_eq!(RetryLoop::new(2).max_attempts(), 2);
+        assert_eq!(RetryLoop::new(10).max_attempts(), MAX_RETRY_ATTEMPTS);
+        assert_eq!(RetryLoop::new(2).attempts().count(), 2);
+    }
+
+    #[test]
+    fn test_tracks_last_error_and_account() {
+        let mut retry = RetryLoop::new(3);
+        assert_eq!(retry.last_error(), "");
+        assert_eq!(retry.last_account_tag(), None);
+
+        retry.use_account("a@example.com");
+        retry.fail("HTTP 429: slow down");
+        retry.use_account("b@example.com");
+        retry.fail("HTTP 503: overloaded");
+
+        assert_eq!(retry.last_error(), "HTTP 503: overloaded");
+        assert_eq!(retry.last_account_tag(), Some("b@example.com"));
+    }
+}
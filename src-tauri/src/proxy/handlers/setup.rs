@@ -0,0 +1,246 @@
+// 客户端引导页 - 在其他设备的浏览器中完成接入配置
+//
+// GET  /setup       单页 HTML：各协议的 Base URL、可用模型别名、生成客户端 Key
+// POST /setup/keys  生成一个客户端 Key，明文与二维码只在本次响应中返回
+//
+// 访问控制由鉴权中间件负责 (需开启 enable_setup_page，且总是要求主 API Key)。
+// 页面中不包含任何已有的 Key，配置中也只保存新 Key 的 SHA-256。
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use qrcode::render::svg;
+use qrcode::QrCode;
+use serde::{Deserialize, Serialize};
+
+use crate::proxy::config::{ClientApiKey, RouteGroup};
+use crate::proxy::security::hash_client_key;
+use crate::proxy::server::AppState;
+
+/// 生成客户端 Key 的请求体
+#[derive(Debug, Deserialize)]
+pub struct CreateClientKeyRequest {
+    #[serde(default)]
+    pub label: String,
+    /// 为空时不限分组
+    #[serde(default)]
+    pub scopes: Vec<RouteGroup>,
+    #[serde(default)]
+    pub default_model: Option<String>,
+    /// 页面所在的地址 (location.origin)，写入二维码
+    pub base_url: String,
+}
+
+/// 二维码中编码的接入配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SetupPayload {
+    pub base_url: String,
+    pub api_key: String,
+    pub default_model: Option<String>,
+}
+
+/// 生成结果 (api_key 只在这里出现一次)
+#[derive(Debug, Serialize)]
+pub struct CreateClientKeyResponse {
+    pub id: String,
+    pub label: String,
+    pub scopes: Vec<RouteGroup>,
+    pub api_key: String,
+    pub qr_svg: String,
+    pub setup: SetupPayload,
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn scope_label(group: RouteGroup) -> (&'static str, &'static str) {
+    match group {
+        RouteGroup::Claude => ("claude", "Anthropic (/v1/messages)"),
+        RouteGroup::Openai => ("openai", "OpenAI (/v1/chat/completions, /v1/responses ...)"),
+        RouteGroup::GeminiNative => ("gemini_native", "Gemini (/v1beta/models)"),
+        RouteGroup::Status => ("status", "Health (/healthz; /metrics uses its own access policy)"),
+    }
+}
+
+/// 引导页
+pub async fn handle_setup_page(State(state): State<AppState>) -> Response {
+    let models =
+        crate::proxy::common::model_mapping::get_all_dynamic_models(&state.custom_mapping).await;
+
+    let model_items: String = models
+        .iter()
+        .map(|m| format!("<li><code>{}</code></li>", escape_html(m)))
+        .collect();
+    let model_options: String = models
+        .iter()
+        .map(|m| {
+            let m = escape_html(m);
+            format!("<option value=\"{m}\">{m}</option>")
+        })
+        .collect();
+    let scope_inputs: String = RouteGroup::ALL
+        .iter()
+        .map(|g| {
+            let (value, label) = scope_label(*g);
+            format!("<label><input type=\"checkbox\" name=\"scope\" value=\"{value}\"> {label}</label><br>")
+        })
+        .collect();
+
+    let html = SETUP_PAGE
+        .replace("{{MODEL_ITEMS}}", &model_items)
+        .replace("{{MODEL_OPTIONS}}", &model_options)
+        .replace("{{SCOPE_INPUTS}}", &scope_inputs);
+
+    (
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        html,
+    )
+        .into_response()
+}
+
+/// 生成客户端 Key：先写入配置文件，成功后立即在运行中的服务生效
+pub async fn handle_create_client_key(
+    State(state): State<AppState>,
+    Json(req): Json<CreateClientKeyRequest>,
+) -> Response {
+    let base_url = req.base_url.trim().trim_end_matches('/').to_string();
+    if base_url.is_empty() {
+        return (StatusCode::BAD_REQUEST, "base_url is required").into_response();
+    }
+    let default_model = req.default_model.filter(|m| !m.trim().is_empty());
+
+    let api_key = format!("sk-{}", uuid::Uuid::new_v4().simple());
+    let mut scopes: Vec<RouteGroup> = Vec::new();
+    for group in req.scopes {
+        if !scopes.contains(&group) {
+            scopes.push(group);
+        }
+    }
+    let key = ClientApiKey {
+        id: uuid::Uuid::new_v4().to_string(),
+        label: req.label.trim().to_string(),
+        key_sha256: hash_client_key(&api_key),
+        scopes,
+        created_at: chrono::Utc::now().timestamp(),
+    };
+
+    let setup = SetupPayload { base_url, api_key: api_key.clone(), default_model };
+    let qr_svg = match serde_json::to_vec(&setup)
+        .map_err(|e| e.to_string())
+        .and_then(|payload| QrCode::new(payload).map_err(|e| e.to_string()))
+    {
+        Ok(code) => code.render::<svg::Color>().min_dimensions(200, 200).build(),
+        Err(e) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, format!("QR code generation failed: {}", e))
+                .into_response()
+        }
+    };
+
+    if let Err(e) = crate::modules::config::add_client_key_in(state.token_manager.data_dir(), key.clone()) {
+        tracing::error!("[Setup] 客户端 Key 保存失败: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+    }
+    state.security.write().await.client_keys.push(key.clone());
+    // 让设置页与托盘重新读取配置，避免旧快照在下次保存时覆盖新 Key
+    state.monitor.notify("config://updated");
+    tracing::info!("[Setup] 已生成客户端 Key {} ({})", key.id, key.label);
+
+    (
+        [(header::CACHE_CONTROL, "no-store")],
+        Json(CreateClientKeyResponse {
+            id: key.id,
+            label: key.label,
+            scopes: key.scopes,
+            api_key,
+            qr_svg,
+            setup,
+        }),
+    )
+        .into_response()
+}
+
+const SETUP_PAGE: &str = r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Antigravity Proxy Setup</title>
+<style>
+body { font-family: system-ui, sans-serif; max-width: 720px; margin: 24px auto; padding: 0 16px; color: #222; }
+section { border: 1px solid #ddd; border-radius: 8px; padding: 12px 16px; margin-bottom: 16px; }
+code { background: #f3f3f3; padding: 1px 4px; border-radius: 4px; }
+ul { columns: 2; }
+#result { display: none; }
+#qr svg { width: 220px; height: 220px; }
+</style>
+</head>
+<body>
+<h1>Antigravity Proxy Setup</h1>
+<section>
+<h2>Base URLs</h2>
+<p>Anthropic: <code id="anthropic-base"></code></p>
+<p>OpenAI: <code id="openai-base"></code></p>
+<p>Gemini: <code id="gemini-base"></code></p>
+</section>
+<section>
+<h2>Generate API key</h2>
+<p><label>Label <input id="label" placeholder="my-phone"></label></p>
+<p>Allowed routes (none checked = all):<br>{{SCOPE_INPUTS}}</p>
+<p><label>Default model <select id="model"><option value="">(none)</option>{{MODEL_OPTIONS}}</select></label></p>
+<button id="generate">Generate</button>
+<div id="result">
+<p>This key is shown only once:</p>
+<p><code id="key"></code> <button id="copy">Copy</button></p>
+<div id="qr"></div>
+</div>
+<p id="error"></p>
+</section>
+<section>
+<h2>Available models</h2>
+<ul>{{MODEL_ITEMS}}</ul>
+</section>
+<script>
+const origin = location.origin;
+document.getElementById('anthropic-base').textContent = origin;
+document.getElementById('openai-base').textContent = origin + '/v1';
+document.getElementById('gemini-base').textContent = origin + '/v1beta';
+document.getElementById('generate').onclick = async () => {
+  const error = document.getElementById('error');
+  error.textContent = '';
+  const scopes = [...document.querySelectorAll('input[name=scope]:checked')].map(i => i.value);
+  const res = await fetch('/setup/keys', {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({
+      label: document.getElementById('label').value,
+      scopes,
+      default_model: document.getElementById('model').value || null,
+      base_url: origin,
+    }),
+  });
+  if (!res.ok) {
+    error.textContent = 'Failed: ' + res.status + ' ' + await res.text();
+    return;
+  }
+  const data = await res.json();
+  document.getElementById('key').textContent = data.api_key;
+  document.getElementById('qr').innerHTML = data.qr_svg;
+  document.getElementById('result').style.display = 'block';
+};
+document.getElementById('copy').onclick = () => {
+  navigator.clipboard.writeText(document.getElementById('key').textContent);
+};
+</script>
+</body>
+</html>
+"#;
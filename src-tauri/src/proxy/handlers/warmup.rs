@@ -49,6 +49,20 @@ pub async fn handle_warmup(
         req.email, req.model
     );
 
+    // 账号设置了不预热时拒绝任何合成流量
+    if state.token_manager.is_warmup_disabled(&req.email) {
+        info!("[Warmup-API] Skipped: {} has no_warmup enabled", req.email);
+        return (
+            StatusCode::FORBIDDEN,
+            Json(WarmupResponse {
+                success: false,
+                message: format!("Warmup disabled for {}", req.email),
+                error: None,
+            }),
+        )
+            .into_response();
+    }
+
     // ===== 步骤 1: 获取 Token =====
     let (access_token, project_id) = if let (Some(at), Some(pid)) = (&req.access_token, &req.project_id) {
         (at.clone(), pid.clone())
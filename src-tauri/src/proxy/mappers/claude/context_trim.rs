@@ -0,0 +1,295 @@
+// 上下文超长时的历史裁剪
+// 上游因上下文超长拒绝请求时，从最早的非系统消息开始丢弃 (保持 tool_use/tool_result 成对)，
+// 直到估算的 token 数落在模型上下文上限 - max_tokens 之内，并插入一条说明被省略消息数的标记。
+
+use super::models::{ClaudeRequest, ContentBlock, Message, MessageContent};
+use std::collections::HashSet;
+
+/// 告知客户端本次请求的历史被裁剪了多少条消息
+pub const CONTEXT_TRIMMED_HEADER: &str = "X-Context-Trimmed";
+
+/// 上游错误正文是否为上下文超长 (不区分大小写)
+pub fn is_context_exceeded_error(error_text: &str, patterns: &[String]) -> bool {
+    let lower = error_text.to_lowercase();
+    patterns
+        .iter()
+        .filter(|p| !p.trim().is_empty())
+        .any(|p| lower.contains(&p.to_lowercase()))
+}
+
+/// 模型的上下文窗口 (tokens)
+pub fn context_limit_for_model(model: &str) -> u32 {
+    if model.starts_with("gemini-") {
+        1_048_576
+    } else {
+        200_000
+    }
+}
+
+/// 粗略估算请求的输入 token 数 (按序列化后约 4 字符 / token)
+pub fn estimate_request_tokens(request: &ClaudeRequest) -> u32 {
+    let chars = serde_json::to_string(&request.messages).map(|s| s.len()).unwrap_or(0)
+        + request
+            .system
+            .as_ref()
+            .and_then(|s| serde_json::to_string(s).ok())
+            .map(|s| s.len())
+            .unwrap_or(0)
+        + request
+            .tools
+            .as_ref()
+            .and_then(|t| serde_json::to_string(t).ok())
+            .map(|s| s.len())
+            .unwrap_or(0);
+    (chars as u64).div_ceil(4).min(u32::MAX as u64) as u32
+}
+
+fn blocks(message: &Message) -> &[ContentBlock] {
+    match &message.content {
+        MessageContent::Array(blocks) => blocks,
+        MessageContent::String(_) => &[],
+    }
+}
+
+fn has_tool_result(message: &Message) -> bool {
+    blocks(message).iter().any(|b| matches!(b, ContentBlock::ToolResult { .. }))
+}
+
+/// 必须保留的尾部消息数：最后一条用户消息；若其携带 tool_result，还需保留前面发起调用的 assistant 消息
+fn protected_tail_len(messages: &[Message]) -> usize {
+    match messages.last() {
+        Some(last) if has_tool_result(last) && messages.len() >= 2 => 2,
+        Some(_) => 1,
+        None => 0,
+    }
+}
+
+/// 移除引用了已被丢弃 tool_use 的 tool_result，返回因此变空而被移除的消息数
+fn drop_orphaned_tool_results(messages: &mut Vec<Message>) -> usize {
+    let tool_use_ids: HashSet<String> = messages
+        .iter()
+        .flat_map(|m| blocks(m).iter())
+        .filter_map(|b| match b {
+            ContentBlock::ToolUse { id, .. } => Some(id.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let before = messages.len();
+    messages.retain_mut(|message| {
+        let MessageContent::Array(blocks) = &mut message.content else {
+            return true;
+        };
+        let had_blocks = !blocks.is_empty();
+        blocks.retain(|b| match b {
+            ContentBlock::ToolResult { tool_use_id, .. } => tool_use_ids.contains(tool_use_id),
+            _ => true,
+        });
+        !had_blocks || !blocks.is_empty()
+    });
+    before - messages.len()
+}
+
+/// 按条数截断历史：只保留最近 `max_messages` 条消息 (0 表示不限制)，返回丢弃的消息数
+///
+/// 被截断的 tool_use 对应的 tool_result 会一并移除；最后一条用户消息及其 tool_use 始终保留
+pub fn truncate_history(messages: &mut Vec<Message>, max_messages: usize) -> usize {
+    if max_messages == 0 || messages.len() <= max_messages {
+        return 0;
+    }
+    let before = messages.len();
+    let keep = max_messages.max(protected_tail_len(messages));
+    messages.drain(..before - keep);
+    drop_orphaned_tool_results(messages);
+    before - messages.len()
+}
+
+fn marker_text(dropped: usize) -> String {
+    format!(
+        "[Context trimmed by proxy: {} earlier message(s) were elided to fit the model's context window.]",
+        dropped
+    )
+}
+
+/// 在保留的历史前加入省略标记：首条为用户消息时并入其中，否则单独插入一条用户消息
+fn with_marker(messages: &[Message], dropped: usize) -> Vec<Message> {
+    let marker = ContentBlock::Text { text: marker_text(dropped) };
+    let mut result = messages.to_vec();
+    match result.first_mut() {
+        Some(first) if first.role == "user" => {
+            let mut content = vec![marker];
+            match &first.content {
+                MessageContent::String(text) => content.push(ContentBlock::Text { text: text.clone() }),
+                MessageContent::Array(blocks) => content.extend(blocks.iter().cloned()),
+            }
+            first.content = MessageContent::Array(content);
+        }
+        _ => result.insert(
+            0,
+            Message {
+                role: "user".to_string(),
+                content: MessageContent::Array(vec![marker]),
+            },
+        ),
+    }
+    result
+}
+
+/// 裁剪最早的历史消息直到估算 token 数不超过 `budget`，返回被省略的消息数
+///
+/// 至少省略一条消息 (上游已判定超长)；保留最后一条用户消息及其对应的 tool_use，
+/// 全部可裁剪消息都移除后仍超出预算时返回错误，且不修改请求
+pub fn trim_history_to_fit(request: &mut ClaudeRequest, budget: u32) -> Result<usize, String> {
+    let mut messages = request.messages.clone();
+    let tail = protected_tail_len(&messages);
+    let mut dropped = 0;
+
+    loop {
+        if dropped > 0 {
+            let mut candidate = request.clone();
+            candidate.messages = with_marker(&messages, dropped);
+            let estimated = estimate_request_tokens(&candidate);
+            if estimated <= budget {
+                request.messages = candidate.messages;
+                return Ok(dropped);
+            }
+        }
+
+        if messages.len() <= tail {
+            let mut candidate = request.clone();
+            candidate.messages = with_marker(&messages, dropped.max(1));
+            return Err(format!(
+                "context still exceeds budget after trimming {} message(s): estimated {} tokens > {}",
+                dropped,
+                estimate_request_tokens(&candidate),
+                budget
+            ));
+        }
+
+        messages.remove(0);
+        dropped += 1 + drop_orphaned_tool_results(&mut messages);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(messages: serde_json::Value) -> ClaudeRequest {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "messages": messages
+        }))
+        .unwrap()
+    }
+
+    fn assert_pairing_intact(messages: &[Message]) {
+        let ids: HashSet<String> = messages
+            .iter()
+            .flat_map(|m| blocks(m).iter())
+            .filter_map(|b| match b {
+                ContentBlock::ToolUse { id, .. } => Some(id.clone()),
+                _ => None,
+            })
+            .collect();
+        for block in messages.iter().flat_map(|m| blocks(m).iter()) {
+            if let ContentBlock::ToolResult { tool_use_id, .. } = block {
+                assert!(ids.contains(tool_use_id), "orphaned tool_result {}", tool_use_id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_trim_preserves_tool_pairing_and_final_turn() {
+        let filler = "x".repeat(4000);
+        let mut req = request(json!([
+            { "role": "user", "content": filler },
+            { "role": "assistant", "content": [
+                { "type": "tool_use", "id": "t1", "name": "read", "input": { "path": filler } }
+            ]},
+            { "role": "user", "content": [
+                { "type": "tool_result", "tool_use_id": "t1", "content": filler }
+            ]},
+            { "role": "assistant", "content": [
+                { "type": "text", "text": "checking" },
+                { "type": "tool_use", "id": "t2", "name": "read", "input": { "path": "b" } }
+            ]},
+            { "role": "user", "content": [
+                { "type": "tool_result", "tool_use_id": "t2", "content": "ok" }
+            ]}
+        ]));
+
+        let dropped = trim_history_to_fit(&mut req, 500).unwrap();
+
+        // 丢弃 u1 与 a1 后 t1 的结果成为孤儿，随之移除
+        assert_eq!(dropped, 3);
+        assert_pairing_intact(&req.messages);
+        assert_eq!(req.messages[0].role, "user");
+        match &req.messages[0].content {
+            MessageContent::Array(blocks) => {
+                assert!(matches!(&blocks[0], ContentBlock::Text { text } if text.contains("3 earlier")))
+            }
+            _ => panic!("marker missing"),
+        }
+        let last = req.messages.last().unwrap();
+        assert!(has_tool_result(last));
+        assert!(estimate_request_tokens(&req) <= 500);
+    }
+
+    #[test]
+    fn test_trim_fails_when_final_turn_alone_exceeds_budget() {
+        let huge = "y".repeat(40_000);
+        let mut req = request(json!([
+            { "role": "user", "content": "hello" },
+            { "role": "assistant", "content": "hi" },
+            { "role": "user", "content": huge }
+        ]));
+        let original = serde_json::to_string(&req.messages).unwrap();
+
+        let err = trim_history_to_fit(&mut req, 1_000).unwrap_err();
+        assert!(err.contains("still exceeds"));
+        // 失败时请求保持原样
+        assert_eq!(serde_json::to_string(&req.messages).unwrap(), original);
+    }
+
+    #[test]
+    fn test_truncate_history_keeps_window_without_orphans() {
+        // 50 条消息：每轮为 user(text) -> assistant(tool_use) -> user(tool_result) 的循环
+        let mut messages = Vec::new();
+        for i in 0..50 {
+            let message = match i % 3 {
+                0 => json!({ "role": "user", "content": format!("step {}", i) }),
+                1 => json!({ "role": "assistant", "content": [
+                    { "type": "tool_use", "id": format!("t{}", i), "name": "run", "input": {} }
+                ]}),
+                _ => json!({ "role": "user", "content": [
+                    { "type": "tool_result", "tool_use_id": format!("t{}", i - 1), "content": "ok" }
+                ]}),
+            };
+            messages.push(message);
+        }
+        let mut req = request(serde_json::Value::Array(messages));
+        let last = serde_json::to_string(req.messages.last().unwrap()).unwrap();
+
+        // 保留窗口起点 (index 38) 恰好是 tool_result，其 tool_use 已被截断
+        let dropped = truncate_history(&mut req.messages, 12);
+
+        assert_eq!(dropped, 39);
+        assert_eq!(req.messages.len(), 11);
+        assert_pairing_intact(&req.messages);
+        assert_eq!(serde_json::to_string(req.messages.last().unwrap()).unwrap(), last);
+        assert_eq!(truncate_history(&mut req.messages, 12), 0);
+    }
+
+    #[test]
+    fn test_context_exceeded_detection() {
+        let patterns = crate::proxy::config::ExperimentalConfig::default().context_exceeded_patterns;
+        assert!(is_context_exceeded_error(
+            r#"{"error":{"code":400,"message":"The input token count (1200000) exceeds the maximum number of tokens allowed (1048576).","status":"INVALID_ARGUMENT"}}"#,
+            &patterns
+        ));
+        assert!(!is_context_exceeded_error("Invalid `signature` in thinking block", &patterns));
+    }
+}
@@ -0,0 +1,142 @@
+// 本地应答 Claude Code 的工具类小请求
+// 只处理能确定答案的请求 (目前为话题检测)，其余一律透传给上游。
+
+use super::models::{ClaudeRequest, ContentBlock, MessageContent, SystemPrompt};
+use crate::proxy::config::LocalResponderConfig;
+
+/// 标记响应由代理本地生成
+pub const LOCALLY_ANSWERED_HEADER: &str = "X-Locally-Answered";
+
+/// 话题检测的安全应答：不开启新话题，客户端保持现有标题
+const TOPIC_DETECTION_ANSWER: &str = r#"{"isNewTopic": false, "title": null}"#;
+
+/// 命中的本地规则
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LocalRule {
+    TopicDetection,
+}
+
+impl LocalRule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LocalRule::TopicDetection => "topic_detection",
+        }
+    }
+
+    pub fn answer(&self) -> &'static str {
+        match self {
+            LocalRule::TopicDetection => TOPIC_DETECTION_ANSWER,
+        }
+    }
+}
+
+/// system 与所有消息中的文本 (不含工具块)
+fn prompt_texts(request: &ClaudeRequest) -> Vec<&str> {
+    let mut texts = Vec::new();
+    match &request.system {
+        Some(SystemPrompt::String(s)) => texts.push(s.as_str()),
+        Some(SystemPrompt::Array(blocks)) => texts.extend(blocks.iter().map(|b| b.text.as_str())),
+        None => {}
+    }
+    for message in &request.messages {
+        match &message.content {
+            MessageContent::String(s) => texts.push(s.as_str()),
+            MessageContent::Array(blocks) => texts.extend(blocks.iter().filter_map(|b| match b {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })),
+        }
+    }
+    texts
+}
+
+/// 请求只含纯文本 (任何工具、图片等块都视为无法确定)
+fn is_text_only(request: &ClaudeRequest) -> bool {
+    request.messages.iter().all(|m| match &m.content {
+        MessageContent::String(_) => true,
+        MessageContent::Array(blocks) => blocks.iter().all(|b| matches!(b, ContentBlock::Text { .. })),
+    })
+}
+
+/// 判断请求是否可在本地应答；未确定命中时返回 None
+pub fn match_local_rule(request: &ClaudeRequest, config: &LocalResponderConfig) -> Option<LocalRule> {
+    if !config.enabled {
+        return None;
+    }
+    let keyword = config.model_keyword.trim().to_lowercase();
+    if keyword.is_empty() || !request.model.to_lowercase().contains(&keyword) {
+        return None;
+    }
+    if request.tools.as_ref().is_some_and(|t| !t.is_empty()) || !is_text_only(request) {
+        return None;
+    }
+
+    let texts = prompt_texts(request);
+    if texts.iter().map(|t| t.chars().count()).sum::<usize>() > config.max_prompt_chars {
+        return None;
+    }
+
+    let is_topic_detection = config
+        .topic_detection_markers
+        .iter()
+        .filter(|m| !m.trim().is_empty())
+        .any(|marker| texts.iter().any(|t| t.contains(marker.as_str())));
+    is_topic_detection.then_some(LocalRule::TopicDetection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn enabled() -> LocalResponderConfig {
+        LocalResponderConfig { enabled: true, ..Default::default() }
+    }
+
+    fn topic_request(model: &str, user_text: &str) -> ClaudeRequest {
+        serde_json::from_value(json!({
+            "model": model,
+            "max_tokens": 512,
+            "system": [{ "type": "text", "text": "Analyze if this message indicates a new conversation topic. Format your response as a JSON object with two fields: 'isNewTopic' (boolean) and 'title' (string, or null if isNewTopic is false)." }],
+            "messages": [{ "role": "user", "content": user_text }]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_topic_detection_is_answered_locally() {
+        let req = topic_request("claude-3-5-haiku-20241022", "fix the login bug");
+        assert_eq!(match_local_rule(&req, &enabled()), Some(LocalRule::TopicDetection));
+        let answer: serde_json::Value = serde_json::from_str(LocalRule::TopicDetection.answer()).unwrap();
+        assert_eq!(answer["isNewTopic"], false);
+
+        // 默认关闭
+        assert_eq!(match_local_rule(&req, &LocalResponderConfig::default()), None);
+    }
+
+    #[test]
+    fn test_unconfident_requests_pass_through() {
+        let cfg = enabled();
+
+        // 非 haiku 模型
+        assert_eq!(match_local_rule(&topic_request("claude-sonnet-4-5", "hi"), &cfg), None);
+
+        // 超过长度阈值
+        let long = "x".repeat(cfg.max_prompt_chars);
+        assert_eq!(match_local_rule(&topic_request("claude-haiku-4-5", &long), &cfg), None);
+
+        // 带工具
+        let mut with_tools = topic_request("claude-haiku-4-5", "hi");
+        with_tools.tools = serde_json::from_value(json!([{ "name": "Bash", "input_schema": { "type": "object" } }])).unwrap();
+        assert_eq!(match_local_rule(&with_tools, &cfg), None);
+
+        // 无识别特征的 haiku 请求
+        let plain: ClaudeRequest = serde_json::from_value(json!({
+            "model": "claude-haiku-4-5",
+            "max_tokens": 64,
+            "messages": [{ "role": "user", "content": "Is this a bash command prefix?" }]
+        }))
+        .unwrap();
+        assert_eq!(match_local_rule(&plain, &cfg), None);
+    }
+}
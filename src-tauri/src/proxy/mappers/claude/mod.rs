@@ -8,12 +8,16 @@ pub mod streaming;
 pub mod utils;
 pub mod thinking_utils;
 pub mod collector;
+pub mod context_trim;
+pub mod recitation;
+pub mod tool_filter;
+pub mod local_responder;
 
 pub use models::*;
-pub use request::transform_claude_request_in;
+pub use request::{perturb_for_recitation, transform_claude_request_in};
 pub use response::transform_response;
-pub use streaming::{PartProcessor, StreamingState};
-pub use thinking_utils::close_tool_loop_for_thinking;
+pub use streaming::{PartProcessor, StreamingState, MALFORMED_FUNCTION_CALL, RECITATION};
+pub use thinking_utils::{close_tool_loop_for_thinking, recover_tool_loop};
 pub use collector::collect_stream_to_json;
 
 use bytes::Bytes;
@@ -21,41 +25,64 @@ use futures::Stream;
 use std::pin::Pin;
 
 /// 创建从 Gemini SSE 流到 Claude SSE 流的转换
+///
+/// `retry_malformed_function_call` 为 true 时，若在输出任何内容之前遇到
+/// `MALFORMED_FUNCTION_CALL`，流会以错误结束，由 handler 轮换账号重试；
+/// `retry_recitation` 对 `RECITATION` 同理；`blocked_tools` 中工具的调用以说明文本代替；
+/// `stream_debug` 开启逐 part 的调试日志
 pub fn create_claude_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     trace_id: String,
     email: String,
+    retry_malformed_function_call: bool,
+    retry_recitation: bool,
+    emit_incremental_usage: bool,
+    blocked_tools: Vec<String>,
+    stream_debug: bool,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
+    use crate::proxy::common::sse_parser::SseEventParser;
     use async_stream::stream;
-    use bytes::BytesMut;
     use futures::StreamExt;
 
     Box::pin(stream! {
         let mut state = StreamingState::new();
-        let mut buffer = BytesMut::new();
+        state.retry_malformed_function_call = retry_malformed_function_call;
+        state.retry_recitation = retry_recitation;
+        state.emit_incremental_usage = emit_incremental_usage;
+        state.blocked_tools = blocked_tools;
+        state.verbose = stream_debug;
+        // 最终的 message_delta / message_stop 在上游流结束时才发出，迟到的用量可以并入
+        state.defer_stop = true;
+        let mut parser = SseEventParser::new();
 
         while let Some(chunk_result) = gemini_stream.next().await {
             match chunk_result {
                 Ok(chunk) => {
-                    buffer.extend_from_slice(&chunk);
-
-                    // Process complete lines
-                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                        let line_raw = buffer.split_to(pos + 1);
-                        if let Ok(line_str) = std::str::from_utf8(&line_raw) {
-                            let line = line_str.trim();
-                            if line.is_empty() { continue; }
-
-                            if let Some(sse_chunks) = process_sse_line(line, &mut state, &trace_id, &email) {
-                                for sse_chunk in sse_chunks {
-                                    yield Ok(sse_chunk);
-                                }
+                    // Process complete events
+                    for data in parser.push(&chunk) {
+                        if let Some(sse_chunks) = process_sse_data(&data, &mut state, &trace_id, &email) {
+                            for sse_chunk in sse_chunks {
+                                yield Ok(sse_chunk);
                             }
                         }
+
+                        if state.malformed_function_call_retry_requested {
+                            yield Err(format!("Upstream finished with {}", MALFORMED_FUNCTION_CALL));
+                            return;
+                        }
+                        if state.recitation_retry_requested {
+                            yield Err(format!("Upstream finished with {}", RECITATION));
+                            return;
+                        }
                     }
                 }
                 Err(e) => {
-                    yield Err(format!("Stream error: {}", e));
+                    if state.is_finished() {
+                        // 结束块已收到，只差延后发出的最终事件：补发结束事件，不向客户端报错
+                        tracing::warn!("[{}] Upstream stream error after finish, completing response: {}", trace_id, e);
+                    } else {
+                        yield Err(format!("Stream error: {}", e));
+                    }
                     break;
                 }
             }
@@ -65,16 +92,26 @@ pub fn create_claude_sse_stream(
         for chunk in emit_force_stop(&mut state) {
             yield Ok(chunk);
         }
+
+        let (ignored, dropped_usage) = state.after_finish_counts();
+        if ignored > 0 || dropped_usage > 0 {
+            tracing::debug!(
+                "[{}] Ignored {} upstream event(s) after finish, dropped {} late usage update(s)",
+                trace_id, ignored, dropped_usage
+            );
+        }
     })
 }
 
-/// 处理单行 SSE 数据
+/// 处理单行 SSE 数据 (测试用，生产路径经由 SseEventParser)
+#[cfg(test)]
 fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, email: &str) -> Option<Vec<Bytes>> {
-    if !line.starts_with("data: ") {
-        return None;
-    }
+    process_sse_data(line.strip_prefix("data: ")?, state, trace_id, email)
+}
 
-    let data_str = line[6..].trim();
+/// 处理一个完整 SSE 事件的 data
+fn process_sse_data(data: &str, state: &mut StreamingState, trace_id: &str, email: &str) -> Option<Vec<Bytes>> {
+    let data_str = data.trim();
     if data_str.is_empty() {
         return None;
     }
@@ -98,6 +135,43 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
     // 解包 response 字段 (如果存在)
     let raw_json = json_value.get("response").unwrap_or(&json_value);
 
+    // 结束后上游偶尔会补发用量、重复发送候选内容或再次结束：一律不再输出事件，
+    // 否则严格的 Anthropic SDK 会因 message_stop 之后的事件直接中止整个对话
+    if state.is_finished() {
+        absorb_after_finish(raw_json, state, trace_id);
+        return None;
+    }
+
+    // MALFORMED_FUNCTION_CALL 视为瞬时错误：尚未向客户端输出任何内容时请求重试
+    let finish_reason = raw_json
+        .get("candidates")
+        .and_then(|c| c.get(0))
+        .and_then(|cand| cand.get("finishReason"))
+        .and_then(|f| f.as_str());
+    if finish_reason == Some(MALFORMED_FUNCTION_CALL)
+        && state.retry_malformed_function_call
+        && !state.message_start_sent
+    {
+        tracing::warn!(
+            "[{}] Upstream finished with {} before any output, requesting retry",
+            trace_id, MALFORMED_FUNCTION_CALL
+        );
+        state.malformed_function_call_retry_requested = true;
+        return None;
+    }
+
+    // RECITATION: 记录发生情况；尚未输出内容且允许重试时交由 handler 扰动请求后重试
+    if finish_reason == Some(RECITATION) {
+        tracing::warn!(
+            "[{}] Upstream finished with {} (output started: {})",
+            trace_id, RECITATION, state.message_start_sent
+        );
+        if state.retry_recitation && !state.message_start_sent {
+            state.recitation_retry_requested = true;
+            return None;
+        }
+    }
+
     // 发送 message_start
     if !state.message_start_sent {
         chunks.push(state.emit_message_start(raw_json));
@@ -187,6 +261,12 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
         }
 
         chunks.extend(state.emit_finish(Some(finish_reason), usage.as_ref()));
+    } else if let Some(usage) = raw_json
+        .get("usageMetadata")
+        .and_then(|u| serde_json::from_value::<UsageMetadata>(u.clone()).ok())
+    {
+        // 流中途的用量更新 (需开启 emit_incremental_usage)
+        chunks.extend(state.emit_usage_delta(&usage));
     }
 
     if chunks.is_empty() {
@@ -196,19 +276,43 @@ fn process_sse_line(line: &str, state: &mut StreamingState, trace_id: &str, emai
     }
 }
 
-/// 发送强制结束事件
-pub fn emit_force_stop(state: &mut StreamingState) -> Vec<Bytes> {
-    if !state.message_stop_sent {
-        let mut chunks = state.emit_finish(None, None);
-        if chunks.is_empty() {
-            chunks.push(Bytes::from(
-                "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
-            ));
-            state.message_stop_sent = true;
-        }
-        return chunks;
+/// 处理结束后到达的上游事件：用量尽量并入尚未发出的 message_delta，其余内容忽略并计数
+fn absorb_after_finish(raw_json: &serde_json::Value, state: &mut StreamingState, trace_id: &str) {
+    let candidate = raw_json.get("candidates").and_then(|c| c.get(0));
+    let has_content = candidate
+        .and_then(|cand| cand.get("content"))
+        .and_then(|content| content.get("parts"))
+        .and_then(|p| p.as_array())
+        .map_or(false, |parts| !parts.is_empty());
+    let finish_reason = candidate
+        .and_then(|cand| cand.get("finishReason"))
+        .and_then(|f| f.as_str());
+    if has_content || finish_reason.is_some() {
+        state.note_ignored_after_finish();
+        tracing::debug!(
+            "[{}] Ignoring upstream event after finish (content: {}, finishReason: {:?})",
+            trace_id, has_content, finish_reason
+        );
+    }
+
+    if let Some(usage) = raw_json
+        .get("usageMetadata")
+        .and_then(|u| serde_json::from_value::<UsageMetadata>(u.clone()).ok())
+    {
+        let merged = state.merge_late_usage(&usage);
+        tracing::debug!("[{}] Late usageMetadata after finish (merged: {})", trace_id, merged);
     }
-    vec![]
+}
+
+/// 发送强制结束事件 (包括暂缓发送的最终事件)
+pub fn emit_force_stop(state: &mut StreamingState) -> Vec<Bytes> {
+    let mut chunks = if state.is_finished() {
+        Vec::new()
+    } else {
+        state.emit_finish(None, None)
+    };
+    chunks.extend(state.flush_stop());
+    chunks
 }
 
 /// Process grounding metadata from Gemini's googleSearch and emit as Claude web_search blocks
@@ -337,6 +441,147 @@ fn process_grounding_metadata(
 mod tests {
     use super::*;
 
+    fn sse_events(chunks: &[Bytes]) -> Vec<serde_json::Value> {
+        chunks
+            .iter()
+            .flat_map(|b| {
+                String::from_utf8(b.to_vec())
+                    .unwrap_or_default()
+                    .lines()
+                    .filter_map(|l| l.strip_prefix("data: ").map(String::from))
+                    .collect::<Vec<_>>()
+            })
+            .filter_map(|d| serde_json::from_str(&d).ok())
+            .collect()
+    }
+
+    fn mid_stream_usage_lines() -> Vec<String> {
+        vec![
+            r#"data: {"response":{"candidates":[{"content":{"parts":[{"text":"Hel"}]}}],"usageMetadata":{"promptTokenCount":10,"candidatesTokenCount":2},"modelVersion":"test","responseId":"1"}}"#.to_string(),
+            r#"data: {"response":{"candidates":[{"content":{"parts":[{"text":"lo"}]}}],"usageMetadata":{"promptTokenCount":10,"candidatesTokenCount":5},"modelVersion":"test","responseId":"1"}}"#.to_string(),
+            r#"data: {"response":{"candidates":[{"content":{"parts":[{"text":"!"}]},"finishReason":"STOP"}],"usageMetadata":{"promptTokenCount":10,"candidatesTokenCount":7,"totalTokenCount":17},"modelVersion":"test","responseId":"1"}}"#.to_string(),
+        ]
+    }
+
+    fn usage_deltas(incremental: bool) -> Vec<serde_json::Value> {
+        let mut state = StreamingState::new();
+        state.emit_incremental_usage = incremental;
+        let chunks: Vec<Bytes> = mid_stream_usage_lines()
+            .iter()
+            .flat_map(|line| process_sse_line(line, &mut state, "test_id", "test@example.com").unwrap_or_default())
+            .collect();
+        sse_events(&chunks)
+            .into_iter()
+            .filter(|e| e["type"] == "message_delta")
+            .collect()
+    }
+
+    #[test]
+    fn test_incremental_usage_deltas_emitted_mid_stream() {
+        let deltas = usage_deltas(true);
+        // 第一块用于 message_start，第二块产生中途用量，最后为权威的结束用量
+        assert_eq!(deltas.len(), 2);
+        assert!(deltas[0]["delta"]["stop_reason"].is_null());
+        assert_eq!(deltas[0]["usage"]["output_tokens"], 5);
+        assert_eq!(deltas[1]["delta"]["stop_reason"], "end_turn");
+        assert_eq!(deltas[1]["usage"]["output_tokens"], 7);
+    }
+
+    #[test]
+    fn test_incremental_usage_disabled_by_default() {
+        let deltas = usage_deltas(false);
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0]["usage"]["output_tokens"], 7);
+    }
+
+    #[test]
+    fn test_events_after_finish_are_ignored_and_counted() {
+        let mut state = StreamingState::new();
+        let lines = mid_stream_usage_lines();
+        for line in &lines {
+            process_sse_line(line, &mut state, "test_id", "test@example.com");
+        }
+        assert!(state.message_stop_sent);
+
+        // 重复的结束块与迟到的用量都不再产生事件 (最终事件已发出，两次用量都只能丢弃)
+        assert!(process_sse_line(&lines[2], &mut state, "test_id", "test@example.com").is_none());
+        let late_usage = r#"data: {"response":{"usageMetadata":{"promptTokenCount":10,"candidatesTokenCount":9}}}"#;
+        assert!(process_sse_line(late_usage, &mut state, "test_id", "test@example.com").is_none());
+        assert!(process_sse_line("data: [DONE]", &mut state, "test_id", "test@example.com").is_none());
+        assert_eq!(state.after_finish_counts(), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_crlf_and_multiline_data_lose_no_content() {
+        use futures::StreamExt;
+
+        let input = concat!(
+            ": proxy keep-alive\r\n",
+            "event: message\r\n",
+            "data: {\"response\":{\"candidates\":[{\"content\":{\"parts\":\r\n",
+            "data: [{\"text\":\"Hello\"}]}}],\"modelVersion\":\"test\",\"responseId\":\"1\"}}\r\n",
+            "\r\n",
+            ": ping\r\n",
+            "retry: 1000\r\n",
+            "data: {\"response\":{\"candidates\":[{\"content\":{\"parts\":[{\"text\":\" world\"}]},\r\n",
+            "data: \"finishReason\":\"STOP\"}],\"modelVersion\":\"test\",\"responseId\":\"1\"}}\r\n",
+            "\r\n",
+        );
+        let chunks: Vec<Result<Bytes, reqwest::Error>> = input
+            .as_bytes()
+            .chunks(11)
+            .map(|c| Ok(Bytes::copy_from_slice(c)))
+            .collect();
+        let output: Vec<Bytes> = create_claude_sse_stream(
+            Box::pin(futures::stream::iter(chunks)),
+            "test_id".to_string(),
+            "test@example.com".to_string(),
+            false,
+            false,
+            false,
+            Vec::new(),
+            false,
+        )
+        .map(|item| item.unwrap())
+        .collect()
+        .await;
+
+        let events = sse_events(&output);
+        let text: String = events
+            .iter()
+            .filter(|e| e["type"] == "content_block_delta")
+            .filter_map(|e| e["delta"]["text"].as_str())
+            .collect();
+        assert_eq!(text, "Hello world");
+        assert!(events.iter().any(|e| e["delta"]["stop_reason"] == "end_turn"));
+    }
+
+    #[tokio::test]
+    async fn test_transport_error_after_finish_still_completes_message() {
+        use futures::StreamExt;
+
+        let finish = "data: {\"response\":{\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"Hi\"}]},\"finishReason\":\"STOP\"}],\"modelVersion\":\"test\",\"responseId\":\"1\"}}\n\n";
+        let transport_error = reqwest::Client::new().get("not a url").build().unwrap_err();
+        let chunks: Vec<Result<Bytes, reqwest::Error>> = vec![Ok(Bytes::from(finish)), Err(transport_error)];
+        let output: Vec<Result<Bytes, String>> = create_claude_sse_stream(
+            Box::pin(futures::stream::iter(chunks)),
+            "test_id".to_string(),
+            "test@example.com".to_string(),
+            false,
+            false,
+            false,
+            Vec::new(),
+            false,
+        )
+        .collect()
+        .await;
+
+        assert!(output.iter().all(|item| item.is_ok()), "error after finish must be swallowed");
+        let events = sse_events(&output.into_iter().map(Result::unwrap).collect::<Vec<_>>());
+        assert!(events.iter().any(|e| e["delta"]["stop_reason"] == "end_turn"));
+        assert_eq!(events.last().unwrap()["type"], "message_stop");
+    }
+
     #[test]
     fn test_process_sse_line_done() {
         let mut state = StreamingState::new();
@@ -374,4 +619,98 @@ mod tests {
         assert!(all_text.contains("content_block_start"));
         assert!(all_text.contains("Hello"));
     }
+
+    #[test]
+    fn test_blocked_tool_call_becomes_note() {
+        let mut state = StreamingState::new();
+        state.blocked_tools = vec!["Bash".to_string()];
+
+        let test_data = r#"data: {"candidates":[{"content":{"parts":[{"functionCall":{"name":"Bash","args":{"command":"ls"}}}]},"finishReason":"STOP"}],"usageMetadata":{},"modelVersion":"test","responseId":"123"}"#;
+        let chunks = process_sse_line(test_data, &mut state, "test_id", "test@example.com").unwrap();
+        let events = sse_events(&chunks);
+
+        assert!(!events.iter().any(|e| e["content_block"]["type"] == "tool_use"));
+        let text: String = events
+            .iter()
+            .filter_map(|e| e["delta"]["text"].as_str())
+            .collect();
+        assert_eq!(text, tool_filter::blocked_tool_note("Bash"));
+        // 没有真正的工具调用，客户端不应等待工具结果
+        assert!(events.iter().any(|e| e["delta"]["stop_reason"] == "end_turn"));
+    }
+
+    const MALFORMED_LINE: &str = r#"data: {"candidates":[{"content":{"parts":[]},"finishReason":"MALFORMED_FUNCTION_CALL"}],"usageMetadata":{},"modelVersion":"test","responseId":"123"}"#;
+
+    #[test]
+    fn test_malformed_function_call_requests_retry() {
+        let mut state = StreamingState::new();
+        state.retry_malformed_function_call = true;
+
+        let result = process_sse_line(MALFORMED_LINE, &mut state, "test_id", "test@example.com");
+        assert!(result.is_none());
+        assert!(state.malformed_function_call_retry_requested);
+        assert!(!state.message_start_sent);
+    }
+
+    #[test]
+    fn test_malformed_function_call_mapped_with_note_when_retry_disabled() {
+        let mut state = StreamingState::new();
+
+        let chunks = process_sse_line(MALFORMED_LINE, &mut state, "test_id", "test@example.com").unwrap();
+        let all_text: String = chunks
+            .iter()
+            .map(|b| String::from_utf8(b.to_vec()).unwrap_or_default())
+            .collect();
+
+        assert!(!state.malformed_function_call_retry_requested);
+        assert!(all_text.contains("MALFORMED_FUNCTION_CALL"));
+        assert!(all_text.contains(r#""stop_reason":"end_turn""#));
+        assert!(all_text.contains("message_stop"));
+    }
+
+    const RECITATION_LINE: &str = r#"data: {"candidates":[{"content":{"parts":[]},"finishReason":"RECITATION"}],"usageMetadata":{},"modelVersion":"test","responseId":"123"}"#;
+
+    #[test]
+    fn test_recitation_requests_retry_when_enabled() {
+        let mut state = StreamingState::new();
+        state.retry_recitation = true;
+
+        let result = process_sse_line(RECITATION_LINE, &mut state, "test_id", "test@example.com");
+        assert!(result.is_none());
+        assert!(state.recitation_retry_requested);
+        assert!(!state.message_start_sent);
+    }
+
+    #[test]
+    fn test_recitation_mid_stream_maps_to_refusal_with_note() {
+        let mut state = StreamingState::new();
+        state.retry_recitation = true;
+
+        // 已经输出内容后无法重试，以 refusal 结束并附带说明
+        let text_line = r#"data: {"candidates":[{"content":{"parts":[{"text":"fn main() {"}]}}],"usageMetadata":{},"modelVersion":"test","responseId":"123"}"#;
+        process_sse_line(text_line, &mut state, "test_id", "test@example.com").unwrap();
+        let events = sse_events(&process_sse_line(RECITATION_LINE, &mut state, "test_id", "test@example.com").unwrap());
+
+        assert!(!state.recitation_retry_requested);
+        let note = events
+            .iter()
+            .find(|e| e["type"] == "content_block_delta" && e["delta"]["text"].as_str().map_or(false, |t| t.contains(RECITATION)));
+        assert!(note.is_some());
+        let delta = events.iter().find(|e| e["type"] == "message_delta").unwrap();
+        assert_eq!(delta["delta"]["stop_reason"], "refusal");
+    }
+
+    #[test]
+    fn test_perturb_for_recitation_appends_nonce() {
+        let mut body = serde_json::json!({ "request": { "contents": [] } });
+        perturb_for_recitation(&mut body, "n1");
+        perturb_for_recitation(&mut body, "n2");
+        let parts = body["request"]["systemInstruction"]["parts"].as_array().unwrap();
+        assert_eq!(parts.len(), 2);
+        assert!(parts[1]["text"].as_str().unwrap().contains("n2"));
+
+        // 未指定温度时按 1.0 起算，每次扰动提高一档
+        let temperature = body["request"]["generationConfig"]["temperature"].as_f64().unwrap();
+        assert!((temperature - (1.0 + 2.0 * recitation::RECITATION_TEMPERATURE_BUMP)).abs() < 1e-9);
+    }
 }
@@ -0,0 +1,120 @@
+// RECITATION 处理
+// 上游因输出与已有内容逐字重合而截断时，按 recitation_policy 决定是否重试，
+// 重试后在两次结果中取输出更多的一次返回。
+
+use super::models::{ClaudeResponse, ContentBlock};
+use super::streaming::RECITATION_NOTE;
+use crate::proxy::config::RecitationPolicy;
+
+/// 告知客户端上游以 RECITATION 结束
+pub const FINISH_REASON_HEADER: &str = "X-Finish-Reason";
+
+/// 重试时在原温度 (缺省按 1.0) 基础上提高的幅度
+pub const RECITATION_TEMPERATURE_BUMP: f64 = 0.2;
+
+/// 遇到 RECITATION 时是否重试 (每个请求最多重试一次)
+pub fn should_retry(policy: RecitationPolicy, already_retried: bool) -> bool {
+    policy == RecitationPolicy::Retry && !already_retried
+}
+
+/// 响应是否被 RECITATION 截断 (stop_reason 为 refusal，以末尾的说明文本区分安全拦截)
+pub fn is_recitation(response: &ClaudeResponse) -> bool {
+    response.stop_reason == "refusal"
+        && matches!(response.content.last(), Some(ContentBlock::Text { text }) if text == RECITATION_NOTE)
+}
+
+/// 转换后的 SSE 块是否包含 RECITATION 说明 (用于统计)
+pub fn is_recitation_event(chunk: &[u8]) -> bool {
+    let needle = RECITATION_NOTE.as_bytes();
+    chunk.windows(needle.len()).any(|w| w == needle)
+}
+
+/// 响应中模型实际输出的字符数 (文本、思考与工具调用参数，不含附加的 RECITATION 说明)
+fn output_len(response: &ClaudeResponse) -> usize {
+    response
+        .content
+        .iter()
+        .map(|block| match block {
+            ContentBlock::Text { text } if text == RECITATION_NOTE => 0,
+            ContentBlock::Text { text } => text.chars().count(),
+            ContentBlock::Thinking { thinking, .. } => thinking.chars().count(),
+            ContentBlock::ToolUse { input, .. } => input.to_string().chars().count(),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// 在首次 (RECITATION) 结果与重试结果间取输出更多的一次，相同时取重试结果
+pub fn pick_longer(first: ClaudeResponse, retry: ClaudeResponse) -> ClaudeResponse {
+    if output_len(&first) > output_len(&retry) {
+        first
+    } else {
+        retry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::models::Usage;
+
+    fn response(text: &str, stop_reason: &str) -> ClaudeResponse {
+        ClaudeResponse {
+            id: "msg_1".to_string(),
+            type_: "message".to_string(),
+            role: "assistant".to_string(),
+            model: "gemini-3-flash".to_string(),
+            content: vec![ContentBlock::Text { text: text.to_string() }],
+            stop_reason: stop_reason.to_string(),
+            stop_sequence: None,
+            usage: Usage {
+                input_tokens: 0,
+                output_tokens: 0,
+                cache_read_input_tokens: None,
+                cache_creation_input_tokens: None,
+                server_tool_use: None,
+            },
+        }
+    }
+
+    /// 被 RECITATION 截断的响应：refusal + 末尾说明
+    fn cut_off(text: &str) -> ClaudeResponse {
+        let mut response = response(text, "refusal");
+        response.content.push(ContentBlock::Text { text: RECITATION_NOTE.to_string() });
+        response
+    }
+
+    #[test]
+    fn test_retry_decision() {
+        assert!(should_retry(RecitationPolicy::Retry, false));
+        assert!(!should_retry(RecitationPolicy::Retry, true));
+        assert!(!should_retry(RecitationPolicy::PassThrough, false));
+    }
+
+    #[test]
+    fn test_pick_longer_keeps_more_output() {
+        let first = cut_off("fn main() { println!(\"partial");
+        assert!(is_recitation(&first));
+        // 安全拦截同为 refusal，但没有 RECITATION 说明
+        assert!(!is_recitation(&response("partial", "refusal")));
+
+        // 重试结果更短 (再次被截断) 时保留首次结果
+        let picked = pick_longer(first.clone(), cut_off("fn"));
+        assert_eq!(output_len(&picked), output_len(&first));
+
+        // 说明文本不计入输出长度
+        let full = response("fn main() { println!(\"hello\"); }", "end_turn");
+        let picked = pick_longer(first, full);
+        assert!(!is_recitation(&picked));
+    }
+
+    #[test]
+    fn test_recitation_event_detection() {
+        let note = format!(
+            "event: content_block_delta\ndata: {}",
+            serde_json::json!({ "type": "content_block_delta", "delta": { "type": "text_delta", "text": RECITATION_NOTE } })
+        );
+        assert!(is_recitation_event(note.as_bytes()));
+        assert!(!is_recitation_event(br#"data: {"delta":{"stop_reason":"refusal"}}"#));
+    }
+}
@@ -244,8 +244,8 @@ pub fn transform_claude_request_in(
     // [NEW FIX] Check if target model supports thinking
     // Only models with "-thinking" suffix or Claude models support thinking
     // Regular Gemini models (gemini-2.5-flash, gemini-2.5-pro) do NOT support thinking
-    let target_model_supports_thinking = mapped_model.contains("-thinking") 
-        || mapped_model.starts_with("claude-");
+    let target_model_supports_thinking =
+        crate::proxy::common::model_capabilities::supports_thinking(mapped_model);
     
     if is_thinking_enabled && !target_model_supports_thinking {
         tracing::warn!(
@@ -408,6 +408,28 @@ pub fn transform_claude_request_in(
     Ok(body)
 }
 
+/// RECITATION 重试前扰动请求：略微提高温度，并在 systemInstruction 末尾追加改写提示与随机标记，降低与已有内容的逐字匹配
+pub fn perturb_for_recitation(body: &mut Value, nonce: &str) {
+    use super::recitation::RECITATION_TEMPERATURE_BUMP;
+
+    let part = json!({
+        "text": format!("Prefer paraphrasing over reproducing long verbatim passages. [ref:{}]", nonce)
+    });
+    let request = &mut body["request"];
+    match request
+        .get_mut("systemInstruction")
+        .and_then(|s| s.get_mut("parts"))
+        .and_then(|p| p.as_array_mut())
+    {
+        Some(parts) => parts.push(part),
+        None => request["systemInstruction"] = json!({ "role": "user", "parts": [part] }),
+    }
+
+    let generation_config = &mut request["generationConfig"];
+    let temperature = generation_config.get("temperature").and_then(|t| t.as_f64()).unwrap_or(1.0);
+    generation_config["temperature"] = json!((temperature + RECITATION_TEMPERATURE_BUMP).min(2.0));
+}
+
 /// 检查是否因为历史消息原因需要禁用 Thinking
 /// 
 /// 场景: 如果最后一条 Assistant 消息处于 Tool Use 流程中，但没有 Thinking 块，
@@ -2,7 +2,8 @@
 // 对应 NonStreamingProcessor
 
 use super::models::*;
-use super::utils::to_claude_usage;
+use super::streaming::{RECITATION, RECITATION_NOTE};
+use super::utils::{to_claude_stop_reason, to_claude_usage};
 
 /// Known parameter remappings for Gemini → Claude compatibility
 /// [FIX] Gemini sometimes uses different parameter names than specified in tool schema
@@ -373,13 +374,7 @@ impl NonStreamingProcessor {
             .and_then(|c| c.get(0))
             .and_then(|candidate| candidate.finish_reason.as_deref());
 
-        let stop_reason = if self.has_tool_call {
-            "tool_use"
-        } else if finish_reason == Some("MAX_TOKENS") {
-            "max_tokens"
-        } else {
-            "end_turn"
-        };
+        let stop_reason = to_claude_stop_reason(finish_reason, self.has_tool_call);
 
         let usage = gemini_response
             .usage_metadata
@@ -393,6 +388,14 @@ impl NonStreamingProcessor {
                 server_tool_use: None,
             });
 
+        let mut content = self.content_blocks.clone();
+        if finish_reason == Some(RECITATION) {
+            tracing::warn!("[Claude] Upstream finished with RECITATION, surfacing note");
+            content.push(ContentBlock::Text {
+                text: RECITATION_NOTE.to_string(),
+            });
+        }
+
         ClaudeResponse {
             id: gemini_response.response_id.clone().unwrap_or_else(|| {
                 format!("msg_{}", crate::proxy::common::utils::generate_random_id())
@@ -400,7 +403,7 @@ impl NonStreamingProcessor {
             type_: "message".to_string(),
             role: "assistant".to_string(),
             model: gemini_response.model_version.clone().unwrap_or_default(),
-            content: self.content_blocks.clone(),
+            content,
             stop_reason: stop_reason.to_string(),
             stop_sequence: None,
             usage,
@@ -2,12 +2,25 @@
 // 对应 StreamingState + PartProcessor
 
 use super::models::*;
-use super::utils::to_claude_usage;
+use super::utils::{to_claude_stop_reason, to_claude_usage};
 // use crate::proxy::mappers::signature_store::store_thought_signature; // Deprecated
 use crate::proxy::SignatureCache;
 use bytes::Bytes;
 use serde_json::json;
 
+/// 每个 input_json_delta 分片的最大字符数
+/// Gemini 一次性返回完整的 args 对象，这里切片后逐段下发，模拟 Anthropic 的增量参数流
+const INPUT_JSON_DELTA_CHUNK_CHARS: usize = 64;
+
+/// 按字符边界将 JSON 字符串切分为若干片段
+fn split_partial_json(json_str: &str, max_chars: usize) -> Vec<String> {
+    let chars: Vec<char> = json_str.chars().collect();
+    chars
+        .chunks(max_chars.max(1))
+        .map(|c| c.iter().collect())
+        .collect()
+}
+
 /// Known parameter remappings for Gemini → Claude compatibility
 /// [FIX] Gemini sometimes uses different parameter names than specified in tool schema
 fn remap_function_call_args(tool_name: &str, args: &mut serde_json::Value) {
@@ -140,6 +153,20 @@ impl SignatureManager {
     }
 }
 
+/// Gemini 在工具调用参数无法解析时返回的 finishReason
+pub const MALFORMED_FUNCTION_CALL: &str = "MALFORMED_FUNCTION_CALL";
+
+/// 未重试 MALFORMED_FUNCTION_CALL 时附加到响应中的诊断说明
+pub const MALFORMED_FUNCTION_CALL_NOTE: &str =
+    "[Upstream ended with MALFORMED_FUNCTION_CALL: the model produced an invalid tool call. Please retry the request.]";
+
+/// Gemini 因输出与训练数据逐字重合而中止时返回的 finishReason
+pub const RECITATION: &str = "RECITATION";
+
+/// RECITATION 未被重试时附加到响应中的说明 (stop_reason 为 refusal，以此说明区分安全拦截)
+pub const RECITATION_NOTE: &str =
+    "[Upstream ended with RECITATION: the output was cut off because it closely matched existing content. Try rephrasing the request or asking for a paraphrase.]";
+
 /// 流式状态机
 pub struct StreamingState {
     block_type: BlockType,
@@ -158,6 +185,34 @@ pub struct StreamingState {
     last_valid_state: Option<BlockType>,
     // [NEW] Model tracking for signature cache
     pub model_name: Option<String>,
+    /// 尚未输出任何内容时遇到 MALFORMED_FUNCTION_CALL 是否请求重试
+    pub retry_malformed_function_call: bool,
+    /// 已检测到需要重试的 MALFORMED_FUNCTION_CALL
+    pub malformed_function_call_retry_requested: bool,
+    /// 尚未输出任何内容时遇到 RECITATION 是否请求重试
+    pub retry_recitation: bool,
+    /// 已检测到需要重试的 RECITATION
+    pub recitation_retry_requested: bool,
+    /// 流中途收到 usageMetadata 时发送增量 message_delta
+    pub emit_incremental_usage: bool,
+    /// 最近一次收到的 usageMetadata (结束事件缺少用量时兜底)
+    latest_usage: Option<UsageMetadata>,
+    /// 最近一次发送的增量用量，避免重复发送相同数值
+    last_usage_delta: Option<serde_json::Value>,
+    /// 按工具过滤策略从请求中剔除的工具，模型仍调用时以说明文本代替
+    pub blocked_tools: Vec<String>,
+    /// 输出逐 part 的调试日志 (AppState.stream_debug)
+    pub verbose: bool,
+    /// 暂缓发送最终的 message_delta / message_stop，直到上游流结束 (期间迟到的用量可以并入)
+    pub defer_stop: bool,
+    /// 已处理过 finishReason (重复的结束只有第一次生效)
+    finished: bool,
+    /// 尚未发出的最终 stop_reason 与用量
+    pending_stop: Option<(&'static str, Usage)>,
+    /// 结束后被忽略的上游事件数
+    ignored_after_finish: usize,
+    /// 结束后无法再并入的迟到用量数
+    late_usage_dropped: usize,
 }
 
 impl StreamingState {
@@ -176,7 +231,45 @@ impl StreamingState {
             parse_error_count: 0,
             last_valid_state: None,
             model_name: None,
+            retry_malformed_function_call: false,
+            malformed_function_call_retry_requested: false,
+            retry_recitation: false,
+            recitation_retry_requested: false,
+            emit_incremental_usage: false,
+            latest_usage: None,
+            last_usage_delta: None,
+            blocked_tools: Vec::new(),
+            verbose: false,
+            defer_stop: false,
+            finished: false,
+            pending_stop: None,
+            ignored_after_finish: 0,
+            late_usage_dropped: 0,
+        }
+    }
+
+    /// 逐 part 调试日志的内容，未开启 verbose 时为 None
+    pub fn describe_part(&self, part: &GeminiPart) -> Option<String> {
+        if !self.verbose {
+            return None;
         }
+        let kind = if let Some(fc) = &part.function_call {
+            format!("functionCall name={} args_len={}", fc.name, fc.args.as_ref().map_or(0, |a| a.to_string().len()))
+        } else if let Some(text) = &part.text {
+            let kind = if part.thought.unwrap_or(false) { "thought" } else { "text" };
+            format!("{} len={}", kind, text.chars().count())
+        } else if let Some(img) = &part.inline_data {
+            format!("inlineData mime={}", img.mime_type)
+        } else {
+            "empty".to_string()
+        };
+        Some(format!(
+            "block_index={} block_type={:?} part={} signature={}",
+            self.block_index,
+            self.block_type,
+            kind,
+            part.thought_signature.as_ref().map_or(0, |s| s.len())
+        ))
     }
 
     /// 发送 SSE 事件
@@ -221,6 +314,8 @@ impl StreamingState {
 
         if let Some(u) = usage {
             message["usage"] = json!(u);
+            // message_start 已携带的用量不再作为增量重复发送
+            self.last_usage_delta = Some(message["usage"].clone());
         }
 
         let result = self.emit(
@@ -307,12 +402,39 @@ impl StreamingState {
         )
     }
 
+    /// 发送流中途的增量用量 (message_delta 不带 stop_reason)，用量未变化时不发送
+    pub fn emit_usage_delta(&mut self, usage_metadata: &UsageMetadata) -> Option<Bytes> {
+        if !self.emit_incremental_usage || !self.message_start_sent || self.finished {
+            return None;
+        }
+        self.latest_usage = Some(usage_metadata.clone());
+
+        let usage = json!(to_claude_usage(usage_metadata));
+        if self.last_usage_delta.as_ref() == Some(&usage) {
+            return None;
+        }
+        self.last_usage_delta = Some(usage.clone());
+
+        Some(self.emit(
+            "message_delta",
+            json!({
+                "type": "message_delta",
+                "delta": { "stop_reason": null, "stop_sequence": null },
+                "usage": usage
+            }),
+        ))
+    }
+
     /// 发送结束事件
     pub fn emit_finish(
         &mut self,
         finish_reason: Option<&str>,
         usage_metadata: Option<&UsageMetadata>,
     ) -> Vec<Bytes> {
+        if self.finished {
+            return vec![];
+        }
+        self.finished = true;
         let mut chunks = Vec::new();
 
         // 关闭最后一个块
@@ -340,6 +462,32 @@ impl StreamingState {
             self.block_index += 1;
         }
 
+        // MALFORMED_FUNCTION_CALL 未被重试时，附加诊断说明，避免客户端收到无解释的空回复
+        if finish_reason == Some(MALFORMED_FUNCTION_CALL) && !self.used_tool {
+            tracing::warn!("[Claude-Stream] Upstream finished with MALFORMED_FUNCTION_CALL, surfacing diagnostic note");
+            chunks.push(self.emit("content_block_start", json!({
+                "type": "content_block_start",
+                "index": self.block_index,
+                "content_block": { "type": "text", "text": "" }
+            })));
+            chunks.push(self.emit_delta("text_delta", json!({ "text": MALFORMED_FUNCTION_CALL_NOTE })));
+            chunks.push(self.emit("content_block_stop", json!({ "type": "content_block_stop", "index": self.block_index })));
+            self.block_index += 1;
+        }
+
+        // RECITATION 会截断输出，附加说明避免客户端误以为回复已完整
+        if finish_reason == Some(RECITATION) {
+            tracing::warn!("[Claude-Stream] Upstream finished with RECITATION, surfacing note");
+            chunks.push(self.emit("content_block_start", json!({
+                "type": "content_block_start",
+                "index": self.block_index,
+                "content_block": { "type": "text", "text": "" }
+            })));
+            chunks.push(self.emit_delta("text_delta", json!({ "text": RECITATION_NOTE })));
+            chunks.push(self.emit("content_block_stop", json!({ "type": "content_block_stop", "index": self.block_index })));
+            self.block_index += 1;
+        }
+
         // 处理 grounding(web search) -> 转换为 Markdown 文本块
         if self.web_search_query.is_some() || self.grounding_chunks.is_some() {
             let mut grounding_text = String::new();
@@ -383,15 +531,11 @@ impl StreamingState {
         }
 
         // 确定 stop_reason
-        let stop_reason = if self.used_tool {
-            "tool_use"
-        } else if finish_reason == Some("MAX_TOKENS") {
-            "max_tokens"
-        } else {
-            "end_turn"
-        };
+        let stop_reason = to_claude_stop_reason(finish_reason, self.used_tool);
 
+        // 结束事件的用量为权威值；缺失时使用流中途最近一次的用量
         let usage = usage_metadata
+            .or(self.latest_usage.as_ref())
             .map(|u| to_claude_usage(u))
             .unwrap_or(Usage {
                 input_tokens: 0,
@@ -401,25 +545,67 @@ impl StreamingState {
                 server_tool_use: None,
             });
 
-        chunks.push(self.emit(
-            "message_delta",
-            json!({
-                "type": "message_delta",
-                "delta": { "stop_reason": stop_reason, "stop_sequence": null },
-                "usage": usage
-            }),
-        ));
+        self.pending_stop = Some((stop_reason, usage));
+        if !self.defer_stop {
+            chunks.extend(self.flush_stop());
+        }
 
-        if !self.message_stop_sent {
-            chunks.push(Bytes::from(
-                "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        chunks
+    }
+
+    /// 发出暂缓的 message_delta 与 message_stop (只发送一次)
+    pub fn flush_stop(&mut self) -> Vec<Bytes> {
+        if self.message_stop_sent {
+            return vec![];
+        }
+        let mut chunks = Vec::new();
+        if let Some((stop_reason, usage)) = self.pending_stop.take() {
+            chunks.push(self.emit(
+                "message_delta",
+                json!({
+                    "type": "message_delta",
+                    "delta": { "stop_reason": stop_reason, "stop_sequence": null },
+                    "usage": usage
+                }),
             ));
-            self.message_stop_sent = true;
         }
-
+        chunks.push(Bytes::from(
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        ));
+        self.message_stop_sent = true;
         chunks
     }
 
+    /// 是否已处理过 finishReason
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// 结束后迟到的用量：最终 message_delta 尚未发出时以其为准，否则丢弃并计数
+    pub fn merge_late_usage(&mut self, usage_metadata: &UsageMetadata) -> bool {
+        match self.pending_stop.as_mut() {
+            Some((_, usage)) if !self.message_stop_sent => {
+                *usage = to_claude_usage(usage_metadata);
+                self.latest_usage = Some(usage_metadata.clone());
+                true
+            }
+            _ => {
+                self.late_usage_dropped += 1;
+                false
+            }
+        }
+    }
+
+    /// 记录一个结束后被忽略的上游事件
+    pub fn note_ignored_after_finish(&mut self) {
+        self.ignored_after_finish += 1;
+    }
+
+    /// 结束后被忽略的事件数与丢弃的迟到用量数
+    pub fn after_finish_counts(&self) -> (usize, usize) {
+        (self.ignored_after_finish, self.late_usage_dropped)
+    }
+
     /// 标记使用了工具
     pub fn mark_tool_used(&mut self) {
         self.used_tool = true;
@@ -533,6 +719,9 @@ impl<'a> PartProcessor<'a> {
     /// 处理单个 part
     pub fn process(&mut self, part: &GeminiPart) -> Vec<Bytes> {
         let mut chunks = Vec::new();
+        if let Some(detail) = self.state.describe_part(part) {
+            tracing::info!("[Claude-SSE][stream-debug] {}", detail);
+        }
         // [FIX #545] Decode Base64 signature if present (Gemini sends Base64, Claude expects Raw)
         let signature = part.thought_signature.as_ref().map(|sig| {
              // Try to decode as base64
@@ -553,6 +742,13 @@ impl<'a> PartProcessor<'a> {
 
         // 1. FunctionCall 处理
         if let Some(fc) = &part.function_call {
+            // 被策略剔除的工具不交给客户端执行
+            if self.state.blocked_tools.iter().any(|b| b == &fc.name) {
+                tracing::warn!("[Claude-SSE] Blocked call to tool '{}' removed by proxy policy", fc.name);
+                chunks.extend(self.process_text(&super::tool_filter::blocked_tool_note(&fc.name), None));
+                return chunks;
+            }
+
             // 先处理 trailingSignature (B4/C3 场景)
             if self.state.has_trailing_signature() {
                 chunks.extend(self.state.end_block());
@@ -788,17 +984,19 @@ impl<'a> PartProcessor<'a> {
 
         chunks.extend(self.state.start_block(BlockType::Function, tool_use));
 
-        // 2. 发送 input_json_delta (完整的参数 JSON 字符串)
+        // 2. 分片发送 input_json_delta (拼接后为完整的参数 JSON 字符串)
         // [FIX] Remap args before serialization for Gemini → Claude compatibility
         if let Some(args) = &fc.args {
             let mut remapped_args = args.clone();
             remap_function_call_args(&fc.name, &mut remapped_args);
             let json_str =
                 serde_json::to_string(&remapped_args).unwrap_or_else(|_| "{}".to_string());
-            chunks.push(
-                self.state
-                    .emit_delta("input_json_delta", json!({ "partial_json": json_str })),
-            );
+            for fragment in split_partial_json(&json_str, INPUT_JSON_DELTA_CHUNK_CHARS) {
+                chunks.push(
+                    self.state
+                        .emit_delta("input_json_delta", json!({ "partial_json": fragment })),
+                );
+            }
         }
 
         // 3. 结束块
@@ -812,6 +1010,27 @@ impl<'a> PartProcessor<'a> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_stream_debug_toggles_part_logging() {
+        let part: GeminiPart = serde_json::from_value(json!({
+            "text": "hello",
+            "thought": true,
+            "thoughtSignature": "sig123"
+        }))
+        .unwrap();
+
+        let mut state = StreamingState::new();
+        assert!(state.describe_part(&part).is_none());
+
+        state.verbose = true;
+        let detail = state.describe_part(&part).unwrap();
+        assert!(detail.contains("part=thought len=5"), "{}", detail);
+        assert!(detail.contains("signature=6"));
+
+        state.verbose = false;
+        assert!(state.describe_part(&part).is_none());
+    }
+
     #[test]
     fn test_signature_manager() {
         let mut mgr = SignatureManager::new();
@@ -878,4 +1097,61 @@ mod tests {
         // 3. content_block_stop
         assert!(output.contains(r#""type":"content_block_stop""#));
     }
+
+    #[test]
+    fn test_process_function_call_streams_partial_json() {
+        let mut state = StreamingState::new();
+        let mut processor = PartProcessor::new(&mut state);
+
+        let long_value = "参数".repeat(40) + &"x".repeat(100);
+        let args = json!({ "path": "/tmp/file.txt", "content": long_value });
+        let part = GeminiPart {
+            text: None,
+            function_call: Some(FunctionCall {
+                name: "write_file".to_string(),
+                args: Some(args.clone()),
+                id: Some("call_456".to_string()),
+            }),
+            inline_data: None,
+            thought: None,
+            thought_signature: None,
+            function_response: None,
+        };
+
+        let events: Vec<serde_json::Value> = processor
+            .process(&part)
+            .iter()
+            .map(|b| String::from_utf8(b.to_vec()).unwrap())
+            .flat_map(|s| {
+                s.lines()
+                    .filter_map(|l| l.strip_prefix("data: "))
+                    .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        // content_block_start (tool_use) → 多个 input_json_delta → content_block_stop
+        assert_eq!(events.first().unwrap()["type"], "content_block_start");
+        assert_eq!(events.first().unwrap()["content_block"]["type"], "tool_use");
+        assert_eq!(events.last().unwrap()["type"], "content_block_stop");
+
+        let deltas = &events[1..events.len() - 1];
+        assert!(deltas.len() > 1, "expected args to stream in multiple fragments");
+        assert!(deltas.iter().all(|e| e["type"] == "content_block_delta"
+            && e["delta"]["type"] == "input_json_delta"));
+
+        let reassembled: String = deltas
+            .iter()
+            .map(|e| e["delta"]["partial_json"].as_str().unwrap())
+            .collect();
+        let parsed: serde_json::Value = serde_json::from_str(&reassembled).unwrap();
+        assert_eq!(parsed, args);
+    }
+
+    #[test]
+    fn test_split_partial_json_respects_char_boundaries() {
+        let parts = split_partial_json("{\"k\":\"中文字符\"}", 3);
+        assert!(parts.iter().all(|p| p.chars().count() <= 3));
+        assert_eq!(parts.concat(), "{\"k\":\"中文字符\"}");
+    }
 }
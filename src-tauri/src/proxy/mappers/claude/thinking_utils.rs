@@ -1,4 +1,5 @@
 use super::models::{Message, MessageContent, ContentBlock};
+use crate::proxy::config::ToolLoopRecoveryMode;
 use tracing::info;
 
 #[derive(Debug, Default)]
@@ -105,3 +106,44 @@ pub fn close_tool_loop_for_thinking(messages: &mut Vec<Message>) {
         });
     }
 }
+
+/// Remove unsigned thinking blocks from the last assistant message of a broken tool loop
+///
+/// Unlike `close_tool_loop_for_thinking`, no synthetic messages are injected. The ToolUse
+/// blocks stay in place, and the request falls back to non-thinking mode for this turn
+/// (see `should_disable_thinking_due_to_history`).
+pub fn strip_unsigned_thinking_in_tool_loop(messages: &mut Vec<Message>) {
+    let state = analyze_conversation_state(messages);
+
+    if !state.in_tool_loop {
+        return;
+    }
+
+    let Some(idx) = state.last_assistant_idx else {
+        return;
+    };
+
+    if let Some(MessageContent::Array(blocks)) = messages.get_mut(idx).map(|m| &mut m.content) {
+        let original_len = blocks.len();
+        blocks.retain(|b| match b {
+            ContentBlock::Thinking { signature, .. } => {
+                signature.as_ref().map_or(false, |s| !s.is_empty())
+            }
+            _ => true,
+        });
+
+        let removed = original_len - blocks.len();
+        if removed > 0 {
+            info!("[Thinking-Recovery] Stripped {} unsigned thinking block(s) from broken tool loop.", removed);
+        }
+    }
+}
+
+/// Apply tool loop recovery according to the configured mode
+pub fn recover_tool_loop(messages: &mut Vec<Message>, mode: ToolLoopRecoveryMode) {
+    match mode {
+        ToolLoopRecoveryMode::Off => {}
+        ToolLoopRecoveryMode::Synthetic => close_tool_loop_for_thinking(messages),
+        ToolLoopRecoveryMode::Strip => strip_unsigned_thinking_in_tool_loop(messages),
+    }
+}
@@ -0,0 +1,141 @@
+// 工具过滤
+// 转发前按 tool_filter 策略剔除不允许的工具；模型若仍调用了被剔除的工具，
+// 不把该调用交给客户端执行，而是以一段说明文本代替。
+// (assistant 消息中不能出现 tool_result，故以文本块告知模型与客户端)
+
+use super::models::{ClaudeRequest, ClaudeResponse, ContentBlock};
+
+/// 被拦截的工具调用替换成的说明文本
+pub fn blocked_tool_note(name: &str) -> String {
+    format!("[Tool '{}' is not permitted by proxy policy]", name)
+}
+
+/// 从请求中剔除不允许的工具，返回被剔除的工具名
+///
+/// 服务端工具 (如 web_search) 没有名称时按 type 判断；全部剔除后置空 `tools`，
+/// 转换时便不会生成工具声明与 toolConfig
+pub fn strip_denied_tools(req: &mut ClaudeRequest, permits: impl Fn(&str) -> bool) -> Vec<String> {
+    let Some(tools) = req.tools.as_mut() else {
+        return Vec::new();
+    };
+
+    let mut removed = Vec::new();
+    tools.retain(|tool| {
+        let name = tool.name.as_deref().or(tool.type_.as_deref()).unwrap_or_default();
+        if name.is_empty() || permits(name) {
+            true
+        } else {
+            removed.push(name.to_string());
+            false
+        }
+    });
+
+    if tools.is_empty() {
+        req.tools = None;
+    }
+    removed
+}
+
+/// 把非流式响应中对被剔除工具的调用替换为说明文本
+///
+/// 全部调用都被拦截时 stop_reason 改为 end_turn，避免客户端等待工具结果
+pub fn replace_blocked_tool_uses(response: &mut ClaudeResponse, blocked: &[String]) {
+    if blocked.is_empty() {
+        return;
+    }
+
+    let mut replaced = false;
+    for block in response.content.iter_mut() {
+        if let ContentBlock::ToolUse { name, .. } = block {
+            if blocked.iter().any(|b| b == name) {
+                *block = ContentBlock::Text { text: blocked_tool_note(name) };
+                replaced = true;
+            }
+        }
+    }
+
+    let has_tool_use = response
+        .content
+        .iter()
+        .any(|block| matches!(block, ContentBlock::ToolUse { .. }));
+    if replaced && !has_tool_use && response.stop_reason == "tool_use" {
+        response.stop_reason = "end_turn".to_string();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::models::Usage;
+    use serde_json::json;
+
+    fn request_with_tools(names: &[&str]) -> ClaudeRequest {
+        let tools: Vec<_> = names
+            .iter()
+            .map(|n| json!({ "name": n, "input_schema": { "type": "object" } }))
+            .collect();
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{ "role": "user", "content": "hi" }],
+            "tools": tools
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_strip_denied_tools() {
+        let mut req = request_with_tools(&["Read", "Bash", "WebFetch"]);
+        let removed = strip_denied_tools(&mut req, |name| name != "Bash" && name != "WebFetch");
+
+        assert_eq!(removed, vec!["Bash".to_string(), "WebFetch".to_string()]);
+        let kept: Vec<_> = req.tools.as_ref().unwrap().iter().filter_map(|t| t.name.clone()).collect();
+        assert_eq!(kept, vec!["Read".to_string()]);
+    }
+
+    #[test]
+    fn test_strip_all_tools_drops_tool_config() {
+        let mut req = request_with_tools(&["Bash"]);
+        let removed = strip_denied_tools(&mut req, |_| false);
+        assert_eq!(removed, vec!["Bash".to_string()]);
+        assert!(req.tools.is_none());
+
+        // 转换后的请求不再携带工具声明与 toolConfig
+        let body = super::super::transform_claude_request_in(&req, "test-project").unwrap();
+        let request = &body["request"];
+        assert!(request.get("tools").is_none());
+        assert!(request.get("toolConfig").is_none());
+    }
+
+    #[test]
+    fn test_replace_blocked_tool_uses() {
+        let mut response = ClaudeResponse {
+            id: "msg_1".to_string(),
+            type_: "message".to_string(),
+            role: "assistant".to_string(),
+            model: "gemini-3-flash".to_string(),
+            content: vec![
+                ContentBlock::Text { text: "Running it".to_string() },
+                ContentBlock::ToolUse {
+                    id: "call_1".to_string(),
+                    name: "Bash".to_string(),
+                    input: json!({ "command": "rm -rf /" }),
+                    signature: None,
+                    cache_control: None,
+                },
+            ],
+            stop_reason: "tool_use".to_string(),
+            stop_sequence: None,
+            usage: Usage {
+                input_tokens: 0,
+                output_tokens: 0,
+                cache_read_input_tokens: None,
+                cache_creation_input_tokens: None,
+                server_tool_use: None,
+            },
+        };
+
+        replace_blocked_tool_uses(&mut response, &["Bash".to_string()]);
+        assert!(matches!(&response.content[1], ContentBlock::Text { text } if text == &blocked_tool_note("Bash")));
+        assert_eq!(response.stop_reason, "end_turn");
+    }
+}
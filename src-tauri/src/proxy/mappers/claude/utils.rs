@@ -23,6 +23,24 @@ pub fn to_claude_usage(usage_metadata: &super::models::UsageMetadata) -> super::
     }
 }
 
+/// Gemini finishReason -> Claude stop_reason
+///
+/// 工具调用优先；截断映射为 max_tokens，复述截断与安全拦截映射为 refusal (Anthropic 没有复述截断专用的取值)，其余视为正常结束
+pub fn to_claude_stop_reason(finish_reason: Option<&str>, used_tool: bool) -> &'static str {
+    if used_tool {
+        return "tool_use";
+    }
+    match finish_reason {
+        Some("MAX_TOKENS") => "max_tokens",
+        Some(super::streaming::RECITATION)
+        | Some("SAFETY")
+        | Some("PROHIBITED_CONTENT")
+        | Some("BLOCKLIST")
+        | Some("SPII") => "refusal",
+        _ => "end_turn",
+    }
+}
+
 /// 提取 thoughtSignature
 // 已移除未使用的 extract_thought_signature 函数
 
@@ -48,4 +66,27 @@ mod tests {
         assert_eq!(claude_usage.input_tokens, 100);
         assert_eq!(claude_usage.output_tokens, 50);
     }
+
+    #[test]
+    fn test_finish_reason_mapping_table() {
+        use crate::proxy::mappers::openai::to_openai_finish_reason;
+
+        // (Gemini finishReason, Claude stop_reason, OpenAI finish_reason)
+        let cases = [
+            (Some("STOP"), "end_turn", "stop"),
+            (Some("MAX_TOKENS"), "max_tokens", "length"),
+            (Some("SAFETY"), "refusal", "content_filter"),
+            (Some("RECITATION"), "refusal", "content_filter"),
+            (Some("OTHER"), "end_turn", "stop"),
+            (Some("FINISH_REASON_UNSPECIFIED"), "end_turn", "stop"),
+            (None, "end_turn", "stop"),
+        ];
+        for (gemini, claude, openai) in cases {
+            assert_eq!(to_claude_stop_reason(gemini, false), claude, "claude mapping for {:?}", gemini);
+            assert_eq!(to_openai_finish_reason(gemini), openai, "openai mapping for {:?}", gemini);
+        }
+
+        // 工具调用优先于截断原因
+        assert_eq!(to_claude_stop_reason(Some("MAX_TOKENS"), true), "tool_use");
+    }
 }
@@ -5,6 +5,7 @@ use super::models::*;
 use bytes::Bytes;
 use futures::StreamExt;
 use serde_json::Value;
+use std::collections::BTreeMap;
 use std::io;
 
 /// SSE 事件类型
@@ -13,6 +14,14 @@ struct SseEvent {
     data: Value,
 }
 
+/// 单个候选的累积状态
+#[derive(Default)]
+struct ChoiceAccumulator {
+    content: String,
+    tool_calls: Vec<ToolCall>,
+    finish_reason: Option<String>,
+}
+
 /// 解析 SSE 行
 fn parse_sse_line(line: &str) -> Option<(String, String)> {
     if let Some(colon_pos) = line.find(':') {
@@ -68,11 +77,16 @@ where
         choices: vec![],
     };
 
-    let mut content = String::new();
-    let mut tool_calls: Vec<ToolCall> = Vec::new();
-    let mut finish_reason: Option<String> = None;
+    // n > 1 时各候选按 choice.index 分别累积
+    let mut accumulated: BTreeMap<u32, ChoiceAccumulator> = BTreeMap::new();
 
     for event in chunks {
+        // 流中途出错时上游转换器会发送错误帧，非流式客户端应收到错误而不是残缺的结果
+        if let Some(err) = event.data.get("error") {
+            let message = err.get("message").and_then(|v| v.as_str()).unwrap_or("unknown stream error");
+            return Err(format!("Stream error: {}", message));
+        }
+
         // 提取基本信息
         if let Some(id) = event.data.get("id").and_then(|v| v.as_str()) {
             response.id = id.to_string();
@@ -87,10 +101,13 @@ where
         // 处理 choices
         if let Some(choices_arr) = event.data.get("choices").and_then(|v| v.as_array()) {
             for choice in choices_arr {
+                let choice_index = choice.get("index").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+                let acc = accumulated.entry(choice_index).or_default();
+
                 if let Some(delta) = choice.get("delta") {
                     // 累积 content
                     if let Some(text) = delta.get("content").and_then(|v| v.as_str()) {
-                        content.push_str(text);
+                        acc.content.push_str(text);
                     }
 
                     // 累积 tool_calls
@@ -99,8 +116,8 @@ where
                             let index = tc.get("index").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
                             
                             // 确保 tool_calls 有足够的空间
-                            while tool_calls.len() <= index {
-                                tool_calls.push(ToolCall {
+                            while acc.tool_calls.len() <= index {
+                                acc.tool_calls.push(ToolCall {
                                     id: String::new(),
                                     r#type: "function".to_string(),
                                     function: ToolFunction {
@@ -111,14 +128,14 @@ where
                             }
 
                             if let Some(id) = tc.get("id").and_then(|v| v.as_str()) {
-                                tool_calls[index].id = id.to_string();
+                                acc.tool_calls[index].id = id.to_string();
                             }
                             if let Some(func) = tc.get("function") {
                                 if let Some(name) = func.get("name").and_then(|v| v.as_str()) {
-                                    tool_calls[index].function.name = name.to_string();
+                                    acc.tool_calls[index].function.name = name.to_string();
                                 }
                                 if let Some(args) = func.get("arguments").and_then(|v| v.as_str()) {
-                                    tool_calls[index].function.arguments.push_str(args);
+                                    acc.tool_calls[index].function.arguments.push_str(args);
                                 }
                             }
                         }
@@ -127,7 +144,7 @@ where
 
                 // 获取 finish_reason
                 if let Some(reason) = choice.get("finish_reason").and_then(|v| v.as_str()) {
-                    finish_reason = Some(reason.to_string());
+                    acc.finish_reason = Some(reason.to_string());
                 }
             }
         }
@@ -135,32 +152,40 @@ where
         // OpenAIResponse 没有 usage 字段，跳过
     }
 
-    // 3. 构建最终的 choice
-    let message = if !tool_calls.is_empty() {
-        OpenAIMessage {
-            role: "assistant".to_string(),
-            content: if content.is_empty() { None } else { Some(OpenAIContent::String(content)) },
-            tool_calls: Some(tool_calls),
-            reasoning_content: None,
-            tool_call_id: None,
-            name: None,
-        }
-    } else {
-        OpenAIMessage {
-            role: "assistant".to_string(),
-            content: Some(OpenAIContent::String(content)),
-            tool_calls: None,
-            reasoning_content: None,
-            tool_call_id: None,
-            name: None,
-        }
-    };
+    // 流中没有任何 choice 时仍返回一个空的 choice
+    if accumulated.is_empty() {
+        accumulated.insert(0, ChoiceAccumulator::default());
+    }
 
-    response.choices.push(Choice {
-        index: 0,
-        message,
-        finish_reason,
-    });
+    // 3. 构建最终的 choices
+    for (index, acc) in accumulated {
+        let message = if !acc.tool_calls.is_empty() {
+            OpenAIMessage {
+                role: "assistant".to_string(),
+                content: if acc.content.is_empty() { None } else { Some(OpenAIContent::String(acc.content)) },
+                tool_calls: Some(acc.tool_calls),
+                reasoning_content: None,
+                tool_call_id: None,
+                name: None,
+            }
+        } else {
+            OpenAIMessage {
+                role: "assistant".to_string(),
+                content: Some(OpenAIContent::String(acc.content)),
+                tool_calls: None,
+                reasoning_content: None,
+                tool_call_id: None,
+                name: None,
+            }
+        };
+
+        response.choices.push(Choice {
+            index,
+            message,
+            finish_reason: acc.finish_reason,
+            logprobs: None,
+        });
+    }
 
     Ok(response)
 }
@@ -0,0 +1,147 @@
+// n > 1 扇出合并
+// 目标模型不支持 candidateCount 时，handler 并发发出 n 个单候选请求，这里把各自的结果合并为带不同 index 的 choices
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+use std::pin::Pin;
+
+pub type SseStream = Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>>;
+
+/// 合并非流式响应：按子请求顺序为 choices 重新编号，其余字段沿用第一个响应
+pub fn merge_completions(responses: Vec<Value>) -> Value {
+    let mut choices: Vec<Value> = responses
+        .iter()
+        .filter_map(|r| r.get("choices").and_then(Value::as_array))
+        .flatten()
+        .cloned()
+        .collect();
+    for (index, choice) in choices.iter_mut().enumerate() {
+        if let Some(obj) = choice.as_object_mut() {
+            obj.insert("index".to_string(), json!(index));
+        }
+    }
+
+    let mut merged = responses.into_iter().next().unwrap_or_else(|| json!({}));
+    if let Some(obj) = merged.as_object_mut() {
+        obj.insert("choices".to_string(), Value::Array(choices));
+    }
+    merged
+}
+
+/// 改写单个 SSE 事件中 choices 的 index；`[DONE]` 返回 None (由合并流统一发送)
+fn reindex_event(event: &str, index: usize) -> Option<String> {
+    let Some(data) = event.strip_prefix("data:") else {
+        return Some(event.to_string());
+    };
+    let data = data.trim();
+    if data == "[DONE]" {
+        return None;
+    }
+    let Ok(mut json) = serde_json::from_str::<Value>(data) else {
+        return Some(event.to_string());
+    };
+    if let Some(choices) = json.get_mut("choices").and_then(Value::as_array_mut) {
+        for choice in choices.iter_mut().filter_map(Value::as_object_mut) {
+            choice.insert("index".to_string(), json!(index));
+        }
+    }
+    Some(format!("data: {}", json))
+}
+
+/// 合并 n 个子流：第 i 个子流的 choice 记为 index i，丢弃各自的 [DONE]，全部结束后统一发送一次
+pub fn merge_streams(streams: Vec<SseStream>) -> SseStream {
+    let reindexed = streams.into_iter().enumerate().map(|(index, mut stream)| {
+        Box::pin(async_stream::stream! {
+            let mut buffer: Vec<u8> = Vec::new();
+            while let Some(item) = stream.next().await {
+                match item {
+                    Ok(bytes) => {
+                        buffer.extend_from_slice(&bytes);
+                        while let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+                            let event: Vec<u8> = buffer.drain(..end + 2).collect();
+                            if let Some(out) = reindex_event(&String::from_utf8_lossy(&event[..end]), index) {
+                                yield Ok(Bytes::from(format!("{}\n\n", out)));
+                            }
+                        }
+                    }
+                    Err(e) => yield Err(e),
+                }
+            }
+        }) as SseStream
+    });
+
+    Box::pin(
+        futures::stream::select_all(reindexed)
+            .chain(futures::stream::once(async { Ok(Bytes::from_static(b"data: [DONE]\n\n")) })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(content: &str, finish: Option<&str>) -> String {
+        format!(
+            "data: {}\n\n",
+            json!({
+                "id": "chatcmpl-x",
+                "object": "chat.completion.chunk",
+                "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": finish }]
+            })
+        )
+    }
+
+    #[test]
+    fn test_merge_completions_renumbers_choices() {
+        let single = |text: &str| {
+            json!({
+                "id": "chatcmpl-x",
+                "object": "chat.completion",
+                "choices": [{ "index": 0, "message": { "role": "assistant", "content": text }, "finish_reason": "stop" }]
+            })
+        };
+        let merged = merge_completions(vec![single("a"), single("b")]);
+        let choices = merged["choices"].as_array().unwrap();
+        assert_eq!(choices.len(), 2);
+        assert_eq!(choices[0]["index"], 0);
+        assert_eq!(choices[1]["index"], 1);
+        assert_eq!(choices[1]["message"]["content"], "b");
+        assert_eq!(merged["id"], "chatcmpl-x");
+    }
+
+    #[tokio::test]
+    async fn test_merge_streams_reindexes_and_sends_one_done() {
+        let sub = |text: &'static str| -> SseStream {
+            // 事件跨分片边界，验证按空行重新切分
+            let body = format!("{}{}data: [DONE]\n\n", chunk(text, None), chunk("", Some("stop")));
+            let (head, tail) = body.split_at(10);
+            Box::pin(futures::stream::iter(vec![
+                Ok(Bytes::from(head.to_string())),
+                Ok(Bytes::from(tail.to_string())),
+            ]))
+        };
+        let out: Vec<Bytes> = merge_streams(vec![sub("first"), sub("second")])
+            .map(|c| c.unwrap())
+            .collect()
+            .await;
+        let text: String = out.iter().map(|b| String::from_utf8_lossy(b).into_owned()).collect();
+
+        assert_eq!(text.matches("[DONE]").count(), 1);
+        assert!(text.ends_with("data: [DONE]\n\n"));
+        let events: Vec<Value> = text
+            .split("\n\n")
+            .filter_map(|e| e.strip_prefix("data: "))
+            .filter_map(|d| serde_json::from_str(d).ok())
+            .collect();
+        let content_of = |index: u64| -> String {
+            events
+                .iter()
+                .filter(|e| e["choices"][0]["index"] == index)
+                .filter_map(|e| e["choices"][0]["delta"]["content"].as_str())
+                .collect()
+        };
+        assert_eq!(content_of(0), "first");
+        assert_eq!(content_of(1), "second");
+    }
+}
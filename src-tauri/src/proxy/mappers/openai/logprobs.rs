@@ -0,0 +1,149 @@
+// OpenAI logprobs / top_logprobs 映射
+// 支持的模型映射为 Gemini 的 responseLogprobs / logprobs，并把 logprobsResult 转回 OpenAI 格式；
+// 不支持的模型按配置忽略或返回 invalid_request_error。
+
+use super::models::OpenAIRequest;
+use crate::proxy::config::LogprobsMode;
+use serde_json::{json, Value};
+
+/// 上游支持 responseLogprobs 的模型前缀
+const SUPPORTED_MODEL_PREFIXES: &[&str] = &["gemini-1.5-", "gemini-2.0-flash", "gemini-2.5-flash"];
+
+/// Gemini 每步最多返回的候选数
+const MAX_TOP_LOGPROBS: u32 = 20;
+
+pub fn model_supports_logprobs(mapped_model: &str) -> bool {
+    SUPPORTED_MODEL_PREFIXES.iter().any(|p| mapped_model.starts_with(p))
+}
+
+/// 请求了 logprobs 时返回每步的候选数 (0 表示只返回选中 token 的概率)
+pub fn requested_top_logprobs(request: &OpenAIRequest) -> Option<u32> {
+    match &request.logprobs {
+        Some(Value::Bool(true)) => Some(request.top_logprobs.unwrap_or(0)),
+        // 旧版 completions 接口：logprobs 为候选数量
+        Some(Value::Number(n)) => Some(n.as_u64().unwrap_or(0) as u32),
+        _ => request.top_logprobs.filter(|n| *n > 0),
+    }
+}
+
+/// 校验 logprobs 参数；模型不支持且配置为报错时返回错误信息
+pub fn check_logprobs(request: &OpenAIRequest, mapped_model: &str, mode: LogprobsMode) -> Result<(), String> {
+    if requested_top_logprobs(request).is_none() || model_supports_logprobs(mapped_model) {
+        return Ok(());
+    }
+    match mode {
+        LogprobsMode::Ignore => {
+            tracing::debug!("[OpenAI-Request] logprobs not supported by {}, ignoring", mapped_model);
+            Ok(())
+        }
+        LogprobsMode::Error => Err(format!(
+            "logprobs is not supported for model '{}'",
+            request.model
+        )),
+    }
+}
+
+/// 将 logprobs 参数写入 generationConfig (模型不支持时不写入)
+pub fn apply_to_generation_config(gen_config: &mut Value, request: &OpenAIRequest, mapped_model: &str) {
+    let Some(top) = requested_top_logprobs(request) else {
+        return;
+    };
+    if !model_supports_logprobs(mapped_model) {
+        return;
+    }
+    gen_config["responseLogprobs"] = json!(true);
+    if top > 0 {
+        gen_config["logprobs"] = json!(top.min(MAX_TOP_LOGPROBS));
+    }
+}
+
+fn token_entry(candidate: &Value) -> Value {
+    let token = candidate.get("token").and_then(|v| v.as_str()).unwrap_or_default();
+    json!({
+        "token": token,
+        "logprob": candidate.get("logProbability").and_then(|v| v.as_f64()).unwrap_or(0.0),
+        "bytes": token.as_bytes(),
+    })
+}
+
+/// Gemini logprobsResult -> OpenAI choice.logprobs
+pub fn to_openai_logprobs(result: &Value) -> Option<Value> {
+    let chosen = result.get("chosenCandidates")?.as_array()?;
+    if chosen.is_empty() {
+        return None;
+    }
+    let top = result.get("topCandidates").and_then(|v| v.as_array());
+
+    let content: Vec<Value> = chosen
+        .iter()
+        .enumerate()
+        .map(|(i, candidate)| {
+            let mut entry = token_entry(candidate);
+            entry["top_logprobs"] = top
+                .and_then(|steps| steps.get(i))
+                .and_then(|step| step.get("candidates"))
+                .and_then(|v| v.as_array())
+                .map(|candidates| candidates.iter().map(token_entry).collect())
+                .unwrap_or_else(|| json!([]));
+            entry
+        })
+        .collect();
+    Some(json!({ "content": content }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(body: Value) -> OpenAIRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn test_unsupported_model_follows_mode() {
+        let req = request(json!({
+            "model": "gpt-4o",
+            "messages": [{ "role": "user", "content": "hi" }],
+            "logprobs": true,
+            "top_logprobs": 3
+        }));
+        let model = "gemini-3-pro-high";
+
+        assert!(check_logprobs(&req, model, LogprobsMode::Ignore).is_ok());
+        let err = check_logprobs(&req, model, LogprobsMode::Error).unwrap_err();
+        assert!(err.contains("gpt-4o"));
+
+        let mut gen_config = json!({});
+        apply_to_generation_config(&mut gen_config, &req, model);
+        assert!(gen_config.get("responseLogprobs").is_none());
+
+        // 未请求 logprobs 时两种模式都放行
+        let plain = request(json!({ "model": "gpt-4o", "messages": [] }));
+        assert!(check_logprobs(&plain, model, LogprobsMode::Error).is_ok());
+    }
+
+    #[test]
+    fn test_supported_model_maps_both_directions() {
+        let req = request(json!({ "model": "gpt-4o", "messages": [], "logprobs": 50 }));
+        assert!(check_logprobs(&req, "gemini-2.0-flash", LogprobsMode::Error).is_ok());
+
+        let mut gen_config = json!({});
+        apply_to_generation_config(&mut gen_config, &req, "gemini-2.0-flash");
+        assert_eq!(gen_config["responseLogprobs"], true);
+        assert_eq!(gen_config["logprobs"], MAX_TOP_LOGPROBS);
+
+        let result = json!({
+            "topCandidates": [{ "candidates": [
+                { "token": "Hi", "logProbability": -0.1 },
+                { "token": "Hey", "logProbability": -2.5 }
+            ] }],
+            "chosenCandidates": [{ "token": "Hi", "logProbability": -0.1 }]
+        });
+        let mapped = to_openai_logprobs(&result).unwrap();
+        assert_eq!(mapped["content"][0]["token"], "Hi");
+        assert_eq!(mapped["content"][0]["logprob"], -0.1);
+        assert_eq!(mapped["content"][0]["bytes"], json!([72, 105]));
+        assert_eq!(mapped["content"][0]["top_logprobs"][1]["token"], "Hey");
+        assert!(to_openai_logprobs(&json!({})).is_none());
+    }
+}
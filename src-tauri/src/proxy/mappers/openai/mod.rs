@@ -6,6 +6,8 @@ pub mod request;
 pub mod response;
 pub mod streaming;
 pub mod collector;
+pub mod logprobs;
+pub mod fanout;
 
 pub use models::*;
 pub use request::*;
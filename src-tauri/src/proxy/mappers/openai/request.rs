@@ -229,8 +229,11 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
     });
 
     // [NEW] 支持多候选结果数量 (n -> candidateCount)
-    if let Some(n) = request.n {
-        gen_config["candidateCount"] = json!(n);
+    // 不支持的模型由 handler 拆分为多个单候选请求
+    if let Some(n) = request.n.filter(|&n| n > 1) {
+        if crate::proxy::common::model_capabilities::supports_candidate_count(mapped_model) {
+            gen_config["candidateCount"] = json!(n);
+        }
     }
 
     // [FIX PR #368] 为 Gemini 3 Pro 注入 thinkingConfig (使用 thinkingBudget 而非 thinkingLevel)
@@ -248,6 +251,8 @@ pub fn transform_openai_request(request: &OpenAIRequest, project_id: &str, mappe
         else if stop.is_array() { gen_config["stopSequences"] = stop.clone(); }
     }
 
+    super::logprobs::apply_to_generation_config(&mut gen_config, request, mapped_model);
+
     if let Some(fmt) = &request.response_format {
         if fmt.r#type == "json_object" {
             gen_config["responseMimeType"] = json!("application/json");
@@ -441,8 +446,11 @@ mod tests {
             tools: None,
             tool_choice: None,
             parallel_tool_calls: None,
+            logprobs: None,
+            top_logprobs: None,
             instructions: None,
             input: None,
+            user: None,
             prompt: None,
         };
 
@@ -452,4 +460,18 @@ mod tests {
         assert_eq!(parts[0]["text"].as_str().unwrap(), "What is in this image?");
         assert_eq!(parts[1]["inlineData"]["mimeType"].as_str().unwrap(), "image/png");
     }
+
+    #[test]
+    fn test_end_user_is_not_forwarded() {
+        let req: OpenAIRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4",
+            "messages": [{ "role": "user", "content": "hi" }],
+            "user": "employee-1234"
+        }))
+        .unwrap();
+        assert_eq!(req.user.as_deref(), Some("employee-1234"));
+
+        let result = transform_openai_request(&req, "test-v", "gemini-1.5-flash");
+        assert!(!result.to_string().contains("employee-1234"));
+    }
 }
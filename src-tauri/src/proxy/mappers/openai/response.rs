@@ -2,6 +2,17 @@
 use super::models::*;
 use serde_json::Value;
 
+/// Gemini finishReason -> OpenAI finish_reason (未知或缺失时为 stop)
+pub fn to_openai_finish_reason(finish_reason: Option<&str>) -> &'static str {
+    match finish_reason {
+        Some("MAX_TOKENS") => "length",
+        Some("SAFETY") | Some("RECITATION") | Some("PROHIBITED_CONTENT") | Some("BLOCKLIST") | Some("SPII") => {
+            "content_filter"
+        }
+        _ => "stop",
+    }
+}
+
 pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
     // 解包 response 字段
     let raw = gemini_response.get("response").unwrap_or(gemini_response);
@@ -123,17 +134,8 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
             }
 
             // 提取该候选结果的 finish_reason
-            let finish_reason = candidate
-                .get("finishReason")
-                .and_then(|f| f.as_str())
-                .map(|f| match f {
-                    "STOP" => "stop",
-                    "MAX_TOKENS" => "length",
-                    "SAFETY" => "content_filter",
-                    "RECITATION" => "content_filter",
-                    _ => "stop",
-                })
-                .unwrap_or("stop");
+            let finish_reason =
+                to_openai_finish_reason(candidate.get("finishReason").and_then(|f| f.as_str()));
 
             choices.push(Choice {
                 index: idx as u32,
@@ -158,6 +160,7 @@ pub fn transform_openai_response(gemini_response: &Value) -> OpenAIResponse {
                     name: None,
                 },
                 finish_reason: Some(finish_reason.to_string()),
+                logprobs: candidate.get("logprobsResult").and_then(super::logprobs::to_openai_logprobs),
             });
         }
     }
@@ -1,5 +1,5 @@
 // OpenAI 流式转换
-use bytes::{Bytes, BytesMut};
+use bytes::Bytes;
 use futures::{Stream, StreamExt};
 use serde_json::{json, Value};
 use std::pin::Pin;
@@ -9,6 +9,9 @@ use uuid::Uuid;
 use tracing::debug;
 use rand::Rng;
 
+use super::response::to_openai_finish_reason;
+use crate::proxy::common::sse_parser::SseEventParser;
+
 // === 全局 ThoughtSignature 存储 ===
 // 用于在流式响应和后续请求之间传递签名，避免嵌入到用户可见的文本中
 static GLOBAL_THOUGHT_SIG: OnceLock<Mutex<Option<String>>> = OnceLock::new();
@@ -50,192 +53,216 @@ pub fn get_thought_signature() -> Option<String> {
     }
 }
 
+/// 流中途出错时发送的错误帧 (对齐 OpenAI 流式错误格式)，附带已知的部分 usage 供监控统计
+fn stream_error_frame(message: &str, usage_metadata: Option<&Value>) -> Bytes {
+    let mut frame = json!({
+        "error": {
+            "message": message,
+            "type": "upstream_error",
+            "param": null,
+            "code": "stream_interrupted"
+        }
+    });
+    if let Some(u) = usage_metadata {
+        let prompt = u.get("promptTokenCount").and_then(|v| v.as_u64()).unwrap_or(0);
+        let completion = u.get("candidatesTokenCount").and_then(|v| v.as_u64()).unwrap_or(0);
+        frame["usage"] = json!({
+            "prompt_tokens": prompt,
+            "completion_tokens": completion,
+            "total_tokens": u.get("totalTokenCount").and_then(|v| v.as_u64()).unwrap_or(prompt + completion)
+        });
+    }
+    Bytes::from(format!("data: {}\n\ndata: [DONE]\n\n", frame))
+}
+
 pub fn create_openai_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     model: String,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
-    let mut buffer = BytesMut::new();
+    let mut sse_parser = SseEventParser::new();
     
     // 在流开始时生成固定的 ID 和 timestamp，所有 chunk 共用
     let stream_id = format!("chatcmpl-{}", Uuid::new_v4());
     let created_ts = Utc::now().timestamp();
     
     let stream = async_stream::stream! {
+        // 尚未向客户端写出任何字节时出错，直接返回 Err 让处理器重试；否则发送错误帧后正常结束
+        let mut emitted_any = false;
+        let mut last_usage: Option<Value> = None;
+
         while let Some(item) = gemini_stream.next().await {
             match item {
                 Ok(bytes) => {
                     // Verbose logging for debugging image fragmentation
                     debug!("[OpenAI-SSE] Received chunk: {} bytes", bytes.len());
-                    buffer.extend_from_slice(&bytes);
                     
-                    // Process complete lines from buffer
-                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                        let line_raw = buffer.split_to(pos + 1);
-                        if let Ok(line_str) = std::str::from_utf8(&line_raw) {
-                            let line = line_str.trim();
-                            if line.is_empty() { continue; }
-
-                            if line.starts_with("data: ") {
-                                let json_part = line.trim_start_matches("data: ").trim();
-                                if json_part == "[DONE]" {
-                                    continue;
-                                }
+                    // Process complete events
+                    for data in sse_parser.push(&bytes) {
+                        let json_part = data.trim();
+                        if json_part == "[DONE]" { continue; }
+
+                        if let Ok(mut json) = serde_json::from_str::<Value>(json_part) {
+                            // Log raw chunk for debugging gemini-3 thoughts
+                            tracing::debug!("Gemini SSE Chunk: {}", json_part);
+
+                            // Handle v1internal wrapper if present
+                            let actual_data = if let Some(inner) = json.get_mut("response").map(|v| v.take()) {
+                                inner
+                            } else {
+                                json
+                            };
 
-                                if let Ok(mut json) = serde_json::from_str::<Value>(json_part) {
-                                    // Log raw chunk for debugging gemini-3 thoughts
-                                    tracing::debug!("Gemini SSE Chunk: {}", json_part);
-
-                                    // Handle v1internal wrapper if present
-                                    let actual_data = if let Some(inner) = json.get_mut("response").map(|v| v.take()) {
-                                        inner
-                                    } else {
-                                        json
-                                    };
-
-                                    // Extract candidates
-                                    if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
-                                        for (idx, candidate) in candidates.iter().enumerate() {
-                                            let parts = candidate.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array());
-
-                                            let mut content_out = String::new();
-                                            let mut thought_out = String::new();
-                                            
-                                            if let Some(parts_list) = parts {
-                                                for part in parts_list {
-                                                    let is_thought_part = part.get("thought")
-                                                        .and_then(|v| v.as_bool())
-                                                        .unwrap_or(false);
-                                                    
-                                                    if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                                                        if is_thought_part {
-                                                            thought_out.push_str(text);
-                                                        } else {
-                                                            content_out.push_str(text);
-                                                        }
-                                                    }
-                                                    // 捕获 thoughtSignature (Gemini 3 工具调用必需)
-                                                    if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
-                                                        store_thought_signature(sig);
-                                                    }
+                            if let Some(usage) = actual_data.get("usageMetadata") {
+                                last_usage = Some(usage.clone());
+                            }
 
-                                                    if let Some(img) = part.get("inlineData") {
-                                                        let mime_type = img.get("mimeType").and_then(|v| v.as_str()).unwrap_or("image/png");
-                                                        let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
-                                                        if !data.is_empty() {
-                                                            content_out.push_str(&format!("![image](data:{};base64,{})", mime_type, data));
-                                                        }
-                                                    }
+                            // Extract candidates
+                            if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
+                                for (position, candidate) in candidates.iter().enumerate() {
+                                    // candidateCount > 1 时每个分片可能只携带部分候选，以 Gemini 给出的 index 为准
+                                    let idx = candidate.get("index").and_then(|v| v.as_u64()).map_or(position, |i| i as usize);
+                                    let parts = candidate.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array());
+
+                                    let mut content_out = String::new();
+                                    let mut thought_out = String::new();
+
+                                    if let Some(parts_list) = parts {
+                                        for part in parts_list {
+                                            let is_thought_part = part.get("thought")
+                                                .and_then(|v| v.as_bool())
+                                                .unwrap_or(false);
+
+                                            if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                                                if is_thought_part {
+                                                    thought_out.push_str(text);
+                                                } else {
+                                                    content_out.push_str(text);
                                                 }
                                             }
+                                            // 捕获 thoughtSignature (Gemini 3 工具调用必需)
+                                            if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
+                                                store_thought_signature(sig);
+                                            }
 
-
-                                            // 处理联网搜索引文 (Grounding Metadata) - 流式
-                                            if let Some(grounding) = candidate.get("groundingMetadata") {
-                                                let mut grounding_text = String::new();
-                                                
-                                                // 1. 处理搜索词
-                                                if let Some(queries) = grounding.get("webSearchQueries").and_then(|q| q.as_array()) {
-                                                    let query_list: Vec<&str> = queries.iter().filter_map(|v| v.as_str()).collect();
-                                                    if !query_list.is_empty() {
-                                                        grounding_text.push_str("\n\n---\n**🔍 已为您搜索：** ");
-                                                        grounding_text.push_str(&query_list.join(", "));
-                                                    }
+                                            if let Some(img) = part.get("inlineData") {
+                                                let mime_type = img.get("mimeType").and_then(|v| v.as_str()).unwrap_or("image/png");
+                                                let data = img.get("data").and_then(|v| v.as_str()).unwrap_or("");
+                                                if !data.is_empty() {
+                                                    content_out.push_str(&format!("![image](data:{};base64,{})", mime_type, data));
                                                 }
+                                            }
+                                        }
+                                    }
 
-                                                // 2. 处理来源链接 (Chunks)
-                                                if let Some(chunks) = grounding.get("groundingChunks").and_then(|c| c.as_array()) {
-                                                    let mut links = Vec::new();
-                                                    for (i, chunk) in chunks.iter().enumerate() {
-                                                        if let Some(web) = chunk.get("web") {
-                                                            let title = web.get("title").and_then(|v| v.as_str()).unwrap_or("网页来源");
-                                                            let uri = web.get("uri").and_then(|v| v.as_str()).unwrap_or("#");
-                                                            links.push(format!("[{}] [{}]({})", i + 1, title, uri));
-                                                        }
-                                                    }
-                                                    if !links.is_empty() {
-                                                        grounding_text.push_str("\n\n**🌐 来源引文：**\n");
-                                                        grounding_text.push_str(&links.join("\n"));
-                                                    }
-                                                }
-                                                
-                                                if !grounding_text.is_empty() {
-                                                    content_out.push_str(&grounding_text);
-                                                }
+
+                                    // 处理联网搜索引文 (Grounding Metadata) - 流式
+                                    if let Some(grounding) = candidate.get("groundingMetadata") {
+                                        let mut grounding_text = String::new();
+
+                                        // 1. 处理搜索词
+                                        if let Some(queries) = grounding.get("webSearchQueries").and_then(|q| q.as_array()) {
+                                            let query_list: Vec<&str> = queries.iter().filter_map(|v| v.as_str()).collect();
+                                            if !query_list.is_empty() {
+                                                grounding_text.push_str("\n\n---\n**🔍 已为您搜索：** ");
+                                                grounding_text.push_str(&query_list.join(", "));
                                             }
+                                        }
 
-                                            // 只有当 content 和 thought 都为空时才跳过
-                                            if content_out.is_empty() && thought_out.is_empty() {
-                                                // Skip empty chunks if no text/grounding/thought was found
-                                                if candidate.get("finishReason").is_none() {
-                                                    continue;
+                                        // 2. 处理来源链接 (Chunks)
+                                        if let Some(chunks) = grounding.get("groundingChunks").and_then(|c| c.as_array()) {
+                                            let mut links = Vec::new();
+                                            for (i, chunk) in chunks.iter().enumerate() {
+                                                if let Some(web) = chunk.get("web") {
+                                                    let title = web.get("title").and_then(|v| v.as_str()).unwrap_or("网页来源");
+                                                    let uri = web.get("uri").and_then(|v| v.as_str()).unwrap_or("#");
+                                                    links.push(format!("[{}] [{}]({})", i + 1, title, uri));
                                                 }
                                             }
-                                                
-                                            // Extract finish reason
-                                            let finish_reason = candidate.get("finishReason")
-                                                .and_then(|f| f.as_str())
-                                                .map(|f| match f {
-                                                    "STOP" => "stop",
-                                                    "MAX_TOKENS" => "length",
-                                                    "SAFETY" => "content_filter",
-                                                    "RECITATION" => "content_filter",
-                                                    _ => f,
-                                                });
-
-                                            // Construct OpenAI SSE chunk
-                                            // 如果有思考内容，先发送 reasoning_content chunk
-                                            if !thought_out.is_empty() {
-                                                let reasoning_chunk = json!({
-                                                    "id": &stream_id,
-                                                    "object": "chat.completion.chunk",
-                                                    "created": created_ts,
-                                                    "model": model,
-                                                    "choices": [
-                                                        {
-                                                            "index": idx as u32,
-                                                            "delta": {
-                                                                "role": "assistant",
-                                                                "content": serde_json::Value::Null,
-                                                                "reasoning_content": thought_out
-                                                            },
-                                                            "finish_reason": serde_json::Value::Null
-                                                        }
-                                                    ]
-                                                });
-                                                let sse_out = format!("data: {}\n\n", serde_json::to_string(&reasoning_chunk).unwrap_or_default());
-                                                yield Ok::<Bytes, String>(Bytes::from(sse_out));
+                                            if !links.is_empty() {
+                                                grounding_text.push_str("\n\n**🌐 来源引文：**\n");
+                                                grounding_text.push_str(&links.join("\n"));
                                             }
+                                        }
 
-                                            // 发送正常 content chunk
-                                            if !content_out.is_empty() || finish_reason.is_some() {
-                                                let openai_chunk = json!({
-                                                    "id": &stream_id,
-                                                    "object": "chat.completion.chunk",
-                                                    "created": created_ts,
-                                                    "model": model,
-                                                    "choices": [
-                                                        {
-                                                            "index": idx as u32,
-                                                            "delta": {
-                                                                "content": content_out
-                                                            },
-                                                            "finish_reason": finish_reason
-                                                        }
-                                                    ]
-                                                });
+                                        if !grounding_text.is_empty() {
+                                            content_out.push_str(&grounding_text);
+                                        }
+                                    }
 
-                                                let sse_out = format!("data: {}\n\n", serde_json::to_string(&openai_chunk).unwrap_or_default());
-                                                yield Ok::<Bytes, String>(Bytes::from(sse_out));
-                                            }
+                                    // 只有当 content 和 thought 都为空时才跳过
+                                    if content_out.is_empty() && thought_out.is_empty() {
+                                        // Skip empty chunks if no text/grounding/thought was found
+                                        if candidate.get("finishReason").is_none() {
+                                            continue;
                                         }
                                     }
+
+                                    // Extract finish reason
+                                    let finish_reason = candidate.get("finishReason")
+                                        .and_then(|f| f.as_str())
+                                        .map(|f| to_openai_finish_reason(Some(f)));
+
+                                    // Construct OpenAI SSE chunk
+                                    // 如果有思考内容，先发送 reasoning_content chunk
+                                    if !thought_out.is_empty() {
+                                        let reasoning_chunk = json!({
+                                            "id": &stream_id,
+                                            "object": "chat.completion.chunk",
+                                            "created": created_ts,
+                                            "model": model,
+                                            "choices": [
+                                                {
+                                                    "index": idx as u32,
+                                                    "delta": {
+                                                        "role": "assistant",
+                                                        "content": serde_json::Value::Null,
+                                                        "reasoning_content": thought_out
+                                                    },
+                                                    "finish_reason": serde_json::Value::Null
+                                                }
+                                            ]
+                                        });
+                                        let sse_out = format!("data: {}\n\n", serde_json::to_string(&reasoning_chunk).unwrap_or_default());
+                                        emitted_any = true;
+                                        yield Ok::<Bytes, String>(Bytes::from(sse_out));
+                                    }
+
+                                    // 发送正常 content chunk
+                                    if !content_out.is_empty() || finish_reason.is_some() {
+                                        let openai_chunk = json!({
+                                            "id": &stream_id,
+                                            "object": "chat.completion.chunk",
+                                            "created": created_ts,
+                                            "model": model,
+                                            "choices": [
+                                                {
+                                                    "index": idx as u32,
+                                                    "delta": {
+                                                        "content": content_out
+                                                    },
+                                                    "finish_reason": finish_reason
+                                                }
+                                            ]
+                                        });
+
+                                        let sse_out = format!("data: {}\n\n", serde_json::to_string(&openai_chunk).unwrap_or_default());
+                                        emitted_any = true;
+                                        yield Ok::<Bytes, String>(Bytes::from(sse_out));
+                                    }
                                 }
                             }
                         }
                     }
                 }
                 Err(e) => {
-                    yield Err(format!("Upstream error: {}", e));
+                    let message = format!("Upstream error: {}", e);
+                    if !emitted_any {
+                        yield Err(message);
+                    } else {
+                        tracing::warn!("[OpenAI-SSE] Stream interrupted mid-response: {}", message);
+                        yield Ok::<Bytes, String>(stream_error_frame(&message, last_usage.as_ref()));
+                    }
+                    return;
                 }
             }
         }
@@ -250,7 +277,7 @@ pub fn create_legacy_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     model: String,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
-    let mut buffer = BytesMut::new();
+    let mut sse_parser = SseEventParser::new();
     
     // Generate constant alphanumeric ID (mimics OpenAI base62 format)
     let charset = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
@@ -265,82 +292,83 @@ pub fn create_legacy_sse_stream(
     let created_ts = Utc::now().timestamp(); 
     
     let stream = async_stream::stream! {
+        let mut emitted_any = false;
+        let mut last_usage: Option<Value> = None;
+
         while let Some(item) = gemini_stream.next().await {
             match item {
                 Ok(bytes) => {
-                    buffer.extend_from_slice(&bytes);
-                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                        let line_raw = buffer.split_to(pos + 1);
-                        if let Ok(line_str) = std::str::from_utf8(&line_raw) {
-                            let line = line_str.trim();
-                            if line.is_empty() { continue; }
-
-                            if line.starts_with("data: ") {
-                                let json_part = line.trim_start_matches("data: ").trim();
-                                if json_part == "[DONE]" { continue; }
-
-                                if let Ok(mut json) = serde_json::from_str::<Value>(json_part) {
-                                    let actual_data = if let Some(inner) = json.get_mut("response").map(|v| v.take()) { inner } else { json };
-                                    
-                                    let mut content_out = String::new();
-                                    if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
-                                        if let Some(parts) = candidates.get(0).and_then(|c| c.get("content")).and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
-                                            for part in parts {
-                                                if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                                                    content_out.push_str(text);
-                                                }
-                                                /* 禁用思维链输出到正文
-                                                if let Some(thought_text) = part.get("thought").and_then(|t| t.as_str()) {
-                                                    // // content_out.push_str(thought_text);
-                                                }
-                                                */
-                                                // 捕获 thoughtSignature
-                                                // 捕获 thoughtSignature 到全局存储
-                                                if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
-                                                    store_thought_signature(sig);
-                                                }
-                                            }
+                    for data in sse_parser.push(&bytes) {
+                        let json_part = data.trim();
+                        if json_part == "[DONE]" { continue; }
+
+                        if let Ok(mut json) = serde_json::from_str::<Value>(json_part) {
+                            let actual_data = if let Some(inner) = json.get_mut("response").map(|v| v.take()) { inner } else { json };
+                            if let Some(usage) = actual_data.get("usageMetadata") {
+                                last_usage = Some(usage.clone());
+                            }
+
+                            let mut content_out = String::new();
+                            if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
+                                if let Some(parts) = candidates.get(0).and_then(|c| c.get("content")).and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
+                                    for part in parts {
+                                        if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                                            content_out.push_str(text);
+                                        }
+                                        /* 禁用思维链输出到正文
+                                        if let Some(thought_text) = part.get("thought").and_then(|t| t.as_str()) {
+                                            // // content_out.push_str(thought_text);
+                                        }
+                                        */
+                                        // 捕获 thoughtSignature
+                                        // 捕获 thoughtSignature 到全局存储
+                                        if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
+                                            store_thought_signature(sig);
                                         }
                                     }
-
-                                    let finish_reason = actual_data.get("candidates")
-                                        .and_then(|c| c.as_array())
-                                        .and_then(|c| c.get(0))
-                                        .and_then(|c| c.get("finishReason"))
-                                        .and_then(|f| f.as_str())
-                                        .map(|f| match f {
-                                            "STOP" => "stop",
-                                            "MAX_TOKENS" => "length",
-                                            "SAFETY" => "content_filter",
-                                            _ => f,
-                                        });
-
-                                    // Construct LEGACY completion chunk - STRICT VERSION
-                                    let legacy_chunk = json!({
-                                        "id": &stream_id,
-                                        "object": "text_completion",
-                                        "created": created_ts,
-                                        "model": &model,
-                                        "choices": [
-                                            {
-                                                "text": content_out,
-                                                "index": 0,
-                                                "logprobs": null,
-                                                "finish_reason": finish_reason // Will be null if None
-                                            }
-                                        ]
-                                    });
-
-                                    let json_str = serde_json::to_string(&legacy_chunk).unwrap_or_default();
-                                    tracing::debug!("Legacy Stream Chunk: {}", json_str); 
-                                    let sse_out = format!("data: {}\n\n", json_str);
-                                    yield Ok::<Bytes, String>(Bytes::from(sse_out));
                                 }
                             }
+
+                            let finish_reason = actual_data.get("candidates")
+                                .and_then(|c| c.as_array())
+                                .and_then(|c| c.get(0))
+                                .and_then(|c| c.get("finishReason"))
+                                .and_then(|f| f.as_str())
+                                .map(|f| to_openai_finish_reason(Some(f)));
+
+                            // Construct LEGACY completion chunk - STRICT VERSION
+                            let legacy_chunk = json!({
+                                "id": &stream_id,
+                                "object": "text_completion",
+                                "created": created_ts,
+                                "model": &model,
+                                "choices": [
+                                    {
+                                        "text": content_out,
+                                        "index": 0,
+                                        "logprobs": null,
+                                        "finish_reason": finish_reason // Will be null if None
+                                    }
+                                ]
+                            });
+
+                            let json_str = serde_json::to_string(&legacy_chunk).unwrap_or_default();
+                            tracing::debug!("Legacy Stream Chunk: {}", json_str); 
+                            let sse_out = format!("data: {}\n\n", json_str);
+                            emitted_any = true;
+                            yield Ok::<Bytes, String>(Bytes::from(sse_out));
                         }
                     }
                 }
-                Err(e) => yield Err(format!("Upstream error: {}", e)),
+                Err(e) => {
+                    let message = format!("Upstream error: {}", e);
+                    if !emitted_any {
+                        yield Err(message);
+                    } else {
+                        yield Ok::<Bytes, String>(stream_error_frame(&message, last_usage.as_ref()));
+                    }
+                    return;
+                }
             }
         }
         tracing::debug!("Stream finished. Yielding [DONE]");
@@ -356,7 +384,7 @@ pub fn create_codex_sse_stream(
     mut gemini_stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
     _model: String,
 ) -> Pin<Box<dyn Stream<Item = Result<Bytes, String>> + Send>> {
-    let mut buffer = BytesMut::new();
+    let mut sse_parser = SseEventParser::new();
     
     // Generate alphanumeric ID
     let charset = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
@@ -387,223 +415,212 @@ pub fn create_codex_sse_stream(
         while let Some(item) = gemini_stream.next().await {
             match item {
                 Ok(bytes) => {
-                    buffer.extend_from_slice(&bytes);
-                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                        let line_raw = buffer.split_to(pos + 1);
-                        if let Ok(line_str) = std::str::from_utf8(&line_raw) {
-                            let line = line_str.trim();
-                            if line.is_empty() || !line.starts_with("data: ") { continue; }
-                            
-                            let json_part = line.trim_start_matches("data: ").trim();
-                            if json_part == "[DONE]" { continue; }
-
-                            if let Ok(mut json) = serde_json::from_str::<Value>(json_part) {
-                                let actual_data = if let Some(inner) = json.get_mut("response").map(|v| v.take()) { inner } else { json };
-                                
-                                // Capture finish reason
-                                if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
-                                    if let Some(candidate) = candidates.get(0) {
-                                        if let Some(reason) = candidate.get("finishReason").and_then(|r| r.as_str()) {
-                                            last_finish_reason = match reason {
-                                                "STOP" => "stop".to_string(),
-                                                "MAX_TOKENS" => "length".to_string(),
-                                                _ => "stop".to_string(),
-                                            };
-                                        }
+                    for data in sse_parser.push(&bytes) {
+                        let json_part = data.trim();
+                        if json_part == "[DONE]" { continue; }
+
+                        if let Ok(mut json) = serde_json::from_str::<Value>(json_part) {
+                            let actual_data = if let Some(inner) = json.get_mut("response").map(|v| v.take()) { inner } else { json };
+
+                            // Capture finish reason
+                            if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
+                                if let Some(candidate) = candidates.get(0) {
+                                    if let Some(reason) = candidate.get("finishReason").and_then(|r| r.as_str()) {
+                                        last_finish_reason = to_openai_finish_reason(Some(reason)).to_string();
                                     }
                                 }
+                            }
 
-                                // text delta
-                                let mut delta_text = String::new();
-                                if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
-                                    if let Some(candidate) = candidates.get(0) {
-                                        if let Some(parts) = candidate.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
-                                            for part in parts {
-                                                if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                                                    // Sanitize smart quotes to standard quotes for JSON compatibility
-                                                    let clean_text = text.replace('“', "\"").replace('”', "\"");
-                                                    delta_text.push_str(&clean_text);
-                                                }
-                                                /* 禁用思维链输出到正文
-                                                if let Some(thought_text) = part.get("thought").and_then(|t| t.as_str()) {
-                                                    let clean_thought = thought_text.replace('"', "\"").replace('"', "\"");
-                                                    // delta_text.push_str(&clean_thought);
-                                                }
-                                                */
-                                                // 捕获 thoughtSignature (Gemini 3 工具调用必需)
-                                                // 存储到全局状态，不再嵌入到用户可见的文本中
-                                                if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
-                                                    tracing::debug!("[Codex-SSE] 捕获 thoughtSignature (长度: {})", sig.len());
-                                                    store_thought_signature(sig);
-                                                }
-                                                // Handle function call in chunk with deduplication
-                                                if let Some(func_call) = part.get("functionCall") {
-                                                    let call_key = serde_json::to_string(func_call).unwrap_or_default();
-                                                    if !emitted_tool_calls.contains(&call_key) {
-                                                        emitted_tool_calls.insert(call_key);
-
-                                                                                let name = func_call.get("name").and_then(|v| v.as_str()).unwrap_or("unknown");
-                                                                                let _args = func_call.get("args").unwrap_or(&json!({})).to_string();                                                        
-                                                        // Stable ID generation based on hashed content to be consistent
-                                                        let mut hasher = std::collections::hash_map::DefaultHasher::new();
-                                                        use std::hash::{Hash, Hasher};
-                                                        serde_json::to_string(func_call).unwrap_or_default().hash(&mut hasher);
-                                                        let call_id = format!("call_{:x}", hasher.finish());
-                                                        
-                                                        // Parse args once
-                                                        let fallback_args = json!({});
-                                                        let args_obj = func_call.get("args").unwrap_or(&fallback_args);
-                                                        // Fallback for function_call arguments string
-                                                        let args_str = args_obj.to_string();
-
-                                                        let name_str = name.to_string();
-                                                        
-                                                        // Determine event type based on tool name
-                                                        // 使用 Option 来允许某些情况跳过工具调用
-                                                        let maybe_item_added_ev: Option<Value> = if name_str == "shell" || name_str == "local_shell" {
-                                                            // Map to local_shell_call
-                                                            tracing::debug!("[Debug] func_call: {}", serde_json::to_string(&func_call).unwrap_or_default());
-                                                            tracing::debug!("[Debug] args_obj: {}", serde_json::to_string(&args_obj).unwrap_or_default());
-                                                            
-                                                            // 解析命令：支持数组格式、字符串格式，以及空 args 情况
-                                                            let cmd_vec: Vec<String> = if args_obj.as_object().map(|o| o.is_empty()).unwrap_or(true) {
-                                                                // args 为空时使用静默成功命令，避免任务中断
-                                                                tracing::debug!("shell command args 为空，使用静默成功命令继续流程");
-                                                                vec!["powershell.exe".to_string(), "-Command".to_string(), "exit 0".to_string()]
-                                                            } else if let Some(arr) = args_obj.get("command").and_then(|v| v.as_array()) {
-                                                                // 数组格式
-                                                                arr.iter().filter_map(|v| v.as_str()).map(|s| s.to_string()).collect()
-                                                            } else if let Some(cmd_str) = args_obj.get("command").and_then(|v| v.as_str()) {
-                                                                // 字符串格式
-                                                                if cmd_str.contains(' ') {
-                                                                    vec!["powershell.exe".to_string(), "-Command".to_string(), cmd_str.to_string()]
-                                                                } else {
-                                                                    vec![cmd_str.to_string()]
-                                                                }
+                            // text delta
+                            let mut delta_text = String::new();
+                            if let Some(candidates) = actual_data.get("candidates").and_then(|c| c.as_array()) {
+                                if let Some(candidate) = candidates.get(0) {
+                                    if let Some(parts) = candidate.get("content").and_then(|c| c.get("parts")).and_then(|p| p.as_array()) {
+                                        for part in parts {
+                                            if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                                                // Sanitize smart quotes to standard quotes for JSON compatibility
+                                                let clean_text = text.replace('“', "\"").replace('”', "\"");
+                                                delta_text.push_str(&clean_text);
+                                            }
+                                            /* 禁用思维链输出到正文
+                                            if let Some(thought_text) = part.get("thought").and_then(|t| t.as_str()) {
+                                                let clean_thought = thought_text.replace('"', "\"").replace('"', "\"");
+                                                // delta_text.push_str(&clean_thought);
+                                            }
+                                            */
+                                            // 捕获 thoughtSignature (Gemini 3 工具调用必需)
+                                            // 存储到全局状态，不再嵌入到用户可见的文本中
+                                            if let Some(sig) = part.get("thoughtSignature").or(part.get("thought_signature")).and_then(|s| s.as_str()) {
+                                                tracing::debug!("[Codex-SSE] 捕获 thoughtSignature (长度: {})", sig.len());
+                                                store_thought_signature(sig);
+                                            }
+                                            // Handle function call in chunk with deduplication
+                                            if let Some(func_call) = part.get("functionCall") {
+                                                let call_key = serde_json::to_string(func_call).unwrap_or_default();
+                                                if !emitted_tool_calls.contains(&call_key) {
+                                                    emitted_tool_calls.insert(call_key);
+
+                                                                            let name = func_call.get("name").and_then(|v| v.as_str()).unwrap_or("unknown");
+                                                                            let _args = func_call.get("args").unwrap_or(&json!({})).to_string();                                                        
+                                                    // Stable ID generation based on hashed content to be consistent
+                                                    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                                                    use std::hash::{Hash, Hasher};
+                                                    serde_json::to_string(func_call).unwrap_or_default().hash(&mut hasher);
+                                                    let call_id = format!("call_{:x}", hasher.finish());
+
+                                                    // Parse args once
+                                                    let fallback_args = json!({});
+                                                    let args_obj = func_call.get("args").unwrap_or(&fallback_args);
+                                                    // Fallback for function_call arguments string
+                                                    let args_str = args_obj.to_string();
+
+                                                    let name_str = name.to_string();
+
+                                                    // Determine event type based on tool name
+                                                    // 使用 Option 来允许某些情况跳过工具调用
+                                                    let maybe_item_added_ev: Option<Value> = if name_str == "shell" || name_str == "local_shell" {
+                                                        // Map to local_shell_call
+                                                        tracing::debug!("[Debug] func_call: {}", serde_json::to_string(&func_call).unwrap_or_default());
+                                                        tracing::debug!("[Debug] args_obj: {}", serde_json::to_string(&args_obj).unwrap_or_default());
+
+                                                        // 解析命令：支持数组格式、字符串格式，以及空 args 情况
+                                                        let cmd_vec: Vec<String> = if args_obj.as_object().map(|o| o.is_empty()).unwrap_or(true) {
+                                                            // args 为空时使用静默成功命令，避免任务中断
+                                                            tracing::debug!("shell command args 为空，使用静默成功命令继续流程");
+                                                            vec!["powershell.exe".to_string(), "-Command".to_string(), "exit 0".to_string()]
+                                                        } else if let Some(arr) = args_obj.get("command").and_then(|v| v.as_array()) {
+                                                            // 数组格式
+                                                            arr.iter().filter_map(|v| v.as_str()).map(|s| s.to_string()).collect()
+                                                        } else if let Some(cmd_str) = args_obj.get("command").and_then(|v| v.as_str()) {
+                                                            // 字符串格式
+                                                            if cmd_str.contains(' ') {
+                                                                vec!["powershell.exe".to_string(), "-Command".to_string(), cmd_str.to_string()]
                                                             } else {
-                                                                // command 字段缺失，使用静默成功命令
-                                                                tracing::debug!("shell command 缺少 command 字段，使用静默成功命令");
-                                                                vec!["powershell.exe".to_string(), "-Command".to_string(), "exit 0".to_string()]
-                                                            };
-                                                            
-                                                            tracing::debug!("Shell 命令解析: {:?}", cmd_vec);
-                                                            Some(json!({
-                                                                "type": "response.output_item.added",
-                                                                "item": {
-                                                                    "type": "local_shell_call",
-                                                                    "status": "in_progress",
-                                                                    "call_id": &call_id,
-                                                                    "action": {
-                                                                        "type": "exec",
-                                                                        "command": cmd_vec
-                                                                    }
-                                                                }
-                                                            }))
-                                                        } else if name_str == "googleSearch" || name_str == "web_search" || name_str == "google_search" {
-                                                            // Map to web_search_call
-                                                            let query_val = args_obj.get("query").and_then(|v| v.as_str()).unwrap_or("");
-                                                            Some(json!({
-                                                                "type": "response.output_item.added",
-                                                                "item": {
-                                                                    "type": "web_search_call",
-                                                                    "status": "in_progress",
-                                                                    "call_id": &call_id,
-                                                                    "action": {
-                                                                        "type": "search",
-                                                                        "query": query_val
-                                                                    }
-                                                                }
-                                                            }))
+                                                                vec![cmd_str.to_string()]
+                                                            }
                                                         } else {
-                                                            // Default function_call
-                                                            Some(json!({
-                                                                "type": "response.output_item.added",
-                                                                "item": {
-                                                                    "type": "function_call",
-                                                                    "name": name,
-                                                                    "arguments": args_str,
-                                                                    "call_id": &call_id
-                                                                }
-                                                            }))
+                                                            // command 字段缺失，使用静默成功命令
+                                                            tracing::debug!("shell command 缺少 command 字段，使用静默成功命令");
+                                                            vec!["powershell.exe".to_string(), "-Command".to_string(), "exit 0".to_string()]
                                                         };
 
-                                                        // 只有在有事件时才发送
-                                                        if let Some(item_added_ev) = maybe_item_added_ev {
-                                                            yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&item_added_ev).unwrap())));
-
-                                                        // Emit response.output_item.done (matching the added event)
-                                                        // 复用相同的 cmd_vec 逻辑
-                                                        let item_done_ev = if name_str == "shell" || name_str == "local_shell" {
-                                                            let cmd_vec_done: Vec<String> = if let Some(arr) = args_obj.get("command").and_then(|v| v.as_array()) {
-                                                                arr.iter()
-                                                                    .filter_map(|v| v.as_str())
-                                                                    .map(|s| s.to_string())
-                                                                    .collect()
-                                                            } else if let Some(cmd_str) = args_obj.get("command").and_then(|v| v.as_str()) {
-                                                                if cmd_str.contains(' ') {
-                                                                    vec!["powershell.exe".to_string(), "-Command".to_string(), cmd_str.to_string()]
-                                                                } else {
-                                                                    vec![cmd_str.to_string()]
-                                                                }
-                                                            } else {
-                                                                vec!["powershell.exe".to_string(), "-Command".to_string(), "echo 'Invalid command'".to_string()]
-                                                            };
-                                                            json!({
-                                                                "type": "response.output_item.done",
-                                                                "item": {
-                                                                    "type": "local_shell_call",
-                                                                    "status": "in_progress",
-                                                                    "call_id": call_id,
-                                                                     "action": {
-                                                                        "type": "exec",
-                                                                        "command": cmd_vec_done
-                                                                    }
+                                                        tracing::debug!("Shell 命令解析: {:?}", cmd_vec);
+                                                        Some(json!({
+                                                            "type": "response.output_item.added",
+                                                            "item": {
+                                                                "type": "local_shell_call",
+                                                                "status": "in_progress",
+                                                                "call_id": &call_id,
+                                                                "action": {
+                                                                    "type": "exec",
+                                                                    "command": cmd_vec
                                                                 }
-                                                            })
-                                                        } else if name_str == "googleSearch" || name_str == "web_search" || name_str == "google_search" {
-                                                            let query_val = args_obj.get("query").and_then(|v| v.as_str()).unwrap_or("");
-                                                             json!({
-                                                                "type": "response.output_item.done",
-                                                                "item": {
-                                                                    "type": "web_search_call",
-                                                                    "status": "in_progress",
-                                                                    "call_id": call_id,
-                                                                    "action": {
-                                                                        "type": "search",
-                                                                        "query": query_val
-                                                                    }
+                                                            }
+                                                        }))
+                                                    } else if name_str == "googleSearch" || name_str == "web_search" || name_str == "google_search" {
+                                                        // Map to web_search_call
+                                                        let query_val = args_obj.get("query").and_then(|v| v.as_str()).unwrap_or("");
+                                                        Some(json!({
+                                                            "type": "response.output_item.added",
+                                                            "item": {
+                                                                "type": "web_search_call",
+                                                                "status": "in_progress",
+                                                                "call_id": &call_id,
+                                                                "action": {
+                                                                    "type": "search",
+                                                                    "query": query_val
                                                                 }
-                                                            })
+                                                            }
+                                                        }))
+                                                    } else {
+                                                        // Default function_call
+                                                        Some(json!({
+                                                            "type": "response.output_item.added",
+                                                            "item": {
+                                                                "type": "function_call",
+                                                                "name": name,
+                                                                "arguments": args_str,
+                                                                "call_id": &call_id
+                                                            }
+                                                        }))
+                                                    };
+
+                                                    // 只有在有事件时才发送
+                                                    if let Some(item_added_ev) = maybe_item_added_ev {
+                                                        yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&item_added_ev).unwrap())));
+
+                                                    // Emit response.output_item.done (matching the added event)
+                                                    // 复用相同的 cmd_vec 逻辑
+                                                    let item_done_ev = if name_str == "shell" || name_str == "local_shell" {
+                                                        let cmd_vec_done: Vec<String> = if let Some(arr) = args_obj.get("command").and_then(|v| v.as_array()) {
+                                                            arr.iter()
+                                                                .filter_map(|v| v.as_str())
+                                                                .map(|s| s.to_string())
+                                                                .collect()
+                                                        } else if let Some(cmd_str) = args_obj.get("command").and_then(|v| v.as_str()) {
+                                                            if cmd_str.contains(' ') {
+                                                                vec!["powershell.exe".to_string(), "-Command".to_string(), cmd_str.to_string()]
+                                                            } else {
+                                                                vec![cmd_str.to_string()]
+                                                            }
                                                         } else {
-                                                            json!({
-                                                                "type": "response.output_item.done",
-                                                                "item": {
-                                                                    "type": "function_call",
-                                                                    "name": name,
-                                                                    "arguments": args_str,
-                                                                    "call_id": call_id
-                                                                }
-                                                            })
+                                                            vec!["powershell.exe".to_string(), "-Command".to_string(), "echo 'Invalid command'".to_string()]
                                                         };
+                                                        json!({
+                                                            "type": "response.output_item.done",
+                                                            "item": {
+                                                                "type": "local_shell_call",
+                                                                "status": "in_progress",
+                                                                "call_id": call_id,
+                                                                 "action": {
+                                                                    "type": "exec",
+                                                                    "command": cmd_vec_done
+                                                                }
+                                                            }
+                                                        })
+                                                    } else if name_str == "googleSearch" || name_str == "web_search" || name_str == "google_search" {
+                                                        let query_val = args_obj.get("query").and_then(|v| v.as_str()).unwrap_or("");
+                                                         json!({
+                                                            "type": "response.output_item.done",
+                                                            "item": {
+                                                                "type": "web_search_call",
+                                                                "status": "in_progress",
+                                                                "call_id": call_id,
+                                                                "action": {
+                                                                    "type": "search",
+                                                                    "query": query_val
+                                                                }
+                                                            }
+                                                        })
+                                                    } else {
+                                                        json!({
+                                                            "type": "response.output_item.done",
+                                                            "item": {
+                                                                "type": "function_call",
+                                                                "name": name,
+                                                                "arguments": args_str,
+                                                                "call_id": call_id
+                                                            }
+                                                        })
+                                                    };
 
-                                                        yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&item_done_ev).unwrap())));
-                                                        } // 关闭 if let Some(item_added_ev)
-                                                    }
+                                                    yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&item_done_ev).unwrap())));
+                                                    } // 关闭 if let Some(item_added_ev)
                                                 }
                                             }
                                         }
                                     }
                                 }
+                            }
 
-                                if !delta_text.is_empty() {
-                                    full_content.push_str(&delta_text);
-                                    // 2. Emit response.output_text.delta
-                                    let delta_ev = json!({
-                                        "type": "response.output_text.delta",
-                                        "delta": delta_text
-                                    });
-                                    yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&delta_ev).unwrap())));
-                                }
+                            if !delta_text.is_empty() {
+                                full_content.push_str(&delta_text);
+                                // 2. Emit response.output_text.delta
+                                let delta_ev = json!({
+                                    "type": "response.output_text.delta",
+                                    "delta": delta_text
+                                });
+                                yield Ok::<Bytes, String>(Bytes::from(format!("data: {}\n\n", serde_json::to_string(&delta_ev).unwrap())));
                             }
                         }
                     }
@@ -817,3 +834,114 @@ pub fn create_codex_sse_stream(
 
     Box::pin(stream)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// 模拟上游：以 chunked 编码发送若干 SSE 片段后直接断开连接 (不发送结束块)
+    async fn upstream_that_drops_after(chunks: Vec<&'static str>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/stream", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await;
+            let _ = socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nTransfer-Encoding: chunked\r\n\r\n")
+                .await;
+            for chunk in chunks {
+                let _ = socket.write_all(format!("{:x}\r\n{}\r\n", chunk.len(), chunk).as_bytes()).await;
+                let _ = socket.flush().await;
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            // 直接关闭，触发客户端的 body 读取错误
+        });
+        url
+    }
+
+    async fn collect_output(url: &str) -> Vec<Result<String, String>> {
+        let response = reqwest::get(url).await.unwrap();
+        let stream = create_openai_sse_stream(Box::pin(response.bytes_stream()), "gpt-test".to_string());
+        stream
+            .map(|item| item.map(|b| String::from_utf8_lossy(&b).to_string()))
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_mid_stream_error_emits_error_frame_and_done() {
+        let url = upstream_that_drops_after(vec![
+            "data: {\"response\":{\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"Hello\"}]}}],\"usageMetadata\":{\"promptTokenCount\":7,\"candidatesTokenCount\":1}}}\n\n",
+            "data: {\"response\":{\"candidates\":[{\"content\":{\"parts\":[{\"text\":\" world\"}]}}],\"usageMetadata\":{\"promptTokenCount\":7,\"candidatesTokenCount\":2}}}\n\n",
+        ])
+        .await;
+
+        let output = collect_output(&url).await;
+        assert!(output.iter().all(|item| item.is_ok()), "mid-stream error must not drop the connection");
+        let text: String = output.into_iter().map(|item| item.unwrap()).collect();
+
+        let frames: Vec<&str> = text
+            .split("\n\n")
+            .filter_map(|f| f.strip_prefix("data: "))
+            .collect();
+        assert!(frames[0].contains("Hello"));
+        assert!(frames[1].contains(" world"));
+
+        let error_frame: Value = serde_json::from_str(frames[2]).unwrap();
+        assert_eq!(error_frame["error"]["type"], "upstream_error");
+        assert!(error_frame["error"]["message"].as_str().unwrap().starts_with("Upstream error"));
+        assert_eq!(error_frame["usage"]["prompt_tokens"], 7);
+        assert_eq!(error_frame["usage"]["completion_tokens"], 2);
+
+        assert_eq!(frames.last(), Some(&"[DONE]"));
+        assert_eq!(frames.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_error_before_any_output_is_returned_as_err() {
+        let url = upstream_that_drops_after(vec![]).await;
+
+        let output = collect_output(&url).await;
+        assert_eq!(output.len(), 1);
+        assert!(output[0].is_err(), "errors before the first byte must stay retryable");
+    }
+
+    /// CRLF 行尾、跨多行的 data 与穿插的注释/字段行 (企业代理常见的改写)
+    const CRLF_MULTILINE_FIXTURE: &str = concat!(
+        ": proxy keep-alive\r\n",
+        "event: message\r\n",
+        "data: {\"response\":{\"candidates\":[{\"content\":{\"parts\":\r\n",
+        "data: [{\"text\":\"Hello\"}]}}]}}\r\n",
+        "\r\n",
+        ": ping\r\n",
+        "id: 2\r\n",
+        "data: {\"response\":{\"candidates\":[{\"content\":{\"parts\":[{\"text\":\" world\"}]},\r\n",
+        "data: \"finishReason\":\"STOP\"}]}}\r\n",
+        "\r\n",
+    );
+
+    #[tokio::test]
+    async fn test_crlf_and_multiline_data_lose_no_content() {
+        let chunks: Vec<Result<Bytes, reqwest::Error>> = CRLF_MULTILINE_FIXTURE
+            .as_bytes()
+            .chunks(9)
+            .map(|c| Ok(Bytes::copy_from_slice(c)))
+            .collect();
+        let output: Vec<Result<Bytes, String>> =
+            create_openai_sse_stream(Box::pin(futures::stream::iter(chunks)), "gpt-test".to_string())
+                .collect()
+                .await;
+        let text: String = output.into_iter().map(|item| String::from_utf8_lossy(&item.unwrap()).to_string()).collect();
+
+        let content: String = text
+            .split("\n\n")
+            .filter_map(|f| f.strip_prefix("data: "))
+            .filter_map(|d| serde_json::from_str::<Value>(d).ok())
+            .filter_map(|v| v["choices"][0]["delta"]["content"].as_str().map(String::from))
+            .collect();
+        assert_eq!(content, "Hello world");
+        assert!(text.contains("\"finish_reason\":\"stop\""));
+    }
+}
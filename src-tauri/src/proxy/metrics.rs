@@ -0,0 +1,528 @@
+// Prometheus 指标 (文本暴露格式)
+// 计数全部基于原子量，/metrics 抓取时只读取快照，不与请求热路径争用锁。
+// 标签基数受限：只按 handler / model / status 聚合，模型数超过上限后归入 "other"；
+// 按账号统计需显式开启 per_account_labels。
+
+use crate::proxy::config::MetricsConfig;
+use crate::proxy::monitor::ProxyRequestLog;
+use dashmap::DashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+pub const METRICS_PATH: &str = "/metrics";
+
+/// 延迟直方图的桶上界 (秒)
+const LATENCY_BUCKETS_SECS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+/// 模型标签的最大取值数
+const MAX_MODEL_LABELS: usize = 64;
+/// 账号标签的最大取值数
+const MAX_ACCOUNT_LABELS: usize = 256;
+/// 上游区域标签的最大取值数
+const MAX_REGION_LABELS: usize = 32;
+/// 超出上限的标签值统一归入此值
+const OVERFLOW_LABEL: &str = "other";
+
+/// 号池账号状态计数
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AccountStateCounts {
+    pub loaded: usize,
+    pub rate_limited: usize,
+    /// 因连续失败被临时停用
+    pub disabled: usize,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS_SECS.len()],
+    count: AtomicU64,
+    sum_ms: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, duration_ms: u64) {
+        let secs = duration_ms as f64 / 1000.0;
+        if let Some(i) = LATENCY_BUCKETS_SECS.iter().position(|le| secs <= *le) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_ms.fetch_add(duration_ms, Ordering::Relaxed);
+    }
+
+    /// 以单个标签输出该直方图的 bucket / sum / count 行
+    fn render(&self, out: &mut String, name: &str, label: &str, value: &str) {
+        let mut cumulative = 0;
+        for (i, le) in LATENCY_BUCKETS_SECS.iter().enumerate() {
+            cumulative += self.buckets[i].load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{{}=\"{}\",le=\"{}\"}} {}", name, label, value, le, cumulative);
+        }
+        let count = self.count.load(Ordering::Relaxed).max(cumulative);
+        let _ = writeln!(out, "{}_bucket{{{}=\"{}\",le=\"+Inf\"}} {}", name, label, value, count);
+        let _ = writeln!(
+            out,
+            "{}_sum{{{}=\"{}\"}} {}",
+            name,
+            label,
+            value,
+            self.sum_ms.load(Ordering::Relaxed) as f64 / 1000.0
+        );
+        let _ = writeln!(out, "{}_count{{{}=\"{}\"}} {}", name, label, value, count);
+    }
+}
+
+/// 代理请求指标
+#[derive(Default)]
+pub struct ProxyMetrics {
+    enabled: AtomicBool,
+    per_account_labels: AtomicBool,
+    /// 已出现过的模型标签 (用于限制基数)
+    models: DashMap<String, ()>,
+    accounts: DashMap<String, ()>,
+    /// (handler, model, status) -> 请求数
+    requests: DashMap<(&'static str, String, u16), AtomicU64>,
+    latency: DashMap<&'static str, Histogram>,
+    regions: DashMap<String, ()>,
+    /// 上游区域 -> 延迟 (仅客户端指定 X-Upstream-Region 的请求)
+    region_latency: DashMap<String, Histogram>,
+    /// (model, direction) -> token 数
+    tokens: DashMap<(String, &'static str), AtomicU64>,
+    /// model -> 命中缓存的请求数
+    cache_hits: DashMap<String, AtomicU64>,
+    cached_tokens: DashMap<String, AtomicU64>,
+    retries: DashMap<&'static str, AtomicU64>,
+    /// outcome -> 最后探测次数
+    probes: DashMap<&'static str, AtomicU64>,
+    /// (account, status) -> 请求数 (仅 per_account_labels 开启时记录)
+    account_requests: DashMap<(String, u16), AtomicU64>,
+}
+
+fn bump<K: std::hash::Hash + Eq>(map: &DashMap<K, AtomicU64>, key: K, value: u64) {
+    if let Some(counter) = map.get(&key) {
+        counter.fetch_add(value, Ordering::Relaxed);
+        return;
+    }
+    map.entry(key).or_default().fetch_add(value, Ordering::Relaxed);
+}
+
+/// 按请求路径归类 handler (固定取值集合)
+pub fn handler_label(url: &str) -> &'static str {
+    let path = url.split('?').next().unwrap_or(url);
+    if path.starts_with("/v1/messages/count_tokens") {
+        "claude_count_tokens"
+    } else if path.starts_with("/v1/messages") {
+        "claude"
+    } else if path.starts_with("/v1/chat/completions") {
+        "openai_chat"
+    } else if path.starts_with("/v1/completions") || path.starts_with("/v1/responses") {
+        "openai_completions"
+    } else if path.starts_with("/v1/images") {
+        "openai_images"
+    } else if path.starts_with("/v1/audio") {
+        "audio"
+    } else if path.starts_with("/v1beta/models") && path.contains(":countTokens") {
+        "gemini_count_tokens"
+    } else if path.starts_with("/v1beta/models") {
+        "gemini"
+    } else if path.starts_with("/mcp/") {
+        "mcp"
+    } else {
+        "other"
+    }
+}
+
+fn bounded_label(seen: &DashMap<String, ()>, value: &str, limit: usize) -> String {
+    if seen.contains_key(value) {
+        return value.to_string();
+    }
+    if seen.len() >= limit {
+        return OVERFLOW_LABEL.to_string();
+    }
+    seen.insert(value.to_string(), ());
+    value.to_string()
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sorted<K: Clone + Ord + std::hash::Hash + Eq>(map: &DashMap<K, AtomicU64>) -> Vec<(K, u64)> {
+    let mut entries: Vec<(K, u64)> = map
+        .iter()
+        .map(|e| (e.key().clone(), e.value().load(Ordering::Relaxed)))
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries
+}
+
+impl ProxyMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 应用配置 (启动与热更新时调用)
+    pub fn configure(&self, config: &MetricsConfig) {
+        self.enabled.store(config.enabled, Ordering::Relaxed);
+        self.per_account_labels.store(config.per_account_labels, Ordering::Relaxed);
+        if !config.per_account_labels {
+            self.account_requests.clear();
+            self.accounts.clear();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// 记录一条已完成的请求
+    pub fn record(&self, log: &ProxyRequestLog) {
+        if !self.is_enabled() {
+            return;
+        }
+        let handler = handler_label(&log.url);
+        let model = log
+            .mapped_model
+            .as_deref()
+            .or(log.model.as_deref())
+            .map(|m| bounded_label(&self.models, m, MAX_MODEL_LABELS))
+            .unwrap_or_else(|| "unknown".to_string());
+
+        bump(&self.requests, (handler, model.clone(), log.status), 1);
+        match self.latency.get(handler) {
+            Some(hist) => hist.observe(log.duration),
+            None => self.latency.entry(handler).or_default().observe(log.duration),
+        }
+        if let Some(region) = log.upstream_region.as_deref() {
+            let region = bounded_label(&self.regions, region, MAX_REGION_LABELS);
+            self.region_latency.entry(region).or_default().observe(log.duration);
+        }
+
+        if let Some(input) = log.input_tokens {
+            bump(&self.tokens, (model.clone(), "input"), input as u64);
+        }
+        if let Some(output) = log.output_tokens {
+            bump(&self.tokens, (model.clone(), "output"), output as u64);
+        }
+        if let Some(cached) = log.cached_tokens.filter(|c| *c > 0) {
+            bump(&self.cache_hits, model.clone(), 1);
+            bump(&self.cached_tokens, model, cached as u64);
+        }
+
+        if self.per_account_labels.load(Ordering::Relaxed) {
+            if let Some(account) = log.account_email.as_deref() {
+                let account = bounded_label(&self.accounts, account, MAX_ACCOUNT_LABELS);
+                bump(&self.account_requests, (account, log.status), 1);
+            }
+        }
+    }
+
+    /// 记录一次 handler 内的重试 (换号或降级后重新请求上游)
+    pub fn record_retry(&self, handler: &'static str) {
+        if self.is_enabled() {
+            bump(&self.retries, handler, 1);
+        }
+    }
+
+    /// 记录一次最后探测 (outcome: recovered / failed / throttled)
+    pub fn record_probe(&self, outcome: &'static str) {
+        if self.is_enabled() {
+            bump(&self.probes, outcome, 1);
+        }
+    }
+
+    /// 以 Prometheus 文本暴露格式输出全部指标
+    pub fn render(&self, accounts: AccountStateCounts) -> String {
+        let mut out = String::new();
+
+        header(&mut out, "antigravity_requests_total", "counter", "Proxied requests by handler, model and status.");
+        for ((handler, model, status), value) in sorted(&self.requests) {
+            let _ = writeln!(
+                out,
+                "antigravity_requests_total{{handler=\"{}\",model=\"{}\",status=\"{}\"}} {}",
+                handler,
+                escape_label(&model),
+                status,
+                value
+            );
+        }
+
+        header(&mut out, "antigravity_request_duration_seconds", "histogram", "End-to-end request latency by handler.");
+        let mut handlers: Vec<&'static str> = self.latency.iter().map(|e| *e.key()).collect();
+        handlers.sort();
+        for handler in handlers {
+            let Some(hist) = self.latency.get(handler) else { continue };
+            hist.render(&mut out, "antigravity_request_duration_seconds", "handler", handler);
+        }
+
+        header(
+            &mut out,
+            "antigravity_upstream_region_duration_seconds",
+            "histogram",
+            "End-to-end request latency by client-selected upstream region.",
+        );
+        let mut regions: Vec<String> = self.region_latency.iter().map(|e| e.key().clone()).collect();
+        regions.sort();
+        for region in regions {
+            let Some(hist) = self.region_latency.get(&region) else { continue };
+            hist.render(&mut out, "antigravity_upstream_region_duration_seconds", "region", &escape_label(&region));
+        }
+
+        header(&mut out, "antigravity_accounts_loaded", "gauge", "Accounts loaded into the proxy pool.");
+        let _ = writeln!(out, "antigravity_accounts_loaded {}", accounts.loaded);
+        header(&mut out, "antigravity_accounts_rate_limited", "gauge", "Pool accounts currently rate limited.");
+        let _ = writeln!(out, "antigravity_accounts_rate_limited {}", accounts.rate_limited);
+        header(&mut out, "antigravity_accounts_disabled", "gauge", "Pool accounts temporarily disabled after repeated failures.");
+        let _ = writeln!(out, "antigravity_accounts_disabled {}", accounts.disabled);
+
+        header(&mut out, "antigravity_tokens_total", "counter", "Tokens reported by upstream usage, by model and direction.");
+        for ((model, direction), value) in sorted(&self.tokens) {
+            let _ = writeln!(
+                out,
+                "antigravity_tokens_total{{model=\"{}\",direction=\"{}\"}} {}",
+                escape_label(&model),
+                direction,
+                value
+            );
+        }
+
+        header(&mut out, "antigravity_cache_hits_total", "counter", "Requests whose usage reported cached input tokens.");
+        for (model, value) in sorted(&self.cache_hits) {
+            let _ = writeln!(out, "antigravity_cache_hits_total{{model=\"{}\"}} {}", escape_label(&model), value);
+        }
+        header(&mut out, "antigravity_cached_tokens_total", "counter", "Cached input tokens reported by upstream usage.");
+        for (model, value) in sorted(&self.cached_tokens) {
+            let _ = writeln!(out, "antigravity_cached_tokens_total{{model=\"{}\"}} {}", escape_label(&model), value);
+        }
+
+        header(&mut out, "antigravity_retries_total", "counter", "Upstream retries performed by handlers.");
+        for (handler, value) in sorted(&self.retries) {
+            let _ = writeln!(out, "antigravity_retries_total{{handler=\"{}\"}} {}", handler, value);
+        }
+
+        header(&mut out, "antigravity_last_resort_probes_total", "counter", "Last-resort probes of locked accounts by outcome.");
+        for (outcome, value) in sorted(&self.probes) {
+            let _ = writeln!(out, "antigravity_last_resort_probes_total{{outcome=\"{}\"}} {}", outcome, value);
+        }
+
+        if self.per_account_labels.load(Ordering::Relaxed) {
+            header(&mut out, "antigravity_account_requests_total", "counter", "Proxied requests by account and status.");
+            for ((account, status), value) in sorted(&self.account_requests) {
+                let _ = writeln!(
+                    out,
+                    "antigravity_account_requests_total{{account=\"{}\",status=\"{}\"}} {}",
+                    escape_label(&account),
+                    status,
+                    value
+                );
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn log(url: &str, model: &str, status: u16, duration: u64) -> ProxyRequestLog {
+        ProxyRequestLog {
+            id: "id".to_string(),
+            timestamp: 0,
+            method: "POST".to_string(),
+            url: url.to_string(),
+            status,
+            duration,
+            model: Some(model.to_string()),
+            mapped_model: None,
+            account_email: Some("a@example.com".to_string()),
+            error: None,
+            request_body: None,
+            response_body: None,
+            input_tokens: Some(100),
+            output_tokens: Some(20),
+            replay_of: None,
+            priority: None,
+            account_tier: None,
+            cached_tokens: Some(40),
+            cost_pico: None,
+            locally_answered: false,
+            end_user: None,
+            upstream_region: None,
+        }
+    }
+
+    fn is_metric_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+    }
+
+    /// 解析 `{k="v",...}`，返回剩余部分
+    fn parse_labels(s: &str) -> Result<(Vec<(String, String)>, &str), String> {
+        let mut labels = Vec::new();
+        let mut rest = s.strip_prefix('{').ok_or("missing {")?;
+        loop {
+            if let Some(r) = rest.strip_prefix('}') {
+                return Ok((labels, r));
+            }
+            let eq = rest.find('=').ok_or("missing =")?;
+            let key = &rest[..eq];
+            if !is_metric_name(key) {
+                return Err(format!("bad label name {}", key));
+            }
+            rest = rest[eq + 1..].strip_prefix('"').ok_or("missing quote")?;
+            let mut value = String::new();
+            let mut chars = rest.char_indices();
+            let end = loop {
+                match chars.next().ok_or("unterminated value")? {
+                    (_, '\\') => match chars.next().ok_or("bad escape")?.1 {
+                        'n' => value.push('\n'),
+                        c @ ('\\' | '"') => value.push(c),
+                        c => return Err(format!("bad escape \\{}", c)),
+                    },
+                    (i, '"') => break i,
+                    (_, c) => value.push(c),
+                }
+            };
+            labels.push((key.to_string(), value));
+            rest = &rest[end + 1..];
+            rest = rest.strip_prefix(',').unwrap_or(rest);
+        }
+    }
+
+    /// 简易文本暴露格式校验器，返回 (指标名, 标签, 值) 列表
+    fn validate_exposition(text: &str) -> Result<Vec<(String, Vec<(String, String)>, f64)>, String> {
+        let mut types: HashMap<String, String> = HashMap::new();
+        let mut samples = Vec::new();
+        for line in text.lines().filter(|l| !l.is_empty()) {
+            if let Some(comment) = line.strip_prefix("# ") {
+                let mut parts = comment.splitn(3, ' ');
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some("HELP"), Some(name), Some(_)) if is_metric_name(name) => {}
+                    (Some("TYPE"), Some(name), Some(kind)) if is_metric_name(name) => {
+                        if !["counter", "gauge", "histogram"].contains(&kind) {
+                            return Err(format!("unknown type {}", kind));
+                        }
+                        if types.insert(name.to_string(), kind.to_string()).is_some() {
+                            return Err(format!("duplicate TYPE for {}", name));
+                        }
+                    }
+                    _ => return Err(format!("bad comment line: {}", line)),
+                }
+                continue;
+            }
+            let name_end = line.find(|c: char| c == '{' || c == ' ').ok_or("missing value")?;
+            let name = &line[..name_end];
+            if !is_metric_name(name) {
+                return Err(format!("bad metric name {}", name));
+            }
+            let (labels, rest) = if line[name_end..].starts_with('{') {
+                parse_labels(&line[name_end..])?
+            } else {
+                (Vec::new(), &line[name_end..])
+            };
+            let value: f64 = rest
+                .trim()
+                .parse()
+                .map_err(|_| format!("bad value in line: {}", line))?;
+            let family = ["_bucket", "_sum", "_count"]
+                .iter()
+                .find_map(|s| name.strip_suffix(*s).filter(|f| types.get(*f).map(String::as_str) == Some("histogram")))
+                .unwrap_or(name);
+            if !types.contains_key(family) {
+                return Err(format!("sample {} has no TYPE", name));
+            }
+            samples.push((name.to_string(), labels, value));
+        }
+        Ok(samples)
+    }
+
+    #[test]
+    fn test_exposition_is_valid_and_bounded() {
+        let metrics = ProxyMetrics::new();
+        metrics.configure(&MetricsConfig { enabled: true, ..Default::default() });
+
+        metrics.record(&log("/v1/messages", "claude-sonnet-4-5", 200, 800));
+        metrics.record(&log("/v1/messages", "claude-sonnet-4-5", 429, 50));
+        metrics.record(&log("/v1beta/models/gemini-3-pro:generateContent", "weird\"model\\", 200, 200_000));
+        metrics.record(&log("/v1/chat/completions?x=1", "gpt-4o", 200, 1500));
+        metrics.record_retry("claude");
+        metrics.record_probe("recovered");
+
+        let text = metrics.render(AccountStateCounts { loaded: 3, rate_limited: 1, disabled: 0 });
+        let samples = validate_exposition(&text).unwrap_or_else(|e| panic!("{}\n{}", e, text));
+
+        let find = |name: &str, labels: &[(&str, &str)]| {
+            samples
+                .iter()
+                .find(|(n, l, _)| n == name && labels.iter().all(|(k, v)| l.iter().any(|(lk, lv)| lk == k && lv == v)))
+                .map(|(_, _, v)| *v)
+        };
+        assert_eq!(find("antigravity_requests_total", &[("handler", "claude"), ("status", "429")]), Some(1.0));
+        assert_eq!(find("antigravity_requests_total", &[("handler", "gemini"), ("model", "weird\"model\\")]), Some(1.0));
+        assert_eq!(find("antigravity_request_duration_seconds_bucket", &[("handler", "claude"), ("le", "1")]), Some(2.0));
+        assert_eq!(find("antigravity_request_duration_seconds_bucket", &[("handler", "gemini"), ("le", "120")]), Some(0.0));
+        assert_eq!(find("antigravity_request_duration_seconds_bucket", &[("handler", "gemini"), ("le", "+Inf")]), Some(1.0));
+        assert_eq!(find("antigravity_tokens_total", &[("model", "claude-sonnet-4-5"), ("direction", "input")]), Some(200.0));
+        assert_eq!(find("antigravity_cache_hits_total", &[("model", "gpt-4o")]), Some(1.0));
+        assert_eq!(find("antigravity_retries_total", &[("handler", "claude")]), Some(1.0));
+        assert_eq!(find("antigravity_last_resort_probes_total", &[("outcome", "recovered")]), Some(1.0));
+        assert_eq!(find("antigravity_accounts_rate_limited", &[]), Some(1.0));
+
+        // 未开启时不出现账号标签
+        assert!(!text.contains("a@example.com"));
+    }
+
+    #[test]
+    fn test_pinned_region_latency_is_exported() {
+        let metrics = ProxyMetrics::new();
+        metrics.configure(&MetricsConfig { enabled: true, ..Default::default() });
+
+        metrics.record(&log("/v1/messages", "claude-sonnet-4-5", 200, 800));
+        let mut pinned = log("/v1/messages", "claude-sonnet-4-5", 200, 3000);
+        pinned.upstream_region = Some("daily".to_string());
+        metrics.record(&pinned);
+
+        let text = metrics.render(AccountStateCounts::default());
+        let samples = validate_exposition(&text).unwrap_or_else(|e| panic!("{}\n{}", e, text));
+        let find = |name: &str, labels: &[(&str, &str)]| {
+            samples
+                .iter()
+                .find(|(n, l, _)| n == name && labels.iter().all(|(k, v)| l.iter().any(|(lk, lv)| lk == k && lv == v)))
+                .map(|(_, _, v)| *v)
+        };
+        assert_eq!(find("antigravity_upstream_region_duration_seconds_bucket", &[("region", "daily"), ("le", "2.5")]), Some(0.0));
+        assert_eq!(find("antigravity_upstream_region_duration_seconds_bucket", &[("region", "daily"), ("le", "+Inf")]), Some(1.0));
+        assert_eq!(find("antigravity_upstream_region_duration_seconds_count", &[("region", "daily")]), Some(1.0));
+        // 未指定区域的请求不计入区域直方图
+        assert_eq!(samples.iter().filter(|(n, _, _)| n == "antigravity_upstream_region_duration_seconds_count").count(), 1);
+    }
+
+    #[test]
+    fn test_model_labels_are_capped() {
+        let metrics = ProxyMetrics::new();
+        metrics.configure(&MetricsConfig { enabled: true, per_account_labels: true, ..Default::default() });
+        for i in 0..(MAX_MODEL_LABELS + 10) {
+            metrics.record(&log("/v1/messages", &format!("model-{}", i), 200, 10));
+        }
+        let text = metrics.render(AccountStateCounts::default());
+        let samples = validate_exposition(&text).unwrap();
+        let request_series = samples.iter().filter(|(n, _, _)| n == "antigravity_requests_total").count();
+        assert_eq!(request_series, MAX_MODEL_LABELS + 1);
+        assert!(text.contains("model=\"other\""));
+        assert!(text.contains("antigravity_account_requests_total{account=\"a@example.com\",status=\"200\"} 74"));
+    }
+
+    #[test]
+    fn test_disabled_metrics_record_nothing() {
+        let metrics = ProxyMetrics::new();
+        metrics.record(&log("/v1/messages", "claude-sonnet-4-5", 200, 10));
+        assert!(!metrics.render(AccountStateCounts::default()).contains("antigravity_requests_total{"));
+    }
+}
@@ -1,16 +1,51 @@
 // API Key 认证中间件
 use axum::{
+    extract::ConnectInfo,
     extract::State,
     extract::Request,
-    http::{header, StatusCode},
+    http::{header, HeaderMap, StatusCode},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
 };
+use base64::{engine::general_purpose, Engine as _};
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::proxy::config::MetricsAccess;
+use crate::proxy::metrics::METRICS_PATH;
+use crate::proxy::server::{is_setup_path, ADMIN_PATH_PREFIX};
 use crate::proxy::{ProxyAuthMode, ProxySecurityConfig};
 
+/// 客户端携带的 API Key (Authorization: Bearer 或 x-api-key)
+pub fn client_api_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer ").or(Some(s)))
+        .or_else(|| headers.get("x-api-key").and_then(|h| h.to_str().ok()))
+}
+
+/// HTTP Basic 认证中的密码部分 (浏览器访问 /setup 时以密码输入 API Key)
+fn basic_auth_password(headers: &HeaderMap) -> Option<String> {
+    let encoded = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.strip_prefix("Basic "))?;
+    let decoded = general_purpose::STANDARD.decode(encoded.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    decoded.split_once(':').map(|(_, password)| password.to_string())
+}
+
+/// /setup 的 401 响应带 Basic 质询，让浏览器弹出输入框
+fn setup_challenge() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Basic realm=\"Antigravity proxy setup\", charset=\"UTF-8\"")],
+    )
+        .into_response()
+}
+
 /// API Key 认证中间件
 pub async fn auth_middleware(
     State(security): State<Arc<RwLock<ProxySecurityConfig>>>,
@@ -21,7 +56,8 @@ pub async fn auth_middleware(
     let path = request.uri().path().to_string();
 
     // 过滤心跳和健康检查请求,避免日志噪音
-    if !path.contains("event_logging") && path != "/healthz" {
+    let is_metrics = path == METRICS_PATH;
+    if !path.contains("event_logging") && path != "/healthz" && !is_metrics {
         tracing::info!("Request: {} {}", method, path);
     } else {
         tracing::trace!("Heartbeat: {} {}", method, path);
@@ -33,28 +69,53 @@ pub async fn auth_middleware(
     }
 
     let security = security.read().await.clone();
-    let effective_mode = security.effective_auth_mode();
 
-    if matches!(effective_mode, ProxyAuthMode::Off) {
-        return Ok(next.run(request).await);
+    if is_setup_path(&path) {
+        // 引导页未开启时不存在；开启时不受鉴权模式影响，总是要求主 API Key (客户端 Key 不能再生成 Key)
+        if !security.enable_setup_page {
+            return Err(StatusCode::NOT_FOUND);
+        }
+        let key = basic_auth_password(request.headers())
+            .or_else(|| client_api_key(request.headers()).map(str::to_string));
+        return if !security.api_key.is_empty() && key.as_deref() == Some(security.api_key.as_str()) {
+            Ok(next.run(request).await)
+        } else {
+            Ok(setup_challenge())
+        };
     }
 
-    if matches!(effective_mode, ProxyAuthMode::AllExceptHealth) && path == "/healthz" {
-        return Ok(next.run(request).await);
+    if is_metrics {
+        // /metrics 使用独立的访问策略：未开启时不存在；本机模式只看来源地址；鉴权模式总是要求 API Key
+        if !security.metrics.enabled {
+            return Err(StatusCode::NOT_FOUND);
+        }
+        if security.metrics.access == MetricsAccess::Localhost {
+            let is_loopback = request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|info| info.0.ip().is_loopback())
+                .unwrap_or(false);
+            return if is_loopback {
+                Ok(next.run(request).await)
+            } else {
+                Err(StatusCode::FORBIDDEN)
+            };
+        }
+    } else if !path.starts_with(ADMIN_PATH_PREFIX) {
+        // 管理接口不受全局鉴权模式影响，总是要求 API Key；其余路径按所属路由分组的鉴权模式
+        let effective_mode = security.effective_auth_mode_for(&path);
+
+        if matches!(effective_mode, ProxyAuthMode::Off) {
+            return Ok(next.run(request).await);
+        }
+
+        if matches!(effective_mode, ProxyAuthMode::AllExceptHealth) && path == "/healthz" {
+            return Ok(next.run(request).await);
+        }
     }
     
     // 从 header 中提取 API key
-    let api_key = request
-        .headers()
-        .get(header::AUTHORIZATION)
-        .and_then(|h| h.to_str().ok())
-        .and_then(|s| s.strip_prefix("Bearer ").or(Some(s)))
-        .or_else(|| {
-            request
-                .headers()
-                .get("x-api-key")
-                .and_then(|h| h.to_str().ok())
-        });
+    let api_key = client_api_key(request.headers());
 
     if security.api_key.is_empty() {
         tracing::error!("Proxy auth is enabled but api_key is empty; denying request");
@@ -62,7 +123,16 @@ pub async fn auth_middleware(
     }
 
     // Constant-time compare is unnecessary here, but keep strict equality and avoid leaking values.
-    let authorized = api_key.map(|k| k == security.api_key).unwrap_or(false);
+    // 管理接口只接受主 API Key，其余路径也接受有权访问该分组的客户端 Key
+    let authorized = api_key
+        .map(|k| {
+            if path.starts_with(ADMIN_PATH_PREFIX) {
+                k == security.api_key
+            } else {
+                security.authorize_key(k, &path)
+            }
+        })
+        .unwrap_or(false);
 
     if authorized {
         Ok(next.run(request).await)
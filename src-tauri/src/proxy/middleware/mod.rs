@@ -4,6 +4,10 @@ pub mod auth;
 pub mod cors;
 pub mod logging;
 pub mod monitor;
+pub mod response_headers;
+pub mod upstream_region;
 
 pub use auth::auth_middleware;
 pub use cors::cors_layer;
+pub use response_headers::response_header_middleware;
+pub use upstream_region::upstream_region_middleware;
@@ -7,6 +7,8 @@ use axum::{
 use std::time::Instant;
 use crate::proxy::server::AppState;
 use crate::proxy::monitor::ProxyRequestLog;
+use crate::proxy::common::account_identity::{ACCOUNT_EMAIL_HEADER, ACCOUNT_ID_HEADER};
+use crate::proxy::common::request_priority::RequestPriority;
 use serde_json::Value;
 use futures::StreamExt;
 
@@ -18,15 +20,22 @@ pub async fn monitor_middleware(
     request: Request,
     next: Next,
 ) -> Response {
-    if !state.monitor.is_enabled() {
+    if !state.monitor.is_observing() {
         return next.run(request).await;
     }
 
     let start = Instant::now();
     let method = request.method().to_string();
     let uri = request.uri().to_string();
+    // 只有内部发起的重放 / 自测请求才会被排除出使用统计
+    let replay_of = crate::proxy::replay::internal_replay_of(request.headers());
+    let requested_priority = RequestPriority::from_headers(request.headers());
     
-    if uri.contains("event_logging") {
+    // 引导页的响应中含有新生成的 Key，不能进入请求日志
+    if uri.contains("event_logging")
+        || request.uri().path() == crate::proxy::metrics::METRICS_PATH
+        || crate::proxy::server::is_setup_path(request.uri().path())
+    {
         return next.run(request).await;
     }
     
@@ -39,17 +48,37 @@ pub async fn monitor_middleware(
         None
     };
 
+    // 文件上传 (如音频转录) 不缓冲请求体，由处理器边读边写入暂存
+    let is_multipart = request
+        .headers()
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|ct| ct.starts_with("multipart/form-data"))
+        .unwrap_or(false);
+
     let request_body_str;
-    let request = if method == "POST" {
+    let mut end_user = None;
+    let request = if is_multipart {
+        request_body_str = Some("[Multipart Upload]".to_string());
+        request
+    } else if method == "POST" {
         let (parts, body) = request.into_parts();
         match axum::body::to_bytes(body, MAX_REQUEST_LOG_SIZE).await {
             Ok(bytes) => {
-                if model.is_none() {
-                    model = serde_json::from_slice::<Value>(&bytes).ok().and_then(|v|
-                        v.get("model").and_then(|m| m.as_str()).map(|s| s.to_string())
-                    );
+                // 与处理器使用同一套宽松解码 (BOM / gzip / deflate)
+                let mut redacted_body = None;
+                if let Ok(mut v) = crate::proxy::common::request_body::decode_json_body::<Value>(&parts.headers, &bytes) {
+                    if model.is_none() {
+                        model = v.get("model").and_then(|m| m.as_str()).map(|s| s.to_string());
+                    }
+                    end_user = crate::proxy::common::end_user::end_user_label(&v, state.monitor.hash_end_users());
+                    if state.monitor.redact_content() && crate::proxy::monitor::redact_message_content(&mut v) {
+                        redacted_body = Some(v.to_string());
+                    }
                 }
-                request_body_str = if let Ok(s) = std::str::from_utf8(&bytes) {
+                request_body_str = if redacted_body.is_some() {
+                    redacted_body
+                } else if let Ok(s) = std::str::from_utf8(&bytes) {
                     Some(s.to_string())
                 } else {
                     Some("[Binary Request Data]".to_string())
@@ -76,10 +105,10 @@ pub async fn monitor_middleware(
         .unwrap_or("")
         .to_string();
 
-    // Extract account email from X-Account-Email header if present
-    let account_email = response
-        .headers()
-        .get("X-Account-Email")
+    // Extract account identifier from X-Account-Email (or X-Account-Id when anonymized) if present
+    let account_email = [ACCOUNT_EMAIL_HEADER, ACCOUNT_ID_HEADER]
+        .iter()
+        .find_map(|h| response.headers().get(*h))
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string());
 
@@ -90,6 +119,22 @@ pub async fn monitor_middleware(
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string());
 
+    let upstream_region = response
+        .headers()
+        .get(crate::proxy::upstream::region::UPSTREAM_REGION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    let locally_answered = response
+        .headers()
+        .contains_key(crate::proxy::mappers::claude::local_responder::LOCALLY_ANSWERED_HEADER);
+
+    // 记录生效的优先级与服务账号等级，用于统计批量请求是否落在低等级账号上
+    let priority = Some(state.token_manager.effective_priority(requested_priority).await.as_str().to_string());
+    let account_tier = account_email
+        .as_deref()
+        .and_then(|identifier| state.token_manager.tier_for_identifier(identifier));
+
     let monitor = state.monitor.clone();
     let mut log = ProxyRequestLog {
         id: uuid::Uuid::new_v4().to_string(),
@@ -106,6 +151,14 @@ pub async fn monitor_middleware(
         response_body: None,
         input_tokens: None,
         output_tokens: None,
+        replay_of,
+        priority,
+        account_tier,
+        cached_tokens: None,
+        cost_pico: None,
+        locally_answered,
+        end_user,
+        upstream_region,
     };
 
     if content_type.contains("text/event-stream") {
@@ -133,6 +186,23 @@ pub async fn monitor_middleware(
             }
             
             if let Ok(full_tail) = std::str::from_utf8(&last_few_bytes) {
+                // 流中途出错的错误帧 (data: {"error": {...}})，记录为失败请求
+                log.error = full_tail
+                    .lines()
+                    .rev()
+                    .filter_map(|line| line.strip_prefix("data: "))
+                    .filter(|data| data.contains("\"error\""))
+                    .filter_map(|data| serde_json::from_str::<Value>(data.trim()).ok())
+                    .find_map(|json| {
+                        let err = json.get("error")?;
+                        Some(
+                            err.get("message")
+                                .and_then(|m| m.as_str())
+                                .map(|m| m.to_string())
+                                .unwrap_or_else(|| err.to_string()),
+                        )
+                    });
+
                 for line in full_tail.lines().rev() {
                     if line.starts_with("data: ") && (line.contains("\"usage\"") || line.contains("\"usageMetadata\"")) {
                         let json_str = line.trim_start_matches("data: ").trim();
@@ -149,6 +219,11 @@ pub async fn monitor_middleware(
                                     .or(usage.get("candidatesTokenCount"))
                                     .and_then(|v| v.as_u64())
                                     .map(|v| v as u32);
+                                log.cached_tokens = usage.get("cache_read_input_tokens")
+                                    .or(usage.get("cachedContentTokenCount"))
+                                    .or(usage.pointer("/prompt_tokens_details/cached_tokens"))
+                                    .and_then(|v| v.as_u64())
+                                    .map(|v| v as u32);
                                 
                                 if log.input_tokens.is_none() && log.output_tokens.is_none() {
                                     log.output_tokens = usage.get("total_tokens")
@@ -163,7 +238,7 @@ pub async fn monitor_middleware(
                 }
             }
             
-            if log.status >= 400 {
+            if log.status >= 400 && log.error.is_none() {
                 log.error = Some("Stream Error or Failed".to_string());
             }
             monitor.log_request(log).await;
@@ -188,6 +263,11 @@ pub async fn monitor_middleware(
                                 .or(usage.get("candidatesTokenCount"))
                                 .and_then(|v| v.as_u64())
                                 .map(|v| v as u32);
+                            log.cached_tokens = usage.get("cache_read_input_tokens")
+                                .or(usage.get("cachedContentTokenCount"))
+                                .or(usage.pointer("/prompt_tokens_details/cached_tokens"))
+                                .and_then(|v| v.as_u64())
+                                .map(|v| v as u32);
                                 
                             if log.input_tokens.is_none() && log.output_tokens.is_none() {
                                 log.output_tokens = usage.get("total_tokens")
@@ -0,0 +1,210 @@
+// 响应头暴露策略中间件
+// 处理器统一写入账号与模型响应头 (监控中间件据此记录真实值)，
+// 本中间件位于监控之外，在响应离开反代前按配置打码或移除这些头。
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::proxy::common::account_identity::{ACCOUNT_EMAIL_HEADER, ACCOUNT_ID_HEADER};
+use crate::proxy::config::{ResponseHeaderPolicy, ResponseHeadersConfig};
+use crate::proxy::ProxySecurityConfig;
+
+/// 映射后模型响应头
+pub const MAPPED_MODEL_HEADER: &str = "X-Mapped-Model";
+
+/// 自定义响应头不能覆盖的名称 (协议/编码相关、CORS、诊断头)
+const RESERVED_HEADERS: &[&str] = &[
+    "content-type",
+    "content-length",
+    "content-encoding",
+    "transfer-encoding",
+    "connection",
+    "keep-alive",
+    "upgrade",
+    "trailer",
+    "te",
+    "retry-after",
+    "set-cookie",
+    "www-authenticate",
+    "x-account-email",
+    "x-account-id",
+    "x-mapped-model",
+];
+
+/// 自定义响应头名称是否被保留 (含 CORS 与反代内部前缀)
+fn is_reserved_header(name: &HeaderName) -> bool {
+    let name = name.as_str();
+    RESERVED_HEADERS.contains(&name) || name.starts_with("access-control-") || name.starts_with("x-antigravity-")
+}
+
+/// 按响应头暴露策略处理响应头
+pub async fn response_header_middleware(
+    State(security): State<Arc<RwLock<ProxySecurityConfig>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (policy, config) = {
+        let security = security.read().await;
+        (security.response_header_policy, security.response_headers.clone())
+    };
+    let mut response = next.run(request).await;
+    apply_header_policy(response.headers_mut(), policy);
+    apply_header_config(response.headers_mut(), &config);
+    response
+}
+
+/// 对响应头应用暴露策略
+pub fn apply_header_policy(headers: &mut HeaderMap, policy: ResponseHeaderPolicy) {
+    match policy {
+        ResponseHeaderPolicy::Full => {}
+        ResponseHeaderPolicy::Masked => {
+            let masked = headers
+                .get(ACCOUNT_EMAIL_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(mask_email);
+            if let Some(masked) = masked {
+                match HeaderValue::from_str(&masked) {
+                    Ok(v) => {
+                        headers.insert(ACCOUNT_EMAIL_HEADER, v);
+                    }
+                    Err(_) => {
+                        headers.remove(ACCOUNT_EMAIL_HEADER);
+                    }
+                }
+            }
+        }
+        ResponseHeaderPolicy::Minimal => {
+            headers.remove(ACCOUNT_EMAIL_HEADER);
+            headers.remove(ACCOUNT_ID_HEADER);
+            headers.remove(MAPPED_MODEL_HEADER);
+        }
+    }
+}
+
+/// 按诊断头开关移除账号/模型头，并写入自定义响应头
+pub fn apply_header_config(headers: &mut HeaderMap, config: &ResponseHeadersConfig) {
+    if !config.expose_account {
+        headers.remove(ACCOUNT_EMAIL_HEADER);
+        headers.remove(ACCOUNT_ID_HEADER);
+    }
+    if !config.expose_model {
+        headers.remove(MAPPED_MODEL_HEADER);
+    }
+    for (name, value) in &config.extra {
+        match (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+            (Ok(header), _) if is_reserved_header(&header) => {
+                tracing::warn!("Ignoring custom response header {}: reserved by the proxy", name);
+            }
+            (Ok(name), Ok(value)) => {
+                headers.insert(name, value);
+            }
+            _ => tracing::debug!("Ignoring invalid custom response header: {}", name),
+        }
+    }
+}
+
+/// 邮箱部分打码：保留用户名前两位与域名，如 `al***@example.com`
+pub fn mask_email(email: &str) -> String {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            let visible: String = local.chars().take(2.min(local.chars().count().saturating_sub(1))).collect();
+            format!("{}***@{}", visible, domain)
+        }
+        None => "***".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mocked_response_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCOUNT_EMAIL_HEADER, HeaderValue::from_static("alice@example.com"));
+        headers.insert(MAPPED_MODEL_HEADER, HeaderValue::from_static("gemini-3-flash"));
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        headers
+    }
+
+    #[test]
+    fn test_full_policy_keeps_headers() {
+        let mut headers = mocked_response_headers();
+        apply_header_policy(&mut headers, ResponseHeaderPolicy::Full);
+        assert_eq!(headers.get(ACCOUNT_EMAIL_HEADER).unwrap(), "alice@example.com");
+        assert_eq!(headers.get(MAPPED_MODEL_HEADER).unwrap(), "gemini-3-flash");
+    }
+
+    #[test]
+    fn test_masked_policy_masks_email_only() {
+        let mut headers = mocked_response_headers();
+        apply_header_policy(&mut headers, ResponseHeaderPolicy::Masked);
+        assert_eq!(headers.get(ACCOUNT_EMAIL_HEADER).unwrap(), "al***@example.com");
+        assert_eq!(headers.get(MAPPED_MODEL_HEADER).unwrap(), "gemini-3-flash");
+    }
+
+    #[test]
+    fn test_minimal_policy_strips_account_and_model_headers() {
+        let mut headers = mocked_response_headers();
+        headers.insert(ACCOUNT_ID_HEADER, HeaderValue::from_static("acct-0123456789ab"));
+        apply_header_policy(&mut headers, ResponseHeaderPolicy::Minimal);
+        assert!(headers.get(ACCOUNT_EMAIL_HEADER).is_none());
+        assert!(headers.get(ACCOUNT_ID_HEADER).is_none());
+        assert!(headers.get(MAPPED_MODEL_HEADER).is_none());
+        assert_eq!(headers.get("content-type").unwrap(), "application/json");
+    }
+
+    #[test]
+    fn test_header_config_toggles_and_extra_headers() {
+        let mut headers = mocked_response_headers();
+        headers.insert(ACCOUNT_ID_HEADER, HeaderValue::from_static("acct-0123456789ab"));
+        let config = ResponseHeadersConfig {
+            expose_account: false,
+            expose_model: true,
+            extra: [
+                ("X-Served-By".to_string(), "edge-1".to_string()),
+                ("bad header".to_string(), "ignored".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        apply_header_config(&mut headers, &config);
+        assert!(headers.get(ACCOUNT_EMAIL_HEADER).is_none());
+        assert!(headers.get(ACCOUNT_ID_HEADER).is_none());
+        assert_eq!(headers.get(MAPPED_MODEL_HEADER).unwrap(), "gemini-3-flash");
+        assert_eq!(headers.get("X-Served-By").unwrap(), "edge-1");
+        assert_eq!(headers.len(), 3);
+
+        // 保留的响应头不会被覆盖
+        let mut headers = mocked_response_headers();
+        let config = ResponseHeadersConfig {
+            extra: [
+                ("Content-Type".to_string(), "text/html".to_string()),
+                ("X-Mapped-Model".to_string(), "spoofed".to_string()),
+                ("Access-Control-Allow-Origin".to_string(), "*".to_string()),
+                ("Transfer-Encoding".to_string(), "chunked".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            ..ResponseHeadersConfig::default()
+        };
+        apply_header_config(&mut headers, &config);
+        assert_eq!(headers, mocked_response_headers());
+
+        // 默认配置不改动任何响应头
+        let mut headers = mocked_response_headers();
+        apply_header_config(&mut headers, &ResponseHeadersConfig::default());
+        assert_eq!(headers, mocked_response_headers());
+    }
+
+    #[test]
+    fn test_mask_email_short_local_part() {
+        assert_eq!(mask_email("a@example.com"), "***@example.com");
+        assert_eq!(mask_email("ab@example.com"), "a***@example.com");
+        assert_eq!(mask_email("not-an-email"), "***");
+    }
+}
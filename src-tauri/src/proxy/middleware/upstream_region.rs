@@ -0,0 +1,52 @@
+// 上游区域中间件
+// 解析 X-Upstream-Region 并写入请求扩展，处理器据此把整个请求 (含重试) 固定到该区域；
+// 响应回显实际使用的区域，监控中间件据此记录。
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+use crate::proxy::server::AppState;
+use crate::proxy::upstream::region::{self, UPSTREAM_REGION_HEADER};
+
+pub async fn upstream_region_middleware(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let resolved = {
+        let experimental = state.experimental.read().await;
+        region::resolve(request.headers(), &experimental.upstream_regions)
+    };
+
+    let region = match resolved {
+        Ok(Some(region)) => region,
+        Ok(None) => return next.run(request).await,
+        Err(message) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": {
+                        "message": message,
+                        "type": "invalid_request_error",
+                        "param": UPSTREAM_REGION_HEADER,
+                        "code": null
+                    }
+                })),
+            )
+                .into_response();
+        }
+    };
+
+    let name = HeaderValue::from_str(&region.name).ok();
+    request.extensions_mut().insert(region);
+    let mut response = next.run(request).await;
+    if let Some(name) = name {
+        response.headers_mut().insert(UPSTREAM_REGION_HEADER, name);
+    }
+    response
+}
@@ -3,6 +3,7 @@
 // 现有模块 (保留)
 pub mod config;
 pub mod token_manager;
+pub mod token_persister;
 pub mod project_resolver;
 pub mod server;
 pub mod security;
@@ -18,10 +19,18 @@ pub mod zai_vision_mcp;    // Built-in Vision MCP server state
 pub mod zai_vision_tools;  // Built-in Vision MCP tools (z.ai vision API)
 pub mod monitor;           // 监控
 pub mod rate_limit;        // 限流跟踪
+pub mod probe;             // 号池耗尽前的最后探测
+pub mod clock;             // 时钟抽象 (测试可注入假时钟)
 pub mod sticky_config;     // 粘性调度配置
 pub mod session_manager;   // 会话指纹管理
 pub mod audio;             // 音频处理模块 (PR #311)
 pub mod signature_cache;   // Signature Cache (v3.3.16)
+pub mod replay;            // 请求重放
+pub mod metrics;           // Prometheus 指标
+pub mod self_test;         // 端到端吞吐自测
+pub mod port_check;        // 端口占用检测
+pub mod tls;               // 监听端 HTTPS
+pub mod pricing;           // 用量费用估算
 
 
 pub use config::ProxyConfig;
@@ -1,9 +1,43 @@
 use serde::{Serialize, Deserialize};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use tokio::sync::RwLock;
 use tauri::Emitter;
 use std::sync::atomic::{AtomicBool, Ordering};
 
+/// 日志中被脱敏的消息内容使用的占位符
+pub const REDACTED_PLACEHOLDER: &str = "[REDACTED]";
+
+/// 请求体中承载消息内容的字段 (OpenAI / Claude / Gemini)
+const MESSAGE_CONTENT_KEYS: [&str; 6] = ["messages", "contents", "system", "systemInstruction", "prompt", "input"];
+
+/// 消息内容中保留原值的结构字段
+const STRUCTURAL_KEYS: [&str; 8] = ["role", "type", "id", "tool_use_id", "tool_call_id", "name", "media_type", "mimeType"];
+
+/// 将请求体中的消息内容替换为占位符 (保留模型、参数与消息结构)，返回是否有内容被替换
+pub fn redact_message_content(body: &mut serde_json::Value) -> bool {
+    fn redact(value: &mut serde_json::Value) -> bool {
+        match value {
+            serde_json::Value::String(s) => {
+                *s = REDACTED_PLACEHOLDER.to_string();
+                true
+            }
+            serde_json::Value::Array(items) => items.iter_mut().fold(false, |acc, v| redact(v) || acc),
+            serde_json::Value::Object(map) => map
+                .iter_mut()
+                .filter(|(key, _)| !STRUCTURAL_KEYS.contains(&key.as_str()))
+                .fold(false, |acc, (_, v)| redact(v) || acc),
+            _ => false,
+        }
+    }
+
+    let Some(map) = body.as_object_mut() else {
+        return false;
+    };
+    map.iter_mut()
+        .filter(|(key, _)| MESSAGE_CONTENT_KEYS.contains(&key.as_str()))
+        .fold(false, |acc, (_, v)| redact(v) || acc)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProxyRequestLog {
     pub id: String,
@@ -20,6 +54,68 @@ pub struct ProxyRequestLog {
     pub response_body: Option<String>,
     pub input_tokens: Option<u32>,
     pub output_tokens: Option<u32>,
+    /// 若为重放请求，记录被重放的原始日志 ID (重放请求不计入统计)
+    #[serde(default)]
+    pub replay_of: Option<String>,
+    /// 生效的请求优先级 (interactive / bulk)
+    #[serde(default)]
+    pub priority: Option<String>,
+    /// 实际服务该请求的账号订阅等级
+    #[serde(default)]
+    pub account_tier: Option<String>,
+    /// 命中缓存的输入 token 数
+    #[serde(default)]
+    pub cached_tokens: Option<u32>,
+    /// 按配置单价估算的费用 (皮美元)，模型没有单价或没有用量时为空
+    #[serde(default)]
+    pub cost_pico: Option<i64>,
+    /// 由代理本地应答，未转发上游 (单独计数)
+    #[serde(default)]
+    pub locally_answered: bool,
+    /// 客户端传入的终端用户标识 (可能已哈希)
+    #[serde(default)]
+    pub end_user: Option<String>,
+    /// 客户端通过 X-Upstream-Region 指定的上游区域
+    #[serde(default)]
+    pub upstream_region: Option<String>,
+}
+
+/// 单个终端用户的请求量与 token 用量
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct EndUserStats {
+    pub end_user: String,
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// 最后探测的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeOutcome {
+    /// 探测成功，账号已解除锁定
+    Recovered,
+    /// 探测请求失败，账号保持锁定
+    Failed,
+    /// 距上次探测过近，本次未探测
+    Throttled,
+}
+
+impl ProbeOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProbeOutcome::Recovered => "recovered",
+            ProbeOutcome::Failed => "failed",
+            ProbeOutcome::Throttled => "throttled",
+        }
+    }
+}
+
+/// 最后探测的次数统计 (本次运行期间)
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ProbeStats {
+    pub recovered: u64,
+    pub failed: u64,
+    pub throttled: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -27,6 +123,30 @@ pub struct ProxyStats {
     pub total_requests: u64,
     pub success_count: u64,
     pub error_count: u64,
+    /// 各优先级下请求落在各订阅等级账号上的数量 (priority -> tier -> count)
+    #[serde(default)]
+    pub priority_breakdown: BTreeMap<String, BTreeMap<String, u64>>,
+    /// 本地应答的请求数 (不计入上面的上游请求统计)
+    #[serde(default)]
+    pub locally_answered: u64,
+    /// z.ai 各 Key 的请求数与锁定状态 (仅服务运行时填充)
+    #[serde(default)]
+    pub zai_keys: Vec<crate::proxy::providers::zai_key_pool::ZaiKeyStats>,
+    /// 各模型上游以 RECITATION 截断输出的次数 (本次运行期间)
+    #[serde(default)]
+    pub recitations: BTreeMap<String, u64>,
+    /// 请求数最多的终端用户 (按请求数降序)
+    #[serde(default)]
+    pub top_end_users: Vec<EndUserStats>,
+    /// 号池耗尽前的最后探测结果 (本次运行期间)
+    #[serde(default)]
+    pub last_resort_probes: ProbeStats,
+    /// 备用账号被启用的次数 (仅服务运行时填充)
+    #[serde(default)]
+    pub standby_activations: u64,
+    /// 各配额分组的选号次数 (group -> count，仅服务运行时填充)
+    #[serde(default)]
+    pub group_selections: BTreeMap<String, u64>,
 }
 
 pub struct ProxyMonitor {
@@ -34,6 +154,20 @@ pub struct ProxyMonitor {
     pub stats: RwLock<ProxyStats>,
     pub max_logs: usize,
     pub enabled: AtomicBool,
+    /// Prometheus 指标 (独立于日志开关)
+    pub metrics: crate::proxy::metrics::ProxyMetrics,
+    /// 各模型 RECITATION 次数 (流式响应的转换闭包中同步记录，不受日志开关影响)
+    recitations: std::sync::Mutex<BTreeMap<String, u64>>,
+    /// 最后探测结果计数
+    probes: std::sync::Mutex<ProbeStats>,
+    /// 以哈希记录终端用户标识
+    hash_end_users: AtomicBool,
+    /// 请求日志中的消息内容以占位符保存 (此类记录不可重放)
+    redact_content: AtomicBool,
+    /// 费用估算单价
+    pricing: std::sync::RwLock<crate::proxy::pricing::PricingTable>,
+    /// 计价请求后刷新托盘中的当天费用
+    tray_cost: AtomicBool,
     app_handle: Option<tauri::AppHandle>,
 }
 
@@ -63,6 +197,13 @@ impl ProxyMonitor {
             stats: RwLock::new(ProxyStats::default()),
             max_logs,
             enabled: AtomicBool::new(false), // Default to disabled
+            metrics: crate::proxy::metrics::ProxyMetrics::new(),
+            recitations: std::sync::Mutex::new(BTreeMap::new()),
+            probes: std::sync::Mutex::new(ProbeStats::default()),
+            hash_end_users: AtomicBool::new(false),
+            redact_content: AtomicBool::new(false),
+            pricing: std::sync::RwLock::new(crate::proxy::pricing::PricingTable::default()),
+            tray_cost: AtomicBool::new(false),
             app_handle,
         }
     }
@@ -71,17 +212,86 @@ impl ProxyMonitor {
         self.enabled.store(enabled, Ordering::Relaxed);
     }
 
+    /// 向前端广播一个无负载事件 (如 `config://updated`)
+    pub fn notify(&self, event: &str) {
+        if let Some(app) = &self.app_handle {
+            let _ = app.emit(event, ());
+        }
+    }
+
+    pub fn set_hash_end_users(&self, hashed: bool) {
+        self.hash_end_users.store(hashed, Ordering::Relaxed);
+    }
+
+    pub fn set_redact_content(&self, redact: bool) {
+        self.redact_content.store(redact, Ordering::Relaxed);
+    }
+
+    /// 更新费用估算单价与托盘费用显示开关
+    pub fn set_pricing(&self, pricing: crate::proxy::pricing::PricingTable, show_in_tray: bool) {
+        if let Ok(mut table) = self.pricing.write() {
+            *table = pricing;
+        }
+        self.tray_cost.store(show_in_tray, Ordering::Relaxed);
+    }
+
+    /// 按当前单价估算请求费用 (使用实际路由的模型)
+    fn estimate_cost(&self, log: &ProxyRequestLog) -> Option<i64> {
+        if log.input_tokens.is_none() && log.output_tokens.is_none() {
+            return None;
+        }
+        let model = log.mapped_model.as_deref().or(log.model.as_deref())?;
+        let cached = u64::from(log.cached_tokens.unwrap_or(0));
+        let input = u64::from(log.input_tokens.unwrap_or(0));
+        // Claude 协议的 input_tokens 已扣除缓存部分，其余协议包含缓存部分
+        let uncached = if log.url.starts_with("/v1/messages") { input } else { input.saturating_sub(cached) };
+        let output = u64::from(log.output_tokens.unwrap_or(0));
+        self.pricing.read().ok()?.cost_pico(model, uncached, cached, output)
+    }
+
+    pub fn hash_end_users(&self) -> bool {
+        self.hash_end_users.load(Ordering::Relaxed)
+    }
+
+    pub fn redact_content(&self) -> bool {
+        self.redact_content.load(Ordering::Relaxed)
+    }
+
     pub fn is_enabled(&self) -> bool {
         self.enabled.load(Ordering::Relaxed)
     }
 
-    pub async fn log_request(&self, log: ProxyRequestLog) {
+    /// 是否需要经过监控中间件 (日志或指标任一开启)
+    pub fn is_observing(&self) -> bool {
+        self.is_enabled() || self.metrics.is_enabled()
+    }
+
+    pub async fn log_request(&self, mut log: ProxyRequestLog) {
+        log.cost_pico = self.estimate_cost(&log);
+        if log.replay_of.is_none() {
+            self.metrics.record(&log);
+        }
+        // 费用统计独立于请求日志：日志关闭或被清空时历史费用仍然保留
+        // (无 app_handle 的测试实例不落盘)
+        if let Some(app) = self.app_handle.clone().filter(|_| crate::modules::proxy_db::counts_toward_usage(&log)) {
+            let usage = log.clone();
+            let refresh_tray = log.cost_pico.is_some() && self.tray_cost.load(Ordering::Relaxed);
+            tokio::spawn(async move {
+                if let Err(e) = crate::modules::proxy_db::record_usage(&usage) {
+                    tracing::error!("Failed to record usage cost: {}", e);
+                } else if refresh_tray {
+                    crate::modules::tray::update_tray_menus(&app);
+                }
+            });
+        }
         if !self.is_enabled() {
             return;
         }
         tracing::info!("[Monitor] Logging request: {} {}", log.method, log.url);
-        // Update stats
-        {
+        // Update stats (replays are tagged and excluded)
+        if log.locally_answered {
+            self.stats.write().await.locally_answered += 1;
+        } else if log.replay_of.is_none() {
             let mut stats = self.stats.write().await;
             stats.total_requests += 1;
             if log.status >= 200 && log.status < 400 {
@@ -89,6 +299,15 @@ impl ProxyMonitor {
             } else {
                 stats.error_count += 1;
             }
+            if let Some(priority) = &log.priority {
+                let tier = log.account_tier.clone().unwrap_or_else(|| "UNKNOWN".to_string());
+                *stats
+                    .priority_breakdown
+                    .entry(priority.clone())
+                    .or_default()
+                    .entry(tier)
+                    .or_insert(0) += 1;
+            }
         }
 
         // Add log to memory
@@ -127,14 +346,42 @@ impl ProxyMonitor {
         }
     }
 
+    /// 记录一次上游 RECITATION
+    pub fn record_recitation(&self, model: &str) {
+        if let Ok(mut counts) = self.recitations.lock() {
+            *counts.entry(model.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    /// 记录一次最后探测的结果
+    pub fn record_probe(&self, outcome: ProbeOutcome) {
+        if let Ok(mut probes) = self.probes.lock() {
+            match outcome {
+                ProbeOutcome::Recovered => probes.recovered += 1,
+                ProbeOutcome::Failed => probes.failed += 1,
+                ProbeOutcome::Throttled => probes.throttled += 1,
+            }
+        }
+        self.metrics.record_probe(outcome.as_str());
+    }
+
+    pub fn probe_stats(&self) -> ProbeStats {
+        self.probes.lock().map(|p| p.clone()).unwrap_or_default()
+    }
+
     pub async fn get_stats(&self) -> ProxyStats {
-        match crate::modules::proxy_db::get_stats() {
+        let mut stats = match crate::modules::proxy_db::get_stats() {
             Ok(stats) => stats,
             Err(e) => {
                 tracing::error!("Failed to get stats from DB: {}", e);
                 self.stats.read().await.clone()
             }
+        };
+        if let Ok(counts) = self.recitations.lock() {
+            stats.recitations = counts.clone();
         }
+        stats.last_resort_probes = self.probe_stats();
+        stats
     }
     
     pub async fn clear(&self) {
@@ -142,6 +389,12 @@ impl ProxyMonitor {
         logs.clear();
         let mut stats = self.stats.write().await;
         *stats = ProxyStats::default();
+        if let Ok(mut counts) = self.recitations.lock() {
+            counts.clear();
+        }
+        if let Ok(mut probes) = self.probes.lock() {
+            *probes = ProbeStats::default();
+        }
 
         if let Err(e) = crate::modules::proxy_db::clear_logs() {
             tracing::error!("Failed to clear logs in DB: {}", e);
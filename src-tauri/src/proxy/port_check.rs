@@ -0,0 +1,91 @@
+// 端口占用检测
+// 启动前先尝试绑定监听地址，端口被占用时给出可读的提示与候选端口，
+// 开启 auto_pick_port 时直接改用附近的空闲端口。
+
+use std::io::ErrorKind;
+use std::net::TcpListener;
+
+/// 在配置端口之后依次尝试的候选端口数量
+const CANDIDATE_RANGE: u16 = 20;
+/// 冲突提示中列出的候选端口数量
+const SUGGESTION_COUNT: usize = 3;
+
+/// 尝试绑定 host:port，成功后立即释放
+fn try_bind(host: &str, port: u16) -> std::io::Result<()> {
+    TcpListener::bind((host, port)).map(drop)
+}
+
+/// 配置端口之后的若干空闲端口
+pub fn suggest_free_ports(host: &str, port: u16, count: usize) -> Vec<u16> {
+    (1..=CANDIDATE_RANGE)
+        .filter_map(|offset| port.checked_add(offset))
+        .filter(|candidate| try_bind(host, *candidate).is_ok())
+        .take(count)
+        .collect()
+}
+
+/// 选一个空闲端口：优先配置端口附近，都被占用时由系统分配
+pub fn pick_free_port(host: &str, port: u16) -> Result<u16, String> {
+    if let Some(candidate) = suggest_free_ports(host, port, 1).first() {
+        return Ok(*candidate);
+    }
+    TcpListener::bind((host, 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .map_err(|e| format!("无法分配空闲端口: {}", e))
+}
+
+/// 端口被占用时的提示文本
+fn conflict_message(host: &str, port: u16, suggestions: &[u16]) -> String {
+    let mut message = format!("端口 {} 已被其他程序占用 (监听地址 {}:{})", port, host, port);
+    if !suggestions.is_empty() {
+        let list: Vec<String> = suggestions.iter().map(|p| p.to_string()).collect();
+        message.push_str(&format!("，可改用空闲端口: {}", list.join(", ")));
+    }
+    message.push_str("，或开启「自动选择端口」");
+    message
+}
+
+/// 确认监听端口可用，返回实际使用的端口
+///
+/// 端口被占用时：`auto_pick` 为 true 则改用空闲端口，否则返回带候选端口的错误
+pub fn resolve_listen_port(host: &str, port: u16, auto_pick: bool) -> Result<u16, String> {
+    match try_bind(host, port) {
+        Ok(()) => Ok(port),
+        Err(e) if e.kind() == ErrorKind::AddrInUse => {
+            if auto_pick {
+                let picked = pick_free_port(host, port)?;
+                tracing::warn!("端口 {} 已被占用，自动改用端口 {}", port, picked);
+                Ok(picked)
+            } else {
+                Err(conflict_message(host, port, &suggest_free_ports(host, port, SUGGESTION_COUNT)))
+            }
+        }
+        Err(e) => Err(format!("地址 {}:{} 无法绑定: {}", host, port, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_occupied_port_reports_conflict() {
+        let holder = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = holder.local_addr().unwrap().port();
+
+        let err = resolve_listen_port("127.0.0.1", port, false).unwrap_err();
+        assert!(err.contains(&format!("端口 {} 已被其他程序占用", port)), "{}", err);
+        assert!(err.contains("自动选择端口"));
+
+        let picked = resolve_listen_port("127.0.0.1", port, true).unwrap();
+        assert_ne!(picked, port);
+        assert!(try_bind("127.0.0.1", picked).is_ok());
+    }
+
+    #[test]
+    fn test_free_port_is_kept() {
+        let port = TcpListener::bind(("127.0.0.1", 0)).unwrap().local_addr().unwrap().port();
+        assert_eq!(resolve_listen_port("127.0.0.1", port, true).unwrap(), port);
+    }
+}
@@ -0,0 +1,204 @@
+// 用量费用估算
+// 单价按「微美元 / 百万 token」取整保存，单个请求的费用 = token 数 × 单价，单位为皮美元 (1e-12 美元)。
+// 费用全程以整数累加，只在展示时换算为美元，避免数千次请求后出现分位漂移。
+
+use chrono::{DateTime, Local, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::models::config::ModelPrice;
+
+/// 1 美元对应的皮美元数
+pub const PICO_PER_USD: i64 = 1_000_000_000_000;
+
+/// 取整后的单价 (微美元 / 百万 token，即皮美元 / token)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PriceMicros {
+    input: i64,
+    output: i64,
+    cached_input: i64,
+}
+
+fn to_micros(pattern: &str, field: &str, price: f64) -> Result<i64, String> {
+    if !price.is_finite() || price < 0.0 {
+        return Err(format!("模型 {} 的 {} 单价无效: {}", pattern, field, price));
+    }
+    Ok((price * 1_000_000.0).round() as i64)
+}
+
+/// 按模型匹配的单价表
+#[derive(Debug, Clone, Default)]
+pub struct PricingTable {
+    entries: HashMap<String, PriceMicros>,
+}
+
+impl PricingTable {
+    /// 由配置生成单价表，单价为负数或非有限值时报错
+    pub fn from_config(pricing: &HashMap<String, ModelPrice>) -> Result<Self, String> {
+        let mut entries = HashMap::with_capacity(pricing.len());
+        for (pattern, price) in pricing {
+            let input = to_micros(pattern, "input", price.input)?;
+            let cached_input = match price.cached_input {
+                Some(cached) => to_micros(pattern, "cached_input", cached)?,
+                None => input,
+            };
+            entries.insert(
+                pattern.clone(),
+                PriceMicros {
+                    input,
+                    output: to_micros(pattern, "output", price.output)?,
+                    cached_input,
+                },
+            );
+        }
+        Ok(Self { entries })
+    }
+
+    /// 精确匹配优先，其次最长的前缀匹配 (与 sampling_limits.per_model 一致)
+    fn lookup(&self, model: &str) -> Option<&PriceMicros> {
+        if let Some(price) = self.entries.get(model) {
+            return Some(price);
+        }
+        self.entries
+            .iter()
+            .filter_map(|(pattern, price)| {
+                pattern
+                    .strip_suffix('*')
+                    .filter(|prefix| model.starts_with(prefix))
+                    .map(|prefix| (prefix.len(), price))
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, price)| price)
+    }
+
+    /// 单个请求的估算费用 (皮美元)，模型没有单价时为 None
+    pub fn cost_pico(&self, model: &str, uncached_input: u64, cached_input: u64, output: u64) -> Option<i64> {
+        let price = self.lookup(model)?;
+        let total = i128::from(uncached_input) * i128::from(price.input)
+            + i128::from(cached_input) * i128::from(price.cached_input)
+            + i128::from(output) * i128::from(price.output);
+        Some(i64::try_from(total).unwrap_or(i64::MAX))
+    }
+}
+
+/// 皮美元换算为美元 (仅用于展示)
+pub fn pico_to_usd(pico: i128) -> f64 {
+    let whole = pico / i128::from(PICO_PER_USD);
+    let frac = pico % i128::from(PICO_PER_USD);
+    whole as f64 + frac as f64 / PICO_PER_USD as f64
+}
+
+/// 费用统计的时间范围
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageRange {
+    /// 本地时间的今天
+    Today,
+    /// 最近 7 天
+    Week,
+    /// 最近 30 天
+    Month,
+    All,
+}
+
+impl UsageRange {
+    /// 范围起点 (毫秒时间戳，与请求日志一致)
+    pub fn since_ms(self, now: DateTime<Local>) -> i64 {
+        match self {
+            UsageRange::Today => Local
+                .from_local_datetime(&now.date_naive().and_time(NaiveTime::MIN))
+                .earliest()
+                .map(|midnight| midnight.timestamp_millis())
+                .unwrap_or_else(|| now.timestamp_millis() - 86_400_000),
+            UsageRange::Week => now.timestamp_millis() - 7 * 86_400_000,
+            UsageRange::Month => now.timestamp_millis() - 30 * 86_400_000,
+            UsageRange::All => 0,
+        }
+    }
+}
+
+/// 按天 / 账号 / 模型聚合的已计价用量
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct UsageCostRow {
+    /// 本地日期 (YYYY-MM-DD)
+    pub day: String,
+    pub account_email: String,
+    pub model: String,
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cached_tokens: u64,
+    pub cost_usd: f64,
+    #[serde(skip)]
+    pub cost_pico: i64,
+}
+
+/// 没有匹配单价的用量 (按模型)
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct UnpricedUsage {
+    pub model: String,
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cached_tokens: u64,
+}
+
+/// 费用估算报告
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageCostReport {
+    pub range: UsageRange,
+    pub since: i64,
+    pub total_usd: f64,
+    pub rows: Vec<UsageCostRow>,
+    pub unpriced: Vec<UnpricedUsage>,
+}
+
+impl UsageCostReport {
+    pub fn new(range: UsageRange, since: i64, rows: Vec<UsageCostRow>, unpriced: Vec<UnpricedUsage>) -> Self {
+        let total: i128 = rows.iter().map(|r| i128::from(r.cost_pico)).sum();
+        Self { range, since, total_usd: pico_to_usd(total), rows, unpriced }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(input: f64, output: f64, cached_input: Option<f64>) -> ModelPrice {
+        ModelPrice { input, output, cached_input }
+    }
+
+    #[test]
+    fn test_cost_uses_cached_price_and_longest_prefix() {
+        let mut pricing = HashMap::new();
+        pricing.insert("claude-*".to_string(), price(3.0, 15.0, Some(0.3)));
+        pricing.insert("claude-opus-*".to_string(), price(15.0, 75.0, None));
+        pricing.insert("gemini-2.5-flash".to_string(), price(0.075, 0.3, None));
+        let table = PricingTable::from_config(&pricing).unwrap();
+
+        // 1000 × 3 + 500 × 0.3 + 200 × 15 (皮美元 × 1e6)
+        assert_eq!(table.cost_pico("claude-sonnet-4-5", 1000, 500, 200), Some(6_150_000_000));
+        // 未设置缓存单价时按输入单价计
+        assert_eq!(table.cost_pico("claude-opus-4", 0, 1000, 0), Some(15_000_000_000));
+        assert_eq!(table.cost_pico("gemini-2.5-flash", 1, 0, 0), Some(75_000));
+        assert_eq!(table.cost_pico("gpt-4o", 1000, 0, 1000), None);
+
+        let mut invalid = HashMap::new();
+        invalid.insert("x".to_string(), price(-1.0, 0.0, None));
+        assert!(PricingTable::from_config(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_totals_do_not_drift_over_many_requests() {
+        let mut pricing = HashMap::new();
+        pricing.insert("m".to_string(), price(0.1, 0.7, None));
+        let table = PricingTable::from_config(&pricing).unwrap();
+
+        // 10 万次 × 7 token × 0.1 美元/百万 token = 0.07 美元
+        let total: i128 = (0..100_000)
+            .map(|_| i128::from(table.cost_pico("m", 7, 0, 0).unwrap()))
+            .sum();
+        assert_eq!(total, 70_000_000_000);
+        assert_eq!(pico_to_usd(total), 0.07);
+    }
+}
@@ -0,0 +1,85 @@
+// 最后探测
+// 所有账号都被锁定时，部分锁定可能是上游 5xx 突发导致的误判 (锁定按最坏情况设置)。
+// 在返回"无可用账号"之前，用 1 token 的请求测试锁定最快到期的账号 (仅限 5xx / 未知原因的锁定，配额类锁定不探测)，
+// 成功则解除锁定并交给当前请求使用。
+// 全局限频，避免大量失败请求同时触发探测。
+
+use serde_json::json;
+use std::time::Duration;
+
+use crate::models::QuotaGroup;
+use crate::proxy::mappers::gemini::wrapper::wrap_request;
+use crate::proxy::monitor::ProbeOutcome;
+use crate::proxy::server::AppState;
+
+/// 单次探测请求的超时
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// 获取账号失败后的最后探测：成功时返回可用于当前请求的 (access_token, project_id, email)
+/// 只探测允许服务 `group` 的账号；未开启、没有锁定中的账号、被限频或探测失败时返回 None，调用方照常返回原错误
+pub async fn last_resort_token(state: &AppState, group: QuotaGroup) -> Option<(String, String, String)> {
+    let config = state.experimental.read().await.last_resort_probe.clone();
+    if !config.enabled {
+        return None;
+    }
+
+    let token_manager = &state.token_manager;
+    let (account_id, email) = token_manager.probe_candidate(group)?;
+    if !token_manager.try_acquire_probe_slot(Duration::from_secs(config.min_interval_seconds)) {
+        tracing::debug!("[Probe] Skipped: last-resort probe is rate limited");
+        state.monitor.record_probe(ProbeOutcome::Throttled);
+        return None;
+    }
+
+    let account_tag = token_manager.account_identifier(&email);
+    tracing::warn!("[Probe] Pool exhausted, probing account {} with a minimal request", account_tag);
+
+    let (access_token, project_id, email) = match token_manager.get_token_by_email(&email).await {
+        Ok(token) => token,
+        Err(e) => {
+            tracing::warn!("[Probe] Failed to obtain token for {}: {}", account_tag, e);
+            state.monitor.record_probe(ProbeOutcome::Failed);
+            return None;
+        }
+    };
+
+    let body = wrap_request(
+        &json!({
+            "model": config.model,
+            "contents": [{ "role": "user", "parts": [{ "text": "ping" }] }],
+            "generationConfig": { "maxOutputTokens": 1 }
+        }),
+        &project_id,
+        &config.model,
+    );
+
+    let result = tokio::time::timeout(
+        PROBE_TIMEOUT,
+        state.upstream.call_v1_internal("generateContent", &access_token, body, None),
+    )
+    .await;
+
+    match result {
+        Ok(Ok(response)) if response.status().is_success() => {
+            tracing::info!("[Probe] Account {} is healthy, lockout cleared", account_tag);
+            token_manager.release_probed_account(&account_id, &email);
+            state.monitor.record_probe(ProbeOutcome::Recovered);
+            Some((access_token, project_id, email))
+        }
+        Ok(Ok(response)) => {
+            tracing::warn!("[Probe] Account {} still failing: upstream returned {}", account_tag, response.status());
+            state.monitor.record_probe(ProbeOutcome::Failed);
+            None
+        }
+        Ok(Err(e)) => {
+            tracing::warn!("[Probe] Account {} probe request failed: {}", account_tag, e);
+            state.monitor.record_probe(ProbeOutcome::Failed);
+            None
+        }
+        Err(_) => {
+            tracing::warn!("[Probe] Account {} probe timed out", account_tag);
+            state.monitor.record_probe(ProbeOutcome::Failed);
+            None
+        }
+    }
+}
@@ -1,2 +1,2 @@
 pub mod zai_anthropic;
-
+pub mod zai_key_pool;
@@ -140,9 +140,19 @@ pub async fn forward_anthropic_json(
         return (StatusCode::BAD_REQUEST, "z.ai is disabled").into_response();
     }
 
-    if zai.api_key.trim().is_empty() {
+    let keys = zai.effective_keys();
+    if keys.is_empty() {
         return (StatusCode::BAD_REQUEST, "z.ai api_key is not set").into_response();
     }
+    let Some(selected) = state.zai_keys.select(&keys) else {
+        let retry_after = state.zai_keys.min_remaining_seconds(&keys).max(1);
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            "All z.ai api keys are rate limited",
+        )
+            .into_response();
+    };
 
     if let Some(model) = body.get("model").and_then(|v| v.as_str()) {
         let mapped = map_model_for_zai(model, &zai);
@@ -162,7 +172,7 @@ pub async fn forward_anthropic_json(
     };
 
     let mut headers = copy_passthrough_headers(incoming_headers);
-    set_zai_auth(&mut headers, incoming_headers, &zai.api_key);
+    set_zai_auth(&mut headers, incoming_headers, &selected.key);
 
     // Ensure JSON content type.
     headers
@@ -178,7 +188,15 @@ pub async fn forward_anthropic_json(
     let body_bytes = serde_json::to_vec(&body).unwrap_or_default();
     let body_len = body_bytes.len();
     
-    tracing::debug!("Forwarding request to z.ai (len: {} bytes): {}", body_len, url);
+    tracing::debug!(
+        "Forwarding request to z.ai (len: {} bytes, key: {}): {}",
+        body_len,
+        selected
+            .label
+            .clone()
+            .unwrap_or_else(|| super::zai_key_pool::mask_key(&selected.key)),
+        url
+    );
 
     let req = client.request(method, &url)
         .headers(headers)
@@ -187,6 +205,7 @@ pub async fn forward_anthropic_json(
     let resp = match req.send().await {
         Ok(r) => r,
         Err(e) => {
+            state.zai_keys.record_result(&selected.key, StatusCode::BAD_GATEWAY.as_u16(), None);
             return (
                 StatusCode::BAD_GATEWAY,
                 format!("Upstream request failed: {}", e),
@@ -196,6 +215,12 @@ pub async fn forward_anthropic_json(
     };
 
     let status = StatusCode::from_u16(resp.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+    let retry_after = resp
+        .headers()
+        .get(header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    state.zai_keys.record_result(&selected.key, status.as_u16(), retry_after);
 
     let mut out = Response::builder().status(status);
     if let Some(ct) = resp.headers().get(header::CONTENT_TYPE) {
@@ -0,0 +1,233 @@
+// z.ai API Key 号池
+// 按请求轮换 Key，并为每个 Key 独立记录 429 / 5xx 锁定 (与 Google 账号的 RateLimitTracker 相互独立)。
+// Key 列表每次选择时从当前配置读取，热更新增删 Key 不影响进行中的请求。
+
+use crate::proxy::config::ZaiApiKey;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+/// 429 且未给出 Retry-After 时的默认锁定时长
+const RATE_LIMIT_LOCKOUT: Duration = Duration::from_secs(60);
+
+/// 5xx 的锁定时长
+const SERVER_ERROR_LOCKOUT: Duration = Duration::from_secs(30);
+
+/// 单个 Key 的运行状态
+#[derive(Debug, Clone, Default)]
+struct KeyState {
+    requests: u64,
+    failures: u64,
+    locked_until: Option<SystemTime>,
+    last_status: Option<u16>,
+}
+
+/// 单个 Key 的统计 (Key 仅保留末 4 位)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ZaiKeyStats {
+    pub key: String,
+    pub label: Option<String>,
+    pub requests: u64,
+    pub failures: u64,
+    pub locked: bool,
+    pub remaining_seconds: u64,
+    pub last_status: Option<u16>,
+}
+
+/// 选中的 Key
+#[derive(Debug, Clone)]
+pub struct SelectedKey {
+    pub key: String,
+    pub label: Option<String>,
+}
+
+/// 显示用的 Key：仅保留末 4 位
+pub fn mask_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 4 {
+        return "****".to_string();
+    }
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("****{}", tail)
+}
+
+pub struct ZaiKeyPool {
+    states: DashMap<String, KeyState>,
+    cursor: AtomicUsize,
+}
+
+impl ZaiKeyPool {
+    pub fn new() -> Self {
+        Self {
+            states: DashMap::new(),
+            cursor: AtomicUsize::new(0),
+        }
+    }
+
+    fn is_locked(&self, key: &str, now: SystemTime) -> bool {
+        self.states
+            .get(key)
+            .and_then(|s| s.locked_until)
+            .is_some_and(|until| until > now)
+    }
+
+    /// 同步配置中的 Key 列表：移除已删除 Key 的状态，新 Key 从零开始计数
+    pub fn sync(&self, keys: &[ZaiApiKey]) {
+        self.states.retain(|k, _| keys.iter().any(|e| &e.key == k));
+        for entry in keys {
+            self.states.entry(entry.key.clone()).or_default();
+        }
+    }
+
+    /// 当前未被锁定的 Key 数
+    pub fn healthy_count(&self, keys: &[ZaiApiKey]) -> usize {
+        let now = SystemTime::now();
+        keys.iter().filter(|k| !self.is_locked(&k.key, now)).count()
+    }
+
+    /// 轮询选择一个未锁定的 Key 并计入请求数；全部锁定时返回 None
+    pub fn select(&self, keys: &[ZaiApiKey]) -> Option<SelectedKey> {
+        if keys.is_empty() {
+            return None;
+        }
+        let now = SystemTime::now();
+        let start = self.cursor.fetch_add(1, Ordering::Relaxed);
+        let entry = (0..keys.len())
+            .map(|offset| &keys[(start + offset) % keys.len()])
+            .find(|k| !self.is_locked(&k.key, now))?;
+
+        self.states.entry(entry.key.clone()).or_default().requests += 1;
+        Some(SelectedKey {
+            key: entry.key.clone(),
+            label: entry.label.clone(),
+        })
+    }
+
+    /// 所有 Key 中最早解除锁定的剩余秒数
+    pub fn min_remaining_seconds(&self, keys: &[ZaiApiKey]) -> u64 {
+        let now = SystemTime::now();
+        keys.iter()
+            .filter_map(|k| self.states.get(&k.key).and_then(|s| s.locked_until))
+            .filter_map(|until| until.duration_since(now).ok())
+            .map(|d| d.as_secs().max(1))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// 记录请求结果：429 / 5xx 锁定该 Key，成功时解除锁定
+    ///
+    /// 进行中的请求完成时 Key 可能已从配置中移除，此时忽略结果
+    pub fn record_result(&self, key: &str, status: u16, retry_after_secs: Option<u64>) {
+        let Some(mut state) = self.states.get_mut(key) else {
+            return;
+        };
+        state.last_status = Some(status);
+        let lockout = match status {
+            429 => Some(retry_after_secs.map(Duration::from_secs).unwrap_or(RATE_LIMIT_LOCKOUT)),
+            s if s >= 500 => Some(SERVER_ERROR_LOCKOUT),
+            _ => None,
+        };
+        match lockout {
+            Some(duration) => {
+                state.failures += 1;
+                state.locked_until = Some(SystemTime::now() + duration);
+                tracing::warn!(
+                    "[z.ai] Key {} locked for {}s after HTTP {}",
+                    mask_key(key),
+                    duration.as_secs(),
+                    status
+                );
+            }
+            None if (200..300).contains(&status) => state.locked_until = None,
+            None => {}
+        }
+    }
+
+    /// 按配置顺序输出各 Key 的统计
+    pub fn stats(&self, keys: &[ZaiApiKey]) -> Vec<ZaiKeyStats> {
+        let now = SystemTime::now();
+        keys.iter()
+            .map(|entry| {
+                let state = self.states.get(&entry.key).map(|s| s.clone()).unwrap_or_default();
+                let remaining = state
+                    .locked_until
+                    .and_then(|until| until.duration_since(now).ok())
+                    .map(|d| d.as_secs().max(1))
+                    .unwrap_or(0);
+                ZaiKeyStats {
+                    key: mask_key(&entry.key),
+                    label: entry.label.clone(),
+                    requests: state.requests,
+                    failures: state.failures,
+                    locked: remaining > 0,
+                    remaining_seconds: remaining,
+                    last_status: state.last_status,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for ZaiKeyPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(values: &[&str]) -> Vec<ZaiApiKey> {
+        values
+            .iter()
+            .map(|k| ZaiApiKey { key: k.to_string(), label: None, enabled: true })
+            .collect()
+    }
+
+    #[test]
+    fn test_rotation_skips_locked_keys() {
+        let pool = ZaiKeyPool::new();
+        let keys = keys(&["key-aaaa", "key-bbbb", "key-cccc"]);
+        pool.sync(&keys);
+
+        let picked: Vec<String> = (0..3).map(|_| pool.select(&keys).unwrap().key).collect();
+        assert_eq!(picked, vec!["key-aaaa", "key-bbbb", "key-cccc"]);
+
+        pool.record_result("key-bbbb", 429, None);
+        assert_eq!(pool.healthy_count(&keys), 2);
+        for _ in 0..4 {
+            assert_ne!(pool.select(&keys).unwrap().key, "key-bbbb");
+        }
+
+        pool.record_result("key-aaaa", 503, None);
+        pool.record_result("key-cccc", 429, Some(5));
+        assert!(pool.select(&keys).is_none());
+        assert!(pool.min_remaining_seconds(&keys) <= 5);
+
+        let stats = pool.stats(&keys);
+        assert_eq!(stats[1].key, "****bbbb");
+        assert!(stats.iter().all(|s| s.locked));
+        assert_eq!(stats[0].requests, 3);
+    }
+
+    #[test]
+    fn test_sync_handles_removed_keys_in_flight() {
+        let pool = ZaiKeyPool::new();
+        let before = keys(&["old-key-1111", "kept-key-2222"]);
+        pool.sync(&before);
+        let in_flight = pool.select(&before).unwrap();
+        assert_eq!(in_flight.key, "old-key-1111");
+
+        // 热更新移除 old-key 后，进行中的请求完成时不应复活其状态
+        let after = keys(&["kept-key-2222", "new-key-3333"]);
+        pool.sync(&after);
+        pool.record_result(&in_flight.key, 429, None);
+
+        assert_eq!(pool.healthy_count(&after), 2);
+        let stats = pool.stats(&after);
+        assert_eq!(stats.iter().map(|s| s.key.as_str()).collect::<Vec<_>>(), vec!["****2222", "****3333"]);
+        assert!(!pool.states.contains_key("old-key-1111"));
+    }
+}
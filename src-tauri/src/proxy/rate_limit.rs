@@ -1,9 +1,22 @@
 use dashmap::DashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
 use std::time::{SystemTime, Duration};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::clock::{Clock, SystemClock};
+use crate::proxy::config::AccountIdentifierMode;
+
+/// 不健康账号的停用阶梯 (秒)：第1次 1分钟，第2次 5分钟，第3次及以后 15分钟
+const UNHEALTHY_BENCH_LADDER: [u64; 3] = [60, 300, 900];
+
+/// 默认的连续失败阈值
+const DEFAULT_UNHEALTHY_THRESHOLD: u32 = 3;
 
 /// 限流原因类型
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RateLimitReason {
     /// 配额耗尽 (QUOTA_EXHAUSTED)
     QuotaExhausted,
@@ -13,10 +26,36 @@ pub enum RateLimitReason {
     ModelCapacityExhausted,
     /// 服务器错误 (5xx)
     ServerError,
+    /// 连续失败过多，账号被临时停用 (与配额锁定相互独立)
+    Unhealthy,
     /// 未知原因
     Unknown,
 }
 
+/// 限流状态条目 (用于 UI 展示)
+#[derive(Debug, Clone, Serialize)]
+pub struct RateLimitStatusEntry {
+    /// 跟踪器内部键 (账号 ID 或邮箱，取决于记录时使用的键)
+    #[serde(skip)]
+    pub key: String,
+    /// 账号 ID (由 TokenManager 解析；跟踪器快照中与 key 相同)
+    pub account_id: String,
+    /// 账号邮箱 (由 TokenManager 解析，无法解析时为空)
+    pub email: Option<String>,
+    pub reason: RateLimitReason,
+    pub remaining_seconds: u64,
+    pub model: Option<String>,
+    /// 当前连续失败次数
+    pub consecutive_failures: u32,
+}
+
+/// 判断某个上游状态码是否应计入账号健康度的连续失败
+///
+/// 400/404 等通常由请求内容导致，与账号本身无关，不计入
+pub fn is_health_failure_status(status: u16) -> bool {
+    status == 401 || status == 403 || status == 429 || status >= 500
+}
+
 /// 限流信息
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
@@ -36,30 +75,120 @@ pub struct RateLimitInfo {
     pub model: Option<String>,
 }
 
+/// 单条限流 / 停用记录的可序列化形式 (时间为 Unix 秒)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitRecordSnapshot {
+    pub account_id: String,
+    pub reason: RateLimitReason,
+    pub reset_at: u64,
+    pub detected_at: u64,
+    pub retry_after_sec: u64,
+    pub model: Option<String>,
+}
+
+/// 限流跟踪器的完整状态快照 (用于复现调度问题)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitTrackerSnapshot {
+    pub limits: Vec<RateLimitRecordSnapshot>,
+    pub benched: Vec<RateLimitRecordSnapshot>,
+    pub failure_counts: std::collections::BTreeMap<String, u32>,
+    pub failure_streaks: std::collections::BTreeMap<String, u32>,
+    pub bench_levels: std::collections::BTreeMap<String, u32>,
+}
+
+fn to_unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn from_unix_secs(secs: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+impl RateLimitRecordSnapshot {
+    fn capture(account_id: &str, info: &RateLimitInfo) -> Self {
+        Self {
+            account_id: account_id.to_string(),
+            reason: info.reason,
+            reset_at: to_unix_secs(info.reset_time),
+            detected_at: to_unix_secs(info.detected_at),
+            retry_after_sec: info.retry_after_sec,
+            model: info.model.clone(),
+        }
+    }
+
+    fn to_info(&self) -> RateLimitInfo {
+        RateLimitInfo {
+            reset_time: from_unix_secs(self.reset_at),
+            retry_after_sec: self.retry_after_sec,
+            detected_at: from_unix_secs(self.detected_at),
+            reason: self.reason,
+            model: self.model.clone(),
+        }
+    }
+}
+
 /// 限流跟踪器
 pub struct RateLimitTracker {
     limits: DashMap<String, RateLimitInfo>,
     /// 连续失败计数（用于智能指数退避）
     failure_counts: DashMap<String, u32>,
+    /// 跨错误类型的连续失败次数 (用于健康度判断)
+    failure_streaks: DashMap<String, u32>,
+    /// 因连续失败被临时停用的账号 (独立于 limits，不会被配额锁定覆盖)
+    benched: DashMap<String, RateLimitInfo>,
+    /// 账号已被停用的次数 (决定停用阶梯)
+    bench_levels: DashMap<String, u32>,
+    /// 连续失败阈值，0 表示禁用
+    unhealthy_threshold: AtomicU32,
+    /// 时间来源 (测试中替换为假时钟)
+    clock: Arc<dyn Clock>,
+    /// 日志中的账号标识方式 (与 TokenManager 保持一致)
+    identifier_mode: std::sync::RwLock<AccountIdentifierMode>,
 }
 
 impl RateLimitTracker {
     pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    /// 使用指定时钟创建
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
         Self {
             limits: DashMap::new(),
             failure_counts: DashMap::new(),
+            failure_streaks: DashMap::new(),
+            benched: DashMap::new(),
+            bench_levels: DashMap::new(),
+            unhealthy_threshold: AtomicU32::new(DEFAULT_UNHEALTHY_THRESHOLD),
+            clock,
+            identifier_mode: std::sync::RwLock::new(AccountIdentifierMode::default()),
+        }
+    }
+
+    /// 设置日志中的账号标识方式
+    pub fn set_identifier_mode(&self, mode: AccountIdentifierMode) {
+        if let Ok(mut current) = self.identifier_mode.write() {
+            *current = mode;
         }
     }
+
+    /// 日志中展示的账号标识：记录以邮箱为键时按配置脱敏 (标签模式下跟踪器不知道标签，退化为哈希)
+    fn display(&self, key: &str) -> String {
+        if !key.contains('@') {
+            return key.to_string();
+        }
+        let mode = self.identifier_mode.read().map(|m| *m).unwrap_or_default();
+        crate::proxy::common::account_identity::account_identifier(mode, key, None)
+    }
+
+    /// 跟踪器使用的时钟 (账号选择的缓冲延迟与之共用)
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
     
     /// 获取账号剩余的等待时间(秒)
     pub fn get_remaining_wait(&self, account_id: &str) -> u64 {
-        if let Some(info) = self.limits.get(account_id) {
-            let now = SystemTime::now();
-            if info.reset_time > now {
-                return info.reset_time.duration_since(now).unwrap_or(Duration::from_secs(0)).as_secs();
-            }
-        }
-        0
+        self.get_reset_seconds(account_id).unwrap_or(0)
     }
     
     /// 标记账号请求成功，重置连续失败计数
@@ -68,11 +197,81 @@ impl RateLimitTracker {
     /// 这样下次失败时会从最短的锁定时间（60秒）开始。
     pub fn mark_success(&self, account_id: &str) {
         if self.failure_counts.remove(account_id).is_some() {
-            tracing::debug!("账号 {} 请求成功，已重置失败计数", account_id);
+            tracing::debug!("账号 {} 请求成功，已重置失败计数", self.display(account_id));
         }
+        self.failure_streaks.remove(account_id);
+        self.bench_levels.remove(account_id);
+        self.benched.remove(account_id);
         // 同时清除限流记录（如果有）
         self.limits.remove(account_id);
     }
+
+    /// 设置连续失败阈值 (0 表示禁用不健康停用)
+    pub fn set_unhealthy_threshold(&self, threshold: u32) {
+        self.unhealthy_threshold.store(threshold, Ordering::Relaxed);
+    }
+
+    /// 获取账号当前的连续失败次数
+    pub fn get_failure_streak(&self, account_id: &str) -> u32 {
+        self.failure_streaks.get(account_id).map(|v| *v).unwrap_or(0)
+    }
+
+    /// 记录一次失败 (任意错误类型：网络错误、超时、429、5xx 等)
+    ///
+    /// 连续失败达到阈值时，账号按阶梯被临时停用 (1分钟 / 5分钟 / 15分钟)，
+    /// 返回本次产生的停用信息。停用期间的失败不再累计，避免在途请求放大停用时间。
+    pub fn record_failure(&self, account_id: &str) -> Option<RateLimitInfo> {
+        let threshold = self.unhealthy_threshold.load(Ordering::Relaxed);
+        if threshold == 0 || self.is_benched(account_id) {
+            return None;
+        }
+
+        let streak = {
+            let mut streak = self.failure_streaks.entry(account_id.to_string()).or_insert(0);
+            *streak += 1;
+            *streak
+        };
+        if streak < threshold {
+            tracing::debug!("账号 {} 连续失败 {}/{}", self.display(account_id), streak, threshold);
+            return None;
+        }
+
+        let level = {
+            let mut level = self.bench_levels.entry(account_id.to_string()).or_insert(0);
+            *level += 1;
+            *level
+        };
+        let idx = (level as usize - 1).min(UNHEALTHY_BENCH_LADDER.len() - 1);
+        let bench_sec = UNHEALTHY_BENCH_LADDER[idx];
+
+        let now = self.clock.now();
+        let info = RateLimitInfo {
+            reset_time: now + Duration::from_secs(bench_sec),
+            retry_after_sec: bench_sec,
+            detected_at: now,
+            reason: RateLimitReason::Unhealthy,
+            model: None,
+        };
+        self.benched.insert(account_id.to_string(), info.clone());
+        self.failure_streaks.remove(account_id);
+
+        tracing::warn!(
+            "账号 {} 连续失败 {} 次，标记为不健康，第{}次停用 {}秒",
+            self.display(account_id),
+            streak,
+            level,
+            bench_sec
+        );
+        Some(info)
+    }
+
+    /// 检查账号是否处于不健康停用期
+    pub fn is_benched(&self, account_id: &str) -> bool {
+        self.benched
+            .get(account_id)
+            .map(|info| info.reset_time > self.clock.now())
+            .unwrap_or(false)
+    }
     
     /// 精确锁定账号到指定时间点
     /// 
@@ -82,7 +281,7 @@ impl RateLimitTracker {
     /// # 参数
     /// - `model`: 可选的模型名称,用于模型级别限流。None 表示账号级别限流
     pub fn set_lockout_until(&self, account_id: &str, reset_time: SystemTime, reason: RateLimitReason, model: Option<String>) {
-        let now = SystemTime::now();
+        let now = self.clock.now();
         let retry_sec = reset_time
             .duration_since(now)
             .map(|d| d.as_secs())
@@ -101,14 +300,14 @@ impl RateLimitTracker {
         if let Some(m) = &model {
             tracing::info!(
                 "账号 {} 的模型 {} 已精确锁定到配额刷新时间,剩余 {} 秒",
-                account_id,
+                self.display(account_id),
                 m,
                 retry_sec
             );
         } else {
             tracing::info!(
                 "账号 {} 已精确锁定到配额刷新时间,剩余 {} 秒",
-                account_id,
+                self.display(account_id),
                 retry_sec
             );
         }
@@ -122,17 +321,17 @@ impl RateLimitTracker {
     /// - `model`: 可选的模型名称,用于模型级别限流
     pub fn set_lockout_until_iso(&self, account_id: &str, reset_time_str: &str, reason: RateLimitReason, model: Option<String>) -> bool {
         // 尝试解析 ISO 8601 格式
-        match chrono::DateTime::parse_from_rfc3339(reset_time_str) {
-            Ok(dt) => {
+        match crate::modules::quota::parse_reset_time(reset_time_str) {
+            Some(ts) => {
                 let reset_time = SystemTime::UNIX_EPOCH + 
-                    std::time::Duration::from_secs(dt.timestamp() as u64);
+                    std::time::Duration::from_secs(ts as u64);
                 self.set_lockout_until(account_id, reset_time, reason, model);
                 true
             },
-            Err(e) => {
+            None => {
                 tracing::warn!(
-                    "无法解析配额刷新时间 '{}': {},将使用默认退避策略",
-                    reset_time_str, e
+                    "无法解析配额刷新时间 '{}',将使用默认退避策略",
+                    reset_time_str
                 );
                 false
             }
@@ -235,7 +434,7 @@ impl RateLimitTracker {
                         tracing::warn!("检测到 5xx 错误 ({}), 执行 20s 软避让...", status);
                         20
                     },
-                    RateLimitReason::Unknown => {
+                    RateLimitReason::Unhealthy | RateLimitReason::Unknown => {
                         // 未知原因：使用中等默认值（60秒）
                         tracing::debug!("无法解析 429 限流原因, 使用默认值 60秒");
                         60
@@ -244,10 +443,11 @@ impl RateLimitTracker {
             }
         };
         
+        let now = self.clock.now();
         let info = RateLimitInfo {
-            reset_time: SystemTime::now() + Duration::from_secs(retry_sec),
+            reset_time: now + Duration::from_secs(retry_sec),
             retry_after_sec: retry_sec,
-            detected_at: SystemTime::now(),
+            detected_at: now,
             reason,
             model,
         };
@@ -257,7 +457,7 @@ impl RateLimitTracker {
         
         tracing::warn!(
             "账号 {} [{}] 限流类型: {:?}, 重置延时: {}秒",
-            account_id,
+            self.display(account_id),
             status,
             reason,
             retry_sec
@@ -441,31 +641,82 @@ impl RateLimitTracker {
         self.limits.get(account_id).map(|r| r.clone())
     }
     
-    /// 检查账号是否仍在限流中
+    /// 检查账号是否仍在限流中 (包括不健康停用)
     pub fn is_rate_limited(&self, account_id: &str) -> bool {
-        if let Some(info) = self.get(account_id) {
-            info.reset_time > SystemTime::now()
-        } else {
-            false
-        }
+        let limited = self
+            .get(account_id)
+            .map(|info| info.reset_time > self.clock.now())
+            .unwrap_or(false);
+        limited || self.is_benched(account_id)
     }
     
-    /// 获取距离限流重置还有多少秒
+    /// 账号当前的锁定是否都可能是误判 (仅由 5xx、连续失败停用或未知原因导致)
+    ///
+    /// 配额耗尽、速率限制、容量耗尽等锁定由上游明确给出，探测其他模型成功也不代表已恢复
+    pub fn is_probeable(&self, account_id: &str) -> bool {
+        let now = self.clock.now();
+        let mut active = self
+            .get(account_id)
+            .into_iter()
+            .chain(self.benched.get(account_id).map(|r| r.clone()))
+            .filter(|info| info.reset_time > now)
+            .peekable();
+        active.peek().is_some()
+            && active.all(|info| {
+                matches!(
+                    info.reason,
+                    RateLimitReason::ServerError | RateLimitReason::Unhealthy | RateLimitReason::Unknown
+                )
+            })
+    }
+
+    /// 获取距离限流重置还有多少秒 (限流与停用同时存在时取较晚者)
     pub fn get_reset_seconds(&self, account_id: &str) -> Option<u64> {
-        if let Some(info) = self.get(account_id) {
-            info.reset_time
-                .duration_since(SystemTime::now())
-                .ok()
-                .map(|d| d.as_secs())
-        } else {
-            None
-        }
+        self.get_reset_duration(account_id).map(|d| d.as_secs())
+    }
+
+    /// 距离限流/停用解除的精确剩余时长 (不取整，等待时使用)
+    pub fn get_reset_duration(&self, account_id: &str) -> Option<Duration> {
+        let now = self.clock.now();
+        let limit = self.get(account_id).map(|info| info.reset_time);
+        let bench = self.benched.get(account_id).map(|info| info.reset_time);
+        limit
+            .into_iter()
+            .chain(bench)
+            .max()
+            .and_then(|t| t.duration_since(now).ok())
+    }
+
+    /// 获取所有生效中的限流与停用记录
+    pub fn status_snapshot(&self) -> Vec<RateLimitStatusEntry> {
+        let now = self.clock.now();
+        let mut entries: Vec<RateLimitStatusEntry> = self
+            .limits
+            .iter()
+            .chain(self.benched.iter())
+            .filter(|r| r.reset_time > now)
+            .map(|r| RateLimitStatusEntry {
+                key: r.key().clone(),
+                account_id: r.key().clone(),
+                email: None,
+                reason: r.reason,
+                remaining_seconds: r
+                    .reset_time
+                    .duration_since(now)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+                model: r.model.clone(),
+                consecutive_failures: self.get_failure_streak(r.key()),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        entries
     }
     
     /// 清除过期的限流记录
     #[allow(dead_code)]
     pub fn cleanup_expired(&self) -> usize {
-        let now = SystemTime::now();
+        let now = self.clock.now();
         let mut count = 0;
         
         self.limits.retain(|_k, v| {
@@ -476,6 +727,14 @@ impl RateLimitTracker {
                 true
             }
         });
+        self.benched.retain(|_k, v| {
+            if v.reset_time <= now {
+                count += 1;
+                false
+            } else {
+                true
+            }
+        });
         
         if count > 0 {
             tracing::debug!("清除了 {} 个过期的限流记录", count);
@@ -499,6 +758,54 @@ impl RateLimitTracker {
         self.limits.clear();
         tracing::warn!("🔄 Optimistic reset: Cleared all {} rate limit record(s)", count);
     }
+
+    /// 导出全部限流、停用与失败计数状态
+    pub fn snapshot(&self) -> RateLimitTrackerSnapshot {
+        fn records(map: &DashMap<String, RateLimitInfo>) -> Vec<RateLimitRecordSnapshot> {
+            let mut out: Vec<_> = map
+                .iter()
+                .map(|r| RateLimitRecordSnapshot::capture(r.key(), r.value()))
+                .collect();
+            out.sort_by(|a, b| a.account_id.cmp(&b.account_id));
+            out
+        }
+        fn counters(map: &DashMap<String, u32>) -> std::collections::BTreeMap<String, u32> {
+            map.iter().map(|r| (r.key().clone(), *r.value())).collect()
+        }
+
+        RateLimitTrackerSnapshot {
+            limits: records(&self.limits),
+            benched: records(&self.benched),
+            failure_counts: counters(&self.failure_counts),
+            failure_streaks: counters(&self.failure_streaks),
+            bench_levels: counters(&self.bench_levels),
+        }
+    }
+
+    /// 用快照整体替换当前状态 (阈值配置不受影响)
+    pub fn restore(&self, snapshot: &RateLimitTrackerSnapshot) {
+        self.limits.clear();
+        self.benched.clear();
+        self.failure_counts.clear();
+        self.failure_streaks.clear();
+        self.bench_levels.clear();
+
+        for record in &snapshot.limits {
+            self.limits.insert(record.account_id.clone(), record.to_info());
+        }
+        for record in &snapshot.benched {
+            self.benched.insert(record.account_id.clone(), record.to_info());
+        }
+        for (id, v) in &snapshot.failure_counts {
+            self.failure_counts.insert(id.clone(), *v);
+        }
+        for (id, v) in &snapshot.failure_streaks {
+            self.failure_streaks.insert(id.clone(), *v);
+        }
+        for (id, v) in &snapshot.bench_levels {
+            self.bench_levels.insert(id.clone(), *v);
+        }
+    }
 }
 
 impl Default for RateLimitTracker {
@@ -510,6 +817,26 @@ impl Default for RateLimitTracker {
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn test_logged_account_follows_identifier_mode() {
+        let tracker = RateLimitTracker::new();
+        assert_eq!(tracker.display("user@example.com"), "user@example.com");
+
+        tracker.set_identifier_mode(AccountIdentifierMode::Hash);
+        let shown = tracker.display("user@example.com");
+        assert!(!shown.contains("user@example.com"));
+        assert_eq!(
+            shown,
+            crate::proxy::common::account_identity::account_identifier(
+                AccountIdentifierMode::Hash,
+                "user@example.com",
+                None
+            )
+        );
+        // 非邮箱键 (账号 ID) 原样输出
+        assert_eq!(tracker.display("acc-1"), "acc-1");
+    }
     
     #[test]
     fn test_parse_retry_time_minutes_seconds() {
@@ -572,4 +899,223 @@ mod tests {
         // 应该被识别为 RateLimitExceeded，而不是 QuotaExhausted
         assert_eq!(reason, RateLimitReason::RateLimitExceeded);
     }
+
+    /// 让停用记录立即过期 (模拟时间流逝)
+    fn expire_bench(tracker: &RateLimitTracker, account_id: &str) {
+        if let Some(mut info) = tracker.benched.get_mut(account_id) {
+            info.reset_time = SystemTime::now() - Duration::from_secs(1);
+        }
+    }
+
+    #[test]
+    fn test_unhealthy_bench_escalation_ladder() {
+        let tracker = RateLimitTracker::new();
+        tracker.set_unhealthy_threshold(2);
+
+        let mut benches = Vec::new();
+        for _ in 0..4 {
+            assert!(tracker.record_failure("acc1").is_none());
+            assert!(!tracker.is_rate_limited("acc1"));
+            let info = tracker.record_failure("acc1").expect("threshold reached");
+            assert_eq!(info.reason, RateLimitReason::Unhealthy);
+            assert!(tracker.is_rate_limited("acc1"));
+            benches.push(info.retry_after_sec);
+            expire_bench(&tracker, "acc1");
+        }
+        // 1分钟 → 5分钟 → 15分钟 → 封顶 15分钟
+        assert_eq!(benches, vec![60, 300, 900, 900]);
+
+        // 成功请求清空连续失败与停用阶梯
+        tracker.mark_success("acc1");
+        assert_eq!(tracker.get_failure_streak("acc1"), 0);
+        tracker.record_failure("acc1");
+        let info = tracker.record_failure("acc1").unwrap();
+        assert_eq!(info.retry_after_sec, 60);
+    }
+
+    #[test]
+    fn test_success_resets_streak_before_threshold() {
+        let tracker = RateLimitTracker::new();
+        tracker.set_unhealthy_threshold(3);
+        tracker.record_failure("acc1");
+        tracker.record_failure("acc1");
+        tracker.mark_success("acc1");
+        assert!(tracker.record_failure("acc1").is_none());
+        assert!(tracker.record_failure("acc1").is_none());
+        assert!(!tracker.is_rate_limited("acc1"));
+    }
+
+    #[test]
+    fn test_zero_threshold_disables_bench() {
+        let tracker = RateLimitTracker::new();
+        tracker.set_unhealthy_threshold(0);
+        for _ in 0..10 {
+            assert!(tracker.record_failure("acc1").is_none());
+        }
+        assert!(!tracker.is_rate_limited("acc1"));
+    }
+
+    #[test]
+    fn test_benched_account_hits_real_429() {
+        let tracker = RateLimitTracker::new();
+        tracker.set_unhealthy_threshold(1);
+        tracker.record_failure("acc1").unwrap();
+
+        // 停用期间的在途请求又收到 429：配额锁定单独记录，停用既不被覆盖也不升级
+        tracker.parse_from_error("acc1", 429, Some("30"), "", None);
+        assert!(tracker.record_failure("acc1").is_none());
+
+        let limit = tracker.get("acc1").unwrap();
+        assert_eq!(limit.reason, RateLimitReason::Unknown);
+        assert!(tracker.is_benched("acc1"));
+
+        // 剩余等待取两者中较晚者 (停用 60s > 限流 30s)
+        let wait = tracker.get_remaining_wait("acc1");
+        assert!(wait > 55 && wait <= 60);
+
+        let status = tracker.status_snapshot();
+        let reasons: Vec<RateLimitReason> = status.iter().map(|e| e.reason).collect();
+        assert_eq!(status.len(), 2);
+        assert!(reasons.contains(&RateLimitReason::Unhealthy));
+        assert!(reasons.contains(&RateLimitReason::Unknown));
+        assert_eq!(
+            serde_json::to_value(RateLimitReason::Unhealthy).unwrap(),
+            serde_json::json!("unhealthy")
+        );
+
+        // 停用结束后 429 锁定仍然生效
+        expire_bench(&tracker, "acc1");
+        assert!(!tracker.is_benched("acc1"));
+        assert!(tracker.is_rate_limited("acc1"));
+
+        tracker.mark_success("acc1");
+        assert!(!tracker.is_rate_limited("acc1"));
+        assert!(tracker.status_snapshot().is_empty());
+    }
+
+    // ===== 假时钟驱动的时序测试 =====
+
+    use crate::proxy::clock::FakeClock;
+
+    /// 2026-01-08T16:00:00Z
+    const FAKE_NOW: u64 = 1_767_888_000;
+
+    const QUOTA_EXHAUSTED_BODY: &str = r#"{"error":{"details":[{"reason":"QUOTA_EXHAUSTED"}]}}"#;
+
+    fn fake_tracker() -> (Arc<FakeClock>, RateLimitTracker) {
+        let clock = Arc::new(FakeClock::at_unix(FAKE_NOW));
+        let tracker = RateLimitTracker::with_clock(clock.clone());
+        (clock, tracker)
+    }
+
+    #[test]
+    fn test_retry_after_lockout_expires_on_time() {
+        let (clock, tracker) = fake_tracker();
+        tracker.parse_from_error("acc1", 429, Some("30"), "", None);
+        assert_eq!(tracker.get_remaining_wait("acc1"), 30);
+
+        clock.advance(Duration::from_secs(29));
+        assert!(tracker.is_rate_limited("acc1"));
+        assert_eq!(tracker.get_remaining_wait("acc1"), 1);
+
+        // 到达重置时间即解除
+        clock.advance(Duration::from_secs(1));
+        assert!(!tracker.is_rate_limited("acc1"));
+        assert_eq!(tracker.get_remaining_wait("acc1"), 0);
+    }
+
+    #[test]
+    fn test_iso_reset_time_in_future_and_past() {
+        let (clock, tracker) = fake_tracker();
+
+        assert!(tracker.set_lockout_until_iso("acc1", "2026-01-08T17:00:00Z", RateLimitReason::QuotaExhausted, None));
+        assert_eq!(tracker.get("acc1").unwrap().retry_after_sec, 3600);
+        assert_eq!(tracker.get_remaining_wait("acc1"), 3600);
+        clock.advance(Duration::from_secs(3599));
+        assert!(tracker.is_rate_limited("acc1"));
+        clock.advance(Duration::from_secs(1));
+        assert!(!tracker.is_rate_limited("acc1"));
+
+        // 重置时间已过：记录保留 (retry_after 回退为 60 秒)，但不锁定账号
+        assert!(tracker.set_lockout_until_iso("acc2", "2026-01-08T15:00:00Z", RateLimitReason::QuotaExhausted, None));
+        assert_eq!(tracker.get("acc2").unwrap().retry_after_sec, 60);
+        assert!(!tracker.is_rate_limited("acc2"));
+
+        // 无法解析时不写入记录，由调用方回退到默认退避
+        assert!(!tracker.set_lockout_until_iso("acc3", "tomorrow", RateLimitReason::QuotaExhausted, None));
+        assert!(tracker.get("acc3").is_none());
+    }
+
+    #[test]
+    fn test_model_scoped_and_account_wide_limits() {
+        let (_clock, tracker) = fake_tracker();
+
+        // 模型级限流按账号存储，model 标注作用范围
+        tracker.parse_from_error("acc1", 429, Some("120"), "", Some("gemini-3-pro-high".to_string()));
+        let info = tracker.get("acc1").unwrap();
+        assert_eq!(info.model.as_deref(), Some("gemini-3-pro-high"));
+        assert_eq!(tracker.status_snapshot()[0].model.as_deref(), Some("gemini-3-pro-high"));
+        assert!(!tracker.is_rate_limited("acc2"));
+
+        // 后到的账号级锁定覆盖模型级记录
+        tracker.parse_from_error("acc1", 429, Some("30"), "", None);
+        let info = tracker.get("acc1").unwrap();
+        assert!(info.model.is_none());
+        assert_eq!(tracker.get_remaining_wait("acc1"), 30);
+    }
+
+    #[test]
+    fn test_quota_escalation_and_mark_success_reset() {
+        let (clock, tracker) = fake_tracker();
+
+        let mut lockouts = Vec::new();
+        for _ in 0..5 {
+            let info = tracker.parse_from_error("acc1", 429, None, QUOTA_EXHAUSTED_BODY, None).unwrap();
+            assert_eq!(info.reason, RateLimitReason::QuotaExhausted);
+            lockouts.push(info.retry_after_sec);
+            clock.advance(Duration::from_secs(info.retry_after_sec));
+        }
+        // 60秒 → 5分钟 → 30分钟 → 2小时 → 封顶 2小时
+        assert_eq!(lockouts, vec![60, 300, 1800, 7200, 7200]);
+
+        tracker.mark_success("acc1");
+        let info = tracker.parse_from_error("acc1", 429, None, QUOTA_EXHAUSTED_BODY, None).unwrap();
+        assert_eq!(info.retry_after_sec, 60);
+    }
+
+    #[test]
+    fn test_cleanup_expired_with_clock() {
+        let (clock, tracker) = fake_tracker();
+        tracker.set_unhealthy_threshold(1);
+        tracker.parse_from_error("short", 429, Some("10"), "", None);
+        tracker.parse_from_error("long", 429, Some("100"), "", None);
+        tracker.record_failure("benched").unwrap(); // 停用 60 秒
+
+        assert_eq!(tracker.cleanup_expired(), 0);
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(tracker.cleanup_expired(), 2);
+        assert!(tracker.get("short").is_none());
+        assert!(!tracker.is_benched("benched"));
+        assert!(tracker.is_rate_limited("long"));
+
+        clock.advance(Duration::from_secs(40));
+        assert_eq!(tracker.cleanup_expired(), 1);
+        assert!(tracker.status_snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_only_transient_lockouts_are_probeable() {
+        let (_clock, tracker) = fake_tracker();
+        let until = tracker.clock().now() + Duration::from_secs(60);
+        tracker.set_lockout_until("flaky", until, RateLimitReason::ServerError, None);
+        tracker.set_lockout_until("quota", until, RateLimitReason::QuotaExhausted, None);
+        tracker.set_lockout_until("mixed", until, RateLimitReason::RateLimitExceeded, None);
+        tracker.set_unhealthy_threshold(1);
+        tracker.record_failure("mixed");
+
+        assert!(tracker.is_probeable("flaky"));
+        assert!(!tracker.is_probeable("quota"));
+        assert!(!tracker.is_probeable("mixed"));
+        assert!(!tracker.is_probeable("free"));
+    }
 }
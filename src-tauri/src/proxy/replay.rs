@@ -0,0 +1,306 @@
+// 请求重放 (Request Replay)
+// 基于监控日志中保存的原始客户端请求，重新走一遍当前的转换/处理流程
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// 重放请求携带的标记头，监控中间件据此将日志标记为重放 (不计入使用统计)
+pub const REPLAY_HEADER: &str = "X-Antigravity-Replay-Of";
+
+/// 吞吐自测请求携带的标记头 (值为本次自测的运行 ID)，同样不计入使用统计
+pub const SELF_TEST_HEADER: &str = "X-Antigravity-Self-Test";
+
+/// 内部请求 (重放 / 自测) 携带的令牌头；令牌在进程启动时随机生成，客户端无法伪造
+pub const INTERNAL_TOKEN_HEADER: &str = "X-Antigravity-Internal";
+
+static INTERNAL_TOKEN: Lazy<String> = Lazy::new(|| uuid::Uuid::new_v4().simple().to_string());
+
+/// 本进程内部请求使用的令牌
+pub fn internal_token() -> &'static str {
+    INTERNAL_TOKEN.as_str()
+}
+
+/// 内部发起的请求的重放 / 自测标记；客户端自行携带这些标记头时忽略
+pub fn internal_replay_of(headers: &axum::http::HeaderMap) -> Option<String> {
+    let internal = headers
+        .get(INTERNAL_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|token| token == internal_token());
+    if !internal {
+        return None;
+    }
+    [REPLAY_HEADER, SELF_TEST_HEADER]
+        .iter()
+        .find_map(|name| headers.get(*name).and_then(|v| v.to_str().ok()))
+        .map(|s| s.to_string())
+}
+
+/// 超过该大小的原始请求需要显式确认才能重放 (256KB)
+pub const LARGE_REPLAY_THRESHOLD: usize = 256 * 1024;
+
+/// dry-run 模式下用于填充 v1internal 包装的占位 project_id
+const DRY_RUN_PROJECT_ID: &str = "dry-run-project";
+
+/// 最多返回的差异条目数，避免超大 body 生成海量 diff
+const MAX_DIFF_ENTRIES: usize = 200;
+
+/// 重放请求所属协议
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayProtocol {
+    Claude,
+    OpenAI,
+    Gemini,
+}
+
+impl ReplayProtocol {
+    /// 根据原始请求 URL 推断协议，无法转换的端点返回 None
+    pub fn from_url(url: &str) -> Option<Self> {
+        let path = url.split('?').next().unwrap_or(url);
+        if path == "/v1/messages" {
+            Some(Self::Claude)
+        } else if path == "/v1/chat/completions" {
+            Some(Self::OpenAI)
+        } else if path.starts_with("/v1beta/models/") && path.contains(':') {
+            Some(Self::Gemini)
+        } else {
+            None
+        }
+    }
+}
+
+/// 一次请求的结果摘要 (原始 / 重放)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayOutcome {
+    pub status: u16,
+    pub mapped_model: Option<String>,
+    pub account_email: Option<String>,
+    pub error: Option<String>,
+    pub duration: u64, // ms
+}
+
+/// 重放结果对比
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayTraceResult {
+    pub trace_id: String,
+    pub dry_run: bool,
+    pub url: String,
+    pub model: Option<String>,
+    pub original: ReplayOutcome,
+    /// dry-run 模式下为 None
+    pub replayed: Option<ReplayOutcome>,
+    /// 当前映射规则下的目标模型
+    pub mapped_model_now: Option<String>,
+    /// dry-run 模式下：按原映射模型转换 vs 按当前映射转换的 Gemini body 差异
+    pub gemini_body_diff: Vec<String>,
+    /// dry-run 模式下：当前转换得到的 Gemini body
+    pub gemini_body: Option<Value>,
+}
+
+/// 判断日志中的请求体是否可以用于重放
+/// 占位内容 (二进制 / 脱敏 / 截断) 一律拒绝，避免发出与原请求不一致的内容
+pub fn check_replayable(request_body: Option<&str>) -> Result<Value, String> {
+    let body = request_body.ok_or("该记录未保存原始请求体，无法重放")?;
+    if body.contains(crate::proxy::monitor::REDACTED_PLACEHOLDER) {
+        return Err("该记录的消息内容已脱敏，禁止重放".to_string());
+    }
+    if body.starts_with("[Binary") {
+        return Err("该记录为二进制请求体，不支持重放".to_string());
+    }
+    serde_json::from_str::<Value>(body)
+        .map_err(|e| format!("原始请求体不是有效的 JSON，无法重放: {}", e))
+}
+
+/// 使用当前的映射和转换逻辑生成 Gemini 请求体 (不发出任何网络请求)
+///
+/// `mapped_model_override` 用于以指定目标模型转换 (例如原始记录中的 mapped_model)
+pub fn transform_for_replay(
+    protocol: ReplayProtocol,
+    url: &str,
+    body: &Value,
+    custom_mapping: &HashMap<String, crate::proxy::config::ModelMappingTarget>,
+    mapped_model_override: Option<&str>,
+) -> Result<(String, Value), String> {
+    match protocol {
+        ReplayProtocol::Claude => {
+            let mut request: crate::proxy::mappers::claude::ClaudeRequest =
+                serde_json::from_value(body.clone())
+                    .map_err(|e| format!("Invalid Claude request: {}", e))?;
+            let mapped_model = mapped_model_override
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| {
+                    crate::proxy::common::model_mapping::resolve_model_route(&request.model, custom_mapping)
+                });
+            request.model = mapped_model.clone();
+            let gemini_body = crate::proxy::mappers::claude::transform_claude_request_in(
+                &request,
+                DRY_RUN_PROJECT_ID,
+            )?;
+            Ok((mapped_model, gemini_body))
+        }
+        ReplayProtocol::OpenAI => {
+            let request: crate::proxy::mappers::openai::OpenAIRequest =
+                serde_json::from_value(body.clone())
+                    .map_err(|e| format!("Invalid OpenAI request: {}", e))?;
+            let mapped_model = mapped_model_override
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| {
+                    crate::proxy::common::model_mapping::resolve_model_route(&request.model, custom_mapping)
+                });
+            let gemini_body = crate::proxy::mappers::openai::transform_openai_request(
+                &request,
+                DRY_RUN_PROJECT_ID,
+                &mapped_model,
+            );
+            Ok((mapped_model, gemini_body))
+        }
+        ReplayProtocol::Gemini => {
+            let model_name = url
+                .split("/v1beta/models/")
+                .nth(1)
+                .and_then(|s| s.split(':').next())
+                .ok_or("无法从 URL 中解析 Gemini 模型名")?;
+            let mapped_model = mapped_model_override
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| {
+                    crate::proxy::common::model_mapping::resolve_model_route(model_name, custom_mapping)
+                });
+            let gemini_body =
+                crate::proxy::mappers::gemini::wrap_request(body, DRY_RUN_PROJECT_ID, &mapped_model);
+            Ok((mapped_model, gemini_body))
+        }
+    }
+}
+
+/// 计算两个 JSON 值之间的差异，以 JSON Pointer 路径描述
+pub fn diff_json(old: &Value, new: &Value) -> Vec<String> {
+    let mut out = Vec::new();
+    diff_json_at("", old, new, &mut out);
+    out
+}
+
+fn diff_json_at(path: &str, old: &Value, new: &Value, out: &mut Vec<String>) {
+    if out.len() >= MAX_DIFF_ENTRIES {
+        return;
+    }
+    match (old, new) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (k, va) in a {
+                let child = format!("{}/{}", path, k);
+                match b.get(k) {
+                    Some(vb) => diff_json_at(&child, va, vb, out),
+                    None => out.push(format!("- {}: {}", child, preview(va))),
+                }
+            }
+            for (k, vb) in b {
+                if !a.contains_key(k) {
+                    out.push(format!("+ {}/{}: {}", path, k, preview(vb)));
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for i in 0..a.len().max(b.len()) {
+                let child = format!("{}/{}", path, i);
+                match (a.get(i), b.get(i)) {
+                    (Some(va), Some(vb)) => diff_json_at(&child, va, vb, out),
+                    (Some(va), None) => out.push(format!("- {}: {}", child, preview(va))),
+                    (None, Some(vb)) => out.push(format!("+ {}: {}", child, preview(vb))),
+                    (None, None) => {}
+                }
+            }
+        }
+        _ => {
+            if old != new {
+                out.push(format!("~ {}: {} -> {}", path, preview(old), preview(new)));
+            }
+        }
+    }
+}
+
+fn preview(v: &Value) -> String {
+    let s = v.to_string();
+    if s.chars().count() > 120 {
+        format!("{}...", s.chars().take(120).collect::<String>())
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_protocol_from_url() {
+        assert_eq!(ReplayProtocol::from_url("/v1/messages"), Some(ReplayProtocol::Claude));
+        assert_eq!(ReplayProtocol::from_url("/v1/chat/completions"), Some(ReplayProtocol::OpenAI));
+        assert_eq!(
+            ReplayProtocol::from_url("/v1beta/models/gemini-2.5-flash:streamGenerateContent?alt=sse"),
+            Some(ReplayProtocol::Gemini)
+        );
+        assert_eq!(ReplayProtocol::from_url("/v1/models"), None);
+    }
+
+    #[test]
+    fn test_diff_json() {
+        let old = json!({"model": "a", "contents": [1, 2], "removed": true});
+        let new = json!({"model": "b", "contents": [1, 2, 3], "added": {"x": 1}});
+        let diff = diff_json(&old, &new);
+        assert!(diff.contains(&"~ /model: \"a\" -> \"b\"".to_string()));
+        assert!(diff.contains(&"+ /contents/2: 3".to_string()));
+        assert!(diff.contains(&"- /removed: true".to_string()));
+        assert!(diff.contains(&"+ /added: {\"x\":1}".to_string()));
+        assert!(diff_json(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_check_replayable_rejects_placeholders() {
+        assert!(check_replayable(None).is_err());
+        assert!(check_replayable(Some("[Binary Request Data]")).is_err());
+        let redacted = format!("{{\"messages\":\"{}\"}}", crate::proxy::monitor::REDACTED_PLACEHOLDER);
+        assert!(check_replayable(Some(&redacted)).is_err());
+        assert!(check_replayable(Some("{\"model\":\"x\"}")).is_ok());
+    }
+
+    #[test]
+    fn test_replay_marker_requires_internal_token() {
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(REPLAY_HEADER, "trace-1".parse().unwrap());
+        // 客户端伪造的重放标记不会被排除出统计
+        assert_eq!(internal_replay_of(&headers), None);
+
+        headers.insert(INTERNAL_TOKEN_HEADER, "guess".parse().unwrap());
+        assert_eq!(internal_replay_of(&headers), None);
+
+        headers.insert(INTERNAL_TOKEN_HEADER, internal_token().parse().unwrap());
+        assert_eq!(internal_replay_of(&headers).as_deref(), Some("trace-1"));
+
+        // 自测使用独立的标记头
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(SELF_TEST_HEADER, "self-test-1".parse().unwrap());
+        assert_eq!(internal_replay_of(&headers), None);
+        headers.insert(INTERNAL_TOKEN_HEADER, internal_token().parse().unwrap());
+        assert_eq!(internal_replay_of(&headers).as_deref(), Some("self-test-1"));
+    }
+
+    #[test]
+    fn test_redacted_capture_is_not_replayable() {
+        let mut body = json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 16,
+            "system": "secret system prompt",
+            "messages": [{ "role": "user", "content": [{ "type": "text", "text": "secret" }] }]
+        });
+        assert!(crate::proxy::monitor::redact_message_content(&mut body));
+        assert_eq!(body["model"], "claude-sonnet-4-5");
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert_eq!(body["messages"][0]["content"][0]["type"], "text");
+        assert!(!body.to_string().contains("secret"));
+
+        let err = check_replayable(Some(&body.to_string())).unwrap_err();
+        assert!(err.contains("脱敏"));
+    }
+}
@@ -1,23 +1,48 @@
-use crate::proxy::config::{ProxyAuthMode, ProxyConfig};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::proxy::config::{
+    ClientApiKey, MetricsConfig, ProxyAuthMode, ProxyConfig, ResponseHeaderPolicy, ResponseHeadersConfig,
+    RouteAuthConfig, RouteGroup, ToolFilterConfig,
+};
 
 #[derive(Debug, Clone)]
 pub struct ProxySecurityConfig {
     pub auth_mode: ProxyAuthMode,
+    pub route_auth: RouteAuthConfig,
     pub api_key: String,
     pub allow_lan_access: bool,
+    pub response_header_policy: ResponseHeaderPolicy,
+    pub response_headers: ResponseHeadersConfig,
+    pub metrics: MetricsConfig,
+    pub tool_filter: ToolFilterConfig,
+    pub enable_setup_page: bool,
+    pub client_keys: Vec<ClientApiKey>,
+}
+
+/// 客户端 Key 的哈希 (配置中只保存该值)
+pub fn hash_client_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
 }
 
 impl ProxySecurityConfig {
     pub fn from_proxy_config(config: &ProxyConfig) -> Self {
         Self {
             auth_mode: config.auth_mode.clone(),
+            route_auth: config.route_auth.clone(),
             api_key: config.api_key.clone(),
             allow_lan_access: config.allow_lan_access,
+            response_header_policy: config.response_header_policy,
+            response_headers: config.response_headers.clone(),
+            metrics: config.metrics.clone(),
+            tool_filter: config.tool_filter.clone(),
+            enable_setup_page: config.enable_setup_page,
+            client_keys: config.client_keys.clone(),
         }
     }
 
-    pub fn effective_auth_mode(&self) -> ProxyAuthMode {
-        match self.auth_mode {
+    fn resolve(&self, mode: &ProxyAuthMode) -> ProxyAuthMode {
+        match mode {
             ProxyAuthMode::Auto => {
                 if self.allow_lan_access {
                     ProxyAuthMode::AllExceptHealth
@@ -25,11 +50,79 @@ impl ProxySecurityConfig {
                     ProxyAuthMode::Off
                 }
             }
-            ref other => other.clone(),
+            other => other.clone(),
+        }
+    }
+
+    pub fn effective_auth_mode(&self) -> ProxyAuthMode {
+        self.resolve(&self.auth_mode)
+    }
+
+    /// 路由分组生效的鉴权模式 (未单独设置时使用全局 auth_mode)
+    pub fn effective_group_mode(&self, group: RouteGroup) -> ProxyAuthMode {
+        self.resolve(self.route_auth.get(group).unwrap_or(&self.auth_mode))
+    }
+
+    /// 请求路径生效的鉴权模式；未归入任何分组的路径使用已配置模式中最严格的一个，避免新增路由默认开放
+    pub fn effective_auth_mode_for(&self, path: &str) -> ProxyAuthMode {
+        match RouteGroup::for_path(path) {
+            Some(group) => self.effective_group_mode(group),
+            None => self.strictest_mode(),
+        }
+    }
+
+    fn strictest_mode(&self) -> ProxyAuthMode {
+        RouteGroup::ALL
+            .iter()
+            .map(|g| self.effective_group_mode(*g))
+            .fold(self.effective_auth_mode(), |a, b| if strictness(&b) > strictness(&a) { b } else { a })
+    }
+
+    /// 请求携带的 Key 是否可访问该路径：api_key 不限；客户端 Key 设置了分组时只能访问这些分组
+    pub fn authorize_key(&self, key: &str, path: &str) -> bool {
+        if !self.api_key.is_empty() && key == self.api_key {
+            return true;
+        }
+        if self.client_keys.is_empty() {
+            return false;
+        }
+        let hash = hash_client_key(key);
+        let group = RouteGroup::for_path(path);
+        self.client_keys.iter().any(|k| {
+            k.key_sha256 == hash && (k.scopes.is_empty() || group.map_or(false, |g| k.scopes.contains(&g)))
+        })
+    }
+
+    /// 各分组生效的鉴权模式 (用于状态展示)
+    pub fn effective_route_modes(&self) -> EffectiveAuthModes {
+        EffectiveAuthModes {
+            claude: self.effective_group_mode(RouteGroup::Claude),
+            openai: self.effective_group_mode(RouteGroup::Openai),
+            gemini_native: self.effective_group_mode(RouteGroup::GeminiNative),
+            status: self.effective_group_mode(RouteGroup::Status),
+            other: self.strictest_mode(),
         }
     }
 }
 
+/// 已解析 (不含 Auto) 的各分组鉴权模式；other 为未归入分组的路径
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct EffectiveAuthModes {
+    pub claude: ProxyAuthMode,
+    pub openai: ProxyAuthMode,
+    pub gemini_native: ProxyAuthMode,
+    pub status: ProxyAuthMode,
+    pub other: ProxyAuthMode,
+}
+
+fn strictness(mode: &ProxyAuthMode) -> u8 {
+    match mode {
+        ProxyAuthMode::Off | ProxyAuthMode::Auto => 0,
+        ProxyAuthMode::AllExceptHealth => 1,
+        ProxyAuthMode::Strict => 2,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -38,8 +131,15 @@ mod tests {
     fn auto_mode_resolves_off_for_local_only() {
         let s = ProxySecurityConfig {
             auth_mode: ProxyAuthMode::Auto,
+            route_auth: RouteAuthConfig::default(),
             api_key: "sk-test".to_string(),
             allow_lan_access: false,
+            response_header_policy: ResponseHeaderPolicy::Full,
+            response_headers: ResponseHeadersConfig::default(),
+            metrics: MetricsConfig::default(),
+            tool_filter: ToolFilterConfig::default(),
+            enable_setup_page: false,
+            client_keys: Vec::new(),
         };
         assert!(matches!(s.effective_auth_mode(), ProxyAuthMode::Off));
     }
@@ -48,13 +148,67 @@ mod tests {
     fn auto_mode_resolves_all_except_health_for_lan() {
         let s = ProxySecurityConfig {
             auth_mode: ProxyAuthMode::Auto,
+            route_auth: RouteAuthConfig::default(),
             api_key: "sk-test".to_string(),
             allow_lan_access: true,
+            response_header_policy: ResponseHeaderPolicy::Full,
+            response_headers: ResponseHeadersConfig::default(),
+            metrics: MetricsConfig::default(),
+            tool_filter: ToolFilterConfig::default(),
+            enable_setup_page: false,
+            client_keys: Vec::new(),
         };
         assert!(matches!(
             s.effective_auth_mode(),
             ProxyAuthMode::AllExceptHealth
         ));
     }
-}
 
+    #[test]
+    fn route_groups_override_global_mode_and_unmatched_paths_use_strictest() {
+        let config = ProxyConfig {
+            auth_mode: ProxyAuthMode::Off,
+            route_auth: RouteAuthConfig {
+                claude: Some(ProxyAuthMode::Strict),
+                gemini_native: Some(ProxyAuthMode::AllExceptHealth),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let s = ProxySecurityConfig::from_proxy_config(&config);
+
+        assert_eq!(s.effective_auth_mode_for("/v1/messages"), ProxyAuthMode::Strict);
+        assert_eq!(s.effective_auth_mode_for("/v1/chat/completions"), ProxyAuthMode::Off);
+        assert_eq!(
+            s.effective_auth_mode_for("/v1beta/models/gemini-pro:generateContent"),
+            ProxyAuthMode::AllExceptHealth
+        );
+        assert_eq!(s.effective_auth_mode_for("/healthz"), ProxyAuthMode::Off);
+        // 未归入分组的路径不能因全局 Off 而开放
+        assert_eq!(s.effective_auth_mode_for("/v1/models/detect"), ProxyAuthMode::Strict);
+        assert_eq!(s.effective_route_modes().other, ProxyAuthMode::Strict);
+    }
+
+    #[test]
+    fn scoped_client_key_only_opens_its_groups() {
+        let config = ProxyConfig {
+            api_key: "sk-main".to_string(),
+            client_keys: vec![ClientApiKey {
+                id: "k1".to_string(),
+                label: "phone".to_string(),
+                key_sha256: hash_client_key("sk-phone"),
+                scopes: vec![RouteGroup::Claude],
+                created_at: 0,
+            }],
+            ..Default::default()
+        };
+        let s = ProxySecurityConfig::from_proxy_config(&config);
+
+        assert!(s.authorize_key("sk-main", "/v1/models/detect"));
+        assert!(s.authorize_key("sk-phone", "/v1/messages"));
+        assert!(!s.authorize_key("sk-phone", "/v1/chat/completions"));
+        // 不属于任何分组的路径不对限定分组的 Key 开放
+        assert!(!s.authorize_key("sk-phone", "/v1/models/detect"));
+        assert!(!s.authorize_key(&hash_client_key("sk-phone"), "/v1/messages"));
+    }
+}
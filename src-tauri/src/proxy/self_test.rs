@@ -0,0 +1,172 @@
+// 端到端吞吐自测
+// 通过本地回环向运行中的反代并发发送最小化的补全请求，统计吞吐、延迟分位数、错误率与账号分布，
+// 用于评估账号池规模与调度配置。请求带有自测标记头，不计入使用统计。
+// 会消耗真实配额，需在实验性配置中开启 enable_self_test 后才能从界面调用。
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::proxy::tls::LoopbackEndpoint;
+
+/// 自测使用的模型 (走最便宜的 Flash 路径)
+pub const SELF_TEST_MODEL: &str = "gemini-2.5-flash";
+
+/// 单次自测的请求数与并发上限，避免误操作耗尽配额
+pub const MAX_SELF_TEST_REQUESTS: usize = 500;
+pub const MAX_SELF_TEST_CONCURRENCY: usize = 64;
+
+/// 响应头中没有账号标识时的分组名
+const UNKNOWN_ACCOUNT: &str = "unknown";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelfTestReport {
+    pub requests: usize,
+    pub concurrency: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub error_rate: f64,
+    pub duration_ms: u64,
+    pub throughput_rps: f64,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    /// 账号标识 -> 成功请求数
+    pub per_account: BTreeMap<String, usize>,
+    /// 首个错误 (便于排查)
+    pub first_error: Option<String>,
+}
+
+struct Sample {
+    latency_ms: u64,
+    account: Option<String>,
+    error: Option<String>,
+}
+
+/// 最近秩法求分位数 (`sorted` 需已升序)
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+async fn send_one(client: &reqwest::Client, url: &str, api_key: &str, run_id: &str, prompt: &str) -> Sample {
+    let start = Instant::now();
+    let result = client
+        .post(url)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header(crate::proxy::replay::SELF_TEST_HEADER, run_id)
+        .header(crate::proxy::replay::INTERNAL_TOKEN_HEADER, crate::proxy::replay::internal_token())
+        .json(&json!({
+            "model": SELF_TEST_MODEL,
+            "max_tokens": 16,
+            "messages": [{ "role": "user", "content": prompt }]
+        }))
+        .send()
+        .await;
+
+    let (account, error) = match result {
+        Ok(resp) => {
+            let status = resp.status();
+            let account = [
+                crate::proxy::common::account_identity::ACCOUNT_EMAIL_HEADER,
+                crate::proxy::common::account_identity::ACCOUNT_ID_HEADER,
+            ]
+            .iter()
+            .find_map(|name| resp.headers().get(*name).and_then(|v| v.to_str().ok()).map(|s| s.to_string()));
+            let body = resp.text().await.unwrap_or_default();
+            if status.is_success() {
+                (account, None)
+            } else {
+                let preview: String = body.chars().take(300).collect();
+                (account, Some(format!("HTTP {}: {}", status.as_u16(), preview)))
+            }
+        }
+        Err(e) => (None, Some(e.to_string())),
+    };
+
+    Sample {
+        latency_ms: start.elapsed().as_millis() as u64,
+        account,
+        error,
+    }
+}
+
+/// 向 `endpoint` 上的反代发送 `requests` 个请求 (并发 `concurrency`)，返回统计报告
+pub async fn run_self_test(
+    endpoint: &LoopbackEndpoint,
+    api_key: &str,
+    concurrency: usize,
+    requests: usize,
+    prompt: &str,
+) -> Result<SelfTestReport, String> {
+    if requests == 0 || requests > MAX_SELF_TEST_REQUESTS {
+        return Err(format!("请求数必须在 1-{} 之间", MAX_SELF_TEST_REQUESTS));
+    }
+    if concurrency == 0 || concurrency > MAX_SELF_TEST_CONCURRENCY {
+        return Err(format!("并发数必须在 1-{} 之间", MAX_SELF_TEST_CONCURRENCY));
+    }
+
+    let client = endpoint.client(Duration::from_secs(300))?;
+    let url = format!("{}/v1/chat/completions", endpoint.base_url.trim_end_matches('/'));
+    let run_id = format!("self-test-{}", uuid::Uuid::new_v4().simple());
+
+    let start = Instant::now();
+    let samples: Vec<Sample> = futures::stream::iter(0..requests)
+        .map(|_| send_one(&client, &url, api_key, &run_id, prompt))
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+    let elapsed = start.elapsed();
+
+    let mut latencies: Vec<u64> = samples.iter().map(|s| s.latency_ms).collect();
+    latencies.sort_unstable();
+
+    let mut per_account = BTreeMap::new();
+    for sample in samples.iter().filter(|s| s.error.is_none()) {
+        let account = sample.account.clone().unwrap_or_else(|| UNKNOWN_ACCOUNT.to_string());
+        *per_account.entry(account).or_insert(0) += 1;
+    }
+    let failed = samples.iter().filter(|s| s.error.is_some()).count();
+
+    let report = SelfTestReport {
+        requests,
+        concurrency,
+        succeeded: requests - failed,
+        failed,
+        error_rate: failed as f64 / requests as f64,
+        duration_ms: elapsed.as_millis() as u64,
+        throughput_rps: requests as f64 / elapsed.as_secs_f64().max(0.001),
+        p50_ms: percentile(&latencies, 50.0),
+        p95_ms: percentile(&latencies, 95.0),
+        per_account,
+        first_error: samples.iter().find_map(|s| s.error.clone()),
+    };
+    tracing::info!(
+        "[SelfTest] {} requests @ {} concurrency: {:.1} req/s, p50 {}ms, p95 {}ms, {} failed",
+        requests,
+        concurrency,
+        report.throughput_rps,
+        report.p50_ms,
+        report.p95_ms,
+        failed
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let sorted: Vec<u64> = (1..=20).collect();
+        assert_eq!(percentile(&sorted, 50.0), 10);
+        assert_eq!(percentile(&sorted, 95.0), 19);
+        assert_eq!(percentile(&[7], 95.0), 7);
+        assert_eq!(percentile(&[], 50.0), 0);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::proxy::TokenManager;
 use axum::{
-    extract::DefaultBodyLimit,
+    extract::{DefaultBodyLimit, State},
     http::StatusCode,
     response::{IntoResponse, Json, Response},
     routing::{any, get, post},
@@ -11,13 +11,27 @@ use tokio::sync::oneshot;
 use tower_http::trace::TraceLayer;
 use tracing::{debug, error};
 use tokio::sync::RwLock;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// 只读管理接口的路径前缀 (无论全局鉴权模式如何，总是要求 API Key)
+pub const ADMIN_PATH_PREFIX: &str = "/admin/";
+
+/// 调度配置查询接口
+pub const ADMIN_SCHEDULING_PATH: &str = "/admin/scheduling";
+
+/// 客户端引导页 (需开启 enable_setup_page，总是要求主 API Key)
+pub const SETUP_PATH: &str = "/setup";
+
+/// 引导页及其下的接口
+pub fn is_setup_path(path: &str) -> bool {
+    path == SETUP_PATH || path.strip_prefix(SETUP_PATH).map_or(false, |rest| rest.starts_with('/'))
+}
 
 /// Axum 应用状态
 #[derive(Clone)]
 pub struct AppState {
     pub token_manager: Arc<TokenManager>,
-    pub custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
+    pub custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, crate::proxy::config::ModelMappingTarget>>>,
     #[allow(dead_code)]
     pub request_timeout: u64, // API 请求超时(秒)
     #[allow(dead_code)]
@@ -26,30 +40,76 @@ pub struct AppState {
     pub upstream_proxy: Arc<tokio::sync::RwLock<crate::proxy::config::UpstreamProxyConfig>>,
     pub upstream: Arc<crate::proxy::upstream::client::UpstreamClient>,
     pub zai: Arc<RwLock<crate::proxy::ZaiConfig>>,
+    /// z.ai Key 轮换与独立限流状态
+    pub zai_keys: Arc<crate::proxy::providers::zai_key_pool::ZaiKeyPool>,
     pub provider_rr: Arc<AtomicUsize>,
     pub zai_vision_mcp: Arc<crate::proxy::zai_vision_mcp::ZaiVisionMcpState>,
     pub monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
     pub experimental: Arc<RwLock<crate::proxy::config::ExperimentalConfig>>,
+    pub sampling_limits: Arc<RwLock<crate::proxy::config::SamplingLimitsConfig>>,
+    /// 消息历史上限 (0 表示不限制)
+    pub max_history_messages: Arc<AtomicUsize>,
+    /// 安全策略 (工具过滤按请求所用的 API Key 生效)
+    pub security: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
+    /// 逐 part 的流式调试日志 (运行时开关，默认关闭)
+    pub stream_debug: Arc<AtomicBool>,
+    /// 音频转录的上传上限与分段配置
+    pub audio: Arc<RwLock<crate::proxy::config::AudioConfig>>,
 }
 
 /// Axum 服务器实例
 pub struct AxumServer {
     shutdown_tx: Option<oneshot::Sender<()>>,
-    custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, String>>>,
+    custom_mapping: Arc<tokio::sync::RwLock<std::collections::HashMap<String, crate::proxy::config::ModelMappingTarget>>>,
     proxy_state: Arc<tokio::sync::RwLock<crate::proxy::config::UpstreamProxyConfig>>,
     security_state: Arc<RwLock<crate::proxy::ProxySecurityConfig>>,
     zai_state: Arc<RwLock<crate::proxy::ZaiConfig>>,
+    zai_keys: Arc<crate::proxy::providers::zai_key_pool::ZaiKeyPool>,
+    sampling_state: Arc<RwLock<crate::proxy::config::SamplingLimitsConfig>>,
+    audio_state: Arc<RwLock<crate::proxy::config::AudioConfig>>,
+    history_limit: Arc<AtomicUsize>,
+    stream_debug: Arc<AtomicBool>,
+    monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
+    upstream: Arc<crate::proxy::upstream::client::UpstreamClient>,
+    /// HTTPS 监听 (未启用 TLS 时为 None)
+    tls: Option<HttpsListener>,
+}
+
+/// 运行中的 HTTPS 监听：证书解析器与实际端口
+struct HttpsListener {
+    resolver: Arc<crate::proxy::tls::ReloadableCertResolver>,
+    port: u16,
 }
 
 impl AxumServer {
     pub async fn update_mapping(&self, config: &crate::proxy::config::ProxyConfig) {
         {
             let mut m = self.custom_mapping.write().await;
+            crate::proxy::common::model_mapping::reset_changed_route_stats(&m, &config.custom_mapping);
             *m = config.custom_mapping.clone();
         }
         tracing::debug!("模型映射 (Custom) 已全量热更新");
     }
 
+    /// 将代理配置全量热更新到运行中的服务 (映射 / 上游代理 / 安全策略 / z.ai)
+    pub async fn apply_proxy_config(&self, config: &crate::proxy::config::ProxyConfig) {
+        self.update_mapping(config).await;
+        self.update_proxy(config.upstream_proxy.clone()).await;
+        self.update_upstream_client(config);
+        self.update_security(config).await;
+        self.update_zai(config).await;
+        self.update_sampling_limits(config).await;
+        self.update_audio(config).await;
+        self.update_history_limit(config);
+        self.monitor.set_hash_end_users(config.hash_end_user_labels);
+        self.monitor.set_redact_content(config.redact_logged_content);
+    }
+
+    /// 获取当前生效的模型映射
+    pub async fn get_mapping(&self) -> std::collections::HashMap<String, crate::proxy::config::ModelMappingTarget> {
+        self.custom_mapping.read().await.clone()
+    }
+
     /// 更新代理配置
     pub async fn update_proxy(&self, new_config: crate::proxy::config::UpstreamProxyConfig) {
         let mut proxy = self.proxy_state.write().await;
@@ -57,39 +117,100 @@ impl AxumServer {
         tracing::info!("上游代理配置已热更新");
     }
 
+    /// 按上游代理与连接池配置重建上游 HTTP 客户端
+    pub fn update_upstream_client(&self, config: &crate::proxy::config::ProxyConfig) {
+        let pool = crate::proxy::upstream::client::UpstreamPoolConfig::from_proxy_config(config);
+        if let Err(e) = self.upstream.rebuild(&config.upstream_proxy, pool) {
+            tracing::error!("上游 HTTP 客户端重建失败，继续使用旧客户端: {}", e);
+        }
+    }
+
     pub async fn update_security(&self, config: &crate::proxy::config::ProxyConfig) {
         let mut sec = self.security_state.write().await;
         *sec = crate::proxy::ProxySecurityConfig::from_proxy_config(config);
+        self.monitor.metrics.configure(&sec.metrics);
         tracing::info!("反代服务安全配置已热更新");
     }
 
     pub async fn update_zai(&self, config: &crate::proxy::config::ProxyConfig) {
         let mut zai = self.zai_state.write().await;
         *zai = config.zai.clone();
+        self.zai_keys.sync(&zai.effective_keys());
         tracing::info!("z.ai 配置已热更新");
     }
+
+    /// z.ai 各 Key 的请求数与锁定状态 (Key 已脱敏)
+    pub async fn zai_key_stats(&self) -> Vec<crate::proxy::providers::zai_key_pool::ZaiKeyStats> {
+        let keys = self.zai_state.read().await.effective_keys();
+        self.zai_keys.stats(&keys)
+    }
+
+    pub async fn update_sampling_limits(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut limits = self.sampling_state.write().await;
+        *limits = config.sampling_limits.clone();
+        tracing::info!("采样参数钳制配置已热更新");
+    }
+
+    pub async fn update_audio(&self, config: &crate::proxy::config::ProxyConfig) {
+        let mut audio = self.audio_state.write().await;
+        *audio = config.audio.clone();
+        tracing::info!("音频转录配置已热更新");
+    }
+
+    pub fn update_history_limit(&self, config: &crate::proxy::config::ProxyConfig) {
+        self.history_limit
+            .store(config.max_history_messages.unwrap_or(0), std::sync::atomic::Ordering::Relaxed);
+        tracing::info!("消息历史上限已热更新: {:?}", config.max_history_messages);
+    }
+
+    pub fn set_stream_debug(&self, enabled: bool) {
+        self.stream_debug.store(enabled, Ordering::Relaxed);
+        tracing::info!("流式调试日志已{}", if enabled { "开启" } else { "关闭" });
+    }
+
+    pub fn stream_debug(&self) -> bool {
+        self.stream_debug.load(Ordering::Relaxed)
+    }
     /// 启动 Axum 服务器
     pub async fn start(
         host: String,
         port: u16,
         token_manager: Arc<TokenManager>,
-        custom_mapping: std::collections::HashMap<String, String>,
+        custom_mapping: std::collections::HashMap<String, crate::proxy::config::ModelMappingTarget>,
         _request_timeout: u64,
         upstream_proxy: crate::proxy::config::UpstreamProxyConfig,
         security_config: crate::proxy::ProxySecurityConfig,
         zai_config: crate::proxy::ZaiConfig,
         monitor: Arc<crate::proxy::monitor::ProxyMonitor>,
         experimental_config: crate::proxy::config::ExperimentalConfig,
-
+        sampling_limits: crate::proxy::config::SamplingLimitsConfig,
+        max_history_messages: Option<usize>,
+        mock_upstream: crate::proxy::config::MockUpstreamConfig,
+        upstream_pool: crate::proxy::upstream::client::UpstreamPoolConfig,
+        audio_config: crate::proxy::config::AudioConfig,
+        tls: Option<crate::proxy::tls::ListenerTls>,
     ) -> Result<(Self, tokio::task::JoinHandle<()>), String> {
+        monitor.metrics.configure(&security_config.metrics);
         let custom_mapping_state = Arc::new(tokio::sync::RwLock::new(custom_mapping));
 	        let proxy_state = Arc::new(tokio::sync::RwLock::new(upstream_proxy.clone()));
 	        let security_state = Arc::new(RwLock::new(security_config));
+	        let zai_keys = Arc::new(crate::proxy::providers::zai_key_pool::ZaiKeyPool::new());
+	        zai_keys.sync(&zai_config.effective_keys());
 	        let zai_state = Arc::new(RwLock::new(zai_config));
 	        let provider_rr = Arc::new(AtomicUsize::new(0));
 	        let zai_vision_mcp_state =
 	            Arc::new(crate::proxy::zai_vision_mcp::ZaiVisionMcpState::new());
 	        let experimental_state = Arc::new(RwLock::new(experimental_config));
+	        let sampling_state = Arc::new(RwLock::new(sampling_limits));
+	        let audio_state = Arc::new(RwLock::new(audio_config));
+	        crate::proxy::audio::spool::cleanup_stale_files();
+	        let history_limit = Arc::new(AtomicUsize::new(max_history_messages.unwrap_or(0)));
+	        let stream_debug = Arc::new(AtomicBool::new(false));
+	        let upstream = Arc::new(if mock_upstream.enabled {
+	            crate::proxy::upstream::client::UpstreamClient::new_mock(mock_upstream)
+	        } else {
+	            crate::proxy::upstream::client::UpstreamClient::new(Some(upstream_proxy.clone()), upstream_pool)
+	        });
 
 	        let state = AppState {
 	            token_manager: token_manager.clone(),
@@ -99,14 +220,18 @@ impl AxumServer {
                 std::collections::HashMap::new(),
             )),
             upstream_proxy: proxy_state.clone(),
-            upstream: Arc::new(crate::proxy::upstream::client::UpstreamClient::new(Some(
-                upstream_proxy.clone(),
-            ))),
+            upstream: upstream.clone(),
             zai: zai_state.clone(),
+            zai_keys: zai_keys.clone(),
             provider_rr: provider_rr.clone(),
             zai_vision_mcp: zai_vision_mcp_state,
             monitor: monitor.clone(),
             experimental: experimental_state,
+            sampling_limits: sampling_state.clone(),
+            max_history_messages: history_limit.clone(),
+            security: security_state.clone(),
+            stream_debug: stream_debug.clone(),
+            audio: audio_state.clone(),
         };
 
 
@@ -135,7 +260,8 @@ impl AxumServer {
             ) // 图像编辑 API
             .route(
                 "/v1/audio/transcriptions",
-                post(handlers::audio::handle_audio_transcription),
+                // 上传大小由 audio.max_upload_mb 在读取时限制 (文件与其余表单字段合计)，不受全局请求体上限约束
+                post(handlers::audio::handle_audio_transcription).layer(DefaultBodyLimit::disable()),
             ) // 音频转录 API (PR #311)
             // Claude Protocol
             .route("/v1/messages", post(handlers::claude::handle_messages))
@@ -176,8 +302,22 @@ impl AxumServer {
             .route("/v1/api/event_logging/batch", post(silent_ok_handler))
             .route("/v1/api/event_logging", post(silent_ok_handler))
             .route("/healthz", get(health_check_handler))
+            .route(crate::proxy::metrics::METRICS_PATH, get(metrics_handler))
+            .route(ADMIN_SCHEDULING_PATH, get(admin_scheduling_handler))
+            .route(SETUP_PATH, get(handlers::setup::handle_setup_page))
+            .route("/setup/keys", post(handlers::setup::handle_create_client_key))
             .layer(DefaultBodyLimit::max(100 * 1024 * 1024))
+            // 位于监控之内：未知区域的 400 与实际使用的区域都会被监控记录
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                crate::proxy::middleware::upstream_region_middleware,
+            ))
             .layer(axum::middleware::from_fn_with_state(state.clone(), crate::proxy::middleware::monitor::monitor_middleware))
+            // 位于监控之外：监控记录真实值，对外响应再按策略处理
+            .layer(axum::middleware::from_fn_with_state(
+                security_state.clone(),
+                crate::proxy::middleware::response_header_middleware,
+            ))
             .layer(TraceLayer::new_for_http())
             .layer(axum::middleware::from_fn_with_state(
                 security_state.clone(),
@@ -192,10 +332,35 @@ impl AxumServer {
             .await
             .map_err(|e| format!("地址 {} 绑定失败: {}", addr, e))?;
 
-        tracing::info!("反代服务器启动在 http://{}", addr);
+        // TLS：未设置 https_port 时主端口只接受 HTTPS，否则在 https_port 上额外监听 HTTPS
+        let mut primary_tls = None;
+        let mut https_listener = None;
+        let mut https_state = None;
+        if let Some(tls) = tls {
+            let resolver = Arc::new(crate::proxy::tls::ReloadableCertResolver::new(tls.cert));
+            let acceptor = crate::proxy::tls::acceptor(resolver.clone())?;
+            match tls.https_port {
+                None => {
+                    primary_tls = Some(acceptor);
+                    https_state = Some(HttpsListener { resolver, port });
+                }
+                Some(https_port) => {
+                    let https_addr = format!("{}:{}", host, https_port);
+                    let l = tokio::net::TcpListener::bind(&https_addr)
+                        .await
+                        .map_err(|e| format!("HTTPS 地址 {} 绑定失败: {}", https_addr, e))?;
+                    tracing::info!("反代服务器 HTTPS 监听在 https://{}", https_addr);
+                    https_listener = Some((l, acceptor));
+                    https_state = Some(HttpsListener { resolver, port: https_port });
+                }
+            }
+        }
+
+        let scheme = if primary_tls.is_some() { "https" } else { "http" };
+        tracing::info!("反代服务器启动在 {}://{}", scheme, addr);
 
         // 创建关闭通道
-        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
 
         let server_instance = Self {
             shutdown_tx: Some(shutdown_tx),
@@ -203,41 +368,30 @@ impl AxumServer {
             proxy_state,
             security_state,
             zai_state,
+            zai_keys,
+            sampling_state,
+            audio_state,
+            history_limit,
+            stream_debug,
+            monitor,
+            upstream,
+            tls: https_state,
         };
 
-        // 在新任务中启动服务器
+        // 在新任务中启动服务器 (HTTP 与可选的 HTTPS 监听共用同一个关闭通道)
         let handle = tokio::spawn(async move {
-            use hyper::server::conn::http1;
-            use hyper_util::rt::TokioIo;
-            use hyper_util::service::TowerToHyperService;
-
-            loop {
-                tokio::select! {
-                    res = listener.accept() => {
-                        match res {
-                            Ok((stream, _)) => {
-                                let io = TokioIo::new(stream);
-                                let service = TowerToHyperService::new(app.clone());
-
-                                tokio::task::spawn(async move {
-                                    if let Err(err) = http1::Builder::new()
-                                        .serve_connection(io, service)
-                                        .with_upgrades() // 支持 WebSocket (如果以后需要)
-                                        .await
-                                    {
-                                        debug!("连接处理结束或出错: {:?}", err);
-                                    }
-                                });
-                            }
-                            Err(e) => {
-                                error!("接收连接失败: {:?}", e);
-                            }
-                        }
-                    }
-                    _ = &mut shutdown_rx => {
-                        tracing::info!("反代服务器停止监听");
-                        break;
-                    }
+            let primary = accept_loop(listener, app.clone(), primary_tls);
+            let secondary = async move {
+                match https_listener {
+                    Some((listener, acceptor)) => accept_loop(listener, app, Some(acceptor)).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                _ = primary => {}
+                _ = secondary => {}
+                _ = shutdown_rx => {
+                    tracing::info!("反代服务器停止监听");
                 }
             }
         });
@@ -245,6 +399,45 @@ impl AxumServer {
         Ok((server_instance, handle))
     }
 
+    /// 各路由分组当前生效的鉴权模式
+    pub async fn effective_auth_modes(&self) -> crate::proxy::security::EffectiveAuthModes {
+        self.security_state.read().await.effective_route_modes()
+    }
+
+    /// 当前 HTTPS 监听状态 (未启用 TLS 时为 None)
+    pub fn tls_status(&self) -> Option<crate::proxy::tls::TlsStatus> {
+        self.tls.as_ref().map(|tls| {
+            let cert = tls.resolver.current();
+            crate::proxy::tls::TlsStatus {
+                https_port: tls.port,
+                self_signed: cert.self_signed,
+                fingerprint_sha256: cert.fingerprint_sha256,
+            }
+        })
+    }
+
+    /// 本地回环访问入口：主端口 `port` 为明文 HTTP 时直接使用 (即使另有 HTTPS 端口)；
+    /// 主端口只接受 HTTPS 时返回 https 地址，并附带当前证书供回环客户端信任
+    pub fn loopback_endpoint(&self, port: u16) -> crate::proxy::tls::LoopbackEndpoint {
+        match self.tls.as_ref() {
+            Some(tls) if tls.port == port => crate::proxy::tls::LoopbackEndpoint {
+                base_url: format!("https://127.0.0.1:{}", port),
+                trusted_cert: tls.resolver.current().key.cert.first().cloned(),
+            },
+            _ => crate::proxy::tls::LoopbackEndpoint::plain(format!("http://127.0.0.1:{}", port)),
+        }
+    }
+
+    /// 热替换 HTTPS 证书：新连接使用新证书，已有连接与号池状态不受影响
+    pub fn reload_tls(&self, cert: crate::proxy::tls::LoadedCert) -> Result<crate::proxy::tls::TlsStatus, String> {
+        let Some(tls) = self.tls.as_ref() else {
+            return Err("反代服务未启用 TLS，请在配置中开启后重启服务".to_string());
+        };
+        tls.resolver.replace(cert);
+        tracing::info!("反代 HTTPS 证书已热更新");
+        self.tls_status().ok_or_else(|| "反代服务未启用 TLS".to_string())
+    }
+
     /// 停止服务器
     pub fn stop(mut self) {
         if let Some(tx) = self.shutdown_tx.take() {
@@ -253,6 +446,60 @@ impl AxumServer {
     }
 }
 
+/// 持续接受连接并交给 axum 处理；设置了 TLS 时先完成握手
+async fn accept_loop(
+    listener: tokio::net::TcpListener,
+    app: Router,
+    tls: Option<tokio_rustls::TlsAcceptor>,
+) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer_addr)) => {
+                let app = app.clone();
+                let tls = tls.clone();
+                tokio::task::spawn(async move {
+                    match tls {
+                        Some(acceptor) => match acceptor.accept(stream).await {
+                            Ok(stream) => serve_connection(stream, app, peer_addr).await,
+                            Err(e) => debug!("TLS 握手失败 ({}): {:?}", peer_addr, e),
+                        },
+                        None => serve_connection(stream, app, peer_addr).await,
+                    }
+                });
+            }
+            Err(e) => {
+                error!("接收连接失败: {:?}", e);
+            }
+        }
+    }
+}
+
+async fn serve_connection<I>(io: I, app: Router, peer_addr: std::net::SocketAddr)
+where
+    I: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    use hyper::server::conn::http1;
+    use hyper_util::rt::TokioIo;
+    use hyper_util::service::TowerToHyperService;
+    use tower::ServiceExt;
+
+    // 注入来源地址，供 /metrics 的本机访问限制使用
+    let service = TowerToHyperService::new(app.map_request(
+        move |mut req: axum::http::Request<hyper::body::Incoming>| {
+            req.extensions_mut().insert(axum::extract::ConnectInfo(peer_addr));
+            req
+        },
+    ));
+
+    if let Err(err) = http1::Builder::new()
+        .serve_connection(TokioIo::new(io), service)
+        .with_upgrades() // 支持 WebSocket (如果以后需要)
+        .await
+    {
+        debug!("连接处理结束或出错: {:?}", err);
+    }
+}
+
 // ===== API 处理器 (旧代码已移除，由 src/proxy/handlers/* 接管) =====
 
 /// 健康检查处理器
@@ -263,7 +510,122 @@ async fn health_check_handler() -> Response {
     .into_response()
 }
 
+/// Prometheus 指标处理器 (访问控制由鉴权中间件负责)
+async fn metrics_handler(State(state): State<AppState>) -> Response {
+    let body = state
+        .monitor
+        .metrics
+        .render(state.token_manager.account_state_counts());
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
+/// 运行中的调度配置 (`config` 与 get_proxy_scheduling_config 命令的返回一致)
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SchedulingStatus {
+    pub mode: crate::proxy::sticky_config::SchedulingMode,
+    pub pool_size: usize,
+    pub config: crate::proxy::sticky_config::StickySessionConfig,
+}
+
+/// 调度配置查询处理器 (只读，访问控制由鉴权中间件负责)
+async fn admin_scheduling_handler(State(state): State<AppState>) -> Response {
+    let config = state.token_manager.get_sticky_config().await;
+    Json(SchedulingStatus {
+        mode: config.mode,
+        pool_size: state.token_manager.len(),
+        config,
+    })
+    .into_response()
+}
+
 /// 静默成功处理器 (用于拦截遥测日志等)
 async fn silent_ok_handler() -> Response {
     StatusCode::OK.into_response()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_server(config: &crate::proxy::config::ProxyConfig) -> AxumServer {
+        AxumServer {
+            shutdown_tx: None,
+            custom_mapping: Arc::new(RwLock::new(config.custom_mapping.clone())),
+            proxy_state: Arc::new(RwLock::new(config.upstream_proxy.clone())),
+            security_state: Arc::new(RwLock::new(
+                crate::proxy::ProxySecurityConfig::from_proxy_config(config),
+            )),
+            zai_state: Arc::new(RwLock::new(config.zai.clone())),
+            zai_keys: Arc::new(crate::proxy::providers::zai_key_pool::ZaiKeyPool::new()),
+            sampling_state: Arc::new(RwLock::new(config.sampling_limits.clone())),
+            audio_state: Arc::new(RwLock::new(config.audio.clone())),
+            history_limit: Arc::new(AtomicUsize::new(config.max_history_messages.unwrap_or(0))),
+            stream_debug: Arc::new(AtomicBool::new(false)),
+            monitor: Arc::new(crate::proxy::monitor::ProxyMonitor::new(10, None)),
+            upstream: Arc::new(crate::proxy::upstream::client::UpstreamClient::new(
+                Some(config.upstream_proxy.clone()),
+                crate::proxy::upstream::client::UpstreamPoolConfig::from_proxy_config(config),
+            )),
+            tls: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reload_from_disk_updates_live_mapping() {
+        let dir = std::env::temp_dir().join(format!("ag_config_reload_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("gui_config.json");
+
+        let mut config = crate::models::AppConfig::new();
+        std::fs::write(&path, serde_json::to_string_pretty(&config).unwrap()).unwrap();
+        let server = test_server(&config.proxy);
+        assert!(server.get_mapping().await.get("gpt-4o").is_none());
+
+        // 模拟用户直接编辑磁盘上的配置文件
+        config
+            .proxy
+            .custom_mapping
+            .insert("gpt-4o".to_string(), "gemini-3-flash".into());
+        std::fs::write(&path, serde_json::to_string_pretty(&config).unwrap()).unwrap();
+
+        let reloaded = crate::modules::config::load_app_config_from(&path).unwrap();
+        server.apply_proxy_config(&reloaded.proxy).await;
+
+        assert_eq!(
+            server.get_mapping().await.get("gpt-4o").map(|t| t.primary().to_string()).as_deref(),
+            Some("gemini-3-flash")
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_apply_proxy_config_rebuilds_upstream_client() {
+        let mut config = crate::proxy::config::ProxyConfig::default();
+        let server = test_server(&config);
+        assert_eq!(server.upstream.pool_config().max_idle_per_host, 16);
+
+        config.upstream_pool_max_idle_per_host = 128;
+        config.upstream_pool_idle_timeout_secs = 15;
+        server.apply_proxy_config(&config).await;
+
+        let pool = server.upstream.pool_config();
+        assert_eq!(pool.max_idle_per_host, 128);
+        assert_eq!(pool.idle_timeout_secs, 15);
+    }
+
+    #[test]
+    fn test_stream_debug_toggle_is_runtime_only() {
+        let server = test_server(&crate::proxy::config::ProxyConfig::default());
+        assert!(!server.stream_debug());
+
+        server.set_stream_debug(true);
+        assert!(server.stream_debug());
+        server.set_stream_debug(false);
+        assert!(!server.stream_debug());
+    }
+}
@@ -65,6 +65,16 @@ impl SessionManager {
         sid
     }
 
+    /// 由 Claude 请求体 (JSON) 计算会话指纹，纯计算无副作用，用于排查会话粘性
+    ///
+    /// 与反代一致：Claude 处理器在净化 thinking 块、按 max_history_messages 截断历史之前，
+    /// 对刚解析出的原始请求计算指纹，因此这里同样直接对请求体计算
+    pub fn session_id_for_body(body: Value) -> Result<String, String> {
+        let request: ClaudeRequest =
+            serde_json::from_value(body).map_err(|e| format!("无法解析为 Claude 请求: {}", e))?;
+        Ok(Self::extract_session_id(&request))
+    }
+
     /// 根据 OpenAI 请求生成稳定的会话指纹
     pub fn extract_openai_session_id(request: &OpenAIRequest) -> String {
         let mut hasher = Sha256::new();
@@ -148,3 +158,59 @@ impl SessionManager {
         sid
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn body(latest: &str) -> Value {
+        json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [
+                { "role": "user", "content": "Refactor the session manager module please" },
+                { "role": "assistant", "content": "Sure, here is the plan." },
+                { "role": "user", "content": [{ "type": "text", "text": latest }] }
+            ]
+        })
+    }
+
+    #[test]
+    fn test_content_fingerprint_ignores_latest_user_message() {
+        let first = SessionManager::session_id_for_body(body("Now add tests for it")).unwrap();
+        let second = SessionManager::session_id_for_body(body("Actually rename the struct instead")).unwrap();
+        assert_eq!(first, second);
+        assert!(first.starts_with("sid-"));
+
+        // 不同的首条用户消息得到不同的会话
+        let mut other = body("Now add tests for it");
+        other["messages"][0]["content"] = json!("Write a completely different program");
+        assert_ne!(SessionManager::session_id_for_body(other).unwrap(), first);
+
+        assert!(SessionManager::session_id_for_body(json!({ "messages": [] })).is_err());
+    }
+
+    #[test]
+    fn test_body_fingerprint_matches_proxy_despite_history_truncation() {
+        let start = SessionManager::session_id_for_body(body("Now add tests for it")).unwrap();
+
+        // 会话增长到超过历史上限：指纹仍与会话开始时一致
+        let mut grown = body("Now add tests for it");
+        for i in 0..6 {
+            let role = if i % 2 == 0 { "assistant" } else { "user" };
+            grown["messages"].as_array_mut().unwrap().push(json!({
+                "role": role,
+                "content": format!("Follow-up message number {} in this session", i)
+            }));
+        }
+        let from_body = SessionManager::session_id_for_body(grown.clone()).unwrap();
+        assert_eq!(from_body, start);
+
+        // 反代在截断之前计算指纹；若在截断之后计算，锚点消息被丢弃，指纹就会漂移
+        let mut request: ClaudeRequest = serde_json::from_value(grown).unwrap();
+        assert_eq!(SessionManager::extract_session_id(&request), from_body);
+        let dropped = crate::proxy::mappers::claude::context_trim::truncate_history(&mut request.messages, 2);
+        assert!(dropped > 0);
+        assert_ne!(SessionManager::extract_session_id(&request), from_body);
+    }
+}
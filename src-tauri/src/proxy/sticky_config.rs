@@ -17,6 +17,22 @@ impl Default for SchedulingMode {
     }
 }
 
+/// 会话绑定的账号被限流时的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StickyRateLimitPolicy {
+    /// 立即解绑并切换到其他账号
+    Switch,
+    /// 限流剩余时间不超过 sticky_wait_max_seconds 时等待原账号恢复，否则切换
+    WaitIfShort,
+}
+
+impl Default for StickyRateLimitPolicy {
+    fn default() -> Self {
+        Self::Switch
+    }
+}
+
 /// 粘性会话配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StickySessionConfig {
@@ -24,6 +40,83 @@ pub struct StickySessionConfig {
     pub mode: SchedulingMode,
     /// 缓存优先模式下的最大等待时间 (秒)
     pub max_wait_seconds: u64,
+    /// 连续失败多少次后将账号标记为不健康并临时停用 (0 表示禁用)
+    #[serde(default = "default_unhealthy_failure_threshold")]
+    pub unhealthy_failure_threshold: u32,
+    /// 是否遵循客户端的 X-Priority 请求头 (bulk 请求优先使用 FREE 账号)
+    #[serde(default)]
+    pub priority_hints: bool,
+    /// 默认请求类型 (agent / web_search / image_gen)，用于覆盖自动推断的配额分组；为空时按模型推断
+    #[serde(default)]
+    pub default_request_type: Option<String>,
+    /// 绑定账号被限流时切换还是短暂等待
+    #[serde(default)]
+    pub sticky_rate_limit_policy: StickyRateLimitPolicy,
+    /// wait_if_short 策略下最多等待的秒数
+    #[serde(default = "default_sticky_wait_max_seconds")]
+    pub sticky_wait_max_seconds: u64,
+    /// 容量评分中各订阅等级的权重
+    #[serde(default)]
+    pub tier_weights: TierWeights,
+}
+
+/// 容量评分中各订阅等级的权重 (按其中的最大值归一化)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TierWeights {
+    pub ultra: f64,
+    pub pro: f64,
+    pub free: f64,
+    /// 未知等级 (尚未拉取过配额)
+    pub unknown: f64,
+}
+
+impl Default for TierWeights {
+    fn default() -> Self {
+        Self {
+            ultra: 1.0,
+            pro: 0.8,
+            free: 0.5,
+            unknown: 0.3,
+        }
+    }
+}
+
+impl TierWeights {
+    pub fn weight(&self, tier: Option<&str>) -> f64 {
+        match tier {
+            Some("ULTRA") => self.ultra,
+            Some("PRO") => self.pro,
+            Some("FREE") => self.free,
+            _ => self.unknown,
+        }
+    }
+
+    fn max(&self) -> f64 {
+        self.ultra.max(self.pro).max(self.free).max(self.unknown)
+    }
+}
+
+/// 配额未知时按剩余一半估算
+const UNKNOWN_QUOTA_PERCENT: f64 = 50.0;
+
+/// 账号容量评分 (0-100)：等级权重 (归一化) × 剩余配额百分比
+pub fn capacity_score(weights: &TierWeights, tier: Option<&str>, quota_percent: Option<i32>) -> u8 {
+    let max = weights.max();
+    if max <= 0.0 {
+        return 0;
+    }
+    let tier_factor = (weights.weight(tier).max(0.0) / max).min(1.0);
+    let quota = quota_percent.map_or(UNKNOWN_QUOTA_PERCENT, |p| p.clamp(0, 100) as f64);
+    (tier_factor * quota).round() as u8
+}
+
+fn default_unhealthy_failure_threshold() -> u32 {
+    3
+}
+
+fn default_sticky_wait_max_seconds() -> u64 {
+    5
 }
 
 impl Default for StickySessionConfig {
@@ -31,6 +124,12 @@ impl Default for StickySessionConfig {
         Self {
             mode: SchedulingMode::Balance,
             max_wait_seconds: 60,
+            unhealthy_failure_threshold: default_unhealthy_failure_threshold(),
+            priority_hints: false,
+            default_request_type: None,
+            sticky_rate_limit_policy: StickyRateLimitPolicy::Switch,
+            sticky_wait_max_seconds: default_sticky_wait_max_seconds(),
+            tier_weights: TierWeights::default(),
         }
     }
 }
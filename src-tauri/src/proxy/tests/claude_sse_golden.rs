@@ -0,0 +1,247 @@
+// Golden 文件测试：将录制的 Gemini SSE 片段送入真实的 create_claude_sse_stream，
+// 与期望的 Claude SSE 事件序列逐条比对
+//
+// 目录：src/proxy/tests/golden/claude_sse/
+// - <场景>.sse          Gemini 上游原始 SSE 行
+// - <场景>.golden.json  期望输出的 Claude 事件 ([{ "event": ..., "data": ... }])
+//
+// 有意修改转换逻辑后，使用 `UPDATE_GOLDENS=1 cargo test claude_sse_golden` 重新生成期望输出
+#[cfg(test)]
+mod tests {
+    use crate::proxy::mappers::claude::create_claude_sse_stream;
+    use bytes::Bytes;
+    use futures::StreamExt;
+    use serde_json::{json, Value};
+    use std::path::PathBuf;
+
+    /// 输入按固定大小切块，覆盖跨 chunk 的行拼接
+    const INPUT_CHUNK_BYTES: usize = 17;
+
+    fn golden_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/proxy/tests/golden/claude_sse")
+    }
+
+    fn update_goldens() -> bool {
+        std::env::var("UPDATE_GOLDENS").map(|v| v == "1").unwrap_or(false)
+    }
+
+    /// 以合成字节流驱动真实的流式转换，返回全部输出 (错误项记为 stream_error 事件)
+    async fn run_fixture(input: &[u8]) -> Vec<Result<Bytes, String>> {
+        let chunks: Vec<Result<Bytes, reqwest::Error>> = input
+            .chunks(INPUT_CHUNK_BYTES)
+            .map(|c| Ok(Bytes::copy_from_slice(c)))
+            .collect();
+        let stream = create_claude_sse_stream(
+            Box::pin(futures::stream::iter(chunks)),
+            "golden_trace".to_string(),
+            "golden@example.com".to_string(),
+            false,
+            false,
+            false,
+            Vec::new(),
+            false,
+        );
+        stream.collect().await
+    }
+
+    /// 解析 SSE 输出为事件列表，并归一化易变字段 (消息 ID、随机生成的工具调用 ID)
+    fn normalize_events(output: Vec<Result<Bytes, String>>) -> Vec<Value> {
+        let mut text = String::new();
+        let mut events = Vec::new();
+        for item in output {
+            match item {
+                Ok(bytes) => text.push_str(&String::from_utf8_lossy(&bytes)),
+                Err(e) => events.push(json!({ "event": "stream_error", "data": e })),
+            }
+        }
+
+        let mut parsed: Vec<Value> = text
+            .split("\n\n")
+            .filter(|block| !block.trim().is_empty())
+            .map(|block| {
+                let mut event = "";
+                let mut data = Value::Null;
+                for line in block.lines() {
+                    if let Some(e) = line.strip_prefix("event: ") {
+                        event = e;
+                    } else if let Some(d) = line.strip_prefix("data: ") {
+                        data = serde_json::from_str(d)
+                            .unwrap_or_else(|e| panic!("invalid SSE data JSON ({}): {}", e, d));
+                    }
+                }
+                normalize_data(&mut data);
+                json!({ "event": event, "data": data })
+            })
+            .collect();
+        parsed.extend(events);
+        parsed
+    }
+
+    fn normalize_data(data: &mut Value) {
+        if let Some(id) = data.pointer_mut("/message/id") {
+            *id = json!("<message_id>");
+        }
+        if let Some(block) = data.get_mut("content_block") {
+            if block["type"] == "tool_use" {
+                let name = block["name"].as_str().unwrap_or_default().to_string();
+                let generated_prefix = format!("{}-", name);
+                if let Some(id) = block.get_mut("id") {
+                    if id.as_str().is_some_and(|s| s.starts_with(&generated_prefix)) {
+                        *id = json!(format!("{}<random>", generated_prefix));
+                    }
+                }
+            }
+        }
+    }
+
+    /// 严格的 Anthropic SDK 要求：message_stop 至多一次且其后没有任何事件，带 stop_reason 的 message_delta 至多一次
+    fn assert_spec_clean(name: &str, events: &[Value]) {
+        let sse: Vec<&Value> = events.iter().filter(|e| e["event"] != "stream_error").collect();
+        let stops = sse.iter().filter(|e| e["event"] == "message_stop").count();
+        assert!(stops <= 1, "'{}' emitted {} message_stop events", name, stops);
+        if stops == 1 {
+            assert_eq!(sse.last().unwrap()["event"], "message_stop", "'{}' emitted events after message_stop", name);
+        }
+        let final_deltas = sse
+            .iter()
+            .filter(|e| e["event"] == "message_delta" && !e["data"]["delta"]["stop_reason"].is_null())
+            .count();
+        assert!(final_deltas <= 1, "'{}' emitted {} final message_delta events", name, final_deltas);
+    }
+
+    async fn check_golden(name: &str) {
+        let dir = golden_dir();
+        let input = std::fs::read(dir.join(format!("{}.sse", name)))
+            .unwrap_or_else(|e| panic!("missing fixture {}.sse: {}", name, e));
+        let events = normalize_events(run_fixture(&input).await);
+        assert_spec_clean(name, &events);
+        let actual = Value::Array(events);
+
+        let golden_path = dir.join(format!("{}.golden.json", name));
+        if update_goldens() {
+            let pretty = serde_json::to_string_pretty(&actual).unwrap();
+            std::fs::write(&golden_path, format!("{}\n", pretty)).unwrap();
+            return;
+        }
+
+        let expected: Value = serde_json::from_str(
+            &std::fs::read_to_string(&golden_path).unwrap_or_else(|e| {
+                panic!("missing golden {}.golden.json ({}), run with UPDATE_GOLDENS=1", name, e)
+            }),
+        )
+        .unwrap();
+
+        if actual != expected {
+            let (actual_events, expected_events) =
+                (actual.as_array().unwrap(), expected.as_array().unwrap());
+            let first_diff = actual_events
+                .iter()
+                .zip(expected_events.iter())
+                .position(|(a, e)| a != e)
+                .unwrap_or(actual_events.len().min(expected_events.len()));
+            panic!(
+                "golden mismatch for '{}' at event #{} (actual {} events, expected {})\n  actual:   {}\n  expected: {}\nrun with UPDATE_GOLDENS=1 if the change is intentional",
+                name,
+                first_diff,
+                actual_events.len(),
+                expected_events.len(),
+                actual_events.get(first_diff).map(|v| v.to_string()).unwrap_or_default(),
+                expected_events.get(first_diff).map(|v| v.to_string()).unwrap_or_default(),
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn golden_text_only() {
+        check_golden("text_only").await;
+    }
+
+    #[tokio::test]
+    async fn golden_thinking_with_signature() {
+        check_golden("thinking_with_signature").await;
+    }
+
+    #[tokio::test]
+    async fn golden_thinking_raw_signature_max_tokens() {
+        check_golden("thinking_raw_signature_max_tokens").await;
+    }
+
+    #[tokio::test]
+    async fn golden_trailing_signature() {
+        check_golden("trailing_signature").await;
+    }
+
+    #[tokio::test]
+    async fn golden_function_call() {
+        check_golden("function_call").await;
+    }
+
+    #[tokio::test]
+    async fn golden_parallel_function_calls() {
+        check_golden("parallel_function_calls").await;
+    }
+
+    #[tokio::test]
+    async fn golden_grounding_metadata() {
+        check_golden("grounding_metadata").await;
+    }
+
+    #[tokio::test]
+    async fn golden_usage_only_final_chunk() {
+        check_golden("usage_only_final_chunk").await;
+    }
+
+    #[tokio::test]
+    async fn golden_malformed_lines() {
+        check_golden("malformed_lines").await;
+    }
+
+    #[tokio::test]
+    async fn golden_force_stop_mid_thinking() {
+        check_golden("force_stop_mid_thinking").await;
+    }
+
+    #[tokio::test]
+    async fn golden_done_marker_without_finish() {
+        check_golden("done_marker_without_finish").await;
+    }
+
+    /// 结束后上游补发的用量并入最终 message_delta，重复的候选内容与 [DONE] 之后的事件被忽略
+    #[tokio::test]
+    async fn golden_post_stop_chunk() {
+        check_golden("post_stop_chunk").await;
+    }
+
+    /// 第二次 finishReason 不生效，但其携带的用量仍然并入
+    #[tokio::test]
+    async fn golden_double_finish() {
+        check_golden("double_finish").await;
+    }
+
+    /// 每个 fixture 都必须有对应的测试，避免新增场景后忘记接入
+    #[test]
+    fn every_fixture_is_wired() {
+        let wired = [
+            "text_only",
+            "thinking_with_signature",
+            "thinking_raw_signature_max_tokens",
+            "trailing_signature",
+            "function_call",
+            "parallel_function_calls",
+            "grounding_metadata",
+            "usage_only_final_chunk",
+            "malformed_lines",
+            "force_stop_mid_thinking",
+            "done_marker_without_finish",
+            "post_stop_chunk",
+            "double_finish",
+        ];
+        for entry in std::fs::read_dir(golden_dir()).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|e| e.to_str()) == Some("sse") {
+                let stem = path.file_stem().unwrap().to_str().unwrap().to_string();
+                assert!(wired.contains(&stem.as_str()), "fixture {} has no golden test", stem);
+            }
+        }
+    }
+}
@@ -0,0 +1,1061 @@
+// 端到端测试：基于模拟上游启动完整的 Axum 服务，驱动 Claude / OpenAI 协议的流式与非流式路径
+#[cfg(test)]
+mod tests {
+    use crate::proxy::config::{AccountIdentifierMode, MockUpstreamConfig, ProxyConfig, ResponseHeaderPolicy};
+    use crate::proxy::monitor::ProxyMonitor;
+    use crate::proxy::upstream::client::UpstreamPoolConfig;
+    use crate::proxy::{AxumServer, ProxySecurityConfig, TokenManager};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    const REPLY: &str = "Mock says hello to the end-to-end test";
+
+    struct TestServer {
+        base_url: String,
+        server: AxumServer,
+        handle: tokio::task::JoinHandle<()>,
+        token_manager: Arc<TokenManager>,
+        monitor: Arc<ProxyMonitor>,
+    }
+
+    impl TestServer {
+        async fn stop(self) {
+            self.server.stop();
+            let _ = self.handle.await;
+        }
+    }
+
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    async fn start_mock_server(status_script: Vec<u16>) -> TestServer {
+        start_mock_server_with_mode(status_script, AccountIdentifierMode::Email).await
+    }
+
+    async fn start_mock_server_with_mode(status_script: Vec<u16>, mode: AccountIdentifierMode) -> TestServer {
+        start_mock_server_with(status_script, mode, ResponseHeaderPolicy::Full, 1).await
+    }
+
+    async fn start_mock_server_with(
+        status_script: Vec<u16>,
+        mode: AccountIdentifierMode,
+        header_policy: ResponseHeaderPolicy,
+        accounts: usize,
+    ) -> TestServer {
+        let config = ProxyConfig {
+            response_header_policy: header_policy,
+            ..ProxyConfig::default()
+        };
+        start_mock_server_with_config(status_script, mode, config, accounts).await
+    }
+
+    async fn start_mock_server_with_config(
+        status_script: Vec<u16>,
+        mode: AccountIdentifierMode,
+        config: ProxyConfig,
+        accounts: usize,
+    ) -> TestServer {
+        let data_dir = std::env::temp_dir().join(format!("ag_mock_e2e_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(data_dir.join("accounts")).unwrap();
+        let tls = crate::proxy::tls::listener_tls(&config.tls, &data_dir).expect("tls config should load");
+        let scheme = if tls.as_ref().is_some_and(|t| t.https_port.is_none()) { "https" } else { "http" };
+
+        let token_manager = Arc::new(TokenManager::new(data_dir));
+        assert_eq!(token_manager.add_mock_accounts(accounts), accounts);
+        token_manager.set_account_identifier_mode(mode);
+
+        let monitor = Arc::new(ProxyMonitor::new(100, None));
+        let port = free_port();
+        let (server, handle) = AxumServer::start(
+            "127.0.0.1".to_string(),
+            port,
+            token_manager.clone(),
+            config.custom_mapping.clone(),
+            config.request_timeout,
+            config.upstream_proxy.clone(),
+            ProxySecurityConfig::from_proxy_config(&config),
+            config.zai.clone(),
+            monitor.clone(),
+            config.experimental.clone(),
+            config.sampling_limits.clone(),
+            config.max_history_messages,
+            MockUpstreamConfig {
+                enabled: true,
+                reply_text: REPLY.to_string(),
+                latency_ms: 5,
+                status_script,
+                first_chunk_delay_ms: config.mock_upstream.first_chunk_delay_ms,
+            },
+            UpstreamPoolConfig::from_proxy_config(&config),
+            config.audio.clone(),
+            tls,
+        )
+        .await
+        .expect("mock server should start");
+
+        TestServer {
+            base_url: format!("{}://127.0.0.1:{}", scheme, port),
+            server,
+            handle,
+            token_manager,
+            monitor,
+        }
+    }
+
+    fn sse_data_lines(body: &str) -> Vec<String> {
+        body.lines()
+            .filter_map(|l| l.strip_prefix("data:"))
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect()
+    }
+
+    fn claude_request(stream: bool) -> Value {
+        json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 256,
+            "stream": stream,
+            "messages": [{ "role": "user", "content": "Say hello" }]
+        })
+    }
+
+    fn openai_request(stream: bool) -> Value {
+        json!({
+            "model": "gpt-4o-mini",
+            "stream": stream,
+            "messages": [{ "role": "user", "content": "Say hello" }]
+        })
+    }
+
+    #[tokio::test]
+    async fn test_claude_messages_non_stream() {
+        let srv = start_mock_server(vec![]).await;
+        let resp = reqwest::Client::new()
+            .post(format!("{}/v1/messages", srv.base_url))
+            .json(&claude_request(false))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["type"], "message");
+        assert_eq!(body["role"], "assistant");
+        assert_eq!(body["content"][0]["type"], "text");
+        assert_eq!(body["content"][0]["text"], REPLY);
+        assert_eq!(body["stop_reason"], "end_turn");
+        assert!(body["usage"]["input_tokens"].as_u64().unwrap() > 0);
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_claude_messages_stream() {
+        let srv = start_mock_server(vec![]).await;
+        let resp = reqwest::Client::new()
+            .post(format!("{}/v1/messages", srv.base_url))
+            .json(&claude_request(true))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), 200);
+        let text = resp.text().await.unwrap();
+
+        let events: Vec<Value> = sse_data_lines(&text)
+            .iter()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect();
+        let types: Vec<&str> = events.iter().filter_map(|e| e["type"].as_str()).collect();
+
+        assert_eq!(types.first(), Some(&"message_start"));
+        assert_eq!(types.last(), Some(&"message_stop"));
+        assert!(types.contains(&"content_block_start"));
+        assert!(types.contains(&"content_block_stop"));
+
+        let streamed: String = events
+            .iter()
+            .filter(|e| e["type"] == "content_block_delta")
+            .filter_map(|e| e["delta"]["text"].as_str())
+            .collect();
+        assert_eq!(streamed, REPLY);
+
+        let message_delta = events.iter().find(|e| e["type"] == "message_delta").unwrap();
+        assert_eq!(message_delta["delta"]["stop_reason"], "end_turn");
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_openai_chat_completions_non_stream() {
+        let srv = start_mock_server(vec![]).await;
+        let resp = reqwest::Client::new()
+            .post(format!("{}/v1/chat/completions", srv.base_url))
+            .json(&openai_request(false))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["object"], "chat.completion");
+        assert_eq!(body["choices"][0]["message"]["role"], "assistant");
+        assert_eq!(body["choices"][0]["message"]["content"], REPLY);
+        assert_eq!(body["choices"][0]["finish_reason"], "stop");
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_openai_chat_completions_stream() {
+        let srv = start_mock_server(vec![]).await;
+        let resp = reqwest::Client::new()
+            .post(format!("{}/v1/chat/completions", srv.base_url))
+            .json(&openai_request(true))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), 200);
+        let text = resp.text().await.unwrap();
+        let lines = sse_data_lines(&text);
+        assert_eq!(lines.last().map(String::as_str), Some("[DONE]"));
+
+        let chunks: Vec<Value> = lines
+            .iter()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect();
+        assert!(chunks.iter().all(|c| c["object"] == "chat.completion.chunk"));
+
+        let streamed: String = chunks
+            .iter()
+            .filter_map(|c| c["choices"][0]["delta"]["content"].as_str())
+            .collect();
+        assert_eq!(streamed, REPLY);
+        assert!(chunks
+            .iter()
+            .any(|c| c["choices"][0]["finish_reason"] == "stop"));
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_openai_n_returns_multiple_choices() {
+        let srv = start_mock_server(vec![]).await;
+        let client = reqwest::Client::new();
+
+        // Gemini 模型：candidateCount=2，模拟上游返回两个候选
+        let mut req = openai_request(false);
+        req["n"] = json!(2);
+        let resp = client
+            .post(format!("{}/v1/chat/completions", srv.base_url))
+            .json(&req)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: Value = resp.json().await.unwrap();
+        let choices = body["choices"].as_array().unwrap();
+        assert_eq!(choices.len(), 2);
+        assert_eq!(choices[0]["index"], 0);
+        assert_eq!(choices[1]["index"], 1);
+        assert!(choices.iter().all(|c| c["message"]["content"] == REPLY));
+
+        // 超过 max_completions 上限时拒绝
+        req["n"] = json!(5);
+        let resp = client
+            .post(format!("{}/v1/chat/completions", srv.base_url))
+            .json(&req)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status().as_u16(), 400);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["error"]["param"], "n");
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_openai_n_fans_out_when_candidate_count_unsupported() {
+        let srv = start_mock_server(vec![]).await;
+        let mut req = openai_request(true);
+        req["model"] = json!("claude-sonnet-4-5");
+        req["n"] = json!(2);
+        let resp = reqwest::Client::new()
+            .post(format!("{}/v1/chat/completions", srv.base_url))
+            .json(&req)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), 200);
+        let lines = sse_data_lines(&resp.text().await.unwrap());
+        assert_eq!(lines.iter().filter(|l| l.as_str() == "[DONE]").count(), 1);
+        assert_eq!(lines.last().map(String::as_str), Some("[DONE]"));
+
+        let chunks: Vec<Value> = lines.iter().filter_map(|l| serde_json::from_str(l).ok()).collect();
+        for index in 0..2u64 {
+            let streamed: String = chunks
+                .iter()
+                .filter(|c| c["choices"][0]["index"] == index)
+                .filter_map(|c| c["choices"][0]["delta"]["content"].as_str())
+                .collect();
+            assert_eq!(streamed, REPLY, "choice {}", index);
+        }
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_scripted_server_error_is_retried() {
+        // 第一次上游调用返回 500，重试机制应在第二次调用拿到成功响应
+        let srv = start_mock_server(vec![500]).await;
+        let resp = reqwest::Client::new()
+            .post(format!("{}/v1/messages", srv.base_url))
+            .json(&claude_request(false))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["content"][0]["text"], REPLY);
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_deadline_header_stops_retry_loop() {
+        // 三个账号 + 持续 503：不设截止时间时退避合计 1s + 2s + 4s
+        let srv = start_mock_server_with(vec![503; 3], AccountIdentifierMode::Email, ResponseHeaderPolicy::Full, 3).await;
+        let started = std::time::Instant::now();
+        let resp = reqwest::Client::new()
+            .post(format!("{}/v1/messages", srv.base_url))
+            .header("X-Max-Duration-Ms", "300")
+            .json(&claude_request(false))
+            .send()
+            .await
+            .unwrap();
+
+        // 首次退避 (1s) 已超出截止时间：不再等待，以 504 结束并附带最后一次上游错误
+        assert!(started.elapsed() < std::time::Duration::from_millis(900));
+        assert_eq!(resp.status().as_u16(), 504);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["error"]["type"], "api_error");
+        let message = body["error"]["message"].as_str().unwrap();
+        assert!(message.contains("deadline of 300ms"));
+        assert!(message.contains("HTTP 503"));
+
+        srv.stop().await;
+    }
+
+    /// 收集日志输出的 writer
+    #[derive(Clone, Default)]
+    struct LogCapture(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogCapture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hash_identifier_mode_hides_email() {
+        use crate::proxy::common::account_identity::hash_email;
+        use crate::proxy::upstream::mock::MOCK_ACCOUNT_EMAIL;
+
+        let capture = LogCapture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::INFO)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let srv = start_mock_server_with_mode(vec![], AccountIdentifierMode::Hash).await;
+        for stream in [false, true] {
+            let resp = reqwest::Client::new()
+                .post(format!("{}/v1/messages", srv.base_url))
+                .json(&claude_request(stream))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status().as_u16(), 200);
+            assert!(resp.headers().get("X-Account-Email").is_none());
+            assert_eq!(
+                resp.headers().get("X-Account-Id").and_then(|v| v.to_str().ok()),
+                Some(hash_email(MOCK_ACCOUNT_EMAIL).as_str())
+            );
+            let _ = resp.text().await.unwrap();
+        }
+        srv.stop().await;
+
+        let logs = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains(&format!("Using account: {}", hash_email(MOCK_ACCOUNT_EMAIL))));
+        assert!(!logs.contains(MOCK_ACCOUNT_EMAIL));
+    }
+
+    #[tokio::test]
+    async fn test_response_header_policy_modes() {
+        use crate::proxy::middleware::response_headers::mask_email;
+        use crate::proxy::upstream::mock::MOCK_ACCOUNT_EMAIL;
+
+        for policy in [ResponseHeaderPolicy::Full, ResponseHeaderPolicy::Masked, ResponseHeaderPolicy::Minimal] {
+            // 最后一次请求命中脚本中的 404，覆盖错误响应路径
+            let srv = start_mock_server_with(vec![200, 200, 200, 404], AccountIdentifierMode::Email, policy, 1).await;
+            let requests = [
+                ("/v1/messages", claude_request(false)),
+                ("/v1/messages", claude_request(true)),
+                ("/v1/chat/completions", openai_request(false)),
+                ("/v1/chat/completions", openai_request(false)),
+            ];
+            for (path, body) in requests {
+                let resp = reqwest::Client::new()
+                    .post(format!("{}{}", srv.base_url, path))
+                    .json(&body)
+                    .send()
+                    .await
+                    .unwrap();
+                let email = resp.headers().get("X-Account-Email").and_then(|v| v.to_str().ok()).map(String::from);
+                let mapped = resp.headers().get("X-Mapped-Model").is_some();
+                match policy {
+                    ResponseHeaderPolicy::Full => {
+                        assert_eq!(email.as_deref(), Some(MOCK_ACCOUNT_EMAIL));
+                    }
+                    ResponseHeaderPolicy::Masked => {
+                        assert_eq!(email, Some(mask_email(MOCK_ACCOUNT_EMAIL)));
+                    }
+                    ResponseHeaderPolicy::Minimal => {
+                        assert!(email.is_none());
+                        assert!(resp.headers().get("X-Account-Id").is_none());
+                        assert!(!mapped);
+                    }
+                }
+                let _ = resp.text().await.unwrap();
+            }
+            srv.stop().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_account_exposure_disabled_on_all_response_paths() {
+        use crate::proxy::config::ResponseHeadersConfig;
+
+        let config = ProxyConfig {
+            response_headers: ResponseHeadersConfig {
+                expose_account: false,
+                ..ResponseHeadersConfig::default()
+            },
+            ..ProxyConfig::default()
+        };
+        // 最后一次请求命中脚本中的 404，覆盖错误响应路径
+        let srv = start_mock_server_with_config(vec![200, 200, 200, 200, 404], AccountIdentifierMode::Email, config, 1).await;
+        let requests = [
+            ("/v1/messages", claude_request(false)),
+            ("/v1/messages", claude_request(true)),
+            ("/v1/chat/completions", openai_request(false)),
+            ("/v1/chat/completions", openai_request(true)),
+            ("/v1/messages", claude_request(false)),
+        ];
+        for (index, (path, body)) in requests.into_iter().enumerate() {
+            let resp = reqwest::Client::new()
+                .post(format!("{}{}", srv.base_url, path))
+                .json(&body)
+                .send()
+                .await
+                .unwrap();
+            if index == 4 {
+                assert!(!resp.status().is_success());
+            }
+            assert!(resp.headers().get("X-Account-Email").is_none(), "{} #{}", path, index);
+            assert!(resp.headers().get("X-Account-Id").is_none());
+            // 模型头不受影响
+            assert!(resp.headers().get("X-Mapped-Model").is_some() || index == 4);
+            let _ = resp.text().await.unwrap();
+        }
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_gated_by_config() {
+        use crate::proxy::config::{MetricsAccess, MetricsConfig};
+
+        let srv = start_mock_server(vec![]).await;
+        let client = reqwest::Client::new();
+
+        // 默认关闭
+        let resp = client.get(format!("{}/metrics", srv.base_url)).send().await.unwrap();
+        assert_eq!(resp.status(), 404);
+
+        let mut config = ProxyConfig {
+            metrics: MetricsConfig { enabled: true, access: MetricsAccess::Localhost, per_account_labels: false },
+            ..ProxyConfig::default()
+        };
+        srv.server.update_security(&config).await;
+
+        let resp = client
+            .post(format!("{}/v1/messages", srv.base_url))
+            .json(&claude_request(false))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        let _ = resp.text().await.unwrap();
+
+        let resp = client.get(format!("{}/metrics", srv.base_url)).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let body = resp.text().await.unwrap();
+        assert!(body.contains("# TYPE antigravity_requests_total counter"));
+        assert!(body.contains("handler=\"claude\""));
+        assert!(body.contains("antigravity_accounts_loaded 1"));
+
+        // 鉴权模式下即使全局鉴权关闭也需要 API Key
+        config.metrics.access = MetricsAccess::Auth;
+        srv.server.update_security(&config).await;
+        let resp = client.get(format!("{}/metrics", srv.base_url)).send().await.unwrap();
+        assert_eq!(resp.status(), 401);
+        let resp = client
+            .get(format!("{}/metrics", srv.base_url))
+            .bearer_auth(&config.api_key)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_openai_accepts_gzip_bom_and_charset() {
+        use std::io::Write;
+
+        let srv = start_mock_server(vec![]).await;
+        let mut raw = vec![0xEF, 0xBB, 0xBF];
+        raw.extend_from_slice(openai_request(false).to_string().as_bytes());
+        let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(&raw).unwrap();
+
+        let resp = reqwest::Client::new()
+            .post(format!("{}/v1/chat/completions", srv.base_url))
+            .header("Content-Type", "application/json; charset=UTF-8")
+            .header("Content-Encoding", "gzip")
+            .body(enc.finish().unwrap())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status().as_u16(), 200);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["choices"][0]["message"]["content"], REPLY);
+
+        let resp = reqwest::Client::new()
+            .post(format!("{}/v1/messages", srv.base_url))
+            .header("Content-Type", "application/json")
+            .body("{\"model\": ")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status().as_u16(), 400);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_self_test_reports_metrics() {
+        use crate::proxy::self_test::run_self_test;
+        use crate::proxy::tls::LoopbackEndpoint;
+
+        let srv = start_mock_server_with(vec![], AccountIdentifierMode::Email, ResponseHeaderPolicy::Full, 2).await;
+        let endpoint = LoopbackEndpoint::plain(&srv.base_url);
+        let report = run_self_test(&endpoint, "", 4, 12, "ping").await.unwrap();
+
+        assert_eq!(report.requests, 12);
+        assert_eq!(report.succeeded, 12);
+        assert_eq!(report.failed, 0);
+        assert_eq!(report.error_rate, 0.0);
+        assert!(report.throughput_rps > 0.0);
+        // 本地模拟上游可能在 1ms 内完成，只校验分位数的相对关系
+        assert!(report.p95_ms >= report.p50_ms);
+        assert_eq!(report.per_account.values().sum::<usize>(), 12);
+        assert!(report.per_account.keys().all(|k| k.ends_with("@antigravity.local")));
+
+        assert!(run_self_test(&endpoint, "", 0, 1, "ping").await.is_err());
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_self_test_over_https_only_primary_port() {
+        use crate::proxy::self_test::run_self_test;
+
+        let config = ProxyConfig {
+            tls: crate::proxy::config::ProxyTlsConfig {
+                enabled: true,
+                self_signed: true,
+                ..Default::default()
+            },
+            ..ProxyConfig::default()
+        };
+        let srv = start_mock_server_with_config(vec![], AccountIdentifierMode::Email, config, 1).await;
+        let port: u16 = srv.base_url.rsplit(':').next().unwrap().parse().unwrap();
+
+        // 主端口只接受 HTTPS：回环入口切换到 https 并信任反代自己的自签名证书
+        let endpoint = srv.server.loopback_endpoint(port);
+        assert_eq!(endpoint.base_url, srv.base_url);
+        assert!(endpoint.trusted_cert.is_some());
+        let report = run_self_test(&endpoint, "", 2, 2, "ping").await.unwrap();
+        assert_eq!(report.succeeded, 2, "first error: {:?}", report.first_error);
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_heartbeats_reach_client_while_upstream_is_silent() {
+        let mut config = ProxyConfig::default();
+        config.experimental.stream_heartbeat_seconds = 1;
+        config.mock_upstream.first_chunk_delay_ms = 2500;
+        let srv = start_mock_server_with_config(vec![], AccountIdentifierMode::Email, config, 1).await;
+        let client = reqwest::Client::new();
+
+        for (path, body, heartbeat, first_content) in [
+            ("/v1/chat/completions", openai_request(true), ": keepalive", "data: {"),
+            ("/v1/messages", claude_request(true), "event: ping", "event: content_block_start"),
+        ] {
+            let started = std::time::Instant::now();
+            let resp = client.post(format!("{}{}", srv.base_url, path)).json(&body).send().await.unwrap();
+            // 上游首个分片到达之前响应头就已发出
+            assert_eq!(resp.status().as_u16(), 200);
+            assert!(started.elapsed() < std::time::Duration::from_millis(2000), "{} waited for upstream", path);
+
+            let text = resp.text().await.unwrap();
+            let content_start = text.find(first_content).unwrap();
+            assert!(text[..content_start].contains(heartbeat), "{} sent no heartbeat: {:?}", path, text);
+            assert!(!text[content_start..].contains(heartbeat));
+        }
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_last_resort_probe_recovers_locked_account() {
+        use crate::proxy::config::LastResortProbeConfig;
+        use crate::proxy::monitor::ProbeStats;
+        use crate::proxy::upstream::mock::MOCK_ACCOUNT_ID;
+
+        let mut config = ProxyConfig::default();
+        config.experimental.last_resort_probe = LastResortProbeConfig {
+            enabled: true,
+            min_interval_seconds: 3600,
+            ..LastResortProbeConfig::default()
+        };
+        let srv = start_mock_server_with_config(vec![], AccountIdentifierMode::Email, config, 1).await;
+        // 悲观锁定 (例如 5xx 突发后)：账号实际可用
+        let lock = || srv.token_manager.mark_rate_limited(MOCK_ACCOUNT_ID, 429, Some("3600"), "");
+        lock();
+        assert!(srv.token_manager.is_rate_limited(MOCK_ACCOUNT_ID));
+
+        let client = reqwest::Client::new();
+        let resp = client
+            .post(format!("{}/v1/messages", srv.base_url))
+            .json(&claude_request(false))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status().as_u16(), 200);
+        assert!(!srv.token_manager.is_rate_limited(MOCK_ACCOUNT_ID));
+        assert_eq!(srv.monitor.probe_stats(), ProbeStats { recovered: 1, failed: 0, throttled: 0 });
+
+        // 限频窗口内再次耗尽：不再探测，照常返回无可用账号
+        lock();
+        let resp = client
+            .post(format!("{}/v1/messages", srv.base_url))
+            .json(&claude_request(false))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status().as_u16(), 503);
+        assert!(srv.token_manager.is_rate_limited(MOCK_ACCOUNT_ID));
+        assert_eq!(srv.monitor.probe_stats().throttled, 1);
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_admin_scheduling_endpoint_reflects_live_config() {
+        use crate::proxy::server::SchedulingStatus;
+        use crate::proxy::sticky_config::SchedulingMode;
+
+        let srv = start_mock_server_with(vec![], AccountIdentifierMode::Email, ResponseHeaderPolicy::Full, 3).await;
+        let config = ProxyConfig::default();
+        srv.server.update_security(&config).await;
+        let url = format!("{}/admin/scheduling", srv.base_url);
+        let client = reqwest::Client::new();
+
+        // 全局鉴权关闭时也需要 API Key
+        assert_eq!(client.get(&url).send().await.unwrap().status(), 401);
+
+        // 与 update_proxy_scheduling_config 相同的更新路径
+        let mut scheduling = srv.token_manager.get_sticky_config().await;
+        scheduling.mode = SchedulingMode::PerformanceFirst;
+        scheduling.max_wait_seconds = 17;
+        srv.token_manager.update_sticky_config(scheduling).await;
+
+        let resp = client.get(&url).bearer_auth(&config.api_key).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["mode"], "PerformanceFirst");
+        assert_eq!(body["pool_size"], 3);
+        assert_eq!(body["config"]["max_wait_seconds"], 17);
+        // config 部分与命令返回的结构一致
+        let status: SchedulingStatus = serde_json::from_value(body.clone()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&status.config).unwrap(),
+            serde_json::to_value(srv.token_manager.get_sticky_config().await).unwrap()
+        );
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_upstream_region_header_validated_and_recorded() {
+        use crate::proxy::config::{MetricsAccess, MetricsConfig, UpstreamRegionsConfig};
+
+        let mut config = ProxyConfig {
+            metrics: MetricsConfig { enabled: true, access: MetricsAccess::Localhost, per_account_labels: false },
+            ..ProxyConfig::default()
+        };
+        config.experimental.upstream_regions = UpstreamRegionsConfig {
+            enabled: true,
+            regions: [
+                ("prod".to_string(), "https://cloudcode-pa.googleapis.com/v1internal".to_string()),
+                ("daily".to_string(), "https://daily-cloudcode-pa.sandbox.googleapis.com/v1internal".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let srv = start_mock_server_with_config(vec![], AccountIdentifierMode::Email, config, 1).await;
+        let client = reqwest::Client::new();
+
+        // 未知区域：400 并列出可用区域
+        let resp = client
+            .post(format!("{}/v1/messages", srv.base_url))
+            .header("X-Upstream-Region", "eu-west")
+            .json(&claude_request(false))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 400);
+        let body: Value = resp.json().await.unwrap();
+        let message = body["error"]["message"].as_str().unwrap();
+        assert!(message.contains("eu-west"));
+        assert!(message.contains("[daily, prod]"));
+
+        // 已知区域：响应回显区域并计入区域延迟直方图
+        let resp = client
+            .post(format!("{}/v1/chat/completions", srv.base_url))
+            .header("X-Upstream-Region", "daily")
+            .json(&openai_request(false))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("X-Upstream-Region").unwrap(), "daily");
+        let _ = resp.text().await.unwrap();
+
+        let metrics = client.get(format!("{}/metrics", srv.base_url)).send().await.unwrap().text().await.unwrap();
+        assert!(metrics.contains("antigravity_upstream_region_duration_seconds_count{region=\"daily\"} 1"));
+        assert!(!metrics.contains("region=\"eu-west\""));
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_failed_zai_forward_falls_back_to_google_pool() {
+        use crate::proxy::config::ZaiConfig;
+        use crate::proxy::ZaiDispatchMode;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // 始终返回 500 的 z.ai 上游
+        let zai_hits = Arc::new(AtomicUsize::new(0));
+        let hits = zai_hits.clone();
+        let app = axum::Router::new().fallback(move || {
+            let hits = hits.clone();
+            async move {
+                hits.fetch_add(1, Ordering::SeqCst);
+                (axum::http::StatusCode::INTERNAL_SERVER_ERROR, "z.ai down")
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let zai_addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let config = ProxyConfig {
+            zai: ZaiConfig {
+                enabled: true,
+                base_url: format!("http://{}", zai_addr),
+                api_key: "zai-test-key".to_string(),
+                dispatch_mode: ZaiDispatchMode::Pooled,
+                failure_fallback_to_google: true,
+                ..ZaiConfig::default()
+            },
+            ..ProxyConfig::default()
+        };
+        let srv = start_mock_server_with_config(vec![], AccountIdentifierMode::Email, config, 1).await;
+
+        // 1 个 Google 账号 + 1 个 z.ai Key：首个请求落在 z.ai 槽位
+        let resp = reqwest::Client::new()
+            .post(format!("{}/v1/messages", srv.base_url))
+            .json(&claude_request(false))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        let body: Value = resp.json().await.unwrap();
+        assert_eq!(body["content"][0]["text"], REPLY);
+        assert_eq!(zai_hits.load(Ordering::SeqCst), 1);
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_https_listener_alongside_http_and_cert_hot_reload() {
+        let https_port = free_port();
+        let config = ProxyConfig {
+            tls: crate::proxy::config::ProxyTlsConfig {
+                enabled: true,
+                self_signed: true,
+                https_port: Some(https_port),
+                ..Default::default()
+            },
+            ..ProxyConfig::default()
+        };
+        let srv = start_mock_server_with_config(vec![], AccountIdentifierMode::Email, config, 2).await;
+        let https_url = format!("https://127.0.0.1:{}/healthz", https_port);
+        let client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
+
+        // 迁移期间 HTTP 与 HTTPS 同时可用
+        let resp = client.get(format!("{}/healthz", srv.base_url)).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let resp = client.get(&https_url).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+
+        // 主端口仍为明文 HTTP 时，回环入口优先使用它
+        let port: u16 = srv.base_url.rsplit(':').next().unwrap().parse().unwrap();
+        let endpoint = srv.server.loopback_endpoint(port);
+        assert_eq!(endpoint.base_url, srv.base_url);
+        assert!(endpoint.trusted_cert.is_none());
+
+        let before = srv.server.tls_status().expect("tls should be running");
+        assert_eq!(before.https_port, https_port);
+        assert!(before.self_signed);
+
+        // 热替换证书：指纹更新，服务与号池不受影响
+        let dir = std::env::temp_dir().join(format!("ag_mock_e2e_tls_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let generated = rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+        let cert_path = dir.join("rotated.crt");
+        let key_path = dir.join("rotated.key");
+        std::fs::write(&cert_path, generated.cert.pem()).unwrap();
+        std::fs::write(&key_path, generated.key_pair.serialize_pem()).unwrap();
+        let rotated = crate::proxy::tls::load_cert(
+            &crate::proxy::config::ProxyTlsConfig {
+                enabled: true,
+                cert_path: Some(cert_path.display().to_string()),
+                key_path: Some(key_path.display().to_string()),
+                ..Default::default()
+            },
+            &dir,
+        )
+        .unwrap();
+        let after = srv.server.reload_tls(rotated).unwrap();
+        assert_ne!(after.fingerprint_sha256, before.fingerprint_sha256);
+        assert!(!after.self_signed);
+
+        let resp = client.get(&https_url).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(srv.token_manager.len(), 2);
+
+        let _ = std::fs::remove_dir_all(&dir);
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_route_group_auth_modes_and_unmatched_path_fallback() {
+        use crate::proxy::config::RouteAuthConfig;
+        use crate::proxy::ProxyAuthMode;
+
+        // 全局关闭，仅 Claude 严格、Gemini 原生除健康检查外鉴权
+        let mut config = ProxyConfig {
+            auth_mode: ProxyAuthMode::Off,
+            route_auth: RouteAuthConfig {
+                claude: Some(ProxyAuthMode::Strict),
+                gemini_native: Some(ProxyAuthMode::AllExceptHealth),
+                ..Default::default()
+            },
+            ..ProxyConfig::default()
+        };
+        let srv = start_mock_server_with_config(vec![], AccountIdentifierMode::Email, config.clone(), 1).await;
+        let client = reqwest::Client::new();
+        let get = |path: &str, key: Option<&str>| {
+            let mut req = client.get(format!("{}{}", srv.base_url, path));
+            if let Some(key) = key {
+                req = req.bearer_auth(key);
+            }
+            req.send()
+        };
+        let detect = |key: Option<&str>| {
+            let mut req = client
+                .post(format!("{}/v1/models/detect", srv.base_url))
+                .json(&json!({ "model": "gpt-4o-mini" }));
+            if let Some(key) = key {
+                req = req.bearer_auth(key);
+            }
+            req.send()
+        };
+        let key = config.api_key.clone();
+
+        assert_eq!(get("/v1/models", None).await.unwrap().status(), 200);
+        assert_eq!(get("/healthz", None).await.unwrap().status(), 200);
+        assert_eq!(get("/v1/models/claude", None).await.unwrap().status(), 401);
+        assert_eq!(get("/v1/models/claude", Some(key.as_str())).await.unwrap().status(), 200);
+        assert_eq!(get("/v1beta/models", None).await.unwrap().status(), 401);
+        assert_eq!(get("/v1beta/models", Some(key.as_str())).await.unwrap().status(), 200);
+        // 未归入分组的路径按最严格的已配置模式鉴权，而不是跟随全局 Off 开放
+        assert_eq!(detect(None).await.unwrap().status(), 401);
+        assert_ne!(detect(Some(key.as_str())).await.unwrap().status(), 401);
+
+        let modes = srv.server.effective_auth_modes().await;
+        assert_eq!(modes.claude, ProxyAuthMode::Strict);
+        assert_eq!(modes.openai, ProxyAuthMode::Off);
+        assert_eq!(modes.other, ProxyAuthMode::Strict);
+
+        // 热更新：OpenAI 改为严格、健康检查改为严格，撤销 Claude 覆盖
+        config.route_auth = RouteAuthConfig {
+            openai: Some(ProxyAuthMode::Strict),
+            status: Some(ProxyAuthMode::Strict),
+            ..Default::default()
+        };
+        srv.server.update_security(&config).await;
+        assert_eq!(get("/v1/models", None).await.unwrap().status(), 401);
+        assert_eq!(get("/healthz", None).await.unwrap().status(), 401);
+        assert_eq!(get("/healthz", Some(key.as_str())).await.unwrap().status(), 200);
+        assert_eq!(get("/v1/models/claude", None).await.unwrap().status(), 200);
+        assert_eq!(get("/v1beta/models", None).await.unwrap().status(), 200);
+        assert_eq!(detect(None).await.unwrap().status(), 401);
+
+        // 没有任何分组覆盖时，未归入分组的路径与全局模式一致
+        config.route_auth = RouteAuthConfig::default();
+        srv.server.update_security(&config).await;
+        assert_ne!(detect(None).await.unwrap().status(), 401);
+
+        srv.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_setup_page_generates_scoped_client_key() {
+        use crate::proxy::config::RouteGroup;
+        use crate::proxy::ProxyAuthMode;
+
+        let mut config = ProxyConfig {
+            auth_mode: ProxyAuthMode::Strict,
+            ..ProxyConfig::default()
+        };
+        let srv = start_mock_server_with_config(vec![], AccountIdentifierMode::Email, config.clone(), 1).await;
+        let client = reqwest::Client::new();
+        let main_key = config.api_key.clone();
+        let setup_url = format!("{}/setup", srv.base_url);
+
+        // 未开启时页面不存在
+        let resp = client.get(&setup_url).basic_auth("", Some(&main_key)).send().await.unwrap();
+        assert_eq!(resp.status(), 404);
+
+        config.enable_setup_page = true;
+        srv.server.update_security(&config).await;
+
+        let resp = client.get(&setup_url).send().await.unwrap();
+        assert_eq!(resp.status(), 401);
+        assert!(resp.headers().get("www-authenticate").unwrap().to_str().unwrap().starts_with("Basic "));
+
+        let resp = client.get(&setup_url).basic_auth("", Some(&main_key)).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let html = resp.text().await.unwrap();
+        assert!(html.contains("claude-sonnet-4-5"));
+        assert!(!html.contains(&main_key));
+
+        let resp = client
+            .post(format!("{}/keys", setup_url))
+            .bearer_auth(&main_key)
+            .json(&json!({
+                "label": "phone",
+                "scopes": ["claude"],
+                "default_model": "claude-sonnet-4-5",
+                "base_url": srv.base_url
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        let body: Value = resp.json().await.unwrap();
+        let new_key = body["api_key"].as_str().unwrap().to_string();
+        assert_ne!(new_key, main_key);
+        assert_eq!(body["setup"]["api_key"], new_key.as_str());
+        assert_eq!(body["setup"]["default_model"], "claude-sonnet-4-5");
+        assert!(body["qr_svg"].as_str().unwrap().contains("<svg"));
+
+        // 新 Key 只能访问所选分组，也不能访问引导页
+        let resp = client
+            .post(format!("{}/v1/messages", srv.base_url))
+            .bearer_auth(&new_key)
+            .json(&claude_request(false))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        let resp = client
+            .post(format!("{}/v1/chat/completions", srv.base_url))
+            .bearer_auth(&new_key)
+            .json(&openai_request(false))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 401);
+        let resp = client.get(&setup_url).basic_auth("", Some(&new_key)).send().await.unwrap();
+        assert_eq!(resp.status(), 401);
+
+        // 配置文件中只保存哈希
+        let saved = std::fs::read_to_string(srv.token_manager.data_dir().join("gui_config.json")).unwrap();
+        assert!(!saved.contains(&new_key));
+        let saved = crate::modules::config::load_app_config_from(
+            &srv.token_manager.data_dir().join("gui_config.json"),
+        )
+        .unwrap();
+        assert_eq!(saved.proxy.client_keys.len(), 1);
+        assert_eq!(saved.proxy.client_keys[0].label, "phone");
+        assert_eq!(saved.proxy.client_keys[0].scopes, vec![RouteGroup::Claude]);
+        assert_eq!(
+            saved.proxy.client_keys[0].key_sha256,
+            crate::proxy::security::hash_client_key(&new_key)
+        );
+
+        srv.stop().await;
+    }
+}
@@ -1 +1,3 @@
 pub mod comprehensive;
+pub mod mock_upstream_e2e;
+pub mod claude_sse_golden;
@@ -0,0 +1,340 @@
+// 反代监听的 TLS (HTTPS) 支持
+// 证书来自用户提供的 PEM 文件，或 self_signed 模式下自动生成并保存在数据目录的自签名证书。
+// 握手时经由 ReloadableCertResolver 读取当前证书，reload_proxy_tls 替换证书无需重启服务 (号池状态不受影响)。
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::proxy::config::ProxyTlsConfig;
+
+/// 自签名证书在数据目录下的保存位置
+const SELF_SIGNED_DIR: &str = "proxy_tls";
+const SELF_SIGNED_CERT_FILE: &str = "cert.pem";
+const SELF_SIGNED_KEY_FILE: &str = "key.pem";
+
+/// 证书加载错误 (文件缺失 / 解析失败 / 密钥不匹配分别报告)
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum TlsLoadError {
+    #[error("TLS 已启用但未配置 cert_path / key_path (或启用 self_signed)")]
+    NotConfigured,
+    #[error("{kind}文件不存在: {path}")]
+    FileMissing { kind: &'static str, path: String },
+    #[error("读取{kind}文件失败 ({path}): {message}")]
+    Read { kind: &'static str, path: String, message: String },
+    #[error("{kind}解析失败 ({path}): {message}")]
+    Parse { kind: &'static str, path: String, message: String },
+    #[error("证书与私钥不匹配 (证书: {cert_path}, 私钥: {key_path})")]
+    KeyMismatch { cert_path: String, key_path: String },
+    #[error("生成自签名证书失败: {0}")]
+    SelfSigned(String),
+}
+
+/// 已加载的证书
+#[derive(Debug, Clone)]
+pub struct LoadedCert {
+    pub key: Arc<CertifiedKey>,
+    /// 叶子证书 DER 的 SHA-256 指纹 (大写十六进制，冒号分隔)，供客户端固定证书
+    pub fingerprint_sha256: String,
+    pub self_signed: bool,
+}
+
+/// 启动 HTTPS 监听所需的参数
+#[derive(Debug, Clone)]
+pub struct ListenerTls {
+    pub cert: LoadedCert,
+    /// 单独的 HTTPS 端口；为空时主端口只接受 HTTPS
+    pub https_port: Option<u16>,
+}
+
+/// 运行中的 HTTPS 监听状态 (get_proxy_status / reload_proxy_tls 的返回)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TlsStatus {
+    pub https_port: u16,
+    pub self_signed: bool,
+    pub fingerprint_sha256: String,
+}
+
+/// 本地回环访问反代的入口 (重放、吞吐自测)
+#[derive(Debug, Clone)]
+pub struct LoopbackEndpoint {
+    pub base_url: String,
+    /// 主端口只接受 HTTPS 时为反代当前使用的证书；回环客户端只信任该证书，自签名证书也能通过校验
+    pub trusted_cert: Option<CertificateDer<'static>>,
+}
+
+impl LoopbackEndpoint {
+    /// 明文 HTTP 入口
+    pub fn plain(base_url: impl Into<String>) -> Self {
+        Self { base_url: base_url.into(), trusted_cert: None }
+    }
+
+    /// 构建访问该入口的 HTTP 客户端
+    pub fn client(&self, timeout: Duration) -> Result<reqwest::Client, String> {
+        let mut builder = reqwest::Client::builder().timeout(timeout);
+        if let Some(cert) = &self.trusted_cert {
+            let cert = reqwest::Certificate::from_der(cert.as_ref())
+                .map_err(|e| format!("加载反代证书失败: {}", e))?;
+            builder = builder.tls_built_in_root_certs(false).add_root_certificate(cert);
+        }
+        builder
+            .build()
+            .map_err(|e| format!("Failed to build HTTP client: {}", e))
+    }
+}
+
+/// 握手时返回当前证书，证书可在运行中整体替换
+#[derive(Debug)]
+pub struct ReloadableCertResolver {
+    current: RwLock<LoadedCert>,
+}
+
+impl ReloadableCertResolver {
+    pub fn new(cert: LoadedCert) -> Self {
+        Self { current: RwLock::new(cert) }
+    }
+
+    /// 替换证书，之后的新连接使用新证书 (已建立的连接不受影响)
+    pub fn replace(&self, cert: LoadedCert) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = cert;
+    }
+
+    pub fn current(&self) -> LoadedCert {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl ResolvesServerCert for ReloadableCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap_or_else(|e| e.into_inner()).key.clone())
+    }
+}
+
+/// 按配置构建监听 TLS 参数；未启用时为 None
+pub fn listener_tls(config: &ProxyTlsConfig, data_dir: &Path) -> Result<Option<ListenerTls>, TlsLoadError> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    Ok(Some(ListenerTls {
+        cert: load_cert(config, data_dir)?,
+        https_port: config.https_port,
+    }))
+}
+
+/// 按配置加载证书：self_signed 时使用 (必要时生成) 数据目录下的自签名证书，否则读取配置的 PEM 文件
+pub fn load_cert(config: &ProxyTlsConfig, data_dir: &Path) -> Result<LoadedCert, TlsLoadError> {
+    if config.self_signed {
+        let (cert_path, key_path) = ensure_self_signed(data_dir)?;
+        return load_pem_files(&cert_path, &key_path, true);
+    }
+    match (config.cert_path.as_deref(), config.key_path.as_deref()) {
+        (Some(cert), Some(key)) if !cert.trim().is_empty() && !key.trim().is_empty() => {
+            load_pem_files(Path::new(cert.trim()), Path::new(key.trim()), false)
+        }
+        _ => Err(TlsLoadError::NotConfigured),
+    }
+}
+
+fn read_file(path: &Path, kind: &'static str) -> Result<Vec<u8>, TlsLoadError> {
+    if !path.exists() {
+        return Err(TlsLoadError::FileMissing { kind, path: path.display().to_string() });
+    }
+    std::fs::read(path).map_err(|e| TlsLoadError::Read {
+        kind,
+        path: path.display().to_string(),
+        message: e.to_string(),
+    })
+}
+
+fn load_pem_files(cert_path: &Path, key_path: &Path, self_signed: bool) -> Result<LoadedCert, TlsLoadError> {
+    let cert_pem = read_file(cert_path, "证书")?;
+    let key_pem = read_file(key_path, "私钥")?;
+    let cert_display = cert_path.display().to_string();
+    let key_display = key_path.display().to_string();
+
+    let certs = CertificateDer::pem_slice_iter(&cert_pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| TlsLoadError::Parse { kind: "证书", path: cert_display.clone(), message: e.to_string() })?;
+    if certs.is_empty() {
+        return Err(TlsLoadError::Parse {
+            kind: "证书",
+            path: cert_display,
+            message: "未找到 PEM 格式的证书".to_string(),
+        });
+    }
+    let key = PrivateKeyDer::from_pem_slice(&key_pem)
+        .map_err(|e| TlsLoadError::Parse { kind: "私钥", path: key_display.clone(), message: e.to_string() })?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&key)
+        .map_err(|e| TlsLoadError::Parse { kind: "私钥", path: key_display.clone(), message: e.to_string() })?;
+
+    let fingerprint_sha256 = fingerprint(&certs[0]);
+    let key = CertifiedKey::new(certs, signing_key);
+    // 无法从私钥推导公钥时 (Unknown) 不视为不匹配
+    if let Err(rustls::Error::InconsistentKeys(rustls::InconsistentKeys::KeyMismatch)) = key.keys_match() {
+        return Err(TlsLoadError::KeyMismatch { cert_path: cert_display, key_path: key_display });
+    }
+
+    Ok(LoadedCert { key: Arc::new(key), fingerprint_sha256, self_signed })
+}
+
+/// 证书 DER 的 SHA-256 指纹，格式如 `AB:CD:...`
+pub fn fingerprint(cert: &CertificateDer<'_>) -> String {
+    Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// 自签名证书的路径；首次使用时生成，之后复用以保持指纹不变
+fn ensure_self_signed(data_dir: &Path) -> Result<(PathBuf, PathBuf), TlsLoadError> {
+    let dir = data_dir.join(SELF_SIGNED_DIR);
+    let cert_path = dir.join(SELF_SIGNED_CERT_FILE);
+    let key_path = dir.join(SELF_SIGNED_KEY_FILE);
+    if cert_path.exists() && key_path.exists() {
+        return Ok((cert_path, key_path));
+    }
+
+    let names = vec!["localhost".to_string(), "127.0.0.1".to_string(), "::1".to_string()];
+    let generated = rcgen::generate_simple_self_signed(names).map_err(|e| TlsLoadError::SelfSigned(e.to_string()))?;
+    std::fs::create_dir_all(&dir).map_err(|e| TlsLoadError::SelfSigned(e.to_string()))?;
+    write_private_key(&key_path, generated.key_pair.serialize_pem().as_bytes())
+        .map_err(|e| TlsLoadError::SelfSigned(e.to_string()))?;
+    std::fs::write(&cert_path, generated.cert.pem()).map_err(|e| TlsLoadError::SelfSigned(e.to_string()))?;
+    tracing::info!("已生成反代自签名证书: {}", cert_path.display());
+    Ok((cert_path, key_path))
+}
+
+/// 写入私钥文件；Unix 上创建时即为 0600，避免私钥短暂以默认权限落盘
+/// (覆盖已存在的旧私钥时同样收紧权限)
+fn write_private_key(path: &Path, pem: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(pem)
+}
+
+/// 使用可热替换证书的 TLS 握手器 (仅 HTTP/1.1)
+pub fn acceptor(resolver: Arc<ReloadableCertResolver>) -> Result<tokio_rustls::TlsAcceptor, String> {
+    let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("初始化 TLS 失败: {}", e))?
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(tokio_rustls::TlsAcceptor::from(Arc::new(config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ag_proxy_tls_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_generated(dir: &Path, name: &str) -> (String, String) {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert = dir.join(format!("{}.crt", name));
+        let key = dir.join(format!("{}.key", name));
+        std::fs::write(&cert, generated.cert.pem()).unwrap();
+        std::fs::write(&key, generated.key_pair.serialize_pem()).unwrap();
+        (cert.display().to_string(), key.display().to_string())
+    }
+
+    fn files(cert: &str, key: &str) -> ProxyTlsConfig {
+        ProxyTlsConfig {
+            enabled: true,
+            cert_path: Some(cert.to_string()),
+            key_path: Some(key.to_string()),
+            ..ProxyTlsConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_load_errors_are_specific() {
+        let dir = temp_dir();
+        let (cert, key) = write_generated(&dir, "a");
+        let (_, other_key) = write_generated(&dir, "b");
+
+        assert!(load_cert(&files(&cert, &key), &dir).is_ok());
+        assert_eq!(
+            load_cert(&ProxyTlsConfig { enabled: true, ..ProxyTlsConfig::default() }, &dir).unwrap_err(),
+            TlsLoadError::NotConfigured
+        );
+
+        let missing = dir.join("missing.crt").display().to_string();
+        assert!(matches!(
+            load_cert(&files(&missing, &key), &dir),
+            Err(TlsLoadError::FileMissing { kind: "证书", .. })
+        ));
+
+        let garbage = dir.join("garbage.pem");
+        std::fs::write(&garbage, "not a pem").unwrap();
+        let garbage = garbage.display().to_string();
+        assert!(matches!(
+            load_cert(&files(&garbage, &key), &dir),
+            Err(TlsLoadError::Parse { kind: "证书", .. })
+        ));
+        assert!(matches!(
+            load_cert(&files(&cert, &garbage), &dir),
+            Err(TlsLoadError::Parse { kind: "私钥", .. })
+        ));
+
+        assert!(matches!(
+            load_cert(&files(&cert, &other_key), &dir),
+            Err(TlsLoadError::KeyMismatch { .. })
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_self_signed_cert_is_persisted_and_reused() {
+        let dir = temp_dir();
+        let config = ProxyTlsConfig { enabled: true, self_signed: true, ..ProxyTlsConfig::default() };
+
+        let first = load_cert(&config, &dir).unwrap();
+        assert!(first.self_signed);
+        assert_eq!(first.fingerprint_sha256.split(':').count(), 32);
+        assert!(dir.join(SELF_SIGNED_DIR).join(SELF_SIGNED_CERT_FILE).exists());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let key = dir.join(SELF_SIGNED_DIR).join(SELF_SIGNED_KEY_FILE);
+            assert_eq!(std::fs::metadata(key).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        // 重启后指纹不变，客户端固定的证书继续有效
+        let second = load_cert(&config, &dir).unwrap();
+        assert_eq!(first.fingerprint_sha256, second.fingerprint_sha256);
+
+        let resolver = ReloadableCertResolver::new(first);
+        let (cert, key) = write_generated(&dir, "rotated");
+        let rotated = load_cert(&files(&cert, &key), &dir).unwrap();
+        resolver.replace(rotated.clone());
+        assert_eq!(resolver.current().fingerprint_sha256, rotated.fingerprint_sha256);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,12 +1,44 @@
 // 移除冗余的顶层导入，因为这些在代码中已由 full path 或局部导入处理
 use dashmap::DashMap;
+use once_cell::sync::Lazy;
 use std::collections::HashSet;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
 
+use crate::models::QuotaGroup;
+use crate::modules::account_reliability::ReliabilityEventKind;
+use crate::proxy::common::request_priority::RequestPriority;
+use crate::proxy::config::AccountIdentifierMode;
 use crate::proxy::rate_limit::RateLimitTracker;
 use crate::proxy::sticky_config::StickySessionConfig;
+use crate::proxy::token_persister::{TokenPersister, TokenUpdate, UsageUpdate};
+
+/// access_token 在过期前多少秒开始刷新
+pub const TOKEN_REFRESH_LEAD_SECS: i64 = 300;
+
+/// access_token 是否需要刷新 (`expiry_timestamp` 为过期时刻，而非签发时刻)
+fn needs_refresh(expiry_timestamp: i64, now: i64) -> bool {
+    now >= expiry_timestamp - TOKEN_REFRESH_LEAD_SECS
+}
+
+/// 运行中反代的号池 (反代停止后 Weak 失效)
+static ACTIVE_MANAGER: Lazy<std::sync::RwLock<Weak<TokenManager>>> = Lazy::new(|| std::sync::RwLock::new(Weak::new()));
+
+/// 登记运行中的号池，配额刷新等入口据此复用反代的 Token
+pub fn set_active(manager: &Arc<TokenManager>) {
+    *ACTIVE_MANAGER.write().unwrap_or_else(|e| e.into_inner()) = Arc::downgrade(manager);
+}
+
+/// 反代停止时清除登记
+pub fn clear_active() {
+    *ACTIVE_MANAGER.write().unwrap_or_else(|e| e.into_inner()) = Weak::new();
+}
+
+/// 当前运行中的号池 (反代未运行时为 None)
+pub fn active() -> Option<Arc<TokenManager>> {
+    ACTIVE_MANAGER.read().unwrap_or_else(|e| e.into_inner()).upgrade()
+}
 
 #[derive(Debug, Clone)]
 pub struct ProxyToken {
@@ -20,17 +52,68 @@ pub struct ProxyToken {
     pub project_id: Option<String>,
     pub subscription_tier: Option<String>, // "FREE" | "PRO" | "ULTRA"
     pub remaining_quota: Option<i32>, // [FIX #563] Remaining quota for priority sorting
+    pub quota_percent: Option<i32>, // 各模型剩余百分比的平均值 (0-100)，用于容量评分
+    pub quota_reset_time: Option<String>, // 配额模型中最早的 reset_time (ISO 8601)
+    pub label: Option<String>, // 账号标签 (日志标识模式为 label 时使用)
+    pub no_warmup: bool, // 禁止内部预热接口使用该账号
+    pub standby: bool, // 备用账号：仅在其他账号全部不可用时才会被选中
+    pub last_proxy_used_at: Option<i64>, // 账号文件中记录的最近一次反代使用时间
+    pub allowed_groups: Vec<QuotaGroup>, // 允许服务的配额分组，为空时不限
+}
+
+impl ProxyToken {
+    /// 账号是否可以服务该配额分组
+    pub fn serves(&self, group: QuotaGroup) -> bool {
+        self.allowed_groups.is_empty() || self.allowed_groups.contains(&group)
+    }
+
+    /// 综合订阅等级与剩余配额的容量评分 (0-100)
+    pub fn capacity_score(&self, weights: &crate::proxy::sticky_config::TierWeights) -> u8 {
+        crate::proxy::sticky_config::capacity_score(weights, self.subscription_tier.as_deref(), self.quota_percent)
+    }
+}
+
+/// 单个账号的容量评分 (仪表盘展示)
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AccountCapacity {
+    pub account_id: String,
+    pub email: String,
+    pub subscription_tier: Option<String>,
+    pub quota_percent: Option<i32>,
+    pub capacity_score: u8,
+}
+
+/// 速率限制提示 (基于内存中的配额快照估算，并非上游权威数据)
+///
+/// 上游只提供各模型的剩余百分比，无法换算成剩余请求数，因此这里只给出百分比
+#[derive(Debug, Clone, Default)]
+pub struct RateLimitHints {
+    /// 当前账号各模型剩余百分比的平均值 (0-100)
+    pub remaining_percent: Option<i32>,
+    /// 当前账号最早的配额重置时间
+    pub reset_time: Option<String>,
+    /// 号池中已知配额账号的剩余百分比平均值 (0-100)
+    pub pool_remaining_percent: Option<i32>,
 }
 
 
 pub struct TokenManager {
     tokens: Arc<DashMap<String, ProxyToken>>,  // account_id -> ProxyToken
-    current_index: Arc<AtomicUsize>,
+    group_indices: DashMap<String, AtomicUsize>, // 各请求分组 (get_token 的 quota_group，如 claude / gemini / image_gen) 独立的轮询游标
+    group_selections: [AtomicU64; QuotaGroup::ALL.len()], // 本次运行中各配额分组的选号次数
     last_used_account: Arc<tokio::sync::Mutex<Option<(String, std::time::Instant)>>>,
     data_dir: PathBuf,
     rate_limit_tracker: Arc<RateLimitTracker>,  // 新增: 限流跟踪器
     sticky_config: Arc<tokio::sync::RwLock<StickySessionConfig>>, // 新增：调度配置
     session_accounts: Arc<DashMap<String, String>>, // 新增：会话与账号映射 (SessionID -> AccountID)
+    persister: TokenPersister, // 刷新后 token 的异步落盘队列
+    mock_mode: AtomicBool, // 模拟上游模式：只使用合成账号，忽略磁盘账号
+    identifier_mode: std::sync::RwLock<AccountIdentifierMode>, // 日志与响应头中的账号标识方式
+    expired_snoozes: std::sync::Mutex<Vec<String>>, // 加载时自动恢复的临时停用账号 (待发出事件)
+    proxy_usage: DashMap<String, i64>, // 本次运行中账号最近被选中的时间 (account_id -> 时间戳)，重载号池时保留
+    usage_dirty: std::sync::Mutex<HashSet<String>>, // 使用时间尚未落盘的账号
+    last_probe_at: std::sync::Mutex<Option<std::time::SystemTime>>, // 最近一次最后探测的时间 (全局限频)
+    standby_activations: AtomicU64, // 本次运行中备用账号被启用的次数
 }
 
 impl TokenManager {
@@ -38,17 +121,44 @@ impl TokenManager {
     pub fn new(data_dir: PathBuf) -> Self {
         Self {
             tokens: Arc::new(DashMap::new()),
-            current_index: Arc::new(AtomicUsize::new(0)),
+            group_indices: DashMap::new(),
+            group_selections: Default::default(),
             last_used_account: Arc::new(tokio::sync::Mutex::new(None)),
             data_dir,
             rate_limit_tracker: Arc::new(RateLimitTracker::new()),
             sticky_config: Arc::new(tokio::sync::RwLock::new(StickySessionConfig::default())),
             session_accounts: Arc::new(DashMap::new()),
+            persister: TokenPersister::spawn(),
+            mock_mode: AtomicBool::new(false),
+            identifier_mode: std::sync::RwLock::new(AccountIdentifierMode::default()),
+            expired_snoozes: std::sync::Mutex::new(Vec::new()),
+            proxy_usage: DashMap::new(),
+            usage_dirty: std::sync::Mutex::new(HashSet::new()),
+            last_probe_at: std::sync::Mutex::new(None),
+            standby_activations: AtomicU64::new(0),
+        }
+    }
+    
+    /// 数据目录 (账号与配置文件所在目录)
+    pub fn data_dir(&self) -> &std::path::Path {
+        &self.data_dir
+    }
+
+    /// 使用指定时钟创建 (测试中注入假时钟)
+    #[cfg(test)]
+    pub fn with_clock(data_dir: PathBuf, clock: Arc<dyn crate::proxy::clock::Clock>) -> Self {
+        Self {
+            rate_limit_tracker: Arc::new(RateLimitTracker::with_clock(clock)),
+            ..Self::new(data_dir)
         }
     }
     
     /// 从主应用账号目录加载所有账号
     pub async fn load_accounts(&self) -> Result<usize, String> {
+        if self.mock_mode.load(Ordering::SeqCst) {
+            return Ok(self.tokens.len());
+        }
+
         let accounts_dir = self.data_dir.join("accounts");
         
         if !accounts_dir.exists() {
@@ -57,7 +167,7 @@ impl TokenManager {
 
         // Reload should reflect current on-disk state (accounts can be added/removed/disabled).
         self.tokens.clear();
-        self.current_index.store(0, Ordering::SeqCst);
+        self.group_indices.clear();
         {
             let mut last_used = self.last_used_account.lock().await;
             *last_used = None;
@@ -95,8 +205,57 @@ impl TokenManager {
         Ok(count)
     }
 
+    /// 注入模拟账号 (仅用于模拟上游模式，不读取也不写入任何账号文件)
+    pub fn add_mock_account(&self) -> usize {
+        self.add_mock_accounts(1)
+    }
+
+    /// 注入 `count` 个模拟账号 (首个使用固定的模拟账号信息，用于测试多账号重试)
+    pub fn add_mock_accounts(&self, count: usize) -> usize {
+        use crate::proxy::upstream::mock::{MOCK_ACCOUNT_EMAIL, MOCK_ACCOUNT_ID, MOCK_PROJECT_ID};
+
+        self.mock_mode.store(true, Ordering::SeqCst);
+        self.tokens.clear();
+        for i in 0..count.max(1) {
+            let (account_id, email) = if i == 0 {
+                (MOCK_ACCOUNT_ID.to_string(), MOCK_ACCOUNT_EMAIL.to_string())
+            } else {
+                (format!("{}-{}", MOCK_ACCOUNT_ID, i), format!("mock{}@antigravity.local", i))
+            };
+            self.tokens.insert(
+                account_id.clone(),
+                ProxyToken {
+                    account_path: self.data_dir.join("accounts").join(format!("{}.json", account_id)),
+                    account_id,
+                    access_token: "mock-access-token".to_string(),
+                    refresh_token: "mock-refresh-token".to_string(),
+                    expires_in: i64::MAX / 2,
+                    // 远期过期时间，避免触发 OAuth 刷新
+                    timestamp: i64::MAX / 2,
+                    email,
+                    project_id: Some(MOCK_PROJECT_ID.to_string()),
+                    subscription_tier: None,
+                    remaining_quota: None,
+                    quota_percent: None,
+                    quota_reset_time: None,
+                    label: None,
+                    no_warmup: false,
+                    standby: false,
+                    last_proxy_used_at: None,
+                    allowed_groups: Vec::new(),
+                },
+            );
+        }
+        tracing::warn!("TokenManager running in MOCK mode with {} synthetic account(s)", self.tokens.len());
+        self.tokens.len()
+    }
+
     /// 重新加载指定账号（用于配额更新后的实时同步）
     pub async fn reload_account(&self, account_id: &str) -> Result<(), String> {
+        if self.mock_mode.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
         let path = self.data_dir.join("accounts").join(format!("{}.json", account_id));
         if !path.exists() {
             return Err(format!("账号文件不存在: {:?}", path));
@@ -112,6 +271,44 @@ impl TokenManager {
         }
     }
 
+    /// 恢复临时停用已到期的账号并重新加入账号池，返回恢复的账号 ID (供运行中的反代定期调用)
+    pub async fn release_expired_snoozes(&self) -> Vec<String> {
+        if self.mock_mode.load(Ordering::SeqCst) {
+            return Vec::new();
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        if let Ok(entries) = std::fs::read_dir(self.data_dir.join("accounts")) {
+            for path in entries.flatten().map(|e| e.path()) {
+                if path.extension().and_then(|s| s.to_str()) != Some("json") {
+                    continue;
+                }
+                let Some(account) = std::fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|c| serde_json::from_str::<serde_json::Value>(&c).ok())
+                else {
+                    continue;
+                };
+                let expired = account
+                    .get("proxy_disabled_until")
+                    .and_then(|v| v.as_i64())
+                    .is_some_and(|until| until <= now);
+                if !expired {
+                    continue;
+                }
+                // 由 load_single_account 统一执行恢复并记录
+                if let Ok(Some(token)) = self.load_single_account(&path).await {
+                    self.tokens.insert(token.account_id.clone(), token);
+                }
+            }
+        }
+
+        self.expired_snoozes
+            .lock()
+            .map(|mut expired| std::mem::take(&mut *expired))
+            .unwrap_or_default()
+    }
+
     /// 重新加载所有账号
     pub async fn reload_all_accounts(&self) -> Result<usize, String> {
         self.load_accounts().await
@@ -122,8 +319,9 @@ impl TokenManager {
         let content = std::fs::read_to_string(path)
             .map_err(|e| format!("读取文件失败: {}", e))?;
         
-        let account: serde_json::Value = serde_json::from_str(&content)
+        let mut account: serde_json::Value = serde_json::from_str(&content)
             .map_err(|e| format!("解析 JSON 失败: {}", e))?;
+        crate::modules::token_crypto::decrypt_account_json(&mut account)?;
 
         if account
             .get("disabled")
@@ -138,6 +336,20 @@ impl TokenManager {
             return Ok(None);
         }
 
+        // 临时停用到期：恢复账号 (写回原始文件，避免把解密后的 token 落盘)
+        let now = chrono::Utc::now().timestamp();
+        if crate::modules::account_snooze::clear_expired(&mut account, now) {
+            crate::modules::account_snooze::release_if_expired(path, now)?;
+            let account_id = account.get("id").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+            tracing::info!(
+                "Snooze expired, re-enabling account {}",
+                self.account_identifier(account.get("email").and_then(|v| v.as_str()).unwrap_or(&account_id))
+            );
+            if let Ok(mut expired) = self.expired_snoozes.lock() {
+                expired.push(account_id);
+            }
+        }
+
         // 【新增】配额保护检查 - 在检查 proxy_disabled 之前执行
         // 这样可以在加载时自动恢复配额已恢复的账号
         if self.check_and_protect_quota(&account, path).await {
@@ -204,7 +416,43 @@ impl TokenManager {
         let remaining_quota = account.get("quota")
             .map(|q| self.calculate_quota_stats(q).1) // (total, remaining) -> remaining
             .filter(|&r| r > 0);
+
+        let quota_percent = account.get("quota")
+            .and_then(|q| q.get("models"))
+            .and_then(|m| m.as_array())
+            .map(|models| models.iter().filter_map(|m| m.get("percentage").and_then(|p| p.as_i64())).collect::<Vec<_>>())
+            .filter(|percentages| !percentages.is_empty())
+            .map(|percentages| (percentages.iter().sum::<i64>() / percentages.len() as i64) as i32);
+
+        let quota_reset_time = account.get("quota")
+            .and_then(|q| q.get("models"))
+            .and_then(|m| m.as_array())
+            .and_then(|models| {
+                models.iter()
+                    .filter_map(|m| m.get("reset_time").and_then(|r| r.as_str()))
+                    .filter(|r| !r.is_empty())
+                    .min()
+                    .map(|r| r.to_string())
+            });
         
+        let label = account.get("label")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+
+        let no_warmup = account.get("no_warmup")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let standby = account.get("standby")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let last_proxy_used_at = account.get("last_proxy_used_at").and_then(|v| v.as_i64());
+
+        let allowed_groups = account.get("allowed_groups")
+            .and_then(|v| serde_json::from_value::<Vec<QuotaGroup>>(v.clone()).ok())
+            .unwrap_or_default();
+
         Ok(Some(ProxyToken {
             account_id,
             access_token,
@@ -216,6 +464,13 @@ impl TokenManager {
             project_id,
             subscription_tier,
             remaining_quota,
+            quota_percent,
+            quota_reset_time,
+            label,
+            no_warmup,
+            standby,
+            last_proxy_used_at,
+            allowed_groups,
         }))
     }
 
@@ -240,15 +495,10 @@ impl TokenManager {
         };
         
         // 3. 检查是否已经被配额保护禁用
-        if account_json.get("proxy_disabled")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false)
-        {
-            if let Some(reason) = account_json.get("proxy_disabled_reason").and_then(|v| v.as_str()) {
-                if reason.contains("quota_protection") {
-                    // 已经被配额保护禁用，检查是否可以恢复
-                    return self.check_and_restore_quota(account_json, account_path, quota, &config).await;
-                }
+        if let Some(category) = crate::modules::proxy_disable::category_of_json(account_json) {
+            if category == crate::models::ProxyDisableCategory::QuotaProtection {
+                // 已经被配额保护禁用，检查是否可以恢复
+                return self.check_and_restore_quota(account_json, account_path, quota, &config).await;
             }
             return true; // 被其他原因禁用，跳过
         }
@@ -318,15 +568,14 @@ impl TokenManager {
         )
         .map_err(|e| format!("解析 JSON 失败: {}", e))?;
         
-        let now = chrono::Utc::now().timestamp();
-        content["proxy_disabled"] = serde_json::Value::Bool(true);
-        content["proxy_disabled_at"] = serde_json::Value::Number(now.into());
-        content["proxy_disabled_reason"] = serde_json::Value::String(
-            format!("quota_protection: {}/{} (阈值: {})", remaining, total, threshold)
+        crate::modules::proxy_disable::disable_json(
+            &mut content,
+            crate::models::ProxyDisableCategory::QuotaProtection,
+            format!("quota_protection: {}/{} (阈值: {})", remaining, total, threshold),
+            chrono::Utc::now().timestamp(),
         );
         
-        std::fs::write(account_path, serde_json::to_string_pretty(&content).unwrap())
-            .map_err(|e| format!("写入文件失败: {}", e))?;
+        crate::modules::account::write_account_json(account_path, &content)?;
         
         tracing::info!("账号 {} 已被配额保护自动禁用", account_id);
         Ok(())
@@ -378,12 +627,9 @@ impl TokenManager {
         )
         .map_err(|e| format!("解析 JSON 失败: {}", e))?;
         
-        content["proxy_disabled"] = serde_json::Value::Bool(false);
-        content["proxy_disabled_reason"] = serde_json::Value::Null;
-        content["proxy_disabled_at"] = serde_json::Value::Null;
+        crate::modules::proxy_disable::enable_json(&mut content);
         
-        std::fs::write(account_path, serde_json::to_string_pretty(&content).unwrap())
-            .map_err(|e| format!("写入文件失败: {}", e))?;
+        crate::modules::account::write_account_json(account_path, &content)?;
         
         tracing::info!("账号 {} 配额保护已自动恢复", account_id);
         Ok(())
@@ -391,35 +637,179 @@ impl TokenManager {
 
     
     /// 获取当前可用的 Token（支持粘性会话与智能调度）
-    /// 参数 `quota_group` 用于区分 "claude" vs "gemini" 组
+    /// 参数 `quota_group` 用于区分 "claude" vs "gemini" 组 (每个组使用独立的轮询游标，"image_gen" 另有独立的账号子集)
     /// 参数 `force_rotate` 为 true 时将忽略锁定，强制切换账号
     /// 参数 `session_id` 用于跨请求维持会话粘性
     pub async fn get_token(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<(String, String, String), String> {
-        // 【优化 Issue #284】添加 5 秒超时，防止死锁
-        let timeout_duration = std::time::Duration::from_secs(5);
-        match tokio::time::timeout(timeout_duration, self.get_token_internal(quota_group, force_rotate, session_id)).await {
-            Ok(result) => result,
-            Err(_) => Err("Token acquisition timeout (5s) - system too busy or deadlock detected".to_string()),
+        self.get_token_with_priority(quota_group, force_rotate, session_id, RequestPriority::Interactive).await
+    }
+
+    /// 同 `get_token`，额外携带客户端的优先级提示 (仅在调度配置开启 priority_hints 时生效)
+    pub async fn get_token_with_priority(
+        &self,
+        quota_group: &str,
+        force_rotate: bool,
+        session_id: Option<&str>,
+        priority: RequestPriority,
+    ) -> Result<(String, String, String), String> {
+        // 【优化 Issue #284】添加 5 秒超时，防止死锁 (wait_if_short 策略额外加上允许的等待时间)
+        let wait_budget = {
+            let scheduling = self.sticky_config.read().await;
+            match scheduling.sticky_rate_limit_policy {
+                crate::proxy::sticky_config::StickyRateLimitPolicy::WaitIfShort => scheduling.sticky_wait_max_seconds,
+                crate::proxy::sticky_config::StickyRateLimitPolicy::Switch => 0,
+            }
+        };
+        let timeout_secs = 5 + wait_budget;
+        let timeout_duration = std::time::Duration::from_secs(timeout_secs);
+        match tokio::time::timeout(timeout_duration, self.get_token_internal(quota_group, force_rotate, session_id, priority)).await {
+            Ok(result) => {
+                if let Ok((_, _, email)) = &result {
+                    self.record_proxy_use(email);
+                }
+                result
+            }
+            Err(_) => Err(format!("Token acquisition timeout ({}s) - system too busy or deadlock detected", timeout_secs)),
+        }
+    }
+
+    /// 调度配置未开启优先级提示时，所有请求都按 interactive 处理
+    pub async fn effective_priority(&self, requested: RequestPriority) -> RequestPriority {
+        if self.sticky_config.read().await.priority_hints {
+            requested
+        } else {
+            RequestPriority::Interactive
+        }
+    }
+
+    /// 确定账号调度使用的配额分组：X-Request-Type 请求头 > 调度配置默认值 > 自动推断
+    pub async fn resolve_quota_group(&self, inferred: &str, header_override: Option<&'static str>) -> String {
+        let config_default = self
+            .sticky_config
+            .read()
+            .await
+            .default_request_type
+            .as_deref()
+            .and_then(crate::proxy::common::request_type::parse_request_type);
+
+        let (resolved, source) = match (header_override, config_default) {
+            (Some(t), _) => (t, "X-Request-Type header"),
+            (None, Some(t)) => (t, "scheduling default"),
+            (None, None) => return inferred.to_string(),
+        };
+        if resolved != inferred {
+            tracing::info!(
+                "[Scheduling] Request type overridden by {}: {} -> {}",
+                source,
+                inferred,
+                resolved
+            );
         }
+        resolved.to_string()
+    }
+
+    /// 根据日志/响应头中的账号标识反查订阅等级
+    pub fn tier_for_identifier(&self, identifier: &str) -> Option<String> {
+        // 先取快照，避免在遍历 DashMap 时再次进入 account_identifier 的遍历
+        let entries: Vec<(String, Option<String>)> = self
+            .tokens
+            .iter()
+            .map(|e| (e.value().email.clone(), e.value().subscription_tier.clone()))
+            .collect();
+        entries
+            .into_iter()
+            .find(|(email, _)| email == identifier || self.account_identifier(email) == identifier)
+            .and_then(|(_, tier)| tier)
+    }
+
+    /// 批量请求的账号选择：按 FREE 优先的顺序挑选同等级账号轮询，
+    /// 避开交互式流量正在使用的热账号；只剩热账号 (或只剩高等级账号) 时仍然返回，保证请求可被服务
+    fn select_bulk_candidate(
+        &self,
+        tokens_snapshot: &[ProxyToken],
+        attempted: &HashSet<String>,
+        warm_account_id: Option<&str>,
+        quota_group: &str,
+    ) -> Option<ProxyToken> {
+        let available: Vec<&ProxyToken> = tokens_snapshot
+            .iter()
+            .filter(|t| !attempted.contains(&t.account_id) && !self.is_rate_limited(&t.account_id))
+            .collect();
+        let cold: Vec<&ProxyToken> = available
+            .iter()
+            .copied()
+            .filter(|t| Some(t.account_id.as_str()) != warm_account_id)
+            .collect();
+        let pool = if cold.is_empty() { available } else { cold };
+
+        let preferred_tier = &pool.first()?.subscription_tier;
+        let same_tier: Vec<&ProxyToken> = pool
+            .iter()
+            .copied()
+            .filter(|t| &t.subscription_tier == preferred_tier)
+            .collect();
+        let idx = self.next_index(quota_group) % same_tier.len();
+        Some(same_tier[idx].clone())
+    }
+
+    /// 推进请求分组的轮询游标，返回推进前的位置
+    fn next_index(&self, quota_group: &str) -> usize {
+        if let Some(index) = self.group_indices.get(quota_group) {
+            return index.fetch_add(1, Ordering::SeqCst);
+        }
+        self.group_indices
+            .entry(quota_group.to_string())
+            .or_default()
+            .fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// 本次运行中各配额分组的选号次数 (group -> count)
+    pub fn group_selections(&self) -> std::collections::BTreeMap<String, u64> {
+        QuotaGroup::ALL
+            .iter()
+            .map(|g| (g.as_str().to_string(), self.group_selections[g.index()].load(Ordering::Relaxed)))
+            .collect()
     }
 
     /// 内部实现：获取 Token 的核心逻辑
-    async fn get_token_internal(&self, quota_group: &str, force_rotate: bool, session_id: Option<&str>) -> Result<(String, String, String), String> {
-        let mut tokens_snapshot: Vec<ProxyToken> = self.tokens.iter().map(|e| e.value().clone()).collect();
-        let total = tokens_snapshot.len();
-        if total == 0 {
+    async fn get_token_internal(
+        &self,
+        quota_group: &str,
+        force_rotate: bool,
+        session_id: Option<&str>,
+        priority: RequestPriority,
+    ) -> Result<(String, String, String), String> {
+        if self.tokens.is_empty() {
             return Err("Token pool is empty".to_string());
         }
+        // 各配额分组只在允许服务该分组的账号中轮询，会话绑定与 60s 粘性窗口只作用于文本分组
+        let group = QuotaGroup::from_request_type(quota_group);
+        let sticky = group.is_sticky();
+        let mut tokens_snapshot: Vec<ProxyToken> = self
+            .tokens
+            .iter()
+            .filter(|e| e.value().serves(group))
+            .map(|e| e.value().clone())
+            .collect();
+        if tokens_snapshot.is_empty() {
+            return Err(format!("No accounts are allowed to serve the {} quota group", group.as_str()));
+        }
+
+        // 0. 读取当前调度配置
+        let scheduling = self.sticky_config.read().await.clone();
+        use crate::proxy::sticky_config::{SchedulingMode, StickyRateLimitPolicy};
+        let bulk = scheduling.priority_hints && priority == RequestPriority::Bulk;
 
         // ===== 【优化】根据订阅等级和剩余配额排序 =====
         // [FIX #563] 优先级: ULTRA > PRO > FREE, 同tier内优先高配额账号
         // 理由: ULTRA/PRO 重置快，优先消耗；FREE 重置慢，用于兜底
         //       高配額账号优先使用，避免低配额账号被用光
+        // 批量请求反转等级偏好 (FREE > PRO > ULTRA)，把高等级账号留给交互式流量
         tokens_snapshot.sort_by(|a, b| {
-            let tier_priority = |tier: &Option<String>| match tier.as_deref() {
-                Some("ULTRA") => 0,
-                Some("PRO") => 1,
-                Some("FREE") => 2,
+            let tier_priority = |tier: &Option<String>| match (tier.as_deref(), bulk) {
+                (Some("ULTRA"), false) | (Some("FREE"), true) => 0,
+                (Some("PRO"), _) => 1,
+                (Some("FREE"), false) | (Some("ULTRA"), true) => 2,
                 _ => 3,
             };
             
@@ -435,17 +825,23 @@ impl TokenManager {
             // Accounts with unknown/zero quota go last within their tier
             let quota_a = a.remaining_quota.unwrap_or(0);
             let quota_b = b.remaining_quota.unwrap_or(0);
-            quota_b.cmp(&quota_a)  // Descending: higher quota first
+            quota_b
+                .cmp(&quota_a) // Descending: higher quota first
+                // Third: 容量评分高者优先
+                .then_with(|| {
+                    b.capacity_score(&scheduling.tier_weights)
+                        .cmp(&a.capacity_score(&scheduling.tier_weights))
+                })
         });
 
-
-        // 0. 读取当前调度配置
-        let scheduling = self.sticky_config.read().await.clone();
-        use crate::proxy::sticky_config::SchedulingMode;
+        // 备用账号不参与常规选择，只在主号池选不出候选时的第二轮中使用 (保持相同排序)
+        let (standby_snapshot, tokens_snapshot): (Vec<ProxyToken>, Vec<ProxyToken>) =
+            tokens_snapshot.into_iter().partition(|t| t.standby);
+        let total = tokens_snapshot.len();
 
         // 【优化 Issue #284】将锁操作移到循环外，避免重复获取锁
         // 预先获取 last_used_account 的快照，避免在循环中多次加锁
-        let last_used_account_id = if quota_group != "image_gen" {
+        let last_used_account_id = if sticky {
             let last_used = self.last_used_account.lock().await;
             last_used.clone()
         } else {
@@ -456,35 +852,81 @@ impl TokenManager {
         let mut last_error: Option<String> = None;
         let mut need_update_last_used: Option<(String, std::time::Instant)> = None;
 
-        for attempt in 0..total {
+        for attempt in 0..total + standby_snapshot.len() {
             let rotate = force_rotate || attempt > 0;
+            // 主号池中是否还有未尝试且未限流的账号 (有则不使用备用账号)
+            let main_available = tokens_snapshot
+                .iter()
+                .any(|t| !attempted.contains(&t.account_id) && !self.is_token_locked(t));
 
             // ===== 【核心】粘性会话与智能调度逻辑 =====
             let mut target_token: Option<ProxyToken> = None;
+
+            // 批量请求: 不参与会话绑定与 60s 粘性窗口，也不更新 last_used_account
+            if bulk {
+                let warm_account_id = last_used_account_id
+                    .as_ref()
+                    .filter(|(_, t)| t.elapsed().as_secs() < 60)
+                    .map(|(id, _)| id.as_str());
+                target_token = self.select_bulk_candidate(&tokens_snapshot, &attempted, warm_account_id, quota_group);
+            }
             
             // 模式 A: 粘性会话处理 (CacheFirst 或 Balance 且有 session_id)
-            if !rotate && session_id.is_some() && scheduling.mode != SchedulingMode::PerformanceFirst {
+            if !bulk && sticky && !rotate && session_id.is_some() && scheduling.mode != SchedulingMode::PerformanceFirst {
                 let sid = session_id.unwrap();
                 
                 // 1. 检查会话是否已绑定账号
                 if let Some(bound_id) = self.session_accounts.get(sid).map(|v| v.clone()) {
                     // 【修复】先通过 account_id 找到对应的账号，获取其 email
                     // 因为限流记录是以 email 为 key 存储的
-                    if let Some(bound_token) = tokens_snapshot.iter().find(|t| t.account_id == bound_id) {
-                        // 2. 使用 email 检查绑定的账号是否限流
-                        let reset_sec = self.rate_limit_tracker.get_remaining_wait(&bound_token.email);
-                        if reset_sec > 0 {
-                            // 【修复 Issue #284】立即解绑并切换账号，不再阻塞等待
-                            // 原因：阻塞等待会导致并发请求时客户端 socket 超时 (UND_ERR_SOCKET)
-                            tracing::warn!(
-                                "Session {} bound account {} is rate-limited ({}s remaining). Unbinding and switching to next available account.", 
-                                sid, bound_token.email, reset_sec
+                    if let Some(bound_token) = tokens_snapshot
+                        .iter()
+                        .chain(standby_snapshot.iter())
+                        .find(|t| t.account_id == bound_id)
+                    {
+                        if bound_token.standby && main_available {
+                            // 主号池已恢复，备用账号的会话绑定立即失效
+                            tracing::info!(
+                                "Session {} bound to standby account {}, main pool recovered. Unbinding.",
+                                sid, self.account_identifier(&bound_token.email)
                             );
                             self.session_accounts.remove(sid);
-                        } else if !attempted.contains(&bound_id) {
-                            // 3. 账号可用且未被标记为尝试失败，优先复用
-                            tracing::debug!("Sticky Session: Successfully reusing bound account {} for session {}", bound_token.email, sid);
-                            target_token = Some(bound_token.clone());
+                        } else {
+                            // 2. 使用 email 检查绑定的账号是否限流
+                            let mut remaining = self
+                                .rate_limit_tracker
+                                .get_reset_duration(&bound_token.email)
+                                .filter(|d| !d.is_zero());
+                            if let Some(wait) = remaining {
+                                if scheduling.sticky_rate_limit_policy == StickyRateLimitPolicy::WaitIfShort
+                                    && wait <= std::time::Duration::from_secs(scheduling.sticky_wait_max_seconds)
+                                {
+                                    // 限流即将解除，按精确剩余时长等待以保留会话绑定 (等待上限可配置，避免客户端超时)
+                                    tracing::info!(
+                                        "Session {} bound account {} is rate-limited ({}ms remaining). Waiting for it to recover.",
+                                        sid, self.account_identifier(&bound_token.email), wait.as_millis()
+                                    );
+                                    self.rate_limit_tracker.clock().sleep(wait).await;
+                                    remaining = self
+                                        .rate_limit_tracker
+                                        .get_reset_duration(&bound_token.email)
+                                        .filter(|d| !d.is_zero());
+                                }
+                            }
+                            if let Some(remaining) = remaining {
+                                let reset_sec = remaining.as_secs_f64().ceil() as u64;
+                                // 【修复 Issue #284】立即解绑并切换账号，不再阻塞等待
+                                // 原因：阻塞等待会导致并发请求时客户端 socket 超时 (UND_ERR_SOCKET)
+                                tracing::warn!(
+                                    "Session {} bound account {} is rate-limited ({}s remaining). Unbinding and switching to next available account.", 
+                                    sid, self.account_identifier(&bound_token.email), reset_sec
+                                );
+                                self.session_accounts.remove(sid);
+                            } else if !attempted.contains(&bound_id) {
+                                // 3. 账号可用且未被标记为尝试失败，优先复用
+                                tracing::debug!("Sticky Session: Successfully reusing bound account {} for session {}", bound_token.email, sid);
+                                target_token = Some(bound_token.clone());
+                            }
                         }
                     } else {
                         // 绑定的账号已不存在（可能被删除），解绑
@@ -495,7 +937,7 @@ impl TokenManager {
             }
 
             // 模式 B: 原子化 60s 全局锁定 (针对无 session_id 情况的默认保护)
-            if target_token.is_none() && !rotate && quota_group != "image_gen" {
+            if !bulk && sticky && target_token.is_none() && !rotate {
                 // 【优化】使用预先获取的快照，不再在循环内加锁
                 if let Some((account_id, last_time)) = &last_used_account_id {
                     if last_time.elapsed().as_secs() < 60 && !attempted.contains(account_id) {
@@ -512,8 +954,8 @@ impl TokenManager {
                 }
                 
                 // 若无锁定，则轮询选择新账号
-                if target_token.is_none() {
-                    let start_idx = self.current_index.fetch_add(1, Ordering::SeqCst) % total;
+                if target_token.is_none() && total > 0 {
+                    let start_idx = self.next_index(quota_group) % total;
                     for offset in 0..total {
                         let idx = (start_idx + offset) % total;
                         let candidate = &tokens_snapshot[idx];
@@ -540,9 +982,9 @@ impl TokenManager {
                         break;
                     }
                 }
-            } else if target_token.is_none() {
+            } else if target_token.is_none() && total > 0 {
                 // 模式 C: 纯轮询模式 (Round-robin) 或强制轮换
-                let start_idx = self.current_index.fetch_add(1, Ordering::SeqCst) % total;
+                let start_idx = self.next_index(quota_group) % total;
                 for offset in 0..total {
                     let idx = (start_idx + offset) % total;
                     let candidate = &tokens_snapshot[idx];
@@ -564,6 +1006,21 @@ impl TokenManager {
                 }
             }
             
+            // 第二轮: 主号池没有选出任何候选 (全部限流、禁用或已在本次请求中失败) 时，启用备用账号
+            if target_token.is_none() {
+                if let Some(candidate) = standby_snapshot
+                    .iter()
+                    .find(|t| !attempted.contains(&t.account_id) && !self.is_token_locked(t))
+                {
+                    target_token = Some(candidate.clone());
+                    if let Some(sid) = session_id {
+                        if !bulk && sticky && scheduling.mode != SchedulingMode::PerformanceFirst {
+                            self.session_accounts.insert(sid.to_string(), candidate.account_id.clone());
+                        }
+                    }
+                }
+            }
+
             let mut token = match target_token {
                 Some(t) => t,
                 None => {
@@ -572,6 +1029,7 @@ impl TokenManager {
                     
                     // 计算最短等待时间
                     let min_wait = tokens_snapshot.iter()
+                        .chain(standby_snapshot.iter())
                         .filter_map(|t| self.rate_limit_tracker.get_reset_seconds(&t.account_id))
                         .min();
                     
@@ -583,21 +1041,22 @@ impl TokenManager {
                                 wait_sec
                             );
                             
-                            // 缓冲延迟 500ms
-                            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+                            // 缓冲延迟 500ms (与限流跟踪器共用时钟)
+                            self.rate_limit_tracker.clock().sleep(std::time::Duration::from_millis(500)).await;
                             
                             // 重新尝试选择账号
                             let retry_token = tokens_snapshot.iter()
+                                .chain(standby_snapshot.iter())
                                 .find(|t| !attempted.contains(&t.account_id) && !self.is_rate_limited(&t.account_id));
                             
                             if let Some(t) = retry_token {
-                                tracing::info!("✅ Buffer delay successful! Found available account: {}", t.email);
+                                tracing::info!("✅ Buffer delay successful! Found available account: {}", self.account_identifier(&t.email));
                                 t.clone()
                             } else {
                                 // Layer 2: 缓冲后仍无可用账号,执行乐观重置
                                 tracing::warn!(
                                     "Buffer delay failed. Executing optimistic reset for all {} accounts...",
-                                    tokens_snapshot.len()
+                                    total + standby_snapshot.len()
                                 );
                                 
                                 // 清除所有限流记录
@@ -605,10 +1064,11 @@ impl TokenManager {
                                 
                                 // 再次尝试选择账号
                                 let final_token = tokens_snapshot.iter()
+                                    .chain(standby_snapshot.iter())
                                     .find(|t| !attempted.contains(&t.account_id));
                                 
                                 if let Some(t) = final_token {
-                                    tracing::info!("✅ Optimistic reset successful! Using account: {}", t.email);
+                                    tracing::info!("✅ Optimistic reset successful! Using account: {}", self.account_identifier(&t.email));
                                     t.clone()
                                 } else {
                                     // 所有策略都失败,返回错误
@@ -631,13 +1091,14 @@ impl TokenManager {
         
             // 3. 检查 token 是否过期（提前5分钟刷新）
             let now = chrono::Utc::now().timestamp();
-            if now >= token.timestamp - 300 {
+            if needs_refresh(token.timestamp, now) {
                 tracing::debug!("账号 {} 的 token 即将过期，正在刷新...", token.email);
 
                 // 调用 OAuth 刷新 token
                 match crate::modules::oauth::refresh_access_token(&token.refresh_token).await {
                     Ok(token_response) => {
                         tracing::debug!("Token 刷新成功！");
+                        self.record_reliability(&token.account_id, ReliabilityEventKind::Refresh, true, None);
 
                         // 更新本地内存对象供后续使用
                         token.access_token = token_response.access_token.clone();
@@ -651,17 +1112,16 @@ impl TokenManager {
                             entry.timestamp = token.timestamp;
                         }
 
-                        // 同步落盘（避免重启后继续使用过期 timestamp 导致频繁刷新）
-                        if let Err(e) = self.save_refreshed_token(&token.account_id, &token_response).await {
-                            tracing::debug!("保存刷新后的 token 失败 ({}): {}", token.email, e);
-                        }
+                        // 异步落盘（避免重启后继续使用过期 timestamp 导致频繁刷新），不阻塞当前请求
+                        self.persist_refreshed_token(&token.account_id, &token.access_token, token.expires_in, token.timestamp);
                     }
                     Err(e) => {
-                        tracing::error!("Token 刷新失败 ({}): {}，尝试下一个账号", token.email, e);
+                        tracing::error!("Token 刷新失败 ({}): {}，尝试下一个账号", self.account_identifier(&token.email), e);
+                        self.record_reliability(&token.account_id, ReliabilityEventKind::Refresh, false, Some(e.clone()));
                         if e.contains("\"invalid_grant\"") || e.contains("invalid_grant") {
                             tracing::error!(
                                 "Disabling account due to invalid_grant ({}): refresh_token likely revoked/expired",
-                                token.email
+                                self.account_identifier(&token.email)
                             );
                             let _ = self
                                 .disable_account(&token.account_id, &format!("invalid_grant: {}", e))
@@ -673,7 +1133,7 @@ impl TokenManager {
                         attempted.insert(token.account_id.clone());
 
                         // 【优化】标记需要清除锁定，避免在循环内加锁
-                        if quota_group != "image_gen" {
+                        if sticky {
                             if matches!(&last_used_account_id, Some((id, _)) if id == &token.account_id) {
                                 need_update_last_used = Some((String::new(), std::time::Instant::now())); // 空字符串表示需要清除
                             }
@@ -697,12 +1157,12 @@ impl TokenManager {
                         pid
                     }
                     Err(e) => {
-                        tracing::error!("Failed to fetch project_id for {}: {}", token.email, e);
+                        tracing::error!("Failed to fetch project_id for {}: {}", self.account_identifier(&token.email), e);
                         last_error = Some(format!("Failed to fetch project_id for {}: {}", token.email, e));
                         attempted.insert(token.account_id.clone());
 
                         // 【优化】标记需要清除锁定，避免在循环内加锁
-                        if quota_group != "image_gen" {
+                        if sticky {
                             if matches!(&last_used_account_id, Some((id, _)) if id == &token.account_id) {
                                 need_update_last_used = Some((String::new(), std::time::Instant::now())); // 空字符串表示需要清除
                             }
@@ -714,7 +1174,7 @@ impl TokenManager {
 
             // 【优化】在成功返回前，统一更新 last_used_account（如果需要）
             if let Some((new_account_id, new_time)) = need_update_last_used {
-                if quota_group != "image_gen" {
+                if sticky {
                     let mut last_used = self.last_used_account.lock().await;
                     if new_account_id.is_empty() {
                         // 空字符串表示需要清除锁定
@@ -725,12 +1185,22 @@ impl TokenManager {
                 }
             }
 
+            self.group_selections[group.index()].fetch_add(1, Ordering::Relaxed);
+            if token.standby {
+                let activations = self.standby_activations.fetch_add(1, Ordering::Relaxed) + 1;
+                tracing::warn!(
+                    "[Standby] Main pool exhausted, serving {} request with standby account {} (activation #{})",
+                    quota_group, self.account_identifier(&token.email), activations
+                );
+            }
+
             return Ok((token.access_token, project_id, token.email));
         }
 
         Err(last_error.unwrap_or_else(|| "All accounts failed".to_string()))
     }
 
+    /// 授权失效 (invalid_grant) 时停用账号
     async fn disable_account(&self, account_id: &str, reason: &str) -> Result<(), String> {
         let path = if let Some(entry) = self.tokens.get(account_id) {
             entry.account_path.clone()
@@ -746,17 +1216,32 @@ impl TokenManager {
         .map_err(|e| format!("解析 JSON 失败: {}", e))?;
 
         let now = chrono::Utc::now().timestamp();
+        let reason = truncate_reason(reason, 800);
         content["disabled"] = serde_json::Value::Bool(true);
         content["disabled_at"] = serde_json::Value::Number(now.into());
-        content["disabled_reason"] = serde_json::Value::String(truncate_reason(reason, 800));
+        content["disabled_reason"] = serde_json::Value::String(reason.clone());
+        // 同时写入反代停用类别，号池快照与账号列表可据此区分授权失效
+        crate::modules::proxy_disable::disable_json(
+            &mut content,
+            crate::models::ProxyDisableCategory::InvalidGrant,
+            reason,
+            now,
+        );
 
-        std::fs::write(&path, serde_json::to_string_pretty(&content).unwrap())
-            .map_err(|e| format!("写入文件失败: {}", e))?;
+        crate::modules::account::write_account_json(&path, &content)?;
 
         tracing::warn!("Account disabled: {} ({:?})", account_id, path);
+        self.record_reliability(account_id, ReliabilityEventKind::Disabled, false, Some(reason.to_string()));
+        let email = content["email"].as_str().unwrap_or(account_id);
+        crate::modules::account_notifier::notify_account_disabled(account_id, email, reason);
         Ok(())
     }
 
+    /// 记录账号可靠性事件 (写入数据目录下的 sidecar 文件)
+    fn record_reliability(&self, account_id: &str, kind: ReliabilityEventKind, success: bool, detail: Option<String>) {
+        crate::modules::account_reliability::record_in(&self.data_dir, account_id, kind, success, detail);
+    }
+
     /// 保存 project_id 到账号文件
     async fn save_project_id(&self, account_id: &str, project_id: &str) -> Result<(), String> {
         let entry = self.tokens.get(account_id)
@@ -768,43 +1253,140 @@ impl TokenManager {
             &std::fs::read_to_string(path).map_err(|e| format!("读取文件失败: {}", e))?
         ).map_err(|e| format!("解析 JSON 失败: {}", e))?;
         
-        content["token"]["project_id"] = serde_json::Value::String(project_id.to_string());
+        crate::modules::token_crypto::update_token_fields(&mut content, |token| {
+            token["project_id"] = serde_json::Value::String(project_id.to_string());
+        })?;
         
-        std::fs::write(path, serde_json::to_string_pretty(&content).unwrap())
-            .map_err(|e| format!("写入文件失败: {}", e))?;
+        crate::modules::account::write_account_json(path, &content)?;
         
         tracing::debug!("已保存 project_id 到账号 {}", account_id);
         Ok(())
     }
     
-    /// 保存刷新后的 token 到账号文件
-    async fn save_refreshed_token(&self, account_id: &str, token_response: &crate::modules::oauth::TokenResponse) -> Result<(), String> {
-        let entry = self.tokens.get(account_id)
-            .ok_or("账号不存在")?;
-        
-        let path = &entry.account_path;
-        
-        let mut content: serde_json::Value = serde_json::from_str(
-            &std::fs::read_to_string(path).map_err(|e| format!("读取文件失败: {}", e))?
-        ).map_err(|e| format!("解析 JSON 失败: {}", e))?;
-        
-        let now = chrono::Utc::now().timestamp();
-        
-        content["token"]["access_token"] = serde_json::Value::String(token_response.access_token.clone());
-        content["token"]["expires_in"] = serde_json::Value::Number(token_response.expires_in.into());
-        content["token"]["expiry_timestamp"] = serde_json::Value::Number((now + token_response.expires_in).into());
-        
-        std::fs::write(path, serde_json::to_string_pretty(&content).unwrap())
-            .map_err(|e| format!("写入文件失败: {}", e))?;
-        
-        tracing::debug!("已保存刷新后的 token 到账号 {}", account_id);
-        Ok(())
+    /// 将刷新后的 token 提交到后台落盘队列 (立即返回，不等待磁盘写入)
+    fn persist_refreshed_token(&self, account_id: &str, access_token: &str, expires_in: i64, expiry_timestamp: i64) {
+        let (account_path, refresh_token) = match self.tokens.get(account_id) {
+            Some(entry) => (entry.account_path.clone(), entry.refresh_token.clone()),
+            None => {
+                tracing::debug!("账号 {} 已不在池中，跳过 token 落盘", account_id);
+                return;
+            }
+        };
+
+        self.persister.enqueue(TokenUpdate {
+            account_id: account_id.to_string(),
+            account_path,
+            refresh_token,
+            access_token: access_token.to_string(),
+            expires_in,
+            expiry_timestamp,
+        });
+        // 顺带提交积压的使用时间
+        self.flush_proxy_usage();
+    }
+
+    /// 等待所有待落盘的 token 写入磁盘 (停止服务 / 退出应用前调用)
+    pub async fn flush_token_state(&self) {
+        self.flush_proxy_usage();
+        self.persister.flush().await;
+    }
+
+    /// 记录账号被反代选中 (只更新内存，落盘随后续持久化或定期刷新批量完成)
+    fn record_proxy_use(&self, email: &str) {
+        let Some(account_id) = self.tokens.iter().find(|t| t.email == email).map(|t| t.account_id.clone()) else {
+            return;
+        };
+        self.proxy_usage.insert(account_id.clone(), chrono::Utc::now().timestamp());
+        self.usage_dirty.lock().unwrap_or_else(|e| e.into_inner()).insert(account_id);
+    }
+
+    /// 账号最近一次被反代使用的时间 (内存与账号文件中较新的一个)，从未使用时为 None
+    pub fn last_proxy_used_at(&self, account_id: &str) -> Option<i64> {
+        let in_memory = self.proxy_usage.get(account_id).map(|v| *v);
+        let on_disk = self.tokens.get(account_id).and_then(|t| t.last_proxy_used_at);
+        in_memory.max(on_disk)
+    }
+
+    /// 将尚未落盘的使用时间提交到持久化队列 (模拟模式下不写文件)
+    pub fn flush_proxy_usage(&self) {
+        if self.mock_mode.load(Ordering::SeqCst) {
+            return;
+        }
+        let dirty: Vec<String> = self.usage_dirty.lock().unwrap_or_else(|e| e.into_inner()).drain().collect();
+        for account_id in dirty {
+            let (Some(used_at), Some(account_path)) = (
+                self.proxy_usage.get(&account_id).map(|v| *v),
+                self.tokens.get(&account_id).map(|t| t.account_path.clone()),
+            ) else {
+                continue;
+            };
+            let queued = self.persister.enqueue_usage(UsageUpdate {
+                account_id: account_id.clone(),
+                account_path,
+                last_proxy_used_at: used_at,
+            });
+            if !queued {
+                // 队列繁忙，留待下次刷新
+                self.usage_dirty.lock().unwrap_or_else(|e| e.into_inner()).insert(account_id);
+            }
+        }
     }
     
     pub fn len(&self) -> usize {
         self.tokens.len()
     }
 
+    /// 号池账号状态计数 (仅读取内存状态，供指标导出)
+    pub fn account_state_counts(&self) -> crate::proxy::metrics::AccountStateCounts {
+        let mut counts = crate::proxy::metrics::AccountStateCounts {
+            loaded: self.tokens.len(),
+            ..Default::default()
+        };
+        for entry in self.tokens.iter() {
+            let token = entry.value();
+            // 限流记录可能以账号 ID 或邮箱为键
+            let keys = [token.account_id.as_str(), token.email.as_str()];
+            if keys.iter().any(|k| self.rate_limit_tracker.is_benched(k)) {
+                counts.disabled += 1;
+            } else if keys.iter().any(|k| self.rate_limit_tracker.is_rate_limited(k)) {
+                counts.rate_limited += 1;
+            }
+        }
+        counts
+    }
+
+    /// 获取指定账号及号池的速率限制提示 (仅读取内存状态)
+    /// 未知的字段保持为 None，由调用方决定省略
+    pub fn get_rate_limit_hints(&self, email: &str) -> RateLimitHints {
+        let mut hints = RateLimitHints::default();
+        let mut pool_total: i64 = 0;
+        let mut pool_known: i64 = 0;
+
+        for entry in self.tokens.iter() {
+            let token = entry.value();
+            if let Some(percent) = token.quota_percent {
+                pool_total += percent as i64;
+                pool_known += 1;
+            }
+            if token.email == email {
+                hints.remaining_percent = token.quota_percent;
+                hints.reset_time = token.quota_reset_time.clone();
+            }
+        }
+
+        if pool_known > 0 {
+            hints.pool_remaining_percent = Some((pool_total / pool_known) as i32);
+        }
+        hints
+    }
+
+    /// 账号是否设置了不预热 (按邮箱匹配，未知账号视为允许)
+    pub fn is_warmup_disabled(&self, email: &str) -> bool {
+        self.tokens
+            .iter()
+            .any(|entry| entry.value().email == email && entry.value().no_warmup)
+    }
+
     /// 通过 email 获取指定账号的 Token（用于预热等需要指定账号的场景）
     /// 此方法会自动刷新过期的 token
     pub async fn get_token_by_email(&self, email: &str) -> Result<(String, String, String), String> {
@@ -819,7 +1401,6 @@ impl TokenManager {
                         token.access_token.clone(),
                         token.refresh_token.clone(),
                         token.timestamp,
-                        token.expires_in,
                         chrono::Utc::now().timestamp(),
                         token.project_id.clone(),
                     ));
@@ -834,7 +1415,6 @@ impl TokenManager {
             current_access_token,
             refresh_token,
             timestamp,
-            expires_in,
             now,
             project_id_opt,
         ) = match token_info {
@@ -844,8 +1424,8 @@ impl TokenManager {
 
         let project_id = project_id_opt.unwrap_or_else(|| "bamboo-precept-lgxtn".to_string());
         
-        // 检查是否过期 (提前5分钟)
-        if now < timestamp + expires_in - 300 {
+        // 检查是否过期 (提前5分钟，timestamp 为过期时间)
+        if !needs_refresh(timestamp, now) {
             return Ok((current_access_token, project_id, email.to_string()));
         }
 
@@ -855,24 +1435,81 @@ impl TokenManager {
         match crate::modules::oauth::refresh_access_token(&refresh_token).await {
             Ok(token_response) => {
                 tracing::info!("[Warmup] Token refresh successful for {}", email);
+                self.record_reliability(&account_id, ReliabilityEventKind::Refresh, true, None);
                 let new_now = chrono::Utc::now().timestamp();
                 
                 // 更新缓存
                 if let Some(mut entry) = self.tokens.get_mut(&account_id) {
                     entry.access_token = token_response.access_token.clone();
                     entry.expires_in = token_response.expires_in;
-                    entry.timestamp = new_now;
+                    entry.timestamp = new_now + token_response.expires_in;
                 }
 
                 // 保存到磁盘
-                let _ = self.save_refreshed_token(&account_id, &token_response).await;
+                self.persist_refreshed_token(
+                    &account_id,
+                    &token_response.access_token,
+                    token_response.expires_in,
+                    new_now + token_response.expires_in,
+                );
 
                 Ok((token_response.access_token, project_id, email.to_string()))
             }
-            Err(e) => Err(format!("[Warmup] Token refresh failed for {}: {}", email, e)),
+            Err(e) => {
+                self.record_reliability(&account_id, ReliabilityEventKind::Refresh, false, Some(e.clone()));
+                Err(format!("[Warmup] Token refresh failed for {}: {}", email, e))
+            }
         }
     }
     
+    /// 以号池为准获取账号的有效 Token (临近过期时由号池刷新并落盘)
+    /// 供配额查询等反代之外的入口复用，避免与反代各自刷新同一账号。账号不在号池中时返回 None
+    pub async fn fresh_token_data(&self, email: &str, base: &crate::models::TokenData) -> Option<Result<crate::models::TokenData, String>> {
+        if !self.tokens.iter().any(|entry| entry.email == email) {
+            return None;
+        }
+        if let Err(e) = self.get_token_by_email(email).await {
+            return Some(Err(e));
+        }
+        let entry = self.tokens.iter().find(|entry| entry.email == email)?;
+        Some(Ok(crate::models::TokenData {
+            access_token: entry.access_token.clone(),
+            expires_in: entry.expires_in,
+            expiry_timestamp: entry.timestamp,
+            project_id: entry.project_id.clone().or_else(|| base.project_id.clone()),
+            ..base.clone()
+        }))
+    }
+
+    /// 号池中的账号 (按邮箱排序)，供刷新耗时基准使用内存中的最新 token
+    pub fn refresh_targets(&self) -> Vec<crate::modules::refresh_benchmark::RefreshTarget> {
+        let mut targets: Vec<_> = self
+            .tokens
+            .iter()
+            .map(|entry| crate::modules::refresh_benchmark::RefreshTarget {
+                account_id: entry.account_id.clone(),
+                email: entry.email.clone(),
+                refresh_token: entry.refresh_token.clone(),
+                expiry_timestamp: entry.timestamp,
+            })
+            .collect();
+        targets.sort_by(|a, b| a.email.cmp(&b.email));
+        targets
+    }
+
+    /// 应用号池之外完成的 token 刷新：更新内存中的 token 并提交落盘
+    pub fn apply_refreshed_token(&self, account_id: &str, access_token: &str, expires_in: i64, expiry_timestamp: i64) {
+        match self.tokens.get_mut(account_id) {
+            Some(mut entry) => {
+                entry.access_token = access_token.to_string();
+                entry.expires_in = expires_in;
+                entry.timestamp = expiry_timestamp;
+            }
+            None => return,
+        }
+        self.persist_refreshed_token(account_id, access_token, expires_in, expiry_timestamp);
+    }
+
     // ===== 限流管理方法 =====
     
     /// 标记账号限流(从外部调用,通常在 handler 中)
@@ -897,6 +1534,16 @@ impl TokenManager {
         self.rate_limit_tracker.is_rate_limited(account_id)
     }
     
+    /// 账号是否处于锁定状态 (限流记录可能以 account_id 或 email 为 key)
+    fn is_token_locked(&self, token: &ProxyToken) -> bool {
+        self.is_rate_limited(&token.account_id) || self.is_rate_limited(&token.email)
+    }
+
+    /// 本次运行中备用账号被启用的次数
+    pub fn standby_activations(&self) -> u64 {
+        self.standby_activations.load(Ordering::Relaxed)
+    }
+
     /// 获取距离限流重置还有多少秒
     #[allow(dead_code)]
     pub fn get_rate_limit_reset_seconds(&self, account_id: &str) -> Option<u64> {
@@ -922,6 +1569,93 @@ impl TokenManager {
     pub fn mark_account_success(&self, account_id: &str) {
         self.rate_limit_tracker.mark_success(account_id);
     }
+
+    /// 记录账号的一次失败 (网络错误、超时、429、5xx 等)
+    ///
+    /// 连续失败达到阈值后账号将被临时停用，原因标记为 "unhealthy"
+    pub fn mark_account_failure(&self, account_id: &str) {
+        self.rate_limit_tracker.record_failure(account_id);
+    }
+
+    /// 最后探测的候选账号：允许服务该配额分组、锁定最快到期的账号 (account_id, email)，没有时为 None
+    /// 与常规选择一致，主号池有候选时不探测备用账号
+    /// 限流记录在不同路径下分别以 account_id 或 email 为键，两者都要考虑
+    pub fn probe_candidate(&self, group: QuotaGroup) -> Option<(String, String)> {
+        self.tokens
+            .iter()
+            .filter(|t| t.serves(group))
+            .filter(|t| self.is_probeable_lockout(&t.account_id, &t.email))
+            .filter_map(|t| {
+                let wait = self
+                    .rate_limit_tracker
+                    .get_reset_seconds(&t.account_id)
+                    .max(self.rate_limit_tracker.get_reset_seconds(&t.email))?;
+                Some((t.standby, wait, t.account_id.clone(), t.email.clone()))
+            })
+            .min_by(|a, b| (a.0, a.1, &a.2).cmp(&(b.0, b.1, &b.2)))
+            .map(|(_, _, account_id, email)| (account_id, email))
+    }
+
+    /// 账号 (两种键) 上生效中的锁定是否都可能由上游突发错误误判，配额类锁定不参与探测
+    fn is_probeable_lockout(&self, account_id: &str, email: &str) -> bool {
+        let keys = [account_id, email];
+        let locked: Vec<&str> = keys
+            .into_iter()
+            .filter(|key| self.rate_limit_tracker.is_rate_limited(key))
+            .collect();
+        !locked.is_empty() && locked.iter().all(|key| self.rate_limit_tracker.is_probeable(key))
+    }
+
+    /// 占用一次最后探测的机会：距上次探测不足 `min_interval` 时返回 false
+    pub fn try_acquire_probe_slot(&self, min_interval: std::time::Duration) -> bool {
+        let now = self.rate_limit_tracker.clock().now();
+        let mut last = self.last_probe_at.lock().unwrap_or_else(|e| e.into_inner());
+        let allowed = last.map_or(true, |at| now.duration_since(at).map_or(true, |elapsed| elapsed >= min_interval));
+        if allowed {
+            *last = Some(now);
+        }
+        allowed
+    }
+
+    /// 探测成功后解除账号的限流与停用 (两种键都清除)，并重置连续失败计数
+    pub fn release_probed_account(&self, account_id: &str, email: &str) {
+        self.rate_limit_tracker.mark_success(account_id);
+        self.rate_limit_tracker.mark_success(email);
+    }
+
+    /// 获取所有生效中的限流与不健康停用记录
+    ///
+    /// 跟踪器键可能是账号 ID 也可能是邮箱，这里统一解析为真实账号 ID 并单独给出邮箱
+    pub fn get_rate_limit_status(&self) -> Vec<crate::proxy::rate_limit::RateLimitStatusEntry> {
+        let mut entries = self.rate_limit_tracker.status_snapshot();
+        for entry in entries.iter_mut() {
+            let resolved = self.tokens.iter().find_map(|t| {
+                (t.account_id == entry.key || t.email == entry.key)
+                    .then(|| (t.account_id.clone(), t.email.clone()))
+            });
+            match resolved {
+                Some((account_id, email)) => {
+                    entry.account_id = account_id;
+                    entry.email = Some(email);
+                }
+                None if entry.key.contains('@') => {
+                    entry.email = Some(entry.key.clone());
+                }
+                None => {}
+            }
+        }
+        entries
+    }
+
+    /// 当前处于锁定期的账号快照 (跟踪器键 -> 剩余秒数，同一键取较晚者)
+    pub fn lockout_snapshot(&self) -> std::collections::HashMap<String, u64> {
+        let mut lockouts = std::collections::HashMap::new();
+        for entry in self.rate_limit_tracker.status_snapshot() {
+            let remaining = lockouts.entry(entry.key).or_insert(0);
+            *remaining = (*remaining).max(entry.remaining_seconds);
+        }
+        lockouts
+    }
     
     /// 从账号文件获取配额刷新时间
     /// 
@@ -1137,10 +1871,42 @@ impl TokenManager {
     /// 更新调度配置
     pub async fn update_sticky_config(&self, new_config: StickySessionConfig) {
         let mut config = self.sticky_config.write().await;
+        self.rate_limit_tracker.set_unhealthy_threshold(new_config.unhealthy_failure_threshold);
         *config = new_config;
         tracing::debug!("Scheduling configuration updated: {:?}", *config);
     }
 
+    /// 设置日志与响应头中的账号标识方式
+    pub fn set_account_identifier_mode(&self, mode: AccountIdentifierMode) {
+        if let Ok(mut current) = self.identifier_mode.write() {
+            *current = mode;
+        }
+        self.rate_limit_tracker.set_identifier_mode(mode);
+    }
+
+    fn current_identifier_mode(&self) -> AccountIdentifierMode {
+        self.identifier_mode.read().map(|m| *m).unwrap_or_default()
+    }
+
+    /// 获取账号在日志与响应头中的标识 (邮箱 / 标签 / 哈希)
+    pub fn account_identifier(&self, email: &str) -> String {
+        let mode = self.current_identifier_mode();
+        let label = match mode {
+            AccountIdentifierMode::Label => self
+                .tokens
+                .iter()
+                .find(|t| t.email == email)
+                .and_then(|t| t.label.clone()),
+            _ => None,
+        };
+        crate::proxy::common::account_identity::account_identifier(mode, email, label.as_deref())
+    }
+
+    /// 获取携带账号标识的响应头名称
+    pub fn account_header_name(&self) -> &'static str {
+        crate::proxy::common::account_identity::header_name(self.current_identifier_mode())
+    }
+
     /// 清除特定会话的粘性映射
     #[allow(dead_code)]
     pub fn clear_session_binding(&self, session_id: &str) {
@@ -1151,6 +1917,300 @@ impl TokenManager {
     pub fn clear_all_sessions(&self) {
         self.session_accounts.clear();
     }
+
+    /// 按策略重新平衡会话与账号的绑定
+    ///
+    /// 仅修改粘性映射表，正在进行中的请求已持有其 token，不受影响；
+    /// 只有后续请求会看到新的绑定。
+    pub fn rebalance_sessions(&self, strategy: SessionRebalanceStrategy) -> SessionRebalanceSummary {
+        let mut summary = SessionRebalanceSummary::default();
+
+        match strategy {
+            SessionRebalanceStrategy::DropAll => {
+                self.session_accounts.retain(|_, _| {
+                    summary.dropped += 1;
+                    false
+                });
+            }
+            SessionRebalanceStrategy::MoveLowestQuota => {
+                let accounts: Vec<(String, String, Option<i32>)> = self
+                    .tokens
+                    .iter()
+                    .map(|e| (e.account_id.clone(), e.email.clone(), e.remaining_quota))
+                    .collect();
+
+                let mut quotas: Vec<i32> = accounts.iter().filter_map(|(_, _, q)| *q).collect();
+                quotas.sort_unstable();
+                let cutoff = quotas
+                    .get(((quotas.len().saturating_sub(1)) as f64 * REBALANCE_LOW_QUOTA_PERCENTILE) as usize)
+                    .copied();
+
+                let low_accounts: HashSet<String> = match cutoff {
+                    Some(c) => accounts
+                        .iter()
+                        .filter(|(_, _, q)| q.map_or(false, |q| q <= c))
+                        .map(|(id, _, _)| id.clone())
+                        .collect(),
+                    None => HashSet::new(),
+                };
+
+                // 目标账号：配额高于分位线且当前未被限流，按剩余配额降序
+                let mut targets: Vec<(String, i32)> = accounts
+                    .iter()
+                    .filter_map(|(id, email, q)| {
+                        let q = (*q)?;
+                        if low_accounts.contains(id) || self.is_rate_limited(id) || self.is_rate_limited(email) {
+                            return None;
+                        }
+                        Some((id.clone(), q))
+                    })
+                    .collect();
+                targets.sort_by(|a, b| b.1.cmp(&a.1));
+
+                let bindings: Vec<(String, String)> = self
+                    .session_accounts
+                    .iter()
+                    .map(|e| (e.key().clone(), e.value().clone()))
+                    .collect();
+
+                let mut next_target = 0usize;
+                for (sid, bound_id) in bindings {
+                    if !self.tokens.contains_key(&bound_id) {
+                        // 绑定的账号已不存在，直接解绑
+                        if self.session_accounts.remove_if(&sid, |_, v| *v == bound_id).is_some() {
+                            summary.dropped += 1;
+                        }
+                        continue;
+                    }
+
+                    if !low_accounts.contains(&bound_id) || targets.is_empty() {
+                        summary.kept += 1;
+                        continue;
+                    }
+
+                    let target = &targets[next_target % targets.len()].0;
+                    // 单会话原子替换：仅当绑定未被并发请求修改时才改绑
+                    match self.session_accounts.get_mut(&sid) {
+                        Some(mut entry) if *entry == bound_id => {
+                            *entry = target.clone();
+                            next_target += 1;
+                            summary.moved += 1;
+                            tracing::debug!("Session {} rebound from {} to {}", sid, bound_id, target);
+                        }
+                        Some(_) => summary.kept += 1,
+                        None => {}
+                    }
+                }
+            }
+        }
+
+        tracing::info!(
+            "Session rebalance ({:?}): moved={}, kept={}, dropped={}",
+            strategy,
+            summary.moved,
+            summary.kept,
+            summary.dropped
+        );
+        summary
+    }
+
+    /// 号池中各账号的容量评分，按评分从高到低排列
+    pub async fn capacity_scores(&self) -> Vec<AccountCapacity> {
+        let weights = self.sticky_config.read().await.tier_weights.clone();
+        let mut scores: Vec<AccountCapacity> = self
+            .tokens
+            .iter()
+            .map(|t| AccountCapacity {
+                account_id: t.account_id.clone(),
+                email: t.email.clone(),
+                subscription_tier: t.subscription_tier.clone(),
+                quota_percent: t.quota_percent,
+                capacity_score: t.capacity_score(&weights),
+            })
+            .collect();
+        scores.sort_by(|a, b| b.capacity_score.cmp(&a.capacity_score).then_with(|| a.email.cmp(&b.email)));
+        scores
+    }
+
+    /// 导出当前运行时调度状态 (号池概要、限流记录、会话绑定与调度配置)，不包含任何 token
+    pub async fn snapshot_state(&self) -> ProxyStateSnapshot {
+        let mut tokens: Vec<TokenSnapshotEntry> = self
+            .tokens
+            .iter()
+            .map(|t| TokenSnapshotEntry {
+                account_id: t.account_id.clone(),
+                email: t.email.clone(),
+                label: t.label.clone(),
+                project_id: t.project_id.clone(),
+                subscription_tier: t.subscription_tier.clone(),
+                remaining_quota: t.remaining_quota,
+                quota_reset_time: t.quota_reset_time.clone(),
+                token_expiry_timestamp: t.timestamp,
+                // 遍历 tokens 期间不再重入 tokens，直接合并内存记录
+                last_proxy_used_at: self.proxy_usage.get(&t.account_id).map(|v| *v).max(t.last_proxy_used_at),
+            })
+            .collect();
+        tokens.sort_by(|a, b| a.account_id.cmp(&b.account_id));
+
+        ProxyStateSnapshot {
+            captured_at: chrono::Utc::now().timestamp(),
+            tokens,
+            disabled: self.disabled_accounts(),
+            rate_limits: self.rate_limit_tracker.snapshot(),
+            session_bindings: self
+                .session_accounts
+                .iter()
+                .map(|r| (r.key().clone(), r.value().clone()))
+                .collect(),
+            scheduling: self.get_sticky_config().await,
+        }
+    }
+
+    /// 磁盘上未进入号池的停用账号及其停用类别
+    fn disabled_accounts(&self) -> Vec<DisabledSnapshotEntry> {
+        let Ok(entries) = std::fs::read_dir(self.data_dir.join("accounts")) else {
+            return Vec::new();
+        };
+        let mut disabled: Vec<DisabledSnapshotEntry> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().and_then(|s| s.to_str()) == Some("json"))
+            .filter_map(|p| {
+                let account: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&p).ok()?).ok()?;
+                let text = |key: &str| account.get(key).and_then(|v| v.as_str()).map(str::to_string);
+                let (category, reason) = match crate::modules::proxy_disable::category_of_json(&account) {
+                    Some(category) => (category, text("proxy_disabled_reason")),
+                    // 旧版本只写入了账号级停用 (invalid_grant)
+                    None if account.get("disabled").and_then(|v| v.as_bool()).unwrap_or(false) => {
+                        let reason = text("disabled_reason");
+                        (crate::modules::proxy_disable::categorize(reason.as_deref(), false), reason)
+                    }
+                    None => return None,
+                };
+                Some(DisabledSnapshotEntry {
+                    account_id: text("id")?,
+                    email: text("email").unwrap_or_default(),
+                    category,
+                    reason,
+                })
+            })
+            .collect();
+        disabled.sort_by(|a, b| a.account_id.cmp(&b.account_id));
+        disabled
+    }
+
+    /// 从快照恢复限流与会话绑定状态 (号池与调度配置仅作参考，不会被覆盖)
+    pub fn restore_state(&self, snapshot: &ProxyStateSnapshot) -> ProxyStateRestoreSummary {
+        self.rate_limit_tracker.restore(&snapshot.rate_limits);
+
+        self.session_accounts.clear();
+        for (session_id, account_id) in &snapshot.session_bindings {
+            self.session_accounts.insert(session_id.clone(), account_id.clone());
+        }
+
+        let unknown_accounts = snapshot
+            .session_bindings
+            .values()
+            .filter(|id| !self.tokens.contains_key(*id))
+            .collect::<HashSet<_>>()
+            .len();
+
+        let summary = ProxyStateRestoreSummary {
+            rate_limits: snapshot.rate_limits.limits.len() + snapshot.rate_limits.benched.len(),
+            sessions: snapshot.session_bindings.len(),
+            unknown_accounts,
+        };
+        tracing::info!(
+            "Proxy state restored: rate_limits={}, sessions={}, unknown_accounts={}",
+            summary.rate_limits,
+            summary.sessions,
+            summary.unknown_accounts
+        );
+        summary
+    }
+}
+
+/// 号池中单个账号的概要 (不含 access_token / refresh_token)
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TokenSnapshotEntry {
+    pub account_id: String,
+    pub email: String,
+    pub label: Option<String>,
+    pub project_id: Option<String>,
+    pub subscription_tier: Option<String>,
+    pub remaining_quota: Option<i32>,
+    pub quota_reset_time: Option<String>,
+    /// access_token 的过期时间戳
+    pub token_expiry_timestamp: i64,
+    /// 最近一次被反代使用的时间 (从未使用为 None)
+    #[serde(default)]
+    pub last_proxy_used_at: Option<i64>,
+}
+
+/// 快照中的停用账号
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DisabledSnapshotEntry {
+    pub account_id: String,
+    pub email: String,
+    pub category: crate::models::ProxyDisableCategory,
+    pub reason: Option<String>,
+}
+
+/// 反代运行时状态快照 (用于复现 issue 中报告的调度卡死等问题)
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ProxyStateSnapshot {
+    pub captured_at: i64,
+    pub tokens: Vec<TokenSnapshotEntry>,
+    /// 未进入号池的停用账号 (旧快照中没有该字段)
+    #[serde(default)]
+    pub disabled: Vec<DisabledSnapshotEntry>,
+    pub rate_limits: crate::proxy::rate_limit::RateLimitTrackerSnapshot,
+    /// 会话 ID -> 账号 ID
+    pub session_bindings: std::collections::BTreeMap<String, String>,
+    pub scheduling: StickySessionConfig,
+}
+
+/// 状态恢复结果
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct ProxyStateRestoreSummary {
+    /// 恢复的限流与停用记录数
+    pub rate_limits: usize,
+    /// 恢复的会话绑定数
+    pub sessions: usize,
+    /// 会话绑定指向当前号池中不存在的账号数
+    pub unknown_accounts: usize,
+}
+
+/// 低配额账号的分位线 (剩余配额处于该分位及以下的账号视为低配额)
+const REBALANCE_LOW_QUOTA_PERCENTILE: f64 = 0.25;
+
+/// 会话重平衡策略
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionRebalanceStrategy {
+    /// 清除全部绑定
+    DropAll,
+    /// 将绑定在低配额账号上的会话迁移到高配额账号
+    MoveLowestQuota,
+}
+
+impl std::str::FromStr for SessionRebalanceStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop_all" => Ok(Self::DropAll),
+            "move_lowest_quota" => Ok(Self::MoveLowestQuota),
+            other => Err(format!("未知的重平衡策略: {} (可选: drop_all, move_lowest_quota)", other)),
+        }
+    }
+}
+
+/// 会话重平衡结果
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct SessionRebalanceSummary {
+    pub moved: usize,
+    pub kept: usize,
+    pub dropped: usize,
 }
 
 fn truncate_reason(reason: &str, max_len: usize) -> String {
@@ -1161,3 +2221,813 @@ fn truncate_reason(reason: &str, max_len: usize) -> String {
     s.push('…');
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_test_account(dir: &std::path::Path, id: &str, expiry_timestamp: i64) -> PathBuf {
+        let path = dir.join("accounts").join(format!("{}.json", id));
+        let account = serde_json::json!({
+            "id": id,
+            "email": format!("{}@example.com", id),
+            "token": {
+                "access_token": "old-access",
+                "refresh_token": "refresh",
+                "expires_in": 3600,
+                "expiry_timestamp": expiry_timestamp,
+                "project_id": "test-project"
+            }
+        });
+        std::fs::write(&path, serde_json::to_string_pretty(&account).unwrap()).unwrap();
+        path
+    }
+
+    fn temp_data_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ag_token_manager_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("accounts")).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_refreshed_token_visible_on_disk_after_flush() {
+        let dir = temp_data_dir();
+        let path = write_test_account(&dir, "acc1", 1_000);
+
+        let manager = TokenManager::new(dir.clone());
+        assert_eq!(manager.load_accounts().await.unwrap(), 1);
+
+        let new_expiry = chrono::Utc::now().timestamp() + 3600;
+        manager.persist_refreshed_token("acc1", "new-access", 3600, new_expiry);
+        manager.flush_token_state().await;
+
+        let on_disk: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(on_disk["token"]["expiry_timestamp"].as_i64(), Some(new_expiry));
+        assert_eq!(on_disk["token"]["access_token"].as_str(), Some("new-access"));
+        // 其余字段保持不变
+        assert_eq!(on_disk["token"]["refresh_token"].as_str(), Some("refresh"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_needs_refresh_treats_timestamp_as_expiry() {
+        let now = 1_700_000_000;
+        assert!(!needs_refresh(now + 3600, now));
+        assert!(needs_refresh(now + TOKEN_REFRESH_LEAD_SECS, now));
+        // 已过期的 token 必须刷新 (旧逻辑把 timestamp 当作签发时间再加 expires_in，会继续使用过期 token)
+        assert!(needs_refresh(now - 10, now));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_targets_follow_live_pool() {
+        let manager = TokenManager::new(temp_data_dir());
+        insert_test_token(&manager, "acc2", None);
+        insert_test_token(&manager, "acc1", None);
+
+        let targets = manager.refresh_targets();
+        assert_eq!(
+            targets.iter().map(|t| t.account_id.as_str()).collect::<Vec<_>>(),
+            vec!["acc1", "acc2"]
+        );
+        assert_eq!(targets[0].refresh_token, "refresh");
+
+        let expiry = chrono::Utc::now().timestamp() + 7200;
+        manager.apply_refreshed_token("acc1", "benchmarked", 7200, expiry);
+        let entry = manager.tokens.get("acc1").unwrap();
+        assert_eq!(entry.access_token, "benchmarked");
+        assert_eq!(entry.timestamp, expiry);
+    }
+
+    #[test]
+    fn test_rate_limit_status_resolves_account_id_and_email() {
+        let manager = TokenManager::new(temp_data_dir());
+        insert_test_token(&manager, "acc1", None);
+        insert_test_token(&manager, "acc2", None);
+        let until = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        let reason = crate::proxy::rate_limit::RateLimitReason::QuotaExhausted;
+        // 一条按邮箱记录，一条按账号 ID 记录，另一条无法解析
+        manager.rate_limit_tracker.set_lockout_until("acc1@example.com", until, reason, None);
+        manager.rate_limit_tracker.set_lockout_until("acc2", until, reason, None);
+        manager.rate_limit_tracker.set_lockout_until("gone@example.com", until, reason, None);
+
+        let status = manager.get_rate_limit_status();
+        let find = |key: &str| status.iter().find(|e| e.key == key).unwrap();
+        assert_eq!(find("acc1@example.com").account_id, "acc1");
+        assert_eq!(find("acc1@example.com").email.as_deref(), Some("acc1@example.com"));
+        assert_eq!(find("acc2").account_id, "acc2");
+        assert_eq!(find("acc2").email.as_deref(), Some("acc2@example.com"));
+        assert_eq!(find("gone@example.com").email.as_deref(), Some("gone@example.com"));
+
+        // 锁定快照仍按跟踪器键统计
+        assert!(manager.lockout_snapshot().contains_key("acc1@example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_fresh_token_data_reuses_pool_token_without_refresh() {
+        let dir = temp_data_dir();
+        let expiry = chrono::Utc::now().timestamp() + 3600;
+        write_test_account(&dir, "acc1", expiry);
+
+        let manager = TokenManager::new(dir.clone());
+        assert_eq!(manager.load_accounts().await.unwrap(), 1);
+
+        let base = crate::models::TokenData::new(
+            "stale-access".to_string(),
+            "refresh".to_string(),
+            3600,
+            Some("acc1@example.com".to_string()),
+            None,
+            None,
+        );
+        let token = manager.fresh_token_data("acc1@example.com", &base).await.unwrap().unwrap();
+        assert_eq!(token.access_token, "old-access");
+        assert_eq!(token.expiry_timestamp, expiry);
+        assert_eq!(token.project_id.as_deref(), Some("test-project"));
+        // 未临近过期，不应发生刷新
+        assert!(crate::modules::account_reliability::load_history(&dir, "acc1").is_empty());
+
+        // 不在号池中的账号交给调用方独立处理
+        assert!(manager.fresh_token_data("other@example.com", &base).await.is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_usage_recorded_in_memory_and_flushed_lazily() {
+        let dir = temp_data_dir();
+        let path = write_test_account(&dir, "acc1", chrono::Utc::now().timestamp() + 3600);
+
+        let manager = TokenManager::new(dir.clone());
+        assert_eq!(manager.load_accounts().await.unwrap(), 1);
+        // 旧账号文件没有该字段，视为从未使用
+        assert_eq!(manager.last_proxy_used_at("acc1"), None);
+
+        let before = chrono::Utc::now().timestamp();
+        manager.get_token("claude", false, None).await.unwrap();
+        let used_at = manager.last_proxy_used_at("acc1").expect("recorded");
+        assert!(used_at >= before);
+        assert_eq!(manager.snapshot_state().await.tokens[0].last_proxy_used_at, Some(used_at));
+
+        // 选中账号本身不写文件
+        let on_disk: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(on_disk.get("last_proxy_used_at").is_none());
+
+        manager.flush_token_state().await;
+        let on_disk: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(on_disk["last_proxy_used_at"].as_i64(), Some(used_at));
+        assert_eq!(on_disk["token"]["access_token"].as_str(), Some("old-access"));
+
+        // 重载号池后仍能读到
+        assert_eq!(manager.load_accounts().await.unwrap(), 1);
+        assert_eq!(manager.last_proxy_used_at("acc1"), Some(used_at));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_persist_does_not_block_on_disk() {
+        let dir = temp_data_dir();
+        let valid_until = chrono::Utc::now().timestamp() + 3600;
+        for i in 0..50 {
+            write_test_account(&dir, &format!("acc{}", i), valid_until);
+        }
+
+        let manager = TokenManager::new(dir.clone());
+        assert_eq!(manager.load_accounts().await.unwrap(), 50);
+
+        // 连续提交大量更新 (远超单批容量)，调用方只做入队，不应等待任何磁盘 IO
+        let start = std::time::Instant::now();
+        for round in 0..20 {
+            for i in 0..50 {
+                manager.persist_refreshed_token(&format!("acc{}", i), "new-access", 3600, 10_000 + round);
+            }
+        }
+        let enqueue_elapsed = start.elapsed();
+        assert!(
+            enqueue_elapsed < std::time::Duration::from_millis(200),
+            "enqueue took {:?}",
+            enqueue_elapsed
+        );
+
+        // 获取 token 也不受落盘影响 (token 未过期，不触发刷新)
+        let start = std::time::Instant::now();
+        let _ = manager.get_token("claude", false, None).await;
+        assert!(start.elapsed() < std::time::Duration::from_millis(200));
+
+        manager.flush_token_state().await;
+        let on_disk: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(dir.join("accounts").join("acc7.json")).unwrap(),
+        )
+        .unwrap();
+        // 合并写入后保留的是最新一次更新
+        assert_eq!(on_disk["token"]["expiry_timestamp"].as_i64(), Some(10_019));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_expired_snooze_is_released_on_load() {
+        let dir = temp_data_dir();
+        let now = chrono::Utc::now().timestamp();
+        for (id, until) in [("expired", now - 1), ("snoozed", now + 3600)] {
+            let path = write_test_account(&dir, id, now + 3600);
+            let mut account: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            crate::modules::account_snooze::apply_snooze(&mut account, 60, None, until - 3600);
+            std::fs::write(&path, account.to_string()).unwrap();
+        }
+
+        let manager = TokenManager::new(dir.clone());
+        assert_eq!(manager.load_accounts().await.unwrap(), 1);
+        assert!(manager.tokens.contains_key("expired"));
+        assert_eq!(manager.release_expired_snoozes().await, vec!["expired".to_string()]);
+
+        let on_disk: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(dir.join("accounts").join("expired.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(on_disk["proxy_disabled"], false);
+        assert!(on_disk.get("proxy_disabled_until").is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn insert_test_token(manager: &TokenManager, id: &str, remaining_quota: Option<i32>) {
+        manager.tokens.insert(
+            id.to_string(),
+            ProxyToken {
+                account_id: id.to_string(),
+                access_token: "access".to_string(),
+                refresh_token: "refresh".to_string(),
+                expires_in: 3600,
+                timestamp: chrono::Utc::now().timestamp() + 3600,
+                email: format!("{}@example.com", id),
+                account_path: PathBuf::new(),
+                project_id: Some("test-project".to_string()),
+                subscription_tier: None,
+                remaining_quota,
+                quota_percent: None,
+                quota_reset_time: None,
+                label: None,
+                no_warmup: false,
+                standby: false,
+                last_proxy_used_at: None,
+                allowed_groups: Vec::new(),
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_drop_all_reports_count() {
+        let manager = TokenManager::new(temp_data_dir());
+        manager.session_accounts.insert("s1".to_string(), "a".to_string());
+        manager.session_accounts.insert("s2".to_string(), "b".to_string());
+
+        let summary = manager.rebalance_sessions(SessionRebalanceStrategy::DropAll);
+        assert_eq!(summary, SessionRebalanceSummary { moved: 0, kept: 0, dropped: 2 });
+        assert!(manager.session_accounts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_move_lowest_quota() {
+        let manager = TokenManager::new(temp_data_dir());
+        insert_test_token(&manager, "tired", Some(5));
+        insert_test_token(&manager, "fresh1", Some(100));
+        insert_test_token(&manager, "fresh2", Some(90));
+        insert_test_token(&manager, "limited", Some(200));
+        manager.rate_limit_tracker.parse_from_error("limited@example.com", 429, Some("60"), "", None);
+
+        manager.session_accounts.insert("s1".to_string(), "tired".to_string());
+        manager.session_accounts.insert("s2".to_string(), "tired".to_string());
+        manager.session_accounts.insert("s3".to_string(), "fresh1".to_string());
+        manager.session_accounts.insert("s4".to_string(), "deleted".to_string());
+
+        let summary = manager.rebalance_sessions(SessionRebalanceStrategy::MoveLowestQuota);
+        assert_eq!(summary, SessionRebalanceSummary { moved: 2, kept: 1, dropped: 1 });
+
+        // 低配额会话分散到最高配额的可用账号上，限流账号不作为目标
+        let mut moved: Vec<String> = ["s1", "s2"]
+            .iter()
+            .map(|s| manager.session_accounts.get(*s).unwrap().clone())
+            .collect();
+        moved.sort();
+        assert_eq!(moved, vec!["fresh1".to_string(), "fresh2".to_string()]);
+        assert_eq!(manager.session_accounts.get("s3").unwrap().as_str(), "fresh1");
+        assert!(manager.session_accounts.get("s4").is_none());
+    }
+
+    #[test]
+    fn test_rebalance_strategy_parse() {
+        assert_eq!("drop_all".parse::<SessionRebalanceStrategy>(), Ok(SessionRebalanceStrategy::DropAll));
+        assert_eq!(
+            "move_lowest_quota".parse::<SessionRebalanceStrategy>(),
+            Ok(SessionRebalanceStrategy::MoveLowestQuota)
+        );
+        assert!("shuffle".parse::<SessionRebalanceStrategy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lockout_snapshot_lists_locked_accounts() {
+        let manager = TokenManager::new(temp_data_dir());
+        assert!(manager.lockout_snapshot().is_empty());
+
+        manager.mark_rate_limited("locked@example.com", 429, Some("120"), "");
+        manager.mark_rate_limited("healthy@example.com", 429, Some("120"), "");
+        manager.mark_account_success("healthy@example.com");
+
+        let lockouts = manager.lockout_snapshot();
+        assert_eq!(lockouts.len(), 1);
+        assert!(lockouts["locked@example.com"] > 0);
+    }
+
+    #[tokio::test]
+    async fn test_state_snapshot_round_trips_rate_limits_and_sessions() {
+        let manager = TokenManager::new(temp_data_dir());
+        insert_test_token(&manager, "a", Some(50));
+        insert_test_token(&manager, "b", Some(10));
+        manager.mark_rate_limited("a@example.com", 429, Some("300"), "");
+        manager.mark_account_failure("b@example.com");
+        manager.session_accounts.insert("s1".to_string(), "a".to_string());
+        manager.session_accounts.insert("s2".to_string(), "b".to_string());
+
+        let snapshot = manager.snapshot_state().await;
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert!(!json.contains("access"), "snapshot must not contain tokens");
+        assert!(!json.contains("refresh"), "snapshot must not contain tokens");
+
+        // 状态被清空后从 JSON 恢复
+        let restored = TokenManager::new(temp_data_dir());
+        insert_test_token(&restored, "a", Some(50));
+        let parsed: ProxyStateSnapshot = serde_json::from_str(&json).unwrap();
+        let summary = restored.restore_state(&parsed);
+        assert_eq!(summary.sessions, 2);
+        assert_eq!(summary.unknown_accounts, 1);
+
+        assert_eq!(restored.rate_limit_tracker.snapshot(), snapshot.rate_limits);
+        assert!(restored.is_rate_limited("a@example.com"));
+        assert_eq!(restored.session_accounts.get("s1").unwrap().as_str(), "a");
+        assert_eq!(restored.session_accounts.get("s2").unwrap().as_str(), "b");
+        assert_eq!(restored.snapshot_state().await.session_bindings, snapshot.session_bindings);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_grant_disable_is_categorized_in_snapshot() {
+        let dir = temp_data_dir();
+        let manager = TokenManager::new(dir.clone());
+        let accounts = dir.join("accounts");
+        std::fs::write(
+            accounts.join("revoked.json"),
+            serde_json::json!({ "id": "revoked", "email": "revoked@example.com", "token": {} }).to_string(),
+        )
+        .unwrap();
+        std::fs::write(
+            accounts.join("manual.json"),
+            serde_json::json!({
+                "id": "manual", "email": "manual@example.com", "token": {},
+                "proxy_disabled": true, "proxy_disabled_reason": "testing"
+            })
+            .to_string(),
+        )
+        .unwrap();
+        // 旧版本只写入账号级停用
+        std::fs::write(
+            accounts.join("legacy.json"),
+            serde_json::json!({
+                "id": "legacy", "email": "legacy@example.com", "token": {},
+                "disabled": true, "disabled_reason": "invalid_grant: revoked"
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        manager.disable_account("revoked", "invalid_grant: Token has been revoked").await.unwrap();
+        let on_disk: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(accounts.join("revoked.json")).unwrap()).unwrap();
+        assert_eq!(on_disk["disabled"], true);
+        assert_eq!(on_disk["proxy_disabled"], true);
+        assert_eq!(on_disk["proxy_disabled_category"], "invalid_grant");
+
+        let disabled = manager.snapshot_state().await.disabled;
+        let categories: Vec<_> = disabled.iter().map(|d| (d.account_id.as_str(), d.category)).collect();
+        assert_eq!(
+            categories,
+            vec![
+                ("legacy", crate::models::ProxyDisableCategory::InvalidGrant),
+                ("manual", crate::models::ProxyDisableCategory::Manual),
+                ("revoked", crate::models::ProxyDisableCategory::InvalidGrant),
+            ]
+        );
+    }
+
+    /// 锁定账号 `duration` (限流记录可能以账号 ID 或邮箱为键，两者都锁定)
+    fn lock_account_for(manager: &TokenManager, id: &str, duration: std::time::Duration) {
+        lock_account_with_reason(manager, id, duration, crate::proxy::rate_limit::RateLimitReason::RateLimitExceeded);
+    }
+
+    fn lock_account_with_reason(
+        manager: &TokenManager,
+        id: &str,
+        duration: std::time::Duration,
+        reason: crate::proxy::rate_limit::RateLimitReason,
+    ) {
+        let until = manager.rate_limit_tracker.clock().now() + duration;
+        for key in [id.to_string(), format!("{}@example.com", id)] {
+            manager.rate_limit_tracker.set_lockout_until(&key, until, reason, None);
+        }
+    }
+
+    /// 返回会话最终使用的账号与等待的 (假时钟) 时长
+    async fn bound_account_after_rate_limit(
+        policy: crate::proxy::sticky_config::StickyRateLimitPolicy,
+        lockout: std::time::Duration,
+    ) -> (String, std::time::Duration) {
+        let clock = Arc::new(crate::proxy::clock::FakeClock::new(std::time::SystemTime::now()));
+        let manager = TokenManager::with_clock(temp_data_dir(), clock);
+        let started = manager.rate_limit_tracker.clock().now();
+        insert_test_token(&manager, "a", Some(100));
+        insert_test_token(&manager, "b", Some(50));
+        let mut config = manager.get_sticky_config().await;
+        config.sticky_rate_limit_policy = policy;
+        config.sticky_wait_max_seconds = 5;
+        manager.update_sticky_config(config).await;
+
+        manager.session_accounts.insert("s1".to_string(), "a".to_string());
+        lock_account_for(&manager, "a", lockout);
+
+        let (_, _, email) = manager.get_token("claude", false, Some("s1")).await.unwrap();
+        let waited = manager.rate_limit_tracker.clock().now().duration_since(started).unwrap();
+        (email, waited)
+    }
+
+    #[tokio::test]
+    async fn test_sticky_rate_limit_policy_switch_or_wait() {
+        use crate::proxy::sticky_config::StickyRateLimitPolicy;
+        let short = std::time::Duration::from_millis(1500);
+        let long = std::time::Duration::from_secs(30);
+
+        // switch: 即使限流马上结束也立即切换
+        let (email, _) = bound_account_after_rate_limit(StickyRateLimitPolicy::Switch, short).await;
+        assert_eq!(email, "b@example.com");
+        // wait_if_short: 等待精确的剩余时长 (1.5 秒而非取整后的 1 秒)，之后原账号已解锁
+        let (email, waited) = bound_account_after_rate_limit(StickyRateLimitPolicy::WaitIfShort, short).await;
+        assert_eq!(email, "a@example.com");
+        assert_eq!(waited, short);
+        // wait_if_short: 剩余 30 秒超过等待上限，仍然切换
+        let (email, waited) = bound_account_after_rate_limit(StickyRateLimitPolicy::WaitIfShort, long).await;
+        assert_eq!(email, "b@example.com");
+        assert!(waited < long);
+    }
+
+    /// 两个账号均被锁定 `lockout` 的 TokenManager (假时钟)
+    fn fully_locked_manager(lockout: std::time::Duration) -> TokenManager {
+        let clock = Arc::new(crate::proxy::clock::FakeClock::new(std::time::SystemTime::now()));
+        let manager = TokenManager::with_clock(temp_data_dir(), clock);
+        for id in ["a", "b"] {
+            insert_test_token(&manager, id, Some(100));
+            lock_account_for(&manager, id, lockout);
+        }
+        manager
+    }
+
+    #[tokio::test]
+    async fn test_lockout_expiring_during_buffer_is_used() {
+        // 剩余 300ms：500ms 缓冲期间到期，直接使用该账号，不触发乐观重置
+        let manager = fully_locked_manager(std::time::Duration::from_millis(300));
+        assert!(manager.get_token("claude", false, None).await.is_ok());
+        assert!(manager.rate_limit_tracker.get("a").is_some());
+        assert!(manager.rate_limit_tracker.get("b").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_optimistic_reset_when_buffer_is_not_enough() {
+        // 剩余 1.5 秒 (<= 2 秒)：缓冲后仍被锁定，清空限流记录后继续
+        let manager = fully_locked_manager(std::time::Duration::from_millis(1500));
+        assert!(manager.get_token("claude", false, None).await.is_ok());
+        assert!(manager.rate_limit_tracker.get("a").is_none());
+        assert!(manager.rate_limit_tracker.get("b").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_long_lockout_reports_wait_time() {
+        let manager = fully_locked_manager(std::time::Duration::from_secs(30));
+        let err = manager.get_token("claude", false, None).await.unwrap_err();
+        assert!(err.contains("Please wait 30s"), "{}", err);
+        assert!(manager.is_rate_limited("a"));
+    }
+
+    #[tokio::test]
+    async fn test_standby_account_only_used_when_main_pool_exhausted() {
+        let clock = Arc::new(crate::proxy::clock::FakeClock::new(std::time::SystemTime::now()));
+        let manager = TokenManager::with_clock(temp_data_dir(), clock.clone());
+        insert_test_token(&manager, "main", Some(10));
+        // 备用账号配额更高，也不应抢在主号池前面
+        insert_test_token(&manager, "spare", Some(100));
+        manager.tokens.get_mut("spare").unwrap().standby = true;
+
+        for force_rotate in [false, true, true] {
+            let (_, _, email) = manager.get_token("claude", force_rotate, None).await.unwrap();
+            assert_eq!(email, "main@example.com");
+        }
+        assert_eq!(manager.standby_activations(), 0);
+
+        // 主号池全部锁定：第二轮启用备用账号并计数
+        lock_account_for(&manager, "main", std::time::Duration::from_secs(30));
+        let (_, _, email) = manager.get_token("claude", false, Some("s1")).await.unwrap();
+        assert_eq!(email, "spare@example.com");
+        assert_eq!(manager.standby_activations(), 1);
+        assert_eq!(manager.session_accounts.get("s1").unwrap().as_str(), "spare");
+
+        // 主账号恢复后，备用账号的会话绑定立即失效
+        clock.advance(std::time::Duration::from_secs(31));
+        let (_, _, email) = manager.get_token("claude", false, Some("s1")).await.unwrap();
+        assert_eq!(email, "main@example.com");
+        assert!(manager.session_accounts.get("s1").is_none());
+        assert_eq!(manager.standby_activations(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_standby_only_pool_is_still_served() {
+        let manager = TokenManager::new(temp_data_dir());
+        insert_test_token(&manager, "spare", Some(100));
+        manager.tokens.get_mut("spare").unwrap().standby = true;
+
+        let (_, _, email) = manager.get_token("claude", true, None).await.unwrap();
+        assert_eq!(email, "spare@example.com");
+        assert_eq!(manager.standby_activations(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_probe_candidate_is_closest_to_expiry_and_release_clears_lock() {
+        let clock = Arc::new(crate::proxy::clock::FakeClock::new(std::time::SystemTime::now()));
+        let manager = TokenManager::with_clock(temp_data_dir(), clock);
+        assert_eq!(manager.probe_candidate(QuotaGroup::Text), None);
+
+        for (id, secs) in [("a", 600), ("b", 90), ("c", 3600)] {
+            insert_test_token(&manager, id, Some(100));
+            lock_account_with_reason(
+                &manager,
+                id,
+                std::time::Duration::from_secs(secs),
+                crate::proxy::rate_limit::RateLimitReason::ServerError,
+            );
+        }
+        insert_test_token(&manager, "free", Some(100));
+        // 配额耗尽的锁定即使最快到期也不参与探测
+        insert_test_token(&manager, "quota", Some(100));
+        lock_account_with_reason(
+            &manager,
+            "quota",
+            std::time::Duration::from_secs(10),
+            crate::proxy::rate_limit::RateLimitReason::QuotaExhausted,
+        );
+        let (account_id, email) = manager.probe_candidate(QuotaGroup::Text).unwrap();
+        assert_eq!((account_id.as_str(), email.as_str()), ("b", "b@example.com"));
+
+        manager.release_probed_account(&account_id, &email);
+        assert!(!manager.is_rate_limited("b"));
+        assert!(!manager.rate_limit_tracker.is_rate_limited("b@example.com"));
+        assert_eq!(manager.probe_candidate(QuotaGroup::Text).map(|(id, _)| id).as_deref(), Some("a"));
+    }
+
+    #[tokio::test]
+    async fn test_probe_candidate_respects_allowed_groups_and_standby() {
+        let clock = Arc::new(crate::proxy::clock::FakeClock::new(std::time::SystemTime::now()));
+        let manager = TokenManager::with_clock(temp_data_dir(), clock);
+        for (id, secs) in [("spare", 30), ("image-only", 60), ("main", 600)] {
+            insert_test_token(&manager, id, Some(100));
+            lock_account_with_reason(
+                &manager,
+                id,
+                std::time::Duration::from_secs(secs),
+                crate::proxy::rate_limit::RateLimitReason::ServerError,
+            );
+        }
+        manager.tokens.get_mut("spare").unwrap().standby = true;
+        manager.tokens.get_mut("image-only").unwrap().allowed_groups = vec![QuotaGroup::ImageGen];
+
+        // 备用账号最快到期，但主号池仍有候选；只服务图片的账号不用于文本请求
+        assert_eq!(manager.probe_candidate(QuotaGroup::Text).map(|(id, _)| id).as_deref(), Some("main"));
+        assert_eq!(manager.probe_candidate(QuotaGroup::ImageGen).map(|(id, _)| id).as_deref(), Some("image-only"));
+
+        // 主号池没有候选时才探测备用账号
+        manager.tokens.remove("main");
+        assert_eq!(manager.probe_candidate(QuotaGroup::Text).map(|(id, _)| id).as_deref(), Some("spare"));
+
+        // 没有任何账号允许服务该分组时不探测
+        manager.tokens.remove("spare");
+        assert_eq!(manager.probe_candidate(QuotaGroup::Text), None);
+    }
+
+    #[tokio::test]
+    async fn test_probe_slot_is_rate_limited_globally() {
+        let clock = Arc::new(crate::proxy::clock::FakeClock::new(std::time::SystemTime::now()));
+        let manager = TokenManager::with_clock(temp_data_dir(), clock.clone());
+        let interval = std::time::Duration::from_secs(30);
+
+        assert!(manager.try_acquire_probe_slot(interval));
+        assert!(!manager.try_acquire_probe_slot(interval));
+        clock.advance(std::time::Duration::from_secs(29));
+        assert!(!manager.try_acquire_probe_slot(interval));
+        clock.advance(std::time::Duration::from_secs(1));
+        assert!(manager.try_acquire_probe_slot(interval));
+    }
+
+    #[tokio::test]
+    async fn test_capacity_score_prefers_high_quota_pro_over_low_quota_ultra() {
+        let manager = TokenManager::new(temp_data_dir());
+        insert_test_token(&manager, "ultra", None);
+        insert_test_token(&manager, "pro", None);
+        for (id, tier, percent) in [("ultra", "ULTRA", 15), ("pro", "PRO", 90)] {
+            let mut token = manager.tokens.get_mut(id).unwrap();
+            token.subscription_tier = Some(tier.to_string());
+            token.quota_percent = Some(percent);
+        }
+
+        let scores = manager.capacity_scores().await;
+        assert_eq!(scores[0].account_id, "pro");
+        assert_eq!(scores[0].capacity_score, 72);
+        assert_eq!(scores[1].capacity_score, 15);
+
+        // 等级权重可配置：大幅降低 PRO 权重后排序反转
+        let mut config = manager.get_sticky_config().await;
+        config.tier_weights.pro = 0.1;
+        manager.update_sticky_config(config).await;
+        assert_eq!(manager.capacity_scores().await[0].account_id, "ultra");
+    }
+
+    fn insert_tiered_token(manager: &TokenManager, id: &str, tier: &str) {
+        insert_test_token(manager, id, Some(100));
+        manager.tokens.get_mut(id).unwrap().subscription_tier = Some(tier.to_string());
+    }
+
+    async fn enable_priority_hints(manager: &TokenManager) {
+        let mut config = manager.get_sticky_config().await;
+        config.priority_hints = true;
+        manager.update_sticky_config(config).await;
+    }
+
+    #[tokio::test]
+    async fn test_bulk_prefers_free_and_avoids_warm_account() {
+        let manager = TokenManager::new(temp_data_dir());
+        insert_tiered_token(&manager, "ultra", "ULTRA");
+        insert_tiered_token(&manager, "free1", "FREE");
+        insert_tiered_token(&manager, "free2", "FREE");
+        enable_priority_hints(&manager).await;
+
+        // free1 正在被交互式流量使用 (60s 窗口内)
+        *manager.last_used_account.lock().await = Some(("free1".to_string(), std::time::Instant::now()));
+
+        for _ in 0..4 {
+            let (_, _, email) = manager
+                .get_token_with_priority("claude", false, Some("bulk-session"), RequestPriority::Bulk)
+                .await
+                .unwrap();
+            assert_eq!(email, "free2@example.com");
+        }
+        // 批量请求不建立会话绑定，也不改变热账号
+        assert!(manager.session_accounts.get("bulk-session").is_none());
+        assert_eq!(manager.last_used_account.lock().await.as_ref().unwrap().0, "free1");
+    }
+
+    #[tokio::test]
+    async fn test_bulk_is_served_when_only_ultra_available() {
+        let manager = TokenManager::new(temp_data_dir());
+        insert_tiered_token(&manager, "ultra", "ULTRA");
+        enable_priority_hints(&manager).await;
+        *manager.last_used_account.lock().await = Some(("ultra".to_string(), std::time::Instant::now()));
+
+        let (_, _, email) = manager
+            .get_token_with_priority("claude", false, None, RequestPriority::Bulk)
+            .await
+            .unwrap();
+        assert_eq!(email, "ultra@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_bulk_hint_ignored_when_disabled() {
+        let manager = TokenManager::new(temp_data_dir());
+        insert_tiered_token(&manager, "ultra", "ULTRA");
+        insert_tiered_token(&manager, "free1", "FREE");
+        *manager.last_used_account.lock().await = Some(("ultra".to_string(), std::time::Instant::now()));
+
+        // 未开启 priority_hints 时与交互式请求一致，复用 60s 窗口内的账号
+        let (_, _, email) = manager
+            .get_token_with_priority("claude", false, None, RequestPriority::Bulk)
+            .await
+            .unwrap();
+        assert_eq!(email, "ultra@example.com");
+        assert_eq!(manager.effective_priority(RequestPriority::Bulk).await, RequestPriority::Interactive);
+        assert_eq!(manager.tier_for_identifier("free1@example.com").as_deref(), Some("FREE"));
+    }
+
+    #[tokio::test]
+    async fn test_request_type_header_forces_quota_group() {
+        use crate::proxy::common::request_type::request_type_from_headers;
+
+        let manager = TokenManager::new(temp_data_dir());
+        insert_test_token(&manager, "a", Some(100));
+
+        // 自动推断为 agent：选中的账号进入 60s 粘性窗口
+        let group = manager.resolve_quota_group("agent", None).await;
+        assert_eq!(group, "agent");
+        manager.get_token(&group, false, None).await.unwrap();
+        assert!(manager.last_used_account.lock().await.is_some());
+
+        // 请求头强制归入 image_gen 分组：不参与也不更新粘性窗口
+        *manager.last_used_account.lock().await = None;
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert("X-Request-Type", "image_gen".parse().unwrap());
+        let group = manager.resolve_quota_group("agent", request_type_from_headers(&headers)).await;
+        assert_eq!(group, "image_gen");
+        manager.get_token(&group, false, None).await.unwrap();
+        assert!(manager.last_used_account.lock().await.is_none());
+
+        // 调度配置默认值生效，但请求头优先
+        let mut config = manager.get_sticky_config().await;
+        config.default_request_type = Some("web_search".to_string());
+        manager.update_sticky_config(config).await;
+        assert_eq!(manager.resolve_quota_group("agent", None).await, "web_search");
+        assert_eq!(manager.resolve_quota_group("agent", Some("image_gen")).await, "image_gen");
+    }
+
+    fn cursor(manager: &TokenManager, quota_group: &str) -> usize {
+        manager.group_indices.get(quota_group).map_or(0, |i| i.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn test_claude_and_gemini_keep_separate_cursors() {
+        let manager = TokenManager::new(temp_data_dir());
+        insert_test_token(&manager, "a", Some(100));
+        insert_test_token(&manager, "b", Some(50));
+        insert_test_token(&manager, "c", Some(10));
+
+        let (_, _, claude_first) = manager.get_token("claude", true, None).await.unwrap();
+        for _ in 0..4 {
+            manager.get_token("gemini", true, None).await.unwrap();
+        }
+        assert_eq!(cursor(&manager, "claude"), 1);
+        assert_eq!(cursor(&manager, "gemini"), 4);
+
+        // Gemini 流量不打乱 Claude 的轮询位置
+        let (_, _, claude_second) = manager.get_token("claude", true, None).await.unwrap();
+        assert_ne!(claude_first, claude_second);
+        assert_eq!(cursor(&manager, "claude"), 2);
+
+        // 两者都属于文本分组，统计合并计入
+        assert_eq!(manager.group_selections()["text"], 6);
+    }
+
+    #[tokio::test]
+    async fn test_image_gen_does_not_advance_text_cursor() {
+        let manager = TokenManager::new(temp_data_dir());
+        insert_test_token(&manager, "a", Some(100));
+        insert_test_token(&manager, "b", Some(50));
+        insert_test_token(&manager, "c", Some(10));
+
+        let (_, _, first) = manager.get_token("claude", true, None).await.unwrap();
+        let text_cursor = cursor(&manager, "claude");
+
+        // 一轮图片生成突发：只推进 image_gen 分组的游标
+        for _ in 0..5 {
+            manager.get_token("image_gen", true, None).await.unwrap();
+        }
+        assert_eq!(cursor(&manager, "claude"), text_cursor);
+        assert_eq!(cursor(&manager, "image_gen"), 5);
+
+        // 文本分组的轮询从上次的位置继续
+        let (_, _, second) = manager.get_token("claude", true, None).await.unwrap();
+        assert_ne!(first, second);
+        assert_eq!(second, "b@example.com");
+
+        let selections = manager.group_selections();
+        assert_eq!(selections["text"], 2);
+        assert_eq!(selections["image_gen"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_allowed_groups_restrict_selection() {
+        let manager = TokenManager::new(temp_data_dir());
+        insert_test_token(&manager, "text-only", Some(100));
+        insert_test_token(&manager, "image-only", Some(50));
+        manager.tokens.get_mut("text-only").unwrap().allowed_groups = vec![QuotaGroup::Text];
+        manager.tokens.get_mut("image-only").unwrap().allowed_groups = vec![QuotaGroup::ImageGen];
+
+        for _ in 0..3 {
+            let (_, _, email) = manager.get_token("image_gen", true, None).await.unwrap();
+            assert_eq!(email, "image-only@example.com");
+            let (_, _, email) = manager.get_token("claude", true, Some("s1")).await.unwrap();
+            assert_eq!(email, "text-only@example.com");
+        }
+
+        // 图片请求即使携带会话标识也不建立会话绑定
+        manager.get_token("image_gen", false, Some("img-session")).await.unwrap();
+        assert!(manager.session_accounts.get("img-session").is_none());
+
+        manager.tokens.remove("image-only");
+        let err = manager.get_token("image_gen", false, None).await.unwrap_err();
+        assert!(err.contains("image_gen"));
+    }
+}
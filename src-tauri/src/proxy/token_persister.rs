@@ -0,0 +1,320 @@
+// Token 持久化队列
+// 将刷新后的 token 落盘从请求热路径中剥离：内存 DashMap 立即更新，文件写入交给后台任务批量完成。
+// 落盘时重新读取账号文件并只合并 token 字段，通过临时文件 + 重命名写入，避免崩溃时截断账号文件。
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+
+/// 持久化队列容量
+const PERSIST_CHANNEL_CAPACITY: usize = 256;
+
+/// 单次批量处理的最大消息数
+const MAX_BATCH_SIZE: usize = 64;
+
+/// 一次待落盘的 token 更新
+#[derive(Debug, Clone)]
+pub struct TokenUpdate {
+    pub account_id: String,
+    pub account_path: PathBuf,
+    /// 刷新所用的 refresh_token；落盘时文件中的 refresh_token 已变化 (重新登录等) 则不覆盖
+    pub refresh_token: String,
+    pub access_token: String,
+    pub expires_in: i64,
+    pub expiry_timestamp: i64,
+}
+
+/// 一次待落盘的反代使用时间 (延迟批量写入，不随每个请求落盘)
+#[derive(Debug, Clone)]
+pub struct UsageUpdate {
+    pub account_id: String,
+    pub account_path: PathBuf,
+    pub last_proxy_used_at: i64,
+}
+
+enum PersistMessage {
+    /// (序号, 更新)，序号用于丢弃乱序到达的旧更新
+    Update(u64, TokenUpdate),
+    Usage(UsageUpdate),
+    Flush(oneshot::Sender<()>),
+}
+
+/// 后台 token 持久化任务的句柄
+pub struct TokenPersister {
+    tx: mpsc::Sender<PersistMessage>,
+    /// 尚未启动的后台任务接收端，首次在 runtime 中使用时取出并启动
+    rx: Mutex<Option<mpsc::Receiver<PersistMessage>>>,
+    seq: AtomicU64,
+    /// 队列满时转交给独立任务、尚未入队的更新数
+    overflow: Arc<AtomicUsize>,
+}
+
+impl TokenPersister {
+    /// 创建持久化队列，后台写入任务延迟到首次在 tokio runtime 中使用时启动
+    pub fn spawn() -> Self {
+        let (tx, rx) = mpsc::channel(PERSIST_CHANNEL_CAPACITY);
+        let persister = Self {
+            tx,
+            rx: Mutex::new(Some(rx)),
+            seq: AtomicU64::new(0),
+            overflow: Arc::new(AtomicUsize::new(0)),
+        };
+        persister.ensure_started();
+        persister
+    }
+
+    /// 当前线程处于 tokio runtime 中时启动后台写入任务 (只启动一次)
+    /// 不在 runtime 中时消息暂存在队列里，等下次调用再启动
+    fn ensure_started(&self) {
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let Ok(mut rx) = self.rx.lock() else {
+            return;
+        };
+        if let Some(rx) = rx.take() {
+            handle.spawn(run_persist_loop(rx));
+        }
+    }
+
+    /// 提交一次 token 更新，不等待磁盘写入
+    /// 队列已满时转交给独立任务等待入队，调用方永远不会被磁盘 IO 阻塞
+    pub fn enqueue(&self, update: TokenUpdate) {
+        self.ensure_started();
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed) + 1;
+        match self.tx.try_send(PersistMessage::Update(seq, update)) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(msg)) => {
+                let tx = self.tx.clone();
+                let overflow = self.overflow.clone();
+                overflow.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let _ = tx.send(msg).await;
+                    overflow.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                tracing::warn!("Token 持久化任务已退出，本次更新未落盘");
+            }
+        }
+    }
+
+    /// 提交反代使用时间，队列已满时直接丢弃 (内存中仍保留最新值，下次刷新时再写)
+    pub fn enqueue_usage(&self, update: UsageUpdate) -> bool {
+        self.ensure_started();
+        self.tx.try_send(PersistMessage::Usage(update)).is_ok()
+    }
+
+    /// 等待此前提交的所有更新写入磁盘
+    pub async fn flush(&self) {
+        self.ensure_started();
+        // 先等待溢出的更新全部入队，保证 Flush 排在它们之后
+        while self.overflow.load(Ordering::SeqCst) > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.tx.send(PersistMessage::Flush(done_tx)).await.is_ok() {
+            let _ = done_rx.await;
+        }
+    }
+}
+
+async fn run_persist_loop(mut rx: mpsc::Receiver<PersistMessage>) {
+    let mut pending: HashMap<String, (u64, TokenUpdate)> = HashMap::new();
+    let mut written_seq: HashMap<String, u64> = HashMap::new();
+    let mut pending_usage: HashMap<String, UsageUpdate> = HashMap::new();
+
+    while let Some(first) = rx.recv().await {
+        let mut flush_waiters = Vec::new();
+        let mut batch = vec![first];
+
+        // 合并当前队列中已到达的消息，同一账号只保留最新一次更新
+        while batch.len() < MAX_BATCH_SIZE {
+            match rx.try_recv() {
+                Ok(msg) => batch.push(msg),
+                Err(_) => break,
+            }
+        }
+
+        for msg in batch {
+            match msg {
+                PersistMessage::Update(seq, update) => {
+                    let newer = pending.get(&update.account_id).map_or(true, |(s, _)| seq > *s)
+                        && written_seq.get(&update.account_id).map_or(true, |s| seq > *s);
+                    if newer {
+                        pending.insert(update.account_id.clone(), (seq, update));
+                    }
+                }
+                PersistMessage::Usage(usage) => {
+                    let newer = pending_usage
+                        .get(&usage.account_id)
+                        .map_or(true, |u| usage.last_proxy_used_at > u.last_proxy_used_at);
+                    if newer {
+                        pending_usage.insert(usage.account_id.clone(), usage);
+                    }
+                }
+                PersistMessage::Flush(done) => flush_waiters.push(done),
+            }
+        }
+
+        for (account_id, (seq, update)) in pending.drain() {
+            // 同一账号的使用时间随 token 一并写入，只读写一次文件
+            let usage = pending_usage.remove(&account_id).map(|u| u.last_proxy_used_at);
+            if let Err(e) = write_account_update(&update.account_path, Some(&update), usage).await {
+                tracing::debug!("保存刷新后的 token 失败 ({}): {}", account_id, e);
+            }
+            written_seq.insert(account_id, seq);
+        }
+
+        for (account_id, usage) in pending_usage.drain() {
+            if let Err(e) = write_account_update(&usage.account_path, None, Some(usage.last_proxy_used_at)).await {
+                tracing::debug!("保存反代使用时间失败 ({}): {}", account_id, e);
+            }
+        }
+
+        for done in flush_waiters {
+            let _ = done.send(());
+        }
+    }
+}
+
+async fn write_account_update(
+    account_path: &std::path::Path,
+    update: Option<&TokenUpdate>,
+    last_proxy_used_at: Option<i64>,
+) -> Result<(), String> {
+    let content = tokio::fs::read_to_string(account_path)
+        .await
+        .map_err(|e| format!("读取文件失败: {}", e))?;
+    let mut json: serde_json::Value =
+        serde_json::from_str(&content).map_err(|e| format!("解析 JSON 失败: {}", e))?;
+
+    let mut token_applied = false;
+    if let Some(update) = update {
+        crate::modules::token_crypto::update_token_fields(&mut json, |token| {
+            token_applied = token_update_is_current(token, update);
+            if token_applied {
+                token["access_token"] = serde_json::Value::String(update.access_token.clone());
+                token["expires_in"] = serde_json::Value::Number(update.expires_in.into());
+                token["expiry_timestamp"] = serde_json::Value::Number(update.expiry_timestamp.into());
+            }
+        })?;
+    }
+
+    if let Some(used_at) = last_proxy_used_at {
+        // 只前进不回退 (文件可能已被其他入口写入更新的值)
+        let current = json.get("last_proxy_used_at").and_then(|v| v.as_i64()).unwrap_or(0);
+        if used_at > current {
+            json["last_proxy_used_at"] = serde_json::Value::Number(used_at.into());
+        }
+    }
+
+    // 只读模式 (其他实例持有数据目录) 或文件由更新版本的应用写入时不落盘
+    crate::modules::instance_lock::ensure_writable()?;
+    crate::modules::account_schema::ensure_version_writable(crate::modules::account_schema::schema_version(&json))?;
+    let serialized = serde_json::to_string_pretty(&json).map_err(|e| format!("序列化失败: {}", e))?;
+    let temp_path = account_path.with_extension("json.tmp");
+    tokio::fs::write(&temp_path, serialized)
+        .await
+        .map_err(|e| format!("写入临时文件失败: {}", e))?;
+    if let Err(e) = tokio::fs::rename(&temp_path, account_path).await {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        return Err(format!("替换账号文件失败: {}", e));
+    }
+
+    match update {
+        Some(update) if token_applied => tracing::debug!("已保存刷新后的 token 到账号 {}", update.account_id),
+        Some(update) => tracing::debug!("账号 {} 的 token 已在磁盘上被更新，跳过过期的刷新结果", update.account_id),
+        None => {}
+    }
+    Ok(())
+}
+
+/// 排队的刷新结果是否仍适用于磁盘上的 token：
+/// refresh_token 未变化 (未重新登录 / 替换凭证)，且磁盘上没有更晚到期的 access_token
+fn token_update_is_current(token: &serde_json::Value, update: &TokenUpdate) -> bool {
+    let same_refresh_token = token
+        .get("refresh_token")
+        .and_then(|v| v.as_str())
+        .map_or(true, |rt| rt == update.refresh_token);
+    let on_disk_expiry = token.get("expiry_timestamp").and_then(|v| v.as_i64()).unwrap_or(0);
+    same_refresh_token && on_disk_expiry <= update.expiry_timestamp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn update(path: &std::path::Path, refresh_token: &str, expiry_timestamp: i64) -> TokenUpdate {
+        TokenUpdate {
+            account_id: "acc".to_string(),
+            account_path: path.to_path_buf(),
+            refresh_token: refresh_token.to_string(),
+            access_token: "ya29.queued".to_string(),
+            expires_in: 3600,
+            expiry_timestamp,
+        }
+    }
+
+    fn write_account(path: &std::path::Path, refresh_token: &str, expiry_timestamp: i64) {
+        let account = json!({
+            "id": "acc",
+            "email": "a@example.com",
+            "label": "set from ui",
+            "token": {
+                "access_token": "ya29.on-disk",
+                "refresh_token": refresh_token,
+                "expires_in": 3600,
+                "expiry_timestamp": expiry_timestamp
+            }
+        });
+        std::fs::write(path, serde_json::to_string_pretty(&account).unwrap()).unwrap();
+    }
+
+    fn read(path: &std::path::Path) -> serde_json::Value {
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_update_merges_into_latest_file_atomically() {
+        let dir = std::env::temp_dir().join(format!("ag_token_persister_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("acc.json");
+        write_account(&path, "1//rt", 1_000);
+
+        write_account_update(&path, Some(&update(&path, "1//rt", 2_000)), Some(42)).await.unwrap();
+        let saved = read(&path);
+        assert_eq!(saved["token"]["access_token"], "ya29.queued");
+        assert_eq!(saved["token"]["expiry_timestamp"], 2_000);
+        assert_eq!(saved["last_proxy_used_at"], 42);
+        // 排队之后由其他入口写入的字段保持不变
+        assert_eq!(saved["label"], "set from ui");
+        assert!(!path.with_extension("json.tmp").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_stale_update_does_not_overwrite_newer_save() {
+        let dir = std::env::temp_dir().join(format!("ag_token_persister_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("acc.json");
+
+        // 排队后用户重新登录 (refresh_token 已变化)
+        write_account(&path, "1//relogin", 1_000);
+        write_account_update(&path, Some(&update(&path, "1//rt", 2_000)), None).await.unwrap();
+        assert_eq!(read(&path)["token"]["access_token"], "ya29.on-disk");
+
+        // 磁盘上已有更晚到期的 token
+        write_account(&path, "1//rt", 3_000);
+        write_account_update(&path, Some(&update(&path, "1//rt", 2_000)), None).await.unwrap();
+        assert_eq!(read(&path)["token"]["expiry_timestamp"], 3_000);
+        assert_eq!(read(&path)["token"]["access_token"], "ya29.on-disk");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
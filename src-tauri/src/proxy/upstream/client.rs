@@ -5,6 +5,9 @@ use reqwest::{header, Client, Response, StatusCode};
 use serde_json::Value;
 use tokio::time::Duration;
 
+use super::mock::MockUpstream;
+use super::region::UpstreamRegion;
+
 // Cloud Code v1internal endpoints (fallback order: prod → daily)
 // 优先使用稳定的 prod 端点，避免影响缓存命中率
 const V1_INTERNAL_BASE_URL_PROD: &str = "https://cloudcode-pa.googleapis.com/v1internal";
@@ -14,33 +17,193 @@ const V1_INTERNAL_BASE_URL_FALLBACKS: [&str; 2] = [
     V1_INTERNAL_BASE_URL_DAILY,  // 备用测试环境（新功能）
 ];
 
+/// 上游连接的最低 TLS 版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MinTlsVersion {
+    Tls12,
+    Tls13,
+}
+
+impl MinTlsVersion {
+    /// 解析配置值 ("1.2" / "1.3")
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim() {
+            "1.2" => Ok(Self::Tls12),
+            "1.3" => Ok(Self::Tls13),
+            other => Err(format!("无效的上游 TLS 最低版本: {} (可选 \"1.2\" 或 \"1.3\")", other)),
+        }
+    }
+
+    fn as_reqwest(self) -> reqwest::tls::Version {
+        match self {
+            Self::Tls12 => reqwest::tls::Version::TLS_1_2,
+            Self::Tls13 => reqwest::tls::Version::TLS_1_3,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Tls12 => "TLS 1.2",
+            Self::Tls13 => "TLS 1.3",
+        }
+    }
+}
+
+/// 上游连接参数 (连接池与 TLS 策略，来自 ProxyConfig，热更新时重建客户端)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpstreamPoolConfig {
+    /// 每主机最多保留的空闲连接数
+    pub max_idle_per_host: usize,
+    /// 空闲连接保留时间(秒)
+    pub idle_timeout_secs: u64,
+    /// 最低 TLS 版本 (None 时使用 TLS 库默认值)
+    pub min_tls: Option<MinTlsVersion>,
+}
+
+impl UpstreamPoolConfig {
+    /// 无效的 upstream_min_tls 在保存与启动时已被拒绝，这里仅记录警告并回退到库默认值
+    pub fn from_proxy_config(config: &crate::proxy::config::ProxyConfig) -> Self {
+        let min_tls = config.upstream_min_tls.as_deref().and_then(|value| {
+            MinTlsVersion::parse(value)
+                .map_err(|e| tracing::warn!("{}", e))
+                .ok()
+        });
+        Self {
+            max_idle_per_host: config.upstream_pool_max_idle_per_host,
+            idle_timeout_secs: config.upstream_pool_idle_timeout_secs,
+            min_tls,
+        }
+    }
+
+    /// 生效的 TLS 策略描述 (用于日志)
+    pub fn tls_policy(&self) -> &'static str {
+        self.min_tls.map(MinTlsVersion::label).unwrap_or("TLS 库默认")
+    }
+}
+
+/// 校验 upstream_min_tls：值必须有效，且当前 TLS 后端能以该最低版本构建客户端
+pub fn validate_upstream_tls(config: &crate::proxy::config::ProxyConfig) -> Result<(), String> {
+    let Some(value) = config.upstream_min_tls.as_deref() else {
+        return Ok(());
+    };
+    let pool = UpstreamPoolConfig {
+        min_tls: Some(MinTlsVersion::parse(value)?),
+        ..UpstreamPoolConfig::from_proxy_config(config)
+    };
+    build_http_client(None, pool)
+        .map(|_| ())
+        .map_err(|e| format!("上游 TLS 最低版本 {} 无法应用: {}", value, e))
+}
+
+impl Default for UpstreamPoolConfig {
+    fn default() -> Self {
+        Self::from_proxy_config(&crate::proxy::config::ProxyConfig::default())
+    }
+}
+
+/// 当前使用的 HTTP 客户端及构建它的连接池参数
+struct HttpClientSlot {
+    client: Client,
+    pool: UpstreamPoolConfig,
+}
+
 pub struct UpstreamClient {
-    http_client: Client,
+    http: std::sync::RwLock<HttpClientSlot>,
+    /// 模拟上游 (开启后所有调用由本地模拟器处理，不访问网络)
+    mock: Option<MockUpstream>,
 }
 
-impl UpstreamClient {
-    pub fn new(proxy_config: Option<crate::proxy::config::UpstreamProxyConfig>) -> Self {
-        let mut builder = Client::builder()
-            // Connection settings (优化连接复用，减少建立开销)
-            .connect_timeout(Duration::from_secs(20))
-            .pool_max_idle_per_host(16)                  // 每主机最多 16 个空闲连接
-            .pool_idle_timeout(Duration::from_secs(90))  // 空闲连接保持 90 秒
-            .tcp_keepalive(Duration::from_secs(60))      // TCP 保活探测 60 秒
-            .timeout(Duration::from_secs(600))
-            .user_agent("antigravity/1.11.9 windows/amd64");
-
-        if let Some(config) = proxy_config {
-            if config.enabled && !config.url.is_empty() {
-                if let Ok(proxy) = reqwest::Proxy::all(&config.url) {
-                    builder = builder.proxy(proxy);
-                    tracing::info!("UpstreamClient enabled proxy: {}", config.url);
-                }
+/// 按代理与连接池参数构建 HTTP 客户端
+fn build_http_client(
+    proxy_config: Option<&crate::proxy::config::UpstreamProxyConfig>,
+    pool: UpstreamPoolConfig,
+) -> Result<Client, String> {
+    let mut builder = Client::builder()
+        // Connection settings (优化连接复用，减少建立开销)
+        .connect_timeout(Duration::from_secs(20))
+        .pool_max_idle_per_host(pool.max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(pool.idle_timeout_secs))
+        .tcp_keepalive(Duration::from_secs(60))      // TCP 保活探测 60 秒
+        .timeout(Duration::from_secs(600))
+        .user_agent("antigravity/1.11.9 windows/amd64");
+
+    if let Some(min_tls) = pool.min_tls {
+        builder = builder.min_tls_version(min_tls.as_reqwest());
+    }
+
+    if let Some(config) = proxy_config {
+        if config.enabled && !config.url.is_empty() {
+            if let Ok(proxy) = reqwest::Proxy::all(&config.url) {
+                builder = builder.proxy(proxy);
+                tracing::info!("UpstreamClient enabled proxy: {}", config.url);
             }
         }
+    }
+
+    builder.build().map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+impl UpstreamClient {
+    pub fn new(
+        proxy_config: Option<crate::proxy::config::UpstreamProxyConfig>,
+        pool: UpstreamPoolConfig,
+    ) -> Self {
+        let client = build_http_client(proxy_config.as_ref(), pool).expect("Failed to create HTTP client");
+        tracing::info!("上游 TLS 策略: 最低版本 {}", pool.tls_policy());
+
+        Self {
+            http: std::sync::RwLock::new(HttpClientSlot { client, pool }),
+            mock: None,
+        }
+    }
+
+    /// 按新的代理与连接池配置重建 HTTP 客户端 (热更新)
+    ///
+    /// 进行中的请求继续使用旧客户端直至结束；构建失败时保留旧客户端
+    pub fn rebuild(
+        &self,
+        proxy_config: &crate::proxy::config::UpstreamProxyConfig,
+        pool: UpstreamPoolConfig,
+    ) -> Result<(), String> {
+        if self.mock.is_some() {
+            return Ok(());
+        }
+        let client = build_http_client(Some(proxy_config), pool)?;
+        *self.http.write().unwrap_or_else(|e| e.into_inner()) = HttpClientSlot { client, pool };
+        tracing::info!(
+            "上游 HTTP 客户端已重建 (pool_max_idle_per_host={}, pool_idle_timeout={}s, min_tls={})",
+            pool.max_idle_per_host,
+            pool.idle_timeout_secs,
+            pool.tls_policy()
+        );
+        Ok(())
+    }
+
+    /// 当前客户端使用的连接池参数
+    pub fn pool_config(&self) -> UpstreamPoolConfig {
+        self.http.read().unwrap_or_else(|e| e.into_inner()).pool
+    }
 
-        let http_client = builder.build().expect("Failed to create HTTP client");
+    /// 当前的 HTTP 客户端 (Client 内部为 Arc，克隆开销很小)
+    fn http_client(&self) -> Client {
+        self.http.read().unwrap_or_else(|e| e.into_inner()).client.clone()
+    }
+
+    /// 创建使用模拟上游的客户端
+    pub fn new_mock(config: crate::proxy::config::MockUpstreamConfig) -> Self {
+        tracing::warn!("UpstreamClient running in MOCK mode: responses are simulated, no network access");
+        Self {
+            http: std::sync::RwLock::new(HttpClientSlot {
+                client: Client::new(),
+                pool: UpstreamPoolConfig::default(),
+            }),
+            mock: Some(MockUpstream::new(config)),
+        }
+    }
 
-        Self { http_client }
+    /// 是否为模拟上游
+    pub fn is_mock(&self) -> bool {
+        self.mock.is_some()
     }
 
     /// 构建 v1internal URL
@@ -68,17 +231,8 @@ impl UpstreamClient {
             || status.is_server_error()
     }
 
-    /// 调用 v1internal API（基础方法）
-    /// 
-    /// 发起基础网络请求，支持多端点自动 Fallback
-    pub async fn call_v1_internal(
-        &self,
-        method: &str,
-        access_token: &str,
-        body: Value,
-        query_string: Option<&str>,
-    ) -> Result<Response, String> {
-        // 构建 Headers (所有端点复用)
+    /// v1internal 请求头
+    fn request_headers(access_token: &str) -> Result<header::HeaderMap, String> {
         let mut headers = header::HeaderMap::new();
         headers.insert(
             header::CONTENT_TYPE,
@@ -93,6 +247,57 @@ impl UpstreamClient {
             header::USER_AGENT,
             header::HeaderValue::from_static("antigravity/1.11.9 windows/amd64"),
         );
+        Ok(headers)
+    }
+
+    /// 调用 v1internal API（基础方法）
+    /// 
+    /// 发起基础网络请求，支持多端点自动 Fallback
+    pub async fn call_v1_internal(
+        &self,
+        method: &str,
+        access_token: &str,
+        body: Value,
+        query_string: Option<&str>,
+    ) -> Result<Response, String> {
+        self.call_v1_internal_at(method, access_token, body, query_string, None).await
+    }
+
+    /// 调用 v1internal API，可固定到指定上游区域
+    ///
+    /// `region` 为 None 时按默认端点顺序回退；指定区域时仅请求该区域，错误状态原样返回
+    pub async fn call_v1_internal_at(
+        &self,
+        method: &str,
+        access_token: &str,
+        body: Value,
+        query_string: Option<&str>,
+        region: Option<&UpstreamRegion>,
+    ) -> Result<Response, String> {
+        if let Some(mock) = &self.mock {
+            return Ok(mock.respond(method, &body, query_string).await);
+        }
+
+        // 指定区域时只访问该区域，不回退到其他端点 (避免用其他区域的成功掩盖该区域的故障)
+        if let Some(region) = region {
+            let url = Self::build_url(&region.base_url, method, query_string);
+            return self
+                .http_client()
+                .post(&url)
+                .headers(Self::request_headers(access_token)?)
+                .json(&body)
+                .send()
+                .await
+                .map_err(|e| {
+                    format!(
+                        "Upstream region '{}' ({}) request failed: {}",
+                        region.name, region.base_url, e
+                    )
+                });
+        }
+
+        // 构建 Headers (所有端点复用)
+        let headers = Self::request_headers(access_token)?;
 
         let mut last_err: Option<String> = None;
 
@@ -102,7 +307,7 @@ impl UpstreamClient {
             let has_next = idx + 1 < V1_INTERNAL_BASE_URL_FALLBACKS.len();
 
             let response = self
-                .http_client
+                .http_client()
                 .post(&url)
                 .headers(headers.clone())
                 .json(&body)
@@ -183,20 +388,11 @@ impl UpstreamClient {
     /// 获取远端模型列表，支持多端点自动 Fallback
     #[allow(dead_code)]
     pub async fn fetch_available_models(&self, access_token: &str) -> Result<Value, String> {
-        let mut headers = header::HeaderMap::new();
-        headers.insert(
-            header::CONTENT_TYPE,
-            header::HeaderValue::from_static("application/json"),
-        );
-        headers.insert(
-            header::AUTHORIZATION,
-            header::HeaderValue::from_str(&format!("Bearer {}", access_token))
-                .map_err(|e| e.to_string())?,
-        );
-        headers.insert(
-            header::USER_AGENT,
-            header::HeaderValue::from_static("antigravity/1.11.9 windows/amd64"),
-        );
+        if self.mock.is_some() {
+            return Ok(serde_json::json!({ "models": {} }));
+        }
+
+        let headers = Self::request_headers(access_token)?;
 
         let mut last_err: Option<String> = None;
 
@@ -205,7 +401,7 @@ impl UpstreamClient {
             let url = Self::build_url(base_url, "fetchAvailableModels", None);
 
             let response = self
-                .http_client
+                .http_client()
                 .post(&url)
                 .headers(headers.clone())
                 .json(&serde_json::json!({}))
@@ -286,4 +482,81 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_rebuild_applies_pool_config() {
+        let initial = UpstreamPoolConfig { max_idle_per_host: 4, idle_timeout_secs: 30, min_tls: None };
+        let client = UpstreamClient::new(None, initial);
+        assert_eq!(client.pool_config(), initial);
+
+        // 热更新后换用按新参数构建的客户端
+        let tuned = UpstreamPoolConfig { max_idle_per_host: 64, idle_timeout_secs: 300, min_tls: None };
+        client
+            .rebuild(&crate::proxy::config::UpstreamProxyConfig::default(), tuned)
+            .unwrap();
+        assert_eq!(client.pool_config(), tuned);
+
+        // 关闭空闲连接复用 (0) 同样可以构建
+        let disabled = UpstreamPoolConfig { max_idle_per_host: 0, idle_timeout_secs: 0, min_tls: None };
+        assert!(build_http_client(None, disabled).is_ok());
+    }
+
+    #[test]
+    fn test_min_tls_is_applied_and_invalid_value_rejected() {
+        let pool = UpstreamPoolConfig {
+            min_tls: Some(MinTlsVersion::parse("1.2").unwrap()),
+            ..UpstreamPoolConfig::default()
+        };
+        assert!(build_http_client(None, pool).is_ok());
+        assert_eq!(pool.tls_policy(), "TLS 1.2");
+        assert_eq!(MinTlsVersion::parse(" 1.3 ").unwrap(), MinTlsVersion::Tls13);
+
+        let mut config = crate::proxy::config::ProxyConfig::default();
+        assert!(validate_upstream_tls(&config).is_ok());
+        config.upstream_min_tls = Some("1.2".to_string());
+        assert!(validate_upstream_tls(&config).is_ok());
+        assert_eq!(UpstreamPoolConfig::from_proxy_config(&config).min_tls, Some(MinTlsVersion::Tls12));
+        // rustls 后端支持以 TLS 1.3 为最低版本
+        config.upstream_min_tls = Some("1.3".to_string());
+        assert!(validate_upstream_tls(&config).is_ok());
+
+        for invalid in ["1.1", "tls1.2", ""] {
+            config.upstream_min_tls = Some(invalid.to_string());
+            assert!(validate_upstream_tls(&config).is_err(), "{} should be rejected", invalid);
+            assert_eq!(UpstreamPoolConfig::from_proxy_config(&config).min_tls, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pinned_region_does_not_fall_back() {
+        // 区域端点返回 503 时应原样返回，而不是切换到其他端点
+        let app = axum::Router::new().fallback(|| async { (axum::http::StatusCode::SERVICE_UNAVAILABLE, "down") });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let client = UpstreamClient::new(None, UpstreamPoolConfig::default());
+        let region = UpstreamRegion {
+            name: "local".to_string(),
+            base_url: format!("http://{}/v1internal", addr),
+        };
+        let resp = client
+            .call_v1_internal_at("generateContent", "token", serde_json::json!({}), None, Some(&region))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // 连接失败时错误信息指明区域
+        let unreachable = UpstreamRegion {
+            name: "gone".to_string(),
+            base_url: "http://127.0.0.1:1/v1internal".to_string(),
+        };
+        let err = client
+            .call_v1_internal_at("generateContent", "token", serde_json::json!({}), None, Some(&unreachable))
+            .await
+            .unwrap_err();
+        assert!(err.contains("Upstream region 'gone'"));
+    }
+
 }
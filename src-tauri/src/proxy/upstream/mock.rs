@@ -0,0 +1,243 @@
+// 模拟上游 (Mock Upstream)
+// 不访问网络、不需要真实账号，返回确定性的 Gemini v1internal 格式响应，用于集成测试与演示
+
+use bytes::Bytes;
+use reqwest::Response;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::time::Duration;
+
+use crate::proxy::config::MockUpstreamConfig;
+
+/// 模拟账号信息 (TokenManager 在模拟模式下注入)
+pub const MOCK_ACCOUNT_ID: &str = "mock-account";
+pub const MOCK_ACCOUNT_EMAIL: &str = "mock@antigravity.local";
+pub const MOCK_PROJECT_ID: &str = "mock-project";
+
+/// 流式响应中每个分片的最大字符数
+const STREAM_CHUNK_CHARS: usize = 8;
+
+pub struct MockUpstream {
+    config: MockUpstreamConfig,
+    calls: AtomicUsize,
+}
+
+impl MockUpstream {
+    pub fn new(config: MockUpstreamConfig) -> Self {
+        Self {
+            config,
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    /// 已处理的上游调用次数
+    pub fn call_count(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// 模拟一次 v1internal 调用
+    ///
+    /// 按调用顺序消费 `status_script`，脚本耗尽或对应状态为 200 时返回成功响应
+    pub async fn respond(&self, method: &str, body: &Value, query_string: Option<&str>) -> Response {
+        let call_index = self.calls.fetch_add(1, Ordering::SeqCst);
+
+        if self.config.latency_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(self.config.latency_ms)).await;
+        }
+
+        if let Some(&status) = self.config.status_script.get(call_index) {
+            if status != 200 {
+                tracing::info!("[Mock-Upstream] Scripted status {} for call #{}", status, call_index);
+                return error_response(status);
+            }
+        }
+
+        let model = body.get("model").and_then(|v| v.as_str()).unwrap_or("mock-model");
+        let response_id = format!("mock-{}", call_index);
+        let prompt_tokens = (body.to_string().len() / 4).max(1) as u32;
+        // 按 candidateCount 返回多个内容相同的候选
+        let candidates = body["request"]["generationConfig"]["candidateCount"]
+            .as_u64()
+            .unwrap_or(1)
+            .max(1) as usize;
+
+        match method {
+            "streamGenerateContent" if query_string.map_or(false, |q| q.contains("alt=sse")) => {
+                self.stream_response(model, &response_id, prompt_tokens, candidates)
+            }
+            "generateContent" | "streamGenerateContent" => {
+                let payload = json!({
+                    "response": self.build_candidate(&self.config.reply_text, model, &response_id, Some(prompt_tokens), candidates)
+                });
+                build_response(200, "application/json", reqwest::Body::from(payload.to_string()))
+            }
+            "fetchAvailableModels" => {
+                build_response(200, "application/json", reqwest::Body::from(json!({ "models": {} }).to_string()))
+            }
+            _ => error_response(404),
+        }
+    }
+
+    fn stream_response(&self, model: &str, response_id: &str, prompt_tokens: u32, candidates: usize) -> Response {
+        let chars: Vec<char> = self.config.reply_text.chars().collect();
+        let pieces: Vec<String> = chars
+            .chunks(STREAM_CHUNK_CHARS)
+            .map(|c| c.iter().collect())
+            .collect();
+
+        let mut events: Vec<Result<Bytes, std::io::Error>> = Vec::new();
+        let last = pieces.len().saturating_sub(1);
+        for (i, piece) in pieces.iter().enumerate() {
+            let usage = if i == last { Some(prompt_tokens) } else { None };
+            let mut candidate = self.build_candidate(piece, model, response_id, usage, candidates);
+            if i != last {
+                if let Some(list) = candidate["candidates"].as_array_mut() {
+                    for c in list.iter_mut().filter_map(Value::as_object_mut) {
+                        c.remove("finishReason");
+                    }
+                }
+            }
+            events.push(Ok(Bytes::from(format!(
+                "data: {}\r\n\r\n",
+                json!({ "response": candidate })
+            ))));
+        }
+        if events.is_empty() {
+            let candidate = self.build_candidate("", model, response_id, Some(prompt_tokens), candidates);
+            events.push(Ok(Bytes::from(format!("data: {}\r\n\r\n", json!({ "response": candidate })))));
+        }
+
+        let first_chunk_delay = Duration::from_millis(self.config.first_chunk_delay_ms);
+        let stream = async_stream::stream! {
+            if !first_chunk_delay.is_zero() {
+                tokio::time::sleep(first_chunk_delay).await;
+            }
+            for event in events {
+                yield event;
+            }
+        };
+        build_response(200, "text/event-stream", reqwest::Body::wrap_stream(stream))
+    }
+
+    fn build_candidate(
+        &self,
+        text: &str,
+        model: &str,
+        response_id: &str,
+        prompt_tokens: Option<u32>,
+        candidates: usize,
+    ) -> Value {
+        let candidates: Vec<Value> = (0..candidates)
+            .map(|index| {
+                json!({
+                    "content": { "role": "model", "parts": [{ "text": text }] },
+                    "finishReason": "STOP",
+                    "index": index
+                })
+            })
+            .collect();
+        let mut candidate = json!({
+            "candidates": candidates,
+            "modelVersion": model,
+            "responseId": response_id
+        });
+        if let Some(prompt) = prompt_tokens {
+            let output = (self.config.reply_text.chars().count() / 4).max(1) as u32;
+            candidate["usageMetadata"] = json!({
+                "promptTokenCount": prompt,
+                "candidatesTokenCount": output,
+                "totalTokenCount": prompt + output
+            });
+        }
+        candidate
+    }
+}
+
+fn error_response(status: u16) -> Response {
+    let google_status = match status {
+        429 => "RESOURCE_EXHAUSTED",
+        503 => "UNAVAILABLE",
+        400 => "INVALID_ARGUMENT",
+        404 => "NOT_FOUND",
+        _ => "INTERNAL",
+    };
+    let payload = json!({
+        "error": {
+            "code": status,
+            "message": format!("Mock upstream scripted error ({})", status),
+            "status": google_status
+        }
+    });
+    let resp = axum::http::Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        // 显式给出重试时间，避免限流逻辑触发实时配额查询 (模拟模式下无网络)
+        .header("Retry-After", "0")
+        .body(reqwest::Body::from(payload.to_string()))
+        .expect("valid mock response");
+    Response::from(resp)
+}
+
+fn build_response(status: u16, content_type: &str, body: reqwest::Body) -> Response {
+    let resp = axum::http::Response::builder()
+        .status(status)
+        .header("Content-Type", content_type)
+        .body(body)
+        .expect("valid mock response");
+    Response::from(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(script: Vec<u16>) -> MockUpstreamConfig {
+        MockUpstreamConfig {
+            enabled: true,
+            reply_text: "Hello from mock upstream".to_string(),
+            latency_ms: 0,
+            status_script: script,
+            first_chunk_delay_ms: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scripted_statuses_then_success() {
+        let mock = MockUpstream::new(config(vec![429, 500]));
+        let body = json!({ "model": "gemini-2.5-flash" });
+
+        assert_eq!(mock.respond("generateContent", &body, None).await.status().as_u16(), 429);
+        assert_eq!(mock.respond("generateContent", &body, None).await.status().as_u16(), 500);
+
+        let ok = mock.respond("generateContent", &body, None).await;
+        assert_eq!(ok.status().as_u16(), 200);
+        let json: Value = ok.json().await.unwrap();
+        assert_eq!(
+            json["response"]["candidates"][0]["content"]["parts"][0]["text"],
+            "Hello from mock upstream"
+        );
+        assert_eq!(mock.call_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_stream_reassembles_reply() {
+        let mock = MockUpstream::new(config(vec![]));
+        let resp = mock
+            .respond("streamGenerateContent", &json!({ "model": "m" }), Some("alt=sse"))
+            .await;
+        let text = resp.text().await.unwrap();
+
+        let mut reassembled = String::new();
+        let mut finish_count = 0;
+        for line in text.lines().filter_map(|l| l.strip_prefix("data: ")) {
+            let v: Value = serde_json::from_str(line).unwrap();
+            let cand = &v["response"]["candidates"][0];
+            reassembled.push_str(cand["content"]["parts"][0]["text"].as_str().unwrap());
+            if cand.get("finishReason").is_some() {
+                finish_count += 1;
+            }
+        }
+        assert_eq!(reassembled, "Hello from mock upstream");
+        assert_eq!(finish_count, 1);
+    }
+}
@@ -4,3 +4,5 @@
 pub mod client;
 pub mod retry;
 pub mod models;
+pub mod mock;
+pub mod region;
@@ -0,0 +1,89 @@
+// 上游区域选择
+// 客户端通过 X-Upstream-Region 头把单个请求固定到配置表中的某个上游端点
+
+use axum::http::HeaderMap;
+
+use crate::proxy::config::UpstreamRegionsConfig;
+
+/// 指定上游区域的请求头 (响应中同名头回显实际使用的区域)
+pub const UPSTREAM_REGION_HEADER: &str = "X-Upstream-Region";
+
+/// 单个请求选定的上游区域
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpstreamRegion {
+    pub name: String,
+    pub base_url: String,
+}
+
+/// 解析请求头中的区域
+///
+/// 功能关闭或未携带请求头时返回 None (走默认端点及其回退)；区域名不在配置表中时返回错误并列出可用区域
+pub fn resolve(headers: &HeaderMap, config: &UpstreamRegionsConfig) -> Result<Option<UpstreamRegion>, String> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    let Some(name) = headers
+        .get(UPSTREAM_REGION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+    else {
+        return Ok(None);
+    };
+
+    match config.regions.get(name) {
+        Some(base_url) => Ok(Some(UpstreamRegion {
+            name: name.to_string(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })),
+        None => {
+            let valid: Vec<&str> = config.regions.keys().map(String::as_str).collect();
+            Err(format!(
+                "Unknown upstream region '{}'. Valid regions: [{}]",
+                name,
+                valid.join(", ")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(enabled: bool) -> UpstreamRegionsConfig {
+        UpstreamRegionsConfig {
+            enabled,
+            regions: [
+                ("prod".to_string(), "https://cloudcode-pa.googleapis.com/v1internal/".to_string()),
+                ("daily".to_string(), "https://daily-cloudcode-pa.sandbox.googleapis.com/v1internal".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    fn headers(region: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(UPSTREAM_REGION_HEADER, region.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_resolve_known_and_unknown_regions() {
+        let region = resolve(&headers("prod"), &config(true)).unwrap().unwrap();
+        assert_eq!(region.name, "prod");
+        assert_eq!(region.base_url, "https://cloudcode-pa.googleapis.com/v1internal");
+
+        let err = resolve(&headers("eu-west"), &config(true)).unwrap_err();
+        assert!(err.contains("'eu-west'"));
+        assert!(err.contains("[daily, prod]"));
+
+        assert_eq!(resolve(&HeaderMap::new(), &config(true)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_header_ignored_when_disabled() {
+        assert_eq!(resolve(&headers("eu-west"), &config(false)).unwrap(), None);
+    }
+}
@@ -277,10 +277,8 @@ pub async fn call_tool(
     tool_name: &str,
     arguments: &Value,
 ) -> Result<Value, String> {
-    let api_key = zai.api_key.trim();
-    if api_key.is_empty() {
-        return Err("z.ai api_key is missing".to_string());
-    }
+    let key = zai.first_key().ok_or_else(|| "z.ai api_key is missing".to_string())?;
+    let api_key = key.as_str();
 
     let client = build_client(upstream_proxy, timeout_secs)?;
 
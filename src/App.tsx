@@ -8,12 +8,14 @@ import ApiProxy from './pages/ApiProxy';
 import Monitor from './pages/Monitor';
 import ThemeManager from './components/common/ThemeManager';
 import { UpdateNotification } from './components/UpdateNotification';
+import { InstanceLockBanner } from './components/InstanceLockBanner';
 import { useEffect, useState } from 'react';
 import { useConfigStore } from './stores/useConfigStore';
 import { useAccountStore } from './stores/useAccountStore';
 import { useTranslation } from 'react-i18next';
 import { listen } from '@tauri-apps/api/event';
 import { invoke } from '@tauri-apps/api/core';
+import { showToast } from './components/common/ToastContainer';
 
 const router = createBrowserRouter([
   {
@@ -51,6 +53,11 @@ function App() {
 
   useEffect(() => {
     loadConfig();
+    // 配置在别处被修改 (如 /setup 页面生成客户端 Key) 后重新加载
+    const unlisten = listen('config://updated', () => loadConfig());
+    return () => {
+      unlisten.then(fn => fn());
+    };
   }, [loadConfig]);
 
   // Sync language from config
@@ -82,13 +89,28 @@ function App() {
       })
     );
 
+    // 监听账号被自动禁用事件 (invalid_grant)，提示用户重新授权
+    unlistenPromises.push(
+      listen<{ account_id: string; email: string; reason: string }>('account://disabled', (event) => {
+        showToast(i18n.t('accounts.disabled_notice', { email: event.payload.email }), 'warning', 8000);
+        fetchAccounts();
+      })
+    );
+
+    // 临时停用到期自动恢复后刷新账号列表
+    unlistenPromises.push(
+      listen('account://snooze_expired', () => {
+        fetchAccounts();
+      })
+    );
+
     // Cleanup
     return () => {
       Promise.all(unlistenPromises).then(unlisteners => {
         unlisteners.forEach(unlisten => unlisten());
       });
     };
-  }, [fetchCurrentAccount, fetchAccounts]);
+  }, [fetchCurrentAccount, fetchAccounts, i18n]);
 
   // Update notification state
   const [showUpdateNotification, setShowUpdateNotification] = useState(false);
@@ -124,6 +146,7 @@ function App() {
       {showUpdateNotification && (
         <UpdateNotification onClose={() => setShowUpdateNotification(false)} />
       )}
+      <InstanceLockBanner />
       <RouterProvider router={router} />
     </>
   );
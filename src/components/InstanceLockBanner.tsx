@@ -0,0 +1,57 @@
+import React, { useEffect, useState } from 'react';
+import { Lock } from 'lucide-react';
+import { listen } from '@tauri-apps/api/event';
+import { useTranslation } from 'react-i18next';
+import { showToast } from './common/ToastContainer';
+import { forceTakeOwnership, getInstanceLockStatus, InstanceLockStatus } from '../services/accountService';
+
+// 其他实例持有数据目录时显示只读提示，并提供强制接管入口
+export const InstanceLockBanner: React.FC = () => {
+  const { t } = useTranslation();
+  const [status, setStatus] = useState<InstanceLockStatus | null>(null);
+
+  useEffect(() => {
+    getInstanceLockStatus().then(setStatus).catch(console.error);
+    const unlisten = listen<InstanceLockStatus>('instance://lock-changed', (event) => {
+      setStatus(event.payload);
+    });
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, []);
+
+  if (!status?.read_only || !status.owner) {
+    return null;
+  }
+  const host = status.owner.hostname;
+
+  const handleTakeOwnership = async () => {
+    if (!window.confirm(t('instance_lock.confirm', { host }))) {
+      return;
+    }
+    try {
+      setStatus(await forceTakeOwnership(true));
+      showToast(t('instance_lock.taken'), 'success');
+    } catch (error) {
+      showToast(String(error), 'error');
+    }
+  };
+
+  return (
+    <div className="fixed bottom-6 left-1/2 -translate-x-1/2 z-[100] w-[32rem] max-w-[90vw] p-4 rounded-2xl border border-amber-300/60 bg-amber-50/95 dark:bg-amber-900/80 dark:border-amber-700/60 shadow-lg backdrop-blur-xl">
+      <div className="flex items-start gap-3">
+        <Lock className="w-5 h-5 mt-0.5 text-amber-600 dark:text-amber-300 shrink-0" />
+        <div className="flex-1">
+          <h3 className="font-bold text-amber-800 dark:text-amber-100">{t('instance_lock.title')}</h3>
+          <p className="text-sm text-amber-700 dark:text-amber-200">{t('instance_lock.message', { host })}</p>
+        </div>
+        <button
+          onClick={handleTakeOwnership}
+          className="px-3 py-1.5 text-sm font-medium rounded-lg bg-amber-600 hover:bg-amber-700 text-white transition-colors shrink-0"
+        >
+          {t('instance_lock.take_ownership')}
+        </button>
+      </div>
+    </div>
+  );
+};
@@ -1,4 +1,4 @@
-import { ArrowRightLeft, RefreshCw, Trash2, Download, Info, Lock, Ban, Diamond, Gem, Circle, Clock, ToggleLeft, ToggleRight, Fingerprint, Sparkles } from 'lucide-react';
+import { ArrowRightLeft, RefreshCw, Trash2, Download, Info, Lock, Ban, Diamond, Gem, Circle, Clock, ToggleLeft, ToggleRight, Fingerprint, Sparkles, KeyRound } from 'lucide-react';
 import { Account } from '../../types/account';
 import { getQuotaColor, formatTimeRemaining, getTimeRemainingColor } from '../../utils/format';
 import { cn } from '../../utils/cn';
@@ -19,10 +19,11 @@ interface AccountCardProps {
     onDelete: () => void;
     onToggleProxy: () => void;
     onWarmup?: () => void;
+    onReauthorize?: () => void;
 }
 
 
-function AccountCard({ account, selected, onSelect, isCurrent, isRefreshing, isSwitching = false, onSwitch, onRefresh, onViewDetails, onExport, onDelete, onToggleProxy, onViewDevice, onWarmup }: AccountCardProps) {
+function AccountCard({ account, selected, onSelect, isCurrent, isRefreshing, isSwitching = false, onSwitch, onRefresh, onViewDetails, onExport, onDelete, onToggleProxy, onViewDevice, onWarmup, onReauthorize }: AccountCardProps) {
     const { t } = useTranslation();
     const geminiProModel = account.quota?.models.find(m => m.name === 'gemini-3-pro-high');
     const geminiFlashModel = account.quota?.models.find(m => m.name === 'gemini-3-flash');
@@ -90,6 +91,16 @@ function AccountCard({ account, selected, onSelect, isCurrent, isRefreshing, isS
                                     {t('accounts.disabled').toUpperCase()}
                                 </span>
                             )}
+                            {isDisabled && onReauthorize && (
+                                <button
+                                    className="px-1.5 py-0.5 rounded-md bg-blue-100 dark:bg-blue-900/40 text-blue-700 dark:text-blue-300 text-[9px] font-bold flex items-center gap-1 shadow-sm border border-blue-200/50 hover:bg-blue-200 dark:hover:bg-blue-800/50 transition-colors"
+                                    onClick={(e) => { e.stopPropagation(); onReauthorize(); }}
+                                    title={t('accounts.reauthorize_tooltip')}
+                                >
+                                    <KeyRound className="w-2.5 h-2.5" />
+                                    {t('accounts.reauthorize').toUpperCase()}
+                                </button>
+                            )}
                             {account.quota?.is_forbidden && (
                                 <span className="px-1.5 py-0.5 rounded-md bg-red-100 dark:bg-red-900/40 text-red-600 dark:text-red-400 text-[9px] font-bold flex items-center gap-1 shadow-sm border border-red-200/50" title={t('accounts.forbidden_tooltip')}>
                                     <Lock className="w-2.5 h-2.5" />
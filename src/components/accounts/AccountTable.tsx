@@ -39,6 +39,7 @@ import {
     ToggleLeft,
     ToggleRight,
     Sparkles,
+    KeyRound,
 } from 'lucide-react';
 import { Account } from '../../types/account';
 import { useTranslation } from 'react-i18next';
@@ -65,6 +66,8 @@ interface AccountTableProps {
     onDelete: (accountId: string) => void;
     onToggleProxy: (accountId: string) => void;
     onWarmup?: (accountId: string) => void;
+    /** 重新授权被禁用的账号 */
+    onReauthorize?: (accountId: string) => void;
     /** 拖拽排序回调，当用户完成拖拽时触发 */
     onReorder?: (accountIds: string[]) => void;
 }
@@ -85,6 +88,7 @@ interface SortableRowProps {
     onDelete: () => void;
     onToggleProxy: () => void;
     onWarmup?: () => void;
+    onReauthorize?: () => void;
 }
 
 interface AccountRowContentProps {
@@ -100,6 +104,7 @@ interface AccountRowContentProps {
     onDelete: () => void;
     onToggleProxy: () => void;
     onWarmup?: () => void;
+    onReauthorize?: () => void;
 }
 
 // ============================================================================
@@ -155,6 +160,7 @@ function SortableAccountRow({
     onDelete,
     onToggleProxy,
     onWarmup,
+    onReauthorize,
 }: SortableRowProps) {
     const { t } = useTranslation();
     const {
@@ -218,6 +224,7 @@ function SortableAccountRow({
                 onDelete={onDelete}
                 onToggleProxy={onToggleProxy}
                 onWarmup={onWarmup}
+                onReauthorize={onReauthorize}
             />
         </tr>
     );
@@ -240,6 +247,7 @@ function AccountRowContent({
     onDelete,
     onToggleProxy,
     onWarmup,
+    onReauthorize,
 }: AccountRowContentProps) {
     const { t } = useTranslation();
     const geminiProModel = account.quota?.models.find(m => m.name.toLowerCase() === 'gemini-3-pro-high');
@@ -277,6 +285,17 @@ function AccountRowContent({
                             </span>
                         )}
 
+                        {isDisabled && onReauthorize && (
+                            <button
+                                className="px-2 py-0.5 rounded-md bg-blue-100 dark:bg-blue-900/50 text-blue-700 dark:text-blue-300 text-[10px] font-bold flex items-center gap-1 shadow-sm border border-blue-200/50 hover:bg-blue-200 dark:hover:bg-blue-800/50 transition-colors"
+                                onClick={(e) => { e.stopPropagation(); onReauthorize(); }}
+                                title={t('accounts.reauthorize_tooltip')}
+                            >
+                                <KeyRound className="w-2.5 h-2.5" />
+                                <span>{t('accounts.reauthorize')}</span>
+                            </button>
+                        )}
+
                         {account.proxy_disabled && (
                             <span
                                 className="px-2 py-0.5 rounded-md bg-orange-100 dark:bg-orange-900/50 text-orange-700 dark:text-orange-300 text-[10px] font-bold flex items-center gap-1 shadow-sm border border-orange-200/50"
@@ -454,7 +473,14 @@ function AccountRowContent({
 
             {/* 最后使用时间列 */}
             <td className="px-4 py-1">
-                <div className="flex flex-col">
+                <div
+                    className="flex flex-col"
+                    title={t('accounts.last_proxy_used', {
+                        time: account.last_proxy_used_at
+                            ? new Date(account.last_proxy_used_at * 1000).toLocaleString()
+                            : t('accounts.never_proxy_used'),
+                    })}
+                >
                     <span className="text-xs font-medium text-gray-600 dark:text-gray-400 font-mono whitespace-nowrap">
                         {new Date(account.last_used * 1000).toLocaleDateString()}
                     </span>
@@ -573,6 +599,7 @@ function AccountTable({
     onExport,
     onDelete,
     onToggleProxy,
+    onReauthorize,
     onReorder,
 }: AccountTableProps) {
     const { t } = useTranslation();
@@ -665,6 +692,7 @@ function AccountTable({
                                     onExport={() => onExport(account.id)}
                                     onDelete={() => onDelete(account.id)}
                                     onToggleProxy={() => onToggleProxy(account.id)}
+                                    onReauthorize={onReauthorize ? () => onReauthorize(account.id) : undefined}
                                 />
                             ))}
                         </tbody>
@@ -20,6 +20,8 @@ function AddAccountDialog({ onAdd }: AddAccountDialogProps) {
     const [refreshToken, setRefreshToken] = useState('');
     const [oauthUrl, setOauthUrl] = useState('');
     const [oauthUrlCopied, setOauthUrlCopied] = useState(false);
+    // 导入时同时绑定 storage.json 中的设备指纹
+    const [captureDevice, setCaptureDevice] = useState(false);
 
     // UI State
     const [status, setStatus] = useState<Status>('idle');
@@ -291,7 +293,7 @@ function AddAccountDialog({ onAdd }: AddAccountDialogProps) {
     };
 
     const handleImportDb = () => {
-        handleAction(t('accounts.add.tabs.import'), importFromDb);
+        handleAction(t('accounts.add.tabs.import'), () => importFromDb(captureDevice));
     };
 
     const handleImportV1 = () => {
@@ -312,7 +314,7 @@ function AddAccountDialog({ onAdd }: AddAccountDialogProps) {
             });
 
             if (selected && typeof selected === 'string') {
-                handleAction(t('accounts.add.import.btn_custom_db') || 'Import Custom DB', () => importFromCustomDb(selected));
+                handleAction(t('accounts.add.import.btn_custom_db') || 'Import Custom DB', () => importFromCustomDb(selected, captureDevice));
             }
         } catch (err) {
             console.error('Failed to open dialog:', err);
@@ -506,6 +508,16 @@ function AddAccountDialog({ onAdd }: AddAccountDialogProps) {
                                             <Database className="w-4 h-4" />
                                             {t('accounts.add.import.btn_custom_db') || 'Custom DB (state.vscdb)'}
                                         </button>
+                                        <label className="flex items-center gap-2 pt-1 text-xs text-gray-500 dark:text-gray-400 cursor-pointer select-none">
+                                            <input
+                                                type="checkbox"
+                                                className="checkbox checkbox-xs"
+                                                checked={captureDevice}
+                                                onChange={(e) => setCaptureDevice(e.target.checked)}
+                                                disabled={status === 'loading'}
+                                            />
+                                            {t('accounts.add.import.capture_device')}
+                                        </label>
                                     </div>
 
                                     <div className="divider text-xs text-gray-300 dark:text-gray-600">{t('accounts.add.import.or')}</div>
@@ -0,0 +1,75 @@
+import { createPortal } from 'react-dom';
+import { useEffect, useState } from 'react';
+import { AlertTriangle } from 'lucide-react';
+import { useTranslation } from 'react-i18next';
+import { useAccountStore } from '../../stores/useAccountStore';
+import { ImportConflictChoice, ImportTokenSide } from '../../types/account';
+import { showToast } from '../common/ToastContainer';
+
+// 导入的邮箱已存在且 token 不同时，由用户选择保留哪一个 (逐个处理)
+export default function ImportConflictDialog() {
+    const { t } = useTranslation();
+    const { importConflicts, fetchImportConflicts, resolveImportConflict } = useAccountStore();
+    const [resolving, setResolving] = useState(false);
+
+    useEffect(() => {
+        fetchImportConflicts();
+    }, []);
+
+    const conflict = importConflicts[0];
+    if (!conflict) return null;
+
+    const handleResolve = async (choice: ImportConflictChoice) => {
+        setResolving(true);
+        try {
+            await resolveImportConflict(conflict.email, choice);
+        } catch (error) {
+            showToast(`${t('common.error')}: ${error}`, 'error');
+        } finally {
+            setResolving(false);
+        }
+    };
+
+    const renderSide = (title: string, side: ImportTokenSide) => (
+        <div className="flex-1 rounded-xl border border-gray-100 dark:border-base-300 p-3 space-y-1">
+            <div className="text-xs font-bold text-gray-700 dark:text-gray-300">{title}</div>
+            <div className="font-mono text-[11px] text-gray-500 dark:text-gray-400">{side.refresh_token_hint}</div>
+            <div className="text-[11px] text-gray-500 dark:text-gray-400">
+                {t('accounts.import_conflict.expires')}: {new Date(side.expiry_timestamp * 1000).toLocaleString()}
+            </div>
+            <div className={`text-[11px] font-medium ${side.refreshes ? 'text-green-600' : 'text-red-500'}`}>
+                {side.refreshes ? t('accounts.import_conflict.refresh_ok') : `${t('accounts.import_conflict.refresh_failed')}: ${side.error ?? ''}`}
+            </div>
+        </div>
+    );
+
+    return createPortal(
+        <div className="modal modal-open z-[120]">
+            <div className="modal-box max-w-xl bg-white dark:bg-base-100 shadow-2xl rounded-2xl">
+                <div className="flex items-center gap-3 mb-3">
+                    <AlertTriangle className="w-6 h-6 text-amber-500" />
+                    <h3 className="font-bold text-lg text-gray-900 dark:text-base-content">{t('accounts.import_conflict.title')}</h3>
+                    {importConflicts.length > 1 && (
+                        <span className="text-xs text-gray-400">1 / {importConflicts.length}</span>
+                    )}
+                </div>
+                <p className="text-sm text-gray-500 dark:text-gray-400 mb-4">
+                    {t('accounts.import_conflict.message', { email: conflict.email })}
+                </p>
+                <div className="flex gap-3 mb-5">
+                    {renderSide(t('accounts.import_conflict.existing'), conflict.existing)}
+                    {renderSide(t('accounts.import_conflict.imported'), conflict.imported)}
+                </div>
+                <div className="flex justify-end gap-2">
+                    <button className="btn btn-sm" disabled={resolving} onClick={() => handleResolve('keep_existing')}>
+                        {t('accounts.import_conflict.keep_existing')}
+                    </button>
+                    <button className="btn btn-sm btn-primary" disabled={resolving} onClick={() => handleResolve('use_imported')}>
+                        {t('accounts.import_conflict.use_imported')}
+                    </button>
+                </div>
+            </div>
+        </div>,
+        document.body
+    );
+}
@@ -20,7 +20,7 @@ function BackgroundTaskRunner() {
         // Check if we just turned it on
         if (auto_refresh && !prevAutoRefreshRef.current) {
             console.log('[BackgroundTask] Auto-refresh enabled, executing immediately...');
-            refreshAllQuotas();
+            refreshAllQuotas(true);
         }
         prevAutoRefreshRef.current = auto_refresh;
 
@@ -28,7 +28,7 @@ function BackgroundTaskRunner() {
             console.log(`[BackgroundTask] Starting auto-refresh quota timer: ${refresh_interval} mins`);
             intervalId = setInterval(() => {
                 console.log('[BackgroundTask] Auto-refreshing all quotas...');
-                refreshAllQuotas();
+                refreshAllQuotas(true);
             }, refresh_interval * 60 * 1000);
         }
 
@@ -3,6 +3,7 @@ import { getCurrentWindow } from '@tauri-apps/api/window';
 import Navbar from './Navbar';
 import BackgroundTaskRunner from '../common/BackgroundTaskRunner';
 import ToastContainer from '../common/ToastContainer';
+import ImportConflictDialog from '../accounts/ImportConflictDialog';
 
 function Layout() {
     return (
@@ -24,6 +25,7 @@ function Layout() {
             />
             <BackgroundTaskRunner />
             <ToastContainer />
+            <ImportConflictDialog />
             <Navbar />
             <main className="flex-1 overflow-hidden flex flex-col relative">
                 <Outlet />
@@ -22,12 +22,20 @@ interface ProxyRequestLog {
     input_tokens?: number;
     output_tokens?: number;
     account_email?: string;
+    priority?: string;
+    account_tier?: string;
+    locally_answered?: boolean;
+    upstream_region?: string;
 }
 
 interface ProxyStats {
     total_requests: number;
     success_count: number;
     error_count: number;
+    priority_breakdown?: Record<string, Record<string, number>>;
+    locally_answered?: number;
+    recitations?: Record<string, number>;
+    top_end_users?: { end_user: string; requests: number; input_tokens: number; output_tokens: number }[];
 }
 
 interface ProxyMonitorProps {
@@ -132,8 +140,12 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
                 const newLog = event.payload;
                 setLogs(prev => [newLog, ...prev].slice(0, 1000));
                 setStats((prev: ProxyStats) => {
+                    if (newLog.locally_answered) {
+                        return { ...prev, locally_answered: (prev.locally_answered ?? 0) + 1 };
+                    }
                     const isSuccess = newLog.status >= 200 && newLog.status < 400;
                     return {
+                        ...prev,
                         total_requests: prev.total_requests + 1,
                         success_count: prev.success_count + (isSuccess ? 1 : 0),
                         error_count: prev.error_count + (isSuccess ? 0 : 1),
@@ -218,6 +230,25 @@ export const ProxyMonitor: React.FC<ProxyMonitorProps> = ({ className }) => {
                         <span className="text-blue-500">{formatCompactNumber(stats.total_requests)} REQS</span>
                         <span className="text-green-500">{formatCompactNumber(stats.success_count)} OK</span>
                         <span className="text-red-500">{formatCompactNumber(stats.error_count)} ERR</span>
+                        {!!stats.locally_answered && (
+                            <span className="text-gray-500">{formatCompactNumber(stats.locally_answered)} LOCAL</span>
+                        )}
+                        {stats.recitations && Object.keys(stats.recitations).length > 0 && (
+                            <span
+                                className="text-amber-500"
+                                title={Object.entries(stats.recitations).map(([model, count]) => `${model}: ${count}`).join('\n')}
+                            >
+                                {formatCompactNumber(Object.values(stats.recitations).reduce((a, b) => a + b, 0))} RECIT
+                            </span>
+                        )}
+                        {stats.top_end_users && stats.top_end_users.length > 0 && (
+                            <span
+                                className="text-purple-500"
+                                title={stats.top_end_users.map(u => `${u.end_user}: ${u.requests} reqs, ${u.input_tokens + u.output_tokens} tokens`).join('\n')}
+                            >
+                                {stats.top_end_users.length} USERS
+                            </span>
+                        )}
                     </div>
 
                     <button onClick={clearLogs} className="btn btn-sm btn-ghost text-gray-400">
@@ -40,6 +40,7 @@ function Accounts() {
         reorderAccounts,
         warmUpAccounts,
         warmUpAccount,
+        reauthorizeAccount,
     } = useAccountStore();
     const { config } = useConfigStore();
 
@@ -278,6 +279,15 @@ function Accounts() {
         }
     };
 
+    const handleReauthorize = async (accountId: string) => {
+        try {
+            await reauthorizeAccount(accountId);
+            showToast(t('accounts.reauthorize_success'), 'success');
+        } catch (error) {
+            showToast(`${t('common.error')}: ${error}`, 'error');
+        }
+    };
+
     const handleRefresh = async (accountId: string) => {
         setRefreshingIds(prev => {
             const next = new Set(prev);
@@ -398,6 +408,7 @@ function Accounts() {
             const isBatch = selectedIds.size > 0;
             let successCount = 0;
             let failedCount = 0;
+            let deferredCount = 0;
             const details: string[] = [];
 
             if (isBatch) {
@@ -423,9 +434,14 @@ function Accounts() {
                 const stats = await useAccountStore.getState().refreshAllQuotas();
                 successCount = stats.success;
                 failedCount = stats.failed;
+                deferredCount = stats.deferred;
                 details.push(...stats.details);
             }
 
+            if (deferredCount > 0) {
+                showToast(t('accounts.refresh_deferred', { count: deferredCount }), 'info');
+            }
+
             if (failedCount === 0) {
                 showToast(t('accounts.refresh_selected', { count: successCount }), 'success');
             } else {
@@ -743,6 +759,7 @@ function Accounts() {
                                 onToggleProxy={(id) => handleToggleProxy(id, !!accounts.find(a => a.id === id)?.proxy_disabled)}
                                 onReorder={reorderAccounts}
                                 onWarmup={handleWarmup}
+                                onReauthorize={handleReauthorize}
                             />
                         </div>
                     </div>
@@ -763,6 +780,7 @@ function Accounts() {
                             onDelete={handleDelete}
                             onToggleProxy={(id) => handleToggleProxy(id, !!accounts.find(a => a.id === id)?.proxy_disabled)}
                             onWarmup={handleWarmup}
+                            onReauthorize={handleReauthorize}
                         />
                     </div>
                 )}
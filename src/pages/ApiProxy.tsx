@@ -2,6 +2,7 @@ import { useState, useEffect, useMemo } from 'react';
 import { useTranslation } from 'react-i18next';
 import { useNavigate } from 'react-router-dom';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import {
     Power,
     Copy,
@@ -24,21 +25,46 @@ import {
     X,
     Edit2
 } from 'lucide-react';
-import { AppConfig, ProxyConfig, StickySessionConfig } from '../types/config';
+import { AppConfig, ModelMappingTarget, ProxyConfig, StickySessionConfig, WeightedModelTarget } from '../types/config';
 import HelpTooltip from '../components/common/HelpTooltip';
 import ModalDialog from '../components/common/ModalDialog';
 import { showToast } from '../components/common/ToastContainer';
 import { cn } from '../utils/cn';
 import { useProxyModels } from '../hooks/useProxyModels';
 import GroupedSelect, { SelectOption } from '../components/common/GroupedSelect';
+import type { MappingWarning } from '../services/configService';
+import type { EffectiveAuthModes, ProxyTlsStatus } from '../types/config';
 
 interface ProxyStatus {
     running: boolean;
     port: number;
     base_url: string;
     active_accounts: number;
+    state?: 'stopped' | 'starting' | 'running' | 'stopping';
+    already_running?: boolean;
+    requested_port?: number | null;
+    stream_debug?: boolean;
+    tls?: ProxyTlsStatus | null;
+    auth_modes?: EffectiveAuthModes | null;
 }
 
+// 加权映射显示为 "model:weight / model:weight"
+function formatMappingTarget(target: ModelMappingTarget): string {
+    if (typeof target === 'string') return target;
+    return target.map(t => `${t.model}:${t.weight}`).join(' / ');
+}
+
+// formatMappingTarget 的逆操作：含 "model:weight" 片段时解析为加权列表，否则视为单个模型
+function parseMappingTarget(text: string): ModelMappingTarget {
+    const parts = text.split('/').map(p => p.trim()).filter(Boolean);
+    const weighted = parts.map(part => {
+        const idx = part.lastIndexOf(':');
+        const weight = idx > 0 ? Number(part.slice(idx + 1)) : NaN;
+        return Number.isFinite(weight) ? { model: part.slice(0, idx).trim(), weight } : null;
+    });
+    if (weighted.length === 0 || weighted.some(w => w === null)) return text.trim();
+    return weighted as WeightedModelTarget[];
+}
 
 interface CollapsibleCardProps {
     title: string;
@@ -184,7 +210,12 @@ export default function ApiProxy() {
         loadConfig();
         loadStatus();
         const interval = setInterval(loadStatus, 3000);
-        return () => clearInterval(interval);
+        // /setup 页面生成或吊销客户端 Key 后重新读取配置
+        const unlisten = listen('config://updated', () => loadConfig());
+        return () => {
+            clearInterval(interval);
+            unlisten.then(fn => fn());
+        };
     }, []);
 
     const loadConfig = async () => {
@@ -216,7 +247,7 @@ export default function ApiProxy() {
     };
 
     // 专门处理模型映射的热更新 (全量)
-    const handleMappingUpdate = async (type: 'custom', key: string, value: string) => {
+    const handleMappingUpdate = async (type: 'custom', key: string, value: ModelMappingTarget) => {
         if (!appConfig) return;
 
         console.log('[DEBUG] handleMappingUpdate called:', { type, key, value });
@@ -225,10 +256,14 @@ export default function ApiProxy() {
         newConfig.custom_mapping = { ...(newConfig.custom_mapping || {}), [key]: value };
 
         try {
-            await invoke('update_model_mapping', { config: newConfig });
+            const warnings = await invoke<MappingWarning[]>('update_model_mapping', { config: newConfig });
             setAppConfig({ ...appConfig, proxy: newConfig });
             console.log('[DEBUG] Mapping updated successfully');
-            showToast(t('common.saved'), 'success');
+            if (warnings.length > 0) {
+                showToast(warnings.map(w => w.message).join('\n'), 'warning');
+            } else {
+                showToast(t('common.saved'), 'success');
+            }
         } catch (error) {
             console.error('Failed to update mapping:', error);
             showToast(`${t('common.error')}: ${error}`, 'error');
@@ -309,6 +344,16 @@ export default function ApiProxy() {
         }
     };
 
+    const handleRevokeClientKey = async (keyId: string) => {
+        try {
+            await invoke('revoke_client_key', { keyId });
+            await loadConfig();
+            showToast(t('proxy.config.setup_page.revoked'), 'success');
+        } catch (error) {
+            showToast(`${t('common.error')}: ${error}`, 'error');
+        }
+    };
+
     const updateProxyConfig = (updates: Partial<ProxyConfig>) => {
         if (!appConfig) return;
         const newConfig = {
@@ -445,7 +490,10 @@ export default function ApiProxy() {
                 await invoke('stop_proxy_service');
             } else {
                 // 使用当前的 appConfig.proxy 启动
-                await invoke('start_proxy_service', { config: appConfig.proxy });
+                const started = await invoke<ProxyStatus>('start_proxy_service', { config: appConfig.proxy });
+                if (started.requested_port) {
+                    showToast(t('proxy.config.port_auto_picked', { requested: started.requested_port, port: started.port }), 'info');
+                }
             }
             await loadStatus();
         } catch (error: any) {
@@ -652,6 +700,16 @@ print(response.text)`;
                                     <p className="mt-0.5 text-[10px] text-gray-500 dark:text-gray-400">
                                         {t('proxy.config.port_hint')}
                                     </p>
+                                    <label className="mt-1 flex items-center gap-1.5 text-[10px] text-gray-600 dark:text-gray-400 cursor-pointer">
+                                        <input
+                                            type="checkbox"
+                                            className="checkbox checkbox-xs"
+                                            checked={appConfig.proxy.auto_pick_port || false}
+                                            onChange={(e) => updateProxyConfig({ auto_pick_port: e.target.checked })}
+                                            disabled={status.running}
+                                        />
+                                        {t('proxy.config.auto_pick_port')}
+                                    </label>
                                 </div>
                                 <div>
                                     <label className="block text-xs font-medium text-gray-700 dark:text-gray-300 mb-1">
@@ -850,6 +908,58 @@ print(response.text)`;
                                 </p>
                             </div>
 
+                            {/* 客户端引导页 & 客户端 Key */}
+                            <div className="border-t border-gray-200 dark:border-base-300 pt-3 mt-3 space-y-2">
+                                <div className="flex items-center justify-between">
+                                    <span className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                        {t('proxy.config.setup_page.enable')}
+                                        <HelpTooltip
+                                            text={t('proxy.config.setup_page.enable_tooltip')}
+                                            ariaLabel={t('proxy.config.setup_page.enable')}
+                                            placement="right"
+                                        />
+                                    </span>
+                                    <input
+                                        type="checkbox"
+                                        className="toggle toggle-sm bg-gray-200 dark:bg-gray-700 border-gray-300 dark:border-gray-600 checked:bg-blue-500 checked:border-blue-500"
+                                        checked={appConfig.proxy.enable_setup_page || false}
+                                        onChange={(e) => updateProxyConfig({ enable_setup_page: e.target.checked })}
+                                    />
+                                </div>
+                                {(appConfig.proxy.client_keys || []).length === 0 ? (
+                                    <p className="text-[10px] text-gray-500 dark:text-gray-400">
+                                        {t('proxy.config.setup_page.no_keys')}
+                                    </p>
+                                ) : (
+                                    <div className="space-y-1">
+                                        {(appConfig.proxy.client_keys || []).map(key => (
+                                            <div
+                                                key={key.id}
+                                                className="flex items-center justify-between gap-2 px-2.5 py-1.5 rounded-lg bg-gray-50 dark:bg-base-200 text-xs"
+                                            >
+                                                <div className="min-w-0">
+                                                    <div className="font-medium text-gray-700 dark:text-gray-300 truncate">
+                                                        {key.label || key.id}
+                                                    </div>
+                                                    <div className="text-[10px] text-gray-500 dark:text-gray-400">
+                                                        {(key.scopes.length > 0 ? key.scopes.join(', ') : t('proxy.config.setup_page.all_scopes'))}
+                                                        {' · '}
+                                                        {new Date(key.created_at * 1000).toLocaleString()}
+                                                    </div>
+                                                </div>
+                                                <button
+                                                    onClick={() => handleRevokeClientKey(key.id)}
+                                                    className="px-2 py-1 rounded-lg text-red-500 hover:bg-red-50 dark:hover:bg-red-900/20 transition-colors"
+                                                    title={t('proxy.config.setup_page.revoke')}
+                                                >
+                                                    <Trash2 size={14} />
+                                                </button>
+                                            </div>
+                                        ))}
+                                    </div>
+                                )}
+                            </div>
+
 
                         </div>
                     </div>
@@ -922,6 +1032,26 @@ print(response.text)`;
                                         />
                                     </div>
 
+                                    <div className="space-y-1">
+                                        <label className="text-[11px] font-medium text-gray-500 dark:text-gray-400 flex items-center gap-1">
+                                            <span>{t('proxy.config.zai.extra_keys')}</span>
+                                            <HelpTooltip text={t('proxy.config.zai.extra_keys_hint')} />
+                                        </label>
+                                        <textarea
+                                            value={(appConfig.proxy.zai?.api_keys || []).map(k => k.key).join('\n')}
+                                            onChange={(e) => {
+                                                const existing = appConfig.proxy.zai?.api_keys || [];
+                                                const api_keys = e.target.value
+                                                    .split('\n')
+                                                    .map(line => line.trim())
+                                                    .map(key => existing.find(k => k.key === key) || { key, enabled: true });
+                                                updateZaiGeneralConfig({ api_keys });
+                                            }}
+                                            rows={3}
+                                            className="textarea textarea-bordered w-full font-mono text-xs"
+                                        />
+                                    </div>
+
                                     {/* Model Mapping Section */}
                                     <div className="pt-4 border-t border-gray-100 dark:border-base-200">
                                         <div className="flex items-center justify-between mb-3">
@@ -930,7 +1060,7 @@ print(response.text)`;
                                             </h4>
                                             <button
                                                 onClick={refreshZaiModels}
-                                                disabled={zaiModelsLoading || !appConfig.proxy.zai?.api_key}
+                                                disabled={zaiModelsLoading || !(appConfig.proxy.zai?.api_key || appConfig.proxy.zai?.api_keys?.length)}
                                                 className="btn btn-ghost btn-xs gap-1"
                                             >
                                                 <RefreshCw size={12} className={zaiModelsLoading ? 'animate-spin' : ''} />
@@ -1174,6 +1304,63 @@ print(response.text)`;
                                                 </div>
                                             </div>
 
+                                            <div className="flex items-center justify-between bg-slate-100 dark:bg-slate-800/80 rounded-xl p-4 border border-slate-200 dark:border-slate-700">
+                                                <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                    {t('proxy.config.scheduling.priority_hints')}
+                                                    <HelpTooltip text={t('proxy.config.scheduling.priority_hints_tooltip')} />
+                                                </label>
+                                                <input
+                                                    type="checkbox"
+                                                    className="toggle toggle-sm bg-gray-200 dark:bg-gray-700 border-gray-300 dark:border-gray-600 checked:bg-blue-500 checked:border-blue-500"
+                                                    checked={!!appConfig.proxy.scheduling?.priority_hints}
+                                                    onChange={(e) => updateSchedulingConfig({ priority_hints: e.target.checked })}
+                                                />
+                                            </div>
+
+                                            <div className="flex items-center justify-between gap-3 bg-slate-100 dark:bg-slate-800/80 rounded-xl p-4 border border-slate-200 dark:border-slate-700">
+                                                <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                    {t('proxy.config.scheduling.default_request_type')}
+                                                    <HelpTooltip text={t('proxy.config.scheduling.default_request_type_tooltip')} />
+                                                </label>
+                                                <select
+                                                    value={appConfig.proxy.scheduling?.default_request_type || ''}
+                                                    onChange={(e) => updateSchedulingConfig({ default_request_type: e.target.value || null })}
+                                                    className="px-2.5 py-1.5 border border-gray-300 dark:border-base-200 rounded-lg bg-white dark:bg-base-200 text-xs text-gray-900 dark:text-base-content focus:ring-2 focus:ring-blue-500 focus:border-transparent"
+                                                >
+                                                    <option value="">{t('proxy.config.scheduling.request_type_auto')}</option>
+                                                    <option value="agent">agent</option>
+                                                    <option value="web_search">web_search</option>
+                                                    <option value="image_gen">image_gen</option>
+                                                </select>
+                                            </div>
+
+                                            <div className="flex items-center justify-between gap-3 bg-slate-100 dark:bg-slate-800/80 rounded-xl p-4 border border-slate-200 dark:border-slate-700">
+                                                <label className="text-xs font-medium text-gray-700 dark:text-gray-300 inline-flex items-center gap-1">
+                                                    {t('proxy.config.scheduling.sticky_rate_limit_policy')}
+                                                    <HelpTooltip text={t('proxy.config.scheduling.sticky_rate_limit_policy_tooltip')} />
+                                                </label>
+                                                <div className="flex items-center gap-2">
+                                                    <select
+                                                        value={appConfig.proxy.scheduling?.sticky_rate_limit_policy || 'switch'}
+                                                        onChange={(e) => updateSchedulingConfig({ sticky_rate_limit_policy: e.target.value as 'switch' | 'wait_if_short' })}
+                                                        className="px-2.5 py-1.5 border border-gray-300 dark:border-base-200 rounded-lg bg-white dark:bg-base-200 text-xs text-gray-900 dark:text-base-content focus:ring-2 focus:ring-blue-500 focus:border-transparent"
+                                                    >
+                                                        <option value="switch">{t('proxy.config.scheduling.sticky_policy_switch')}</option>
+                                                        <option value="wait_if_short">{t('proxy.config.scheduling.sticky_policy_wait')}</option>
+                                                    </select>
+                                                    <input
+                                                        type="number"
+                                                        min={1}
+                                                        max={30}
+                                                        disabled={(appConfig.proxy.scheduling?.sticky_rate_limit_policy || 'switch') !== 'wait_if_short'}
+                                                        value={appConfig.proxy.scheduling?.sticky_wait_max_seconds ?? 5}
+                                                        onChange={(e) => updateSchedulingConfig({ sticky_wait_max_seconds: Math.max(1, parseInt(e.target.value) || 1) })}
+                                                        className="w-16 px-2 py-1.5 border border-gray-300 dark:border-base-200 rounded-lg bg-white dark:bg-base-200 text-xs text-gray-900 dark:text-base-content disabled:opacity-50"
+                                                    />
+                                                    <span className="text-xs text-gray-500">s</span>
+                                                </div>
+                                            </div>
+
                                             <div className="p-3 bg-amber-50 dark:bg-amber-900/10 border border-amber-100 dark:border-amber-900/20 rounded-xl">
                                                 <p className="text-[10px] text-amber-700 dark:text-amber-500 leading-relaxed">
                                                     <strong>{t('common.info')}:</strong> {t('proxy.config.scheduling.subtitle')}
@@ -1248,18 +1435,28 @@ print(response.text)`;
 
                                                                     {editingKey === key ? (
                                                                         <div className="flex-1 mr-2">
-                                                                            <GroupedSelect
-                                                                                value={editingValue}
-                                                                                onChange={setEditingValue}
-                                                                                options={customMappingOptions}
-                                                                                placeholder="Select..."
-                                                                                className="font-mono text-[10px] h-7 dark:bg-gray-800 border-blue-200 dark:border-blue-800"
-                                                                            />
+                                                                            {typeof val === 'string' ? (
+                                                                                <GroupedSelect
+                                                                                    value={editingValue}
+                                                                                    onChange={setEditingValue}
+                                                                                    options={customMappingOptions}
+                                                                                    placeholder="Select..."
+                                                                                    className="font-mono text-[10px] h-7 dark:bg-gray-800 border-blue-200 dark:border-blue-800"
+                                                                                />
+                                                                            ) : (
+                                                                                // 加权映射按 "model:weight / model:weight" 文本编辑
+                                                                                <input
+                                                                                    type="text"
+                                                                                    value={editingValue}
+                                                                                    onChange={(e) => setEditingValue(e.target.value)}
+                                                                                    className="input input-xs w-full font-mono text-[10px] h-7 dark:bg-gray-800 border-blue-200 dark:border-blue-800"
+                                                                                />
+                                                                            )}
                                                                         </div>
                                                                     ) : (
                                                                         <span className="font-mono text-[10px] text-gray-500 dark:text-gray-400 truncate cursor-pointer hover:text-blue-500"
-                                                                            onClick={() => { setEditingKey(key); setEditingValue(val); }}
-                                                                            title={val}>{val}</span>
+                                                                            onClick={() => { setEditingKey(key); setEditingValue(formatMappingTarget(val)); }}
+                                                                            title={formatMappingTarget(val)}>{formatMappingTarget(val)}</span>
                                                                     )}
                                                                 </div>
 
@@ -1269,7 +1466,7 @@ print(response.text)`;
                                                                             <button
                                                                                 className="btn btn-ghost btn-xs text-primary hover:bg-blue-50 dark:hover:bg-blue-900/30 p-0 h-6 w-6 min-h-0"
                                                                                 onClick={() => {
-                                                                                    handleMappingUpdate('custom', key, editingValue);
+                                                                                    handleMappingUpdate('custom', key, parseMappingTarget(editingValue));
                                                                                     setEditingKey(null);
                                                                                 }}
                                                                                 title={t('common.save') || 'Save'}
@@ -1289,7 +1486,7 @@ print(response.text)`;
                                                                         <div className="flex items-center gap-1 opacity-0 group-hover:opacity-100 transition-opacity">
                                                                             <button
                                                                                 className="btn btn-ghost btn-xs text-gray-400 hover:text-blue-500 hover:bg-blue-50 dark:hover:bg-white/10 p-0 h-6 w-6 min-h-0"
-                                                                                onClick={() => { setEditingKey(key); setEditingValue(val); }}
+                                                                                onClick={() => { setEditingKey(key); setEditingValue(formatMappingTarget(val)); }}
                                                                                 title={t('common.edit') || 'Edit'}
                                                                             >
                                                                                 <Edit2 size={12} />
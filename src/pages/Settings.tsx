@@ -172,7 +172,13 @@ function Settings() {
                 latest_version: string;
                 current_version: string;
                 download_url: string;
-            }>('check_for_updates');
+                rate_limited?: boolean;
+            }>('check_for_updates', { force: true });
+
+            if (result.rate_limited) {
+                showToast(t('settings.about.update_rate_limited'), 'warning');
+                return;
+            }
 
             setUpdateInfo({
                 hasUpdate: result.has_update,
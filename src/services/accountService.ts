@@ -1,6 +1,6 @@
 import i18n from '../i18n';
 import { request as invoke } from '../utils/request';
-import { Account, QuotaData, DeviceProfile, DeviceProfileVersion } from '../types/account';
+import { Account, QuotaData, DeviceProfile, DeviceProfileVersion, ReliabilityReport, QuotaResetTime, ImportReport, ImportConflict, ImportConflictChoice } from '../types/account';
 
 // 检查 Tauri 环境
 function ensureTauriEnvironment() {
@@ -19,7 +19,7 @@ export async function getCurrentAccount(): Promise<Account | null> {
     return await invoke('get_current_account');
 }
 
-export async function addAccount(email: string, refreshToken: string): Promise<Account> {
+export async function addAccount(email: string, refreshToken: string): Promise<ImportReport> {
     return await invoke('add_account', { email, refreshToken });
 }
 
@@ -31,10 +31,64 @@ export async function deleteAccounts(accountIds: string[]): Promise<void> {
     return await invoke('delete_accounts', { accountIds });
 }
 
+// 回收站：删除的账号保留 trash_retention_days 天 (默认 7)
+export interface TrashedAccount {
+    account_id: string;
+    email: string;
+    name?: string | null;
+    created_at: number;
+    last_used: number;
+    deleted_at: number;
+}
+
+export interface TrashRestoreResult {
+    restored?: Account | null;
+    conflict?: ImportConflict | null; // 同邮箱账号已存在，需通过 resolveImportConflict 处理
+}
+
+export async function listTrashedAccounts(): Promise<TrashedAccount[]> {
+    return await invoke('list_trashed_accounts');
+}
+
+export async function restoreTrashedAccount(accountId: string): Promise<TrashRestoreResult> {
+    return await invoke('restore_trashed_account', { accountId });
+}
+
+export async function purgeTrash(olderThanDays?: number): Promise<number> {
+    return await invoke('purge_trash', { olderThanDays: olderThanDays ?? null });
+}
+
 export async function switchAccount(accountId: string): Promise<void> {
     return await invoke('switch_account', { accountId });
 }
 
+export interface SwitchPlan {
+    account_id: string;
+    email: string;
+    storage_path?: string | null;
+    storage_error?: string | null;
+    device_profile?: DeviceProfile | null; // 来源为 generated 时切换时才生成
+    profile_source: 'bound' | 'global_original' | 'current_storage' | 'generated';
+    db_path?: string | null;
+    db_exists: boolean;
+    db_error?: string | null;
+    ide_running: boolean; // 切换时会先关闭 Antigravity
+    token_refresh_needed: boolean;
+}
+
+// 预演切换：不写文件、不关闭/启动进程
+export async function switchAccountDryRun(accountId: string): Promise<SwitchPlan> {
+    return await invoke('switch_account_dry_run', { accountId });
+}
+
+export async function switchToNextAccount(): Promise<Account> {
+    return await invoke('switch_to_next_account');
+}
+
+export async function switchToPreviousAccount(): Promise<Account> {
+    return await invoke('switch_to_previous_account');
+}
+
 export async function fetchAccountQuota(accountId: string): Promise<QuotaData> {
     return await invoke('fetch_account_quota', { accountId });
 }
@@ -43,11 +97,18 @@ export interface RefreshStats {
     total: number;
     success: number;
     failed: number;
+    deferred: number; // 处于限流锁定期而推迟刷新的账号数
     details: string[];
 }
 
-export async function refreshAllQuotas(): Promise<RefreshStats> {
-    return await invoke('refresh_all_quotas');
+// background: 定时自动刷新 (静默时段内由后端跳过)
+export async function refreshAllQuotas(force = false, background = false): Promise<RefreshStats> {
+    return await invoke('refresh_all_quotas', { force, background });
+}
+
+/** 各账号各模型距配额刷新的秒数 (用于显示「3 小时后重置」) */
+export async function getQuotaResetTimes(): Promise<QuotaResetTime[]> {
+    return await invoke('get_quota_reset_times');
 }
 
 // OAuth
@@ -85,22 +146,37 @@ export async function completeOAuthLogin(): Promise<Account> {
     }
 }
 
+// 重新授权被禁用的账号 (授权邮箱必须与原账号一致)
+export async function reauthorizeAccount(accountId: string): Promise<Account> {
+    ensureTauriEnvironment();
+    return await invoke('reauthorize_account', { accountId });
+}
+
 export async function cancelOAuthLogin(): Promise<void> {
     ensureTauriEnvironment();
     return await invoke('cancel_oauth_login');
 }
 
 // 导入
-export async function importV1Accounts(): Promise<Account[]> {
+export async function importV1Accounts(): Promise<ImportReport> {
     return await invoke('import_v1_accounts');
 }
 
-export async function importFromDb(): Promise<Account> {
-    return await invoke('import_from_db');
+// captureDevice: 将 storage.json 中的当前设备指纹绑定到导入的账号
+export async function importFromDb(captureDevice = false): Promise<ImportReport> {
+    return await invoke('import_from_db', { captureDevice });
+}
+
+export async function importFromCustomDb(path: string, captureDevice = false): Promise<ImportReport> {
+    return await invoke('import_custom_db', { path, captureDevice });
+}
+
+export async function listImportConflicts(): Promise<ImportConflict[]> {
+    return await invoke('list_import_conflicts');
 }
 
-export async function importFromCustomDb(path: string): Promise<Account> {
-    return await invoke('import_custom_db', { path });
+export async function resolveImportConflict(email: string, choice: ImportConflictChoice): Promise<Account> {
+    return await invoke('resolve_import_conflict', { email, choice });
 }
 
 export async function syncAccountFromDb(): Promise<Account | null> {
@@ -111,14 +187,84 @@ export async function toggleProxyStatus(accountId: string, enable: boolean, reas
     return await invoke('toggle_proxy_status', { accountId, enable, reason });
 }
 
+/** 临时停用账号反代，返回停用截止时间戳 (已在停用中时延长) */
+export async function snoozeAccount(accountId: string, minutes: number, reason?: string): Promise<number> {
+    return await invoke('snooze_account', { accountId, minutes, reason });
+}
+
+/** 账号可靠性报告 (刷新/禁用事件历史、7 天刷新成功率、平均故障间隔与等级) */
+export async function getAccountReliability(accountId: string): Promise<ReliabilityReport> {
+    return await invoke('get_account_reliability', { accountId });
+}
+
+export interface RefreshTiming {
+    email: string;
+    refresh_ms: number;
+    ok: boolean;
+    error: string | null;
+}
+
+export async function benchmarkTokenRefresh(): Promise<RefreshTiming[]> {
+    return await invoke('benchmark_token_refresh');
+}
+
 /**
  * 重新排序账号列表
  * @param accountIds 按新顺序排列的账号ID数组
  */
+export async function exportAccountsReport(path: string, format: 'csv' | 'json'): Promise<number> {
+    return await invoke('export_accounts_report', { path, format });
+}
+
+export interface IdleAccount {
+    id: string;
+    email: string;
+    name?: string;
+    disabled: boolean;
+    proxy_disabled: boolean;
+    /** 缺失表示从未使用 */
+    last_proxy_used_at?: number;
+    last_switched_at?: number;
+}
+
+/** 最近 days 天内未被反代使用、也未切换到 IDE 的账号 (可交给批量禁用/删除) */
+export async function findIdleAccounts(days: number): Promise<IdleAccount[]> {
+    return await invoke('find_idle_accounts', { days });
+}
+
+export interface TokenExpiry {
+    email: string;
+    expiry_ts: number;
+    seconds_until_expiry: number;
+    needs_refresh_soon: boolean;
+}
+
+export async function getTokenExpiries(): Promise<TokenExpiry[]> {
+    return await invoke('get_token_expiries');
+}
+
+export interface StaleDisable {
+    account_id: string;
+    email: string;
+    reason?: string | null;
+    disabled_at: number;
+    days_disabled: number;
+}
+
+export async function listStaleDisables(): Promise<StaleDisable[]> {
+    return await invoke('list_stale_disables');
+}
+
 export async function reorderAccounts(accountIds: string[]): Promise<void> {
     return await invoke('reorder_accounts', { accountIds });
 }
 
+export type AccountSortMetric = 'quota' | 'tier' | 'last_used' | 'email';
+
+export async function reorderAccountsBy(metric: AccountSortMetric, descending: boolean): Promise<string[]> {
+    return await invoke('reorder_accounts_by', { metric, descending });
+}
+
 // 设备指纹相关
 export interface DeviceProfilesResponse {
     current_storage?: DeviceProfile;
@@ -150,6 +296,10 @@ export async function deleteDeviceVersion(accountId: string, versionId: string):
     return await invoke('delete_device_version', { accountId, versionId });
 }
 
+export async function pruneDeviceHistory(accountId: string): Promise<number> {
+    return await invoke('prune_device_history', { accountId });
+}
+
 export async function openDeviceFolder(): Promise<void> {
     return await invoke('open_device_folder');
 }
@@ -158,6 +308,10 @@ export async function previewGenerateProfile(): Promise<DeviceProfile> {
     return await invoke('preview_generate_profile');
 }
 
+export async function validateDeviceProfile(profile: DeviceProfile): Promise<void> {
+    return await invoke('validate_device_profile', { profile });
+}
+
 export async function bindDeviceProfileWithProfile(accountId: string, profile: DeviceProfile): Promise<DeviceProfile> {
     return await invoke('bind_device_profile_with_profile', { accountId, profile });
 }
@@ -167,7 +321,59 @@ export async function warmUpAllAccounts(): Promise<string> {
     return await invoke('warm_up_all_accounts');
 }
 
+export async function toggleAccountWarmup(accountId: string, enable: boolean): Promise<void> {
+    return await invoke('toggle_account_warmup', { accountId, enable });
+}
+
+export async function setAccountStandby(accountId: string, standby: boolean): Promise<void> {
+    return await invoke('set_account_standby', { accountId, standby });
+}
+
+export async function setAccountAllowedGroups(accountId: string, groups: ('text' | 'image_gen')[]): Promise<void> {
+    return await invoke('set_account_allowed_groups', { accountId, groups });
+}
+
 export async function warmUpAccount(accountId: string): Promise<string> {
     return await invoke('warm_up_account', { accountId });
 }
 
+
+// 托盘调试
+export interface TrayMenuEntry {
+    id: string;
+    label: string;
+    enabled: boolean;
+}
+
+export interface TraySnapshot {
+    current_account_id: string | null;
+    current_account_email: string | null;
+    quota_summary: string[];
+    items: TrayMenuEntry[];
+}
+
+export async function refreshTray(): Promise<TraySnapshot> {
+    return await invoke('refresh_tray');
+}
+
+// 数据目录租约 (多实例只读保护)
+export interface InstanceLockOwner {
+    instance_id: string;
+    hostname: string;
+    heartbeat: number;
+}
+
+export interface InstanceLockStatus {
+    enabled: boolean;
+    read_only: boolean;
+    instance_id: string;
+    owner: InstanceLockOwner | null;
+}
+
+export async function getInstanceLockStatus(): Promise<InstanceLockStatus> {
+    return await invoke('get_instance_lock_status');
+}
+
+export async function forceTakeOwnership(confirm: boolean): Promise<InstanceLockStatus> {
+    return await invoke('force_take_ownership', { confirm });
+}
@@ -1,5 +1,5 @@
 import { request as invoke } from '../utils/request';
-import { AppConfig } from '../types/config';
+import { AppConfig, ModelMappingTarget, ProxyTlsStatus } from '../types/config';
 
 export async function loadConfig(): Promise<AppConfig> {
     return await invoke('load_config');
@@ -8,3 +8,180 @@ export async function loadConfig(): Promise<AppConfig> {
 export async function saveConfig(config: AppConfig): Promise<void> {
     return await invoke('save_config', { config });
 }
+
+export interface ConfigFieldDiff {
+    field: string;
+    current: unknown;
+    default: unknown;
+}
+
+export async function diffConfigFromDefaults(): Promise<ConfigFieldDiff[]> {
+    return await invoke('diff_config_from_defaults');
+}
+
+/** 导出脱敏诊断包 (zip)，返回写入的路径 */
+export async function createDiagnosticBundle(path: string): Promise<string> {
+    return await invoke('create_diagnostic_bundle', { path });
+}
+
+export interface WeightedRouteShare {
+    model: string;
+    weight: number;
+    expected_ratio: number;
+    count: number;
+    realized_ratio: number;
+}
+
+export interface WeightedRouteSplit {
+    alias: string;
+    total: number;
+    targets: WeightedRouteShare[];
+}
+
+export async function getModelRouteSplitStats(): Promise<WeightedRouteSplit[]> {
+    return await invoke('get_model_route_split_stats');
+}
+
+export async function exportModelMapping(): Promise<string> {
+    return await invoke('export_model_mapping');
+}
+
+export interface MappingWarning {
+    alias: string;
+    target: string;
+    kind: 'thinking_unsupported' | 'tools_unsupported';
+    message: string;
+}
+
+export async function importModelMapping(json: string, merge: boolean): Promise<MappingWarning[]> {
+    return await invoke('import_model_mapping', { json, merge });
+}
+
+export async function getModelMappingHealth(): Promise<MappingWarning[]> {
+    return await invoke('get_model_mapping_health');
+}
+
+export interface MappingChange {
+    alias: string;
+    old: ModelMappingTarget | null;
+    new: ModelMappingTarget | null;
+}
+
+export interface MappingHistoryEntry {
+    version: number;
+    timestamp: number;
+    source: 'ui' | 'import' | 'auto' | 'revert';
+    changes: MappingChange[];
+}
+
+export async function getModelMappingHistory(): Promise<MappingHistoryEntry[]> {
+    return await invoke('get_model_mapping_history');
+}
+
+export async function revertModelMapping(versionIndex: number): Promise<void> {
+    return await invoke('revert_model_mapping', { versionIndex });
+}
+
+export interface AntigravityInstallation {
+    path: string;
+    version: string | null;
+    source: 'configured' | 'running_process' | 'standard_location';
+}
+
+export async function listAntigravityInstallations(): Promise<AntigravityInstallation[]> {
+    return await invoke('list_antigravity_installations');
+}
+
+export interface BackupSectionInfo {
+    name: string;
+    files: number;
+    bytes: number;
+}
+
+export interface BackupManifest {
+    format: string;
+    version: number;
+    app_version: string;
+    created_at: number;
+    encrypted: boolean;
+    sections: BackupSectionInfo[];
+}
+
+export interface BackupEntry {
+    path: string;
+    file_name: string;
+    size: number;
+    manifest: BackupManifest | null;
+    error: string | null;
+}
+
+export interface SectionRestoreReport {
+    section: string;
+    status: 'restored' | 'skipped' | 'failed';
+    files: number;
+    message: string | null;
+}
+
+export interface RestoreReport {
+    archive: string;
+    proxy_stopped: boolean;
+    proxy_restarted: boolean;
+    sections: SectionRestoreReport[];
+}
+
+export async function createBackup(path?: string): Promise<string> {
+    return await invoke('create_backup', { path: path ?? null });
+}
+
+export async function listBackups(): Promise<BackupEntry[]> {
+    return await invoke('list_backups');
+}
+
+export async function restoreBackup(archivePath: string, sections: string[] = []): Promise<RestoreReport> {
+    return await invoke('restore_backup', { archivePath, sections });
+}
+
+// 按当前配置重新加载反代 HTTPS 证书 (服务需已启用 TLS)
+export async function reloadProxyTls(): Promise<ProxyTlsStatus> {
+    return await invoke('reload_proxy_tls');
+}
+
+export type UsageRange = 'today' | 'week' | 'month' | 'all';
+
+export interface UsageCostRow {
+    day: string; // 本地日期 YYYY-MM-DD
+    account_email: string;
+    model: string;
+    requests: number;
+    input_tokens: number;
+    output_tokens: number;
+    cached_tokens: number;
+    cost_usd: number;
+}
+
+// 没有匹配单价的用量
+export interface UnpricedUsage {
+    model: string;
+    requests: number;
+    input_tokens: number;
+    output_tokens: number;
+    cached_tokens: number;
+}
+
+export interface UsageCostReport {
+    range: UsageRange;
+    since: number;
+    total_usd: number;
+    rows: UsageCostRow[];
+    unpriced: UnpricedUsage[];
+}
+
+// 按配置单价估算的用量费用 (需开启请求日志)
+export async function getUsageCosts(range: UsageRange): Promise<UsageCostReport> {
+    return await invoke('get_usage_costs', { range });
+}
+
+// 计算 Claude 请求体的会话指纹 (排查两个请求是否会被视为同一会话)
+export async function computeSessionId(body: unknown): Promise<string> {
+    return await invoke('compute_session_id', { body });
+}
@@ -1,5 +1,5 @@
 import { create } from 'zustand';
-import { Account } from '../types/account';
+import { Account, ImportConflict, ImportConflictChoice } from '../types/account';
 import * as accountService from '../services/accountService';
 
 interface AccountState {
@@ -7,6 +7,7 @@ interface AccountState {
     currentAccount: Account | null;
     loading: boolean;
     error: string | null;
+    importConflicts: ImportConflict[]; // 等待用户选择保留哪个 token 的导入冲突
 
     // Actions
     fetchAccounts: () => Promise<void>;
@@ -16,20 +17,23 @@ interface AccountState {
     deleteAccounts: (accountIds: string[]) => Promise<void>;
     switchAccount: (accountId: string) => Promise<void>;
     refreshQuota: (accountId: string) => Promise<void>;
-    refreshAllQuotas: () => Promise<accountService.RefreshStats>;
+    refreshAllQuotas: (background?: boolean) => Promise<accountService.RefreshStats>;
     reorderAccounts: (accountIds: string[]) => Promise<void>;
 
     // 新增 actions
     startOAuthLogin: () => Promise<void>;
     completeOAuthLogin: () => Promise<void>;
     cancelOAuthLogin: () => Promise<void>;
+    reauthorizeAccount: (accountId: string) => Promise<void>;
     importV1Accounts: () => Promise<void>;
-    importFromDb: () => Promise<void>;
-    importFromCustomDb: (path: string) => Promise<void>;
+    importFromDb: (captureDevice?: boolean) => Promise<void>;
+    importFromCustomDb: (path: string, captureDevice?: boolean) => Promise<void>;
     syncAccountFromDb: () => Promise<void>;
     toggleProxyStatus: (accountId: string, enable: boolean, reason?: string) => Promise<void>;
     warmUpAccounts: () => Promise<string>;
     warmUpAccount: (accountId: string) => Promise<string>;
+    fetchImportConflicts: () => Promise<void>;
+    resolveImportConflict: (email: string, choice: ImportConflictChoice) => Promise<void>;
 }
 
 export const useAccountStore = create<AccountState>((set, get) => ({
@@ -37,6 +41,7 @@ export const useAccountStore = create<AccountState>((set, get) => ({
     currentAccount: null,
     loading: false,
     error: null,
+    importConflicts: [],
 
     fetchAccounts: async () => {
         set({ loading: true, error: null });
@@ -64,7 +69,10 @@ export const useAccountStore = create<AccountState>((set, get) => ({
         set({ loading: true, error: null });
         try {
             await accountService.addAccount(email, refreshToken);
-            await get().fetchAccounts();
+            await Promise.all([
+                get().fetchAccounts(),
+                get().fetchImportConflicts()
+            ]);
             set({ loading: false });
         } catch (error) {
             set({ error: String(error), loading: false });
@@ -126,10 +134,10 @@ export const useAccountStore = create<AccountState>((set, get) => ({
         }
     },
 
-    refreshAllQuotas: async () => {
+    refreshAllQuotas: async (background = false) => {
         set({ loading: true, error: null });
         try {
-            const stats = await accountService.refreshAllQuotas();
+            const stats = await accountService.refreshAllQuotas(false, background);
             await get().fetchAccounts();
             set({ loading: false });
             return stats;
@@ -208,7 +216,10 @@ export const useAccountStore = create<AccountState>((set, get) => ({
         set({ loading: true, error: null });
         try {
             await accountService.importV1Accounts();
-            await get().fetchAccounts();
+            await Promise.all([
+                get().fetchAccounts(),
+                get().fetchImportConflicts()
+            ]);
             set({ loading: false });
         } catch (error) {
             set({ error: String(error), loading: false });
@@ -216,13 +227,14 @@ export const useAccountStore = create<AccountState>((set, get) => ({
         }
     },
 
-    importFromDb: async () => {
+    importFromDb: async (captureDevice = false) => {
         set({ loading: true, error: null });
         try {
-            await accountService.importFromDb();
+            await accountService.importFromDb(captureDevice);
             await Promise.all([
                 get().fetchAccounts(),
-                get().fetchCurrentAccount()
+                get().fetchCurrentAccount(),
+                get().fetchImportConflicts()
             ]);
             set({ loading: false });
         } catch (error) {
@@ -231,13 +243,14 @@ export const useAccountStore = create<AccountState>((set, get) => ({
         }
     },
 
-    importFromCustomDb: async (path: string) => {
+    importFromCustomDb: async (path: string, captureDevice = false) => {
         set({ loading: true, error: null });
         try {
-            await accountService.importFromCustomDb(path);
+            await accountService.importFromCustomDb(path, captureDevice);
             await Promise.all([
                 get().fetchAccounts(),
-                get().fetchCurrentAccount()
+                get().fetchCurrentAccount(),
+                get().fetchImportConflicts()
             ]);
             set({ loading: false });
         } catch (error) {
@@ -254,6 +267,8 @@ export const useAccountStore = create<AccountState>((set, get) => ({
                 await get().fetchAccounts();
                 set({ currentAccount: syncedAccount });
             }
+            // 同步遇到冲突时不会返回账号，需要单独拉取待处理冲突
+            await get().fetchImportConflicts();
         } catch (error) {
             console.error('[AccountStore] Sync from DB failed:', error);
         }
@@ -269,6 +284,18 @@ export const useAccountStore = create<AccountState>((set, get) => ({
         }
     },
 
+    reauthorizeAccount: async (accountId: string) => {
+        set({ loading: true, error: null });
+        try {
+            await accountService.reauthorizeAccount(accountId);
+            await get().fetchAccounts();
+            set({ loading: false });
+        } catch (error) {
+            set({ error: String(error), loading: false });
+            throw error;
+        }
+    },
+
     warmUpAccounts: async () => {
         set({ loading: true, error: null });
         try {
@@ -294,4 +321,22 @@ export const useAccountStore = create<AccountState>((set, get) => ({
             throw error;
         }
     },
+
+    fetchImportConflicts: async () => {
+        try {
+            const importConflicts = await accountService.listImportConflicts();
+            set({ importConflicts });
+        } catch (error) {
+            console.error('[AccountStore] Fetch import conflicts failed:', error);
+        }
+    },
+
+    resolveImportConflict: async (email: string, choice: ImportConflictChoice) => {
+        await accountService.resolveImportConflict(email, choice);
+        set({ importConflicts: get().importConflicts.filter(c => c.email !== email) });
+        await Promise.all([
+            get().fetchAccounts(),
+            get().fetchCurrentAccount()
+        ]);
+    },
 }));
@@ -1,7 +1,9 @@
 export interface Account {
+    schema_version?: number;
     id: string;
     email: string;
     name?: string;
+    label?: string;
     token: TokenData;
     device_profile?: DeviceProfile;
     device_history?: DeviceProfileVersion[];
@@ -11,11 +13,45 @@ export interface Account {
     disabled_at?: number;
     proxy_disabled?: boolean;
     proxy_disabled_reason?: string;
+    proxy_disabled_category?: 'manual' | 'quota_protection' | 'invalid_grant' | 'snooze' | 'other';
     proxy_disabled_at?: number;
+    proxy_disabled_until?: number;
+    snooze_remaining_seconds?: number;
+    reliability_grade?: string;
+    no_warmup?: boolean;
+    standby?: boolean;
+    /** 允许服务的配额分组，缺失或为空表示不限 */
+    allowed_groups?: ('text' | 'image_gen')[];
+    /** 最近一次被反代使用的时间，缺失表示从未使用 */
+    last_proxy_used_at?: number;
+    /** 最近一次切换到 IDE 的时间 */
+    last_switched_at?: number;
     created_at: number;
     last_used: number;
 }
 
+export interface QuotaResetTime {
+    email: string;
+    model: string;
+    reset_time: string;
+    seconds_until: number;
+}
+
+export interface ReliabilityEvent {
+    timestamp: number;
+    kind: 'refresh' | 'forced_refresh' | 'disabled';
+    success: boolean;
+    detail?: string;
+}
+
+export interface ReliabilityReport {
+    account_id: string;
+    events: ReliabilityEvent[];
+    refresh_success_rate_7d: number | null;
+    mean_time_between_failures_secs: number | null;
+    grade: string;
+}
+
 export interface TokenData {
     access_token: string;
     refresh_token: string;
@@ -53,3 +89,24 @@ export interface DeviceProfileVersion {
     is_current?: boolean;
 }
 
+export interface ImportTokenSide {
+    refresh_token_hint: string;
+    expiry_timestamp: number;
+    refreshes: boolean;
+    error?: string | null;
+}
+
+export interface ImportConflict {
+    email: string;
+    account_id: string;
+    existing: ImportTokenSide;
+    imported: ImportTokenSide;
+}
+
+export interface ImportReport {
+    imported: Account[];
+    kept_existing: string[];
+    conflicts: ImportConflict[];
+}
+
+export type ImportConflictChoice = 'keep_existing' | 'use_imported';
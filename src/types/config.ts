@@ -3,19 +3,156 @@ export interface UpstreamProxyConfig {
     url: string;
 }
 
+// 反代监听的 HTTPS：未设置 https_port 时主端口只接受 HTTPS，否则两个端口并存
+export interface ProxyTlsConfig {
+    enabled: boolean;
+    self_signed: boolean; // 使用数据目录下自动生成的自签名证书
+    cert_path?: string | null;
+    key_path?: string | null;
+    https_port?: number | null;
+}
+
+export type ProxyAuthMode = 'off' | 'strict' | 'all_except_health' | 'auto';
+
+export type RouteGroup = 'claude' | 'openai' | 'gemini_native' | 'status';
+
+// 未设置的分组使用全局 auth_mode；未归入分组的路径使用最严格的模式
+export interface RouteAuthConfig {
+    claude?: ProxyAuthMode | null;
+    openai?: ProxyAuthMode | null;
+    gemini_native?: ProxyAuthMode | null;
+    status?: ProxyAuthMode | null; // /healthz only; /metrics keeps its own access policy
+}
+
+export interface EffectiveAuthModes {
+    claude: ProxyAuthMode;
+    openai: ProxyAuthMode;
+    gemini_native: ProxyAuthMode;
+    status: ProxyAuthMode;
+    other: ProxyAuthMode;
+}
+
+export interface ProxyTlsStatus {
+    https_port: number;
+    self_signed: boolean;
+    fingerprint_sha256: string; // 证书 SHA-256 指纹，供客户端固定证书
+}
+
+export interface WeightedModelTarget {
+    model: string;
+    weight: number;
+}
+
+// 单个目标模型，或按权重分流的目标列表
+export type ModelMappingTarget = string | WeightedModelTarget[];
+
+// 美元 / 百万 token
+export interface ModelPrice {
+    input: number;
+    output: number;
+    cached_input?: number | null; // 未设置时按 input 计
+}
+
+export interface SamplingLimits {
+    temperature_min: number;
+    temperature_max: number;
+    top_p_min: number;
+    top_p_max: number;
+    top_k_min: number;
+    top_k_max: number;
+}
+
+export interface SamplingLimitsConfig {
+    enabled: boolean;
+    default: SamplingLimits;
+    per_model: Record<string, SamplingLimits>; // 键为模型名或以 * 结尾的前缀
+}
+
+export interface ClientApiKey {
+    id: string;
+    label: string;
+    key_sha256: string;
+    scopes: RouteGroup[]; // 为空时不限分组
+    created_at: number;
+}
+
 export interface ProxyConfig {
     enabled: boolean;
     allow_lan_access?: boolean;
-    auth_mode?: 'off' | 'strict' | 'all_except_health' | 'auto';
+    auth_mode?: ProxyAuthMode;
+    route_auth?: RouteAuthConfig; // 按路由分组覆盖 auth_mode
     port: number;
+    auto_pick_port?: boolean; // 端口被占用时自动改用空闲端口
     api_key: string;
     auto_start: boolean;
-    custom_mapping?: Record<string, string>;
+    custom_mapping?: Record<string, ModelMappingTarget>;
     request_timeout: number;
     enable_logging: boolean;
+    hash_end_user_labels?: boolean; // 日志中以哈希代替客户端传入的用户标识
+    redact_logged_content?: boolean; // 日志中的消息内容以占位符保存 (此类记录不可重放)
     upstream_proxy: UpstreamProxyConfig;
+    upstream_pool_max_idle_per_host?: number; // 默认 16
+    upstream_pool_idle_timeout_secs?: number; // 默认 90
+    upstream_min_tls?: '1.2' | '1.3' | null; // 上游最低 TLS 版本，未设置时使用库默认值
+    tls?: ProxyTlsConfig;
     zai?: ZaiConfig;
     scheduling?: StickySessionConfig;
+    log_account_identifier?: 'email' | 'label' | 'hash';
+    response_header_policy?: 'full' | 'masked' | 'minimal'; // 响应头暴露策略
+    response_headers?: ResponseHeadersConfig; // 诊断响应头开关与自定义响应头
+    sampling_limits?: SamplingLimitsConfig; // 采样参数钳制
+    metrics?: MetricsConfig; // Prometheus 指标端点
+    enable_setup_page?: boolean; // 在浏览器中访问 /setup 为其他设备生成客户端 Key
+    client_keys?: ClientApiKey[]; // 附加的客户端 Key (只保存哈希)
+    max_history_messages?: number | null; // 消息历史上限
+    tool_filter?: ToolFilterConfig; // 转发工具的允许/禁止列表
+    audio?: AudioConfig;
+    experimental?: ExperimentalConfig;
+}
+
+export interface ExperimentalConfig {
+    enable_signature_cache: boolean;
+    enable_tool_loop_recovery: boolean;
+    tool_loop_recovery_mode?: 'off' | 'synthetic' | 'strip';
+    empty_assistant_mode?: 'empty_text' | 'drop'; // thinking 过滤后变空的 assistant 消息：插入空文本 / 移除
+    enable_cross_model_checks: boolean;
+    retry_malformed_function_call?: boolean;
+    recitation_policy?: 'pass_through' | 'retry';
+    emit_incremental_usage?: boolean;
+    stream_heartbeat_seconds?: number;
+    max_completions?: number;
+    trim_context_on_overflow?: boolean;
+    context_exceeded_patterns?: string[];
+    logprobs_mode?: 'ignore' | 'error';
+    enable_self_test?: boolean; // 允许运行吞吐自测 (消耗真实配额)
+}
+
+export interface ResponseHeadersConfig {
+    expose_account: boolean; // X-Account-Email / X-Account-Id
+    expose_model: boolean; // X-Mapped-Model
+    extra: Record<string, string>;
+}
+
+export interface AudioConfig {
+    max_upload_mb: number; // 上传大小上限
+    segment_mb: number; // 超过时分段转录 (仅 WAV / MP3)
+    segment_overlap_secs: number;
+}
+
+export interface ToolFilterRule {
+    allow: string[];
+    deny: string[];
+}
+
+export interface ToolFilterConfig {
+    global: ToolFilterRule;
+    per_key: Record<string, ToolFilterRule>;
+}
+
+export interface MetricsConfig {
+    enabled: boolean;
+    access: 'localhost' | 'auth';
+    per_account_labels: boolean;
 }
 
 export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst';
@@ -23,6 +160,27 @@ export type SchedulingMode = 'CacheFirst' | 'Balance' | 'PerformanceFirst';
 export interface StickySessionConfig {
     mode: SchedulingMode;
     max_wait_seconds: number;
+    unhealthy_failure_threshold?: number;
+    priority_hints?: boolean;
+    default_request_type?: string | null;
+    sticky_rate_limit_policy?: 'switch' | 'wait_if_short';
+    sticky_wait_max_seconds?: number;
+    tier_weights?: TierWeights; // 容量评分中的订阅等级权重
+}
+
+export interface TierWeights {
+    ultra: number;
+    pro: number;
+    free: number;
+    unknown: number;
+}
+
+export interface AccountCapacity {
+    account_id: string;
+    email: string;
+    subscription_tier?: string | null;
+    quota_percent?: number | null;
+    capacity_score: number;
 }
 
 export type ZaiDispatchMode = 'off' | 'exclusive' | 'pooled' | 'fallback';
@@ -40,14 +198,22 @@ export interface ZaiModelDefaults {
     haiku: string;
 }
 
+export interface ZaiApiKey {
+    key: string;
+    label?: string | null;
+    enabled: boolean;
+}
+
 export interface ZaiConfig {
     enabled: boolean;
     base_url: string;
     api_key: string;
+    api_keys?: ZaiApiKey[]; // 额外的 Key 号池 (按请求轮换)
     dispatch_mode: ZaiDispatchMode;
     model_mapping?: Record<string, string>;
     models: ZaiModelDefaults;
     mcp: ZaiMcpConfig;
+    failure_fallback_to_google?: boolean; // z.ai 转发失败时改用 Google 号池重试 (仅 pooled 模式)
 }
 
 export interface ScheduledWarmupConfig {
@@ -61,6 +227,20 @@ export interface QuotaProtectionConfig {
     monitored_models: string[];
 }
 
+export interface InstanceLockConfig {
+    enabled: boolean;
+    heartbeat_secs: number;
+    stale_after_secs: number;
+}
+
+export interface BackupConfig {
+    enabled: boolean;
+    directory?: string | null; // 为空时使用数据目录下的 backups
+    interval_hours: number;
+    keep_last: number;
+    encrypt: boolean; // 使用 token 加密口令加密备份
+}
+
 export interface AppConfig {
     language: string;
     theme: string;
@@ -77,6 +257,16 @@ export interface AppConfig {
     accounts_page_size?: number; // 账号列表每页显示数量,默认 0 表示自动计算
     scheduled_warmup: ScheduledWarmupConfig;
     quota_protection: QuotaProtectionConfig; // [NEW] 配额保护配置
+    encrypt_tokens_at_rest?: boolean; // 账号 token 静态加密
+    backup?: BackupConfig; // 定时全量备份
+    instance_lock?: InstanceLockConfig; // 数据目录多实例租约
+    model_mapping_history_limit?: number; // 模型映射变更历史保留条数
+    import_conflict_policy?: 'ask' | 'keep_existing' | 'use_imported'; // 导入账号与已有 token 冲突时的处理策略
+    auto_expire_manual_disables_days?: number | null; // 手动停用超过 N 天时提醒复查 (不会自动恢复)
+    trash_retention_days?: number; // 已删除账号在回收站中的保留天数 (默认 7)
+    pricing?: Record<string, ModelPrice>; // 费用估算单价，键为模型名或以 * 结尾的前缀
+    tray_show_daily_cost?: boolean; // 托盘提示中显示当天估算费用
+    max_device_history?: number; // 每个账号保留的历史指纹条数 (默认 20，0 表示不限制)
     proxy: ProxyConfig;
 }
 